@@ -0,0 +1,200 @@
+use anyhow::{Context, Result};
+use brush_render::{camera::Camera, gaussian_splats::Splats, render_options::RenderOptions};
+use brush_render_bwd::burn_glue::SplatForwardDiff;
+use burn::{
+    backend::{Autodiff, Wgpu, wgpu::WgpuDevice},
+    tensor::{Distribution, Tensor, TensorData, TensorPrimitive, s},
+};
+
+type DiffBack = Autodiff<Wgpu>;
+
+const NUM_SPLATS: usize = 6;
+const IMG_SIZE: u32 = 32;
+
+/// A few overlapping splats at different depths, so the depth depends on the blending order.
+fn scene_params() -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+    let means = (0..NUM_SPLATS)
+        .flat_map(|i| {
+            let x = ((i % 3) as f32 - 1.0) * 0.15;
+            let y = ((i / 3) as f32 - 0.5) * 0.15;
+            let z = i as f32 * 0.2 - 0.5;
+            [x, y, z]
+        })
+        .collect();
+    let log_scales = (0..NUM_SPLATS)
+        .flat_map(|i| [-1.6 - 0.05 * i as f32, -1.5, -1.7 + 0.05 * i as f32])
+        .collect();
+    let raw_opacities = (0..NUM_SPLATS).map(|i| (i as f32 - 2.5) * 0.3).collect();
+    (means, log_scales, raw_opacities)
+}
+
+fn camera() -> Camera {
+    Camera::new(
+        glam::vec3(0.0, 0.0, -5.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    )
+}
+
+fn render_splats(splats: &Splats<DiffBack>) -> Tensor<DiffBack, 3> {
+    let diff_out = DiffBack::render_splats(
+        &camera(),
+        glam::uvec2(IMG_SIZE, IMG_SIZE),
+        splats.means.val().into_primitive().tensor(),
+        splats.log_scales.val().into_primitive().tensor(),
+        splats.rotation.val().into_primitive().tensor(),
+        splats.sh_coeffs.val().into_primitive().tensor(),
+        splats.opacities().into_primitive().tensor(),
+        &RenderOptions {
+            render_depth: true,
+            ..Default::default()
+        },
+    );
+    Tensor::from_primitive(TensorPrimitive::Float(diff_out.img))
+}
+
+fn make_splats(
+    means: &[f32],
+    log_scales: &[f32],
+    raw_opacities: &[f32],
+    device: &WgpuDevice,
+) -> Splats<DiffBack> {
+    let n = raw_opacities.len();
+    let rotation = Tensor::<DiffBack, 2>::zeros([n, 4], device)
+        .slice_assign(s![.., 0], Tensor::ones([n, 1], device));
+    Splats::from_tensor_data(
+        Tensor::from_data(TensorData::new(means.to_vec(), [n, 3]), device),
+        rotation,
+        Tensor::from_data(TensorData::new(log_scales.to_vec(), [n, 3]), device),
+        Tensor::ones([n, 1, 3], device) * 0.5,
+        Tensor::from_data(TensorData::new(raw_opacities.to_vec(), [n]), device),
+    )
+}
+
+/// Weighted sum of the depth channel, so every pixel gets a different gradient.
+fn depth_loss(img: Tensor<DiffBack, 3>, weights: &Tensor<DiffBack, 3>) -> Tensor<DiffBack, 1> {
+    (img.slice(s![.., .., 4..5]) * weights.clone()).sum()
+}
+
+fn to_vec(tensor: Tensor<DiffBack, 1>) -> Result<Vec<f32>> {
+    tensor
+        .into_data()
+        .into_vec::<f32>()
+        .ok()
+        .context("Wrong tensor type")
+}
+
+#[tokio::test]
+async fn depth_channel_is_alpha_weighted_depth() -> Result<()> {
+    let device = WgpuDevice::DefaultDevice;
+    let (means, log_scales, raw_opacities) = scene_params();
+    // Only keep the first splat.
+    let splats = make_splats(&means[..3], &log_scales[..3], &raw_opacities[..1], &device);
+
+    let img = render_splats(&splats);
+    assert_eq!(img.dims(), [IMG_SIZE as usize, IMG_SIZE as usize, 5]);
+
+    let center = (IMG_SIZE / 2) as usize;
+    let pixel = img
+        .slice(s![center..center + 1, center..center + 1, ..])
+        .into_data()
+        .into_vec::<f32>()
+        .ok()
+        .context("Wrong tensor type")?;
+    let (alpha, depth) = (pixel[3], pixel[4]);
+    assert!(alpha > 0.0, "Splat must be visible");
+    // The first splat is 4.5 units in front of the camera.
+    assert!(
+        (depth / alpha - 4.5).abs() < 1e-3,
+        "Expected depth {} doesn't match splat depth",
+        depth / alpha
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn depth_grads_match_finite_differences() -> Result<()> {
+    let device = WgpuDevice::DefaultDevice;
+    let (means, log_scales, raw_opacities) = scene_params();
+
+    let weights = Tensor::<DiffBack, 3>::random(
+        [IMG_SIZE as usize, IMG_SIZE as usize, 1],
+        Distribution::Uniform(0.0, 1.0),
+        &device,
+    );
+
+    let splats = make_splats(&means, &log_scales, &raw_opacities, &device);
+    let loss = depth_loss(render_splats(&splats), &weights);
+    let grads = loss.backward();
+
+    let v_means = to_vec(
+        splats
+            .means
+            .grad(&grads)
+            .context("means grad")?
+            .flatten(0, 1),
+    )?;
+    let v_scales = to_vec(
+        splats
+            .log_scales
+            .grad(&grads)
+            .context("scales grad")?
+            .flatten(0, 1),
+    )?;
+    let v_opac = to_vec(splats.raw_opacity.grad(&grads).context("opacity grad")?)?;
+
+    let eval = |means: &[f32], log_scales: &[f32], raw_opacities: &[f32]| -> Result<f32> {
+        let splats = make_splats(means, log_scales, raw_opacities, &device);
+        let loss = depth_loss(render_splats(&splats), &weights);
+        Ok(to_vec(loss)?[0])
+    };
+
+    // Central differences, large enough to not drown in f32 rounding of the summed loss.
+    let eps = 1e-2;
+    let mut checks = vec![];
+    for i in 0..means.len() {
+        let mut plus = means.clone();
+        let mut minus = means.clone();
+        plus[i] += eps;
+        minus[i] -= eps;
+        let fd = (eval(&plus, &log_scales, &raw_opacities)?
+            - eval(&minus, &log_scales, &raw_opacities)?)
+            / (2.0 * eps);
+        checks.push(("mean", i, v_means[i], fd));
+    }
+    for i in 0..log_scales.len() {
+        let mut plus = log_scales.clone();
+        let mut minus = log_scales.clone();
+        plus[i] += eps;
+        minus[i] -= eps;
+        let fd = (eval(&means, &plus, &raw_opacities)? - eval(&means, &minus, &raw_opacities)?)
+            / (2.0 * eps);
+        checks.push(("log scale", i, v_scales[i], fd));
+    }
+    for i in 0..raw_opacities.len() {
+        let mut plus = raw_opacities.clone();
+        let mut minus = raw_opacities.clone();
+        plus[i] += eps;
+        minus[i] -= eps;
+        let fd =
+            (eval(&means, &log_scales, &plus)? - eval(&means, &log_scales, &minus)?) / (2.0 * eps);
+        checks.push(("opacity", i, v_opac[i], fd));
+    }
+
+    // Compare relative to the largest gradient, as tiny gradients are dominated by noise.
+    let max_grad = checks
+        .iter()
+        .map(|(_, _, _, fd)| fd.abs())
+        .fold(0.0f32, f32::max);
+    assert!(max_grad > 0.0, "Depth must have a gradient");
+
+    for (name, i, analytic, fd) in checks {
+        assert!(
+            (analytic - fd).abs() < 0.05 * fd.abs() + 0.01 * max_grad,
+            "Gradient of {name} {i} is {analytic}, finite differences give {fd}"
+        );
+    }
+    Ok(())
+}
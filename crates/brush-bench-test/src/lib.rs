@@ -1,4 +1,8 @@
 #![cfg(test)]
 
+mod depth_grads;
+mod masked_grads;
+mod opacity_grads;
 mod reference;
 mod safetensor_utils;
+mod sh_bands;
@@ -0,0 +1,131 @@
+use anyhow::{Context, Result};
+use brush_render::{
+    camera::Camera,
+    gaussian_splats::Splats,
+    render_options::{PixelRect, RenderOptions},
+};
+use brush_render_bwd::burn_glue::SplatForwardDiff;
+use burn::{
+    backend::{Autodiff, Wgpu, wgpu::WgpuDevice},
+    tensor::{Distribution, Tensor, TensorPrimitive, s},
+};
+
+type DiffBack = Autodiff<Wgpu>;
+
+const IMG_SIZE: glam::UVec2 = glam::uvec2(64, 48);
+
+fn random_splats(device: &WgpuDevice) -> Splats<DiffBack> {
+    let num_splats = 64;
+    let means = Tensor::random([num_splats, 3], Distribution::Uniform(-1.0, 1.0), device);
+    let rotation = Tensor::random([num_splats, 4], Distribution::Normal(0.0, 1.0), device);
+    let log_scales = Tensor::random([num_splats, 3], Distribution::Uniform(-3.0, -1.5), device);
+    let sh_coeffs = Tensor::random([num_splats, 1, 3], Distribution::Uniform(-0.5, 0.5), device);
+    let raw_opacity = Tensor::random([num_splats], Distribution::Uniform(-1.0, 2.0), device);
+    Splats::from_tensor_data(means, rotation, log_scales, sh_coeffs, raw_opacity)
+}
+
+/// Gradients of the means and colors of a weighted sum of the rendered image.
+fn grads(
+    splats: &Splats<DiffBack>,
+    weights: &Tensor<DiffBack, 3>,
+    backward_region: Option<PixelRect>,
+) -> Result<Vec<Tensor<DiffBack, 1>>> {
+    let diff_out = DiffBack::render_splats(
+        &Camera::new(
+            glam::vec3(0.0, 0.0, -3.0),
+            glam::Quat::IDENTITY,
+            0.8,
+            0.6,
+            glam::vec2(0.5, 0.5),
+        ),
+        IMG_SIZE,
+        splats.means.val().into_primitive().tensor(),
+        splats.log_scales.val().into_primitive().tensor(),
+        splats.rotation.val().into_primitive().tensor(),
+        splats.sh_coeffs.val().into_primitive().tensor(),
+        splats.opacities().into_primitive().tensor(),
+        &RenderOptions {
+            backward_region,
+            ..Default::default()
+        },
+    );
+    let img: Tensor<DiffBack, 3> = Tensor::from_primitive(TensorPrimitive::Float(diff_out.img));
+    let grads = (img * weights.clone()).sum().backward();
+    Ok(vec![
+        splats
+            .means
+            .grad(&grads)
+            .context("means grad")?
+            .flatten(0, 1),
+        splats
+            .sh_coeffs
+            .grad(&grads)
+            .context("coeffs grad")?
+            .flatten(0, 2),
+        splats.raw_opacity.grad(&grads).context("opacity grad")?,
+    ])
+}
+
+async fn max_diff(a: Vec<Tensor<DiffBack, 1>>, b: Vec<Tensor<DiffBack, 1>>) -> f32 {
+    let mut max = 0.0f32;
+    for (a, b) in a.into_iter().zip(b) {
+        max = max.max((a - b).abs().max().into_scalar_async().await);
+    }
+    max
+}
+
+#[tokio::test]
+async fn full_backward_region_matches_unmasked() -> Result<()> {
+    let device = WgpuDevice::DefaultDevice;
+    let splats = random_splats(&device);
+    let weights = Tensor::random(
+        [IMG_SIZE.y as usize, IMG_SIZE.x as usize, 4],
+        Distribution::Uniform(0.0, 1.0),
+        &device,
+    );
+
+    let unmasked = grads(&splats, &weights, None)?;
+    let full = grads(&splats, &weights, Some(PixelRect::full(IMG_SIZE)))?;
+    let diff = max_diff(unmasked, full).await;
+    assert!(
+        diff < 1e-6,
+        "A full image region must match the unmasked gradients, max diff {diff}"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn backward_region_ignores_pixels_outside() -> Result<()> {
+    let device = WgpuDevice::DefaultDevice;
+    let splats = random_splats(&device);
+    let weights = Tensor::random(
+        [IMG_SIZE.y as usize, IMG_SIZE.x as usize, 4],
+        Distribution::Uniform(0.0, 1.0),
+        &device,
+    );
+
+    // A region that doesn't line up with the tiles, so some tiles are partially inside.
+    let region = PixelRect::new(glam::uvec2(10, 6), glam::uvec2(41, 29));
+    let masked_weights = Tensor::zeros(weights.dims(), &device).slice_assign(
+        s![6..29, 10..41, ..],
+        weights.clone().slice(s![6..29, 10..41, ..]),
+    );
+
+    let region_grads = grads(&splats, &weights, Some(region))?;
+    let masked_loss_grads = grads(&splats, &masked_weights, None)?;
+    let total = region_grads
+        .iter()
+        .map(|grad| grad.clone().abs().sum())
+        .reduce(|a, b| a + b)
+        .context("No gradients")?
+        .into_scalar_async()
+        .await;
+    assert!(total > 0.0, "Pixels in the region must have gradients");
+
+    let diff = max_diff(region_grads, masked_loss_grads).await;
+    assert!(
+        diff < 1e-5,
+        "A backward region must match zeroing the loss outside of it, max diff {diff}"
+    );
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use anyhow::{Context, Result};
+use brush_render::{
+    camera::Camera,
+    gaussian_splats::{OpacityActivation, Splats},
+    render_options::RenderOptions,
+};
+use brush_render_bwd::burn_glue::SplatForwardDiff;
+use burn::{
+    backend::{Autodiff, Wgpu, wgpu::WgpuDevice},
+    tensor::{Distribution, Tensor, TensorPrimitive},
+};
+
+type DiffBack = Autodiff<Wgpu>;
+
+const IMG_SIZE: glam::UVec2 = glam::uvec2(64, 48);
+
+/// Render a weighted sum of the image, either activating the opacities in the render kernels or
+/// before rendering. Returns the sum and the gradient of the raw opacities.
+async fn render_opacity_grad(
+    splats: &Splats<DiffBack>,
+    weights: &Tensor<DiffBack, 3>,
+    in_kernel: bool,
+) -> Result<(f32, Tensor<Wgpu, 1>)> {
+    let activation = splats.opacity_activation.0;
+    let (opacities, opacity_activation) = if in_kernel {
+        (splats.raw_opacity.val(), Some(activation))
+    } else {
+        (splats.opacities(), None)
+    };
+    let diff_out = DiffBack::render_splats(
+        &Camera::new(
+            glam::vec3(0.0, 0.0, -3.0),
+            glam::Quat::IDENTITY,
+            0.8,
+            0.6,
+            glam::vec2(0.5, 0.5),
+        ),
+        IMG_SIZE,
+        splats.means.val().into_primitive().tensor(),
+        splats.log_scales.val().into_primitive().tensor(),
+        splats.rotation.val().into_primitive().tensor(),
+        splats.sh_coeffs.val().into_primitive().tensor(),
+        opacities.into_primitive().tensor(),
+        &RenderOptions {
+            opacity_activation,
+            ..Default::default()
+        },
+    );
+    let img: Tensor<DiffBack, 3> = Tensor::from_primitive(TensorPrimitive::Float(diff_out.img));
+    let loss = (img * weights.clone()).sum();
+    let grads = loss.backward();
+    let grad = splats.raw_opacity.grad(&grads).context("opacity grad")?;
+    Ok((loss.into_scalar_async().await, grad))
+}
+
+#[tokio::test]
+async fn kernel_activation_matches_burn_activation() -> Result<()> {
+    let device = WgpuDevice::DefaultDevice;
+    let num_splats = 64;
+    let weights = Tensor::random(
+        [IMG_SIZE.y as usize, IMG_SIZE.x as usize, 4],
+        Distribution::Uniform(0.0, 1.0),
+        &device,
+    );
+
+    for activation in [OpacityActivation::Sigmoid, OpacityActivation::ExpDensity] {
+        let splats = Splats::<DiffBack>::from_tensor_data(
+            Tensor::random([num_splats, 3], Distribution::Uniform(-1.0, 1.0), &device),
+            Tensor::random([num_splats, 4], Distribution::Normal(0.0, 1.0), &device),
+            Tensor::random([num_splats, 3], Distribution::Uniform(-3.0, -1.5), &device),
+            Tensor::random(
+                [num_splats, 1, 3],
+                Distribution::Uniform(-0.5, 0.5),
+                &device,
+            ),
+            Tensor::random([num_splats], Distribution::Uniform(-2.0, 2.0), &device),
+        )
+        .with_opacity_activation(activation);
+
+        let (kernel_loss, kernel_grad) = render_opacity_grad(&splats, &weights, true).await?;
+        let (burn_loss, burn_grad) = render_opacity_grad(&splats, &weights, false).await?;
+
+        assert!(
+            (kernel_loss - burn_loss).abs() <= burn_loss.abs() * 1e-5,
+            "{activation:?} render differs, {kernel_loss} vs {burn_loss}"
+        );
+        let diff: f32 = (kernel_grad - burn_grad.clone())
+            .abs()
+            .max()
+            .into_scalar_async()
+            .await;
+        let scale: f32 = burn_grad.abs().max().into_scalar_async().await;
+        assert!(scale > 0.0, "Opacities must have gradients");
+        assert!(
+            diff <= scale * 1e-4,
+            "{activation:?} opacity gradients differ by {diff}"
+        );
+    }
+    Ok(())
+}
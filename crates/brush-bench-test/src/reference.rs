@@ -2,6 +2,7 @@ use anyhow::{Context, Result};
 use brush_render::{
     camera::{Camera, focal_to_fov, fov_to_focal},
     gaussian_splats::Splats,
+    render_options::RenderOptions,
 };
 use brush_render_bwd::burn_glue::SplatForwardDiff;
 use brush_rerun::burn_to_rerun::{BurnToImage, BurnToRerun};
@@ -130,6 +131,7 @@ async fn test_reference() -> Result<()> {
             splats.rotation.val().into_primitive().tensor(),
             splats.sh_coeffs.val().into_primitive().tensor(),
             splats.opacities().into_primitive().tensor(),
+            &RenderOptions::default(),
         );
 
         let (out, aux) = (
@@ -8,8 +8,11 @@ use std::path::Path;
 use std::{fs::File, io::Read};
 
 use brush_render::{
+    MainBackendBase, SplatForward,
     camera::{Camera, focal_to_fov, fov_to_focal},
     gaussian_splats::Splats,
+    render::{RenderContext, RenderInput, render_forward_with_context},
+    render_options::{RenderOptions, SortAlgorithm, TransmittanceThreshold},
 };
 use brush_render_bwd::burn_glue::SplatForwardDiff;
 use burn::backend::wgpu::WgpuDevice;
@@ -31,6 +34,10 @@ const DENSE_MULT: f32 = 0.25;
 const LOW_RES: glam::UVec2 = glam::uvec2(512, 512);
 const HIGH_RES: glam::UVec2 = glam::uvec2(1024, 1024);
 
+// Small enough that the renders of the sort benchmarks fit the bitonic sort.
+const SORT_RES: glam::UVec2 = glam::uvec2(64, 64);
+const SORT_SPLAT_COUNTS: [usize; 4] = [10, 50, 150, 300];
+
 const TARGET_SAMPLE_COUNT: u32 = 50;
 const INTERNAL_ITERS: u32 = 5;
 
@@ -181,6 +188,7 @@ fn bench_general(
                     splats.rotation.val().into_primitive().tensor(),
                     splats.sh_coeffs.val().into_primitive().tensor(),
                     splats.opacities().into_primitive().tensor(),
+                    &RenderOptions::default(),
                 );
                 let img: Tensor<DiffBack, 3> =
                     Tensor::from_primitive(TensorPrimitive::Float(diff_out.img));
@@ -203,6 +211,239 @@ fn bench_general(
     }
 }
 
+/// Render a static scene repeatedly, like a viewer would, with or without reusing a [`RenderContext`].
+fn bench_context(bencher: divan::Bencher, dens: f32, reuse_context: bool) {
+    if !Path::new("./test_cases/bench_data.safetensors").exists() {
+        generate_bench_data().expect("Failed to generate bench data");
+    }
+
+    let device = WgpuDevice::DefaultDevice;
+    let mut buffer = Vec::new();
+    let _ = File::open("./test_cases/bench_data.safetensors")
+        .expect("Failed to open bench data")
+        .read_to_end(&mut buffer)
+        .expect("Failed to read bench data");
+    let tensors = SafeTensors::deserialize(&buffer).expect("Failed to deserialize bench data");
+    let splats: Splats<MainBackendBase> =
+        splats_from_safetensors(&tensors, &device).expect("Failed to load bench data");
+    let num_points = (splats.num_splats() as f32 * dens) as usize;
+    let means = splats.means.val().slice([0..num_points]);
+    let log_scales = splats.log_scales.val().slice([0..num_points]);
+    let quats = splats.rotation.val().slice([0..num_points]);
+    let sh_coeffs = splats.sh_coeffs.val().slice([0..num_points]);
+    let opacities = splats.opacities().slice([0..num_points]);
+
+    let [w, h] = LOW_RES.into();
+    let fov = std::f64::consts::PI * 0.5;
+    let focal = fov_to_focal(fov, w);
+    let camera = Camera::new(
+        glam::vec3(0.0, 0.0, -8.0),
+        glam::Quat::IDENTITY,
+        focal_to_fov(focal, w),
+        focal_to_fov(focal, h),
+        glam::vec2(0.5, 0.5),
+    );
+
+    let mut context = RenderContext::new();
+
+    bencher.bench_local(move || {
+        for _ in 0..INTERNAL_ITERS {
+            if !reuse_context {
+                context = RenderContext::new();
+            }
+            let input = RenderInput::new(
+                means.clone().into_primitive().tensor(),
+                log_scales.clone().into_primitive().tensor(),
+                quats.clone().into_primitive().tensor(),
+                sh_coeffs.clone().into_primitive().tensor(),
+                opacities.clone().into_primitive().tensor(),
+            );
+            let _ = render_forward_with_context(
+                &mut context,
+                &camera,
+                LOW_RES,
+                input,
+                false,
+                &RenderOptions::default(),
+            );
+        }
+        // Wait for GPU work.
+        <MainBackendBase as burn::prelude::Backend>::sync(&device);
+    });
+}
+
+/// Render a scene with degree 3 spherical harmonics, with or without flat colors.
+fn bench_sh(bencher: divan::Bencher, dens: f32, flat_color: bool) {
+    if !Path::new("./test_cases/bench_data.safetensors").exists() {
+        generate_bench_data().expect("Failed to generate bench data");
+    }
+
+    let device = WgpuDevice::DefaultDevice;
+    let mut buffer = Vec::new();
+    let _ = File::open("./test_cases/bench_data.safetensors")
+        .expect("Failed to open bench data")
+        .read_to_end(&mut buffer)
+        .expect("Failed to read bench data");
+    let tensors = SafeTensors::deserialize(&buffer).expect("Failed to deserialize bench data");
+    let splats: Splats<MainBackendBase> =
+        splats_from_safetensors(&tensors, &device).expect("Failed to load bench data");
+    let num_points = (splats.num_splats() as f32 * dens) as usize;
+    let means = splats.means.val().slice([0..num_points]);
+    let log_scales = splats.log_scales.val().slice([0..num_points]);
+    let quats = splats.rotation.val().slice([0..num_points]);
+    let opacities = splats.opacities().slice([0..num_points]);
+
+    // The bench data only has a base color, add the higher bands.
+    let sh_coeffs = Tensor::cat(
+        vec![
+            splats.sh_coeffs.val().slice([0..num_points]),
+            Tensor::random(
+                [num_points, 15, 3],
+                burn::tensor::Distribution::Normal(0.0, 0.1),
+                &device,
+            ),
+        ],
+        1,
+    );
+
+    let [w, h] = LOW_RES.into();
+    let fov = std::f64::consts::PI * 0.5;
+    let focal = fov_to_focal(fov, w);
+    let camera = Camera::new(
+        glam::vec3(0.0, 0.0, -8.0),
+        glam::Quat::IDENTITY,
+        focal_to_fov(focal, w),
+        focal_to_fov(focal, h),
+        glam::vec2(0.5, 0.5),
+    );
+    let options = RenderOptions {
+        max_sh_degree: flat_color.then_some(0),
+        ..Default::default()
+    };
+
+    bencher.bench_local(move || {
+        for _ in 0..INTERNAL_ITERS {
+            let _ = MainBackendBase::render_splats(
+                &camera,
+                LOW_RES,
+                means.clone().into_primitive().tensor(),
+                log_scales.clone().into_primitive().tensor(),
+                quats.clone().into_primitive().tensor(),
+                sh_coeffs.clone().into_primitive().tensor(),
+                opacities.clone().into_primitive().tensor(),
+                false,
+                &options,
+            );
+        }
+        // Wait for GPU work.
+        <MainBackendBase as burn::prelude::Backend>::sync(&device);
+    });
+}
+
+/// Render the full scene from close by, where many pixels are covered by dense splats, stopping
+/// pixels at the given transmittance.
+fn bench_transmittance(bencher: divan::Bencher, threshold: f32) {
+    if !Path::new("./test_cases/bench_data.safetensors").exists() {
+        generate_bench_data().expect("Failed to generate bench data");
+    }
+
+    let device = WgpuDevice::DefaultDevice;
+    let mut buffer = Vec::new();
+    let _ = File::open("./test_cases/bench_data.safetensors")
+        .expect("Failed to open bench data")
+        .read_to_end(&mut buffer)
+        .expect("Failed to read bench data");
+    let tensors = SafeTensors::deserialize(&buffer).expect("Failed to deserialize bench data");
+    let splats: Splats<MainBackendBase> =
+        splats_from_safetensors(&tensors, &device).expect("Failed to load bench data");
+
+    let [w, h] = LOW_RES.into();
+    let fov = std::f64::consts::PI * 0.5;
+    let focal = fov_to_focal(fov, w);
+    let camera = Camera::new(
+        glam::vec3(0.0, 0.0, -8.0),
+        glam::Quat::IDENTITY,
+        focal_to_fov(focal, w),
+        focal_to_fov(focal, h),
+        glam::vec2(0.5, 0.5),
+    );
+    let options = RenderOptions {
+        transmittance_threshold: Some(
+            TransmittanceThreshold::new(threshold).expect("Invalid transmittance threshold"),
+        ),
+        ..Default::default()
+    };
+
+    bencher.bench_local(move || {
+        for _ in 0..INTERNAL_ITERS {
+            let _ = splats.render_with_options(&camera, LOW_RES, false, &options);
+        }
+        // Wait for GPU work.
+        <MainBackendBase as burn::prelude::Backend>::sync(&device);
+    });
+}
+
+/// Render a handful of random splats to a small image, where the sort of the few intersections
+/// is a large part of the render, to compare sort algorithms.
+fn bench_sort(bencher: divan::Bencher, num_splats: usize, sort_algorithm: SortAlgorithm) {
+    let device = WgpuDevice::DefaultDevice;
+    <MainBackendBase as burn::prelude::Backend>::seed(4);
+
+    let means = Tensor::<MainBackendBase, 2>::random(
+        [num_splats, 3],
+        burn::tensor::Distribution::Uniform(-2.0, 2.0),
+        &device,
+    );
+    let log_scales = Tensor::<MainBackendBase, 2>::random(
+        [num_splats, 3],
+        burn::tensor::Distribution::Uniform(-3.0, -1.0),
+        &device,
+    );
+    let quats = Tensor::<MainBackendBase, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+        .unsqueeze_dim::<2>(0)
+        .repeat_dim(0, num_splats);
+    let sh_coeffs = Tensor::<MainBackendBase, 3>::random(
+        [num_splats, 1, 3],
+        burn::tensor::Distribution::Uniform(0.0, 1.0),
+        &device,
+    );
+    let opacities = Tensor::<MainBackendBase, 1>::random(
+        [num_splats],
+        burn::tensor::Distribution::Uniform(0.0, 1.0),
+        &device,
+    );
+
+    let camera = Camera::new(
+        glam::vec3(0.0, 0.0, -8.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let options = RenderOptions {
+        sort_algorithm,
+        ..Default::default()
+    };
+
+    bencher.bench_local(move || {
+        for _ in 0..INTERNAL_ITERS {
+            let _ = MainBackendBase::render_splats(
+                &camera,
+                SORT_RES,
+                means.clone().into_primitive().tensor(),
+                log_scales.clone().into_primitive().tensor(),
+                quats.clone().into_primitive().tensor(),
+                sh_coeffs.clone().into_primitive().tensor(),
+                opacities.clone().into_primitive().tensor(),
+                false,
+                &options,
+            );
+        }
+        // Wait for GPU work.
+        <MainBackendBase as burn::prelude::Backend>::sync(&device);
+    });
+}
+
 #[divan::bench_group(max_time = 1000, sample_count = TARGET_SAMPLE_COUNT, sample_size = 1)]
 mod fwd {
     use crate::{BENCH_DENSITIES, DENSE_MULT, HIGH_RES, LOW_RES, bench_general};
@@ -242,3 +483,60 @@ mod bwd {
         bench_general(bencher, dens, 1.0, HIGH_RES, true);
     }
 }
+
+#[divan::bench_group(max_time = 20, sample_count = TARGET_SAMPLE_COUNT, sample_size = 1)]
+mod context {
+    use crate::{BENCH_DENSITIES, bench_context};
+
+    #[divan::bench(args = BENCH_DENSITIES)]
+    fn fresh(bencher: divan::Bencher, dens: f32) {
+        bench_context(bencher, dens, false);
+    }
+
+    #[divan::bench(args = BENCH_DENSITIES)]
+    fn reused(bencher: divan::Bencher, dens: f32) {
+        bench_context(bencher, dens, true);
+    }
+}
+
+#[divan::bench_group(max_time = 20, sample_count = TARGET_SAMPLE_COUNT, sample_size = 1)]
+mod sh {
+    use crate::{BENCH_DENSITIES, bench_sh};
+
+    #[divan::bench(args = BENCH_DENSITIES)]
+    fn full(bencher: divan::Bencher, dens: f32) {
+        bench_sh(bencher, dens, false);
+    }
+
+    #[divan::bench(args = BENCH_DENSITIES)]
+    fn flat(bencher: divan::Bencher, dens: f32) {
+        bench_sh(bencher, dens, true);
+    }
+}
+
+#[divan::bench_group(max_time = 20, sample_count = TARGET_SAMPLE_COUNT, sample_size = 1)]
+mod transmittance {
+    use crate::bench_transmittance;
+
+    #[divan::bench(args = [1e-4, 1e-3, 1e-2, 5e-2])]
+    fn threshold(bencher: divan::Bencher, threshold: f32) {
+        bench_transmittance(bencher, threshold);
+    }
+}
+
+#[divan::bench_group(max_time = 20, sample_count = TARGET_SAMPLE_COUNT, sample_size = 1)]
+mod sort {
+    use brush_render::render_options::SortAlgorithm;
+
+    use crate::{SORT_SPLAT_COUNTS, bench_sort};
+
+    #[divan::bench(args = SORT_SPLAT_COUNTS)]
+    fn radix(bencher: divan::Bencher, num_splats: usize) {
+        bench_sort(bencher, num_splats, SortAlgorithm::Radix);
+    }
+
+    #[divan::bench(args = SORT_SPLAT_COUNTS)]
+    fn bitonic(bencher: divan::Bencher, num_splats: usize) {
+        bench_sort(bencher, num_splats, SortAlgorithm::Bitonic);
+    }
+}
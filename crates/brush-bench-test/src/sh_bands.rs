@@ -0,0 +1,64 @@
+use anyhow::{Context, Result};
+use brush_render::{camera::Camera, gaussian_splats::Splats, render_options::RenderOptions};
+use brush_render_bwd::burn_glue::SplatForwardDiff;
+use burn::{
+    backend::{Autodiff, Wgpu, wgpu::WgpuDevice},
+    tensor::{Distribution, Tensor, TensorPrimitive, s},
+};
+
+type DiffBack = Autodiff<Wgpu>;
+
+#[tokio::test]
+async fn inactive_sh_bands_have_no_grad() -> Result<()> {
+    let device = WgpuDevice::DefaultDevice;
+    let num_splats = 64;
+
+    // Splats with degree 1 SH, rendered with only the base color active.
+    let means = Tensor::random([num_splats, 3], Distribution::Uniform(-1.0, 1.0), &device);
+    let rotation = Tensor::<DiffBack, 2>::zeros([num_splats, 4], &device)
+        .slice_assign(s![.., 0], Tensor::ones([num_splats, 1], &device));
+    let log_scales = Tensor::ones([num_splats, 3], &device) * -2.0;
+    let sh_coeffs = Tensor::random([num_splats, 4, 3], Distribution::Normal(0.0, 0.5), &device);
+    let raw_opacity = Tensor::zeros([num_splats], &device);
+    let splats: Splats<DiffBack> =
+        Splats::from_tensor_data(means, rotation, log_scales, sh_coeffs, raw_opacity);
+
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -5.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let diff_out = DiffBack::render_splats(
+        &cam,
+        glam::uvec2(64, 64),
+        splats.means.val().into_primitive().tensor(),
+        splats.log_scales.val().into_primitive().tensor(),
+        splats.rotation.val().into_primitive().tensor(),
+        splats.sh_coeffs.val().into_primitive().tensor(),
+        splats.opacities().into_primitive().tensor(),
+        &RenderOptions {
+            max_sh_degree: Some(0),
+            ..Default::default()
+        },
+    );
+    let img: Tensor<DiffBack, 3> = Tensor::from_primitive(TensorPrimitive::Float(diff_out.img));
+    let grads = img.mean().backward();
+
+    let v_coeffs = splats.sh_coeffs.grad(&grads).context("coeffs grad")?;
+
+    let dc_grad = v_coeffs.clone().slice(s![.., 0..1, ..]).abs().sum();
+    let dc_grad = dc_grad.into_scalar_async().await;
+    assert!(dc_grad > 0.0, "Base color should receive gradients");
+
+    let band_grad = v_coeffs.slice(s![.., 1..4, ..]).abs().sum();
+    let band_grad = band_grad.into_scalar_async().await;
+    assert!(
+        band_grad < 1e-12,
+        "Inactive SH bands should not receive gradients"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,182 @@
+//! Render turntables or flythroughs: interpolate cameras between keyframes, and write the renders
+//! as an image sequence, or encode them to a video with the `video` feature.
+
+use brush_render::camera::Camera;
+#[cfg(not(target_family = "wasm"))]
+use brush_render::{SplatForward, gaussian_splats::Splats, render_options::RenderOptions};
+#[cfg(not(target_family = "wasm"))]
+use burn::prelude::Backend;
+
+/// Number of frames a path of `duration` seconds has at `fps`.
+pub fn num_frames(fps: f32, duration: f32) -> usize {
+    (fps * duration).round().max(0.0) as usize
+}
+
+/// The cameras of each frame of a path through `keyframes`, at `fps` frames per second for
+/// `duration` seconds.
+///
+/// Keyframes are spaced evenly in time, the first frame is the first keyframe and the last frame
+/// is the last keyframe. Cameras in between are interpolated with [`Camera::lerp`]. For a closed
+/// loop like a turntable, end the keyframes with the first keyframe again.
+pub fn interpolate_path(keyframes: &[Camera], fps: f32, duration: f32) -> Vec<Camera> {
+    let frames = num_frames(fps, duration);
+    let Some(first) = keyframes.first() else {
+        return vec![];
+    };
+    if keyframes.len() == 1 || frames == 1 {
+        return vec![first.clone(); frames];
+    }
+
+    let segments = (keyframes.len() - 1) as f32;
+    (0..frames)
+        .map(|frame| {
+            let s = frame as f32 / (frames - 1) as f32 * segments;
+            let segment = (s.floor() as usize).min(keyframes.len() - 2);
+            keyframes[segment].lerp(&keyframes[segment + 1], s - segment as f32)
+        })
+        .collect()
+}
+
+/// File name of a frame in an image sequence, with `extension` like `png`. Frame numbers are
+/// padded so the files sort in order.
+pub fn frame_file_name(frame: usize, extension: &str) -> String {
+    format!("frame_{frame:05}.{extension}")
+}
+
+/// Render each camera, and write the renders to `dir` as an image sequence, named by
+/// [`frame_file_name`], in the format of `extension`. See [`crate::image_export::save_render`].
+/// Frames are rendered to float images, so they keep the full precision of the render.
+///
+/// NB: This doesn't work on a differentiable backend.
+#[cfg(not(target_family = "wasm"))]
+pub async fn render_image_sequence<B: Backend + SplatForward<B>>(
+    splats: &Splats<B>,
+    cameras: &[Camera],
+    img_size: glam::UVec2,
+    options: &RenderOptions,
+    dir: &std::path::Path,
+    extension: &str,
+) -> Result<(), crate::image_export::ImageExportError> {
+    use crate::image_export::{RenderChannel, save_render};
+
+    for (frame, camera) in cameras.iter().enumerate() {
+        let (img, _) = splats.render_with_options(camera, img_size, true, options);
+        let data = img.into_data_async().await;
+        let path = dir.join(frame_file_name(frame, extension));
+        save_render(data, options, RenderChannel::Color, &path).await?;
+    }
+    Ok(())
+}
+
+/// Encode an image sequence written by [`render_image_sequence`] to a video at `output`, with
+/// the `ffmpeg` executable. The container and codec are picked by ffmpeg from the extension of
+/// `output`.
+#[cfg(all(feature = "video", not(target_family = "wasm")))]
+pub async fn encode_video(
+    dir: &std::path::Path,
+    extension: &str,
+    fps: f32,
+    output: &std::path::Path,
+) -> Result<(), crate::image_export::ImageExportError> {
+    use crate::image_export::ImageExportError;
+
+    let status = tokio::process::Command::new("ffmpeg")
+        .arg("-y")
+        .args(["-framerate", &fps.to_string()])
+        .arg("-i")
+        .arg(dir.join(format!("frame_%05d.{extension}")))
+        // Most players only support 4:2:0 chroma subsampling.
+        .args(["-pix_fmt", "yuv420p"])
+        .arg(output)
+        .status()
+        .await?;
+    if !status.success() {
+        return Err(ImageExportError::VideoEncoder(status.to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{frame_file_name, interpolate_path, num_frames, render_image_sequence};
+    use brush_render::{
+        MainBackend, camera::Camera, gaussian_splats::Splats, render_options::RenderOptions,
+    };
+    use burn::backend::wgpu::WgpuDevice;
+    use glam::{Quat, Vec3, vec3};
+
+    #[test]
+    fn frame_count_matches_fps_and_duration() {
+        let keyframes = [
+            Camera::look_at(vec3(0.0, 0.0, -4.0), Vec3::ZERO, Vec3::NEG_Y),
+            Camera::look_at(vec3(4.0, 0.0, 0.0), Vec3::ZERO, Vec3::NEG_Y),
+            Camera::look_at(vec3(0.0, 0.0, 4.0), Vec3::ZERO, Vec3::NEG_Y),
+        ];
+        for (fps, duration) in [(30.0, 2.0), (24.0, 0.5), (60.0, 1.25)] {
+            let path = interpolate_path(&keyframes, fps, duration);
+            assert_eq!(path.len(), num_frames(fps, duration));
+            assert_eq!(path.len(), (fps * duration) as usize);
+        }
+
+        let path = interpolate_path(&keyframes, 10.0, 0.9);
+        assert_eq!(path.len(), 9);
+        assert_eq!(path[0].position, keyframes[0].position);
+        assert!(
+            (path[4].position - keyframes[1].position).length() < 1e-5,
+            "The middle frame must be at the middle keyframe"
+        );
+        assert!(
+            (path[8].position - keyframes[2].position).length() < 1e-5
+                && path[8].rotation.angle_between(keyframes[2].rotation) < 1e-4,
+            "The last frame must be the last keyframe"
+        );
+        assert!(interpolate_path(&[], 30.0, 1.0).is_empty());
+    }
+
+    #[test]
+    fn rotations_use_the_short_arc() {
+        let a = Camera::from_position_rotation(Vec3::ZERO, Quat::from_rotation_y(0.1));
+        let b = Camera::from_position_rotation(Vec3::ZERO, -Quat::from_rotation_y(0.3));
+        let path = interpolate_path(&[a, b], 3.0, 1.0);
+        for (frame, camera) in path.iter().enumerate() {
+            let expected = Quat::from_rotation_y(0.1 + 0.1 * frame as f32);
+            assert!(
+                camera.rotation.angle_between(expected) < 1e-4,
+                "Frame {frame} rotated the long way around"
+            );
+        }
+        assert_eq!(frame_file_name(12, "png"), "frame_00012.png");
+    }
+
+    #[tokio::test]
+    async fn image_sequence_writes_frames() {
+        let device = WgpuDevice::DefaultDevice;
+        let splats =
+            Splats::<MainBackend>::from_raw(&[Vec3::ZERO], None, None, None, None, &device);
+        let keyframes = [
+            Camera::look_at(vec3(0.0, 0.0, -4.0), Vec3::ZERO, Vec3::NEG_Y),
+            Camera::look_at(vec3(4.0, 0.0, 0.0), Vec3::ZERO, Vec3::NEG_Y),
+        ];
+        let cameras = interpolate_path(&keyframes, 2.0, 1.0);
+        let dir = std::env::temp_dir().join(format!("brush_sequence_{}", std::process::id()));
+        let img_size = glam::uvec2(16, 12);
+
+        render_image_sequence(
+            &splats,
+            &cameras,
+            img_size,
+            &RenderOptions::default(),
+            &dir,
+            "png",
+        )
+        .await
+        .expect("Failed to render the sequence");
+
+        for frame in 0..cameras.len() {
+            let image = image::open(dir.join(frame_file_name(frame, "png")))
+                .expect("Failed to read a frame back");
+            assert_eq!((image.width(), image.height()), (img_size.x, img_size.y));
+        }
+        std::fs::remove_dir_all(&dir).expect("Failed to clean up frames");
+    }
+}
@@ -27,4 +27,12 @@ pub struct LoadDataseConfig {
     /// Load only every nth point from the initial sfm data
     #[arg(long, help_heading = "Dataset Options")]
     pub subsample_points: Option<u32>,
+    /// Max SH degree of splats loaded from ply files. Higher bands are dropped while loading,
+    /// which saves memory at the cost of view dependent detail.
+    #[arg(long, help_heading = "Dataset Options")]
+    pub max_sh_degree: Option<u32>,
+    /// Max size in MB of decoded images to keep in memory while training. Images are loaded on
+    /// demand, and the least recently used images are evicted. Defaults to 6GB, or 2GB on the web.
+    #[arg(long, help_heading = "Dataset Options")]
+    pub image_cache_mb: Option<usize>,
 }
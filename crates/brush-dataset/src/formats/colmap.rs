@@ -4,14 +4,15 @@ use super::{DataStream, FormatError};
 use crate::{
     Dataset,
     config::LoadDataseConfig,
-    formats::find_mask_path,
+    formats::{find_background_path, find_mask_path},
     scene::{LoadImage, SceneView},
     splat_import::SplatMessage,
 };
 use async_fn_stream::try_fn_stream;
 use brush_render::{
-    camera::{self, Camera},
-    gaussian_splats::Splats,
+    MainBackend,
+    camera::Camera,
+    gaussian_splats::{InitOptions, Splats},
     sh::rgb_to_sh,
 };
 use brush_vfs::BrushVfs;
@@ -19,6 +20,21 @@ use burn::backend::wgpu::WgpuDevice;
 use glam::Vec3;
 use std::collections::HashMap;
 
+/// Initial splats at the COLMAP sfm `points`, with their colors. Scales and opacities are chosen
+/// by `init`.
+fn splats_from_points(
+    points: &[&colmap_reader::Point3D],
+    init: &InitOptions,
+    device: &WgpuDevice,
+) -> Splats<MainBackend> {
+    let positions: Vec<Vec3> = points.iter().map(|p| p.xyz).collect();
+    let colors: Vec<f32> = points
+        .iter()
+        .flat_map(|p| rgb_to_sh(Vec3::from(p.rgb.map(|c| c as f32 / 255.0))).to_array())
+        .collect();
+    Splats::from_raw_with_init(&positions, None, None, Some(&colors), None, init, device)
+}
+
 fn find_mask_and_img(vfs: &BrushVfs, name: &str) -> Option<(PathBuf, Option<PathBuf>)> {
     // Colmap only specifies an image name, not a full path. We brute force
     // search for the image in the archive.
@@ -30,20 +46,19 @@ fn find_mask_and_img(vfs: &BrushVfs, name: &str) -> Option<(PathBuf, Option<Path
     let paths: Vec<_> = vfs.files_ending_in(name).collect();
 
     let mut path_masks = HashMap::new();
-    let mut masks = vec![];
+    let mut sidecars = vec![];
 
-    // First pass: collect images & masks.
+    // First pass: collect images, masks & backgrounds.
     for path in paths {
         let mask = find_mask_path(vfs, &path);
         path_masks.insert(path.clone(), mask.clone());
-        if let Some(mask_path) = mask {
-            masks.push(mask_path);
-        }
+        sidecars.extend(mask);
+        sidecars.extend(find_background_path(vfs, &path));
     }
 
-    // Remove masks from candidates - shouldn't count as an input image.
-    for mask in masks {
-        path_masks.remove(&mask);
+    // Remove masks & backgrounds from candidates - shouldn't count as an input image.
+    for sidecar in sidecars {
+        path_masks.remove(&sidecar);
     }
 
     // Sort and return the first candidate (alphabetically).
@@ -53,6 +68,7 @@ fn find_mask_and_img(vfs: &BrushVfs, name: &str) -> Option<(PathBuf, Option<Path
 pub(crate) async fn load_dataset(
     vfs: Arc<BrushVfs>,
     load_args: &LoadDataseConfig,
+    init: &InitOptions,
     device: &WgpuDevice,
 ) -> Option<Result<(DataStream<SplatMessage>, Dataset), FormatError>> {
     log::info!("Loading colmap dataset");
@@ -67,12 +83,13 @@ pub(crate) async fn load_dataset(
         return None;
     };
 
-    Some(load_dataset_inner(vfs, load_args, device, cam_path, img_path).await)
+    Some(load_dataset_inner(vfs, load_args, init, device, cam_path, img_path).await)
 }
 
 async fn load_dataset_inner(
     vfs: Arc<BrushVfs>,
     load_args: &LoadDataseConfig,
+    init: &InitOptions,
     device: &WgpuDevice,
     cam_path: PathBuf,
     img_path: PathBuf,
@@ -109,12 +126,8 @@ async fn load_dataset_inner(
 
         // Create a future to handle loading the image.
         let focal = cam_data.focal();
-
-        let fovx = camera::focal_to_fov(focal.0, cam_data.width as u32);
-        let fovy = camera::focal_to_fov(focal.1, cam_data.height as u32);
-
         let center = cam_data.principal_point();
-        let center_uv = center / glam::vec2(cam_data.width as f32, cam_data.height as f32);
+        let img_size = glam::uvec2(cam_data.width as u32, cam_data.height as u32);
 
         // If image isn't found, just ignore it. We can still train on the remaining images.
         let Some((path, mask_path)) = find_mask_and_img(&vfs, &img_info.name) else {
@@ -127,12 +140,20 @@ async fn load_dataset_inner(
         let cam_to_world = world_to_cam.inverse();
         let (_, quat, translation) = cam_to_world.to_scale_rotation_translation();
 
-        let camera = Camera::new(translation, quat, fovx, fovy, center_uv);
+        let camera = Camera::from_intrinsics(
+            translation,
+            quat,
+            glam::dvec2(focal.0, focal.1),
+            center,
+            img_size,
+        );
 
         log::info!("Loaded COLMAP image at path {path:?}");
 
-        let load_img =
-            LoadImage::new(vfs.clone(), &path, mask_path, load_args.max_resolution).await?;
+        let background_path = find_background_path(&vfs, &path);
+        let load_img = LoadImage::new(vfs.clone(), &path, mask_path, load_args.max_resolution)
+            .await?
+            .with_background_path(background_path);
 
         let view = SceneView {
             camera,
@@ -152,6 +173,7 @@ async fn load_dataset_inner(
 
     let device = device.clone();
     let load_args = load_args.clone();
+    let init = *init;
     let init_stream = try_fn_stream(|emitter| async move {
         let points_path = { vfs.files_ending_in("points3d.txt").next() }
             .or_else(|| vfs.files_ending_in("points3d.bin").next());
@@ -181,23 +203,8 @@ async fn load_dataset_inner(
                 // do it manually, maybe nice to unify at some point.
                 let step = load_args.subsample_points.unwrap_or(1) as usize;
 
-                let positions: Vec<Vec3> =
-                    points_data.values().step_by(step).map(|p| p.xyz).collect();
-                let colors: Vec<f32> = points_data
-                    .values()
-                    .step_by(step)
-                    .flat_map(|p| {
-                        let sh = rgb_to_sh(glam::vec3(
-                            p.rgb[0] as f32 / 255.0,
-                            p.rgb[1] as f32 / 255.0,
-                            p.rgb[2] as f32 / 255.0,
-                        ));
-                        [sh.x, sh.y, sh.z]
-                    })
-                    .collect();
-
-                let init_splat =
-                    Splats::from_raw(&positions, None, None, Some(&colors), None, &device);
+                let points: Vec<_> = points_data.values().step_by(step).collect();
+                let init_splat = splats_from_points(&points, &init, &device);
                 emitter
                     .emit(SplatMessage {
                         meta: crate::splat_import::ParseMetadata {
@@ -207,6 +214,7 @@ async fn load_dataset_inner(
                             current_frame: 0,
                         },
                         splats: init_splat,
+                        attributes: HashMap::new(),
                     })
                     .await;
             }
@@ -220,3 +228,65 @@ async fn load_dataset_inner(
         Dataset::from_views(train_views, eval_views),
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::splats_from_points;
+    use brush_render::gaussian_splats::{InitOptions, OpacityActivation};
+    use burn::backend::wgpu::WgpuDevice;
+    use colmap_reader::Point3D;
+    use glam::{Vec3, vec3};
+
+    #[test]
+    fn point_cloud_splats_follow_init_options() {
+        let device = WgpuDevice::DefaultDevice;
+        let point = |xyz: Vec3| Point3D {
+            xyz,
+            rgb: [255, 128, 0],
+            error: 0.0,
+            image_ids: vec![],
+            point2d_idxs: vec![],
+        };
+        let points = [
+            point(Vec3::ZERO),
+            point(vec3(2.0, 0.0, 0.0)),
+            point(vec3(5.0, 0.0, 0.0)),
+        ];
+        let init = InitOptions {
+            knn: 1,
+            scale_multiplier: 0.5,
+            fallback_scale: 0.02,
+            opacity: Some(0.3),
+        };
+        let splats = splats_from_points(&points.iter().collect::<Vec<_>>(), &init, &device);
+
+        let log_scales = splats
+            .log_scales
+            .val()
+            .into_data()
+            .to_vec::<f32>()
+            .expect("Wrong tensor type");
+        // The nearest neighbour of the first point is at 2, and of the last at 3.
+        assert!(
+            log_scales[..3].iter().all(|s| s.abs() < 1e-5)
+                && log_scales[6..]
+                    .iter()
+                    .all(|s| (s - 1.5f32.ln()).abs() < 1e-5),
+            "Scales must follow the init options, got {log_scales:?}"
+        );
+
+        let raw_opacities = splats
+            .raw_opacity
+            .val()
+            .into_data()
+            .to_vec::<f32>()
+            .expect("Wrong tensor type");
+        for raw in raw_opacities {
+            let opacity = OpacityActivation::Sigmoid.activate_value(raw);
+            assert!(
+                (opacity - 0.3).abs() < 1e-6,
+                "Opacity must be the init opacity, got {opacity}"
+            );
+        }
+    }
+}
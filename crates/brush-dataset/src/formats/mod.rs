@@ -3,6 +3,7 @@ use crate::{
     config::LoadDataseConfig,
     splat_import::{SplatImportError, SplatMessage, load_splat_from_ply},
 };
+use brush_render::gaussian_splats::InitOptions;
 use brush_vfs::{BrushVfs, DynStream};
 use burn::backend::wgpu::WgpuDevice;
 use path_clean::PathClean;
@@ -43,9 +44,12 @@ pub enum DatasetError {
     FormatNotSupported,
 }
 
+/// Load a dataset, and a stream of its initial splats. Splats created from the point cloud of a
+/// COLMAP dataset are scaled and given opacities by `init`.
 pub async fn load_dataset(
     vfs: Arc<BrushVfs>,
     load_args: &LoadDataseConfig,
+    init: &InitOptions,
     device: &WgpuDevice,
 ) -> Result<(DataStream<SplatMessage>, Dataset), DatasetError> {
     let nerfstudio_fmt = nerfstudio::read_dataset(vfs.clone(), load_args, device).await;
@@ -53,7 +57,7 @@ pub async fn load_dataset(
     let format = if let Some(fmt) = nerfstudio_fmt {
         fmt?
     } else {
-        let Some(stream) = colmap::load_dataset(vfs.clone(), load_args, device).await else {
+        let Some(stream) = colmap::load_dataset(vfs.clone(), load_args, init, device).await else {
             return Err(DatasetError::FormatNotSupported);
         };
         stream?
@@ -73,6 +77,7 @@ pub async fn load_dataset(
         Box::pin(load_splat_from_ply(
             reader,
             load_args.subsample_points,
+            load_args.max_sh_degree,
             device.clone(),
         ))
     } else {
@@ -83,14 +88,24 @@ pub async fn load_dataset(
 }
 
 fn find_mask_path(vfs: &BrushVfs, path: &Path) -> Option<PathBuf> {
+    find_sidecar_path(vfs, path, "masks")
+}
+
+fn find_background_path(vfs: &BrushVfs, path: &Path) -> Option<PathBuf> {
+    find_sidecar_path(vfs, path, "backgrounds")
+}
+
+/// Find a file with the same stem as the image at `path`, in folder `dir` next to the folder
+/// of the image.
+fn find_sidecar_path(vfs: &BrushVfs, path: &Path, dir: &str) -> Option<PathBuf> {
     let parent = path.parent()?.clean();
     let file_stem = path.file_stem()?.to_str()?;
-    let masks_dir = parent.parent()?.join("masks").clean();
+    let sidecar_dir = parent.parent()?.join(dir).clean();
     for candidate in vfs.files_with_stem(file_stem) {
         let Some(file_parent) = candidate.parent() else {
             continue;
         };
-        if file_parent == masks_dir {
+        if file_parent == sidecar_dir {
             return Some(candidate);
         }
     }
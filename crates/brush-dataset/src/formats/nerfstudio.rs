@@ -1,6 +1,6 @@
 use super::DataStream;
 use super::FormatError;
-use super::find_mask_path;
+use super::{find_background_path, find_mask_path};
 use crate::{
     Dataset,
     config::LoadDataseConfig,
@@ -132,7 +132,11 @@ async fn read_transforms_file(
         }
         let mask_path = find_mask_path(&vfs, &path);
 
-        let image = LoadImage::new(vfs.clone(), &path, mask_path, load_args.max_resolution).await;
+        let background_path = find_background_path(&vfs, &path);
+
+        let image = LoadImage::new(vfs.clone(), &path, mask_path, load_args.max_resolution)
+            .await
+            .map(|image| image.with_background_path(background_path));
 
         let image = match image {
             Ok(image) => image,
@@ -286,8 +290,12 @@ async fn read_dataset_inner(
             let ply_data = vfs.reader_at_path(&init_path).await;
 
             if let Ok(ply_data) = ply_data {
-                let splat_stream =
-                    load_splat_from_ply(ply_data, load_args.subsample_points, device.clone());
+                let splat_stream = load_splat_from_ply(
+                    ply_data,
+                    load_args.subsample_points,
+                    load_args.max_sh_degree,
+                    device.clone(),
+                );
 
                 let mut splat_stream = std::pin::pin!(splat_stream);
 
@@ -0,0 +1,413 @@
+//! Write rendered images to PNG, JPEG or EXR files.
+//!
+//! Renders come in a few layouts: packed 8 bit RGBA, float RGBA, or float RGBA with a depth
+//! channel, with colors either sRGB encoded or linear, and premultiplied or straight alpha. This
+//! converts any of them to what each file format expects: PNG and JPEG files hold sRGB colors,
+//! and EXR files hold linear colors for HDR workflows.
+
+use std::io::Cursor;
+use std::path::Path;
+
+use brush_render::{
+    read_image::unpack_rgba_image,
+    render_options::{AlphaMode, ColorSpace, RenderOptions},
+};
+use burn::tensor::{DType, TensorData};
+use image::{DynamicImage, GrayImage, ImageBuffer, ImageFormat, Luma, Rgb32FImage, RgbImage};
+use thiserror::Error;
+
+/// Image file formats renders can be written as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFileFormat {
+    /// 8 bit sRGB with straight alpha, or 16 bit grayscale.
+    Png,
+    /// 8 bit sRGB, composited over black, or 8 bit grayscale.
+    Jpeg,
+    /// 32 bit float linear RGBA with premultiplied alpha, or grayscale as RGB.
+    Exr,
+}
+
+impl ImageFileFormat {
+    /// The format matching the extension of `path`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "png" => Some(Self::Png),
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "exr" => Some(Self::Exr),
+            _ => None,
+        }
+    }
+
+    fn image_format(self) -> ImageFormat {
+        match self {
+            Self::Png => ImageFormat::Png,
+            Self::Jpeg => ImageFormat::Jpeg,
+            Self::Exr => ImageFormat::OpenExr,
+        }
+    }
+}
+
+/// Which part of a render to write.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenderChannel {
+    /// The colors, with alpha if the format supports it.
+    #[default]
+    Color,
+    /// The alpha channel, as a grayscale image.
+    Alpha,
+    /// The depth channel as a grayscale image, for renders with
+    /// [`RenderOptions::render_depth`].
+    ///
+    /// The blended depth is divided by the alpha, to get the expected depth of each pixel, and
+    /// pixels without coverage are zero. EXR files hold the depth as is, other formats are
+    /// normalized by the largest depth, so only hold relative depths. Renders of the disparity
+    /// are written the same way.
+    Depth,
+}
+
+#[derive(Debug, Error)]
+pub enum ImageExportError {
+    #[error("Unsupported image file extension, expected png, jpg or exr.")]
+    UnsupportedFormat,
+
+    #[error("The render has no depth channel.")]
+    NoDepth,
+
+    #[error("Rendered images must have 3 dimensions and 1, 4 or 5 channels, got {0:?}.")]
+    InvalidShape(Vec<usize>),
+
+    #[error("Failed to encode image.")]
+    Image(#[from] image::ImageError),
+
+    #[error("IO error while writing image.")]
+    Io(#[from] std::io::Error),
+
+    #[error("Failed to encode video: ffmpeg exited with {0}.")]
+    VideoEncoder(String),
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    let c = c.max(0.0);
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    let c = c.max(0.0);
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn to_u8(c: f32) -> u8 {
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Convert the output of a render to an image for `format`.
+///
+/// `data` is the rendered image as read back from the GPU, and `options` the options it was
+/// rendered with, which determine the color space, alpha mode and channels of the render. Packed
+/// images always hold sRGB colors.
+pub fn render_to_image(
+    data: TensorData,
+    options: &RenderOptions,
+    channel: RenderChannel,
+    format: ImageFileFormat,
+) -> Result<DynamicImage, ImageExportError> {
+    let [height, width, channels] = data.shape[..] else {
+        return Err(ImageExportError::InvalidShape(data.shape));
+    };
+    if ![1, 4, 5].contains(&channels) {
+        return Err(ImageExportError::InvalidShape(data.shape));
+    }
+    let (width, height) = (width as u32, height as u32);
+
+    let (pixels, color_space) = if channels == 1 {
+        let packed: Vec<u32> = data
+            .as_bytes()
+            .chunks_exact(4)
+            .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        let pixels = unpack_rgba_image(&packed)
+            .into_iter()
+            .map(|c| c as f32 / 255.0)
+            .collect();
+        (pixels, ColorSpace::Srgb)
+    } else {
+        let data = if data.dtype == DType::F32 {
+            data
+        } else {
+            data.convert::<f32>()
+        };
+        let pixels: Vec<f32> = data
+            .into_vec()
+            .expect("Rendered images must be floats or packed");
+        (pixels, options.color_space)
+    };
+    // Packed pixels are unpacked to RGBA.
+    let pixels = pixels.chunks_exact(if channels == 1 { 4 } else { channels });
+
+    let image = match channel {
+        RenderChannel::Color => {
+            let target = if format == ImageFileFormat::Exr {
+                ColorSpace::Linear
+            } else {
+                ColorSpace::Srgb
+            };
+            let encode = |c: f32| match (color_space, target) {
+                (ColorSpace::Srgb, ColorSpace::Linear) => srgb_to_linear(c),
+                (ColorSpace::Linear, ColorSpace::Srgb) => linear_to_srgb(c),
+                _ => c,
+            };
+
+            // Encode straight colors, then premultiply them again for the formats that need it.
+            // JPEG files have no alpha, which is the same as compositing over black.
+            let colors = pixels.map(|px| {
+                let alpha = px[3];
+                let mut rgb = [px[0], px[1], px[2]];
+                if options.alpha_mode == AlphaMode::Premultiplied && alpha > 0.0 {
+                    rgb = rgb.map(|c| c / alpha);
+                }
+                rgb = rgb.map(encode);
+                if format != ImageFileFormat::Png {
+                    rgb = rgb.map(|c| c * alpha);
+                }
+                (rgb, alpha)
+            });
+
+            match format {
+                ImageFileFormat::Png => {
+                    let data = colors
+                        .flat_map(|([r, g, b], alpha)| [r, g, b, alpha].map(to_u8))
+                        .collect();
+                    DynamicImage::ImageRgba8(
+                        ImageBuffer::from_raw(width, height, data).expect("Image size mismatch"),
+                    )
+                }
+                ImageFileFormat::Jpeg => {
+                    let data = colors.flat_map(|(rgb, _)| rgb.map(to_u8)).collect();
+                    DynamicImage::ImageRgb8(
+                        RgbImage::from_raw(width, height, data).expect("Image size mismatch"),
+                    )
+                }
+                ImageFileFormat::Exr => {
+                    let data = colors
+                        .flat_map(|([r, g, b], alpha)| [r, g, b, alpha])
+                        .collect();
+                    DynamicImage::ImageRgba32F(
+                        ImageBuffer::from_raw(width, height, data).expect("Image size mismatch"),
+                    )
+                }
+            }
+        }
+        RenderChannel::Alpha | RenderChannel::Depth => {
+            let values: Vec<f32> = if channel == RenderChannel::Alpha {
+                pixels.map(|px| px[3]).collect()
+            } else {
+                if channels != 5 {
+                    return Err(ImageExportError::NoDepth);
+                }
+                pixels
+                    .map(|px| if px[3] > 0.0 { px[4] / px[3] } else { 0.0 })
+                    .collect()
+            };
+            // Only HDR formats can hold depths as is.
+            let scale = if channel == RenderChannel::Depth && format != ImageFileFormat::Exr {
+                let max = values.iter().copied().fold(0.0, f32::max);
+                if max > 0.0 { 1.0 / max } else { 1.0 }
+            } else {
+                1.0
+            };
+            let values = values.into_iter().map(|v| v * scale);
+
+            match format {
+                ImageFileFormat::Png => {
+                    let data = values
+                        .map(|v| (v.clamp(0.0, 1.0) * f32::from(u16::MAX)).round() as u16)
+                        .collect();
+                    DynamicImage::ImageLuma16(
+                        ImageBuffer::<Luma<u16>, _>::from_raw(width, height, data)
+                            .expect("Image size mismatch"),
+                    )
+                }
+                ImageFileFormat::Jpeg => DynamicImage::ImageLuma8(
+                    GrayImage::from_raw(width, height, values.map(to_u8).collect())
+                        .expect("Image size mismatch"),
+                ),
+                // EXR images are always RGB(A), so repeat the value in each channel.
+                ImageFileFormat::Exr => DynamicImage::ImageRgb32F(
+                    Rgb32FImage::from_raw(width, height, values.flat_map(|v| [v; 3]).collect())
+                        .expect("Image size mismatch"),
+                ),
+            }
+        }
+    };
+    Ok(image)
+}
+
+/// Encode the output of a render as an image file of `format`. See [`render_to_image`].
+pub fn encode_render(
+    data: TensorData,
+    options: &RenderOptions,
+    channel: RenderChannel,
+    format: ImageFileFormat,
+) -> Result<Vec<u8>, ImageExportError> {
+    let image = render_to_image(data, options, channel, format)?;
+    let mut bytes = Cursor::new(vec![]);
+    image.write_to(&mut bytes, format.image_format())?;
+    Ok(bytes.into_inner())
+}
+
+/// Write the output of a render to `path`, in the format matching its extension. See
+/// [`render_to_image`].
+#[cfg(not(target_family = "wasm"))]
+pub async fn save_render(
+    data: TensorData,
+    options: &RenderOptions,
+    channel: RenderChannel,
+    path: &Path,
+) -> Result<(), ImageExportError> {
+    let format = ImageFileFormat::from_path(path).ok_or(ImageExportError::UnsupportedFormat)?;
+    let bytes = encode_render(data, options, channel, format)?;
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(path, bytes).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImageExportError, ImageFileFormat, RenderChannel, encode_render};
+    use brush_render::{
+        read_image::pack_rgba,
+        render_options::{ColorSpace, RenderOptions},
+    };
+    use burn::tensor::{DType, TensorData};
+
+    // A 2x1 image with a half covered red pixel and an opaque gray pixel, premultiplied.
+    const PIXELS: [f32; 10] = [0.5, 0.0, 0.0, 0.5, 2.0, 0.5, 0.5, 0.5, 1.0, 3.0];
+
+    fn decode(bytes: &[u8]) -> image::DynamicImage {
+        image::load_from_memory(bytes).expect("Failed to decode image")
+    }
+
+    #[test]
+    fn packed_and_float_renders_match() {
+        let options = RenderOptions::default();
+        let rgba: Vec<f32> = PIXELS.chunks(5).flat_map(|px| px[..4].to_vec()).collect();
+        let packed: Vec<u32> = rgba
+            .chunks(4)
+            .map(|px| pack_rgba([0, 1, 2, 3].map(|i| (px[i] * 255.0).round() as u8)))
+            .collect();
+
+        let float = encode_render(
+            TensorData::new(rgba, [1, 2, 4]),
+            &options,
+            RenderChannel::Color,
+            ImageFileFormat::Png,
+        )
+        .expect("Failed to encode");
+        let packed = TensorData::new(packed, [1, 2, 1]);
+        assert_eq!(packed.dtype, DType::U32);
+        let packed_alpha = encode_render(
+            packed.clone(),
+            &options,
+            RenderChannel::Alpha,
+            ImageFileFormat::Png,
+        )
+        .expect("Failed to encode");
+        let packed = encode_render(packed, &options, RenderChannel::Color, ImageFileFormat::Png)
+            .expect("Failed to encode");
+
+        let float = decode(&float).to_rgba8();
+        assert_eq!(float, decode(&packed).to_rgba8());
+        let packed_alpha = decode(&packed_alpha).to_luma16();
+        assert_eq!(packed_alpha.get_pixel(0, 0).0, [128 * 257]);
+        assert_eq!(packed_alpha.get_pixel(1, 0).0, [u16::MAX]);
+        // PNG holds straight alpha.
+        assert_eq!(float.get_pixel(0, 0).0, [255, 0, 0, 128]);
+        assert_eq!(float.get_pixel(1, 0).0, [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn exr_holds_linear_colors() {
+        let options = RenderOptions {
+            render_depth: true,
+            ..Default::default()
+        };
+        let data = || TensorData::new(PIXELS.to_vec(), [1, 2, 5]);
+        let exr = encode_render(data(), &options, RenderChannel::Color, ImageFileFormat::Exr)
+            .expect("Failed to encode");
+        let exr = decode(&exr).to_rgba32f();
+        let [r, g, b, a] = exr.get_pixel(1, 0).0;
+        assert!(
+            (r - 0.214).abs() < 1e-3 && r == g && g == b && a == 1.0,
+            "Gray must be decoded to linear, got {:?}",
+            [r, g, b, a]
+        );
+
+        let linear = RenderOptions {
+            color_space: ColorSpace::Linear,
+            ..options.clone()
+        };
+        let exr = encode_render(data(), &linear, RenderChannel::Color, ImageFileFormat::Exr)
+            .expect("Failed to encode");
+        assert_eq!(
+            decode(&exr).to_rgba32f().get_pixel(0, 0).0,
+            [0.5, 0.0, 0.0, 0.5]
+        );
+
+        // Depth is divided by alpha, and kept as is.
+        let depth = encode_render(data(), &options, RenderChannel::Depth, ImageFileFormat::Exr)
+            .expect("Failed to encode");
+        let depth = decode(&depth).to_rgb32f();
+        assert_eq!(depth.get_pixel(0, 0).0, [4.0; 3]);
+        assert_eq!(depth.get_pixel(1, 0).0, [3.0; 3]);
+    }
+
+    #[test]
+    fn grayscale_channels() {
+        let options = RenderOptions {
+            render_depth: true,
+            ..Default::default()
+        };
+        let data = || TensorData::new(PIXELS.to_vec(), [1, 2, 5]);
+        let alpha = encode_render(
+            data(),
+            &options,
+            RenderChannel::Alpha,
+            ImageFileFormat::Jpeg,
+        )
+        .expect("Failed to encode");
+        let alpha = decode(&alpha).to_luma8();
+        assert!(
+            alpha.get_pixel(0, 0).0[0].abs_diff(128) <= 2 && alpha.get_pixel(1, 0).0[0] >= 253,
+            "Unexpected alpha {alpha:?}"
+        );
+
+        // Depths are normalized by the largest depth.
+        let depth = encode_render(data(), &options, RenderChannel::Depth, ImageFileFormat::Png)
+            .expect("Failed to encode");
+        let depth = decode(&depth).to_luma16();
+        assert_eq!(depth.get_pixel(0, 0).0, [u16::MAX]);
+        assert_eq!(
+            depth.get_pixel(1, 0).0,
+            [(0.75 * 65535.0f32).round() as u16]
+        );
+
+        let no_depth = encode_render(
+            TensorData::new(vec![0.0f32; 8], [1, 2, 4]),
+            &RenderOptions::default(),
+            RenderChannel::Depth,
+            ImageFileFormat::Png,
+        );
+        assert!(matches!(no_depth, Err(ImageExportError::NoDepth)));
+    }
+}
@@ -1,6 +1,8 @@
 #![recursion_limit = "256"]
 
+pub mod camera_path;
 pub mod config;
+pub mod image_export;
 pub mod scene;
 pub mod scene_loader;
 pub mod splat_export;
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use crate::quant::{decode_quat, decode_vec_8_8_8_8, decode_vec_11_10_11};
 
 use brush_render::sh::channel_to_sh;
@@ -18,6 +20,52 @@ pub(crate) struct ParsedGaussian<const QUANT_PARSE: bool> {
     // NB: This is in the inria format, aka [channels, coeffs]
     // not [coeffs, channels].
     pub(crate) sh_coeffs_rest: Vec<f32>,
+    // Values of other scalar properties, in the order of the header.
+    pub(crate) extra: Vec<f32>,
+    // Names of the extra values, only needed when writing.
+    pub(crate) extra_names: Arc<[String]>,
+}
+
+/// Whether a vertex property is one of the standard splat attributes, rather than an extra
+/// attribute. This includes normals, which 3DGS files hold but splats don't use.
+pub(crate) fn is_standard_property(key: &str) -> bool {
+    matches!(
+        key,
+        "x" | "y"
+            | "z"
+            | "nx"
+            | "ny"
+            | "nz"
+            | "scale_0"
+            | "scale_1"
+            | "scale_2"
+            | "opacity"
+            | "rot_0"
+            | "rot_1"
+            | "rot_2"
+            | "rot_3"
+            | "f_dc_0"
+            | "f_dc_1"
+            | "f_dc_2"
+            | "red"
+            | "green"
+            | "blue"
+    ) || key.starts_with("f_rest_")
+}
+
+/// The value of a scalar property, without any normalization.
+fn raw_scalar(property: &Property) -> Option<f32> {
+    match *property {
+        Property::Char(value) => Some(value as f32),
+        Property::UChar(value) => Some(value as f32),
+        Property::Short(value) => Some(value as f32),
+        Property::UShort(value) => Some(value as f32),
+        Property::Int(value) => Some(value as f32),
+        Property::UInt(value) => Some(value as f32),
+        Property::Float(value) => Some(value),
+        Property::Double(value) => Some(value as f32),
+        _ => None,
+    }
 }
 
 impl<const QUANT: bool> ParsedGaussian<QUANT> {
@@ -37,6 +85,14 @@ impl PropertyAccess for ParsedGaussian<false> {
     }
 
     fn set_property(&mut self, key: &str, property: Property) {
+        if !is_standard_property(key) {
+            // Extra attributes, like a semantic class, are kept as they are.
+            if let Some(value) = raw_scalar(&property) {
+                self.extra.push(value);
+            }
+            return;
+        }
+
         let ascii = key.as_bytes();
 
         let value = match property {
@@ -106,7 +162,11 @@ impl PropertyAccess for ParsedGaussian<false> {
                     None
                 }
             }
-            _ => None,
+            _ => self
+                .extra_names
+                .iter()
+                .position(|name| name == key)
+                .and_then(|idx| self.extra.get(idx).copied()),
         }
     }
 }
@@ -1,8 +1,8 @@
-use brush_render::{bounding_box::BoundingBox, camera::Camera};
+use brush_render::{bounding_box::BoundingBox, camera::Camera, render_options::LUMA_WEIGHTS};
 use brush_vfs::BrushVfs;
 use burn::{
     prelude::Backend,
-    tensor::{Tensor, TensorData},
+    tensor::{Tensor, TensorData, module::avg_pool2d, s},
 };
 use glam::{Affine3A, Vec3, vec3};
 use image::{ColorType, DynamicImage, ImageDecoder, ImageReader};
@@ -25,6 +25,8 @@ pub struct LoadImage {
     pub vfs: Arc<BrushVfs>,
     pub path: PathBuf,
     pub mask_path: Option<PathBuf>,
+    /// Image of the static background behind this view, if known.
+    pub background_path: Option<PathBuf>,
     color: image::ColorType,
     size: glam::UVec2,
     max_resolution: u32,
@@ -79,12 +81,18 @@ impl LoadImage {
             vfs,
             path: path.to_path_buf(),
             mask_path,
+            background_path: None,
             max_resolution,
             size: data.0,
             color: data.1,
         })
     }
 
+    pub fn with_background_path(mut self, background_path: Option<PathBuf>) -> Self {
+        self.background_path = background_path;
+        self
+    }
+
     pub fn has_alpha(&self) -> bool {
         self.color.has_alpha() || self.is_masked()
     }
@@ -155,6 +163,27 @@ impl LoadImage {
         ))
     }
 
+    /// Load the background image of this view as RGB, resized to the [`Self::dimensions`] of
+    /// the view if needed. Returns `None` when the view has no known background.
+    pub async fn load_background(&self) -> image::ImageResult<Option<DynamicImage>> {
+        let Some(background_path) = &self.background_path else {
+            return Ok(None);
+        };
+        let mut bytes = vec![];
+        self.vfs
+            .reader_at_path(background_path)
+            .await?
+            .read_to_end(&mut bytes)
+            .await?;
+        let mut background = image::load_from_memory(&bytes)?;
+        let size = self.dimensions();
+        if background.width() != size.x || background.height() != size.y {
+            background =
+                background.resize_exact(size.x, size.y, image::imageops::FilterType::Triangle);
+        }
+        Ok(Some(background.into_rgb8().into()))
+    }
+
     pub fn is_masked(&self) -> bool {
         self.mask_path.is_some()
     }
@@ -290,11 +319,251 @@ pub fn sample_to_tensor<B: Backend>(sample: &DynamicImage, device: &B::Device) -
 pub struct SceneBatch<B: Backend> {
     pub img_tensor: Tensor<B, 3>,
     pub alpha_is_mask: bool,
+    /// Known RGB background behind the scene, matching the size of the image.
+    ///
+    /// When set, training composites renders over this image instead of the background color.
+    /// The scene loader fills this in from an image with the same name in a `backgrounds`
+    /// folder next to the images folder, like masks.
+    pub background: Option<Tensor<B, 3>>,
     pub camera: Camera,
+    /// Index of the view in the scene this batch was loaded from, if any.
+    pub view_index: Option<usize>,
 }
 
 impl<B: Backend> SceneBatch<B> {
     pub fn has_alpha(&self) -> bool {
-        self.img_tensor.shape().dims[2] == 4
+        matches!(self.img_tensor.shape().dims[2], 2 | 4)
+    }
+
+    /// Whether the image has a single gray channel (plus alpha), see [`Self::to_grayscale`].
+    pub fn is_grayscale(&self) -> bool {
+        self.img_tensor.shape().dims[2] <= 2
+    }
+
+    /// Convert the image and background to gray, weighting the color channels by
+    /// [`LUMA_WEIGHTS`] like grayscale renders do. Alpha is kept as is.
+    pub fn to_grayscale(&self) -> Self {
+        if self.is_grayscale() {
+            return self.clone();
+        }
+        let gray = |img: Tensor<B, 3>| -> Tensor<B, 3> {
+            let channels = img.dims()[2];
+            let weights =
+                Tensor::<B, 1>::from_floats(LUMA_WEIGHTS, &img.device()).reshape([1, 1, 3]);
+            let luma = (img.clone().slice(s![.., .., 0..3]) * weights).sum_dim(2);
+            if channels > 3 {
+                Tensor::cat(vec![luma, img.slice(s![.., .., 3..channels])], 2)
+            } else {
+                luma
+            }
+        };
+        Self {
+            img_tensor: gray(self.img_tensor.clone()),
+            background: self.background.clone().map(gray),
+            ..self.clone()
+        }
+    }
+
+    /// Downsample the view by an integer `factor`, averaging blocks of `factor` x `factor`
+    /// pixels.
+    ///
+    /// Pixels past the last full block are cropped, and the camera intrinsics are scaled to match,
+    /// so the view still lines up with the same geometry. The factor is lowered if the image is
+    /// smaller than it.
+    pub fn downsample(&self, factor: u32) -> Self {
+        let [h, w, _] = self.img_tensor.dims();
+        let factor = (factor as usize).min(w).min(h).max(1);
+        if factor == 1 {
+            return self.clone();
+        }
+
+        let pool = |img: Tensor<B, 3>| -> Tensor<B, 3> {
+            // Pooling works on [N, C, H, W] images.
+            let img = img.permute([2, 0, 1]).unsqueeze::<4>();
+            let pooled = avg_pool2d(img, [factor, factor], [factor, factor], [0, 0], true);
+            pooled.squeeze::<3>(0).permute([1, 2, 0])
+        };
+
+        let size = glam::uvec2(w as u32, h as u32);
+        let new_size = size / factor as u32;
+        let focal = self.camera.focal(size).as_dvec2();
+        let center = self.camera.center(size);
+        let camera = Camera::from_intrinsics(
+            self.camera.position,
+            self.camera.rotation,
+            focal / factor as f64,
+            center / factor as f32,
+            new_size,
+        )
+        .with_origin(self.camera.origin);
+
+        Self {
+            img_tensor: pool(self.img_tensor.clone()),
+            alpha_is_mask: self.alpha_is_mask,
+            background: self.background.clone().map(pool),
+            camera,
+            view_index: self.view_index,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LoadImage, SceneBatch};
+    use brush_render::{MainBackend, camera::Camera, render_options::LUMA_WEIGHTS};
+    use brush_vfs::BrushVfs;
+    use burn::{backend::wgpu::WgpuDevice, tensor::Tensor};
+    use std::{
+        path::{Path, PathBuf},
+        sync::Arc,
+    };
+
+    #[test]
+    fn downsampled_views_stay_aligned() {
+        let device = WgpuDevice::DefaultDevice;
+        let size = glam::uvec2(70, 45);
+        let camera = Camera::from_intrinsics(
+            glam::vec3(0.3, -0.2, -2.0),
+            glam::Quat::from_rotation_y(0.2),
+            glam::dvec2(60.0, 55.0),
+            glam::vec2(33.0, 24.0),
+            size,
+        );
+        // Each pixel holds its own coordinates, so the pooled pixels hold the average coordinate
+        // of the block they cover.
+        let coords: Vec<f32> = (0..size.y)
+            .flat_map(|y| (0..size.x).flat_map(move |x| [x as f32 + 0.5, y as f32 + 0.5, 0.0]))
+            .collect();
+        let batch = SceneBatch::<MainBackend> {
+            img_tensor: Tensor::<MainBackend, 1>::from_floats(coords.as_slice(), &device)
+                .reshape([size.y as usize, size.x as usize, 3]),
+            alpha_is_mask: false,
+            background: None,
+            camera: camera.clone(),
+            view_index: None,
+        };
+
+        let project = |camera: &Camera, size: glam::UVec2, point: glam::Vec3| {
+            let local = camera.world_to_local().transform_point3(point);
+            camera.focal(size) * local.truncate() / local.z + camera.center(size)
+        };
+        let point = glam::vec3(0.1, 0.2, 1.0);
+        let full_px = project(&camera, size, point);
+
+        for factor in [1, 2, 4, 8] {
+            let scaled = batch.downsample(factor);
+            let [h, w, _] = scaled.img_tensor.dims();
+            let new_size = glam::uvec2(w as u32, h as u32);
+            assert_eq!(new_size, size / factor);
+
+            // A world point lands on the same spot of the scene at every stage.
+            let px = project(&scaled.camera, new_size, point);
+            assert!(
+                (px * factor as f32 - full_px).length() < 1e-3,
+                "Point projects to {px} at factor {factor}, expected {}",
+                full_px / factor as f32
+            );
+
+            // The pooled pixel centers are the original pixel centers they cover.
+            let data = scaled
+                .img_tensor
+                .into_data()
+                .to_vec::<f32>()
+                .expect("Wrong type");
+            let last = ((h - 1) * w + w - 1) * 3;
+            let expected = (new_size.as_vec2() - 0.5) * factor as f32;
+            assert!(
+                (glam::vec2(data[last], data[last + 1]) - expected).length() < 1e-3,
+                "Pooled pixel is at {}, {}, expected {expected}",
+                data[last],
+                data[last + 1]
+            );
+        }
+    }
+
+    #[test]
+    fn grayscale_keeps_alpha() {
+        let device = WgpuDevice::DefaultDevice;
+        let camera = Camera::from_position_rotation(glam::Vec3::ZERO, glam::Quat::IDENTITY);
+        let pixel = |channels: &[f32]| {
+            Tensor::<MainBackend, 1>::from_floats(channels, &device).reshape([1, 1, channels.len()])
+        };
+        let luma = LUMA_WEIGHTS[0] * 0.2 + LUMA_WEIGHTS[1] * 0.4 + LUMA_WEIGHTS[2] * 0.6;
+
+        for (img, expected) in [
+            (pixel(&[0.2, 0.4, 0.6]), vec![luma]),
+            (pixel(&[0.2, 0.4, 0.6, 0.5]), vec![luma, 0.5]),
+        ] {
+            let batch = SceneBatch::<MainBackend> {
+                img_tensor: img,
+                alpha_is_mask: false,
+                background: Some(pixel(&[0.2, 0.4, 0.6])),
+                camera: camera.clone(),
+                view_index: None,
+            }
+            .to_grayscale();
+            assert!(batch.is_grayscale(), "Converted batch must be grayscale");
+
+            let data = batch
+                .img_tensor
+                .into_data()
+                .to_vec::<f32>()
+                .expect("Wrong type");
+            assert_eq!(data.len(), expected.len());
+            for (value, want) in data.iter().zip(&expected) {
+                assert!(
+                    (value - want).abs() < 1e-5,
+                    "Converted image is {data:?}, expected {expected:?}"
+                );
+            }
+
+            let background = batch
+                .background
+                .expect("Background must be kept")
+                .into_data()
+                .to_vec::<f32>()
+                .expect("Wrong type");
+            assert!(
+                background.len() == 1 && (background[0] - luma).abs() < 1e-5,
+                "Converted background is {background:?}, expected {luma}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn background_matches_image_size() {
+        let dir = std::env::temp_dir().join(format!("brush_background_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("images")).expect("Failed to create images dir");
+        std::fs::create_dir_all(dir.join("backgrounds")).expect("Failed to create backgrounds dir");
+        image::RgbaImage::new(8, 6)
+            .save(dir.join("images/view.png"))
+            .expect("Failed to write image");
+        image::RgbImage::from_pixel(16, 12, image::Rgb([10, 20, 30]))
+            .save(dir.join("backgrounds/view.png"))
+            .expect("Failed to write background");
+
+        let vfs = Arc::new(BrushVfs::from_path(&dir).await.expect("Failed to open dir"));
+        let image = LoadImage::new(vfs, Path::new("images/view.png"), None, 1024)
+            .await
+            .expect("Failed to read image");
+        assert!(
+            image
+                .load_background()
+                .await
+                .expect("Load failed")
+                .is_none(),
+            "View without a background path must not have a background"
+        );
+
+        let background = image
+            .with_background_path(Some(PathBuf::from("backgrounds/view.png")))
+            .load_background()
+            .await
+            .expect("Failed to load background")
+            .expect("Background must be loaded");
+        assert_eq!((background.width(), background.height()), (8, 6));
+        assert!(!background.color().has_alpha(), "Background must be RGB");
+        assert_eq!(background.to_rgb8().get_pixel(3, 3).0, [10, 20, 30]);
+        std::fs::remove_dir_all(&dir).expect("Failed to clean up");
     }
 }
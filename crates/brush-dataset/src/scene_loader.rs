@@ -1,59 +1,139 @@
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 use burn::prelude::Backend;
 use image::DynamicImage;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+use rand::rngs::StdRng;
 use rand::{SeedableRng, seq::SliceRandom};
+use tokio::sync::Mutex;
+use tokio::sync::mpsc;
 use tokio::sync::mpsc::Receiver;
-use tokio::sync::{RwLock, mpsc};
 use tokio_with_wasm::alias as tokio_wasm;
 
 use crate::scene::{Scene, SceneBatch, sample_to_tensor, view_to_sample_image};
 
 pub struct SceneLoader<B: Backend> {
     receiver: Receiver<SceneBatch<B>>,
+    view_order: Arc<Mutex<ViewOrder>>,
 }
 
-struct ImageCache {
-    states: Vec<Option<Arc<DynamicImage>>>,
-    max_size: usize,
-    size: usize,
-}
-
-// Cache at most some nr. of gigs of data.
-// TODO: Not sure if this should be configurable or not.
+/// Default size of the decoded image cache, in MB.
 #[cfg(not(target_family = "wasm"))]
-const MAX_CACHE_MB: usize = 6 * 1024;
+pub const DEFAULT_CACHE_MB: usize = 6 * 1024;
 
-// On WASM, not much hope a big dataset will work anyway but let's not
-// cache more than what fits in memory.
+/// Default size of the decoded image cache, in MB.
+///
+/// On WASM, not much hope a big dataset will work anyway but let's not
+/// cache more than what fits in memory.
 #[cfg(target_family = "wasm")]
-const MAX_CACHE_MB: usize = 2 * 1024;
+pub const DEFAULT_CACHE_MB: usize = 2 * 1024;
+
+/// Decoded image of a view, along with its background image if it has one.
+struct LoadedView {
+    sample: DynamicImage,
+    background: Option<DynamicImage>,
+}
+
+impl LoadedView {
+    fn num_bytes(&self) -> usize {
+        self.sample.as_bytes().len() + self.background.as_ref().map_or(0, |b| b.as_bytes().len())
+    }
+}
+
+/// Keeps recently used decoded images in memory, up to a maximum size.
+///
+/// When the cache is full, the least recently used images are evicted, so memory stays bounded
+/// no matter how large the dataset is.
+struct ImageCache {
+    // Cached views by index, with the time they were last used.
+    entries: HashMap<usize, (Arc<LoadedView>, u64)>,
+    // Indices of cached views by last use, from least to most recently used.
+    lru: BTreeMap<u64, usize>,
+    clock: u64,
+    max_bytes: usize,
+    size: usize,
+}
 
 impl ImageCache {
-    fn new(max_size: usize, n_images: usize) -> Self {
+    fn new(max_mb: usize) -> Self {
+        Self::with_max_bytes(max_mb * 1024 * 1024)
+    }
+
+    fn with_max_bytes(max_bytes: usize) -> Self {
         Self {
-            states: vec![None; n_images],
-            max_size,
+            entries: HashMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+            max_bytes,
             size: 0,
         }
     }
 
-    fn try_get(&self, index: usize) -> Option<Arc<DynamicImage>> {
-        self.states[index].clone()
+    fn get(&mut self, index: usize) -> Option<Arc<LoadedView>> {
+        let (view, last_used) = self.entries.get_mut(&index)?;
+        self.lru.remove(last_used);
+        self.clock += 1;
+        *last_used = self.clock;
+        self.lru.insert(self.clock, index);
+        Some(view.clone())
+    }
+
+    fn insert(&mut self, index: usize, data: Arc<LoadedView>) {
+        let data_size = data.num_bytes();
+
+        if data_size > self.max_bytes || self.entries.contains_key(&index) {
+            return;
+        }
+
+        while self.size + data_size > self.max_bytes {
+            let Some((_, evict)) = self.lru.pop_first() else {
+                break;
+            };
+            if let Some((view, _)) = self.entries.remove(&evict) {
+                self.size -= view.num_bytes();
+            }
+        }
+
+        self.clock += 1;
+        self.entries.insert(index, (data, self.clock));
+        self.lru.insert(self.clock, index);
+        self.size += data_size;
     }
+}
 
-    fn insert(&mut self, index: usize, data: Arc<DynamicImage>) {
-        let data_size_mb = data.as_bytes().len() / (1024 * 1024);
+/// Order in which views are loaded, shared between all loading tasks.
+///
+/// Views are visited in shuffled epochs, where each epoch visits every view once, unless views
+/// are weighted, see [`SceneLoader::set_view_weights`].
+struct ViewOrder {
+    rng: StdRng,
+    remaining: Vec<usize>,
+    num_views: usize,
+    weights: Option<WeightedIndex<f32>>,
+}
 
-        if self.size + data_size_mb < self.max_size && self.states[index].is_none() {
-            self.states[index] = Some(data);
-            self.size += data_size_mb;
+impl ViewOrder {
+    fn next(&mut self) -> usize {
+        if let Some(weights) = &self.weights {
+            return weights.sample(&mut self.rng);
+        }
+        if self.remaining.is_empty() {
+            self.remaining = (0..self.num_views).collect();
+            self.remaining.shuffle(&mut self.rng);
         }
+        self.remaining
+            .pop()
+            .expect("Need at least one view in dataset")
     }
 }
 
 impl<B: Backend> SceneLoader<B> {
-    pub fn new(scene: &Scene, seed: u64, device: &B::Device) -> Self {
+    /// Start loading batches of the views of `scene`.
+    ///
+    /// Images are loaded lazily, and at most `cache_mb` MB of decoded images is kept in memory.
+    pub fn new(scene: &Scene, seed: u64, cache_mb: usize, device: &B::Device) -> Self {
         let num_img_queue = 32;
 
         // The bounded size == number of batches to prefetch.
@@ -70,49 +150,53 @@ impl<B: Backend> SceneLoader<B> {
                 .unwrap_or(8)
                 // Don't need more threads than the image queue can hold, most
                 // threads would just sit around idling!
-                .min(num_img_queue) as u64
+                .min(num_img_queue)
         };
         let num_views = scene.views.len();
 
-        let load_cache = Arc::new(RwLock::new(ImageCache::new(MAX_CACHE_MB, num_views)));
+        let load_cache = Arc::new(Mutex::new(ImageCache::new(cache_mb)));
+        let view_order = Arc::new(Mutex::new(ViewOrder {
+            rng: StdRng::seed_from_u64(seed),
+            remaining: vec![],
+            num_views,
+            weights: None,
+        }));
 
-        for i in 0..parallelism {
-            let mut rng = rand::rngs::StdRng::seed_from_u64(seed + i);
+        for _ in 0..parallelism {
             let send_img = send_img.clone();
             let views = scene.views.clone();
 
             let load_cache = load_cache.clone();
+            let view_order = view_order.clone();
 
             tokio_wasm::spawn(async move {
-                let mut shuf_indices = vec![];
-
                 loop {
-                    let index = shuf_indices.pop().unwrap_or_else(|| {
-                        shuf_indices = (0..num_views).collect();
-                        shuf_indices.shuffle(&mut rng);
-                        shuf_indices
-                            .pop()
-                            .expect("Need at least one view in dataset")
-                    });
-
+                    let index = view_order.lock().await.next();
                     let view = &views[index];
 
-                    let sample = if let Some(image) = load_cache.read().await.try_get(index) {
-                        image
-                    } else {
-                        let image = view
-                            .image
-                            .load()
-                            .await
-                            .expect("Scene loader encountered an error while loading an image");
-                        // Don't premultiply the image if it's a mask - treat as fully opaque.
-                        let sample = Arc::new(view_to_sample_image(image, view.image.is_masked()));
-                        load_cache.write().await.insert(index, sample.clone());
-                        sample
-                    };
+                    let cached = load_cache.lock().await.get(index);
+                    let sample =
+                        if let Some(loaded) = cached {
+                            loaded
+                        } else {
+                            let image =
+                                view.image.load().await.expect(
+                                    "Scene loader encountered an error while loading an image",
+                                );
+                            let background = view.image.load_background().await.expect(
+                                "Scene loader encountered an error while loading a background",
+                            );
+                            // Don't premultiply the image if it's a mask - treat as fully opaque.
+                            let sample = Arc::new(LoadedView {
+                                sample: view_to_sample_image(image, view.image.is_masked()),
+                                background,
+                            });
+                            load_cache.lock().await.insert(index, sample.clone());
+                            sample
+                        };
 
                     if send_img
-                        .send((sample, view.image.is_masked(), view.camera.clone()))
+                        .send((sample, view.image.is_masked(), view.camera.clone(), index))
                         .await
                         .is_err()
                     {
@@ -126,14 +210,20 @@ impl<B: Backend> SceneLoader<B> {
         let device = device.clone();
         tokio_wasm::spawn(async move {
             while let Some(rec) = rec_imag.recv().await {
-                let (sample, alpha_is_mask, camera) = rec;
-                let img_tensor = sample_to_tensor(&sample, &device);
+                let (sample, alpha_is_mask, camera, view_index) = rec;
+                let img_tensor = sample_to_tensor(&sample.sample, &device);
+                let background = sample
+                    .background
+                    .as_ref()
+                    .map(|background| sample_to_tensor(background, &device));
 
                 if send_batch
                     .send(SceneBatch {
                         img_tensor,
                         alpha_is_mask,
+                        background,
                         camera,
+                        view_index: Some(view_index),
                     })
                     .await
                     .is_err()
@@ -145,9 +235,27 @@ impl<B: Backend> SceneLoader<B> {
 
         Self {
             receiver: rec_batch,
+            view_order,
         }
     }
 
+    /// Sample views proportionally to `weights` from now on, instead of visiting them in
+    /// shuffled epochs. Pass `None` to go back to shuffled epochs.
+    ///
+    /// Batches that are already loaded are still returned first, so this takes effect after a
+    /// few batches. Weights that can't be sampled from, eg. when they are all zero, are ignored.
+    pub async fn set_view_weights(&self, weights: Option<&[f32]>) {
+        let mut order = self.view_order.lock().await;
+        order.weights = weights.and_then(|weights| {
+            assert_eq!(
+                weights.len(),
+                order.num_views,
+                "Need a weight for every view"
+            );
+            WeightedIndex::new(weights).ok()
+        });
+    }
+
     pub async fn next_batch(&mut self) -> SceneBatch<B> {
         self.receiver
             .recv()
@@ -155,3 +263,88 @@ impl<B: Backend> SceneLoader<B> {
             .expect("Somehow lost data loading channel!")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ImageCache, LoadedView, ViewOrder};
+    use image::{DynamicImage, RgbImage};
+    use rand::{SeedableRng, distr::weighted::WeightedIndex, rngs::StdRng};
+    use std::sync::Arc;
+
+    // A view taking up `pixels * 3` bytes.
+    fn view(pixels: u32) -> Arc<LoadedView> {
+        Arc::new(LoadedView {
+            sample: DynamicImage::ImageRgb8(RgbImage::new(pixels, 1)),
+            background: None,
+        })
+    }
+
+    #[test]
+    fn cache_evicts_least_recently_used() {
+        let mut cache = ImageCache::with_max_bytes(30);
+        cache.insert(0, view(4));
+        cache.insert(1, view(4));
+        cache.insert(2, view(2));
+        assert_eq!(cache.size, 30);
+
+        // Using view 0 makes view 1 the least recently used one.
+        assert!(cache.get(0).is_some());
+        cache.insert(3, view(2));
+        assert!(
+            cache.get(1).is_none(),
+            "Least recently used view must be evicted"
+        );
+        assert!(cache.get(0).is_some() && cache.get(2).is_some() && cache.get(3).is_some());
+        assert_eq!(cache.size, 24);
+    }
+
+    #[test]
+    fn cache_stays_under_memory_cap() {
+        let mut cache = ImageCache::with_max_bytes(100);
+        for index in 0..50 {
+            cache.insert(index, view(1 + index as u32 % 7));
+            assert!(cache.size <= 100, "Cache grew to {} bytes", cache.size);
+            assert_eq!(
+                cache.size,
+                cache
+                    .entries
+                    .values()
+                    .map(|(v, _)| v.num_bytes())
+                    .sum::<usize>()
+            );
+            assert_eq!(cache.entries.len(), cache.lru.len());
+        }
+
+        // A view bigger than the whole cache isn't cached, and doesn't evict anything.
+        let cached = cache.entries.len();
+        cache.insert(100, view(40));
+        assert!(cache.get(100).is_none());
+        assert_eq!(cache.entries.len(), cached);
+    }
+
+    #[test]
+    fn weighted_views_are_sampled_more() {
+        let mut order = ViewOrder {
+            rng: StdRng::seed_from_u64(0),
+            remaining: vec![],
+            num_views: 4,
+            weights: None,
+        };
+        let mut counts = [0; 4];
+        for _ in 0..400 {
+            counts[order.next()] += 1;
+        }
+        assert_eq!(counts, [100; 4], "Epochs must visit every view once");
+
+        // A view with a much higher loss is picked far more often.
+        order.weights = WeightedIndex::new([1.0, 1.0, 5.0, 1.0]).ok();
+        let mut counts = [0; 4];
+        for _ in 0..8000 {
+            counts[order.next()] += 1;
+        }
+        assert!(
+            counts[2] > 4000 && counts.iter().all(|&c| c > 700),
+            "Got {counts:?}"
+        );
+    }
+}
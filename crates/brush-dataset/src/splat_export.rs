@@ -1,13 +1,21 @@
-use crate::parsed_gaussian::ParsedGaussian;
-use brush_render::gaussian_splats::Splats;
-use burn::prelude::Backend;
+use std::{collections::HashMap, sync::Arc};
+
+use crate::parsed_gaussian::{ParsedGaussian, is_standard_property};
+use brush_render::gaussian_splats::{OpacityActivation, Splats};
+use burn::{prelude::Backend, tensor::Tensor};
 use glam::{Quat, Vec3};
 use ply_rs::{
     ply::{self, Ply, PropertyDef, PropertyType, ScalarType},
     writer::Writer,
 };
 
-async fn read_splat_data<B: Backend>(splats: Splats<B>) -> Vec<ParsedGaussian<false>> {
+async fn read_splat_data<B: Backend>(
+    splats: Splats<B>,
+    extra_names: Arc<[String]>,
+    extra_values: &[Vec<f32>],
+) -> Vec<ParsedGaussian<false>> {
+    // Ply files store opacities before a sigmoid.
+    let splats = splats.with_opacity_activation(OpacityActivation::Sigmoid);
     let means = splats
         .means
         .val()
@@ -36,9 +44,14 @@ async fn read_splat_data<B: Backend>(splats: Splats<B>) -> Vec<ParsedGaussian<fa
         .await
         .to_vec()
         .expect("Unreachable");
-    let sh_coeffs = splats
-        .sh_coeffs
-        .val()
+    // Gray splats are saved with three equal color channels, which renders the same.
+    let sh_coeffs = splats.sh_coeffs.val();
+    let sh_coeffs = if splats.is_grayscale() {
+        sh_coeffs.repeat_dim(2, 3)
+    } else {
+        sh_coeffs
+    };
+    let sh_coeffs = sh_coeffs
         .permute([0, 2, 1]) // Permute to inria format ([n, channel, coeffs]).
         .into_data_async()
         .await
@@ -81,6 +94,8 @@ async fn read_splat_data<B: Backend>(splats: Splats<B>) -> Vec<ParsedGaussian<fa
                 ),
                 sh_dc,
                 sh_coeffs_rest,
+                extra: extra_values.iter().map(|values| values[i]).collect(),
+                extra_names: extra_names.clone(),
             };
 
             splat.is_finite().then_some(splat)
@@ -89,9 +104,49 @@ async fn read_splat_data<B: Backend>(splats: Splats<B>) -> Vec<ParsedGaussian<fa
 }
 
 pub async fn splat_to_ply<B: Backend>(splats: Splats<B>) -> std::io::Result<Vec<u8>> {
+    splat_to_ply_with_attributes(splats, &HashMap::new()).await
+}
+
+/// Write splats to a ply file, with extra scalar attributes per splat, like a semantic class.
+///
+/// The attributes are written as float properties named by their key, after the standard
+/// properties, as read back in [`crate::splat_import::SplatMessage::attributes`]. Each needs
+/// one value per splat, and names can't clash with the standard properties.
+pub async fn splat_to_ply_with_attributes<B: Backend>(
+    splats: Splats<B>,
+    attributes: &HashMap<String, Tensor<B, 1>>,
+) -> std::io::Result<Vec<u8>> {
     let splats = splats.with_normed_rotations();
 
-    let data = read_splat_data(splats.clone()).await;
+    // Sort the attributes so the header doesn't depend on the order of the map.
+    let mut extra_names: Vec<String> = attributes.keys().cloned().collect();
+    extra_names.sort();
+    let invalid = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, msg);
+    let mut extra_values = Vec::with_capacity(extra_names.len());
+    for name in &extra_names {
+        if is_standard_property(name) {
+            return Err(invalid(format!(
+                "Attribute {name} clashes with a standard property"
+            )));
+        }
+        let values = attributes[name]
+            .clone()
+            .into_data_async()
+            .await
+            .convert::<f32>()
+            .to_vec::<f32>()
+            .expect("Unreachable");
+        if values.len() != splats.num_splats() as usize {
+            return Err(invalid(format!(
+                "Attribute {name} has {} values for {} splats",
+                values.len(),
+                splats.num_splats()
+            )));
+        }
+        extra_values.push(values);
+    }
+
+    let data = read_splat_data(splats.clone(), extra_names.clone().into(), &extra_values).await;
 
     let property_names = vec![
         "x", "y", "z", "scale_0", "scale_1", "scale_2", "opacity", "rot_0", "rot_1", "rot_2",
@@ -112,6 +167,13 @@ pub async fn splat_to_ply<B: Backend>(splats: Splats<B>) -> std::io::Result<Vec<
         ));
     }
 
+    for name in &extra_names {
+        properties.push(PropertyDef::new(
+            name,
+            PropertyType::Scalar(ScalarType::Float),
+        ));
+    }
+
     let mut ply: Ply<ParsedGaussian<false>> = Ply::new();
 
     // Create PLY header
@@ -1,8 +1,15 @@
-use std::{collections::HashSet, pin::Pin};
+use std::{
+    collections::{HashMap, HashSet},
+    pin::Pin,
+};
 
 use async_fn_stream::try_fn_stream;
 use brush_render::gaussian_splats::Splats;
-use brush_render::{MainBackend, gaussian_splats::inverse_sigmoid, sh::rgb_to_sh};
+use brush_render::{
+    MainBackend,
+    gaussian_splats::inverse_opacity_activation,
+    sh::{rgb_to_sh, sh_coeffs_for_degree},
+};
 use brush_vfs::{DynStream, SendNotWasm};
 use burn::{
     backend::wgpu::WgpuDevice,
@@ -11,7 +18,7 @@ use burn::{
 use glam::{Quat, Vec3, Vec4};
 use ply_rs::{
     parser::Parser,
-    ply::{DefaultElement, ElementDef, Encoding, Header, Property, PropertyAccess},
+    ply::{DefaultElement, ElementDef, Encoding, Header, Property, PropertyAccess, PropertyType},
 };
 use thiserror::Error;
 use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, BufReader};
@@ -19,7 +26,7 @@ use tokio_stream::{Stream, StreamExt};
 use tokio_with_wasm::alias as tokio_wasm;
 use tracing::trace_span;
 
-use crate::parsed_gaussian::ParsedGaussian;
+use crate::parsed_gaussian::{ParsedGaussian, is_standard_property};
 
 pub struct ParseMetadata {
     pub up_axis: Option<Vec3>,
@@ -31,6 +38,13 @@ pub struct ParseMetadata {
 pub struct SplatMessage {
     pub meta: ParseMetadata,
     pub splats: Splats<MainBackend>,
+    /// Extra scalar attributes of the splats, like a semantic class or a confidence, keyed by
+    /// the name of their ply property. Each is a tensor with one value per splat.
+    ///
+    /// Only plain ply files have extra attributes. Values are read as they are stored, so
+    /// integer classes keep their value. Training only starts from the splats and drops these,
+    /// as refinement adds and removes splats.
+    pub attributes: HashMap<String, Tensor<MainBackend, 1>>,
 }
 
 enum PlyFormat {
@@ -39,11 +53,20 @@ enum PlyFormat {
     SuperSplatCompressed,
 }
 
-fn interleave_coeffs(sh_dc: Vec3, sh_rest: &[f32], result: &mut Vec<f32>) {
+/// Number of non-dc sh coefficients per channel to keep when loading at most `max_sh_degree`.
+fn max_rest_coeffs(max_sh_degree: Option<u32>) -> usize {
+    max_sh_degree.map_or(usize::MAX, |degree| {
+        sh_coeffs_for_degree(degree) as usize - 1
+    })
+}
+
+/// Append the coefficients of a splat to `result`, keeping at most `max_rest` coefficients per
+/// channel after the dc coefficient. Higher bands are dropped without being stored.
+fn interleave_coeffs(sh_dc: Vec3, sh_rest: &[f32], max_rest: usize, result: &mut Vec<f32>) {
     let channels = 3;
     let coeffs_per_channel = sh_rest.len() / channels;
     result.extend([sh_dc.x, sh_dc.y, sh_dc.z]);
-    for i in 0..coeffs_per_channel {
+    for i in 0..coeffs_per_channel.min(max_rest) {
         for j in 0..channels {
             let index = j * coeffs_per_channel + i;
             result.push(sh_rest[index]);
@@ -107,9 +130,14 @@ pub enum SplatImportError {
     InvalidFormat,
 }
 
+/// Load splats from a ply file, as a stream of progressively more complete splats.
+///
+/// `max_sh_degree` caps the degree of the spherical harmonics of the splats. Higher bands in the
+/// file are dropped while reading, so they never take up memory.
 pub fn load_splat_from_ply<T: AsyncRead + SendNotWasm + Unpin + 'static>(
     reader: T,
     subsample_points: Option<u32>,
+    max_sh_degree: Option<u32>,
     device: WgpuDevice,
 ) -> impl DynStream<Result<SplatMessage, SplatImportError>> {
     // set up a reader, in this case a file.
@@ -152,11 +180,20 @@ pub fn load_splat_from_ply<T: AsyncRead + SendNotWasm + Unpin + 'static>(
 
         let mut sub_stream: SplatStream = match ply_type {
             PlyFormat::Ply => {
-                Box::pin(parse_ply(reader, subsample_points, device, header, up_axis))
+                let max_rest = max_rest_coeffs(max_sh_degree);
+                Box::pin(parse_ply(
+                    reader,
+                    subsample_points,
+                    max_rest,
+                    device,
+                    header,
+                    up_axis,
+                ))
             }
             PlyFormat::Brush4DCompressed => Box::pin(parse_delta_ply(
                 reader,
                 subsample_points,
+                max_rest_coeffs(max_sh_degree),
                 device,
                 header,
                 up_axis,
@@ -164,6 +201,7 @@ pub fn load_splat_from_ply<T: AsyncRead + SendNotWasm + Unpin + 'static>(
             PlyFormat::SuperSplatCompressed => Box::pin(parse_compressed_ply(
                 reader,
                 subsample_points,
+                max_rest_coeffs(max_sh_degree),
                 device,
                 header,
                 up_axis,
@@ -181,6 +219,7 @@ pub fn load_splat_from_ply<T: AsyncRead + SendNotWasm + Unpin + 'static>(
 fn parse_ply<T: AsyncBufRead + Unpin + 'static>(
     mut reader: T,
     subsample_points: Option<u32>,
+    max_rest: usize,
     device: WgpuDevice,
     header: Header,
     up_axis: Option<Vec3>,
@@ -210,6 +249,17 @@ fn parse_ply<T: AsyncBufRead + Unpin + 'static>(
         let mut opacity = properties
             .contains("opacity")
             .then(|| Vec::with_capacity(vertex.count));
+        // Extra attributes are parsed in the order of the header.
+        let extra_names: Vec<_> = vertex
+            .properties
+            .iter()
+            .filter(|prop| {
+                matches!(prop.data_type, PropertyType::Scalar(_))
+                    && !is_standard_property(&prop.name)
+            })
+            .map(|prop| prop.name.clone())
+            .collect();
+        let mut extra_values = vec![Vec::with_capacity(vertex.count); extra_names.len()];
 
         let update_every = vertex.count.div_ceil(8);
 
@@ -244,7 +294,10 @@ fn parse_ply<T: AsyncBufRead + Unpin + 'static>(
                 opacity.push(splat.opacity);
             }
             if let Some(sh_coeffs) = &mut sh_coeffs {
-                interleave_coeffs(splat.sh_dc, &splat.sh_coeffs_rest, sh_coeffs);
+                interleave_coeffs(splat.sh_dc, &splat.sh_coeffs_rest, max_rest, sh_coeffs);
+            }
+            for (values, &value) in extra_values.iter_mut().zip(&splat.extra) {
+                values.push(value);
             }
 
             if (i - last_update) >= update_every || i == vertex.count - 1 {
@@ -256,6 +309,14 @@ fn parse_ply<T: AsyncBufRead + Unpin + 'static>(
                     opacity.as_deref(),
                     &device,
                 );
+                let attributes = extra_names
+                    .iter()
+                    .zip(&extra_values)
+                    .map(|(name, values)| {
+                        let data = TensorData::new(values.clone(), [values.len()]);
+                        (name.clone(), Tensor::from_data(data, &device))
+                    })
+                    .collect();
                 emitter
                     .emit(SplatMessage {
                         meta: ParseMetadata {
@@ -265,6 +326,7 @@ fn parse_ply<T: AsyncBufRead + Unpin + 'static>(
                             current_frame: 0,
                         },
                         splats,
+                        attributes,
                     })
                     .await;
 
@@ -279,6 +341,7 @@ fn parse_ply<T: AsyncBufRead + Unpin + 'static>(
 fn parse_compressed_ply<T: AsyncBufRead + Unpin + 'static>(
     mut reader: T,
     subsample_points: Option<u32>,
+    max_rest: usize,
     device: WgpuDevice,
     header: Header,
     up_axis: Option<Vec3>,
@@ -412,7 +475,7 @@ fn parse_compressed_ply<T: AsyncBufRead + Unpin + 'static>(
             rotations.push(splat.rotation);
 
             // Compressed ply specifies things in post-activated values. Convert to pre-activated values.
-            opacity.push(inverse_sigmoid(splat.opacity));
+            opacity.push(inverse_opacity_activation(splat.opacity));
 
             // These come in as RGB colors. Convert to base SH coeffecients.
             let sh_dc = rgb_to_sh(quant_data.color.dequant(splat.sh_dc));
@@ -436,6 +499,7 @@ fn parse_compressed_ply<T: AsyncBufRead + Unpin + 'static>(
                             Some(&opacity),
                             &device,
                         ),
+                        attributes: HashMap::new(),
                     })
                     .await;
                 last_update = i;
@@ -472,7 +536,7 @@ fn parse_compressed_ply<T: AsyncBufRead + Unpin + 'static>(
                     sh_coeffs[splat_index * 3 + 1],
                     sh_coeffs[splat_index * 3 + 2],
                 );
-                interleave_coeffs(dc, &splat.sh_coeffs_rest, &mut total_coeffs);
+                interleave_coeffs(dc, &splat.sh_coeffs_rest, max_rest, &mut total_coeffs);
                 splat_index += 1;
             }
 
@@ -492,6 +556,7 @@ fn parse_compressed_ply<T: AsyncBufRead + Unpin + 'static>(
                         Some(&opacity),
                         &device,
                     ),
+                    attributes: HashMap::new(),
                 })
                 .await;
         }
@@ -503,6 +568,7 @@ fn parse_compressed_ply<T: AsyncBufRead + Unpin + 'static>(
 fn parse_delta_ply<T: AsyncBufRead + Unpin + 'static>(
     mut reader: T,
     subsample_points: Option<u32>,
+    max_rest: usize,
     device: WgpuDevice,
     header: Header,
     up_axis: Option<Vec3>,
@@ -580,6 +646,7 @@ fn parse_delta_ply<T: AsyncBufRead + Unpin + 'static>(
                                     opacity.as_deref(),
                                     &device,
                                 ),
+                                attributes: HashMap::new(),
                             })
                             .await;
                     }
@@ -605,7 +672,7 @@ fn parse_delta_ply<T: AsyncBufRead + Unpin + 'static>(
                         opacity.push(splat.opacity);
                     }
                     if let Some(sh_coeffs) = &mut sh_coeffs {
-                        interleave_coeffs(splat.sh_dc, &splat.sh_coeffs_rest, sh_coeffs);
+                        interleave_coeffs(splat.sh_dc, &splat.sh_coeffs_rest, max_rest, sh_coeffs);
                     }
                 }
                 let splats = Splats::from_raw(
@@ -626,6 +693,7 @@ fn parse_delta_ply<T: AsyncBufRead + Unpin + 'static>(
                             current_frame: frame,
                         },
                         splats,
+                        attributes: HashMap::new(),
                     })
                     .await;
             } else if element.name.starts_with("meta_delta_min_") {
@@ -714,6 +782,7 @@ fn parse_delta_ply<T: AsyncBufRead + Unpin + 'static>(
                             splats.sh_coeffs.val(),
                             splats.raw_opacity.val(),
                         ),
+                        attributes: HashMap::new(),
                     })
                     .await;
 
@@ -724,3 +793,90 @@ fn parse_delta_ply<T: AsyncBufRead + Unpin + 'static>(
         Ok(())
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{interleave_coeffs, load_splat_from_ply, max_rest_coeffs};
+    use crate::splat_export::splat_to_ply_with_attributes;
+    use brush_render::{MainBackend, gaussian_splats::Splats, sh::sh_degree_from_coeffs};
+    use burn::{backend::wgpu::WgpuDevice, tensor::Tensor};
+    use glam::Vec3;
+    use tokio_stream::StreamExt;
+
+    #[test]
+    fn sh_degree_is_capped_while_reading() {
+        // Degree 3 coefficients in the inria layout, 15 per channel.
+        let rest: Vec<f32> = (0..45).map(|i| i as f32).collect();
+
+        for (max_degree, expected_degree) in [(None, 3), (Some(1), 1), (Some(0), 0), (Some(5), 3)] {
+            let mut coeffs = vec![];
+            interleave_coeffs(Vec3::ONE, &rest, max_rest_coeffs(max_degree), &mut coeffs);
+            assert_eq!(
+                sh_degree_from_coeffs(coeffs.len() as u32 / 3),
+                expected_degree,
+                "Wrong degree when capping at {max_degree:?}"
+            );
+        }
+
+        // The kept coefficients are the lowest bands of each channel.
+        let mut coeffs = vec![];
+        interleave_coeffs(Vec3::ONE, &rest, max_rest_coeffs(Some(1)), &mut coeffs);
+        assert_eq!(
+            coeffs,
+            [
+                1.0, 1.0, 1.0, 0.0, 15.0, 30.0, 1.0, 16.0, 31.0, 2.0, 17.0, 32.0
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn extra_attributes_survive_round_trip() {
+        let device = WgpuDevice::DefaultDevice;
+        let means = [Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z];
+        let splats = Splats::<MainBackend>::from_raw(&means, None, None, None, None, &device);
+        let classes = [3.0, 0.0, 7.0, 255.0];
+        let attributes = HashMap::from([(
+            "class".to_owned(),
+            Tensor::<MainBackend, 1>::from_floats(classes, &device),
+        )]);
+
+        let ply = splat_to_ply_with_attributes(splats, &attributes)
+            .await
+            .expect("Failed to write ply");
+        let mut stream = std::pin::pin!(load_splat_from_ply(
+            std::io::Cursor::new(ply),
+            None,
+            None,
+            device,
+        ));
+        let mut last = None;
+        while let Some(message) = stream.next().await {
+            last = Some(message.expect("Failed to read ply"));
+        }
+        let message = last.expect("Must load splats");
+
+        assert_eq!(message.splats.num_splats(), 4);
+        assert_eq!(message.attributes.len(), 1, "Only the class is extra");
+        let loaded = message.attributes["class"]
+            .clone()
+            .into_data_async()
+            .await
+            .to_vec::<f32>()
+            .expect("Wrong tensor type");
+        assert_eq!(loaded, classes);
+
+        for name in ["opacity", "nx"] {
+            let bad = HashMap::from([(
+                name.to_owned(),
+                Tensor::<MainBackend, 1>::from_floats(classes, &device),
+            )]);
+            let splats = Splats::<MainBackend>::from_raw(&means, None, None, None, None, &device);
+            assert!(
+                splat_to_ply_with_attributes(splats, &bad).await.is_err(),
+                "Attribute {name} can't replace a standard property"
+            );
+        }
+    }
+}
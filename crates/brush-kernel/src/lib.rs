@@ -3,7 +3,7 @@
 // generated by the macro below.
 mod shaders;
 
-use burn::tensor::{DType, Shape};
+use burn::tensor::{DType, Element, Shape};
 pub use burn_cubecl::cubecl::prelude::ExecutionMode;
 use burn_cubecl::cubecl::server::{Bindings, MetadataBinding};
 pub use burn_cubecl::cubecl::{
@@ -58,6 +58,13 @@ pub fn module_to_compiled<C: Compiler>(
     }
 }
 
+/// Override the workgroup size of the entry point of a compute shader.
+pub fn set_workgroup_size(module: &mut naga::Module, workgroup_size: [u32; 3]) {
+    for entry_point in &mut module.entry_points {
+        entry_point.workgroup_size = workgroup_size;
+    }
+}
+
 pub fn calc_kernel_id<T: 'static>(values: &[bool]) -> KernelId {
     let mut kernel_id = KernelId::new::<T>();
 
@@ -76,6 +83,7 @@ macro_rules! kernel_source_gen {
             $(
                 $field_name: bool,
             )*
+            workgroup_size: [u32; 3],
         }
 
         impl $struct_name {
@@ -84,11 +92,22 @@ macro_rules! kernel_source_gen {
                     $(
                         $field_name,
                     )*
+                    workgroup_size: Self::WORKGROUP_SIZE,
                 };
 
                 Box::new(kernel)
             }
 
+            /// Compile the kernel with a different workgroup size than the shader declares.
+            ///
+            /// Only valid for kernels whose results don't depend on the workgroup size, eg.
+            /// kernels that only index by the global invocation id.
+            #[allow(dead_code)]
+            pub fn with_workgroup_size(mut self: Box<Self>, workgroup_size: [u32; 3]) -> Box<Self> {
+                self.workgroup_size = workgroup_size;
+                self
+            }
+
             fn create_shader_hashmap(&self) -> std::collections::HashMap<String, naga_oil::compose::ShaderDefValue> {
                 let map = std::collections::HashMap::new();
                 $(
@@ -111,7 +130,7 @@ macro_rules! kernel_source_gen {
 
         impl<C: brush_kernel::Compiler> brush_kernel::CubeTask<C> for $struct_name {
             fn id(&self) -> brush_kernel::KernelId {
-                brush_kernel::calc_kernel_id::<Self>(&[$(self.$field_name),*])
+                brush_kernel::calc_kernel_id::<Self>(&[$(self.$field_name),*]).info(self.workgroup_size)
             }
 
             fn compile(
@@ -120,8 +139,9 @@ macro_rules! kernel_source_gen {
                 _compilation_options: &C::CompilationOptions,
                 _mode: brush_kernel::ExecutionMode
             ) -> brush_kernel::CompiledKernel<C> {
-                let module = self.source();
-                brush_kernel::module_to_compiled(stringify!($struct_name), &module, Self::WORKGROUP_SIZE)
+                let mut module = self.source();
+                brush_kernel::set_workgroup_size(&mut module, self.workgroup_size);
+                brush_kernel::module_to_compiled(stringify!($struct_name), &module, self.workgroup_size)
             }
         }
     };
@@ -157,6 +177,83 @@ pub fn create_tensor<const D: usize, R: CubeRuntime>(
     CubeTensor::new_contiguous(client.clone(), device.clone(), shape, buffer, dtype)
 }
 
+use shaders::fill;
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct FillBuffer {}
+
+impl<C: Compiler> CubeTask<C> for FillBuffer {
+    fn id(&self) -> KernelId {
+        KernelId::new::<Self>()
+    }
+
+    fn compile(
+        &self,
+        _compiler: &mut C,
+        _compilation_options: &C::CompilationOptions,
+        _mode: ExecutionMode,
+    ) -> CompiledKernel<C> {
+        module_to_compiled(
+            "FillBuffer",
+            &fill::create_shader_source(Default::default()),
+            fill::WORKGROUP_SIZE,
+        )
+    }
+}
+
+/// Like [`create_tensor`], but fills the buffer with `value` on the GPU.
+///
+/// The tensor has the dtype of `value`, which must be a 32 or 64 bit type.
+pub fn create_tensor_filled<const D: usize, R: CubeRuntime, E: Element>(
+    shape: [usize; D],
+    device: &R::Device,
+    client: &ComputeClient<R::Server, R::Channel>,
+    value: E,
+) -> CubeTensor<R> {
+    let tensor = create_tensor(shape, device, client, E::dtype());
+    let bytes = bytemuck::bytes_of(&value);
+    let word = |offset: usize| {
+        u32::from_le_bytes(
+            bytes[offset..offset + 4]
+                .try_into()
+                .expect("Words are 4 bytes"),
+        )
+    };
+    let (pattern_lo, pattern_hi) = match bytes.len() {
+        4 => (word(0), word(0)),
+        8 => (word(0), word(4)),
+        _ => panic!("Can only fill tensors of 32 or 64 bit types"),
+    };
+
+    let num_words = tensor.shape.num_elements() * bytes.len() / 4;
+    if num_words == 0 {
+        return tensor;
+    }
+    let data = create_meta_binding(fill::Uniforms {
+        pattern_lo,
+        pattern_hi,
+        num_words: num_words as u32,
+    });
+
+    // Each thread fills multiple words, so the dispatch stays within the limits of one dimension.
+    let num_wgs = (num_words as u32)
+        .div_ceil(fill::WORKGROUP_SIZE[0])
+        .min(u32::from(u16::MAX));
+
+    // SAFETY: wgsl FFI, kernel checked to have no OOB, bounded loops.
+    unsafe {
+        client.execute_unchecked(
+            Box::new(FillBuffer {}),
+            CubeCount::Static(num_wgs, 1, 1),
+            Bindings::new()
+                .with_buffers(vec![tensor.handle.clone().binding()])
+                .with_metadata(data),
+        );
+    }
+
+    tensor
+}
+
 pub fn create_meta_binding<T: Pod>(val: T) -> MetadataBinding {
     // Copy data to u32. If length of T is not % 4, this will correctly
     // pad with zeros.
@@ -211,8 +308,23 @@ pub fn create_dispatch_buffer<R: CubeRuntime>(
     thread_nums: CubeTensor<R>,
     wg_size: [u32; 3],
 ) -> CubeTensor<R> {
+    let ret = create_tensor([3], &thread_nums.device, &thread_nums.client, DType::I32);
+    write_dispatch_buffer(thread_nums, wg_size, &ret);
+    ret
+}
+
+/// Like [`create_dispatch_buffer`], but overwrites an existing buffer of 3 ints, so the same
+/// buffer can be reused for every frame.
+pub fn write_dispatch_buffer<R: CubeRuntime>(
+    thread_nums: CubeTensor<R>,
+    wg_size: [u32; 3],
+    out: &CubeTensor<R>,
+) {
+    assert!(
+        out.shape.dims == [3] && out.dtype == DType::I32,
+        "Dispatch buffers must be 3 ints"
+    );
     let client = thread_nums.client;
-    let ret = create_tensor([3], &thread_nums.device, &client, DType::I32);
 
     let data = create_meta_binding(wg::Uniforms {
         wg_size_x: wg_size[0] as i32,
@@ -228,11 +340,76 @@ pub fn create_dispatch_buffer<R: CubeRuntime>(
             Bindings::new()
                 .with_buffers(vec![
                     thread_nums.handle.binding(),
-                    ret.clone().handle.binding(),
+                    out.handle.clone().binding(),
                 ])
                 .with_metadata(data),
         );
     }
+}
 
-    ret
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use super::{
+        create_dispatch_buffer, create_tensor, create_tensor_filled, write_dispatch_buffer,
+    };
+    use burn::backend::wgpu::{WgpuDevice, WgpuRuntime};
+    use burn::tensor::{DType, Int, Tensor, TensorPrimitive};
+    use burn_cubecl::CubeBackend;
+    use burn_cubecl::cubecl::Runtime;
+
+    type Backend = CubeBackend<WgpuRuntime, f32, i32, u32>;
+
+    #[test]
+    fn filled_tensors_read_back() {
+        let device = WgpuDevice::DefaultDevice;
+        let client = WgpuRuntime::client(&device);
+
+        let floats = create_tensor_filled::<2, WgpuRuntime, f32>([1000, 3], &device, &client, 3.5);
+        let floats = Tensor::<Backend, 2>::from_primitive(TensorPrimitive::Float(floats))
+            .into_data()
+            .to_vec::<f32>()
+            .expect("Wrong type");
+        assert_eq!(floats.len(), 3000);
+        assert!(
+            floats.iter().all(|&v| v.to_bits() == 3.5f32.to_bits()),
+            "Floats must be filled"
+        );
+
+        let ints = create_tensor_filled::<1, WgpuRuntime, i32>([777], &device, &client, -7);
+        let ints = Tensor::<Backend, 1, Int>::from_primitive(ints)
+            .into_data()
+            .to_vec::<i32>()
+            .expect("Wrong type");
+        assert_eq!(ints, vec![-7; 777]);
+
+        // Both words of 64 bit values must end up in the right place.
+        let value = 0x0123_4567_89ab_cdef_u64;
+        let wide = create_tensor_filled::<1, WgpuRuntime, u64>([513], &device, &client, value);
+        let wide = Tensor::<Backend, 1, Int>::from_primitive(wide)
+            .into_data()
+            .to_vec::<u64>()
+            .expect("Wrong type");
+        assert_eq!(wide, vec![value; 513]);
+    }
+
+    #[test]
+    fn reused_dispatch_buffer_matches_fresh() {
+        let device = WgpuDevice::DefaultDevice;
+        let client = WgpuRuntime::client(&device);
+        let read = |tensor| {
+            Tensor::<Backend, 1, Int>::from_primitive(tensor)
+                .into_data()
+                .to_vec::<i32>()
+                .expect("Wrong type")
+        };
+
+        let reused = create_tensor::<1, WgpuRuntime>([3], &device, &client, DType::I32);
+        for count in [1000, 1, 70000] {
+            let threads = Tensor::<Backend, 1, Int>::from_ints([count], &device);
+            write_dispatch_buffer(threads.clone().into_primitive(), [256, 1, 1], &reused);
+            let fresh = create_dispatch_buffer(threads.into_primitive(), [256, 1, 1]);
+            assert_eq!(read(reused.clone()), read(fresh));
+            assert_eq!(read(reused.clone()), vec![(count + 255) / 256, 1, 1]);
+        }
+    }
 }
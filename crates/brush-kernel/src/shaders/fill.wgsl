@@ -0,0 +1,25 @@
+struct Uniforms {
+    // Pattern of two words to fill with, so 64 bit values can be filled too.
+    pattern_lo: u32,
+    pattern_hi: u32,
+    num_words: u32,
+}
+
+@group(0) @binding(0) var<storage, read_write> out: array<u32>;
+
+@group(0) @binding(1) var<storage, read> uniforms: Uniforms;
+
+const WG: u32 = 256u;
+
+@compute
+@workgroup_size(WG, 1, 1)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+) {
+    // The number of workgroups is limited, so each thread fills a strided range of words.
+    let stride = num_workgroups.x * WG;
+    for (var i = global_id.x; i < uniforms.num_words; i += stride) {
+        out[i] = select(uniforms.pattern_lo, uniforms.pattern_hi, i % 2u == 1u);
+    }
+}
@@ -3,9 +3,10 @@ mod shaders;
 use brush_kernel::calc_cube_count;
 use brush_kernel::create_tensor;
 use brush_kernel::kernel_source_gen;
+use burn::prelude::Backend;
 use burn::tensor::DType;
 use burn_cubecl::cubecl::server::Bindings;
-use burn_wgpu::WgpuRuntime;
+use burn_wgpu::{CubeBackend, WgpuDevice, WgpuRuntime};
 use shaders::prefix_sum_add_scanned_sums;
 use shaders::prefix_sum_scan;
 use shaders::prefix_sum_scan_sums;
@@ -16,6 +17,71 @@ kernel_source_gen!(PrefixSumAddScannedSums {}, prefix_sum_add_scanned_sums);
 
 use burn_wgpu::CubeTensor;
 
+type Base = CubeBackend<WgpuRuntime, f32, i32, u32>;
+
+/// Counts of a number of items, laid out to be turned into exclusive offsets by [`prefix_sum`].
+///
+/// The buffer holds one more element than there are items: index 0 stays zero, and kernels write
+/// the count of item `i` at index `i + 1`. After [`Self::scan`], index `i` holds the offset of
+/// item `i`, index `i + 1` the end of its range, and the last index the total of all counts.
+#[derive(Debug, Clone)]
+pub struct ExclusiveScanBuffer {
+    buffer: CubeTensor<WgpuRuntime>,
+}
+
+impl ExclusiveScanBuffer {
+    /// Zero counts for `len` items. Zero counts are already their own scan.
+    pub fn zeros(len: usize, device: &WgpuDevice) -> Self {
+        Self {
+            buffer: Base::int_zeros([len + 1].into(), device),
+        }
+    }
+
+    /// Wrap a buffer of `len + 1` int counts, with the count of item `i` at index `i + 1` and a
+    /// zero at index 0.
+    pub fn from_buffer(buffer: CubeTensor<WgpuRuntime>) -> Self {
+        assert!(
+            buffer.shape.num_dims() == 1 && buffer.shape.dims[0] > 0 && buffer.dtype == DType::I32,
+            "Exclusive scans need a 1D int buffer with room for the leading zero."
+        );
+        Self { buffer }
+    }
+
+    /// Number of items, which is one less than the length of the buffer.
+    pub fn len(&self) -> usize {
+        self.buffer.shape.dims[0] - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The underlying buffer, to bind to kernels.
+    pub fn buffer(&self) -> &CubeTensor<WgpuRuntime> {
+        &self.buffer
+    }
+
+    /// Scan the counts into offsets, see [`prefix_sum`].
+    pub fn scan(self) -> Self {
+        Self {
+            buffer: prefix_sum(self.buffer),
+        }
+    }
+
+    /// The total of all counts after [`Self::scan`], as a tensor with the last element.
+    ///
+    /// This stays on the GPU, eg. to size an indirect dispatch, without waiting for the scan.
+    pub fn total(&self) -> CubeTensor<WgpuRuntime> {
+        let len = self.len();
+        Base::int_slice(self.buffer.clone(), &[len..len + 1])
+    }
+
+    /// The `len + 1` offsets after [`Self::scan`], ending with the total.
+    pub fn into_offsets(self) -> CubeTensor<WgpuRuntime> {
+        self.buffer
+    }
+}
+
 pub fn prefix_sum(input: CubeTensor<WgpuRuntime>) -> CubeTensor<WgpuRuntime> {
     let threads_per_group = shaders::prefix_sum_helpers::THREADS_PER_GROUP as usize;
     let num = input.shape.dims[0];
@@ -114,12 +180,62 @@ pub fn prefix_sum(input: CubeTensor<WgpuRuntime>) -> CubeTensor<WgpuRuntime> {
 
 #[cfg(all(test, not(target_family = "wasm")))]
 mod tests {
-    use crate::prefix_sum;
+    use crate::{ExclusiveScanBuffer, prefix_sum};
     use burn::tensor::{Int, Tensor};
     use burn_wgpu::{CubeBackend, WgpuRuntime};
 
     type Backend = CubeBackend<WgpuRuntime, f32, i32, u32>;
 
+    fn read(tensor: burn_wgpu::CubeTensor<WgpuRuntime>) -> Vec<i32> {
+        Tensor::<Backend, 1, Int>::from_primitive(tensor)
+            .into_data()
+            .into_vec()
+            .expect("Wrong type")
+    }
+
+    #[test]
+    fn exclusive_scan_of_nothing() {
+        let device = Default::default();
+        let scan = ExclusiveScanBuffer::zeros(0, &device);
+        assert!(scan.is_empty(), "No items were allocated");
+        let scan = scan.scan();
+        assert_eq!(read(scan.total()), [0]);
+        assert_eq!(read(scan.into_offsets()), [0]);
+    }
+
+    #[test]
+    fn exclusive_scan_of_single_count() {
+        let device = Default::default();
+        let counts = Tensor::<Backend, 1, Int>::from_data([0, 7], &device).into_primitive();
+        let scan = ExclusiveScanBuffer::from_buffer(counts);
+        assert_eq!(scan.len(), 1);
+        let scan = scan.scan();
+        assert_eq!(read(scan.total()), [7]);
+        assert_eq!(read(scan.into_offsets()), [0, 7]);
+    }
+
+    #[test]
+    fn exclusive_scan_offsets_end_with_total() {
+        let device = Default::default();
+        let counts: Vec<i32> = (0..1000).map(|i| i % 5).collect();
+        let buffer: Vec<i32> = std::iter::once(0).chain(counts.iter().copied()).collect();
+        let buffer =
+            Tensor::<Backend, 1, Int>::from_data(buffer.as_slice(), &device).into_primitive();
+        let scan = ExclusiveScanBuffer::from_buffer(buffer).scan();
+        assert_eq!(read(scan.total()), [counts.iter().sum::<i32>()]);
+
+        let offsets = read(scan.into_offsets());
+        assert_eq!(offsets.len(), counts.len() + 1);
+        for (i, count) in counts.iter().enumerate() {
+            assert_eq!(
+                offsets[i + 1] - offsets[i],
+                *count,
+                "Wrong range of item {i}"
+            );
+        }
+        assert_eq!(offsets[0], 0);
+    }
+
     #[test]
     fn test_sum_tiny() {
         let device = Default::default();
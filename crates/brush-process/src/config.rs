@@ -41,6 +41,17 @@ pub struct ProcessConfig {
     #[config(default = 0)]
     #[arg(long, help_heading = "Process options", default_value = "0")]
     pub start_iter: u32,
+
+    /// Save a checkpoint of the full training state every this many steps, to resume training
+    /// from later with resume-from. Uses export-path for the file location.
+    #[arg(long, help_heading = "Process options")]
+    pub checkpoint_every: Option<u32>,
+
+    /// Resume training from a checkpoint saved with checkpoint-every. Training continues at the
+    /// step the checkpoint was saved at, with its splats and optimizer state, instead of the
+    /// initial splats and start-iter. Use the same training options as the checkpointed run.
+    #[arg(long, help_heading = "Process options")]
+    pub resume_from: Option<String>,
 }
 
 #[derive(Config, Args)]
@@ -37,7 +37,7 @@ pub fn process_stream(
         );
 
         if vfs_counts == ply_count {
-            view_stream(vfs, device, emitter).await?;
+            view_stream(vfs, process_args.load_config.max_sh_degree, device, emitter).await?;
         } else {
             train_stream(vfs, process_args, device, emitter).await?;
         };
@@ -7,17 +7,19 @@ use crate::{
 };
 use anyhow::Context;
 use async_fn_stream::TryStreamEmitter;
-use brush_dataset::scene_loader::SceneLoader;
-use brush_render::{
-    MainBackend,
-    gaussian_splats::{RandomSplatsConfig, Splats},
+use brush_dataset::scene_loader::{DEFAULT_CACHE_MB, SceneLoader};
+use brush_render::{MainBackend, gaussian_splats::RandomSplatsConfig};
+use brush_train::{
+    config::ViewSampling,
+    eval::eval_stats,
+    init::{InitPoints, init_options, seeded_init_splats},
+    train::SplatTrainer,
+    view_sampling::ViewLossEstimates,
 };
-use brush_train::{eval::eval_stats, train::SplatTrainer};
 use brush_vfs::BrushVfs;
 use burn::{module::AutodiffModule, prelude::Backend};
 use burn_cubecl::cubecl::Runtime;
 use burn_wgpu::{WgpuDevice, WgpuRuntime};
-use rand::SeedableRng;
 use tokio_stream::StreamExt;
 use web_time::{Duration, Instant};
 
@@ -40,11 +42,15 @@ pub(crate) async fn train_stream(
 
     log::info!("Using seed {}", process_config.seed);
     <MainBackend as Backend>::seed(process_config.seed);
-    let mut rng = rand::rngs::StdRng::from_seed([process_config.seed as u8; 32]);
 
     log::info!("Loading dataset");
-    let (mut splat_stream, dataset) =
-        brush_dataset::load_dataset(vfs.clone(), &process_args.load_config, &device).await?;
+    let (mut splat_stream, dataset) = brush_dataset::load_dataset(
+        vfs.clone(),
+        &process_args.load_config,
+        &init_options(&process_args.train_config),
+        &device,
+    )
+    .await?;
     log::info!("Dataset loaded");
     emitter
         .emit(ProcessMessage::Dataset {
@@ -52,7 +58,9 @@ pub(crate) async fn train_stream(
         })
         .await;
 
-    visualize.log_scene(&dataset.train, process_args.rerun_config.rerun_max_img_size)?;
+    visualize
+        .log_scene(&dataset.train, process_args.rerun_config.rerun_max_img_size)
+        .await?;
 
     let estimated_up = dataset.estimate_up();
 
@@ -71,6 +79,7 @@ pub(crate) async fn train_stream(
             total_frames: 0,
         };
         emitter.emit(msg).await;
+        // Extra attributes aren't trained, so they're dropped here.
         initial_splats = Some(message.splats);
     }
 
@@ -90,27 +99,82 @@ pub(crate) async fn train_stream(
             .train
             .adjusted_bounds(bounds_extent * 0.25, bounds_extent);
         let config = RandomSplatsConfig::new();
-
-        Splats::from_random_config(&config, adjusted_bounds, &mut rng, &device)
+        let seed = process_args
+            .train_config
+            .init_seed
+            .unwrap_or(process_config.seed);
+
+        seeded_init_splats(
+            seed,
+            config.init_count,
+            &InitPoints::Bounds(adjusted_bounds),
+            &init_options(&process_args.train_config),
+            &device,
+        )
     };
 
     let splats = splats.with_sh_degree(process_args.model_config.sh_degree);
+    let splats = if process_args.train_config.grayscale {
+        splats.to_grayscale()
+    } else {
+        splats
+    };
+    let splats = splats.with_opacity_activation(process_args.train_config.opacity_activation);
     let mut splats = splats.into_autodiff();
 
     let mut eval_scene = dataset.eval;
     let scene_extent = dataset.train.estimate_extent().unwrap_or(1.0);
 
     let mut train_duration = Duration::from_secs(0);
-    let mut dataloader = SceneLoader::new(&dataset.train, 42, &device);
-    let mut trainer = SplatTrainer::new(&process_args.train_config, &device);
+    let cache_mb = process_args
+        .load_config
+        .image_cache_mb
+        .unwrap_or(DEFAULT_CACHE_MB);
+    let mut dataloader = SceneLoader::new(&dataset.train, 42, cache_mb, &device);
+    let mut train_config = process_args.train_config.clone();
+    train_config.refine_seed = train_config.refine_seed.or(Some(process_config.seed));
+    let mut trainer = SplatTrainer::new(&train_config, &device);
+    let mut view_losses = (train_config.view_sampling == ViewSampling::LossWeighted)
+        .then(|| ViewLossEstimates::new(dataset.train.views.len()));
+
+    let start_iter = if let Some(path) = &process_config.resume_from {
+        log::info!("Resuming from checkpoint {path}");
+        let checkpoint = read_checkpoint(path).await?;
+        let (iter, resumed) = trainer
+            .load_checkpoint(&checkpoint, &device)
+            .with_context(|| format!("Failed to load checkpoint {path}"))?;
+        splats = resumed;
+        iter
+    } else {
+        process_config.start_iter
+    };
 
     log::info!("Start training loop.");
-    for iter in process_args.process_config.start_iter..process_args.train_config.total_steps {
+    for iter in start_iter..process_args.train_config.total_steps {
         let step_time = Instant::now();
 
-        let batch = dataloader.next_batch().await;
-        let (new_splats, stats) = trainer.step(scene_extent, iter, &batch, splats);
+        let mut batches = vec![];
+        for _ in 0..process_args.train_config.grad_accum_views.max(1) {
+            let batch = dataloader.next_batch().await;
+            batches.push(if process_args.train_config.grayscale {
+                batch.to_grayscale()
+            } else {
+                batch
+            });
+        }
+        let (new_splats, stats) = trainer.step_batches(scene_extent, iter, &batches, splats);
         splats = new_splats;
+
+        // Reading back the losses waits for the step to finish, so only do it when needed.
+        if let Some(estimates) = view_losses.as_mut() {
+            for (batch, loss) in batches.iter().zip(&stats.view_losses) {
+                if let Some(view) = batch.view_index {
+                    estimates.record(view, loss.clone().into_scalar_async().await);
+                }
+            }
+            let weights = estimates.weights(train_config.view_sampling_temperature);
+            dataloader.set_view_weights(Some(&weights)).await;
+        }
         let (new_splats, refine) = trainer.refine_if_needed(iter, splats).await;
         splats = new_splats;
 
@@ -193,6 +257,22 @@ pub(crate) async fn train_stream(
                 .with_context(|| format!("Failed to export ply {export_path:?}"))?;
         }
 
+        #[cfg(not(target_family = "wasm"))]
+        if let Some(every) = process_config.checkpoint_every {
+            if iter % every == 0 || is_last_step {
+                let total_steps = process_args.train_config.total_steps;
+                let digits = (total_steps as f64).log10().ceil() as usize;
+                let checkpoint_path = export_path.join(format!("checkpoint_{iter:0digits$}.ckpt"));
+
+                tokio::fs::create_dir_all(&export_path).await?;
+
+                let checkpoint = trainer.save_checkpoint(iter, &splats)?;
+                tokio::fs::write(&checkpoint_path, checkpoint)
+                    .await
+                    .with_context(|| format!("Failed to save checkpoint {checkpoint_path:?}"))?;
+            }
+        }
+
         if let Some(every) = process_args.rerun_config.rerun_log_splats_every {
             if iter % every == 0 || is_last_step {
                 visualize.log_splats(iter, splats.valid()).await?;
@@ -203,7 +283,11 @@ pub(crate) async fn train_stream(
 
         // Log out train stats.
         if iter % process_args.rerun_config.rerun_log_train_stats_every == 0 || is_last_step {
-            visualize.log_train_stats(iter, stats.clone()).await?;
+            // The rendered image is of the last view of the step.
+            let camera = &batches.last().expect("Need at least one view").camera;
+            visualize
+                .log_train_stats(iter, camera, stats.clone())
+                .await?;
         }
 
         // Add up time from this step.
@@ -236,3 +320,16 @@ pub(crate) async fn train_stream(
 
     Ok(())
 }
+
+/// Read a checkpoint saved by a previous run.
+#[cfg(not(target_family = "wasm"))]
+async fn read_checkpoint(path: &str) -> anyhow::Result<Vec<u8>> {
+    tokio::fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read checkpoint {path}"))
+}
+
+#[cfg(target_family = "wasm")]
+async fn read_checkpoint(path: &str) -> anyhow::Result<Vec<u8>> {
+    anyhow::bail!("Resuming from a checkpoint isn't supported on the web, can't read {path}")
+}
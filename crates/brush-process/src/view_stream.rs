@@ -11,6 +11,7 @@ use tokio_stream::StreamExt;
 
 pub(crate) async fn view_stream(
     vfs: Arc<BrushVfs>,
+    max_sh_degree: Option<u32>,
     device: WgpuDevice,
     emitter: TryStreamEmitter<ProcessMessage, anyhow::Error>,
 ) -> anyhow::Result<()> {
@@ -32,6 +33,7 @@ pub(crate) async fn view_stream(
         let splat_stream = splat_import::load_splat_from_ply(
             vfs.reader_at_path(path).await?,
             sub_sample,
+            max_sh_degree,
             device.clone(),
         );
 
@@ -1,17 +1,22 @@
 #![allow(unused_imports)]
 
+/// Logs training progress to a rerun.io recording.
+///
+/// Only does anything when built with the `rerun` feature, on other builds all methods are no-ops.
 pub struct VisualizeTools {
-    #[cfg(not(target_family = "wasm"))]
+    #[cfg(all(feature = "rerun", not(target_family = "wasm")))]
     rec: rerun::RecordingStream,
 }
 
-#[cfg(not(target_family = "wasm"))]
+#[cfg(all(feature = "rerun", not(target_family = "wasm")))]
 mod visualize_tools_impl {
     use std::sync::Arc;
 
     use brush_dataset::scene::Scene;
+    use brush_render::camera::Camera;
     use brush_render::gaussian_splats::Splats;
     use brush_render::shaders::project_visible::SH_C0;
+    use brush_rerun::burn_to_rerun::BurnToImage;
     use brush_train::eval::EvalSample;
     use brush_train::msg::{RefineStats, TrainStepStats};
     use burn::prelude::Backend;
@@ -27,6 +32,22 @@ mod visualize_tools_impl {
 
     use super::VisualizeTools;
 
+    fn camera_archetypes(
+        camera: &Camera,
+        img_size: glam::UVec2,
+    ) -> (rerun::Pinhole, rerun::Transform3D) {
+        let focal = camera.focal(img_size);
+        let center = camera.center(img_size);
+        let pinhole = rerun::Pinhole::from_focal_length_and_resolution(
+            [focal.x, focal.y],
+            [img_size.x as f32, img_size.y as f32],
+        )
+        .with_principal_point([center.x, center.y]);
+        let transform =
+            rerun::Transform3D::from_translation_rotation(camera.position, camera.rotation);
+        (pinhole, transform)
+    }
+
     impl VisualizeTools {
         #[allow(unused_variables)]
         pub fn new(enabled: bool) -> Self {
@@ -112,30 +133,34 @@ mod visualize_tools_impl {
             Ok(())
         }
 
-        #[allow(unused_variables)]
-        pub fn log_scene(&self, scene: &Scene, max_img_size: u32) -> Result<()> {
+        pub async fn log_scene(&self, scene: &Scene, max_img_size: u32) -> Result<()> {
             if self.rec.is_enabled() {
                 self.rec
                     .log_static("world", &rerun::ViewCoordinates::RIGHT_HAND_Y_DOWN())?;
                 for (i, view) in scene.views.iter().enumerate() {
                     let path = format!("world/dataset/camera/{i}");
 
-                    let focal = view.camera.focal(glam::uvec2(1, 1));
-
-                    self.rec.log_static(
-                        path.clone(),
-                        &rerun::Pinhole::from_fov_and_aspect_ratio(
-                            view.camera.fov_y as f32,
-                            focal.x / focal.y,
-                        ),
-                    )?;
-                    self.rec.log_static(
-                        path.clone(),
-                        &rerun::Transform3D::from_translation_rotation(
-                            view.camera.position,
-                            view.camera.rotation,
-                        ),
-                    )?;
+                    let mut img = view.image.load().await?;
+                    if img.width().max(img.height()) > max_img_size {
+                        img = img.resize(
+                            max_img_size,
+                            max_img_size,
+                            image::imageops::FilterType::Triangle,
+                        );
+                    }
+                    let [w, h] = [img.width(), img.height()];
+
+                    // The dataset doesn't change, so log it as static data.
+                    let (pinhole, transform) = camera_archetypes(&view.camera, glam::uvec2(w, h));
+                    self.rec.log_static(path.clone(), &pinhole)?;
+                    self.rec.log_static(path.clone(), &transform)?;
+
+                    let rerun_img = if img.color().has_alpha() {
+                        rerun::Image::from_rgba32(img.into_rgba8().into_vec(), [w, h])
+                    } else {
+                        rerun::Image::from_rgb24(img.into_rgb8().into_vec(), [w, h])
+                    };
+                    self.rec.log_static(format!("{path}/image"), &rerun_img)?;
                 }
             }
 
@@ -239,6 +264,7 @@ mod visualize_tools_impl {
         pub async fn log_train_stats<B: Backend>(
             &self,
             iter: u32,
+            camera: &Camera,
             stats: TrainStepStats<B>,
         ) -> Result<()> {
             if self.rec.is_enabled() {
@@ -271,8 +297,19 @@ mod visualize_tools_impl {
                     ]),
                 )?;
 
-                let [img_h, img_w, _] = stats.pred_image.dims();
-                let pred_rgb = stats.pred_image.clone().slice(s![.., .., 0..3]);
+                let [img_h, img_w, img_c] = stats.pred_image.dims();
+                // Drop the alpha channel, after the color or gray channels.
+                let pred_rgb = stats.pred_image.clone().slice(s![.., .., 0..img_c - 1]);
+
+                // Show the render from the viewpoint of the training camera.
+                let (pinhole, transform) =
+                    camera_archetypes(camera, glam::uvec2(img_w as u32, img_h as u32));
+                self.rec.log("world/train/camera", &pinhole)?;
+                self.rec.log("world/train/camera", &transform)?;
+                self.rec.log(
+                    "world/train/camera/render",
+                    &pred_rgb.into_rerun_image().await,
+                )?;
 
                 self.rec.log(
                     "losses/main",
@@ -329,11 +366,12 @@ mod visualize_tools_impl {
     }
 }
 
-#[cfg(target_family = "wasm")]
+#[cfg(not(all(feature = "rerun", not(target_family = "wasm"))))]
 mod visualize_tools_impl {
     use std::sync::Arc;
 
     use brush_dataset::scene::Scene;
+    use brush_render::camera::Camera;
     use brush_render::gaussian_splats::Splats;
     use brush_train::eval::EvalSample;
     use brush_train::msg::{RefineStats, TrainStepStats};
@@ -347,7 +385,12 @@ mod visualize_tools_impl {
     use burn_cubecl::cubecl::MemoryUsage;
 
     impl VisualizeTools {
-        pub fn new(_enabled: bool) -> Self {
+        pub fn new(enabled: bool) -> Self {
+            if enabled {
+                log::warn!(
+                    "Rerun logging was requested, but brush was built without rerun support"
+                );
+            }
             Self {}
         }
 
@@ -355,9 +398,7 @@ mod visualize_tools_impl {
             Ok(())
         }
 
-        #[allow(unused_variables)]
-        #[allow(clippy::unnecessary_wraps, clippy::unused_self)]
-        pub fn log_scene(&self, _scene: &Scene, _max_img_size: u32) -> Result<()> {
+        pub async fn log_scene(&self, _scene: &Scene, _max_img_size: u32) -> Result<()> {
             Ok(())
         }
 
@@ -386,6 +427,7 @@ mod visualize_tools_impl {
         pub async fn log_train_stats<B: Backend>(
             &self,
             _iter: u32,
+            _camera: &Camera,
             _stats: TrainStepStats<B>,
         ) -> Result<()> {
             Ok(())
@@ -1,7 +1,12 @@
 use brush_render::{
     MainBackendBase, SplatForward,
-    camera::Camera,
+    camera::{Camera, ImageOrigin},
+    gaussian_splats::OpacityActivation,
     render_aux::RenderAux,
+    render_options::{
+        AlphaMode, BlendMode, ChannelOrder, ColorSpace, LUMA_WEIGHTS, OutputDType, PixelRect,
+        RenderOptions,
+    },
     sh::{sh_coeffs_for_degree, sh_degree_from_coeffs},
 };
 use burn::{
@@ -16,9 +21,10 @@ use burn::{
     },
     prelude::Backend,
     tensor::{
-        DType, Tensor, TensorPrimitive,
+        DType, Tensor, TensorMetadata, TensorPrimitive,
         backend::AutodiffBackend,
         ops::{FloatTensor, IntTensor},
+        s,
     },
 };
 use burn_cubecl::{BoolElement, fusion::FusionCubeRuntime};
@@ -35,6 +41,8 @@ pub trait SplatForwardDiff<B: Backend> {
     ///
     /// This projects the gaussians, sorts them, and rasterizes them to a buffer, in a
     /// differentiable way.
+    ///
+    /// Not all [`RenderOptions`] are supported when rendering differentiably.
     #[allow(clippy::too_many_arguments)]
     fn render_splats(
         camera: &Camera,
@@ -44,6 +52,7 @@ pub trait SplatForwardDiff<B: Backend> {
         quats: FloatTensor<B>,
         sh_coeffs: FloatTensor<B>,
         raw_opacity: FloatTensor<B>,
+        options: &RenderOptions,
     ) -> SplatOutputDiff<B>;
 }
 
@@ -68,6 +77,7 @@ impl SplatBackwardOps<Self> for MainBackendBase {
             state.means,
             state.quats,
             state.log_scales,
+            state.raw_opac,
             state.out_img,
             state.projected_splats,
             state.uniforms_buffer,
@@ -76,6 +86,9 @@ impl SplatBackwardOps<Self> for MainBackendBase {
             state.tile_offsets,
             state.final_index,
             state.sh_degree,
+            state.opacity_activation,
+            state.disparity,
+            state.region,
         )
     }
 }
@@ -94,6 +107,13 @@ pub struct GaussianBackwardState<B: Backend> {
     tile_offsets: IntTensor<B>,
     final_index: IntTensor<B>,
     sh_degree: u32,
+    /// Activation the raw opacities were rendered with, see `RenderOptions::opacity_activation`.
+    opacity_activation: Option<OpacityActivation>,
+    /// Whether the depth channel holds the disparity, see `RenderOptions::depth_as_disparity`.
+    disparity: bool,
+    /// Pixels that are backpropagated through, see `RenderOptions::backward_region`. Rows are
+    /// counted from the top, as they're rasterized.
+    region: PixelRect,
 }
 
 #[derive(Debug)]
@@ -175,7 +195,85 @@ impl<B: Backend + SplatBackwardOps<B> + SplatForward<B>, C: CheckpointStrategy>
         quats: FloatTensor<Self>,
         sh_coeffs: FloatTensor<Self>,
         raw_opacity: FloatTensor<Self>,
+        options: &RenderOptions,
     ) -> SplatOutputDiff<Self> {
+        assert!(
+            !options.mip_filter,
+            "The mip filter isn't supported when rendering differentiably."
+        );
+        assert_eq!(
+            options.color_space,
+            ColorSpace::Srgb,
+            "Only sRGB output is supported when rendering differentiably."
+        );
+        assert_eq!(
+            options.output_dtype,
+            OutputDType::F32,
+            "Rendering differentiably requires F32 output."
+        );
+        assert!(
+            options.tile_budget.is_none(),
+            "A tile budget isn't supported when rendering differentiably."
+        );
+        assert!(
+            options.depth_peel_layers.is_none(),
+            "Depth peeling isn't supported when rendering differentiably."
+        );
+        assert!(
+            options.sh_channel_degrees.is_none(),
+            "Per channel sh degrees aren't supported when rendering differentiably."
+        );
+        assert_eq!(
+            options.alpha_mode,
+            AlphaMode::Premultiplied,
+            "Only premultiplied alpha is supported when rendering differentiably."
+        );
+        assert_eq!(
+            options.channel_order,
+            ChannelOrder::Rgba,
+            "Only RGBA output is supported when rendering differentiably."
+        );
+        assert!(
+            options.alpha_gamma.is_none(),
+            "An alpha gamma isn't supported when rendering differentiably."
+        );
+        assert_eq!(
+            options.blend_mode,
+            BlendMode::AlphaOver,
+            "Only blending alpha over is supported when rendering differentiably."
+        );
+        assert!(
+            options.pixel_resort.is_none(),
+            "Per pixel re-sorting isn't supported when rendering differentiably."
+        );
+        assert!(
+            options.tonemap.is_none(),
+            "Tonemapping isn't supported when rendering differentiably."
+        );
+        assert_eq!(
+            raw_opacity.shape().num_dims(),
+            1,
+            "View dependent opacity isn't supported when rendering differentiably."
+        );
+
+        // Gray coefficients are rendered as three equal color channels, whose gradients are
+        // summed back into the gray channel.
+        let sh_coeffs = Tensor::<Self, 3>::from_primitive(TensorPrimitive::Float(sh_coeffs));
+        let sh_coeffs = if sh_coeffs.dims()[2] == 1 {
+            sh_coeffs.repeat_dim(2, 3)
+        } else {
+            sh_coeffs
+        };
+        let sh_coeffs = sh_coeffs.into_primitive().tensor();
+
+        // Grayscale images are rendered in color and converted after, so the backward pass only
+        // ever sees RGBA images.
+        let grayscale = options.grayscale;
+        let options = &RenderOptions {
+            grayscale: false,
+            ..options.clone()
+        };
+
         // Get backend tensors & dequantize if needed. Could try and support quantized inputs
         // in the future.
         let device =
@@ -195,6 +293,16 @@ impl<B: Backend + SplatBackwardOps<B> + SplatForward<B>, C: CheckpointStrategy>
             .compute_bound()
             .stateful();
 
+        let region = options
+            .backward_region
+            .map_or(PixelRect::full(img_size), |region| {
+                region.clamp_to(img_size)
+            });
+        let region = match camera.origin {
+            ImageOrigin::TopLeft => region,
+            ImageOrigin::BottomLeft => region.flip_y(img_size.y),
+        };
+
         // Render complete forward pass.
         let (out_img, aux) = <B as SplatForward<B>>::render_splats(
             camera,
@@ -205,6 +313,7 @@ impl<B: Backend + SplatBackwardOps<B> + SplatForward<B>, C: CheckpointStrategy>
             sh_coeffs.clone().into_primitive(),
             raw_opacity.clone().into_primitive(),
             true,
+            options,
         );
 
         let wrapped_aux = RenderAux::<Self> {
@@ -217,7 +326,7 @@ impl<B: Backend + SplatBackwardOps<B> + SplatForward<B>, C: CheckpointStrategy>
             visible: <Self as AutodiffBackend>::from_inner(aux.visible),
         };
 
-        match prep_nodes {
+        let mut output = match prep_nodes {
             OpsKind::Tracked(prep) => {
                 // Save state needed for backward pass.
                 let state = GaussianBackwardState {
@@ -229,6 +338,7 @@ impl<B: Backend + SplatBackwardOps<B> + SplatForward<B>, C: CheckpointStrategy>
                         Tensor::<Self, 3>::from_primitive(TensorPrimitive::Float(sh_coeffs)).dims()
                             [1] as u32,
                     ),
+                    opacity_activation: options.opacity_activation,
                     out_img: out_img.clone(),
                     projected_splats: aux.projected_splats,
                     uniforms_buffer: aux.uniforms_buffer,
@@ -236,6 +346,8 @@ impl<B: Backend + SplatBackwardOps<B> + SplatForward<B>, C: CheckpointStrategy>
                     tile_offsets: aux.tile_offsets,
                     compact_gid_from_isect: aux.compact_gid_from_isect,
                     global_from_compact_gid: aux.global_from_compact_gid,
+                    disparity: options.depth_as_disparity,
+                    region,
                 };
 
                 let out_img = prep.finish(state, out_img);
@@ -255,10 +367,27 @@ impl<B: Backend + SplatBackwardOps<B> + SplatForward<B>, C: CheckpointStrategy>
                     refine_weight_holder,
                 }
             }
+        };
+
+        if grayscale {
+            output.img = color_to_gray::<Self>(output.img);
         }
+        output
     }
 }
 
+/// Convert a rendered `[H, W, 4 (+ depth)]` image to a `[H, W, 2 (+ depth)]` image, with the luma
+/// of the color followed by the other channels, like the rasterizer does for grayscale renders.
+fn color_to_gray<B: Backend>(img: FloatTensor<B>) -> FloatTensor<B> {
+    let img = Tensor::<B, 3>::from_primitive(TensorPrimitive::Float(img));
+    let channels = img.dims()[2];
+    let weights = Tensor::<B, 1>::from_floats(LUMA_WEIGHTS, &img.device()).reshape([1, 1, 3]);
+    let gray = (img.clone().slice(s![.., .., 0..3]) * weights).sum_dim(2);
+    Tensor::cat(vec![gray, img.slice(s![.., .., 3..channels])], 2)
+        .into_primitive()
+        .tensor()
+}
+
 impl SplatBackwardOps<Self> for Fusion<MainBackendBase> {
     fn render_splats_bwd(
         state: GaussianBackwardState<Self>,
@@ -267,6 +396,9 @@ impl SplatBackwardOps<Self> for Fusion<MainBackendBase> {
         struct CustomOp {
             desc: CustomOpIr,
             sh_degree: u32,
+            opacity_activation: Option<OpacityActivation>,
+            disparity: bool,
+            region: PixelRect,
         }
 
         impl<BT: BoolElement> Operation<FusionCubeRuntime<WgpuRuntime, BT>> for CustomOp {
@@ -307,6 +439,9 @@ impl SplatBackwardOps<Self> for Fusion<MainBackendBase> {
                     global_from_compact_gid: h
                         .get_int_tensor::<MainBackendBase>(global_from_compact_gid),
                     sh_degree: self.sh_degree,
+                    opacity_activation: self.opacity_activation,
+                    disparity: self.disparity,
+                    region: self.region,
                 };
 
                 let grads =
@@ -374,6 +509,9 @@ impl SplatBackwardOps<Self> for Fusion<MainBackendBase> {
                 // state,
                 desc,
                 sh_degree: state.sh_degree,
+                opacity_activation: state.opacity_activation,
+                disparity: state.disparity,
+                region: state.region,
             },
         );
         grads
@@ -1,18 +1,37 @@
 use super::shaders::{project_backwards, rasterize_backwards};
 use crate::shaders::gather_grads;
-use brush_kernel::{CubeCount, CubeTensor, calc_cube_count, kernel_source_gen};
+use brush_kernel::{
+    CubeCount, CubeTensor, calc_cube_count, create_uniform_buffer, kernel_source_gen,
+};
 
 use brush_render::MainBackendBase;
+use brush_render::gaussian_splats::OpacityActivation;
+use brush_render::render::opacity_activation_flags;
+use brush_render::render_options::PixelRect;
 use brush_render::sh::sh_coeffs_for_degree;
+use brush_render::shaders::helpers::TILE_WIDTH;
 use burn::tensor::ops::FloatTensorOps;
 use burn::{backend::wgpu::WgpuRuntime, prelude::Backend, tensor::ops::FloatTensor};
 use burn_cubecl::cubecl::AtomicFeature;
 use burn_cubecl::cubecl::server::Bindings;
 use glam::uvec2;
 
-kernel_source_gen!(GatherGrads {}, gather_grads);
+kernel_source_gen!(
+    GatherGrads {
+        sigmoid_opacity,
+        exp_density_opacity
+    },
+    gather_grads
+);
 kernel_source_gen!(ProjectBackwards {}, project_backwards);
-kernel_source_gen!(RasterizeBackwards { hard_float }, rasterize_backwards);
+kernel_source_gen!(
+    RasterizeBackwards {
+        hard_float,
+        depth_output,
+        disparity
+    },
+    rasterize_backwards
+);
 
 #[derive(Debug, Clone)]
 pub struct SplatGrads<B: Backend> {
@@ -31,6 +50,7 @@ pub(crate) fn render_backward(
     means: CubeTensor<WgpuRuntime>,
     quats: CubeTensor<WgpuRuntime>,
     log_scales: CubeTensor<WgpuRuntime>,
+    raw_opac: CubeTensor<WgpuRuntime>,
     out_img: CubeTensor<WgpuRuntime>,
 
     projected_splats: CubeTensor<WgpuRuntime>,
@@ -40,6 +60,9 @@ pub(crate) fn render_backward(
     tile_offsets: CubeTensor<WgpuRuntime>,
     final_index: CubeTensor<WgpuRuntime>,
     sh_degree: u32,
+    opacity_activation: Option<OpacityActivation>,
+    disparity: bool,
+    region: PixelRect,
 ) -> SplatGrads<MainBackendBase> {
     let device = &out_img.device;
     let img_dimgs = out_img.shape.dims;
@@ -63,18 +86,28 @@ pub(crate) fn render_backward(
     );
     let v_opac = MainBackendBase::float_zeros([num_points].into(), device);
 
-    let tile_bounds = uvec2(
-        img_size
-            .x
-            .div_ceil(brush_render::shaders::helpers::TILE_WIDTH),
-        img_size
-            .y
-            .div_ceil(brush_render::shaders::helpers::TILE_WIDTH),
+    // Only rasterize the tiles that overlap the backpropagated region.
+    let region = region.clamp_to(img_size);
+    let tile_min = region.min / TILE_WIDTH;
+    let tile_max = uvec2(
+        region.max.x.div_ceil(TILE_WIDTH),
+        region.max.y.div_ceil(TILE_WIDTH),
+    );
+    let tile_count = tile_max.saturating_sub(tile_min);
+    let invocations = tile_count.x * tile_count.y;
+    let region_buffer = create_uniform_buffer(
+        rasterize_backwards::Region {
+            tile_min: tile_min.into(),
+            tile_count: tile_count.into(),
+            pix_min: region.min.into(),
+            pix_max: region.max.into(),
+        },
+        device,
+        client,
     );
-    let invocations = tile_bounds.x * tile_bounds.y;
 
     // These gradients are atomically added to so important to zero them.
-    let v_grads = MainBackendBase::float_zeros([num_points, 9].into(), device);
+    let v_grads = MainBackendBase::float_zeros([num_points, 10].into(), device);
     let v_refine_weight = MainBackendBase::float_zeros([num_points, 2].into(), device);
 
     let hard_floats =
@@ -84,10 +117,16 @@ pub(crate) fn render_backward(
                 AtomicFeature::Add,
             ));
 
+    // Images with depth have a fifth channel, see `RenderOptions::render_depth`.
+    let depth_output = img_dimgs[2] == 5;
+
     // Use checked execution, as the atomic loops are potentially unbounded.
     tracing::trace_span!("RasterizeBackwards", sync_burn = true).in_scope(|| {
+        if invocations == 0 {
+            return;
+        }
         client.execute(
-            RasterizeBackwards::task(hard_floats),
+            RasterizeBackwards::task(hard_floats, depth_output, depth_output && disparity),
             CubeCount::Static(invocations, 1, 1),
             Bindings::new().with_buffers(vec![
                 uniforms_buffer.clone().handle.binding(),
@@ -99,21 +138,24 @@ pub(crate) fn render_backward(
                 v_output.handle.binding(),
                 v_grads.clone().handle.binding(),
                 v_refine_weight.clone().handle.binding(),
+                region_buffer.handle.binding(),
             ]),
         );
     });
 
     let _span = tracing::trace_span!("GatherGrads", sync_burn = true).entered();
 
+    let (sigmoid_opacity, exp_density_opacity) = opacity_activation_flags(opacity_activation);
+
     // SAFETY: Kernel has to contain no OOB indexing, bounded loops.
     unsafe {
         client.execute_unchecked(
-            GatherGrads::task(),
+            GatherGrads::task(sigmoid_opacity, exp_density_opacity),
             calc_cube_count([num_points as u32], GatherGrads::WORKGROUP_SIZE),
             Bindings::new().with_buffers(vec![
                 uniforms_buffer.clone().handle.binding(),
                 global_from_compact_gid.clone().handle.binding(),
-                // raw_opac.handle.binding(),
+                raw_opac.handle.binding(),
                 means.clone().handle.binding(),
                 v_grads.clone().handle.binding(),
                 v_coeffs.handle.clone().binding(),
@@ -3,11 +3,12 @@
 @group(0) @binding(0) var<storage, read> uniforms: helpers::RenderUniforms;
 
 @group(0) @binding(1) var<storage, read> global_from_compact_gid: array<i32>;
-@group(0) @binding(2) var<storage, read> means: array<helpers::PackedVec3>;
-@group(0) @binding(3) var<storage, read> v_grads: array<f32>;
+@group(0) @binding(2) var<storage, read> raw_opacities: array<f32>;
+@group(0) @binding(3) var<storage, read> means: array<helpers::PackedVec3>;
+@group(0) @binding(4) var<storage, read> v_grads: array<f32>;
 
-@group(0) @binding(4) var<storage, read_write> v_coeffs: array<f32>;
-@group(0) @binding(5) var<storage, read_write> v_opacs: array<f32>;
+@group(0) @binding(5) var<storage, read_write> v_coeffs: array<f32>;
+@group(0) @binding(6) var<storage, read_write> v_opacs: array<f32>;
 
 const SH_C0: f32 = 0.2820947917738781f;
 
@@ -105,6 +106,75 @@ fn sh_coeffs_to_color_fast_vjp(
     v_coeffs.b4_c6 = pSH22 * v_colors;
     v_coeffs.b4_c7 = pSH23 * v_colors;
     v_coeffs.b4_c8 = pSH24 * v_colors;
+    if (degree == 4) {
+        return v_coeffs;
+    }
+    let fTmp0E = 2.03100960115899f * z * fTmp0D - 0.9910312089651149f * fTmp0C;
+    let fTmp1D = z * (7.190305177459987f * z2 - 2.396768392486662f);
+    let fTmp2C = -4.403144694917254f * z2 + 0.4892382994352505f;
+    let fTmp3B = 2.075662314881041f * z;
+    let fTmp4A = -0.6563820568401702f;
+    let fC4 = x * fC3 - y * fS3;
+    let fS4 = x * fS3 + y * fC3;
+    let pSH30 = 1.98997487421324f * z * pSH20 - 1.002853072844814f * pSH12;
+    let pSH31 = fTmp0E * x;
+    let pSH29 = fTmp0E * y;
+    let pSH32 = fTmp1D * fC1;
+    let pSH28 = fTmp1D * fS1;
+    let pSH33 = fTmp2C * fC2;
+    let pSH27 = fTmp2C * fS2;
+    let pSH34 = fTmp3B * fC3;
+    let pSH26 = fTmp3B * fS3;
+    let pSH35 = fTmp4A * fC4;
+    let pSH25 = fTmp4A * fS4;
+    v_coeffs.b5_c0 = pSH25 * v_colors;
+    v_coeffs.b5_c1 = pSH26 * v_colors;
+    v_coeffs.b5_c2 = pSH27 * v_colors;
+    v_coeffs.b5_c3 = pSH28 * v_colors;
+    v_coeffs.b5_c4 = pSH29 * v_colors;
+    v_coeffs.b5_c5 = pSH30 * v_colors;
+    v_coeffs.b5_c6 = pSH31 * v_colors;
+    v_coeffs.b5_c7 = pSH32 * v_colors;
+    v_coeffs.b5_c8 = pSH33 * v_colors;
+    v_coeffs.b5_c9 = pSH34 * v_colors;
+    v_coeffs.b5_c10 = pSH35 * v_colors;
+    if (degree == 5) {
+        return v_coeffs;
+    }
+    let fTmp0F = 2.021314989237028f * z * fTmp0E - 0.9952267030562386f * fTmp0D;
+    let fTmp1E = 2.11394181566097f * z * fTmp1D - 0.9736101204623269f * fTmp1C;
+    let fTmp2D = 2.301368353023109f * z * fTmp2C - 0.9251848886516145f * fTmp2B;
+    let fTmp3C = 5.550213908015966f * z2 - 0.5045649007287242f;
+    let fTmp4B = -2.366619162231752f * z;
+    let fTmp5A = 0.6831841051919143f;
+    let fC5 = x * fC4 - y * fS4;
+    let fS5 = x * fS4 + y * fC4;
+    let pSH42 = 1.993043457183566f * z * pSH30 - 1.001542020962219f * pSH20;
+    let pSH43 = fTmp0F * x;
+    let pSH41 = fTmp0F * y;
+    let pSH44 = fTmp1E * fC1;
+    let pSH40 = fTmp1E * fS1;
+    let pSH45 = fTmp2D * fC2;
+    let pSH39 = fTmp2D * fS2;
+    let pSH46 = fTmp3C * fC3;
+    let pSH38 = fTmp3C * fS3;
+    let pSH47 = fTmp4B * fC4;
+    let pSH37 = fTmp4B * fS4;
+    let pSH48 = fTmp5A * fC5;
+    let pSH36 = fTmp5A * fS5;
+    v_coeffs.b6_c0 = pSH36 * v_colors;
+    v_coeffs.b6_c1 = pSH37 * v_colors;
+    v_coeffs.b6_c2 = pSH38 * v_colors;
+    v_coeffs.b6_c3 = pSH39 * v_colors;
+    v_coeffs.b6_c4 = pSH40 * v_colors;
+    v_coeffs.b6_c5 = pSH41 * v_colors;
+    v_coeffs.b6_c6 = pSH42 * v_colors;
+    v_coeffs.b6_c7 = pSH43 * v_colors;
+    v_coeffs.b6_c8 = pSH44 * v_colors;
+    v_coeffs.b6_c9 = pSH45 * v_colors;
+    v_coeffs.b6_c10 = pSH46 * v_colors;
+    v_coeffs.b6_c11 = pSH47 * v_colors;
+    v_coeffs.b6_c12 = pSH48 * v_colors;
     return v_coeffs;
 }
 
@@ -138,10 +208,32 @@ struct ShCoeffs {
     b4_c6: vec3f,
     b4_c7: vec3f,
     b4_c8: vec3f,
-}
 
-fn num_sh_coeffs(degree: u32) -> u32 {
-    return (degree + 1) * (degree + 1);
+    b5_c0: vec3f,
+    b5_c1: vec3f,
+    b5_c2: vec3f,
+    b5_c3: vec3f,
+    b5_c4: vec3f,
+    b5_c5: vec3f,
+    b5_c6: vec3f,
+    b5_c7: vec3f,
+    b5_c8: vec3f,
+    b5_c9: vec3f,
+    b5_c10: vec3f,
+
+    b6_c0: vec3f,
+    b6_c1: vec3f,
+    b6_c2: vec3f,
+    b6_c3: vec3f,
+    b6_c4: vec3f,
+    b6_c5: vec3f,
+    b6_c6: vec3f,
+    b6_c7: vec3f,
+    b6_c8: vec3f,
+    b6_c9: vec3f,
+    b6_c10: vec3f,
+    b6_c11: vec3f,
+    b6_c12: vec3f,
 }
 
 fn write_coeffs(base_id: ptr<function, i32>, val: vec3f) {
@@ -161,19 +253,19 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     }
 
     // Load colors gradients.
-    let v_color = vec3f(v_grads[compact_gid * 9 + 5], v_grads[compact_gid * 9 + 6], v_grads[compact_gid * 9 + 7]);
-    let v_opac = v_grads[compact_gid * 9 + 8];
+    let v_color = vec3f(v_grads[compact_gid * 10 + 5], v_grads[compact_gid * 10 + 6], v_grads[compact_gid * 10 + 7]);
+    let v_opac = v_grads[compact_gid * 10 + 8];
 
     // Convert RGB to global SH gradients.
     let global_gid = global_from_compact_gid[compact_gid];
 
     let mean = helpers::as_vec(means[global_gid]);
-    let viewdir = normalize(mean - uniforms.camera_position.xyz);
+    let viewdir = helpers::quat_to_mat(uniforms.sh_rotation) * normalize(mean - uniforms.camera_position.xyz);
 
     let sh_degree = uniforms.sh_degree;
     let v_coeff = sh_coeffs_to_color_fast_vjp(sh_degree, viewdir, v_color);
-    let num_coeffs = num_sh_coeffs(sh_degree);
-    var base_id = global_gid * i32(num_coeffs) * 3;
+    // Nb: Only the active bands are written, gradients to any higher bands are left at zero.
+    var base_id = global_gid * i32(uniforms.sh_coeffs_per_splat) * 3;
 
     write_coeffs(&base_id, v_coeff.b0_c0);
     if sh_degree > 0 {
@@ -204,13 +296,39 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
                     write_coeffs(&base_id, v_coeff.b4_c6);
                     write_coeffs(&base_id, v_coeff.b4_c7);
                     write_coeffs(&base_id, v_coeff.b4_c8);
+                    if sh_degree > 4 {
+                        write_coeffs(&base_id, v_coeff.b5_c0);
+                        write_coeffs(&base_id, v_coeff.b5_c1);
+                        write_coeffs(&base_id, v_coeff.b5_c2);
+                        write_coeffs(&base_id, v_coeff.b5_c3);
+                        write_coeffs(&base_id, v_coeff.b5_c4);
+                        write_coeffs(&base_id, v_coeff.b5_c5);
+                        write_coeffs(&base_id, v_coeff.b5_c6);
+                        write_coeffs(&base_id, v_coeff.b5_c7);
+                        write_coeffs(&base_id, v_coeff.b5_c8);
+                        write_coeffs(&base_id, v_coeff.b5_c9);
+                        write_coeffs(&base_id, v_coeff.b5_c10);
+                        if sh_degree > 5 {
+                            write_coeffs(&base_id, v_coeff.b6_c0);
+                            write_coeffs(&base_id, v_coeff.b6_c1);
+                            write_coeffs(&base_id, v_coeff.b6_c2);
+                            write_coeffs(&base_id, v_coeff.b6_c3);
+                            write_coeffs(&base_id, v_coeff.b6_c4);
+                            write_coeffs(&base_id, v_coeff.b6_c5);
+                            write_coeffs(&base_id, v_coeff.b6_c6);
+                            write_coeffs(&base_id, v_coeff.b6_c7);
+                            write_coeffs(&base_id, v_coeff.b6_c8);
+                            write_coeffs(&base_id, v_coeff.b6_c9);
+                            write_coeffs(&base_id, v_coeff.b6_c10);
+                            write_coeffs(&base_id, v_coeff.b6_c11);
+                            write_coeffs(&base_id, v_coeff.b6_c12);
+                        }
+                    }
                 }
             }
         }
     }
 
-    // Transform alpha gradient to opacity gradient.
-    // let raw_opac = opacities[global_gid];
-    // let v_opac_raw = v_opac * v_sigmoid(raw_opac);
-    v_opacs[global_gid] = v_opac;
+    // Transform the opacity gradient to the gradient of the raw opacity.
+    v_opacs[global_gid] = v_opac * helpers::activate_opacity_grad(raw_opacities[global_gid]);
 }
@@ -139,10 +139,6 @@ fn persp_proj_vjp(
                   2.f * focal.x * tx * rz3 * v_J[2][0] +
                   2.f * focal.y * ty * rz3 * v_J[2][1];
 
-    // add contribution from v_depths
-    // Disabled as there is no depth supervision currently.
-    // v_mean3d.z += v_depths[0];
-
     return v_mean3d;
 }
 
@@ -166,8 +162,8 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     // Safe to normalize, quats with norm 0 are invisible.
     let quat = normalize(quat_unorm);
 
-    let v_mean2d = vec2f(v_grads[compact_gid * 9 + 0], v_grads[compact_gid * 9 + 1]);
-    let v_conics = vec3f(v_grads[compact_gid * 9 + 2], v_grads[compact_gid * 9 + 3], v_grads[compact_gid * 9 + 4]);
+    let v_mean2d = vec2f(v_grads[compact_gid * 10 + 0], v_grads[compact_gid * 10 + 1]);
+    let v_conics = vec3f(v_grads[compact_gid * 10 + 2], v_grads[compact_gid * 10 + 3], v_grads[compact_gid * 10 + 4]);
 
     let R = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
     let mean_c = R * mean + viewmat[3].xyz;
@@ -191,7 +187,9 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
 
     // persp_proj_vjp
     let J = helpers::calc_cam_J(mean_c, focal, img_size, pixel_center);
-    let v_mean_c = persp_proj_vjp(J, mean_c, covar_c, focal, pixel_center, img_size, v_covar2d, v_mean2d);
+    var v_mean_c = persp_proj_vjp(J, mean_c, covar_c, focal, pixel_center, img_size, v_covar2d, v_mean2d);
+    // Gradient of the rendered depth, which is only non-zero when rendering depth.
+    v_mean_c.z += v_grads[compact_gid * 10 + 9];
     // cov = J * V * Jt; G = df/dcov = v_cov
     // -> df/dV = Jt * G * J
     // -> df/dJ = G * J * Vt + Gt * J * V
@@ -1,5 +1,17 @@
 #import helpers;
 
+// The part of the image that is backpropagated through.
+struct Region {
+    // First tile of the region, and the number of tiles it covers. Only these tiles are
+    // dispatched, one workgroup each.
+    tile_min: vec2u,
+    tile_count: vec2u,
+    // Pixels in [pix_min, pix_max) receive gradients, in the top down pixel coordinates of
+    // rasterization.
+    pix_min: vec2u,
+    pix_max: vec2u,
+}
+
 @group(0) @binding(0) var<storage, read> uniforms: helpers::RenderUniforms;
 
 @group(0) @binding(1) var<storage, read> compact_gid_from_isect: array<i32>;
@@ -8,8 +20,14 @@
 @group(0) @binding(3) var<storage, read> projected_splats: array<helpers::ProjectedSplat>;
 
 @group(0) @binding(4) var<storage, read> final_index: array<i32>;
-@group(0) @binding(5) var<storage, read> output: array<vec4f>;
-@group(0) @binding(6) var<storage, read> v_output: array<vec4f>;
+#ifdef DEPTH_OUTPUT
+    // Each pixel is RGBA followed by the depth.
+    @group(0) @binding(5) var<storage, read> output: array<f32>;
+    @group(0) @binding(6) var<storage, read> v_output: array<f32>;
+#else
+    @group(0) @binding(5) var<storage, read> output: array<vec4f>;
+    @group(0) @binding(6) var<storage, read> v_output: array<vec4f>;
+#endif
 
 #ifdef HARD_FLOAT
     @group(0) @binding(7) var<storage, read_write> v_splats: array<atomic<f32>>;
@@ -19,6 +37,8 @@
     @group(0) @binding(8) var<storage, read_write> v_refine_grad: array<atomic<u32>>;
 #endif
 
+@group(0) @binding(9) var<storage, read> region: Region;
+
 const BATCH_SIZE = helpers::TILE_SIZE;
 
 // Gaussians gathered in batch.
@@ -73,23 +93,30 @@ fn main(
     let img_size = uniforms.img_size;
     let tile_bounds = uniforms.tile_bounds;
 
-    let tile_id = workgroup_id.x;
-
-    let tile_loc = vec2u(tile_id % tile_bounds.x, tile_id / tile_bounds.x);
+    let tile_loc = region.tile_min + vec2u(
+        workgroup_id.x % region.tile_count.x,
+        workgroup_id.x / region.tile_count.x
+    );
+    let tile_id = tile_loc.x + tile_loc.y * tile_bounds.x;
     let pixel_coordi = tile_loc * helpers::TILE_WIDTH + vec2u(
         local_idx % helpers::TILE_WIDTH,
         local_idx / helpers::TILE_WIDTH
     );
 
-    let pix_id = pixel_coordi.x + pixel_coordi.y * img_size.x;
+    let pix_id = helpers::output_pixel_id(pixel_coordi, img_size, uniforms.flip_y);
     let pixel_coord = vec2f(pixel_coordi) + 0.5;
 
     // return if out of bounds
     // keep not rasterizing threads around for reading data
     let inside = pixel_coordi.x < img_size.x && pixel_coordi.y < img_size.y;
+    let in_region = all(pixel_coordi >= region.pix_min) && all(pixel_coordi < region.pix_max);
 
     // this is the T AFTER the last gaussian in this pixel
+#ifdef DEPTH_OUTPUT
+    let T_final = 1.0 - output[pix_id * 5u + 3u];
+#else
     let T_final = 1.0 - output[pix_id].w;
+#endif
 
     var range = vec2u(
         u32(clamp(tile_offsets[tile_id], 0, i32(uniforms.max_intersects))),
@@ -122,16 +149,24 @@ fn main(
     // current visibility left to render
     var T = T_final;
     var buffer = vec3f(0.0);
+    var depth_buffer = 0.0;
 
     // df/d_out for this pixel
     var v_out = vec4f(0.0);
-    if inside {
+    var v_depth_out = 0.0;
+    if inside && in_region {
+#ifdef DEPTH_OUTPUT
+        let base = pix_id * 5u;
+        v_out = vec4f(v_output[base], v_output[base + 1u], v_output[base + 2u], v_output[base + 3u]);
+        v_depth_out = v_output[base + 4u];
+#else
         v_out = v_output[pix_id];
+#endif
     }
 
     // Not common but when using masked out images, there can be quite large regions where
     // the loss is 0. In that case, can skip gradients entirely as they all depend on v_out.
-    let pixel_active = length(v_out) > 0.0;
+    let pixel_active = length(v_out) > 0.0 || v_depth_out != 0.0;
 
     for (var b = 0u; b < num_batches; b++) {
         // each thread fetch 1 gaussian from back to front
@@ -158,6 +193,7 @@ fn main(
             var v_conic = vec3f(0.0);
             var v_colors = vec4f(0.0);
             var v_refine = vec2f(0.0);
+            var v_depth = 0.0;
 
             var splat_active = false;
 
@@ -192,6 +228,20 @@ fn main(
                     // update the running sum
                     buffer += clamped_rgb * fac;
 
+                    // The depth is blended like the colors, so contributes to alpha the same way.
+                    #ifdef DISPARITY
+                        let splat_depth = 1.0 / projected.depth;
+                    #else
+                        let splat_depth = projected.depth;
+                    #endif
+                    v_alpha += (splat_depth * T - depth_buffer * ra) * v_depth_out;
+                    depth_buffer += splat_depth * fac;
+                    v_depth = fac * v_depth_out;
+                    #ifdef DISPARITY
+                        // d(1 / depth) / d(depth) = -1 / depth^2
+                        v_depth *= -splat_depth * splat_depth;
+                    #endif
+
                     let v_sigma = -color.a * vis * v_alpha;
 
                     v_xy = v_sigma * vec2f(
@@ -203,7 +253,9 @@ fn main(
                                             v_sigma * delta.x * delta.y,
                                     0.5f * v_sigma * delta.y * delta.y);
 
-                    let v_rgb = select(vec3f(0.0), fac * v_out.rgb, color.rgb > vec3f(0.0));
+                    var v_rgb = select(vec3f(0.0), fac * v_out.rgb, color.rgb > vec3f(0.0));
+                    // The projected color was clamped, see project_visible.
+                    v_rgb *= helpers::clamp_color_grad(color.rgb, uniforms.clamp_policy);
                     v_colors = vec4f(v_rgb, vis * v_alpha);
 
                     v_refine = abs(v_xy);
@@ -214,6 +266,9 @@ fn main(
             let v_conic_sum = subgroupAdd(v_conic);
             let v_colors_sum = subgroupAdd(v_colors);
             let v_refine_sum = subgroupAdd(v_refine);
+#ifdef DEPTH_OUTPUT
+            let v_depth_sum = subgroupAdd(v_depth);
+#endif
 
             // Queue a new gradient if this subgroup has any.
             // The gradient is sum of all gradients in the subgroup.
@@ -221,28 +276,34 @@ fn main(
                 let compact_gid = local_id[t];
 
                 switch subgroup_invocation_id {
-                    case 0u:  { write_grads_atomic(compact_gid * 9 + 0, v_xy_sum.x); }
-                    case 1u:  { write_grads_atomic(compact_gid * 9 + 1, v_xy_sum.y); }
-                    case 2u:  { write_grads_atomic(compact_gid * 9 + 2, v_conic_sum.x); }
-                    case 3u:  { write_grads_atomic(compact_gid * 9 + 3, v_conic_sum.y); }
-                    case 4u:  { write_grads_atomic(compact_gid * 9 + 4, v_conic_sum.z); }
-                    case 5u:  { write_grads_atomic(compact_gid * 9 + 5, v_colors_sum.x); }
-                    case 6u:  { write_grads_atomic(compact_gid * 9 + 6, v_colors_sum.y); }
+                    case 0u:  { write_grads_atomic(compact_gid * 10 + 0, v_xy_sum.x); }
+                    case 1u:  { write_grads_atomic(compact_gid * 10 + 1, v_xy_sum.y); }
+                    case 2u:  { write_grads_atomic(compact_gid * 10 + 2, v_conic_sum.x); }
+                    case 3u:  { write_grads_atomic(compact_gid * 10 + 3, v_conic_sum.y); }
+                    case 4u:  { write_grads_atomic(compact_gid * 10 + 4, v_conic_sum.z); }
+                    case 5u:  { write_grads_atomic(compact_gid * 10 + 5, v_colors_sum.x); }
+                    case 6u:  { write_grads_atomic(compact_gid * 10 + 6, v_colors_sum.y); }
                     case 7u:  {
-                        write_grads_atomic(compact_gid * 9 + 7, v_colors_sum.z);
+                        write_grads_atomic(compact_gid * 10 + 7, v_colors_sum.z);
 
                         // Subgroups of size 8 need to be handled separately as there's not enough threads to write
                         // all the gaussian fields. The next size (16) is fine.
                         if subgroup_size == 8u {
-                            write_grads_atomic(compact_gid * 9 + 8, v_colors_sum.w);
+                            write_grads_atomic(compact_gid * 10 + 8, v_colors_sum.w);
                             write_refine_atomic(compact_gid * 2 + 0, v_refine_sum.x);
                             write_refine_atomic(compact_gid * 2 + 1, v_refine_sum.y);
+#ifdef DEPTH_OUTPUT
+                            write_grads_atomic(compact_gid * 10 + 9, v_depth_sum);
+#endif
                         }
                     }
 
-                    case 8u:  { write_grads_atomic(compact_gid * 9 + 8, v_colors_sum.w); }
+                    case 8u:  { write_grads_atomic(compact_gid * 10 + 8, v_colors_sum.w); }
                     case 9u:  { write_refine_atomic(compact_gid * 2 + 0, v_refine_sum.x); }
                     case 10u: { write_refine_atomic(compact_gid * 2 + 1, v_refine_sum.y); }
+#ifdef DEPTH_OUTPUT
+                    case 11u: { write_grads_atomic(compact_gid * 10 + 9, v_depth_sum); }
+#endif
                     default: {}
                 }
             }
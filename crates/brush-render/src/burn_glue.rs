@@ -7,8 +7,12 @@ use burn_wgpu::WgpuRuntime;
 use crate::{
     MainBackendBase, SplatForward,
     camera::Camera,
-    render::{calc_tile_bounds, max_intersections, render_forward},
+    render::{
+        RenderInput, calc_tile_bounds, max_intersections, output_channels, output_dtype,
+        render_forward, render_forward_batch,
+    },
     render_aux::RenderAux,
+    render_options::RenderOptions,
     shaders,
 };
 
@@ -23,9 +27,34 @@ impl SplatForward<Self> for MainBackendBase {
         sh_coeffs: FloatTensor<Self>,
         opacity: FloatTensor<Self>,
         bwd_info: bool,
+        options: &RenderOptions,
     ) -> (FloatTensor<Self>, RenderAux<Self>) {
         render_forward(
-            camera, img_size, means, log_scales, quats, sh_coeffs, opacity, bwd_info,
+            camera,
+            img_size,
+            RenderInput::new(means, log_scales, quats, sh_coeffs, opacity),
+            bwd_info,
+            options,
+        )
+    }
+
+    fn render_splats_batch(
+        cameras: &[Camera],
+        img_size: glam::UVec2,
+        means: FloatTensor<Self>,
+        log_scales: FloatTensor<Self>,
+        quats: FloatTensor<Self>,
+        sh_coeffs: FloatTensor<Self>,
+        opacity: FloatTensor<Self>,
+        bwd_info: bool,
+        options: &RenderOptions,
+    ) -> (FloatTensor<Self>, Vec<RenderAux<Self>>) {
+        render_forward_batch(
+            cameras,
+            img_size,
+            RenderInput::new(means, log_scales, quats, sh_coeffs, opacity),
+            bwd_info,
+            options,
         )
     }
 }
@@ -40,11 +69,13 @@ impl SplatForward<Self> for Fusion<MainBackendBase> {
         sh_coeffs: FloatTensor<Self>,
         opacity: FloatTensor<Self>,
         bwd_info: bool,
+        options: &RenderOptions,
     ) -> (FloatTensor<Self>, RenderAux<Self>) {
         struct CustomOp {
             cam: Camera,
             img_size: glam::UVec2,
             bwd_info: bool,
+            options: RenderOptions,
             desc: CustomOpIr,
         }
 
@@ -76,6 +107,7 @@ impl SplatForward<Self> for Fusion<MainBackendBase> {
                     h.get_float_tensor::<MainBackendBase>(sh_coeffs),
                     h.get_float_tensor::<MainBackendBase>(opacity),
                     self.bwd_info,
+                    &self.options,
                 );
 
                 // Register output.
@@ -108,15 +140,20 @@ impl SplatForward<Self> for Fusion<MainBackendBase> {
         let proj_size = size_of::<shaders::helpers::ProjectedSplat>() / 4;
         let uniforms_size = size_of::<shaders::helpers::RenderUniforms>() / 4;
         let tile_bounds = calc_tile_bounds(img_size);
-        let max_intersects = max_intersections(img_size, num_points as u32);
+        let max_intersects =
+            max_intersections(img_size, num_points as u32, options.intersects_bound());
 
         // If render_u32_buffer is true, we render a packed buffer of u32 values, otherwise
         // render RGBA f32 values.
-        let channels = if bwd_info { 4 } else { 1 };
+        let channels = output_channels(bwd_info, options);
 
         let out_img = client.tensor_uninitialized(
             vec![img_size.y as usize, img_size.x as usize, channels],
-            if bwd_info { DType::F32 } else { DType::U32 },
+            if bwd_info {
+                output_dtype(bwd_info, options)
+            } else {
+                DType::U32
+            },
         );
 
         let final_index_shape = if bwd_info {
@@ -165,6 +202,7 @@ impl SplatForward<Self> for Fusion<MainBackendBase> {
             cam: cam.clone(),
             img_size,
             bwd_info,
+            options: options.clone(),
             desc: desc.clone(),
         };
 
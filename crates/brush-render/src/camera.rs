@@ -1,12 +1,87 @@
 use glam::Affine3A;
 
-#[derive(Debug, Default, Clone)]
+/// Field of view of cameras created without one, in radians (about 57 degrees).
+pub const DEFAULT_FOV: f64 = 1.0;
+
+/// Which corner of the image pixel (0, 0) is in.
+///
+/// This sets both which way pixel coordinates, like the principal point, are measured, and the
+/// order of the rows of rendered images. Rendering with [`ImageOrigin::BottomLeft`] gives the
+/// vertical mirror of a top left render, with the first row at the bottom of the view, as in
+/// OpenGL.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ImageOrigin {
+    #[default]
+    TopLeft,
+    BottomLeft,
+}
+
+/// A pinhole camera.
+///
+/// With the `serde` feature, this serializes as plain numbers, eg. the position as `[x, y, z]`
+/// and rotation as a quaternion `[x, y, z, w]`, with pinhole intrinsics instead of field of
+/// views, see [`CameraJson`].
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "CameraJson", into = "CameraJson")
+)]
 pub struct Camera {
     pub fov_x: f64,
     pub fov_y: f64,
     pub center_uv: glam::Vec2,
     pub position: glam::Vec3,
     pub rotation: glam::Quat,
+    /// Corner of the image pixel coordinates start at, see [`ImageOrigin`].
+    pub origin: ImageOrigin,
+}
+
+/// The serialized form of a [`Camera`].
+///
+/// Cameras render at any resolution, so the intrinsics are stored relative to the image size:
+/// the focal lengths in image widths and heights, and the principal point as a fraction of the
+/// image size. Multiply them by the image size to get the intrinsics in pixels, like
+/// [`Camera::focal`] and [`Camera::center`].
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct CameraJson {
+    pub position: glam::Vec3,
+    pub rotation: glam::Quat,
+    /// Focal lengths `(fx / width, fy / height)`.
+    pub focal: glam::DVec2,
+    /// Principal point `(cx / width, cy / height)`.
+    pub center: glam::Vec2,
+    #[serde(default)]
+    pub origin: ImageOrigin,
+}
+
+#[cfg(feature = "serde")]
+impl From<Camera> for CameraJson {
+    fn from(camera: Camera) -> Self {
+        Self {
+            position: camera.position,
+            rotation: camera.rotation,
+            focal: glam::dvec2(fov_to_focal(camera.fov_x, 1), fov_to_focal(camera.fov_y, 1)),
+            center: camera.center_uv,
+            origin: camera.origin,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<CameraJson> for Camera {
+    fn from(json: CameraJson) -> Self {
+        Self::new(
+            json.position,
+            json.rotation,
+            focal_to_fov(json.focal.x, 1),
+            focal_to_fov(json.focal.y, 1),
+            json.center,
+        )
+        .with_origin(json.origin)
+    }
 }
 
 impl Camera {
@@ -23,9 +98,116 @@ impl Camera {
             center_uv,
             position,
             rotation,
+            origin: ImageOrigin::TopLeft,
+        }
+    }
+
+    /// Create a camera at `position` with orientation `rotation`, which maps camera space to world
+    /// space.
+    ///
+    /// The camera uses [`DEFAULT_FOV`] and a centered principal point, see [`Self::with_fov`] to
+    /// change the field of view.
+    pub fn from_position_rotation(position: glam::Vec3, rotation: glam::Quat) -> Self {
+        Self::new(
+            position,
+            rotation,
+            DEFAULT_FOV,
+            DEFAULT_FOV,
+            glam::vec2(0.5, 0.5),
+        )
+    }
+
+    /// Create a camera at `eye` looking towards `target`, with `up` pointing up in the image.
+    ///
+    /// Cameras look along their local +Z axis, with +X to the right and +Y down in the image.
+    /// `up` doesn't need to be orthogonal to the view direction, but can't be parallel to it.
+    /// Like [`Self::from_position_rotation`] this uses the default field of view.
+    pub fn look_at(eye: glam::Vec3, target: glam::Vec3, up: glam::Vec3) -> Self {
+        let forward = (target - eye).normalize();
+        let right = (-up).cross(forward).normalize();
+        let down = forward.cross(right);
+        let rotation = glam::Quat::from_mat3(&glam::Mat3::from_cols(right, down, forward));
+        Self::from_position_rotation(eye, rotation)
+    }
+
+    /// Set the horizontal and vertical field of view, in radians.
+    pub fn with_fov(mut self, fov_x: f64, fov_y: f64) -> Self {
+        self.fov_x = fov_x;
+        self.fov_y = fov_y;
+        self
+    }
+
+    /// Set the horizontal field of view, in radians, and the vertical field of view that keeps
+    /// pixels square in images of `img_size`.
+    pub fn with_fov_x(self, fov_x: f64, img_size: glam::UVec2) -> Self {
+        let focal = fov_to_focal(fov_x, img_size.x);
+        self.with_fov(fov_x, focal_to_fov(focal, img_size.y))
+    }
+
+    /// Set the vertical field of view, in radians, and the horizontal field of view that keeps
+    /// pixels square in images of `img_size`.
+    pub fn with_fov_y(self, fov_y: f64, img_size: glam::UVec2) -> Self {
+        let focal = fov_to_focal(fov_y, img_size.y);
+        self.with_fov(focal_to_fov(focal, img_size.x), fov_y)
+    }
+
+    /// The horizontal and vertical field of view, in radians.
+    pub fn fov(&self) -> glam::DVec2 {
+        glam::dvec2(self.fov_x, self.fov_y)
+    }
+
+    /// Set which corner of the image pixel coordinates start at.
+    pub fn with_origin(mut self, origin: ImageOrigin) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    /// Interpolate between this camera at `t = 0` and `other` at `t = 1`.
+    ///
+    /// Positions, field of views and centers are interpolated linearly, and rotations spherically
+    /// along the shortest arc, even if the quaternions lie in opposite hemispheres. The origin is
+    /// taken from this camera.
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        // q and -q are the same rotation, pick the one closest to this rotation so the camera
+        // doesn't spin the long way around.
+        let end = if self.rotation.dot(other.rotation) < 0.0 {
+            -other.rotation
+        } else {
+            other.rotation
+        };
+        let t64 = f64::from(t);
+        Self {
+            fov_x: self.fov_x + (other.fov_x - self.fov_x) * t64,
+            fov_y: self.fov_y + (other.fov_y - self.fov_y) * t64,
+            center_uv: self.center_uv.lerp(other.center_uv, t),
+            position: self.position.lerp(other.position, t),
+            rotation: self.rotation.slerp(end, t).normalize(),
+            origin: self.origin,
         }
     }
 
+    /// Create a camera from pinhole intrinsics in pixels, for images of `img_size`.
+    ///
+    /// The focal lengths `(fx, fy)` are independent, eg. for sensors with non-square pixels.
+    /// They're stored as field of views, so the camera can render at any resolution, and
+    /// [`Self::focal`] and [`Self::center`] give back the same intrinsics at `img_size`.
+    pub fn from_intrinsics(
+        position: glam::Vec3,
+        rotation: glam::Quat,
+        focal: glam::DVec2,
+        center: glam::Vec2,
+        img_size: glam::UVec2,
+    ) -> Self {
+        Self::new(
+            position,
+            rotation,
+            focal_to_fov(focal.x, img_size.x),
+            focal_to_fov(focal.y, img_size.y),
+            center / img_size.as_vec2(),
+        )
+    }
+
+    /// The focal lengths `(fx, fy)` in pixels for images of `img_size`, see [`fov_to_focal`].
     pub fn focal(&self, img_size: glam::UVec2) -> glam::Vec2 {
         glam::vec2(
             fov_to_focal(self.fov_x, img_size.x) as f32,
@@ -40,6 +222,30 @@ impl Camera {
         )
     }
 
+    /// The principal point in pixels from the top left of the image, whatever the origin of the
+    /// camera. Splats are projected and rasterized top down, only the output rows are flipped.
+    pub(crate) fn raster_center(&self, img_size: glam::UVec2) -> glam::Vec2 {
+        let center = self.center(img_size);
+        match self.origin {
+            ImageOrigin::TopLeft => center,
+            ImageOrigin::BottomLeft => glam::vec2(center.x, img_size.y as f32 - center.y),
+        }
+    }
+
+    /// [`Self::raster_center`], shifted by `offset` pixels. The offset is in the pixel
+    /// coordinates of the camera, so points up for a bottom left origin.
+    pub(crate) fn raster_center_shifted(
+        &self,
+        img_size: glam::UVec2,
+        offset: glam::Vec2,
+    ) -> glam::Vec2 {
+        let offset = match self.origin {
+            ImageOrigin::TopLeft => offset,
+            ImageOrigin::BottomLeft => glam::vec2(offset.x, -offset.y),
+        };
+        self.raster_center(img_size) + offset
+    }
+
     pub fn local_to_world(&self) -> Affine3A {
         Affine3A::from_rotation_translation(self.rotation, self.position)
     }
@@ -47,13 +253,170 @@ impl Camera {
     pub fn world_to_local(&self) -> Affine3A {
         self.local_to_world().inverse()
     }
+
+    /// World space planes bounding the view frustum, as `(normal, offset)` packed in a `Vec4`.
+    ///
+    /// A point `p` is inside the frustum when `normal.dot(p) + offset >= 0` for all planes. The
+    /// normals are unit length, so this is the distance to the plane. The side planes are widened
+    /// by `margin` pixels around the image. The last plane is the near plane, at `near` units in
+    /// front of the camera.
+    pub fn frustum_planes(&self, img_size: glam::UVec2, margin: f32, near: f32) -> [glam::Vec4; 5] {
+        self.frustum_planes_from(self.world_to_local(), img_size, margin, near)
+    }
+
+    /// Frustum planes like [`Self::frustum_planes`], in the space that `world_to_local` maps to
+    /// camera space. The transform may include a uniform scale, distances are then in units of
+    /// that space.
+    pub(crate) fn frustum_planes_from(
+        &self,
+        world_to_local: Affine3A,
+        img_size: glam::UVec2,
+        margin: f32,
+        near: f32,
+    ) -> [glam::Vec4; 5] {
+        let focal = self.focal(img_size);
+        let center = self.raster_center(img_size);
+        let size = img_size.as_vec2();
+
+        // In camera space a point projects to pixel `focal * p.xy / p.z + center`. Each side of
+        // the image then gives a plane through the camera origin.
+        let local_planes = [
+            glam::vec4(focal.x, 0.0, center.x + margin, 0.0),
+            glam::vec4(-focal.x, 0.0, size.x + margin - center.x, 0.0),
+            glam::vec4(0.0, focal.y, center.y + margin, 0.0),
+            glam::vec4(0.0, -focal.y, size.y + margin - center.y, 0.0),
+            glam::vec4(0.0, 0.0, 1.0, -near),
+        ];
+
+        local_planes.map(|plane| {
+            // Transform plane from camera to world space, for p_local = R * p_world + t.
+            let normal = plane.truncate();
+            let world_normal = world_to_local.matrix3.transpose() * glam::Vec3A::from(normal);
+            let offset = normal.dot(world_to_local.translation.into()) + plane.w;
+            let len = world_normal.length();
+            glam::Vec3::from(world_normal / len).extend(offset / len)
+        })
+    }
 }
-// Converts field of view to focal length
+/// Converts a field of view in radians to the focal length in pixels, for an image `pixels` wide
+/// along the same axis. A 90° field of view has a focal length of half the image size.
 pub fn fov_to_focal(fov_rad: f64, pixels: u32) -> f64 {
     0.5 * (pixels as f64) / (fov_rad * 0.5).tan()
 }
 
-// Converts focal length to field of view
+/// Converts a focal length in pixels to the field of view in radians, for an image `pixels` wide
+/// along the same axis. The inverse of [`fov_to_focal`].
 pub fn focal_to_fov(focal: f64, pixels: u32) -> f64 {
     2.0 * f64::atan((pixels as f64) / (2.0 * focal))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Camera, focal_to_fov, fov_to_focal};
+    use assert_approx_eq::assert_approx_eq;
+    use glam::{Vec3, vec3};
+
+    #[test]
+    fn look_at_view_matrix() {
+        let eye = vec3(1.0, -2.0, 3.0);
+        let target = vec3(-1.0, 0.5, 0.0);
+        let up = vec3(0.2, -1.0, 0.1);
+        let cam = Camera::look_at(eye, target, up);
+
+        assert_eq!(cam.position, eye);
+        let world_to_local = cam.world_to_local();
+
+        // The eye is the origin of the camera, and the target is straight ahead.
+        assert!(
+            world_to_local.transform_point3(eye).length() < 1e-5,
+            "Eye must map to the camera origin"
+        );
+        let local_target = world_to_local.transform_point3(target);
+        let dist = (target - eye).length();
+        assert!(
+            (local_target - vec3(0.0, 0.0, dist)).length() < 1e-4,
+            "Target must be on the view axis, got {local_target}"
+        );
+
+        // Up points towards -Y in the image, and has no sideways component.
+        let local_up = world_to_local.transform_vector3(up);
+        assert!(local_up.y < 0.0, "Up must point up in the image");
+        assert!(local_up.x.abs() < 1e-5, "Up must not tilt the image");
+    }
+
+    #[test]
+    fn look_at_matches_default_orientation() {
+        // A camera looking down +Z with -Y up has no rotation.
+        let cam = Camera::look_at(vec3(0.0, 0.0, -4.0), Vec3::ZERO, Vec3::NEG_Y);
+        assert!(
+            cam.rotation.angle_between(glam::Quat::IDENTITY) < 1e-5,
+            "Expected identity rotation, got {}",
+            cam.rotation
+        );
+    }
+
+    #[test]
+    fn from_position_rotation_view_matrix() {
+        let position = vec3(0.5, 1.0, -2.0);
+        let rotation = glam::Quat::from_euler(glam::EulerRot::YXZ, 0.3, -0.2, 0.1);
+        let cam = Camera::from_position_rotation(position, rotation).with_fov(0.6, 0.4);
+        assert_eq!((cam.fov_x, cam.fov_y), (0.6, 0.4));
+
+        let world_to_local = cam.world_to_local();
+        assert!(
+            world_to_local.transform_point3(position).length() < 1e-5,
+            "Position must map to the camera origin"
+        );
+        let forward = world_to_local.inverse().transform_vector3(Vec3::Z);
+        assert!(
+            (forward - rotation * Vec3::Z).length() < 1e-5,
+            "Camera must look along its rotated Z axis"
+        );
+    }
+
+    #[test]
+    fn lerp_takes_the_short_way_around() {
+        let a = Camera::from_position_rotation(Vec3::ZERO, glam::Quat::from_rotation_y(0.2));
+        // The same small rotation, in the opposite hemisphere of the quaternion double cover.
+        let b =
+            Camera::from_position_rotation(vec3(2.0, 0.0, 0.0), -glam::Quat::from_rotation_y(0.6));
+
+        let mid = a.lerp(&b, 0.5);
+        assert_eq!(mid.position, vec3(1.0, 0.0, 0.0));
+        assert!(
+            mid.rotation.angle_between(glam::Quat::from_rotation_y(0.4)) < 1e-5,
+            "Must rotate the short way around, got {}",
+            mid.rotation
+        );
+        assert!(
+            a.lerp(&b, 1.0).rotation.angle_between(b.rotation) < 1e-5,
+            "Must end at the last rotation"
+        );
+    }
+
+    #[test]
+    fn fov_focal_roundtrip() {
+        // A 90° field of view sees as far to the side as it does ahead.
+        assert_approx_eq!(fov_to_focal(std::f64::consts::FRAC_PI_2, 640), 320.0, 1e-9);
+        let cam = Camera::from_position_rotation(Vec3::ZERO, glam::Quat::IDENTITY)
+            .with_fov(std::f64::consts::FRAC_PI_2, std::f64::consts::FRAC_PI_2);
+        assert_eq!(cam.focal(glam::uvec2(640, 480)), glam::vec2(320.0, 240.0));
+
+        for fov in [0.01, 0.3, 1.0, std::f64::consts::FRAC_PI_2, 2.5, 3.1] {
+            for pixels in [1, 97, 1920] {
+                let back = focal_to_fov(fov_to_focal(fov, pixels), pixels);
+                assert_approx_eq!(back, fov, 1e-12);
+            }
+        }
+
+        // Square pixels, for a 16:9 image with a 90° horizontal field of view.
+        let img_size = glam::uvec2(1600, 900);
+        let cam = Camera::from_position_rotation(Vec3::ZERO, glam::Quat::IDENTITY)
+            .with_fov_x(std::f64::consts::FRAC_PI_2, img_size);
+        let focal = cam.focal(img_size);
+        assert_approx_eq!(focal.x, 800.0, 1e-3);
+        assert_approx_eq!(focal.y, focal.x, 1e-3);
+        let same = cam.clone().with_fov_y(cam.fov_y, img_size);
+        assert_approx_eq!(same.fov().x, cam.fov().x, 1e-12);
+    }
+}
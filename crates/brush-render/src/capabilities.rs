@@ -0,0 +1,256 @@
+//! Estimates of the memory a render needs, to check whether it fits on a device before rendering.
+//!
+//! This mirrors the buffers the render pipeline allocates, so a viewer can limit its resolution
+//! up front instead of running out of memory. Small buffers, like uniforms and the workgroup
+//! sums of prefix sums, are ignored.
+
+use crate::{
+    GAUSSIANS_UPPER_BOUND,
+    render::{
+        BYTES_PER_INTERSECT, calc_tile_bounds, max_intersections, output_channels, output_dtype,
+    },
+    render_options::RenderOptions,
+    shaders,
+};
+
+/// The memory a single render needs, and whether it fits in the limits of a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderMemoryReport {
+    pub num_splats: u32,
+    pub img_size: glam::UVec2,
+    /// Number of intersections buffers are allocated for.
+    pub max_intersects: u32,
+    /// Whether the number of intersections is capped by the pipeline rather than the estimate
+    /// for this scene. Tiles then drop their furthest splats when there are too many of them.
+    pub intersects_clamped: bool,
+    /// Whether the splat count is within what a render can dispatch.
+    pub splats_supported: bool,
+    /// Size of the largest buffer, in bytes.
+    pub largest_buffer_bytes: u64,
+    /// Total size of the buffers, in bytes.
+    pub total_bytes: u64,
+    /// Whether every buffer fits in the buffer and storage binding limits of the device.
+    pub fits_buffer_limits: bool,
+}
+
+impl RenderMemoryReport {
+    /// Whether the render can run on the device, using at most `memory_budget` bytes.
+    pub fn fits(&self, memory_budget: u64) -> bool {
+        self.splats_supported && self.fits_buffer_limits && self.total_bytes <= memory_budget
+    }
+}
+
+/// Estimate the memory needed to render `num_splats` splats to an image of `img_size`, on a
+/// device with `limits`.
+///
+/// `bwd_info` and `options` are the same as passed to the render, as they change the size of the
+/// rendered image.
+pub fn render_memory_report(
+    limits: &wgpu::Limits,
+    num_splats: u32,
+    img_size: glam::UVec2,
+    bwd_info: bool,
+    options: &RenderOptions,
+) -> RenderMemoryReport {
+    let splats = u64::from(num_splats);
+    let pixels = u64::from(img_size.x) * u64::from(img_size.y);
+    let tile_bounds = calc_tile_bounds(img_size);
+    let tiles = u64::from(tile_bounds.x) * u64::from(tile_bounds.y);
+    let max_intersects = max_intersections(img_size, num_splats, options.intersects_bound());
+    let word = size_of::<u32>() as u64;
+
+    let projected_bytes = splats * size_of::<shaders::helpers::ProjectedSplat>() as u64;
+    // Depths, compacted ids and the intersection counts with their prefix sum. The visible flags
+    // and culled ids are only allocated for some renders.
+    let mut splat_words = 4;
+    if bwd_info {
+        splat_words += 1;
+    }
+    if options.frustum_cull {
+        splat_words += 1;
+    }
+    let splat_bytes = splats * word;
+
+    let intersect_bytes = u64::from(max_intersects) * BYTES_PER_INTERSECT;
+    // Intersection counts per tile, and their prefix sum.
+    let tile_bytes = 2 * (tiles + 1) * word;
+
+    let pixel_bytes = output_dtype(bwd_info, options).size() * output_channels(bwd_info, options);
+    let img_bytes = pixels * pixel_bytes as u64;
+    let final_index_bytes = if bwd_info { pixels * word } else { 0 };
+
+    // Each intersection value is its own buffer.
+    let largest_buffer_bytes = [
+        projected_bytes,
+        splat_bytes,
+        u64::from(max_intersects) * word,
+        tile_bytes / 2,
+        img_bytes,
+        final_index_bytes,
+    ]
+    .into_iter()
+    .max()
+    .unwrap_or(0);
+    let total_bytes = projected_bytes
+        + splat_words * splat_bytes
+        + intersect_bytes
+        + tile_bytes
+        + img_bytes
+        + final_index_bytes;
+
+    let buffer_limit = limits
+        .max_buffer_size
+        .min(u64::from(limits.max_storage_buffer_binding_size));
+
+    RenderMemoryReport {
+        num_splats,
+        img_size,
+        max_intersects,
+        intersects_clamped: max_intersects == options.intersects_bound(),
+        splats_supported: num_splats <= GAUSSIANS_UPPER_BOUND,
+        largest_buffer_bytes,
+        total_bytes,
+        fits_buffer_limits: largest_buffer_bytes <= buffer_limit,
+    }
+}
+
+/// The largest image with the given `aspect` (width / height) that `num_splats` splats can be
+/// rendered to within `memory_budget` bytes, on a device with `limits`.
+///
+/// Images are at most as large as the biggest texture the device supports. Returns `None` if not
+/// even a single pixel fits.
+pub fn max_render_size(
+    limits: &wgpu::Limits,
+    num_splats: u32,
+    aspect: f32,
+    memory_budget: u64,
+    bwd_info: bool,
+    options: &RenderOptions,
+) -> Option<glam::UVec2> {
+    assert!(
+        aspect.is_finite() && aspect > 0.0,
+        "The aspect ratio must be positive."
+    );
+
+    let max_dim = limits.max_texture_dimension_2d.max(1);
+    let size_for_height = |height: u32| {
+        let width = (height as f32 * aspect).round().clamp(1.0, max_dim as f32) as u32;
+        glam::uvec2(width, height)
+    };
+    let fits = |height: u32| {
+        render_memory_report(
+            limits,
+            num_splats,
+            size_for_height(height),
+            bwd_info,
+            options,
+        )
+        .fits(memory_budget)
+    };
+
+    // Memory grows with the image size, so search for the largest height that fits.
+    let max_height = ((max_dim as f32 / aspect).floor() as u32).clamp(1, max_dim);
+    if !fits(1) {
+        return None;
+    }
+    let (mut low, mut high) = (1, max_height);
+    while low < high {
+        let mid = low + (high - low).div_ceil(2);
+        if fits(mid) {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    Some(size_for_height(low))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::{max_render_size, render_memory_report};
+    use crate::{GAUSSIANS_UPPER_BOUND, render_options::RenderOptions};
+
+    #[test]
+    fn small_render_fits() {
+        let limits = wgpu::Limits::default();
+        let report = render_memory_report(
+            &limits,
+            10_000,
+            glam::uvec2(640, 480),
+            true,
+            &RenderOptions::default(),
+        );
+        assert!(report.splats_supported && report.fits_buffer_limits);
+        assert!(report.fits(u64::MAX), "A small render must fit: {report:?}");
+        assert!(
+            !report.fits(report.total_bytes - 1),
+            "The budget must cover all buffers"
+        );
+
+        let report = render_memory_report(
+            &limits,
+            GAUSSIANS_UPPER_BOUND + 1,
+            glam::uvec2(64, 64),
+            false,
+            &RenderOptions::default(),
+        );
+        assert!(!report.splats_supported, "Too many splats must be reported");
+    }
+
+    #[test]
+    fn max_size_is_largest_that_fits() {
+        let limits = wgpu::Limits::default();
+        let options = RenderOptions::default();
+        let budget = 256 * 1024 * 1024;
+        let size = max_render_size(&limits, 500_000, 1.5, budget, true, &options)
+            .expect("Some image size must fit");
+
+        let report = render_memory_report(&limits, 500_000, size, true, &options);
+        assert!(report.fits(budget), "The found size must fit: {report:?}");
+        let taller = glam::uvec2(((size.y + 1) as f32 * 1.5).round() as u32, size.y + 1);
+        let report = render_memory_report(&limits, 500_000, taller, true, &options);
+        assert!(
+            !report.fits(budget) || size.x == limits.max_texture_dimension_2d,
+            "A larger image must not fit: {report:?}"
+        );
+
+        assert!(
+            max_render_size(&limits, 500_000, 1.0, 0, true, &options).is_none(),
+            "Nothing fits without memory"
+        );
+    }
+
+    #[test]
+    fn intersect_bound_caps_buffers() {
+        let limits = wgpu::Limits::default();
+        let img_size = glam::uvec2(640, 480);
+        let default =
+            render_memory_report(&limits, 10_000, img_size, true, &RenderOptions::default());
+        assert!(
+            !default.intersects_clamped,
+            "Estimate must be below the bound"
+        );
+
+        let bounded = RenderOptions {
+            intersects_upper_bound: NonZeroU32::new(default.max_intersects / 2),
+            ..Default::default()
+        };
+        let report = render_memory_report(&limits, 10_000, img_size, true, &bounded);
+        assert_eq!(report.max_intersects, default.max_intersects / 2);
+        assert!(report.intersects_clamped, "Lower bound must clamp");
+        assert!(report.total_bytes < default.total_bytes);
+
+        let raised = RenderOptions {
+            intersects_upper_bound: NonZeroU32::new(brush_sort::MAX_SORT_KEYS),
+            ..Default::default()
+        };
+        let report =
+            render_memory_report(&limits, 1_000_000, glam::uvec2(1920, 1080), true, &raised);
+        assert!(
+            report.max_intersects > crate::INTERSECTS_UPPER_BOUND,
+            "Raised bound must allow more intersections"
+        );
+    }
+}
@@ -0,0 +1,599 @@
+//! A slow, straightforward CPU implementation of the forward render.
+//!
+//! This mirrors the GPU pipeline step by step (project, bin into tiles, sort by depth, rasterize),
+//! but is written for clarity rather than speed, so the kernels can be tested against it. It also
+//! allows rendering on machines without a usable GPU. Spherical harmonics are evaluated from their
+//! definition instead of the hand expanded polynomials the shaders use, so the two don't share
+//! mistakes.
+
+use glam::{Mat3, UVec2, Vec2, Vec3, Vec4, Vec4Swizzles};
+
+use crate::{
+    camera::{Camera, ImageOrigin},
+    render_options::{
+        AlphaGamma, AlphaMode, BlendMode, ClampPolicy, ColorSpace, LUMA_WEIGHTS, RenderOptions,
+        ResortWindow,
+    },
+    sh::sh_degree_from_coeffs,
+    shaders::{
+        helpers::{COV_BLUR, FAR_PLANE, MIP_FILTER_VAR, NEAR_PLANE, SOFT_CLIP_KNEE, TILE_WIDTH},
+        map_gaussian_to_intersects::TIE_BITS,
+    },
+};
+
+/// A splat after projecting it to the image.
+#[derive(Debug, Clone, Copy)]
+struct Projected {
+    global_gid: usize,
+    xy: Vec2,
+    /// Upper triangle of the inverse 2D covariance.
+    conic: Vec3,
+    color: Vec4,
+    depth: f32,
+    /// Change of the depth per pixel along the x and y axis of the image.
+    depth_grad: Vec2,
+}
+
+/// Render splats on the CPU, like the GPU render with `bwd_info` set.
+///
+/// The inputs are flat arrays laid out like the tensors passed to the GPU render: `[N, 3]` means,
+/// `[N, 3]` log scales, `[N, 4]` quaternions (w first), `[N, C, 3]` SH coefficients and `[N]`
+/// opacities, with the activation already applied.
+///
+/// Returns a `[height, width, channels]` image, with 4 channels, or 5 when rendering depth, and
+/// two channels less for grayscale images. The output is always F32. Tiles are rasterized on all
+/// available threads.
+pub fn render_reference(
+    camera: &Camera,
+    img_size: UVec2,
+    means: &[f32],
+    log_scales: &[f32],
+    quats: &[f32],
+    sh_coeffs: &[f32],
+    opacities: &[f32],
+    options: &RenderOptions,
+) -> Vec<f32> {
+    let num_splats = opacities.len();
+    assert!(
+        means.len() == num_splats * 3
+            && log_scales.len() == num_splats * 3
+            && quats.len() == num_splats * 4,
+        "Splat attributes must have the same number of splats."
+    );
+    assert!(
+        img_size.x > 0 && img_size.y > 0,
+        "Can't render images with 0 size."
+    );
+
+    assert!(
+        options.sh_channel_degrees.is_none(),
+        "Per channel sh degrees aren't supported by the reference renderer."
+    );
+
+    let coeffs_per_splat = if num_splats == 0 {
+        1
+    } else {
+        sh_coeffs.len() / (num_splats * 3)
+    };
+    let stored_degree = sh_degree_from_coeffs(coeffs_per_splat as u32);
+    let sh_degree = options
+        .max_sh_degree
+        .map_or(stored_degree, |max| max.min(stored_degree));
+
+    let projected: Vec<_> = (0..num_splats)
+        .filter_map(|gid| {
+            project_splat(
+                camera,
+                img_size,
+                gid,
+                Vec3::from_slice(&means[gid * 3..]),
+                Vec3::from_slice(&log_scales[gid * 3..]),
+                Vec4::from_slice(&quats[gid * 4..]),
+                &sh_coeffs[gid * coeffs_per_splat * 3..(gid + 1) * coeffs_per_splat * 3],
+                sh_degree,
+                opacities[gid],
+                options,
+            )
+        })
+        .collect();
+
+    let tiles = bin_tiles(&projected, img_size, options);
+
+    let channels = output_channels(options);
+    let tile_bounds = tile_bounds(img_size);
+    let row_len = (img_size.x * TILE_WIDTH) as usize * channels;
+    let mut img = vec![0.0; (img_size.x * img_size.y) as usize * channels];
+
+    // Split the image into bands of tile rows, one per thread.
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let rows_per_thread = tile_bounds.y.div_ceil(threads as u32).max(1);
+    std::thread::scope(|scope| {
+        for (band, pixels) in img
+            .chunks_mut(row_len * rows_per_thread as usize)
+            .enumerate()
+        {
+            let (tiles, projected) = (&tiles, &projected);
+            scope.spawn(move || {
+                let first_row = band as u32 * rows_per_thread;
+                for (i, pixel) in pixels.chunks_mut(channels).enumerate() {
+                    let i = i as u32;
+                    let pix = glam::uvec2(i % img_size.x, first_row * TILE_WIDTH + i / img_size.x);
+                    let tile = pix / TILE_WIDTH;
+                    let splats = &tiles[(tile.x + tile.y * tile_bounds.x) as usize];
+                    rasterize_pixel(pix, splats, projected, options, pixel);
+                }
+            });
+        }
+    });
+
+    if camera.origin == ImageOrigin::BottomLeft {
+        let row_len = img_size.x as usize * channels;
+        img = img.chunks(row_len).rev().flatten().copied().collect();
+    }
+    img
+}
+
+fn output_channels(options: &RenderOptions) -> usize {
+    let color_channels = if options.grayscale { 1 } else { 3 };
+    color_channels + 1 + usize::from(options.render_depth)
+}
+
+fn tile_bounds(img_size: UVec2) -> UVec2 {
+    glam::uvec2(
+        img_size.x.div_ceil(TILE_WIDTH),
+        img_size.y.div_ceil(TILE_WIDTH),
+    )
+}
+
+fn quat_to_mat(quat: Vec4) -> Mat3 {
+    let [w, x, y, z] = quat.to_array();
+    Mat3::from_cols(
+        Vec3::new(
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y + w * z),
+            2.0 * (x * z - w * y),
+        ),
+        Vec3::new(
+            2.0 * (x * y - w * z),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z + w * x),
+        ),
+        Vec3::new(
+            2.0 * (x * z + w * y),
+            2.0 * (y * z - w * x),
+            1.0 - 2.0 * (x * x + y * y),
+        ),
+    )
+}
+
+/// Upper triangle of the 2D covariance of a splat in pixels, using the local affine
+/// approximation of the perspective projection, and the covariance of the pixel position with
+/// the depth.
+fn project_cov(
+    cov_world: Mat3,
+    rotation: Mat3,
+    mean_c: Vec3,
+    focal: Vec2,
+    center: Vec2,
+    img_size: UVec2,
+) -> (Vec3, Vec2) {
+    let img_size = img_size.as_vec2();
+    let tan_fov = 0.5 * img_size / focal;
+    let lims_pos = (img_size - center) / focal + 0.3 * tan_fov;
+    let lims_neg = center / focal + 0.3 * tan_fov;
+
+    // Splats far outside the view are projected as if they were at the edge of the frustum, as
+    // the approximation breaks down there.
+    let rz = 1.0 / mean_c.z;
+    let t = mean_c.z * (mean_c.truncate() * rz).clamp(-lims_neg, lims_pos);
+
+    // The jacobian as a 3x3 matrix with an empty last row.
+    let jac = Mat3::from_cols(
+        Vec3::new(focal.x * rz, 0.0, 0.0),
+        Vec3::new(0.0, focal.y * rz, 0.0),
+        (-focal * t * rz * rz).extend(0.0),
+    );
+    let cov_cam = rotation * cov_world * rotation.transpose();
+    let cov = jac * cov_cam * jac.transpose();
+    let cov_xy_depth = (jac * cov_cam.z_axis).truncate();
+    (
+        Vec3::new(
+            cov.x_axis.x + COV_BLUR,
+            cov.x_axis.y,
+            cov.y_axis.y + COV_BLUR,
+        ),
+        cov_xy_depth,
+    )
+}
+
+fn project_splat(
+    camera: &Camera,
+    img_size: UVec2,
+    global_gid: usize,
+    mean: Vec3,
+    log_scale: Vec3,
+    quat: Vec4,
+    sh_coeffs: &[f32],
+    sh_degree: u32,
+    opacity: f32,
+    options: &RenderOptions,
+) -> Option<Projected> {
+    let transform = options.world_transform;
+    let world_to_local = camera.world_to_local() * transform.to_affine();
+    let rotation = Mat3::from(world_to_local.matrix3);
+    let mean_c = world_to_local.transform_point3(mean);
+
+    let world_mean = transform.to_affine().transform_point3(mean);
+    if !options
+        .clip_planes
+        .iter()
+        .all(|plane| plane.keeps(world_mean))
+    {
+        return None;
+    }
+
+    // Phrase checks as positive, so NaNs are culled.
+    let valid = mean_c.z > NEAR_PLANE && mean_c.z < FAR_PLANE && quat.length() > 1e-32;
+    if !valid {
+        return None;
+    }
+
+    let focal = camera.focal(img_size);
+    let center = camera.raster_center_shifted(img_size, options.subpixel_offset);
+
+    let mut scale = log_scale.exp();
+    let mut opacity = opacity;
+    if options.mip_filter {
+        let pixel_size = mean_c.z / (focal.max_element() * transform.scale.get());
+        let scale_sq = scale * scale;
+        let filtered_sq = scale_sq + MIP_FILTER_VAR * pixel_size * pixel_size;
+        let ratio = scale_sq / filtered_sq;
+        scale = filtered_sq.sqrt();
+        opacity *= (ratio.x * ratio.y * ratio.z).sqrt();
+    }
+
+    let rot_scale = quat_to_mat(quat.normalize()) * Mat3::from_diagonal(scale);
+    let (cov, cov_xy_depth) = project_cov(
+        rot_scale * rot_scale.transpose(),
+        rotation,
+        mean_c,
+        focal,
+        center,
+        img_size,
+    );
+    let det = cov.x * cov.z - cov.y * cov.y;
+    let valid = det > 0.0 && opacity > 1.0 / 255.0;
+    if !valid {
+        return None;
+    }
+    let conic = Vec3::new(cov.z, -cov.y, cov.x) / det;
+    let xy = focal * mean_c.truncate() / mean_c.z + center;
+    let depth_grad = Vec2::new(
+        conic.x * cov_xy_depth.x + conic.y * cov_xy_depth.y,
+        conic.y * cov_xy_depth.x + conic.z * cov_xy_depth.y,
+    );
+
+    let radius = radius_from_cov(cov);
+    let size = img_size.as_vec2();
+    let finite = conic.abs().max_element() < 1e30 && xy.abs().max_element() < 1e30;
+    let on_screen = finite
+        && radius > 0.0
+        && xy.x + radius > 0.0
+        && xy.x - radius < size.x
+        && xy.y + radius > 0.0
+        && xy.y - radius < size.y;
+    if !on_screen {
+        return None;
+    }
+
+    let camera_position = transform
+        .to_affine()
+        .inverse()
+        .transform_point3(camera.position);
+    let view_dir =
+        options.sh_basis.rotation(&options.world_transform) * (mean - camera_position).normalize();
+    let color = eval_sh(sh_coeffs, sh_degree, view_dir) + 0.5;
+    let (color, opacity) = match options.clamp_policy {
+        ClampPolicy::None => (color, opacity),
+        ClampPolicy::Clamp => (color.min(Vec3::ONE), opacity.clamp(0.0, 1.0)),
+        ClampPolicy::SoftClip => {
+            let width = 1.0 - SOFT_CLIP_KNEE;
+            let roll_off = |c: f32| {
+                if c > SOFT_CLIP_KNEE {
+                    SOFT_CLIP_KNEE + width * ((c - SOFT_CLIP_KNEE) / width).tanh()
+                } else {
+                    c
+                }
+            };
+            let color = Vec3::new(roll_off(color.x), roll_off(color.y), roll_off(color.z));
+            (color, opacity.clamp(0.0, 1.0))
+        }
+    };
+
+    Some(Projected {
+        global_gid,
+        xy,
+        conic,
+        color: color.extend(opacity),
+        depth: mean_c.z,
+        depth_grad,
+    })
+}
+
+/// Radius of a splat in pixels, 3 standard deviations along its major axis.
+fn radius_from_cov(cov: Vec3) -> f32 {
+    let det = cov.x * cov.z - cov.y * cov.y;
+    let mid = 0.5 * (cov.x + cov.z);
+    let max_eigen = mid + (mid * mid - det).max(0.01).sqrt();
+    (3.0 * max_eigen.sqrt()).ceil()
+}
+
+/// Evaluate real spherical harmonics, with the Condon-Shortley phase and bands ordered from
+/// m = -l to l, which matches the convention of the 3DGS reference implementation.
+fn eval_sh(coeffs: &[f32], degree: u32, dir: Vec3) -> Vec3 {
+    let dir = dir.as_dvec3();
+    let mut color = glam::DVec3::ZERO;
+
+    for l in 0..=degree as i32 {
+        for m in -l..=l {
+            let index = (l * l + l + m) as usize;
+            let coeff = Vec3::from_slice(&coeffs[index * 3..]).as_dvec3();
+            color += coeff * sh_basis(l, m, dir);
+        }
+    }
+    color.as_vec3()
+}
+
+fn sh_basis(l: i32, m: i32, dir: glam::DVec3) -> f64 {
+    let am = m.unsigned_abs() as i32;
+
+    // (x + iy)^|m| = sin(theta)^|m| * e^(i |m| phi), which avoids computing the angles.
+    let (mut re, mut im) = (1.0, 0.0);
+    for _ in 0..am {
+        (re, im) = (re * dir.x - im * dir.y, re * dir.y + im * dir.x);
+    }
+
+    let factorial = |n: i32| (1..=n).map(f64::from).product::<f64>();
+    let norm = ((2 * l + 1) as f64 / (4.0 * std::f64::consts::PI) * factorial(l - am)
+        / factorial(l + am))
+    .sqrt();
+    let legendre = legendre_poly(l, am, dir.z);
+
+    match m {
+        0 => norm * legendre,
+        m if m > 0 => std::f64::consts::SQRT_2 * norm * re * legendre,
+        _ => std::f64::consts::SQRT_2 * norm * im * legendre,
+    }
+}
+
+/// The associated Legendre polynomial `P_l^m(z)`, divided by `sin(theta)^m`.
+fn legendre_poly(l: i32, m: i32, z: f64) -> f64 {
+    // P_m^m, including the Condon-Shortley phase.
+    let double_factorial = (1..=m).map(|i| f64::from(2 * i - 1)).product::<f64>();
+    let mut prev = if m % 2 == 0 {
+        double_factorial
+    } else {
+        -double_factorial
+    };
+    if l == m {
+        return prev;
+    }
+    let mut cur = z * f64::from(2 * m + 1) * prev;
+    for n in m + 2..=l {
+        let next =
+            (f64::from(2 * n - 1) * z * cur - f64::from(n + m - 1) * prev) / f64::from(n - m);
+        (prev, cur) = (cur, next);
+    }
+    cur
+}
+
+/// Whether a splat can reach an alpha of 1/255 anywhere inside a tile.
+///
+/// This checks the ellipse where the splat falls off to that alpha against the tile bounds.
+fn can_be_visible(tile: UVec2, splat: &Projected) -> bool {
+    let sigma = (splat.color.w * 255.0).ln();
+    if sigma <= 0.0 {
+        return false;
+    }
+    // Quadratic form of the ellipse, scaled so points on it evaluate to 1.
+    let scale = 1.0 / (2.0 * sigma);
+    let conic = splat.conic * scale;
+    let quad = |p: Vec2| conic.x * p.x * p.x + 2.0 * conic.y * p.x * p.y + conic.z * p.y * p.y;
+
+    let extent = Vec2::splat(TILE_WIDTH as f32 / 2.0);
+    let tile_center = tile.as_vec2() * TILE_WIDTH as f32 + extent;
+    let d = splat.xy - tile_center;
+    if d.abs().cmple(extent).all() {
+        return true;
+    }
+
+    // Nb: Unlike signum, sign in WGSL is 0 for 0.
+    let corner_sign = Vec2::select(d.cmpeq(Vec2::ZERO), Vec2::ZERO, d.signum());
+    let corner = tile_center + corner_sign * extent;
+    if quad(corner - splat.xy) <= 1.0 {
+        return true;
+    }
+
+    // Check whether the ellipse crosses the two edges next to the nearest corner.
+    let crosses = |end: Vec2| {
+        let edge = end - corner;
+        let f = corner - splat.xy;
+        let a = quad(edge);
+        let b = 2.0
+            * (conic.x * f.x * edge.x
+                + conic.y * (f.x * edge.y + f.y * edge.x)
+                + conic.z * f.y * edge.y);
+        let c = quad(f) - 1.0;
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return false;
+        }
+        let root = discriminant.sqrt();
+        let t1 = (-b - root) / (2.0 * a);
+        let t2 = (-b + root) / (2.0 * a);
+        (0.0..=1.0).contains(&t1) || (0.0..=1.0).contains(&t2)
+    };
+    crosses(corner - Vec2::new(corner_sign.x * 2.0 * extent.x, 0.0))
+        || crosses(corner - Vec2::new(0.0, corner_sign.y * 2.0 * extent.y))
+}
+
+/// Indices of the splats overlapping each tile, sorted front to back.
+fn bin_tiles(projected: &[Projected], img_size: UVec2, options: &RenderOptions) -> Vec<Vec<usize>> {
+    let tile_bounds = tile_bounds(img_size);
+    let mut tiles = vec![vec![]; (tile_bounds.x * tile_bounds.y) as usize];
+
+    for (index, splat) in projected.iter().enumerate() {
+        let cov = Vec3::new(splat.conic.z, -splat.conic.y, splat.conic.x)
+            / (splat.conic.x * splat.conic.z - splat.conic.y * splat.conic.y);
+        // Like the GPU, bin with the radius of a fully opaque splat.
+        let radius = radius_from_cov(cov) / TILE_WIDTH as f32;
+        let center = splat.xy / TILE_WIDTH as f32;
+        let bounds = tile_bounds.as_vec2();
+        let min = (center - radius).clamp(Vec2::ZERO, bounds).as_uvec2();
+        let max = (center + radius + 1.0).clamp(Vec2::ZERO, bounds).as_uvec2();
+
+        let opaque = Projected {
+            color: splat.color.xyz().extend(1.0),
+            ..*splat
+        };
+        for ty in min.y..max.y {
+            for tx in min.x..max.x {
+                if can_be_visible(glam::uvec2(tx, ty), &opaque) {
+                    tiles[(tx + ty * tile_bounds.x) as usize].push(index);
+                }
+            }
+        }
+    }
+
+    let tie_mask = (1u32 << TIE_BITS) - 1;
+    for tile in &mut tiles {
+        if options.stable_depth_ties {
+            tile.sort_by_key(|&i| {
+                let splat = &projected[i];
+                (splat.depth.to_bits() & !tie_mask, splat.global_gid)
+            });
+        } else {
+            tile.sort_by(|&a, &b| projected[a].depth.total_cmp(&projected[b].depth));
+        }
+        if let Some(budget) = options.tile_budget {
+            tile.truncate(budget.get() as usize);
+        }
+    }
+    tiles
+}
+
+fn rasterize_pixel(
+    pix: UVec2,
+    splats: &[usize],
+    projected: &[Projected],
+    options: &RenderOptions,
+    out: &mut [f32],
+) {
+    let pixel_coord = pix.as_vec2() + 0.5;
+    let mut transmittance = 1.0;
+    let mut rgb = Vec3::ZERO;
+    let mut depth = 0.0;
+    let threshold = options.transmittance();
+
+    // Blends a splat, unless the pixel is (nearly) opaque already. Returns whether it is.
+    let mut blend = |alpha: f32, splat: &Projected| {
+        let next_transmittance = transmittance * (1.0 - alpha);
+        if options.blend_mode == BlendMode::AlphaOver && next_transmittance <= threshold {
+            return true;
+        }
+
+        let vis = alpha * transmittance;
+        let splat_rgb = splat.color.xyz().max(Vec3::ZERO);
+        match options.blend_mode {
+            BlendMode::AlphaOver => rgb += splat_rgb * vis,
+            BlendMode::Additive => rgb += splat_rgb * alpha,
+            BlendMode::Max => rgb = rgb.max(splat_rgb * alpha),
+        }
+        depth += if options.depth_as_disparity {
+            vis / splat.depth
+        } else {
+            splat.depth * vis
+        };
+        transmittance = next_transmittance;
+        false
+    };
+
+    // With per pixel re-sorting, the splats held back, sorted by their depth at the pixel.
+    let resort_window = options.pixel_resort.map_or(0, ResortWindow::get) as usize;
+    let mut window: Vec<(f32, f32, &Projected)> = Vec::with_capacity(resort_window + 1);
+    let mut done = false;
+
+    for &index in splats {
+        let splat = &projected[index];
+        let delta = splat.xy - pixel_coord;
+        let sigma = 0.5 * (splat.conic.x * delta.x * delta.x + splat.conic.z * delta.y * delta.y)
+            + splat.conic.y * delta.x * delta.y;
+        let alpha = (splat.color.w * (-sigma).exp())
+            .min(0.999)
+            .powf(options.alpha_gamma.map_or(1.0, AlphaGamma::get));
+        if sigma < 0.0 || alpha < 1.0 / 255.0 {
+            continue;
+        }
+
+        if resort_window == 0 {
+            if blend(alpha, splat) {
+                done = true;
+                break;
+            }
+            continue;
+        }
+
+        // Keep the window sorted, and blend its nearest splat once it overflows. Ties keep the
+        // tile order.
+        let key = splat.depth + (pixel_coord - splat.xy).dot(splat.depth_grad);
+        let insert = window.partition_point(|&(other, _, _)| other <= key);
+        window.insert(insert, (key, alpha, splat));
+        if window.len() > resort_window {
+            let (_, alpha, splat) = window.remove(0);
+            if blend(alpha, splat) {
+                done = true;
+                break;
+            }
+        }
+    }
+
+    if !done {
+        for (_, alpha, splat) in window {
+            if blend(alpha, splat) {
+                break;
+            }
+        }
+    }
+
+    let alpha = 1.0 - transmittance;
+    if alpha > 0.0 {
+        if options.color_space == ColorSpace::Linear {
+            rgb = srgb_to_linear(rgb / alpha) * alpha;
+        }
+        if options.alpha_mode == AlphaMode::Straight {
+            rgb /= alpha;
+        }
+    }
+
+    if options.grayscale {
+        out[0] = rgb.dot(Vec3::from_array(LUMA_WEIGHTS));
+        out[1] = alpha;
+    } else {
+        out[..4].copy_from_slice(&rgb.extend(alpha).to_array());
+    }
+    if options.render_depth {
+        out[output_channels(options) - 1] = depth;
+    }
+}
+
+fn srgb_to_linear(color: Vec3) -> Vec3 {
+    let decode = |c: f32| {
+        let c = c.max(0.0);
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    Vec3::new(decode(color.x), decode(color.y), decode(color.z))
+}
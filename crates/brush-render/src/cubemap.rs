@@ -0,0 +1,102 @@
+//! Rendering all directions around a point as a cubemap, eg. to light other objects with a scene.
+//!
+//! Faces follow the layout of cube textures in wgpu (and Vulkan, D3D and OpenGL): the faces are
+//! ordered +X, -X, +Y, -Y, +Z, -Z, and each face is oriented so that uploading the rendered
+//! images as the layers of a cube texture samples the direction they were rendered in.
+
+use crate::camera::{Camera, ImageOrigin};
+
+/// Number of faces of a cubemap.
+pub const CUBEMAP_FACES: usize = 6;
+
+/// View direction, and the directions of the right and bottom edge of each face, in layer order.
+const FACE_AXES: [(glam::Vec3, glam::Vec3, glam::Vec3); CUBEMAP_FACES] = [
+    (glam::Vec3::X, glam::Vec3::NEG_Z, glam::Vec3::NEG_Y),
+    (glam::Vec3::NEG_X, glam::Vec3::Z, glam::Vec3::NEG_Y),
+    (glam::Vec3::Y, glam::Vec3::X, glam::Vec3::Z),
+    (glam::Vec3::NEG_Y, glam::Vec3::X, glam::Vec3::NEG_Z),
+    (glam::Vec3::Z, glam::Vec3::X, glam::Vec3::NEG_Y),
+    (glam::Vec3::NEG_Z, glam::Vec3::NEG_X, glam::Vec3::NEG_Y),
+];
+
+/// The cameras that render each face of a cubemap centered at `position`, in layer order.
+///
+/// Each camera has a 90° field of view, so the faces meet exactly at their edges when rendered
+/// at square sizes. Cube textures are mirrored compared to a camera looking out of the cube, so
+/// the cameras store their rows bottom up, see [`ImageOrigin::BottomLeft`].
+pub fn cubemap_cameras(position: glam::Vec3) -> [Camera; CUBEMAP_FACES] {
+    FACE_AXES.map(|(forward, right, down)| {
+        // Flip the rows so the camera has a right handed basis, and flip them back when storing
+        // the image.
+        let rotation = glam::Quat::from_mat3(&glam::Mat3::from_cols(right, -down, forward));
+        Camera::new(
+            position,
+            rotation,
+            std::f64::consts::FRAC_PI_2,
+            std::f64::consts::FRAC_PI_2,
+            glam::vec2(0.5, 0.5),
+        )
+        .with_origin(ImageOrigin::BottomLeft)
+    })
+}
+
+/// The face a direction falls on, and where on that face, as uv coordinates in `[0, 1]` from the
+/// top left of the face image. This is how cube textures are sampled.
+///
+/// Returns `None` for the zero direction, or directions that aren't finite.
+pub fn cubemap_face_uv(dir: glam::Vec3) -> Option<(usize, glam::Vec2)> {
+    if !dir.is_finite() || dir == glam::Vec3::ZERO {
+        return None;
+    }
+    let abs = dir.abs();
+    // Ties pick the first axis, like the major axis selection of GPUs.
+    let face = if abs.x >= abs.y && abs.x >= abs.z {
+        usize::from(dir.x < 0.0)
+    } else if abs.y >= abs.z {
+        2 + usize::from(dir.y < 0.0)
+    } else {
+        4 + usize::from(dir.z < 0.0)
+    };
+    let (forward, right, down) = FACE_AXES[face];
+    let major = dir.dot(forward);
+    let uv = glam::vec2(dir.dot(right), dir.dot(down)) / major;
+    Some((face, uv * 0.5 + 0.5))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CUBEMAP_FACES, cubemap_cameras, cubemap_face_uv};
+
+    #[test]
+    fn face_centers_look_along_axes() {
+        let cameras = cubemap_cameras(glam::Vec3::ZERO);
+        for (face, camera) in cameras.iter().enumerate() {
+            let forward = camera.rotation * glam::Vec3::Z;
+            let (center_face, uv) = cubemap_face_uv(forward).expect("Direction must be valid");
+            assert_eq!(center_face, face, "Camera {face} must look at its own face");
+            assert!(
+                (uv - glam::vec2(0.5, 0.5)).length() < 1e-5,
+                "Camera {face} must look at the center of its face, got {uv}"
+            );
+
+            // The camera's right is the right of the face, and as rows are stored bottom up, the
+            // bottom of the view is the top of the face.
+            let right = camera.rotation * glam::Vec3::X;
+            let (_, uv) = cubemap_face_uv(forward + 0.5 * right).expect("Valid direction");
+            assert!(uv.x > 0.5 && (uv.y - 0.5).abs() < 1e-5, "Face {face}: {uv}");
+            let below = camera.rotation * glam::Vec3::Y;
+            let (_, uv) = cubemap_face_uv(forward + 0.5 * below).expect("Valid direction");
+            assert!(uv.y < 0.5 && (uv.x - 0.5).abs() < 1e-5, "Face {face}: {uv}");
+        }
+        assert_eq!(cameras.len(), CUBEMAP_FACES);
+    }
+
+    #[test]
+    fn corners_of_faces_meet() {
+        // The top left of +Z is where +Z, -X and +Y meet.
+        let (face, uv) = cubemap_face_uv(glam::vec3(-1.0, 1.0, 1.001)).expect("Valid direction");
+        assert_eq!(face, 4);
+        assert!(uv.x < 0.01 && uv.y < 0.01, "Got {uv}");
+        assert_eq!(cubemap_face_uv(glam::Vec3::ZERO), None);
+    }
+}
@@ -75,3 +75,128 @@ impl From<&'static str> for DimBound {
         }
     }
 }
+
+/// Quats whose length is further than this from one are likely garbage, rather than just not
+/// normalized. The render normalizes quats, but training lets their length drift a little.
+#[cfg(all(feature = "debug_validation", not(target_family = "wasm")))]
+const QUAT_LENGTH_TOLERANCE: f32 = 0.5;
+
+/// Warn, once, if more than 10% of `quats` are far from unit length.
+///
+/// The render normalizes each quat, so these still render as rotations, but so many unnormalized
+/// quats usually means an importer read the wrong properties or in the wrong order.
+#[cfg(all(feature = "debug_validation", not(target_family = "wasm")))]
+pub(crate) fn warn_non_unit_quats(quats: &CubeTensor<burn_wgpu::WgpuRuntime>) {
+    use burn::tensor::{ElementConversion, Tensor, TensorPrimitive};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static WARNED: AtomicBool = AtomicBool::new(false);
+
+    let num_quats = quats.shape.dims[0];
+    if num_quats == 0 || WARNED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let quats =
+        Tensor::<crate::MainBackendBase, 2>::from_primitive(TensorPrimitive::Float(quats.clone()));
+    let lengths = quats.powi_scalar(2).sum_dim(1).sqrt();
+    let num_far = (lengths - 1.0)
+        .abs()
+        .greater_elem(QUAT_LENGTH_TOLERANCE)
+        .int()
+        .sum()
+        .into_scalar()
+        .elem::<i64>();
+
+    if num_far as f32 > 0.1 * num_quats as f32 && !WARNED.swap(true, Ordering::Relaxed) {
+        tracing::warn!(
+            "{num_far} of {num_quats} quaternions are far from unit length, which likely means they were imported wrongly."
+        );
+    }
+}
+
+/// Depths the tile depth sort can't order, as their index and value.
+///
+/// The sort reinterprets the bits of each depth as an integer key, which only orders positive
+/// floats correctly. Zero, negative, denormal or non-finite depths silently sort in the wrong
+/// place, which shows up as splats drawn over splats in front of them. Projection culls splats
+/// outside the depth range, so any of these is a bug.
+#[cfg(any(test, all(feature = "debug_validation", not(target_family = "wasm"))))]
+pub(crate) fn invalid_depths(depths: &[f32]) -> Vec<(usize, f32)> {
+    depths
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|&(_, depth)| !(depth.is_normal() && depth > 0.0))
+        .collect()
+}
+
+/// Panic if any depth of the `num_visible` compacted splats can't be sorted, see
+/// [`invalid_depths`], listing the global ids of the offending splats.
+///
+/// This reads back the depths, so only runs with the `debug_validation` feature.
+#[cfg(all(feature = "debug_validation", not(target_family = "wasm")))]
+pub(crate) fn assert_valid_depths(
+    depths: &CubeTensor<burn_wgpu::WgpuRuntime>,
+    global_from_compact_gid: &CubeTensor<burn_wgpu::WgpuRuntime>,
+    num_visible: &CubeTensor<burn_wgpu::WgpuRuntime>,
+) {
+    use burn::tensor::{ElementConversion, Int, Tensor, TensorPrimitive, s};
+
+    type B = crate::MainBackendBase;
+    let num_visible = Tensor::<B, 1, Int>::from_primitive(num_visible.clone())
+        .into_scalar()
+        .elem::<i64>() as usize;
+    if num_visible == 0 {
+        return;
+    }
+
+    let depths = Tensor::<B, 1>::from_primitive(TensorPrimitive::Float(depths.clone()))
+        .slice(s![0..num_visible])
+        .into_data()
+        .to_vec::<f32>()
+        .expect("Failed to read depths");
+    let invalid = invalid_depths(&depths);
+    if invalid.is_empty() {
+        return;
+    }
+
+    let global_ids = Tensor::<B, 1, Int>::from_primitive(global_from_compact_gid.clone())
+        .slice(s![0..num_visible])
+        .into_data()
+        .to_vec::<i32>()
+        .expect("Failed to read global ids");
+    let offending: Vec<_> = invalid
+        .iter()
+        .take(10)
+        .map(|&(compact_gid, depth)| {
+            format!("splat {} at depth {depth:e}", global_ids[compact_gid])
+        })
+        .collect();
+    panic!(
+        "{} visible splats have depths the depth sort can't order, eg. {}.",
+        invalid.len(),
+        offending.join(", ")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::invalid_depths;
+
+    #[test]
+    fn flags_depths_the_sort_cant_order() {
+        let mut depths = vec![0.5, 1.0, 20.0, 1e-3];
+        assert!(invalid_depths(&depths).is_empty());
+
+        depths[2] = 0.0;
+        assert_eq!(invalid_depths(&depths), vec![(2, 0.0)]);
+
+        depths.extend([-1.0, f32::MIN_POSITIVE / 2.0, f32::INFINITY]);
+        let flagged: Vec<_> = invalid_depths(&depths).iter().map(|&(i, _)| i).collect();
+        assert_eq!(flagged, vec![2, 4, 5, 6]);
+        let nan = invalid_depths(&[1.0, f32::NAN]);
+        assert_eq!(nan.len(), 1);
+        assert_eq!(nan[0].0, 1);
+    }
+}
@@ -2,13 +2,16 @@ use crate::{
     SplatForward,
     bounding_box::BoundingBox,
     camera::Camera,
+    cubemap::{CUBEMAP_FACES, cubemap_cameras},
+    read_image::{ImageData, read_image_u8_async},
     render_aux::RenderAux,
+    render_options::{LUMA_WEIGHTS, RenderOptions},
     sh::{sh_coeffs_for_degree, sh_degree_from_coeffs},
 };
 use ball_tree::BallTree;
 use burn::{
     config::Config,
-    module::{Module, Param, ParamId},
+    module::{Ignored, Module, Param, ParamId},
     prelude::Backend,
     tensor::{
         Tensor, TensorData, TensorPrimitive, activation::sigmoid, backend::AutodiffBackend, s,
@@ -20,7 +23,73 @@ use rand::Rng;
 #[derive(Config)]
 pub struct RandomSplatsConfig {
     #[config(default = 10000)]
-    init_count: usize,
+    pub init_count: usize,
+}
+
+/// Activation function which maps the raw opacity parameters of splats to opacities in `[0, 1]`.
+///
+/// Splats are rendered from their raw opacities, and the renderer applies the activation while
+/// projecting them, see [`RenderOptions::opacity_activation`]. Differentiable renders apply the
+/// derivative of the activation to the opacity gradients.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OpacityActivation {
+    /// `sigmoid(x)`, as in the original 3DGS. This is also how opacities are stored in .ply files.
+    #[default]
+    Sigmoid,
+    /// `1 - exp(-exp(x))`, where `x` is the log density of the splat.
+    ///
+    /// This treats splats as volumes of constant density, whose opacity saturates as the density
+    /// grows.
+    ExpDensity,
+}
+
+impl OpacityActivation {
+    /// Map raw opacities to opacities.
+    pub fn activate<B: Backend>(self, raw: Tensor<B, 1>) -> Tensor<B, 1> {
+        match self {
+            Self::Sigmoid => sigmoid(raw),
+            Self::ExpDensity => 1.0f32 - raw.exp().neg().exp(),
+        }
+    }
+
+    /// Map a single raw opacity to its opacity, like [`Self::activate`].
+    pub fn activate_value(self, raw: f32) -> f32 {
+        match self {
+            Self::Sigmoid => 1.0 / (1.0 + (-raw).exp()),
+            Self::ExpDensity => 1.0 - (-raw.exp()).exp(),
+        }
+    }
+
+    /// Map opacities back to raw opacities. Opacities of exactly 0 or 1 map to infinities.
+    pub fn inverse<B: Backend>(self, opacity: Tensor<B, 1>) -> Tensor<B, 1> {
+        match self {
+            Self::Sigmoid => (opacity.clone() / (1.0f32 - opacity)).log(),
+            Self::ExpDensity => (1.0f32 - opacity).log().neg().log(),
+        }
+    }
+
+    /// Map a single opacity back to its raw opacity, eg. to initialize splats.
+    pub fn inverse_value(self, opacity: f32) -> f32 {
+        match self {
+            Self::Sigmoid => inverse_sigmoid(opacity),
+            Self::ExpDensity => (-(1.0 - opacity).ln()).ln(),
+        }
+    }
+}
+
+impl std::str::FromStr for OpacityActivation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sigmoid" => Ok(Self::Sigmoid),
+            "exp-density" => Ok(Self::ExpDensity),
+            _ => Err(format!(
+                "Unknown opacity activation '{s}', expected 'sigmoid' or 'exp-density'"
+            )),
+        }
+    }
 }
 
 #[derive(Module, Debug)]
@@ -30,6 +99,8 @@ pub struct Splats<B: Backend> {
     pub log_scales: Param<Tensor<B, 2>>,
     pub sh_coeffs: Param<Tensor<B, 3>>,
     pub raw_opacity: Param<Tensor<B, 1>>,
+    /// How `raw_opacity` maps to the opacity of each splat, see [`Self::opacities`].
+    pub opacity_activation: Ignored<OpacityActivation>,
 }
 
 fn norm_vec<B: Backend>(vec: Tensor<B, 2>) -> Tensor<B, 2> {
@@ -42,6 +113,115 @@ pub fn inverse_sigmoid(x: f32) -> f32 {
     (x / (1.0 - x)).ln()
 }
 
+/// Map an opacity to the raw opacity splats store, for the default [`OpacityActivation`]. This is
+/// also how opacities are stored in .ply files. See [`OpacityActivation::inverse_value`] for
+/// other activations.
+pub fn inverse_opacity_activation(opacity: f32) -> f32 {
+    OpacityActivation::default().inverse_value(opacity)
+}
+
+/// Map a raw log scale of a splat to its scale, like the projection kernels do.
+pub fn scale_activation(log_scale: f32) -> f32 {
+    log_scale.exp()
+}
+
+/// Map a scale to the raw log scale splats store. This is the inverse of [`scale_activation`].
+pub fn inverse_scale_activation(scale: f32) -> f32 {
+    scale.ln()
+}
+
+/// Mean distance from each point to its `k` nearest other points.
+///
+/// When there are fewer than `k + 1` points, all other points are used. Points without any
+/// neighbours get a distance of zero.
+pub fn knn_mean_distances(points: &[Vec3], k: usize) -> Vec<f32> {
+    let tree_pos: Vec<[f64; 3]> = points
+        .iter()
+        .map(|v| [v.x as f64, v.y as f64, v.z as f64])
+        .collect();
+    let k = k.min(points.len().saturating_sub(1));
+    if k == 0 {
+        return vec![0.0; points.len()];
+    }
+
+    let empty = vec![(); tree_pos.len()];
+    let tree = BallTree::new(tree_pos.clone(), empty);
+
+    tree_pos
+        .iter()
+        .map(|p| {
+            // The nearest point is the point itself.
+            let sum = tree.query().nn(p).skip(1).take(k).map(|x| x.1).sum::<f64>();
+            (sum / k as f64) as f32
+        })
+        .collect()
+}
+
+/// How the scale and opacity of initial splats are chosen, for splats created without them, eg.
+/// from a point cloud. See [`Splats::from_raw_with_init`].
+///
+/// Each splat is scaled by the mean distance to its `knn` nearest neighbours, times
+/// `scale_multiplier`. Splats are isotropic, so this makes neighbouring splats overlap roughly
+/// by the multiplier: lower it for sparse point clouds of large scenes, where splats would
+/// otherwise cover large empty areas. Only splats without any neighbour at a distance, like a
+/// lone point or points stacked on top of each other, use `fallback_scale` instead. It is in
+/// world units, so should match the scale of the scene.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InitOptions {
+    /// Number of nearest neighbours to estimate the scale of each splat from.
+    pub knn: usize,
+    /// Multiplier of the scale estimated from the nearest neighbours.
+    pub scale_multiplier: f32,
+    /// Scale of splats that have no neighbours to estimate their scale from.
+    pub fallback_scale: f32,
+    /// Opacity of every initial splat. Without one, opacities are random between 0.1 and 0.25.
+    pub opacity: Option<f32>,
+}
+
+impl Default for InitOptions {
+    fn default() -> Self {
+        Self {
+            knn: 3,
+            scale_multiplier: 1.0,
+            fallback_scale: 0.1,
+            opacity: None,
+        }
+    }
+}
+
+impl InitOptions {
+    /// Log scale of each splat at `means`, from the mean distance to its nearest neighbours.
+    pub fn log_scales(&self, means: &[Vec3]) -> Vec<Vec3> {
+        knn_mean_distances(means, self.knn)
+            .into_iter()
+            .map(|dist| {
+                let scale = if dist > 0.0 {
+                    dist * self.scale_multiplier
+                } else {
+                    self.fallback_scale
+                };
+                Vec3::splat(inverse_scale_activation(scale))
+            })
+            .collect()
+    }
+
+    /// Raw opacities of `count` splats, drawn from `rng` when there is no fixed opacity.
+    pub fn raw_opacities(&self, count: usize, rng: &mut impl Rng) -> Vec<f32> {
+        if let Some(opacity) = self.opacity {
+            assert!(
+                opacity > 0.0 && opacity < 1.0,
+                "The initial opacity must be between 0 and 1, got {opacity}."
+            );
+            vec![inverse_opacity_activation(opacity); count]
+        } else {
+            let range = inverse_opacity_activation(0.1)..inverse_opacity_activation(0.25);
+            (0..count)
+                .map(|_| rng.random_range(range.clone()))
+                .collect()
+        }
+    }
+}
+
 impl<B: Backend> Splats<B> {
     pub fn from_random_config(
         config: &RandomSplatsConfig,
@@ -82,6 +262,27 @@ impl<B: Backend> Splats<B> {
         sh_coeffs: Option<&[f32]>,
         raw_opacities: Option<&[f32]>,
         device: &B::Device,
+    ) -> Self {
+        Self::from_raw_with_init(
+            means,
+            rotations,
+            log_scales,
+            sh_coeffs,
+            raw_opacities,
+            &InitOptions::default(),
+            device,
+        )
+    }
+
+    /// Like [`Self::from_raw`], with the scales and opacities that aren't given chosen by `init`.
+    pub fn from_raw_with_init(
+        means: &[Vec3],
+        rotations: Option<&[Quat]>,
+        log_scales: Option<&[Vec3]>,
+        sh_coeffs: Option<&[f32]>,
+        raw_opacities: Option<&[f32]>,
+        init: &InitOptions,
+        device: &B::Device,
     ) -> Self {
         let n_splats = means.len();
 
@@ -103,32 +304,15 @@ impl<B: Backend> Splats<B> {
             ))
         };
 
+        let init_log_scales;
         let log_scales = if let Some(log_scales) = log_scales {
-            let log_scales: Vec<f32> = log_scales.iter().flat_map(|v| [v.x, v.y, v.z]).collect();
-            Tensor::from_data(TensorData::new(log_scales, [n_splats, 3]), device)
+            log_scales
         } else {
-            let tree_pos: Vec<[f64; 3]> = means
-                .iter()
-                .map(|v| [v.x as f64, v.y as f64, v.z as f64])
-                .collect();
-
-            let empty = vec![(); tree_pos.len()];
-            let tree = BallTree::new(tree_pos.clone(), empty);
-
-            let extents: Vec<_> = tree_pos
-                .iter()
-                .map(|p| {
-                    // Get average of 4 nearest distances.
-                    0.5 * tree.query().nn(p).skip(1).take(2).map(|x| x.1).sum::<f64>() / 2.0
-                })
-                .map(|p| p.max(1e-12))
-                .map(|p| p.ln() as f32)
-                .collect();
-
-            Tensor::<B, 1>::from_floats(extents.as_slice(), device)
-                .reshape([n_splats, 1])
-                .repeat_dim(1, 3)
+            init_log_scales = init.log_scales(means);
+            &init_log_scales
         };
+        let log_scales: Vec<f32> = log_scales.iter().flat_map(|v| [v.x, v.y, v.z]).collect();
+        let log_scales = Tensor::from_data(TensorData::new(log_scales, [n_splats, 3]), device);
 
         let sh_coeffs = if let Some(sh_coeffs) = sh_coeffs {
             let n_coeffs = sh_coeffs.len() / n_splats;
@@ -142,19 +326,11 @@ impl<B: Backend> Splats<B> {
                 .repeat_dim(0, n_splats)
         };
 
-        let raw_opacities = if let Some(raw_opacities) = raw_opacities {
-            Tensor::from_data(TensorData::new(raw_opacities.to_vec(), [n_splats]), device)
-                .require_grad()
-        } else {
-            Tensor::random(
-                [n_splats],
-                burn::tensor::Distribution::Uniform(
-                    inverse_sigmoid(0.1) as f64,
-                    inverse_sigmoid(0.25) as f64,
-                ),
-                device,
-            )
-        };
+        let raw_opacities = raw_opacities.map_or_else(
+            || init.raw_opacities(n_splats, &mut rand::rng()),
+            <[f32]>::to_vec,
+        );
+        let raw_opacities = Tensor::from_data(TensorData::new(raw_opacities, [n_splats]), device);
 
         Self::from_tensor_data(
             means_tensor,
@@ -169,7 +345,7 @@ impl<B: Backend> Splats<B> {
     pub fn with_sh_degree(mut self, sh_degree: u32) -> Self {
         let n_coeffs = sh_coeffs_for_degree(sh_degree) as usize;
 
-        let [n, cur_coeffs, _] = self.sh_coeffs.dims();
+        let [n, cur_coeffs, channels] = self.sh_coeffs.dims();
 
         self.sh_coeffs = self.sh_coeffs.map(|coeffs| {
             let device = coeffs.device();
@@ -177,7 +353,7 @@ impl<B: Backend> Splats<B> {
                 Tensor::cat(
                     vec![
                         coeffs,
-                        Tensor::zeros([n, n_coeffs - cur_coeffs, 3], &device),
+                        Tensor::zeros([n, n_coeffs - cur_coeffs, channels], &device),
                     ],
                     1,
                 )
@@ -189,6 +365,55 @@ impl<B: Backend> Splats<B> {
         self
     }
 
+    /// Convert the colors of these splats to gray, keeping a single channel of coefficients of
+    /// shape `[N, C, 1]`, see [`Self::is_grayscale`].
+    ///
+    /// Each coefficient is weighted by [`LUMA_WEIGHTS`]. As spherical harmonics are linear and
+    /// the weights sum to one, the gray splats render the luma of the original colors.
+    pub fn to_grayscale(mut self) -> Self {
+        if self.is_grayscale() {
+            return self;
+        }
+        self.sh_coeffs = self.sh_coeffs.map(|coeffs| {
+            let weights =
+                Tensor::<B, 1>::from_floats(LUMA_WEIGHTS, &coeffs.device()).reshape([1, 1, 3]);
+            (coeffs * weights).sum_dim(2).detach().require_grad()
+        });
+        self
+    }
+
+    /// Merge several sets of splats into one, eg. to render a composition of scenes at once.
+    ///
+    /// Sets with a lower SH degree are padded with zero coefficients up to the highest degree, and
+    /// all opacities are converted to the opacity activation of the first set.
+    pub fn merge(scenes: Vec<Self>) -> Self {
+        assert!(
+            !scenes.is_empty(),
+            "Need at least one set of splats to merge"
+        );
+
+        let sh_degree = scenes.iter().map(Self::sh_degree).max().unwrap_or(0);
+        let activation = scenes[0].opacity_activation.0;
+        let scenes: Vec<_> = scenes
+            .into_iter()
+            .map(|splats| {
+                splats
+                    .with_sh_degree(sh_degree)
+                    .with_opacity_activation(activation)
+            })
+            .collect();
+
+        let mut merged = Self::from_tensor_data(
+            Tensor::cat(scenes.iter().map(|s| s.means.val()).collect(), 0),
+            Tensor::cat(scenes.iter().map(|s| s.rotation.val()).collect(), 0),
+            Tensor::cat(scenes.iter().map(|s| s.log_scales.val()).collect(), 0),
+            Tensor::cat(scenes.iter().map(|s| s.sh_coeffs.val()).collect(), 0),
+            Tensor::cat(scenes.iter().map(|s| s.raw_opacity.val()).collect(), 0),
+        );
+        merged.opacity_activation = Ignored(activation);
+        merged
+    }
+
     pub fn from_tensor_data(
         means: Tensor<B, 2>,
         rotation: Tensor<B, 2>,
@@ -206,11 +431,28 @@ impl<B: Backend> Splats<B> {
             rotation: Param::initialized(ParamId::new(), rotation.detach().require_grad()),
             raw_opacity: Param::initialized(ParamId::new(), raw_opacity.detach().require_grad()),
             log_scales: Param::initialized(ParamId::new(), log_scales.detach().require_grad()),
+            opacity_activation: Ignored(OpacityActivation::default()),
         }
     }
 
     pub fn opacities(&self) -> Tensor<B, 1> {
-        sigmoid(self.raw_opacity.val())
+        self.opacity_activation.0.activate(self.raw_opacity.val())
+    }
+
+    /// Switch to a different opacity activation, converting the raw opacities so the opacity of
+    /// each splat stays the same.
+    pub fn with_opacity_activation(mut self, activation: OpacityActivation) -> Self {
+        let current = self.opacity_activation.0;
+        if current == activation {
+            return self;
+        }
+        self.raw_opacity = self.raw_opacity.map(|raw| {
+            // Keep opacities away from 0 and 1, where the raw opacity would be infinite.
+            let opacity = current.activate(raw).clamp(1e-6, 1.0 - 1e-6);
+            activation.inverse(opacity).detach().require_grad()
+        });
+        self.opacity_activation = Ignored(activation);
+        self
     }
 
     pub fn scales(&self) -> Tensor<B, 2> {
@@ -235,6 +477,11 @@ impl<B: Backend> Splats<B> {
         sh_degree_from_coeffs(coeffs as u32)
     }
 
+    /// Whether the coefficients have a single gray channel instead of red, green and blue.
+    pub fn is_grayscale(&self) -> bool {
+        self.sh_coeffs.dims()[2] == 1
+    }
+
     pub fn device(&self) -> B::Device {
         self.means.device()
     }
@@ -262,6 +509,7 @@ impl<B: Backend> Splats<B> {
                 raw_opacity_id,
                 Tensor::from_inner(raw_opacity).require_grad(),
             ),
+            opacity_activation: self.opacity_activation,
         }
     }
 }
@@ -275,6 +523,19 @@ impl<B: Backend + SplatForward<B>> Splats<B> {
         camera: &Camera,
         img_size: glam::UVec2,
         float_buffer: bool,
+    ) -> (Tensor<B, 3>, RenderAux<B>) {
+        self.render_with_options(camera, img_size, float_buffer, &RenderOptions::default())
+    }
+
+    /// Render the splats with the given [`RenderOptions`].
+    ///
+    /// NB: This doesn't work on a differentiable backend.
+    pub fn render_with_options(
+        &self,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        float_buffer: bool,
+        options: &RenderOptions,
     ) -> (Tensor<B, 3>, RenderAux<B>) {
         let (img, aux) = B::render_splats(
             camera,
@@ -283,8 +544,12 @@ impl<B: Backend + SplatForward<B>> Splats<B> {
             self.log_scales.val().into_primitive().tensor(),
             self.rotation.val().into_primitive().tensor(),
             self.sh_coeffs.val().into_primitive().tensor(),
-            self.opacities().into_primitive().tensor(),
+            self.raw_opacity.val().into_primitive().tensor(),
             float_buffer,
+            &RenderOptions {
+                opacity_activation: Some(self.opacity_activation.0),
+                ..options.clone()
+            },
         );
         let img = Tensor::from_primitive(TensorPrimitive::Float(img));
         if cfg!(feature = "debug_validation") {
@@ -292,4 +557,109 @@ impl<B: Backend + SplatForward<B>> Splats<B> {
         }
         (img, aux)
     }
+
+    /// Render all directions around `position` as the six faces of a cubemap, eg. to use the scene
+    /// as an environment map for image based lighting.
+    ///
+    /// The faces are stacked into one `[6, face_size, face_size, channels]` tensor, laid out like
+    /// the layers of a cube texture, see [`crate::cubemap`].
+    ///
+    /// NB: This doesn't work on a differentiable backend.
+    pub fn render_cubemap(
+        &self,
+        position: glam::Vec3,
+        face_size: u32,
+        float_buffer: bool,
+        options: &RenderOptions,
+    ) -> (Tensor<B, 4>, Vec<RenderAux<B>>) {
+        let cameras = cubemap_cameras(position);
+        let (faces, auxes) = B::render_splats_batch(
+            &cameras,
+            glam::uvec2(face_size, face_size),
+            self.means.val().into_primitive().tensor(),
+            self.log_scales.val().into_primitive().tensor(),
+            self.rotation.val().into_primitive().tensor(),
+            self.sh_coeffs.val().into_primitive().tensor(),
+            self.raw_opacity.val().into_primitive().tensor(),
+            float_buffer,
+            &RenderOptions {
+                opacity_activation: Some(self.opacity_activation.0),
+                ..options.clone()
+            },
+        );
+        debug_assert_eq!(auxes.len(), CUBEMAP_FACES);
+        (Tensor::from_primitive(TensorPrimitive::Float(faces)), auxes)
+    }
+
+    /// Render the splats like [`Self::render_with_options`], resolving once the render has
+    /// finished on the GPU.
+    ///
+    /// Rendering only queues GPU work, and reading the image would normally wait for it at a
+    /// blocking sync point. Awaiting this instead keeps the thread free, eg. the main thread of a
+    /// browser, or lets other tasks run while the GPU works.
+    pub async fn render_async(
+        &self,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        float_buffer: bool,
+        options: &RenderOptions,
+    ) -> (Tensor<B, 3>, RenderAux<B>) {
+        let (img, aux) = self.render_with_options(camera, img_size, float_buffer, options);
+        // Reading a single pixel waits for the whole render, as it's the last pass.
+        let _ = img
+            .clone()
+            .slice(s![0..1, 0..1, ..])
+            .into_data_async()
+            .await;
+        (img, aux)
+    }
+
+    /// Render the splats and read the image back as 8 bit RGBA, without blocking. See
+    /// [`Self::render_async`].
+    pub async fn render_image_async(
+        &self,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        options: &RenderOptions,
+    ) -> (ImageData<u8>, RenderAux<B>) {
+        let (img, aux) = self.render_with_options(camera, img_size, false, options);
+        (read_image_u8_async(img).await, aux)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        OpacityActivation, inverse_opacity_activation, inverse_scale_activation, scale_activation,
+    };
+
+    #[test]
+    fn opacity_activation_roundtrip() {
+        for activation in [OpacityActivation::Sigmoid, OpacityActivation::ExpDensity] {
+            for opacity in [0.01, 0.1, 0.5, 0.9, 0.99] {
+                let raw = activation.inverse_value(opacity);
+                let roundtrip = activation.activate_value(raw);
+                assert!(
+                    (roundtrip - opacity).abs() < 1e-6,
+                    "{activation:?} maps {opacity} to {roundtrip}"
+                );
+            }
+        }
+        let raw = inverse_opacity_activation(0.3);
+        assert!(
+            (OpacityActivation::Sigmoid.activate_value(raw) - 0.3).abs() < 1e-6,
+            "Default inverse must match the sigmoid activation"
+        );
+    }
+
+    #[test]
+    fn scale_activation_roundtrip() {
+        for scale in [1e-4, 0.01, 1.0, 25.0] {
+            let roundtrip = scale_activation(inverse_scale_activation(scale));
+            assert!(
+                (roundtrip - scale).abs() < 1e-6 * scale,
+                "Scale {scale} maps to {roundtrip}"
+            );
+        }
+    }
 }
@@ -1,10 +1,136 @@
-use super::shaders::{map_gaussian_to_intersects, project_forward, project_visible, rasterize};
+use super::shaders::{
+    cull_frustum, map_gaussian_to_intersects, project_forward, project_visible, rasterize,
+    rasterize_depth, rasterize_ids, tonemap,
+};
 use brush_kernel::kernel_source_gen;
 
-kernel_source_gen!(ProjectSplats {}, project_forward);
-kernel_source_gen!(ProjectVisible {}, project_visible);
+kernel_source_gen!(CullFrustum {}, cull_frustum);
 kernel_source_gen!(
-    MapGaussiansToIntersect { prepass },
+    ProjectSplats {
+        mip_filter,
+        frustum_cull,
+        opacity_sh,
+        clip_planes,
+        sigmoid_opacity,
+        exp_density_opacity
+    },
+    project_forward
+);
+kernel_source_gen!(
+    ProjectVisible {
+        mip_filter,
+        flat_color,
+        opacity_sh,
+        channel_sh,
+        rgb_colors,
+        no_color,
+        depth_grads,
+        sigmoid_opacity,
+        exp_density_opacity
+    },
+    project_visible
+);
+kernel_source_gen!(
+    MapGaussiansToIntersect {
+        prepass,
+        stable_ties,
+        log_depth
+    },
     map_gaussian_to_intersects
 );
-kernel_source_gen!(Rasterize { bwd_info }, rasterize);
+kernel_source_gen!(
+    Rasterize {
+        bwd_info,
+        linear_output,
+        f16_output,
+        depth_output,
+        straight_alpha,
+        background_image,
+        disparity,
+        depth_peel,
+        alpha_gamma,
+        pixel_mask,
+        pixel_resort,
+        bgra_output,
+        gray_output,
+        blend_add,
+        blend_max
+    },
+    rasterize
+);
+
+/// The features [`Rasterize`] is compiled with, see the defines of rasterize.wgsl.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RasterizeFlags {
+    pub bwd_info: bool,
+    pub linear_output: bool,
+    pub f16_output: bool,
+    pub depth_output: bool,
+    pub straight_alpha: bool,
+    pub background_image: bool,
+    pub disparity: bool,
+    pub depth_peel: bool,
+    pub alpha_gamma: bool,
+    pub pixel_mask: bool,
+    pub pixel_resort: bool,
+    pub bgra_output: bool,
+    pub gray_output: bool,
+    pub blend_add: bool,
+    pub blend_max: bool,
+}
+
+impl RasterizeFlags {
+    pub fn task(self) -> Box<Rasterize> {
+        let Self {
+            bwd_info,
+            linear_output,
+            f16_output,
+            depth_output,
+            straight_alpha,
+            background_image,
+            disparity,
+            depth_peel,
+            alpha_gamma,
+            pixel_mask,
+            pixel_resort,
+            bgra_output,
+            gray_output,
+            blend_add,
+            blend_max,
+        } = self;
+        Rasterize::task(
+            bwd_info,
+            linear_output,
+            f16_output,
+            depth_output,
+            straight_alpha,
+            background_image,
+            disparity,
+            depth_peel,
+            alpha_gamma,
+            pixel_mask,
+            pixel_resort,
+            bgra_output,
+            gray_output,
+            blend_add,
+            blend_max,
+        )
+    }
+}
+
+kernel_source_gen!(RasterizeIds {}, rasterize_ids);
+kernel_source_gen!(
+    RasterizeDepth {
+        disparity,
+        alpha_gamma
+    },
+    rasterize_depth
+);
+kernel_source_gen!(
+    TonemapImage {
+        aces,
+        srgb_output,
+        premultiplied
+    },
+    tonemap
+);
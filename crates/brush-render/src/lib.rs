@@ -1,6 +1,7 @@
 #![recursion_limit = "256"]
 
 use burn::prelude::Backend;
+use burn::tensor::TensorMetadata;
 use burn::tensor::ops::FloatTensor;
 use burn_cubecl::CubeBackend;
 use burn_fusion::Fusion;
@@ -8,12 +9,15 @@ use burn_wgpu::graphics::{AutoGraphicsApi, GraphicsApi};
 use burn_wgpu::{RuntimeOptions, WgpuDevice, WgpuRuntime};
 use camera::Camera;
 use render_aux::RenderAux;
+use render_options::RenderOptions;
+use std::time::Duration;
 use wgpu::{Adapter, Device, Queue};
 
 mod burn_glue;
 mod dim_check;
 mod kernels;
 pub mod render_aux;
+pub mod render_options;
 pub mod shaders;
 
 pub mod sh;
@@ -23,19 +27,49 @@ mod tests;
 
 pub mod bounding_box;
 pub mod camera;
+pub mod capabilities;
+#[cfg(any(test, feature = "cpu_reference"))]
+pub mod cpu_reference;
+pub mod cubemap;
 pub mod gaussian_splats;
+pub mod lod;
+pub mod mask;
+pub mod read_image;
 pub mod render;
+pub mod tonemap;
+pub mod tuning;
 
 pub type MainBackendBase = CubeBackend<WgpuRuntime, f32, i32, u32>;
 pub type MainBackend = Fusion<MainBackendBase>;
 
-#[derive(Debug, Clone)]
+/// Summary of a single render, for profiling.
+///
+/// See [`render::RenderContext::set_collect_stats`] to collect these.
+#[derive(Debug, Clone, Default)]
 pub struct RenderStats {
     pub num_visible: u32,
     pub num_intersections: u32,
+    /// Number of tiles the image is divided into.
+    pub num_tiles: u32,
+    /// Largest number of intersections in a single tile.
+    pub max_tile_intersections: u32,
+    /// Number of splats culled for NaN or infinite parameters, see
+    /// [`RenderAux::num_non_finite`].
+    pub num_non_finite: u32,
+    /// Time spent in each stage of the render, in order.
+    ///
+    /// Stages are named like their tracing spans. Each stage waits for the GPU to finish, so
+    /// these include some sync overhead, and the render is slower when collecting them.
+    pub kernel_timings: Vec<(&'static str, Duration)>,
+    /// Whether [`Self::kernel_timings`] were measured with GPU timestamp queries, which only
+    /// include the time the GPU spends on each stage. This needs the `gpu_timestamps` feature
+    /// and a device that supports timestamp queries, otherwise stages are timed on the CPU.
+    pub gpu_timings: bool,
 }
 
-const INTERSECTS_UPPER_BOUND: u32 = 512 * 65535;
+/// Most intersections buffers are allocated for, unless overridden with
+/// [`render_options::RenderOptions::intersects_upper_bound`].
+pub const INTERSECTS_UPPER_BOUND: u32 = 512 * 65535;
 const GAUSSIANS_UPPER_BOUND: u32 = 256 * 65535;
 
 pub trait SplatForward<B: Backend> {
@@ -47,6 +81,9 @@ pub trait SplatForward<B: Backend> {
     /// The [`xy_grad_dummy`] variable is only used to carry screenspace xy gradients.
     /// This function can optionally render a "u32" buffer, which is a packed RGBA (8 bits per channel)
     /// buffer. This is useful when the results need to be displayed immediately.
+    /// See [`RenderOptions`] for additional settings that change the rendered output.
+    /// With the `opacity_sh` feature, opacities can also be `[N, coeffs]` sh coefficients, for an
+    /// opacity that depends on the view direction.
     fn render_splats(
         camera: &Camera,
         img_size: glam::UVec2,
@@ -56,7 +93,49 @@ pub trait SplatForward<B: Backend> {
         sh_coeffs: FloatTensor<B>,
         raw_opacities: FloatTensor<B>,
         bwd_info: bool,
+        options: &RenderOptions,
     ) -> (FloatTensor<B>, RenderAux<B>);
+
+    /// Render the same splats from multiple cameras.
+    ///
+    /// The images are stacked into one `[views, height, width, channels]` tensor, with one
+    /// [`RenderAux`] per view. The result matches calling [`SplatForward::render_splats`] for each
+    /// camera, but backends can share work between the views.
+    fn render_splats_batch(
+        cameras: &[Camera],
+        img_size: glam::UVec2,
+        means: FloatTensor<B>,
+        log_scales: FloatTensor<B>,
+        quats: FloatTensor<B>,
+        sh_coeffs: FloatTensor<B>,
+        raw_opacities: FloatTensor<B>,
+        bwd_info: bool,
+        options: &RenderOptions,
+    ) -> (FloatTensor<B>, Vec<RenderAux<B>>) {
+        assert!(!cameras.is_empty(), "Need at least one camera to render.");
+
+        let (imgs, auxes): (Vec<_>, Vec<_>) = cameras
+            .iter()
+            .map(|camera| {
+                let (img, aux) = Self::render_splats(
+                    camera,
+                    img_size,
+                    means.clone(),
+                    log_scales.clone(),
+                    quats.clone(),
+                    sh_coeffs.clone(),
+                    raw_opacities.clone(),
+                    bwd_info,
+                    options,
+                );
+                let mut shape = img.shape().dims;
+                shape.insert(0, 1);
+                (B::float_reshape(img, shape.into()), aux)
+            })
+            .unzip();
+
+        (B::float_cat(imgs, 0), auxes)
+    }
 }
 
 fn burn_options() -> RuntimeOptions {
@@ -66,6 +145,8 @@ fn burn_options() -> RuntimeOptions {
     }
 }
 
+/// Create a burn device for an existing wgpu device, with the kernel launch parameters tuned
+/// for it.
 pub fn burn_init_device(adapter: Adapter, device: Device, queue: Queue) -> WgpuDevice {
     let setup = burn_wgpu::WgpuSetup {
         instance: wgpu::Instance::new(&wgpu::InstanceDescriptor::default()), // unused... need to fix this in Burn.
@@ -74,11 +155,17 @@ pub fn burn_init_device(adapter: Adapter, device: Device, queue: Queue) -> WgpuD
         queue,
         backend: AutoGraphicsApi::backend(),
     };
-    burn_wgpu::init_device(setup, burn_options())
+    let device = burn_wgpu::init_device(setup, burn_options());
+    #[cfg(not(target_family = "wasm"))]
+    tuning::tune_splat_workgroup_size(&device);
+    device
 }
 
+/// Set up the default burn device, with the kernel launch parameters tuned for it.
 pub async fn burn_init_setup() -> WgpuDevice {
     burn_wgpu::init_setup_async::<AutoGraphicsApi>(&WgpuDevice::DefaultDevice, burn_options())
         .await;
+    #[cfg(not(target_family = "wasm"))]
+    tuning::tune_splat_workgroup_size(&WgpuDevice::DefaultDevice);
     WgpuDevice::DefaultDevice
 }
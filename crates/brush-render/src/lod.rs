@@ -0,0 +1,328 @@
+//! Levels of detail, for navigating huge scenes.
+//!
+//! From far away, many splats project smaller than a pixel, and rendering them all is wasted
+//! work. [`SplatLod::build`] merges nearby splats into coarser levels, by clustering them in the
+//! cells of an octree, and [`SplatLod::select`] picks the coarsest level whose cells still project
+//! small enough for the camera.
+
+use std::collections::HashMap;
+
+use burn::prelude::Backend;
+use glam::{Mat3, Quat, Vec3};
+
+use crate::{
+    bounding_box::BoundingBox,
+    camera::Camera,
+    gaussian_splats::{Splats, inverse_opacity_activation},
+};
+
+/// One level of detail.
+pub struct LodLevel<B: Backend> {
+    pub splats: Splats<B>,
+    /// Size of the octree cells that were merged into one splat, or zero for the original splats.
+    pub cell_size: f32,
+}
+
+/// A set of splats, with coarser versions of it, from fine to coarse.
+pub struct SplatLod<B: Backend> {
+    /// The levels, the first of which are the original splats.
+    pub levels: Vec<LodLevel<B>>,
+    /// Bounds of the means of the splats.
+    pub bounds: BoundingBox,
+}
+
+/// Splats read back to the CPU.
+struct SplatData {
+    means: Vec<Vec3>,
+    covariances: Vec<Mat3>,
+    scales: Vec<Vec3>,
+    rotations: Vec<Quat>,
+    opacities: Vec<f32>,
+    sh_coeffs: Vec<f32>,
+    coeffs_per_splat: usize,
+}
+
+/// Eigenvalues and eigenvectors (as columns) of a symmetric matrix, with the Jacobi method.
+fn symmetric_eigen(m: Mat3) -> (Vec3, Mat3) {
+    let mut a = m.to_cols_array_2d();
+    let mut v = Mat3::IDENTITY.to_cols_array_2d();
+
+    for _ in 0..16 {
+        let off = a[0][1].abs() + a[0][2].abs() + a[1][2].abs();
+        if off < 1e-20 {
+            break;
+        }
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q].abs() < 1e-30 {
+                continue;
+            }
+            // Rotate rows and columns p, q to zero out a[p][q].
+            let theta = 0.5 * (a[q][q] - a[p][p]) / a[p][q];
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+            for k in 0..3 {
+                let (akp, akq) = (a[k][p], a[k][q]);
+                a[k][p] = c * akp - s * akq;
+                a[k][q] = s * akp + c * akq;
+            }
+            for k in 0..3 {
+                let (apk, aqk) = (a[p][k], a[q][k]);
+                a[p][k] = c * apk - s * aqk;
+                a[q][k] = s * apk + c * aqk;
+            }
+            for col in &mut v {
+                let (vp, vq) = (col[p], col[q]);
+                col[p] = c * vp - s * vq;
+                col[q] = s * vp + c * vq;
+            }
+        }
+    }
+
+    // The columns of v are the coordinates of the eigenvectors, transposed by the rotations
+    // above.
+    let vectors = Mat3::from_cols_array_2d(&v).transpose();
+    (Vec3::new(a[0][0], a[1][1], a[2][2]), vectors)
+}
+
+/// The scale and rotation of a gaussian with covariance `cov`.
+fn scale_rotation(cov: Mat3) -> (Vec3, Quat) {
+    let (values, mut vectors) = symmetric_eigen(cov);
+    // Make sure the eigenvectors form a rotation, not a reflection.
+    if vectors.determinant() < 0.0 {
+        vectors.z_axis = -vectors.z_axis;
+    }
+    let scale = values.max(Vec3::splat(1e-20)).to_array().map(f32::sqrt);
+    (Vec3::from(scale), Quat::from_mat3(&vectors).normalize())
+}
+
+/// Area of the largest cross section of a splat, up to a constant factor.
+fn splat_area(scale: Vec3) -> f32 {
+    (scale.x * scale.y)
+        .max(scale.y * scale.z)
+        .max(scale.x * scale.z)
+}
+
+async fn read_splats<B: Backend>(splats: &Splats<B>) -> SplatData {
+    let read = |data: burn::tensor::TensorData| data.to_vec::<f32>().expect("Wrong tensor type");
+    let means = read(splats.means.val().into_data_async().await);
+    let scales = read(splats.scales().into_data_async().await);
+    let rotations = read(splats.rotations_normed().into_data_async().await);
+    let opacities = read(splats.opacities().into_data_async().await);
+    let sh_coeffs = read(splats.sh_coeffs.val().into_data_async().await);
+
+    let scales: Vec<_> = scales.chunks_exact(3).map(Vec3::from_slice).collect();
+    // Rotations are stored in scalar first form.
+    let rotations: Vec<_> = rotations
+        .chunks_exact(4)
+        .map(|r| Quat::from_xyzw(r[1], r[2], r[3], r[0]).normalize())
+        .collect();
+    let covariances = scales
+        .iter()
+        .zip(&rotations)
+        .map(|(&scale, &rotation)| {
+            let m = Mat3::from_quat(rotation) * Mat3::from_diagonal(scale);
+            m * m.transpose()
+        })
+        .collect();
+
+    SplatData {
+        means: means.chunks_exact(3).map(Vec3::from_slice).collect(),
+        covariances,
+        scales,
+        rotations,
+        coeffs_per_splat: splats.sh_coeffs.dims()[1] * 3,
+        opacities,
+        sh_coeffs,
+    }
+}
+
+/// Merge the splats in each cell of a grid of `cell_size` into one.
+///
+/// Splats are weighted by their opacity times their area. The merged splat matches the mean and
+/// covariance of the weighted mixture, and has the weighted mean color. Its opacity keeps the
+/// total opacity times area of the cell, so a cell packed with overlapping splats stays opaque,
+/// while a few sparse splats fade out.
+fn merge_cells(data: &SplatData, min: Vec3, cell_size: f32) -> SplatData {
+    let mut cells: HashMap<[i32; 3], Vec<usize>> = HashMap::new();
+    for (i, mean) in data.means.iter().enumerate() {
+        let cell = ((*mean - min) / cell_size).floor().as_ivec3().to_array();
+        cells.entry(cell).or_default().push(i);
+    }
+    // Keep the order stable, so building is deterministic.
+    let mut cells: Vec<_> = cells.into_iter().collect();
+    cells.sort_unstable_by_key(|(cell, _)| *cell);
+
+    let coeffs = data.coeffs_per_splat;
+    let mut merged = SplatData {
+        means: Vec::with_capacity(cells.len()),
+        covariances: Vec::with_capacity(cells.len()),
+        scales: Vec::with_capacity(cells.len()),
+        rotations: Vec::with_capacity(cells.len()),
+        opacities: Vec::with_capacity(cells.len()),
+        sh_coeffs: Vec::with_capacity(cells.len() * coeffs),
+        coeffs_per_splat: coeffs,
+    };
+
+    for (_, members) in cells {
+        let weights: Vec<f32> = members
+            .iter()
+            .map(|&i| data.opacities[i] * splat_area(data.scales[i]) + 1e-12)
+            .collect();
+        let weight_sum: f32 = weights.iter().sum();
+
+        let mean = members
+            .iter()
+            .zip(&weights)
+            .map(|(&i, w)| data.means[i] * *w)
+            .sum::<Vec3>()
+            / weight_sum;
+        let covariance = members
+            .iter()
+            .zip(&weights)
+            .map(|(&i, w)| {
+                let d = data.means[i] - mean;
+                (data.covariances[i] + Mat3::from_cols(d * d.x, d * d.y, d * d.z)) * *w
+            })
+            .fold(Mat3::ZERO, |acc, m| acc + m)
+            * (1.0 / weight_sum);
+        let (scale, rotation) = scale_rotation(covariance);
+        let opacity = weight_sum / splat_area(scale).max(1e-12);
+
+        let mut sh = vec![0.0; coeffs];
+        for (&i, w) in members.iter().zip(&weights) {
+            let splat_sh = &data.sh_coeffs[i * coeffs..(i + 1) * coeffs];
+            for (acc, c) in sh.iter_mut().zip(splat_sh) {
+                *acc += c * w / weight_sum;
+            }
+        }
+
+        merged.means.push(mean);
+        merged.covariances.push(covariance);
+        merged.scales.push(scale);
+        merged.rotations.push(rotation);
+        merged.opacities.push(opacity.clamp(0.0, 1.0));
+        merged.sh_coeffs.extend(sh);
+    }
+
+    merged
+}
+
+impl<B: Backend> SplatLod<B> {
+    /// Build `num_levels` levels of detail from `splats`, including the splats themselves.
+    ///
+    /// The first coarse level merges the splats in octree cells sized to hold about 8 splats each
+    /// if they were spread evenly through their bounds. Each level above merges cells twice as
+    /// large, like the parents in an octree. This reads back the splats, and clusters them on the
+    /// CPU, so takes a moment for large scenes.
+    pub async fn build(splats: Splats<B>, num_levels: u32) -> Self {
+        assert!(num_levels > 0, "Need at least one level of detail");
+
+        let device = splats.device();
+        let activation = splats.opacity_activation.0;
+        let data = read_splats(&splats).await;
+
+        let min = data.means.iter().copied().fold(Vec3::INFINITY, Vec3::min);
+        let max = data
+            .means
+            .iter()
+            .copied()
+            .fold(Vec3::NEG_INFINITY, Vec3::max);
+        let bounds = if data.means.is_empty() {
+            BoundingBox::from_min_max(Vec3::ZERO, Vec3::ZERO)
+        } else {
+            BoundingBox::from_min_max(min, max)
+        };
+
+        // Octree depth at which cells hold about 8 splats on average.
+        let leaf_depth = ((data.means.len().max(1) as f32).log2() / 3.0 - 1.0)
+            .ceil()
+            .max(0.0) as i32;
+        let root_size = (bounds.extent.max_element() * 2.0).max(1e-6);
+
+        let mut levels = vec![LodLevel {
+            splats,
+            cell_size: 0.0,
+        }];
+        let mut level_data = data;
+        for level in 1..num_levels as i32 {
+            let cell_size = root_size / 2.0f32.powi(leaf_depth - level + 1);
+            level_data = merge_cells(&level_data, bounds.min(), cell_size);
+
+            let log_scales: Vec<Vec3> = level_data
+                .scales
+                .iter()
+                .map(|scale| scale.to_array().map(f32::ln).into())
+                .collect();
+            let raw_opacities: Vec<f32> = level_data
+                .opacities
+                .iter()
+                .map(|&opacity| inverse_opacity_activation(opacity.clamp(1e-6, 1.0 - 1e-6)))
+                .collect();
+
+            let splats = Splats::from_raw(
+                &level_data.means,
+                Some(&level_data.rotations),
+                Some(&log_scales),
+                Some(&level_data.sh_coeffs),
+                Some(&raw_opacities),
+                &device,
+            )
+            .with_opacity_activation(activation);
+
+            levels.push(LodLevel { splats, cell_size });
+        }
+
+        Self { levels, bounds }
+    }
+
+    /// The coarsest level whose cells project to at most `max_pixels` pixels, for the part of the
+    /// scene closest to the camera.
+    pub fn level_for(&self, camera: &Camera, img_size: glam::UVec2, max_pixels: f32) -> usize {
+        // Distance to the nearest point of the bounds, zero when inside of them.
+        let offset = (camera.position - self.bounds.center).abs() - self.bounds.extent;
+        let distance = offset.max(Vec3::ZERO).length().max(1e-6);
+        let focal = camera.focal(img_size).max_element();
+
+        self.levels
+            .iter()
+            .rposition(|level| focal * level.cell_size / distance <= max_pixels)
+            .unwrap_or(0)
+    }
+
+    /// The splats of [`Self::level_for`].
+    pub fn select(&self, camera: &Camera, img_size: glam::UVec2, max_pixels: f32) -> &Splats<B> {
+        &self.levels[self.level_for(camera, img_size, max_pixels)].splats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{scale_rotation, symmetric_eigen};
+    use glam::{Mat3, Quat, Vec3};
+
+    #[test]
+    fn eigen_decomposition_recovers_gaussian() {
+        let rotation = Quat::from_euler(glam::EulerRot::XYZ, 0.3, -0.7, 1.1);
+        let scale = Vec3::new(0.5, 2.0, 0.1);
+        let m = Mat3::from_quat(rotation) * Mat3::from_diagonal(scale);
+        let cov = m * m.transpose();
+
+        let (values, vectors) = symmetric_eigen(cov);
+        for i in 0..3 {
+            let residual = cov * vectors.col(i) - vectors.col(i) * values[i];
+            assert!(residual.length() < 1e-5, "Not an eigenvector: {residual}");
+        }
+
+        let (found_scale, found_rotation) = scale_rotation(cov);
+        let m = Mat3::from_quat(found_rotation) * Mat3::from_diagonal(found_scale);
+        let found_cov = m * m.transpose();
+        assert!(
+            (found_cov - cov)
+                .to_cols_array()
+                .iter()
+                .all(|d| d.abs() < 1e-5),
+            "Covariance {found_cov} differs from {cov}"
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! Masks restricting a render to part of the image, see
+//! [`crate::render::RenderInput::pixel_mask`].
+
+/// A pixel mask of an `img_size` image, nonzero for the pixels whose centers lie inside the
+/// convex `polygon`.
+///
+/// The vertices are in pixels, with the origin at the top left corner of the image, and may wind
+/// either way. Points on an edge are inside. The mask is row major, so upload it as a
+/// `[height, width]` int tensor to render with it. Polygons with fewer than three vertices mask
+/// out everything.
+pub fn polygon_mask(img_size: glam::UVec2, polygon: &[glam::Vec2]) -> Vec<i32> {
+    let inside = |point: glam::Vec2| {
+        if polygon.len() < 3 {
+            return false;
+        }
+        // Inside a convex polygon, the point is on the same side of every edge.
+        let (mut left, mut right) = (false, false);
+        for (i, &start) in polygon.iter().enumerate() {
+            let end = polygon[(i + 1) % polygon.len()];
+            let side = (end - start).perp_dot(point - start);
+            left |= side > 0.0;
+            right |= side < 0.0;
+        }
+        !(left && right)
+    };
+
+    (0..img_size.y)
+        .flat_map(|y| (0..img_size.x).map(move |x| glam::vec2(x as f32, y as f32) + 0.5))
+        .map(|center| i32::from(inside(center)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::polygon_mask;
+
+    #[test]
+    fn polygon_mask_covers_inside() {
+        let img_size = glam::uvec2(8, 6);
+        let square = [
+            glam::vec2(2.0, 1.0),
+            glam::vec2(6.0, 1.0),
+            glam::vec2(6.0, 5.0),
+            glam::vec2(2.0, 5.0),
+        ];
+        let mask = polygon_mask(img_size, &square);
+        assert_eq!(mask.len(), 48);
+        for y in 0..6 {
+            for x in 0..8 {
+                let expected = (2..6).contains(&x) && (1..5).contains(&y);
+                assert_eq!(mask[y * 8 + x] != 0, expected, "Pixel ({x}, {y}) is wrong");
+            }
+        }
+
+        let reversed: Vec<_> = square.iter().rev().copied().collect();
+        assert_eq!(
+            polygon_mask(img_size, &reversed),
+            mask,
+            "Winding must not matter"
+        );
+
+        let full = [
+            glam::vec2(0.0, 0.0),
+            glam::vec2(8.0, 0.0),
+            glam::vec2(8.0, 6.0),
+            glam::vec2(0.0, 6.0),
+        ];
+        assert!(
+            polygon_mask(img_size, &full).iter().all(|&m| m != 0),
+            "Polygon covering the image must keep all pixels"
+        );
+        assert!(
+            polygon_mask(img_size, &square[..2]).iter().all(|&m| m == 0),
+            "Degenerate polygon must mask out everything"
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use burn::{
+    prelude::Backend,
+    tensor::{DType, Tensor, TensorData},
+};
+
+use crate::render_options::ChannelOrder;
+
+/// A rendered image, read back from the GPU.
+///
+/// Pixels are stored row by row, starting at the top left, with interleaved RGBA channels.
+/// Colors are premultiplied by alpha, as splats are rendered over a transparent black background.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageData<T> {
+    pub width: u32,
+    pub height: u32,
+    /// Number of channels per pixel. Rendered images always have 4 (RGBA) channels.
+    pub channels: u32,
+    pub data: Vec<T>,
+}
+
+const CHANNELS: u32 = 4;
+
+/// Pack an 8 bit RGBA color into a u32, the same way the rasterizer packs colors.
+///
+/// Red is stored in the lowest byte and alpha in the highest, so in memory (which is little
+/// endian on the GPU) the bytes of a packed image are in RGBA order.
+pub fn pack_rgba(rgba: [u8; 4]) -> u32 {
+    u32::from_le_bytes(rgba)
+}
+
+/// Unpack a color packed by the rasterizer into 8 bit RGBA. The inverse of [`pack_rgba`].
+pub fn unpack_rgba(packed: u32) -> [u8; 4] {
+    packed.to_le_bytes()
+}
+
+/// Pack an 8 bit RGBA color into a u32 with its channels in `order`, the same way the rasterizer
+/// packs colors. The bytes of the packed color are in `order` in memory.
+pub fn pack_color(rgba: [u8; 4], order: ChannelOrder) -> u32 {
+    let [r, g, b, a] = rgba;
+    match order {
+        ChannelOrder::Rgba => pack_rgba(rgba),
+        ChannelOrder::Bgra => pack_rgba([b, g, r, a]),
+    }
+}
+
+/// Unpack a color packed by the rasterizer with its channels in `order` into 8 bit RGBA. The
+/// inverse of [`pack_color`].
+pub fn unpack_color(packed: u32, order: ChannelOrder) -> [u8; 4] {
+    let bytes = unpack_rgba(packed);
+    match order {
+        ChannelOrder::Rgba => bytes,
+        ChannelOrder::Bgra => [bytes[2], bytes[1], bytes[0], bytes[3]],
+    }
+}
+
+/// Unpack the pixels of an image rendered as packed u32's into interleaved 8 bit RGBA.
+pub fn unpack_rgba_image(packed: &[u32]) -> Vec<u8> {
+    packed.iter().flat_map(|&p| unpack_rgba(p)).collect()
+}
+
+/// Packed pixels of a rendered image, read as u32's regardless of the integer type of the tensor.
+fn packed_pixels(data: &TensorData) -> Vec<u32> {
+    data.as_bytes()
+        .chunks_exact(4)
+        .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+impl ImageData<u8> {
+    /// Convert the output of a render to 8 bit RGBA.
+    ///
+    /// This unpacks images rendered as packed u32's, and quantizes float images.
+    pub fn from_render_data(data: TensorData) -> Self {
+        let [height, width, channels] = image_dims(&data);
+
+        let data = if channels == 1 {
+            unpack_rgba_image(&packed_pixels(&data))
+        } else {
+            render_data_to_f32(data)
+                .into_iter()
+                .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+                .collect()
+        };
+
+        Self {
+            width,
+            height,
+            channels: CHANNELS,
+            data,
+        }
+    }
+}
+
+impl ImageData<f32> {
+    /// Convert the output of a render to float RGBA, with values in [0, 1].
+    ///
+    /// This unpacks images rendered as packed u32's, and converts half float images.
+    pub fn from_render_data(data: TensorData) -> Self {
+        let [height, width, channels] = image_dims(&data);
+
+        let data = if channels == 1 {
+            unpack_rgba_image(&packed_pixels(&data))
+                .into_iter()
+                .map(|c| c as f32 / 255.0)
+                .collect()
+        } else {
+            render_data_to_f32(data)
+        };
+
+        Self {
+            width,
+            height,
+            channels: CHANNELS,
+            data,
+        }
+    }
+}
+
+fn image_dims(data: &TensorData) -> [u32; 3] {
+    let [height, width, channels] = data.shape[..] else {
+        panic!(
+            "Rendered images must have 3 dimensions, got {:?}",
+            data.shape
+        );
+    };
+    assert!(
+        channels == 1 || channels == CHANNELS as usize,
+        "Rendered images must either be packed, or have 4 channels"
+    );
+    [height as u32, width as u32, channels as u32]
+}
+
+fn render_data_to_f32(data: TensorData) -> Vec<f32> {
+    let data = if data.dtype == DType::F32 {
+        data
+    } else {
+        data.convert::<f32>()
+    };
+    data.into_vec::<f32>()
+        .expect("Failed to read rendered image")
+}
+
+/// Read a rendered image as 8 bit RGBA. See [`ImageData`] for the layout.
+///
+/// This blocks until the image is available, which isn't possible on wasm. Use
+/// [`read_image_u8_async`] there instead.
+#[cfg(not(target_family = "wasm"))]
+pub fn read_image_u8<B: Backend>(img: Tensor<B, 3>) -> ImageData<u8> {
+    ImageData::<u8>::from_render_data(img.into_data())
+}
+
+/// Read a rendered image as float RGBA. See [`ImageData`] for the layout.
+///
+/// This blocks until the image is available, which isn't possible on wasm. Use
+/// [`read_image_f32_async`] there instead.
+#[cfg(not(target_family = "wasm"))]
+pub fn read_image_f32<B: Backend>(img: Tensor<B, 3>) -> ImageData<f32> {
+    ImageData::<f32>::from_render_data(img.into_data())
+}
+
+/// Read a rendered image as 8 bit RGBA, without blocking. See [`ImageData`] for the layout.
+pub async fn read_image_u8_async<B: Backend>(img: Tensor<B, 3>) -> ImageData<u8> {
+    ImageData::<u8>::from_render_data(img.into_data_async().await)
+}
+
+/// Read a rendered image as float RGBA, without blocking. See [`ImageData`] for the layout.
+pub async fn read_image_f32_async<B: Backend>(img: Tensor<B, 3>) -> ImageData<f32> {
+    ImageData::<f32>::from_render_data(img.into_data_async().await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImageData, pack_color, pack_rgba, unpack_color, unpack_rgba, unpack_rgba_image};
+    use crate::render_options::ChannelOrder;
+    use burn::tensor::TensorData;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn pack_round_trips() {
+        // Matches the packing in the rasterizer: r | g << 8 | b << 16 | a << 24.
+        assert_eq!(pack_rgba([0x10, 0x20, 0x30, 0x40]), 0x4030_2010);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let colors: Vec<[u8; 4]> = (0..1024).map(|_| rng.random()).collect();
+        for &color in &colors {
+            assert_eq!(unpack_rgba(pack_rgba(color)), color);
+        }
+
+        let packed: Vec<u32> = colors.iter().map(|&c| pack_rgba(c)).collect();
+        assert_eq!(unpack_rgba_image(&packed), colors.concat());
+    }
+
+    #[test]
+    fn packs_in_channel_order() {
+        let rgba = [0x10, 0x20, 0x30, 0x40];
+        let bgra = pack_color(rgba, ChannelOrder::Bgra);
+        assert_eq!(bgra.to_le_bytes(), [0x30, 0x20, 0x10, 0x40]);
+        assert_eq!(pack_color(rgba, ChannelOrder::Rgba), pack_rgba(rgba));
+        for order in [ChannelOrder::Rgba, ChannelOrder::Bgra] {
+            assert_eq!(unpack_color(pack_color(rgba, order), order), rgba);
+        }
+    }
+
+    #[test]
+    fn unpacks_u32_images() {
+        let packed = [0x4030_2010u32, 0xff00_80ff];
+        let data = TensorData::new(packed.to_vec(), [1, 2, 1]);
+
+        let img = ImageData::<u8>::from_render_data(data.clone());
+        assert_eq!((img.width, img.height, img.channels), (2, 1, 4));
+        assert_eq!(img.data, [0x10, 0x20, 0x30, 0x40, 0xff, 0x80, 0x00, 0xff]);
+
+        let img = ImageData::<f32>::from_render_data(data);
+        assert_eq!(img.data[0], 16.0 / 255.0);
+        assert_eq!(img.data[7], 1.0);
+    }
+
+    #[test]
+    fn quantizes_float_images() {
+        let floats = [0.0f32, 0.5, 1.5, -1.0, 1.0, 0.25, 0.75, 1.0];
+        let data = TensorData::new(floats.to_vec(), [2, 1, 4]);
+
+        let img = ImageData::<u8>::from_render_data(data.clone());
+        assert_eq!((img.width, img.height, img.channels), (1, 2, 4));
+        assert_eq!(img.data, [0, 128, 255, 0, 255, 64, 191, 255]);
+
+        let img = ImageData::<f32>::from_render_data(data);
+        assert_eq!(img.data, floats);
+    }
+}
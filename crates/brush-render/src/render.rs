@@ -1,31 +1,105 @@
 use crate::{
-    INTERSECTS_UPPER_BOUND, MainBackendBase,
-    camera::Camera,
-    dim_check::DimCheck,
-    kernels::{MapGaussiansToIntersect, ProjectSplats, ProjectVisible, Rasterize},
+    MainBackendBase, RenderStats,
+    camera::{Camera, ImageOrigin},
+    dim_check::{DimBound, DimCheck},
+    gaussian_splats::OpacityActivation,
+    kernels::{
+        CullFrustum, MapGaussiansToIntersect, ProjectSplats, ProjectVisible, Rasterize,
+        RasterizeDepth, RasterizeFlags, RasterizeIds,
+    },
     render_aux::RenderAux,
-    sh::sh_degree_from_coeffs,
+    render_options::{
+        AlphaGamma, AlphaMode, BlendMode, ChannelOrder, ClampPolicy, ColorSpace, DepthKey,
+        OutputDType, RenderOptions, ResortWindow,
+    },
+    sh::{planar_coeffs_for_degrees, sh_degree_from_coeffs},
+    tonemap::tonemap_image,
+    tuning::{DEFAULT_SPLAT_WORKGROUP_SIZE, splat_workgroup_size},
 };
 
 use super::shaders;
 
-use brush_kernel::create_dispatch_buffer;
 use brush_kernel::create_tensor;
 use brush_kernel::create_uniform_buffer;
+use brush_kernel::write_dispatch_buffer;
 use brush_kernel::{CubeCount, calc_cube_count};
-use brush_prefix_sum::prefix_sum;
-use brush_sort::radix_argsort;
-use burn::tensor::{DType, Int, s};
+use brush_prefix_sum::{ExclusiveScanBuffer, prefix_sum};
+use burn::prelude::Backend;
+use burn::tensor::{DType, Int, TensorData, s};
 use burn::tensor::{
-    Tensor,
+    Tensor, TensorPrimitive,
     ops::{FloatTensorOps, IntTensorOps},
 };
 
 use burn_cubecl::cubecl::server::Bindings;
+#[cfg(feature = "gpu_timestamps")]
+use burn_cubecl::cubecl::{
+    Runtime, future,
+    profile::{ProfileDuration, TimingMethod},
+};
 use burn_wgpu::CubeTensor;
 use burn_wgpu::WgpuRuntime;
 use glam::uvec2;
 use std::mem::{offset_of, size_of};
+use std::num::NonZeroU32;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// Compact the splats that might be visible from the camera, based on their distance to the
+/// frustum planes.
+///
+/// Returns the global ids of the remaining splats, and a single element tensor with their count.
+fn cull_frustum(
+    camera: &Camera,
+    world_to_local: glam::Affine3A,
+    img_size: glam::UVec2,
+    means: &CubeTensor<WgpuRuntime>,
+    log_scales: &CubeTensor<WgpuRuntime>,
+) -> (CubeTensor<WgpuRuntime>, CubeTensor<WgpuRuntime>) {
+    let (device, client) = (&means.device, &means.client);
+    let total_splats = means.shape.dims[0];
+
+    // Widen the frustum by a few pixels, to account for the blur added to projected splats. The
+    // near plane is slightly closer than where ProjectSplats culls, so culling stays conservative.
+    let [left, right, top, bottom, near] = camera.frustum_planes_from(
+        world_to_local,
+        img_size,
+        4.0,
+        shaders::helpers::NEAR_PLANE * 0.5,
+    );
+    let uniforms = shaders::cull_frustum::Uniforms {
+        plane_left: left.into(),
+        plane_right: right.into(),
+        plane_top: top.into(),
+        plane_bottom: bottom.into(),
+        plane_near: near.into(),
+        total_splats: total_splats as u32,
+        pad_a: 0,
+        pad_b: 0,
+        pad_c: 0,
+    };
+    let uniforms_buffer = create_uniform_buffer(uniforms, device, client);
+
+    let global_from_culled_gid = create_tensor([total_splats], device, client, DType::I32);
+    let num_culled = MainBackendBase::int_zeros([1].into(), device);
+
+    // SAFETY: Kernel checked to have no OOB, bounded loops.
+    unsafe {
+        client.execute_unchecked(
+            CullFrustum::task(),
+            calc_cube_count([total_splats as u32], CullFrustum::WORKGROUP_SIZE),
+            Bindings::new().with_buffers(vec![
+                uniforms_buffer.handle.binding(),
+                means.handle.clone().binding(),
+                log_scales.handle.clone().binding(),
+                global_from_culled_gid.handle.clone().binding(),
+                num_culled.handle.clone().binding(),
+            ]),
+        );
+    }
+
+    (global_from_culled_gid, num_culled)
+}
 
 pub(crate) fn calc_tile_bounds(img_size: glam::UVec2) -> glam::UVec2 {
     uvec2(
@@ -34,13 +108,30 @@ pub(crate) fn calc_tile_bounds(img_size: glam::UVec2) -> glam::UVec2 {
     )
 }
 
+/// Whether the projection kernels apply the sigmoid or exp density activation to opacities, as
+/// their `sigmoid_opacity` and `exp_density_opacity` flags.
+pub fn opacity_activation_flags(activation: Option<OpacityActivation>) -> (bool, bool) {
+    (
+        activation == Some(OpacityActivation::Sigmoid),
+        activation == Some(OpacityActivation::ExpDensity),
+    )
+}
+
+/// Number of bits of the tile ids the intersections are sorted on, enough for the largest id.
+///
+/// Renders of a single tile, like small thumbnails, don't sort on tile ids at all, which leaves
+/// only the depth sort. Each 4 bits less saves a pass of the radix sort.
+pub(crate) fn tile_sort_bits(num_tiles: u32) -> u32 {
+    u32::BITS - num_tiles.saturating_sub(1).leading_zeros()
+}
+
 // On wasm, we cannot do a sync readback at all.
 // Instead, can just estimate a max number of intersects. All the kernels only handle the actual
 // number of intersects, and spin up empty threads for the rest atm. In the future, could use indirect
 // dispatch to avoid this.
 // Estimating the max number of intersects can be a bad hack though... The worst case sceneario is so massive
 // that it's easy to run out of memory... How do we actually properly deal with this :/
-pub(crate) fn max_intersections(img_size: glam::UVec2, num_splats: u32) -> u32 {
+pub(crate) fn max_intersections(img_size: glam::UVec2, num_splats: u32, upper_bound: u32) -> u32 {
     // Divide screen into tiles.
     let tile_bounds = calc_tile_bounds(img_size);
     // Assume on average each splat is maximally covering half x half the screen,
@@ -50,43 +141,1515 @@ pub(crate) fn max_intersections(img_size: glam::UVec2, num_splats: u32) -> u32 {
     let expected_intersections = (num_tiles / 8)
         .saturating_mul(num_splats)
         .saturating_add(5 * (num_tiles.isqrt().saturating_mul(num_splats.isqrt())));
-    // clamp to max nr. of dispatches.
-    expected_intersections.min(INTERSECTS_UPPER_BOUND)
+    // Clamp to the bound of the render, by default the max nr. of dispatches.
+    expected_intersections.min(upper_bound)
+}
+
+/// Measures how long each stage of a render takes, by waiting for the GPU after each stage.
+///
+/// With the `gpu_timestamps` feature, stages are timed with timestamp queries instead when the
+/// device supports them, which measures the time the GPU spends on the stage's kernels.
+///
+/// A disabled timer doesn't sync, and costs nothing.
+pub(crate) struct StageTimer {
+    last: Option<Instant>,
+    timings: Vec<(&'static str, Duration)>,
+    #[cfg(feature = "gpu_timestamps")]
+    gpu_timings: Option<Vec<(&'static str, ProfileDuration)>>,
+}
+
+impl StageTimer {
+    pub(crate) fn disabled() -> Self {
+        Self {
+            last: None,
+            timings: vec![],
+            #[cfg(feature = "gpu_timestamps")]
+            gpu_timings: None,
+        }
+    }
+
+    /// Start timing, after any pending work on the device is done.
+    pub(crate) fn start(device: &<MainBackendBase as Backend>::Device) -> Self {
+        MainBackendBase::sync(device);
+        // Timestamp queries are optional in wgpu, otherwise fall back to timing on the CPU.
+        #[cfg(feature = "gpu_timestamps")]
+        let gpu_timings = (WgpuRuntime::client(device).properties().timing_method
+            == TimingMethod::Device)
+            .then(Vec::new);
+        Self {
+            last: Some(Instant::now()),
+            timings: vec![],
+            #[cfg(feature = "gpu_timestamps")]
+            gpu_timings,
+        }
+    }
+
+    /// Run the stage `name`, and record how long it takes.
+    pub(crate) fn stage<O>(
+        &mut self,
+        name: &'static str,
+        device: &<MainBackendBase as Backend>::Device,
+        func: impl FnOnce() -> O,
+    ) -> O {
+        #[cfg(feature = "gpu_timestamps")]
+        if let Some(gpu_timings) = &mut self.gpu_timings {
+            let mut out = None;
+            let profile = WgpuRuntime::client(device).profile(|| out = Some(func()), name);
+            // Profiles can't nest, eg. when the render itself is profiled. The stage is then
+            // missing from the timings.
+            if let Ok(duration) = profile {
+                gpu_timings.push((name, duration));
+            }
+            return out.expect("Profiled stage must run");
+        }
+
+        let out = func();
+        self.lap(name, device);
+        out
+    }
+
+    /// Record the time since the previous stage ended as the time of the stage `name`.
+    fn lap(&mut self, name: &'static str, device: &<MainBackendBase as Backend>::Device) {
+        if let Some(last) = self.last {
+            MainBackendBase::sync(device);
+            let now = Instant::now();
+            self.timings.push((name, now - last));
+            self.last = Some(now);
+        }
+    }
+
+    /// Whether the timings are measured on the GPU.
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) fn gpu_timed(&self) -> bool {
+        #[cfg(feature = "gpu_timestamps")]
+        return self.gpu_timings.is_some();
+        #[cfg(not(feature = "gpu_timestamps"))]
+        false
+    }
+
+    /// The time of each stage, in order. This waits for the GPU timings to be read back.
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) fn into_timings(self) -> Vec<(&'static str, Duration)> {
+        #[cfg(feature = "gpu_timestamps")]
+        if let Some(gpu_timings) = self.gpu_timings {
+            return gpu_timings
+                .into_iter()
+                .map(|(name, duration)| (name, future::block_on(duration.resolve()).duration()))
+                .collect();
+        }
+        self.timings
+    }
 }
 
-pub(crate) fn render_forward(
+/// Values derived from the splats and options, which are the same for every view rendered
+/// from them.
+struct SplatSetup {
+    total_splats: usize,
+    sh_degree: u32,
+    sh_coeffs_per_splat: u32,
+    /// Stored degree of each color channel, for planar coefficients with per channel degrees.
+    sh_channel_degrees: Option<[u32; 3]>,
+    /// Degree of the view dependent opacity, if opacities are given as sh coefficients.
+    opacity_sh_degree: Option<u32>,
+    /// Whether the coefficients are precomputed RGB colors, used as is.
+    rgb_colors: bool,
+    /// Whether to skip the colors, for renders that only need the geometry.
+    no_color: bool,
+    /// Workgroup size of the kernels that process one splat per thread.
+    splat_workgroup_size: u32,
+    max_intersects: u32,
+    tile_bounds: glam::UVec2,
+    /// The uniforms of a render, without the camera and the tiles, which are filled in for each
+    /// view.
+    uniforms: shaders::helpers::RenderUniforms,
+}
+
+impl SplatSetup {
+    fn new(img_size: glam::UVec2, input: &RenderInput, options: &RenderOptions) -> Self {
+        assert!(
+            img_size[0] > 0 && img_size[1] > 0,
+            "Can't render images with 0 size."
+        );
+        assert!(
+            !input.rgb_colors || options.sh_channel_degrees.is_none(),
+            "Precomputed colors don't have per channel sh degrees."
+        );
+        let RenderInput {
+            means,
+            log_scales,
+            quats,
+            sh_coeffs,
+            opacities,
+            ..
+        } = input;
+
+        // Check whether input dimensions are valid.
+        DimCheck::new()
+            .check_dims(means, &["D".into(), 3.into()])
+            .check_dims(log_scales, &["D".into(), 3.into()])
+            .check_dims(quats, &["D".into(), 4.into()])
+            .check_dims(sh_coeffs, &["D".into(), "C".into(), DimBound::Any])
+            .check_dims(opacities, &["D".into(), "O".into()]);
+
+        #[cfg(all(feature = "debug_validation", not(target_family = "wasm")))]
+        crate::dim_check::warn_non_unit_quats(quats);
+
+        #[cfg(any(test, feature = "opacity_sh"))]
+        let opacity_sh_degree = (opacities.shape.num_dims() == 2)
+            .then(|| sh_degree_from_coeffs(opacities.shape.dims[1] as u32));
+        #[cfg(not(any(test, feature = "opacity_sh")))]
+        let opacity_sh_degree = None;
+
+        assert!(
+            opacity_sh_degree.is_none() || options.opacity_activation.is_none(),
+            "An opacity activation isn't supported for view dependent opacities."
+        );
+        assert!(
+            opacities.shape.num_dims() == 1 || opacity_sh_degree.is_some(),
+            "Opacities must have one value per splat. Enable the opacity_sh feature for view dependent opacity."
+        );
+
+        let sh_coeffs_per_splat = sh_coeffs.shape.dims[1] as u32;
+        let gray_sh = sh_coeffs.shape.num_dims() == 3 && sh_coeffs.shape.dims[2] == 1;
+        let stored_sh_degree = if let Some(degrees) = options.sh_channel_degrees {
+            assert_eq!(
+                sh_coeffs.shape.num_dims(),
+                2,
+                "Coefficients with per channel degrees must be planar, of shape [N, K]."
+            );
+            assert_eq!(
+                sh_coeffs_per_splat,
+                planar_coeffs_for_degrees(degrees),
+                "Number of coefficients doesn't match the channel degrees {degrees:?}."
+            );
+            degrees.into_iter().max().unwrap_or(0)
+        } else {
+            assert_eq!(
+                sh_coeffs.shape.num_dims(),
+                3,
+                "Coefficients must be of shape [N, C, 3], unless channel degrees are set."
+            );
+            assert!(
+                matches!(sh_coeffs.shape.dims[2], 1 | 3),
+                "Coefficients must have 3 color channels, or a single gray channel."
+            );
+            sh_degree_from_coeffs(sh_coeffs_per_splat)
+        };
+        // Gray coefficients are read like planar coefficients, with the same degree for each
+        // channel.
+        let sh_channel_degrees = if gray_sh {
+            Some([stored_sh_degree; 3])
+        } else {
+            options.sh_channel_degrees
+        };
+        let sh_degree = options
+            .max_sh_degree
+            .map_or(stored_sh_degree, |max| max.min(stored_sh_degree));
+        let total_splats = means.shape.dims[0];
+
+        // Smaller workgroups need more of them, which can exceed the dispatch limit for large
+        // scenes. The default size supports all scenes up to GAUSSIANS_UPPER_BOUND.
+        let tuned_workgroup_size = splat_workgroup_size(&means.device);
+        let splat_workgroup_size =
+            if total_splats <= tuned_workgroup_size as usize * u16::MAX as usize {
+                tuned_workgroup_size
+            } else {
+                DEFAULT_SPLAT_WORKGROUP_SIZE
+            };
+        let max_intersects =
+            max_intersections(img_size, total_splats as u32, options.intersects_bound());
+
+        let (log_depth_near, log_depth_scale) = match options.depth_key {
+            DepthKey::Float => (0.0, 0.0),
+            DepthKey::Log(range) => (
+                range.near().log2(),
+                1.0 / (range.far().log2() - range.near().log2()),
+            ),
+        };
+
+        let uniforms = shaders::helpers::RenderUniforms {
+            sh_rotation: {
+                let rotation = options.sh_basis.rotation(&options.world_transform);
+                [rotation.w, rotation.x, rotation.y, rotation.z]
+            },
+            sh_degree,
+            total_splats: total_splats as u32,
+            max_intersects,
+            sh_coeffs_per_splat,
+            // Nb: Bit of a hack as these aren't _really_ uniforms but are written to by the
+            // shaders.
+            num_visible: 0,
+            tile_budget: options.tile_budget.map_or(0, NonZeroU32::get),
+            transmittance_threshold: options.transmittance(),
+            opacity_sh_degree: opacity_sh_degree.unwrap_or(0),
+            world_scale: options.world_transform.scale.get(),
+            peel_layers: options.depth_peel_layers.map_or(0, NonZeroU32::get),
+            sh_channel_degrees: sh_channel_degrees
+                .map_or([0; 4], |[r, g, b]| [r, g, b, u32::from(gray_sh)]),
+            clamp_policy: match options.clamp_policy {
+                ClampPolicy::None => shaders::helpers::CLAMP_NONE,
+                ClampPolicy::Clamp => shaders::helpers::CLAMP_UNIT,
+                ClampPolicy::SoftClip => shaders::helpers::CLAMP_SOFT,
+            },
+            alpha_gamma: options.alpha_gamma.map_or(1.0, AlphaGamma::get),
+            log_depth_near,
+            log_depth_scale,
+            resort_window: options.pixel_resort.map_or(0, ResortWindow::get),
+            num_non_finite: 0,
+            // The camera and tiles of each view.
+            ..bytemuck::Zeroable::zeroed()
+        };
+
+        Self {
+            total_splats,
+            sh_degree,
+            sh_coeffs_per_splat,
+            sh_channel_degrees,
+            opacity_sh_degree,
+            rgb_colors: input.rgb_colors,
+            no_color: false,
+            splat_workgroup_size,
+            max_intersects,
+            // Divide screen into tiles.
+            tile_bounds: calc_tile_bounds(img_size),
+            uniforms,
+        }
+    }
+}
+
+/// The data type of the rendered image.
+pub(crate) fn output_dtype(bwd_info: bool, options: &RenderOptions) -> DType {
+    match (bwd_info, options.output_dtype) {
+        (true, OutputDType::F16) => DType::F16,
+        // Nb: Packed u32 images are also stored as F32.
+        _ => DType::F32,
+    }
+}
+
+/// The number of channels of the rendered image.
+pub(crate) fn output_channels(bwd_info: bool, options: &RenderOptions) -> usize {
+    let color_channels = if options.grayscale { 1 } else { 3 };
+    match (bwd_info, options.render_depth) {
+        (true, true) => color_channels + 2,
+        (true, false) => color_channels + 1,
+        // Channels are packed into 4 bytes, aka one float.
+        (false, _) => 1,
+    }
+}
+
+/// Buffers that are fully overwritten by each render, so they can be reused between frames.
+#[derive(Debug, Clone)]
+struct ScratchBuffers {
+    depths: CubeTensor<WgpuRuntime>,
+    projected_splats: CubeTensor<WgpuRuntime>,
+    tile_from_isect: CubeTensor<WgpuRuntime>,
+    depth_key_from_isect: CubeTensor<WgpuRuntime>,
+    compact_gid_from_isect: CubeTensor<WgpuRuntime>,
+    out_img: CubeTensor<WgpuRuntime>,
+    final_index: CubeTensor<WgpuRuntime>,
+    // Indirect dispatch sizes, overwritten by each render.
+    num_culled_wg: CubeTensor<WgpuRuntime>,
+    num_vis_wg: CubeTensor<WgpuRuntime>,
+}
+
+impl ScratchBuffers {
+    fn new(
+        setup: &SplatSetup,
+        img_size: glam::UVec2,
+        bwd_info: bool,
+        img_dtype: DType,
+        out_channels: usize,
+        means: &CubeTensor<WgpuRuntime>,
+    ) -> Self {
+        let (device, client) = (&means.device, &means.client);
+        let max_intersects = setup.max_intersects as usize;
+        let [
+            projected_splats,
+            compact_gid_from_isect,
+            out_img,
+            final_index,
+        ] = Self::output_buffers(setup, img_size, bwd_info, img_dtype, out_channels, means);
+
+        Self {
+            depths: create_tensor([setup.total_splats], device, client, DType::F32),
+            projected_splats,
+            tile_from_isect: create_tensor([max_intersects], device, client, DType::I32),
+            depth_key_from_isect: create_tensor([max_intersects], device, client, DType::I32),
+            compact_gid_from_isect,
+            out_img,
+            final_index,
+            num_culled_wg: create_tensor([3], device, client, DType::I32),
+            num_vis_wg: create_tensor([3], device, client, DType::I32),
+        }
+    }
+
+    /// These buffers, with new buffers for everything a render returns in its image and
+    /// [`RenderAux`]. Renders that run one after another can share the other buffers, while each
+    /// keeps its own results.
+    fn with_new_outputs(
+        &self,
+        setup: &SplatSetup,
+        img_size: glam::UVec2,
+        bwd_info: bool,
+        img_dtype: DType,
+        out_channels: usize,
+    ) -> Self {
+        let [
+            projected_splats,
+            compact_gid_from_isect,
+            out_img,
+            final_index,
+        ] = Self::output_buffers(
+            setup,
+            img_size,
+            bwd_info,
+            img_dtype,
+            out_channels,
+            &self.depths,
+        );
+        Self {
+            projected_splats,
+            compact_gid_from_isect,
+            out_img,
+            final_index,
+            ..self.clone()
+        }
+    }
+
+    /// The projected splats, sorted splat ids, image and final indices of a render, on the
+    /// device of `like`.
+    fn output_buffers(
+        setup: &SplatSetup,
+        img_size: glam::UVec2,
+        bwd_info: bool,
+        img_dtype: DType,
+        out_channels: usize,
+        like: &CubeTensor<WgpuRuntime>,
+    ) -> [CubeTensor<WgpuRuntime>; 4] {
+        let (device, client) = (&like.device, &like.client);
+        let max_intersects = setup.max_intersects as usize;
+        let [w, h] = [img_size.x as usize, img_size.y as usize];
+
+        // Create a buffer of 'projected' splats, that is,
+        // project XY, projected conic, and converted color.
+        let projected_size = size_of::<shaders::helpers::ProjectedSplat>() / size_of::<f32>();
+
+        // Buffer containing the final visible splat per tile.
+        let final_index_size = if bwd_info { [h, w] } else { [1, 1] };
+
+        [
+            create_tensor(
+                [setup.total_splats, projected_size],
+                device,
+                client,
+                DType::F32,
+            ),
+            create_tensor([max_intersects], device, client, DType::I32),
+            create_tensor([h, w, out_channels], device, client, img_dtype),
+            create_tensor(final_index_size, device, client, DType::I32),
+        ]
+    }
+}
+
+/// Keeps scratch buffers alive between renders, to avoid allocating them for every frame.
+///
+/// This is useful when rendering the same scene repeatedly, eg. in a viewer. The buffers are
+/// reallocated whenever the number of splats, the image size or the output format changes.
+///
+/// Nb: The image and [`RenderAux`] returned by a render share memory with the context, and are
+/// overwritten by the next render that uses the same context.
+#[derive(Debug, Default)]
+pub struct RenderContext {
+    cached: Option<(ContextKey, ScratchBuffers)>,
+    projection_cache: Option<ProjectionCache>,
+    collect_stats: bool,
+    last_stats: Option<RenderStats>,
+}
+
+/// The projection of every splat to one view, by global id, see [`render_forward_partial`].
+#[derive(Debug, Clone)]
+struct ProjectionCache {
+    key: ProjectionKey,
+    /// Whether each splat is visible, as 0 or 1. Each buffer has a last element past the splats,
+    /// which collects writes that should be dropped.
+    visible: CubeTensor<WgpuRuntime>,
+    /// The [`shaders::helpers::ProjectedSplat`] of each visible splat.
+    projected_splats: CubeTensor<WgpuRuntime>,
+    /// The change of the depth of each visible splat per pixel, only for per pixel re-sorting.
+    depth_grads: Option<CubeTensor<WgpuRuntime>>,
+    /// The camera space depth of each visible splat.
+    depths: CubeTensor<WgpuRuntime>,
+}
+
+/// Everything the cached projections depend on, besides the splats themselves.
+#[derive(Debug, Clone, PartialEq)]
+struct ProjectionKey {
+    camera: Camera,
+    img_size: glam::UVec2,
+    total_splats: usize,
+    sh_degree: u32,
+    sh_coeffs_per_splat: u32,
+    options: RenderOptions,
+}
+
+impl ProjectionCache {
+    fn empty(key: ProjectionKey, device: &<MainBackendBase as Backend>::Device) -> Self {
+        let rows = key.total_splats + 1;
+        let projected_size = size_of::<shaders::helpers::ProjectedSplat>() / size_of::<f32>();
+        let depth_grads = key
+            .options
+            .pixel_resort
+            .is_some()
+            .then(|| MainBackendBase::float_zeros([rows, 2].into(), device));
+        Self {
+            key,
+            visible: MainBackendBase::int_zeros([rows].into(), device),
+            projected_splats: MainBackendBase::float_zeros([rows, projected_size].into(), device),
+            depth_grads,
+            depths: MainBackendBase::float_zeros([rows].into(), device),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ContextKey {
+    total_splats: usize,
+    max_intersects: u32,
+    img_size: glam::UVec2,
+    bwd_info: bool,
+    img_dtype: DType,
+    out_channels: usize,
+}
+
+impl RenderContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collect [`RenderStats`] for every render that uses this context.
+    ///
+    /// This reads back data and waits for the GPU after each stage of the render, so slows down
+    /// rendering. Reading back blocks, so this isn't possible on wasm.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn set_collect_stats(&mut self, collect_stats: bool) {
+        self.collect_stats = collect_stats;
+        if !collect_stats {
+            self.last_stats = None;
+        }
+    }
+
+    /// Stats of the last render that used this context, if collecting stats is enabled.
+    pub fn last_stats(&self) -> Option<&RenderStats> {
+        self.last_stats.as_ref()
+    }
+
+    fn scratch_buffers(
+        &mut self,
+        setup: &SplatSetup,
+        img_size: glam::UVec2,
+        bwd_info: bool,
+        img_dtype: DType,
+        out_channels: usize,
+        means: &CubeTensor<WgpuRuntime>,
+    ) -> ScratchBuffers {
+        let key = ContextKey {
+            total_splats: setup.total_splats,
+            max_intersects: setup.max_intersects,
+            img_size,
+            bwd_info,
+            img_dtype,
+            out_channels,
+        };
+
+        if let Some((_, buffers)) = self.cached.as_ref().filter(|(cached, _)| *cached == key) {
+            return buffers.clone();
+        }
+
+        let buffers =
+            ScratchBuffers::new(setup, img_size, bwd_info, img_dtype, out_channels, means);
+        self.cached = Some((key, buffers.clone()));
+        buffers
+    }
+}
+
+/// The splats to render, and the optional inputs of a render.
+///
+/// The splats are given as the raw tensors of [`crate::gaussian_splats::Splats`]: `[N, 3]` means
+/// and log scales, `[N, 4]` quaternions, `[N, C, 3]` sh coefficients and `[N]` opacities.
+#[derive(Debug, Clone)]
+pub struct RenderInput {
+    pub means: CubeTensor<WgpuRuntime>,
+    pub log_scales: CubeTensor<WgpuRuntime>,
+    pub quats: CubeTensor<WgpuRuntime>,
+    pub sh_coeffs: CubeTensor<WgpuRuntime>,
+    pub opacities: CubeTensor<WgpuRuntime>,
+    /// Whether `sh_coeffs` are precomputed colors, see [`Self::from_colors`].
+    pub rgb_colors: bool,
+    /// Restricts the render to the splats with the given global ids, as an int tensor of unique,
+    /// in-bounds indices. The image is the same as rendering a copy of just those splats, without
+    /// having to gather them first, which is useful to hide parts of a scene while editing it.
+    /// Frustum culling is skipped when rendering a subset.
+    pub subset: Option<CubeTensor<WgpuRuntime>>,
+    /// Composites the splats over a per-pixel background, as an opaque RGB `[height, width, 3]`
+    /// float image in the same color space as the splats. The remaining transmittance of each
+    /// pixel blends in the background, so the rendered alpha is always one. Without a
+    /// background, splats are rendered over transparent black.
+    pub background: Option<CubeTensor<WgpuRuntime>>,
+    /// Only renders the pixels inside a mask, as a `[height, width]` int tensor which is nonzero
+    /// for the pixels to render, eg. from [`crate::mask::polygon_mask`]. Masked out pixels are
+    /// left transparent, or show the background, and tiles that are entirely masked out skip
+    /// their splats.
+    pub pixel_mask: Option<CubeTensor<WgpuRuntime>>,
+}
+
+impl RenderInput {
+    pub fn new(
+        means: CubeTensor<WgpuRuntime>,
+        log_scales: CubeTensor<WgpuRuntime>,
+        quats: CubeTensor<WgpuRuntime>,
+        sh_coeffs: CubeTensor<WgpuRuntime>,
+        opacities: CubeTensor<WgpuRuntime>,
+    ) -> Self {
+        Self {
+            means,
+            log_scales,
+            quats,
+            sh_coeffs,
+            opacities,
+            rgb_colors: false,
+            subset: None,
+            background: None,
+            pixel_mask: None,
+        }
+    }
+
+    /// Render splats with precomputed colors, instead of evaluating their spherical harmonics.
+    ///
+    /// `colors` are the RGB colors of the splats as a `[N, 3]` float tensor, in the same space as
+    /// colors evaluated from spherical harmonics, eg. from a separate appearance model. The
+    /// colors don't depend on the view direction, so a render that only moves the camera can
+    /// reuse them. This is the same as rendering degree 0 spherical harmonics that evaluate to
+    /// the colors.
+    pub fn from_colors(
+        means: CubeTensor<WgpuRuntime>,
+        log_scales: CubeTensor<WgpuRuntime>,
+        quats: CubeTensor<WgpuRuntime>,
+        colors: CubeTensor<WgpuRuntime>,
+        opacities: CubeTensor<WgpuRuntime>,
+    ) -> Self {
+        assert!(
+            colors.shape.num_dims() == 2 && colors.shape.dims[1] == 3,
+            "Colors must be a [N, 3] tensor."
+        );
+        let num_splats = colors.shape.dims[0];
+        // Store the colors like degree 0 coefficients, which ProjectVisible reads as is.
+        let colors = MainBackendBase::float_reshape(colors, [num_splats, 1, 3].into());
+        Self {
+            rgb_colors: true,
+            ..Self::new(means, log_scales, quats, colors, opacities)
+        }
+    }
+
+    /// The means, log scales, quats, sh coefficients and opacities of the splats.
+    fn splats(&self) -> [CubeTensor<WgpuRuntime>; 5] {
+        [
+            self.means.clone(),
+            self.log_scales.clone(),
+            self.quats.clone(),
+            self.sh_coeffs.clone(),
+            self.opacities.clone(),
+        ]
+    }
+
+    /// Check the optional inputs match a render of `img_size`.
+    fn validate(&self, img_size: glam::UVec2) {
+        let [h, w] = [img_size.y as usize, img_size.x as usize];
+        if let Some(subset) = &self.subset {
+            assert!(
+                subset.shape.num_dims() == 1 && subset.dtype == DType::I32,
+                "The subset must be a 1D int tensor of splat indices."
+            );
+        }
+        if let Some(background) = &self.background {
+            assert!(
+                background.shape.dims == [h, w, 3] && background.dtype == DType::F32,
+                "The background must be a float RGB image of the rendered size."
+            );
+        }
+        if let Some(pixel_mask) = &self.pixel_mask {
+            assert!(
+                pixel_mask.shape.dims == [h, w] && pixel_mask.dtype == DType::I32,
+                "The pixel mask must be an int image of the rendered size."
+            );
+        }
+    }
+}
+
+/// Render splats to an image.
+///
+/// See [`RenderInput`] for the splats, and the optional subset, background and pixel mask.
+pub fn render_forward(
     camera: &Camera,
     img_size: glam::UVec2,
-    means: CubeTensor<WgpuRuntime>,
-    log_scales: CubeTensor<WgpuRuntime>,
-    quats: CubeTensor<WgpuRuntime>,
-    sh_coeffs: CubeTensor<WgpuRuntime>,
-    opacities: CubeTensor<WgpuRuntime>,
+    input: RenderInput,
     bwd_info: bool,
+    options: &RenderOptions,
 ) -> (CubeTensor<WgpuRuntime>, RenderAux<MainBackendBase>) {
+    let view = project_and_sort(camera, img_size, input, bwd_info, options);
+    rasterize_stage(view, options)
+}
+
+/// Splats projected to a view and sorted per tile, ready to be rasterized.
+///
+/// This is the state between the stages of a render, see [`project_and_sort`]. The buffers are
+/// indexed like in [`RenderAux`]: visible splats are compacted, and `compact_gid_from_isect`
+/// lists the compact ids of the splats each tile intersects, tile after tile, front to back.
+#[derive(Debug, Clone)]
+pub struct ProjectedView {
+    pub img_size: glam::UVec2,
+    /// Number of tiles of the image, horizontally and vertically.
+    pub tile_bounds: glam::UVec2,
+    /// The [`shaders::helpers::RenderUniforms`] of the render, which include the number of
+    /// visible splats.
+    pub uniforms_buffer: CubeTensor<WgpuRuntime>,
+    /// The visible splats, as [`shaders::helpers::ProjectedSplat`], by compact id.
+    pub projected_splats: CubeTensor<WgpuRuntime>,
+    /// The change of the depth of each visible splat per pixel, along the x and y axis of the
+    /// image, by compact id. Only computed with [`RenderOptions::pixel_resort`].
+    pub depth_grads: Option<CubeTensor<WgpuRuntime>>,
+    /// Start of the intersections of each tile in `compact_gid_from_isect`, with the total number
+    /// of intersections as last element.
+    pub tile_offsets: CubeTensor<WgpuRuntime>,
+    /// Compact id of the splat of each intersection, sorted by tile, and then by depth.
+    pub compact_gid_from_isect: CubeTensor<WgpuRuntime>,
+    /// Global id of each compacted splat.
+    pub global_from_compact_gid: CubeTensor<WgpuRuntime>,
+    total_splats: usize,
+    bwd_info: bool,
+    nothing_visible: bool,
+    out_img: CubeTensor<WgpuRuntime>,
+    final_index: CubeTensor<WgpuRuntime>,
+    background: Option<CubeTensor<WgpuRuntime>>,
+    pixel_mask: Option<CubeTensor<WgpuRuntime>>,
+}
+
+/// Project splats to a view and sort them per tile, the first stage of [`render_forward`].
+///
+/// This takes the same arguments as [`render_forward`]. Pass the result to [`rasterize_stage`]
+/// to finish the render, which composites the background and applies the pixel mask of `input`,
+/// or rasterize the [`ProjectedView`] with a custom kernel, eg. to try a different blending
+/// function, without reimplementing projection and sorting.
+pub fn project_and_sort(
+    camera: &Camera,
+    img_size: glam::UVec2,
+    input: RenderInput,
+    bwd_info: bool,
+    options: &RenderOptions,
+) -> ProjectedView {
+    let setup = SplatSetup::new(img_size, &input, options);
+    project_and_sort_setup(camera, img_size, &setup, input, bwd_info, options)
+}
+
+/// Project and sort splats like [`project_and_sort`], with settings that are already derived.
+fn project_and_sort_setup(
+    camera: &Camera,
+    img_size: glam::UVec2,
+    setup: &SplatSetup,
+    input: RenderInput,
+    bwd_info: bool,
+    options: &RenderOptions,
+) -> ProjectedView {
+    // Check whether any work needs to be flushed.
+    tracing::trace_span!("pre setup", sync_burn = true).in_scope(|| {});
+
+    let _span = tracing::trace_span!("project_and_sort", sync_burn = true).entered();
+
+    let scratch = ScratchBuffers::new(
+        setup,
+        img_size,
+        bwd_info,
+        output_dtype(bwd_info, options),
+        output_channels(bwd_info, options),
+        &input.means,
+    );
+
+    project_view(
+        camera,
+        img_size,
+        0..setup.tile_bounds.y,
+        setup,
+        scratch,
+        input,
+        bwd_info,
+        options,
+        &mut StageTimer::disabled(),
+    )
+}
+
+/// Rasterize a view from [`project_and_sort`], the last stage of [`render_forward`].
+///
+/// `options` should be the same as the view was projected with.
+pub fn rasterize_stage(
+    view: ProjectedView,
+    options: &RenderOptions,
+) -> (CubeTensor<WgpuRuntime>, RenderAux<MainBackendBase>) {
+    let _span = tracing::trace_span!("rasterize_stage", sync_burn = true).entered();
+    rasterize_view(view, options, &mut StageTimer::disabled())
+}
+
+/// Render the global id of the splat that contributes most to each pixel, eg. to select the
+/// splat under the mouse cursor.
+///
+/// The result is a `[height, width]` int image, with -1 where no splat is blended. Splats are
+/// weighted as in blending, by their alpha times the transmittance left in front of them, so
+/// this is the front-most splat unless a splat behind it is much more opaque. Only the weights
+/// are rasterized, which is cheaper than rendering colors. The other arguments are as for
+/// [`render_forward`], without a background or pixel mask. The alpha gamma and depth peeling of
+/// `options` are ignored.
+pub fn render_forward_ids(
+    camera: &Camera,
+    img_size: glam::UVec2,
+    input: RenderInput,
+    options: &RenderOptions,
+) -> CubeTensor<WgpuRuntime> {
     assert!(
-        img_size[0] > 0 && img_size[1] > 0,
-        "Can't render images with 0 size."
+        input.background.is_none() && input.pixel_mask.is_none(),
+        "Id renders don't support a background or pixel mask."
     );
+    let view = project_and_sort(camera, img_size, input, false, options);
+    rasterize_ids(&view)
+}
 
-    let device = &means.device.clone();
-    let client = means.client.clone();
+/// Rasterize the ids of a view from [`project_and_sort`], see [`render_forward_ids`].
+pub fn rasterize_ids(view: &ProjectedView) -> CubeTensor<WgpuRuntime> {
+    let _span = tracing::trace_span!("RasterizeIds", sync_burn = true).entered();
+
+    let device = &view.out_img.device;
+    let client = &view.out_img.client;
+    let out_ids = create_tensor(
+        [view.img_size.y as usize, view.img_size.x as usize],
+        device,
+        client,
+        DType::I32,
+    );
+
+    client.execute(
+        RasterizeIds::task(),
+        calc_cube_count(
+            [
+                view.img_size.x,
+                view.tile_bounds.y * shaders::helpers::TILE_WIDTH,
+            ],
+            RasterizeIds::WORKGROUP_SIZE,
+        ),
+        Bindings::new().with_buffers(vec![
+            view.uniforms_buffer.handle.clone().binding(),
+            view.compact_gid_from_isect.handle.clone().binding(),
+            view.tile_offsets.handle.clone().binding(),
+            view.projected_splats.handle.clone().binding(),
+            view.global_from_compact_gid.handle.clone().binding(),
+            out_ids.handle.clone().binding(),
+        ]),
+    );
+
+    out_ids
+}
+
+/// Render only the depth of splats, eg. for shadow maps or occlusion queries.
+///
+/// The result is a `[height, width]` float image, the same as the depth channel of a render with
+/// [`RenderOptions::render_depth`]: depths are blended like colors, so are weighted by the alpha
+/// of the pixel, and are disparities with [`RenderOptions::depth_as_disparity`]. Colors aren't
+/// evaluated or blended at all, which makes this much cheaper than a full render, and the depth
+/// is written in place of the packed image of the render, so needs no extra memory. The other
+/// arguments are as for [`render_forward`], without a background or pixel mask.
+///
+/// The options of the projection, like the clip planes, mip filter and opacity activation, apply
+/// as in a full render, as do the tile budget, transmittance threshold and alpha gamma. Options
+/// that only affect colors, like the spherical harmonics, tonemap, channel order and alpha mode,
+/// have no effect. Other blend modes than [`BlendMode::AlphaOver`], per pixel re-sorting and depth
+/// peeling aren't supported, and panic.
+pub fn render_forward_depth(
+    camera: &Camera,
+    img_size: glam::UVec2,
+    input: RenderInput,
+    options: &RenderOptions,
+) -> CubeTensor<WgpuRuntime> {
+    assert!(
+        input.background.is_none() && input.pixel_mask.is_none(),
+        "Depth renders don't support a background or pixel mask."
+    );
+    let mut setup = SplatSetup::new(img_size, &input, options);
+    setup.no_color = true;
+
+    let view = project_and_sort_setup(camera, img_size, &setup, input, false, options);
+    rasterize_depth(view, options)
+}
+
+/// Rasterize the depth of a view from [`project_and_sort`], see [`render_forward_depth`].
+///
+/// The view must cover the whole image, and have no background or pixel mask.
+pub fn rasterize_depth(view: ProjectedView, options: &RenderOptions) -> CubeTensor<WgpuRuntime> {
+    assert!(
+        view.background.is_none() && view.pixel_mask.is_none(),
+        "Depth renders don't support a background or pixel mask."
+    );
+    assert!(
+        view.tile_bounds == calc_tile_bounds(view.img_size),
+        "Depth renders need a view of the whole image."
+    );
+    assert!(
+        options.blend_mode == BlendMode::AlphaOver
+            && options.pixel_resort.is_none()
+            && options.depth_peel_layers.is_none(),
+        "Depth renders only support alpha blending, without re-sorting or depth peeling."
+    );
+
+    let _span = tracing::trace_span!("RasterizeDepth", sync_burn = true).entered();
+
+    let client = &view.out_img.client.clone();
+    let [h, w] = [view.img_size.y as usize, view.img_size.x as usize];
+    // The packed image has one float per pixel, so is reused for the depth.
+    let out_depth =
+        if view.out_img.shape.num_elements() == h * w && view.out_img.dtype == DType::F32 {
+            MainBackendBase::float_reshape(view.out_img, [h, w].into())
+        } else {
+            create_tensor([h, w], &view.out_img.device, client, DType::F32)
+        };
+
+    client.execute(
+        RasterizeDepth::task(
+            options.depth_as_disparity,
+            options.alpha_gamma.is_some_and(|gamma| gamma.get() != 1.0),
+        ),
+        calc_cube_count(
+            [
+                view.img_size.x,
+                view.tile_bounds.y * shaders::helpers::TILE_WIDTH,
+            ],
+            RasterizeDepth::WORKGROUP_SIZE,
+        ),
+        Bindings::new().with_buffers(vec![
+            view.uniforms_buffer.handle.clone().binding(),
+            view.compact_gid_from_isect.handle.clone().binding(),
+            view.tile_offsets.handle.clone().binding(),
+            view.projected_splats.handle.clone().binding(),
+            out_depth.handle.clone().binding(),
+        ]),
+    );
+
+    out_depth
+}
+
+/// Render splats like [`render_forward`], but reuse the scratch buffers held by `context`.
+///
+/// See [`RenderContext`] for when buffers are reallocated.
+pub fn render_forward_with_context(
+    context: &mut RenderContext,
+    camera: &Camera,
+    img_size: glam::UVec2,
+    input: RenderInput,
+    bwd_info: bool,
+    options: &RenderOptions,
+) -> (CubeTensor<WgpuRuntime>, RenderAux<MainBackendBase>) {
+    render_with_context_into(context, camera, img_size, input, None, bwd_info, options)
+}
+
+/// Render splats like [`render_forward_with_context`], but write the image into `target`
+/// instead of a buffer of the context.
+///
+/// This lets a viewer render every frame into an image buffer it owns, eg. the buffer it copies
+/// to the screen, without copying the image first. `target` must have the shape and data type
+/// the render would allocate: `[height, width, 1]` packed RGBA8 colors without `bwd_info`, or
+/// `[height, width, 4]` floats (5 with depth) with it. The image is rendered at the size of
+/// `target`. Tonemapping writes a new image, so isn't supported.
+pub fn render_forward_into(
+    context: &mut RenderContext,
+    target: &CubeTensor<WgpuRuntime>,
+    camera: &Camera,
+    input: RenderInput,
+    bwd_info: bool,
+    options: &RenderOptions,
+) -> RenderAux<MainBackendBase> {
+    let dims = &target.shape.dims;
+    assert_eq!(
+        dims.len(),
+        3,
+        "The target must be a [height, width, channels] image."
+    );
+    let channels = output_channels(bwd_info, options);
+    assert_eq!(
+        dims[2], channels,
+        "The target must have {channels} channels for this render."
+    );
+    let dtype = output_dtype(bwd_info, options);
+    assert_eq!(
+        target.dtype, dtype,
+        "The target must be {dtype:?} for this render."
+    );
+    assert_eq!(
+        target.device, input.means.device,
+        "The target must be on the device of the splats."
+    );
+    assert!(
+        !bwd_info || options.tonemap.is_none(),
+        "Tonemapping isn't supported when rendering into a target."
+    );
+
+    let img_size = glam::uvec2(dims[1] as u32, dims[0] as u32);
+    let (_, aux) = render_with_context_into(
+        context,
+        camera,
+        img_size,
+        input,
+        Some(target),
+        bwd_info,
+        options,
+    );
+    aux
+}
+
+/// Render splats like [`render_forward_with_context`], but only project the splats in `dirty`,
+/// and reuse the projections of the other splats from the last partial render with `context`.
+///
+/// This keeps editing responsive on large scenes, where only a few splats change per frame.
+/// `dirty` lists the global ids of every splat that changed since the last partial render, as an
+/// int tensor of unique, in-bounds indices. All splats are projected when `dirty` is `None`, eg.
+/// after loading a new scene, and whenever the camera, the image size, the options or the
+/// number of splats changed since the last partial render, so the image is always the same as a
+/// full render. The visible splats are still sorted and rasterized as a whole.
+///
+/// Splats are projected without frustum culling when only some of them are dirty, and
+/// [`RenderAux::num_non_finite`] only counts the splats that were projected. Partial renders
+/// can't render a subset of the splats.
+pub fn render_forward_partial(
+    context: &mut RenderContext,
+    camera: &Camera,
+    img_size: glam::UVec2,
+    input: RenderInput,
+    dirty: Option<CubeTensor<WgpuRuntime>>,
+    bwd_info: bool,
+    options: &RenderOptions,
+) -> (CubeTensor<WgpuRuntime>, RenderAux<MainBackendBase>) {
+    assert!(
+        input.subset.is_none(),
+        "Partial renders can't render a subset, pass the changed splats as dirty instead."
+    );
+    let setup = SplatSetup::new(img_size, &input, options);
+
+    let _span = tracing::trace_span!("render_forward_partial", sync_burn = true).entered();
+
+    let scratch = context.scratch_buffers(
+        &setup,
+        img_size,
+        bwd_info,
+        output_dtype(bwd_info, options),
+        output_channels(bwd_info, options),
+        &input.means,
+    );
+    let device = &input.means.device.clone();
+    let mut timer = if context.collect_stats {
+        StageTimer::start(device)
+    } else {
+        StageTimer::disabled()
+    };
+
+    let key = ProjectionKey {
+        camera: camera.clone(),
+        img_size,
+        total_splats: setup.total_splats,
+        sh_degree: setup.sh_degree,
+        sh_coeffs_per_splat: setup.sh_coeffs_per_splat,
+        options: options.clone(),
+    };
+    let cached = context
+        .projection_cache
+        .take()
+        .filter(|cache| cache.key == key);
+    let (cache, dirty) = match (cached, dirty) {
+        (Some(cache), Some(dirty)) => (cache, Some(dirty)),
+        _ => (ProjectionCache::empty(key, device), None),
+    };
+
+    // Only the dirty splats are projected, as a subset.
+    let projection = project_splats(
+        camera,
+        img_size,
+        0..setup.tile_bounds.y,
+        &setup,
+        &scratch,
+        &RenderInput {
+            subset: dirty.clone(),
+            ..input.clone()
+        },
+        options,
+        &mut timer,
+    );
+    let (cache, projection) = timer.stage("MergeProjections", device, || {
+        tracing::trace_span!("MergeProjections", sync_burn = true)
+            .in_scope(|| merge_projections(cache, dirty, projection, &setup))
+    });
+    context.projection_cache = Some(cache);
+
+    let view = ProjectedView {
+        background: input.background,
+        pixel_mask: input.pixel_mask,
+        ..sort_projection(
+            projection, img_size, &setup, scratch, bwd_info, options, &mut timer,
+        )
+    };
+    let (img, aux) = rasterize_view(view, options, &mut timer);
+
+    #[cfg(not(target_family = "wasm"))]
+    if context.collect_stats {
+        let mut stats = aux.read_stats();
+        stats.gpu_timings = timer.gpu_timed();
+        stats.kernel_timings = timer.into_timings();
+        context.last_stats = Some(stats);
+    }
+
+    (img, aux)
+}
+
+/// Render with the scratch buffers of `context`, into `target` if given.
+fn render_with_context_into(
+    context: &mut RenderContext,
+    camera: &Camera,
+    img_size: glam::UVec2,
+    input: RenderInput,
+    target: Option<&CubeTensor<WgpuRuntime>>,
+    bwd_info: bool,
+    options: &RenderOptions,
+) -> (CubeTensor<WgpuRuntime>, RenderAux<MainBackendBase>) {
+    let setup = SplatSetup::new(img_size, &input, options);
 
     // Check whether any work needs to be flushed.
     tracing::trace_span!("pre setup", sync_burn = true).in_scope(|| {});
 
     let _span = tracing::trace_span!("render_forward", sync_burn = true).entered();
 
-    // Check whether input dimensions are valid.
-    DimCheck::new()
-        .check_dims(&means, &["D".into(), 3.into()])
-        .check_dims(&log_scales, &["D".into(), 3.into()])
-        .check_dims(&quats, &["D".into(), 4.into()])
-        .check_dims(&sh_coeffs, &["D".into(), "C".into(), 3.into()])
-        .check_dims(&opacities, &["D".into()]);
+    let mut scratch = context.scratch_buffers(
+        &setup,
+        img_size,
+        bwd_info,
+        output_dtype(bwd_info, options),
+        output_channels(bwd_info, options),
+        &input.means,
+    );
+    // Every pixel of the image is written, so the target can stand in for the image buffer.
+    if let Some(target) = target {
+        scratch.out_img = target.clone();
+    }
 
-    // Divide screen into tiles.
-    let tile_bounds = calc_tile_bounds(img_size);
+    let mut timer = if context.collect_stats {
+        StageTimer::start(&input.means.device)
+    } else {
+        StageTimer::disabled()
+    };
+
+    let (img, aux) = render_view(
+        camera,
+        img_size,
+        0..setup.tile_bounds.y,
+        &setup,
+        scratch,
+        input,
+        bwd_info,
+        options,
+        &mut timer,
+    );
+
+    #[cfg(not(target_family = "wasm"))]
+    if context.collect_stats {
+        let mut stats = aux.read_stats();
+        stats.gpu_timings = timer.gpu_timed();
+        stats.kernel_timings = timer.into_timings();
+        context.last_stats = Some(stats);
+    }
+
+    (img, aux)
+}
+
+/// Render the same splats from multiple cameras.
+///
+/// This matches calling [`render_forward`] for each camera, but checks the splats and derives the
+/// settings and uniforms that don't depend on the camera only once, and the views share the
+/// scratch buffers that aren't part of their results. The dispatches of all views are recorded
+/// back to back. The images are stacked into one `[views, height, width, channels]` tensor, with
+/// one [`RenderAux`] per view.
+pub fn render_forward_batch(
+    cameras: &[Camera],
+    img_size: glam::UVec2,
+    input: RenderInput,
+    bwd_info: bool,
+    options: &RenderOptions,
+) -> (CubeTensor<WgpuRuntime>, Vec<RenderAux<MainBackendBase>>) {
+    assert!(!cameras.is_empty(), "Need at least one camera to render.");
+
+    let setup = SplatSetup::new(img_size, &input, options);
+
+    // Check whether any work needs to be flushed.
+    tracing::trace_span!("pre setup", sync_burn = true).in_scope(|| {});
+
+    let _span = tracing::trace_span!("render_forward_batch", sync_burn = true).entered();
+
+    let img_dtype = output_dtype(bwd_info, options);
+    let out_channels = output_channels(bwd_info, options);
+    let shared = ScratchBuffers::new(
+        &setup,
+        img_size,
+        bwd_info,
+        img_dtype,
+        out_channels,
+        &input.means,
+    );
+
+    let (imgs, auxes): (Vec<_>, Vec<_>) = cameras
+        .iter()
+        .enumerate()
+        .map(|(i, camera)| {
+            // The views render one after another, so only the buffers of their results need to
+            // be separate.
+            let scratch = if i == 0 {
+                shared.clone()
+            } else {
+                shared.with_new_outputs(&setup, img_size, bwd_info, img_dtype, out_channels)
+            };
+            let (img, aux) = render_view(
+                camera,
+                img_size,
+                0..setup.tile_bounds.y,
+                &setup,
+                scratch,
+                input.clone(),
+                bwd_info,
+                options,
+                &mut StageTimer::disabled(),
+            );
+            let mut shape = img.shape.dims.clone();
+            shape.insert(0, 1);
+            (MainBackendBase::float_reshape(img, shape.into()), aux)
+        })
+        .unzip();
+
+    (MainBackendBase::float_cat(imgs, 0), auxes)
+}
+
+/// Bytes of scratch memory each intersection needs. The tile ids, depths and splat ids of the
+/// intersections are each 4 bytes, and sorting them needs a second copy of each.
+pub(crate) const BYTES_PER_INTERSECT: u64 = 2 * 3 * size_of::<u32>() as u64;
+
+/// Render splats like [`render_forward`], in horizontal bands of the image.
+///
+/// The intersection buffers of a render grow with the image size, which can use too much memory
+/// for very high resolution images. This renders bands of whole tile rows one after another,
+/// using the tallest bands whose intersection buffers fit in `memory_budget` bytes, but always at
+/// least one tile row. Each band is projected with the full camera and written into the same
+/// image, so the result matches a single render of the whole image exactly.
+///
+/// Only the image is returned, as the [`RenderAux`] of a render only covers a single band.
+/// Tonemapping isn't supported, as that needs the whole image. Render in linear color and use
+/// [`tonemap_image`] on the result instead.
+pub fn render_forward_banded(
+    camera: &Camera,
+    img_size: glam::UVec2,
+    input: RenderInput,
+    bwd_info: bool,
+    options: &RenderOptions,
+    memory_budget: u64,
+) -> CubeTensor<WgpuRuntime> {
+    assert!(
+        options.tonemap.is_none(),
+        "Banded renders can't be tonemapped, tonemap the rendered image instead."
+    );
+
+    let total_splats = input.means.shape.dims[0] as u32;
+    let band_size = |rows: u32| {
+        uvec2(
+            img_size.x,
+            (rows * shaders::helpers::TILE_WIDTH).min(img_size.y),
+        )
+    };
+    let total_rows = calc_tile_bounds(img_size).y;
+    let mut band_rows = total_rows;
+    while band_rows > 1
+        && u64::from(max_intersections(
+            band_size(band_rows),
+            total_splats,
+            options.intersects_bound(),
+        )) * BYTES_PER_INTERSECT
+            > memory_budget
+    {
+        band_rows = band_rows.div_ceil(2);
+    }
+
+    let setup = SplatSetup::new(band_size(band_rows), &input, options);
+
+    // Check whether any work needs to be flushed.
+    tracing::trace_span!("pre setup", sync_burn = true).in_scope(|| {});
+
+    let _span = tracing::trace_span!("render_forward_banded", sync_burn = true).entered();
+
+    // The bands render one after another, so can share their scratch buffers. The image of the
+    // scratch buffers covers the whole image.
+    let scratch = ScratchBuffers::new(
+        &setup,
+        img_size,
+        bwd_info,
+        output_dtype(bwd_info, options),
+        output_channels(bwd_info, options),
+        &input.means,
+    );
+
+    for start in (0..total_rows).step_by(band_rows as usize) {
+        let _ = render_view(
+            camera,
+            img_size,
+            start..(start + band_rows).min(total_rows),
+            &setup,
+            scratch.clone(),
+            input.clone(),
+            bwd_info,
+            options,
+            &mut StageTimer::disabled(),
+        );
+    }
+
+    scratch.out_img
+}
+
+/// Render splats with each sh degree from 0 up to the degree `options` evaluates, to compare the
+/// view dependent contribution of each band.
+///
+/// Projecting and sorting the splats doesn't depend on their colors, so is done once, and only
+/// the colors are evaluated again for each degree before rasterizing. The images are stacked into
+/// one `[degrees, height, width, channels]` tensor, where image `d` matches a render with
+/// `max_sh_degree` set to `d`. Like [`render_forward_banded`], only the images are returned.
+pub fn render_forward_sh_degrees(
+    camera: &Camera,
+    img_size: glam::UVec2,
+    input: RenderInput,
+    bwd_info: bool,
+    options: &RenderOptions,
+) -> CubeTensor<WgpuRuntime> {
+    let setup = SplatSetup::new(img_size, &input, options);
+
+    // Check whether any work needs to be flushed.
+    tracing::trace_span!("pre setup", sync_burn = true).in_scope(|| {});
+
+    let _span = tracing::trace_span!("render_forward_sh_degrees", sync_burn = true).entered();
+
+    let img_dtype = output_dtype(bwd_info, options);
+    let out_channels = output_channels(bwd_info, options);
+    let scratch = ScratchBuffers::new(
+        &setup,
+        img_size,
+        bwd_info,
+        img_dtype,
+        out_channels,
+        &input.means,
+    );
+    let splats = input.splats();
+    let view = project_view(
+        camera,
+        img_size,
+        0..setup.tile_bounds.y,
+        &setup,
+        scratch,
+        input,
+        bwd_info,
+        options,
+        &mut StageTimer::disabled(),
+    );
+
+    let device = &view.out_img.device.clone();
+    let client = &view.out_img.client.clone();
+    let num_vis_wg = create_tensor([3], device, client, DType::I32);
+    if !view.nothing_visible {
+        let offset = offset_of!(shaders::helpers::RenderUniforms, num_visible) / 4;
+        let num_visible =
+            MainBackendBase::int_slice(view.uniforms_buffer.clone(), &[offset..offset + 1]);
+        write_dispatch_buffer(num_visible, [setup.splat_workgroup_size, 1, 1], &num_vis_wg);
+    }
+
+    let sh_degree_offset = offset_of!(shaders::helpers::RenderUniforms, sh_degree) / 4;
+    let imgs = (0..=setup.sh_degree)
+        .map(|degree| {
+            let mut degree_view = view.clone();
+            // Each image needs its own buffer. Everything else is used by one render at a time,
+            // as the renders run one after another.
+            if degree > 0 {
+                degree_view.out_img = create_tensor(
+                    [img_size.y as usize, img_size.x as usize, out_channels],
+                    device,
+                    client,
+                    img_dtype,
+                );
+            }
+            degree_view.uniforms_buffer = MainBackendBase::int_slice_assign(
+                view.uniforms_buffer.clone(),
+                &[sh_degree_offset..sh_degree_offset + 1],
+                MainBackendBase::int_from_data(TensorData::new(vec![degree as i32], [1]), device),
+            );
+
+            if !view.nothing_visible {
+                tracing::trace_span!("ProjectVisible", sync_burn = true).in_scope(|| {
+                    project_visible(
+                        &setup,
+                        options,
+                        degree,
+                        &num_vis_wg,
+                        &degree_view.uniforms_buffer,
+                        splats.clone(),
+                        &view.global_from_compact_gid,
+                        &view.projected_splats,
+                        view.depth_grads.as_ref(),
+                    );
+                });
+            }
+
+            let (img, _) = rasterize_view(degree_view, options, &mut StageTimer::disabled());
+            let mut shape = img.shape.dims.clone();
+            shape.insert(0, 1);
+            MainBackendBase::float_reshape(img, shape.into())
+        })
+        .collect();
+
+    MainBackendBase::float_cat(imgs, 0)
+}
+
+#[cfg(test)]
+thread_local! {
+    static THREAD_FULL_PIPELINE: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Run `func` with renders on the current thread running the full pipeline for views without
+/// visible splats, like on wasm, instead of skipping to the background.
+#[cfg(test)]
+pub(crate) fn with_thread_full_pipeline<O>(func: impl FnOnce() -> O) -> O {
+    let previous = THREAD_FULL_PIPELINE.replace(true);
+    let out = func();
+    THREAD_FULL_PIPELINE.set(previous);
+    out
+}
+
+/// Whether `num_visible`, the number of visible splats of a view, is zero. This waits for the
+/// projection to finish.
+#[cfg(not(target_family = "wasm"))]
+fn none_visible(num_visible: &CubeTensor<WgpuRuntime>) -> bool {
+    #[cfg(test)]
+    if THREAD_FULL_PIPELINE.get() {
+        return false;
+    }
+    Tensor::<MainBackendBase, 1, Int>::from_primitive(num_visible.clone())
+        .into_data()
+        .to_vec::<i32>()
+        .expect("Failed to read the number of visible splats")
+        == [0]
+}
+
+/// Render the rows `tile_rows` of the tile grid of an `img_size` image. The scratch image is
+/// always the full image, and only the pixels of these rows are written.
+fn render_view(
+    camera: &Camera,
+    img_size: glam::UVec2,
+    tile_rows: Range<u32>,
+    setup: &SplatSetup,
+    scratch: ScratchBuffers,
+    input: RenderInput,
+    bwd_info: bool,
+    options: &RenderOptions,
+    timer: &mut StageTimer,
+) -> (CubeTensor<WgpuRuntime>, RenderAux<MainBackendBase>) {
+    let view = project_view(
+        camera, img_size, tile_rows, setup, scratch, input, bwd_info, options, timer,
+    );
+    rasterize_view(view, options, timer)
+}
+
+/// Project and sort the splats of the rows `tile_rows`, see [`render_view`].
+fn project_view(
+    camera: &Camera,
+    img_size: glam::UVec2,
+    tile_rows: Range<u32>,
+    setup: &SplatSetup,
+    scratch: ScratchBuffers,
+    input: RenderInput,
+    bwd_info: bool,
+    options: &RenderOptions,
+    timer: &mut StageTimer,
+) -> ProjectedView {
+    let projection = project_splats(
+        camera, img_size, tile_rows, setup, &scratch, &input, options, timer,
+    );
+    ProjectedView {
+        background: input.background,
+        pixel_mask: input.pixel_mask,
+        ..sort_projection(
+            projection, img_size, setup, scratch, bwd_info, options, timer,
+        )
+    }
+}
+
+/// Splats projected to a view and compacted, before they're sorted per tile.
+struct Projection {
+    tile_bounds: glam::UVec2,
+    uniforms_buffer: CubeTensor<WgpuRuntime>,
+    global_from_compact_gid: CubeTensor<WgpuRuntime>,
+    /// Camera space depth of each compacted splat.
+    depths: CubeTensor<WgpuRuntime>,
+    projected_splats: CubeTensor<WgpuRuntime>,
+    /// Change of the depth of each compacted splat per pixel, only for per pixel re-sorting.
+    depth_grads: Option<CubeTensor<WgpuRuntime>>,
+    /// Single element tensor with the number of visible splats.
+    num_visible: CubeTensor<WgpuRuntime>,
+    /// Dispatch size for one thread per visible splat.
+    num_vis_wg: CubeTensor<WgpuRuntime>,
+    nothing_visible: bool,
+}
+
+/// Project the splats of the rows `tile_rows`, and compact the visible ones, see
+/// [`project_view`].
+fn project_splats(
+    camera: &Camera,
+    img_size: glam::UVec2,
+    tile_rows: Range<u32>,
+    setup: &SplatSetup,
+    scratch: &ScratchBuffers,
+    input: &RenderInput,
+    options: &RenderOptions,
+    timer: &mut StageTimer,
+) -> Projection {
+    input.validate(img_size);
+    let [means, log_scales, quats, sh_coeffs, opacities] = input.splats();
+    let subset = input.subset.clone();
+    let device = &means.device.clone();
+    let client = means.client.clone();
+    assert!(
+        !tile_rows.is_empty() && tile_rows.len() as u32 <= setup.tile_bounds.y,
+        "Can only render bands up to the size the splats were set up for."
+    );
+    let tile_bounds = uvec2(setup.tile_bounds.x, tile_rows.len() as u32);
+    let total_splats = setup.total_splats;
+
+    assert!(
+        options.depth_peel_layers.is_none() || options.blend_mode == BlendMode::AlphaOver,
+        "Depth peeling only supports blending alpha over."
+    );
+    assert!(
+        options.pixel_resort.is_none()
+            || (options.depth_peel_layers.is_none() && options.blend_mode == BlendMode::AlphaOver),
+        "Per pixel re-sorting only supports blending alpha over, without depth peeling."
+    );
+    assert!(
+        !options.grayscale
+            || (options.output_dtype == OutputDType::F32 && options.tonemap.is_none()),
+        "Grayscale images must be F32, and can't be tonemapped."
+    );
+    let opacity_sh = setup.opacity_sh_degree.is_some();
+    let (sigmoid_opacity, exp_density_opacity) =
+        opacity_activation_flags(options.opacity_activation);
+    let splat_wg = [setup.splat_workgroup_size, 1, 1];
 
     // A note on some confusing naming that'll be used throughout this function:
     // Gaussians are stored in various states of buffers, eg. at the start they're all in one big buffer,
@@ -100,23 +1663,27 @@ pub(crate) fn render_forward(
     // Then, various buffers map between these, which are named x_from_y_gid, eg.
     //  global_from_compact_gid.
 
-    // Tile rendering setup.
-    let sh_degree = sh_degree_from_coeffs(sh_coeffs.shape.dims[1] as u32);
-    let total_splats = means.shape.dims[0];
-    let max_intersects = max_intersections(img_size, total_splats as u32);
+    // Fold the placement of the splats into the view matrix, and move the camera into the space of
+    // the splats, so the stored splats can be projected as is.
+    let transform = options.world_transform;
+    let world_to_local = camera.world_to_local() * transform.to_affine();
+    let camera_position = transform
+        .to_affine()
+        .inverse()
+        .transform_point3(camera.position);
 
     let uniforms = shaders::helpers::RenderUniforms {
-        viewmat: glam::Mat4::from(camera.world_to_local()).to_cols_array_2d(),
-        camera_position: [camera.position.x, camera.position.y, camera.position.z, 0.0],
+        viewmat: glam::Mat4::from(world_to_local).to_cols_array_2d(),
+        camera_position: camera_position.extend(0.0).into(),
         focal: camera.focal(img_size).into(),
-        pixel_center: camera.center(img_size).into(),
+        pixel_center: camera
+            .raster_center_shifted(img_size, options.subpixel_offset)
+            .into(),
         img_size: img_size.into(),
         tile_bounds: tile_bounds.into(),
-        sh_degree,
-        total_splats: total_splats as u32,
-        max_intersects,
-        // Nb: Bit of a hack as these aren't _really_ uniforms but are written to by the shaders.
-        num_visible: 0,
+        tile_row_offset: tile_rows.start,
+        flip_y: u32::from(camera.origin == ImageOrigin::BottomLeft),
+        ..setup.uniforms
     };
 
     // Nb: This contains both static metadata and some dynamic data so can't pass this as metadata to execute. In the future
@@ -125,27 +1692,110 @@ pub(crate) fn render_forward(
 
     let client = &means.client.clone();
 
-    let (global_from_compact_gid, num_visible) = {
-        let global_from_presort_gid = MainBackendBase::int_zeros([total_splats].into(), device);
-        let depths = create_tensor([total_splats], device, client, DType::F32);
-
-        tracing::trace_span!("ProjectSplats", sync_burn = true).in_scope(||
-            // SAFETY: Kernel checked to have no OOB, bounded loops.
-            unsafe {
-            client.execute_unchecked(
-                ProjectSplats::task(),
-                calc_cube_count([total_splats as u32], ProjectSplats::WORKGROUP_SIZE),
-                Bindings::new().with_buffers(
-                vec![
-                    uniforms_buffer.clone().handle.binding(),
-                    means.clone().handle.binding(),
-                    quats.clone().handle.binding(),
-                    log_scales.clone().handle.binding(),
-                    opacities.clone().handle.binding(),
-                    global_from_presort_gid.clone().handle.binding(),
-                    depths.clone().handle.binding(),
-                ]),
+    // Project all splats, and compact the visible ones. The compacted splats aren't sorted by
+    // depth, instead intersections are sorted by tile and depth at once below.
+    let (global_from_compact_gid, depths, num_visible, nothing_visible) = {
+        let global_from_compact_gid = MainBackendBase::int_zeros([total_splats].into(), device);
+        let depths = scratch.depths.clone();
+
+        let bindings = Bindings::new().with_buffers(vec![
+            uniforms_buffer.clone().handle.binding(),
+            means.clone().handle.binding(),
+            quats.clone().handle.binding(),
+            log_scales.clone().handle.binding(),
+            opacities.clone().handle.binding(),
+            global_from_compact_gid.clone().handle.binding(),
+            depths.clone().handle.binding(),
+        ]);
+
+        let num_subset = subset.as_ref().map(|subset| subset.shape.dims[0]);
+
+        // Splats to project, as their global ids and a single element tensor with their count.
+        let culled = if let Some(subset) = subset {
+            let num_culled = MainBackendBase::int_from_data(
+                TensorData::new(vec![subset.shape.dims[0] as i32], [1]),
+                device,
             );
+            Some((subset, num_culled))
+        } else if options.frustum_cull {
+            let culled = timer.stage("CullFrustum", device, || {
+                tracing::trace_span!("CullFrustum", sync_burn = true).in_scope(|| {
+                    cull_frustum(camera, world_to_local, img_size, &means, &log_scales)
+                })
+            });
+            Some(culled)
+        } else {
+            None
+        };
+
+        // When nothing can be visible, the rest of the pipeline is skipped, and only the
+        // background is rasterized. Views looking away from the splats are found after the
+        // projection below.
+        let nothing_visible = total_splats == 0 || num_subset == Some(0);
+
+        // Clip planes, moved into the space the splats are stored in.
+        let clip_planes = (!options.clip_planes.is_empty()).then(|| {
+            let to_world = transform.to_affine();
+            let planes: Vec<f32> = options
+                .clip_planes
+                .iter()
+                .flat_map(|plane| plane.to_local(to_world))
+                .collect();
+            let len = planes.len();
+            MainBackendBase::float_from_data(TensorData::new(planes, [len]), device)
+        });
+        let clip = clip_planes.is_some();
+        let clip_bindings: Vec<_> = clip_planes
+            .into_iter()
+            .map(|planes| planes.handle.binding())
+            .collect();
+
+        timer.stage("ProjectSplats", device, || {
+            tracing::trace_span!("ProjectSplats", sync_burn = true).in_scope(|| {
+                if nothing_visible {
+                    // Nothing to project, so no splats are visible.
+                } else if let Some((global_from_culled_gid, num_culled)) = culled {
+                    let num_culled_wg = scratch.num_culled_wg.clone();
+                    write_dispatch_buffer(num_culled.clone(), splat_wg, &num_culled_wg);
+
+                    // Use safe execution as the dynamic work count isn't verified.
+                    client.execute(
+                        ProjectSplats::task(
+                            options.mip_filter,
+                            true,
+                            opacity_sh,
+                            clip,
+                            sigmoid_opacity,
+                            exp_density_opacity,
+                        )
+                        .with_workgroup_size(splat_wg),
+                        CubeCount::Dynamic(num_culled_wg.handle.binding()),
+                        bindings
+                            .with_buffers(vec![
+                                global_from_culled_gid.handle.binding(),
+                                num_culled.handle.binding(),
+                            ])
+                            .with_buffers(clip_bindings),
+                    );
+                } else {
+                    // SAFETY: Kernel checked to have no OOB, bounded loops.
+                    unsafe {
+                        client.execute_unchecked(
+                            ProjectSplats::task(
+                                options.mip_filter,
+                                false,
+                                opacity_sh,
+                                clip,
+                                sigmoid_opacity,
+                                exp_density_opacity,
+                            )
+                            .with_workgroup_size(splat_wg),
+                            calc_cube_count([total_splats as u32], splat_wg),
+                            bindings.with_buffers(clip_bindings),
+                        );
+                    }
+                }
+            });
         });
 
         // Get just the number of visible splats from the uniforms buffer.
@@ -155,149 +1805,456 @@ pub(crate) fn render_forward(
             &[num_vis_field_offset..num_vis_field_offset + 1],
         );
 
-        let (_, global_from_compact_gid) = tracing::trace_span!("DepthSort", sync_burn = true)
-            .in_scope(|| {
-                // Interpret the depth as a u32. This is fine for a radix sort, as long as the depth > 0.0,
-                // which we know to be the case given how we cull splats.
-                radix_argsort(depths, global_from_presort_gid, &num_visible, 32)
+        // Read back whether any splats are visible, and skip the rest of the pipeline for views
+        // looking away from the splats. Blocking readbacks aren't possible on wasm, where the
+        // passes after this are dispatched indirectly, and so have no work for these views.
+        #[cfg(not(target_family = "wasm"))]
+        let nothing_visible = nothing_visible || none_visible(&num_visible);
+
+        (
+            global_from_compact_gid,
+            depths,
+            num_visible,
+            nothing_visible,
+        )
+    };
+
+    let projected_splats = scratch.projected_splats.clone();
+    // Only re-sorting per pixel needs the depth of splats across the image.
+    let depth_grads = options
+        .pixel_resort
+        .is_some()
+        .then(|| create_tensor([total_splats, 2], device, &client, DType::F32));
+
+    let num_vis_wg = scratch.num_vis_wg.clone();
+    if !nothing_visible {
+        // Create a buffer to determine how many threads to dispatch for all visible splats.
+        write_dispatch_buffer(num_visible.clone(), splat_wg, &num_vis_wg);
+
+        timer.stage("ProjectVisible", device, || {
+            tracing::trace_span!("ProjectVisible", sync_burn = true).in_scope(|| {
+                project_visible(
+                    setup,
+                    options,
+                    setup.sh_degree,
+                    &num_vis_wg,
+                    &uniforms_buffer,
+                    [means, log_scales, quats, sh_coeffs, opacities],
+                    &global_from_compact_gid,
+                    &projected_splats,
+                    depth_grads.as_ref(),
+                );
             });
+        });
+    }
+
+    Projection {
+        tile_bounds,
+        uniforms_buffer,
+        global_from_compact_gid,
+        depths,
+        projected_splats,
+        depth_grads,
+        num_visible,
+        num_vis_wg,
+        nothing_visible,
+    }
+}
+
+/// Merge `fresh`, the projections of the `dirty` splats, into `cache`, and compact the visible
+/// splats of the cache. All splats are dirty when `dirty` is `None`.
+fn merge_projections(
+    cache: ProjectionCache,
+    dirty: Option<CubeTensor<WgpuRuntime>>,
+    fresh: Projection,
+    setup: &SplatSetup,
+) -> (ProjectionCache, Projection) {
+    type B = MainBackendBase;
+    let total_splats = setup.total_splats;
+    if total_splats == 0 {
+        return (cache, fresh);
+    }
+    let device = &fresh.uniforms_buffer.device.clone();
+
+    let mut visible = Tensor::<B, 1, Int>::from_primitive(cache.visible);
+    let mut depths = Tensor::<B, 1>::from_primitive(TensorPrimitive::Float(cache.depths));
+
+    // Forget the old projections of the dirty splats.
+    let is_dirty = dirty.map(|dirty| {
+        let dirty = Tensor::<B, 1, Int>::from_primitive(dirty);
+        let num_dirty = dirty.dims()[0];
+        Tensor::<B, 1, Int>::zeros([total_splats + 1], device)
+            .select_assign(0, dirty, Tensor::ones([num_dirty], device))
+            .equal_elem(1)
+    });
+    if let Some(is_dirty) = &is_dirty {
+        visible = visible.mask_fill(is_dirty.clone(), 0);
+        depths = depths.mask_fill(is_dirty.clone(), 0.0);
+    }
+
+    // Write the fresh projections to the global ids of their splats. Nb: The assignments add to
+    // the zeroed rows, so each row must be written at most once. Rows past the fresh visible
+    // splats go to the extra last row.
+    let dropped = Tensor::<B, 1, Int>::full([total_splats], total_splats as i32, device);
+    let rows = Tensor::<B, 1, Int>::arange(0..total_splats as i64, device);
+    let is_fresh = rows
+        .clone()
+        .lower(Tensor::<B, 1, Int>::from_primitive(fresh.num_visible).expand([total_splats]));
+    let fresh_gid = Tensor::<B, 1, Int>::from_primitive(fresh.global_from_compact_gid);
+    let fresh_target = dropped.clone().mask_where(is_fresh, fresh_gid);
+    visible = visible.select_assign(
+        0,
+        fresh_target.clone(),
+        Tensor::ones([total_splats], device),
+    );
+    depths = depths.select_assign(
+        0,
+        fresh_target.clone(),
+        Tensor::from_primitive(TensorPrimitive::Float(fresh.depths)).slice(s![0..total_splats]),
+    );
 
-        (global_from_compact_gid, num_visible)
+    // Compact the visible splats, in order of their global id.
+    let is_visible = visible.clone().slice(s![0..total_splats]);
+    let cum_visible =
+        Tensor::<B, 1, Int>::from_primitive(prefix_sum(is_visible.clone().into_primitive()));
+    let compact_target = dropped.mask_where(is_visible.equal_elem(1), cum_visible.clone() - 1);
+    let global_from_compact_gid = Tensor::<B, 1, Int>::zeros([total_splats + 1], device)
+        .select_assign(0, compact_target.clone(), rows)
+        .slice(s![0..total_splats]);
+    let compact_depths = Tensor::<B, 1>::zeros([total_splats + 1], device).select_assign(
+        0,
+        compact_target.clone(),
+        depths.clone().slice(s![0..total_splats]),
+    );
+
+    // Merge the fresh rows of a per splat buffer into the cached rows, and compact them.
+    let merge_rows = |cached: CubeTensor<WgpuRuntime>, fresh: CubeTensor<WgpuRuntime>| {
+        let mut cached = Tensor::<B, 2>::from_primitive(TensorPrimitive::Float(cached));
+        let width = cached.dims()[1];
+        if let Some(is_dirty) = &is_dirty {
+            cached = cached.mask_fill(
+                is_dirty
+                    .clone()
+                    .unsqueeze_dim::<2>(1)
+                    .expand([total_splats + 1, width]),
+                0.0,
+            );
+        }
+        let cached = cached.select_assign(
+            0,
+            fresh_target.clone(),
+            Tensor::from_primitive(TensorPrimitive::Float(fresh)).slice(s![0..total_splats]),
+        );
+        let compact = Tensor::<B, 2>::zeros([total_splats + 1, width], device).select_assign(
+            0,
+            compact_target.clone(),
+            cached.clone().slice(s![0..total_splats]),
+        );
+        (
+            cached.into_primitive().tensor(),
+            compact.into_primitive().tensor(),
+        )
+    };
+    let (projected, compact_projected) = merge_rows(cache.projected_splats, fresh.projected_splats);
+    let (depth_grads, compact_depth_grads) = match (cache.depth_grads, fresh.depth_grads) {
+        (Some(cached), Some(fresh)) => {
+            let (cached, compact) = merge_rows(cached, fresh);
+            (Some(cached), Some(compact))
+        }
+        _ => (None, None),
     };
+    let num_visible = cum_visible.slice(s![-1]).into_primitive();
 
-    // Create a buffer of 'projected' splats, that is,
-    // project XY, projected conic, and converted color.
-    let projected_size = size_of::<shaders::helpers::ProjectedSplat>() / size_of::<f32>();
-    let projected_splats =
-        create_tensor::<2, _>([total_splats, projected_size], device, client, DType::F32);
+    // Later stages read the number of visible splats from the uniforms.
+    let num_vis_field_offset = offset_of!(shaders::helpers::RenderUniforms, num_visible) / 4;
+    let uniforms_buffer = B::int_slice_assign(
+        fresh.uniforms_buffer,
+        &[num_vis_field_offset..num_vis_field_offset + 1],
+        num_visible.clone(),
+    );
+    write_dispatch_buffer(
+        num_visible.clone(),
+        [setup.splat_workgroup_size, 1, 1],
+        &fresh.num_vis_wg,
+    );
 
-    // Create a buffer to determine how many threads to dispatch for all visible splats.
-    let num_vis_wg = create_dispatch_buffer(num_visible, [shaders::helpers::MAIN_WG, 1, 1]);
+    let cache = ProjectionCache {
+        key: cache.key,
+        visible: visible.into_primitive(),
+        projected_splats: projected,
+        depth_grads,
+        depths: depths.into_primitive().tensor(),
+    };
+    let projection = Projection {
+        tile_bounds: fresh.tile_bounds,
+        uniforms_buffer,
+        global_from_compact_gid: global_from_compact_gid.into_primitive(),
+        depths: compact_depths.into_primitive().tensor(),
+        projected_splats: compact_projected,
+        depth_grads: compact_depth_grads,
+        num_visible,
+        num_vis_wg: fresh.num_vis_wg,
+        nothing_visible: false,
+    };
+    (cache, projection)
+}
 
-    tracing::trace_span!("ProjectVisible", sync_burn = true).in_scope(|| {
-        // Normal execute as loops in here could be iffy.
-        client.execute(
-            ProjectVisible::task(),
-            CubeCount::Dynamic(num_vis_wg.clone().handle.binding()),
-            Bindings::new().with_buffers(vec![
-                uniforms_buffer.clone().handle.binding(),
-                means.handle.binding(),
-                log_scales.handle.binding(),
-                quats.handle.binding(),
-                sh_coeffs.handle.binding(),
-                opacities.handle.binding(),
-                global_from_compact_gid.handle.clone().binding(),
-                projected_splats.handle.clone().binding(),
-            ]),
+/// Sort the intersections of projected splats and tiles, see [`project_view`].
+fn sort_projection(
+    projection: Projection,
+    img_size: glam::UVec2,
+    setup: &SplatSetup,
+    scratch: ScratchBuffers,
+    bwd_info: bool,
+    options: &RenderOptions,
+    timer: &mut StageTimer,
+) -> ProjectedView {
+    // Depths the sort can't order silently mis-sort, so catch them before sorting.
+    #[cfg(all(feature = "debug_validation", not(target_family = "wasm")))]
+    if !projection.nothing_visible {
+        crate::dim_check::assert_valid_depths(
+            &projection.depths,
+            &projection.global_from_compact_gid,
+            &projection.num_visible,
         );
-    });
+    }
 
-    // Each intersection maps to a gaussian.
-    let (tile_offsets, compact_gid_from_isect) = {
-        let num_tiles = tile_bounds.x * tile_bounds.y;
+    let Projection {
+        tile_bounds,
+        uniforms_buffer,
+        global_from_compact_gid,
+        depths,
+        projected_splats,
+        depth_grads,
+        num_vis_wg,
+        nothing_visible,
+        ..
+    } = projection;
+    let client = &uniforms_buffer.client.clone();
+    let device = &uniforms_buffer.device.clone();
+    let total_splats = setup.total_splats;
+    let max_intersects = setup.max_intersects;
+    let splat_wg = [setup.splat_workgroup_size, 1, 1];
+    let log_depth = matches!(options.depth_key, DepthKey::Log(_));
+
+    let num_tiles = tile_bounds.x * tile_bounds.y;
 
+    // Each intersection maps to a gaussian.
+    let (tile_offsets, compact_gid_from_isect) = if nothing_visible {
+        // Without intersections, every tile is empty.
+        let tile_offsets = ExclusiveScanBuffer::zeros(num_tiles as usize, device).into_offsets();
+        (tile_offsets, scratch.compact_gid_from_isect)
+    } else {
         // Number of intersections per tile. Range ID's are later derived from this
         // by a prefix sum.
-        let tile_intersect_counts =
-            MainBackendBase::int_zeros([num_tiles as usize + 1].into(), device);
-        let splat_intersect_counts = MainBackendBase::int_zeros([total_splats + 1].into(), device);
+        let tile_intersect_counts = ExclusiveScanBuffer::zeros(num_tiles as usize, device);
+        let splat_intersect_counts = ExclusiveScanBuffer::zeros(total_splats, device);
+
+        // Breaking ties lays out the intersections of the splats by their global ids, see
+        // map_gaussian_to_intersects.
+        let stable_ties = options.stable_depth_ties;
+        let tie_buffers = || {
+            stable_ties
+                .then(|| global_from_compact_gid.handle.clone().binding())
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
 
         // First do a prepass to compute the tile counts, then fill in intersection counts.
-        tracing::trace_span!("MapGaussiansToIntersectPrepass", sync_burn = true).in_scope(|| {
-            client.execute(
-                MapGaussiansToIntersect::task(true),
-                CubeCount::Dynamic(num_vis_wg.clone().handle.binding()),
-                Bindings::new().with_buffers(vec![
-                    uniforms_buffer.clone().handle.binding(),
-                    projected_splats.clone().handle.binding(),
-                    splat_intersect_counts.clone().handle.binding(),
-                    tile_intersect_counts.clone().handle.binding(),
-                ]),
+        timer.stage("MapGaussiansToIntersectPrepass", device, || {
+            tracing::trace_span!("MapGaussiansToIntersectPrepass", sync_burn = true).in_scope(
+                || {
+                    client.execute(
+                        MapGaussiansToIntersect::task(true, stable_ties, false)
+                            .with_workgroup_size(splat_wg),
+                        CubeCount::Dynamic(num_vis_wg.clone().handle.binding()),
+                        Bindings::new()
+                            .with_buffers(vec![
+                                uniforms_buffer.clone().handle.binding(),
+                                projected_splats.clone().handle.binding(),
+                                splat_intersect_counts.buffer().clone().handle.binding(),
+                                tile_intersect_counts.buffer().clone().handle.binding(),
+                            ])
+                            .with_buffers(tie_buffers()),
+                    );
+                },
             );
         });
 
         // TODO: Only need to do this up to num_visible gaussians really.
-        let cum_tiles_hit = tracing::trace_span!("PrefixSumGaussHits", sync_burn = true)
-            .in_scope(|| prefix_sum(splat_intersect_counts));
+        let cum_tiles_hit = timer.stage("PrefixSumGaussHits", device, || {
+            tracing::trace_span!("PrefixSumGaussHits", sync_burn = true)
+                .in_scope(|| splat_intersect_counts.scan())
+        });
 
-        let tile_id_from_isect =
-            create_tensor::<1, _>([max_intersects as usize], device, client, DType::I32);
-        let compact_gid_from_isect =
-            create_tensor::<1, _>([max_intersects as usize], device, client, DType::I32);
+        let tile_from_isect = scratch.tile_from_isect;
+        let depth_key_from_isect = scratch.depth_key_from_isect;
+        let compact_gid_from_isect = scratch.compact_gid_from_isect;
 
-        tracing::trace_span!("MapGaussiansToIntersect", sync_burn = true).in_scope(|| {
-            client.execute(
-                MapGaussiansToIntersect::task(false),
-                CubeCount::Dynamic(num_vis_wg.clone().handle.binding()),
-                Bindings::new().with_buffers(vec![
-                    uniforms_buffer.clone().handle.binding(),
-                    projected_splats.clone().handle.binding(),
-                    cum_tiles_hit.clone().handle.binding(),
-                    tile_id_from_isect.clone().handle.binding(),
-                    compact_gid_from_isect.clone().handle.binding(),
-                ]),
-            );
+        timer.stage("MapGaussiansToIntersect", device, || {
+            tracing::trace_span!("MapGaussiansToIntersect", sync_burn = true).in_scope(|| {
+                client.execute(
+                    MapGaussiansToIntersect::task(false, stable_ties, log_depth)
+                        .with_workgroup_size(splat_wg),
+                    CubeCount::Dynamic(num_vis_wg.clone().handle.binding()),
+                    Bindings::new()
+                        .with_buffers(vec![
+                            uniforms_buffer.clone().handle.binding(),
+                            projected_splats.clone().handle.binding(),
+                            cum_tiles_hit.buffer().clone().handle.binding(),
+                            tile_from_isect.clone().handle.binding(),
+                            depth_key_from_isect.clone().handle.binding(),
+                            compact_gid_from_isect.clone().handle.binding(),
+                            depths.handle.binding(),
+                        ])
+                        .with_buffers(tie_buffers()),
+                );
+            });
         });
 
         // Create a tensor containing just the number of intersections.
-        let cum_tiles_hit = Tensor::<MainBackendBase, 1, Int>::from_primitive(cum_tiles_hit);
-        let num_intersections = cum_tiles_hit.slice(s![-1]);
-
-        // We're sorting by tile ID, but we know beforehand what the maximum value
-        // can be. We don't need to sort all the leading 0 bits!
-        let bits = u32::BITS - num_tiles.leading_zeros();
+        let num_intersections = cum_tiles_hit.total();
 
-        let (_, compact_gid_from_isect) = tracing::trace_span!("Tile sort", sync_burn = true)
-            .in_scope(|| {
-                radix_argsort(
-                    tile_id_from_isect,
+        // Sort intersections by a 64 bit key of (tile ID, depth), which gives the intersections
+        // per tile in depth order in a single sort. We know beforehand what the maximum tile ID
+        // can be, so don't need to sort all the leading 0 bits!
+        let sort = options.sort_algorithm.backend(max_intersects);
+        let (_, compact_gid_from_isect) = timer.stage("Tile depth sort", device, || {
+            tracing::trace_span!("Tile depth sort", sync_burn = true).in_scope(|| {
+                sort.argsort_u64(
+                    tile_from_isect,
+                    depth_key_from_isect,
                     compact_gid_from_isect,
-                    &num_intersections.clone().into_primitive(),
-                    bits,
+                    &num_intersections,
+                    tile_sort_bits(num_tiles),
                 )
-            });
+            })
+        });
 
-        let tile_offsets = tracing::trace_span!("PrefixSumTileHits", sync_burn = true)
-            .in_scope(|| prefix_sum(tile_intersect_counts));
+        let tile_offsets = timer.stage("PrefixSumTileHits", device, || {
+            tracing::trace_span!("PrefixSumTileHits", sync_burn = true)
+                .in_scope(|| tile_intersect_counts.scan().into_offsets())
+        });
 
         (tile_offsets, compact_gid_from_isect)
     };
 
-    let _span = tracing::trace_span!("Rasterize", sync_burn = true).entered();
+    ProjectedView {
+        img_size,
+        tile_bounds,
+        uniforms_buffer,
+        projected_splats,
+        depth_grads,
+        tile_offsets,
+        compact_gid_from_isect,
+        global_from_compact_gid,
+        total_splats,
+        bwd_info,
+        nothing_visible,
+        out_img: scratch.out_img,
+        final_index: scratch.final_index,
+        background: None,
+        pixel_mask: None,
+    }
+}
 
-    let out_dim = if bwd_info {
-        4
-    } else {
-        // Channels are packed into 4 bytes, aka one float.
-        1
-    };
+/// Finish projecting the visible splats of a view, including their colors up to `sh_degree`.
+///
+/// `splats` are the means, log scales, quats, sh coefficients and opacities of the splats.
+fn project_visible(
+    setup: &SplatSetup,
+    options: &RenderOptions,
+    sh_degree: u32,
+    num_vis_wg: &CubeTensor<WgpuRuntime>,
+    uniforms_buffer: &CubeTensor<WgpuRuntime>,
+    splats: [CubeTensor<WgpuRuntime>; 5],
+    global_from_compact_gid: &CubeTensor<WgpuRuntime>,
+    projected_splats: &CubeTensor<WgpuRuntime>,
+    depth_grads: Option<&CubeTensor<WgpuRuntime>>,
+) {
+    let client = &uniforms_buffer.client;
+    let mut buffers = vec![uniforms_buffer.handle.clone().binding()];
+    buffers.extend(splats.map(|tensor| tensor.handle.binding()));
+    buffers.extend([
+        global_from_compact_gid.handle.clone().binding(),
+        projected_splats.handle.clone().binding(),
+    ]);
+    buffers.extend(depth_grads.map(|grads| grads.handle.clone().binding()));
 
-    let out_img = create_tensor(
-        [img_size.y as usize, img_size.x as usize, out_dim],
-        device,
-        client,
-        DType::F32,
+    // Normal execute as loops in here could be iffy.
+    let (sigmoid_opacity, exp_density_opacity) =
+        opacity_activation_flags(options.opacity_activation);
+    client.execute(
+        // Splats with only a base color don't need the higher bands compiled in.
+        ProjectVisible::task(
+            options.mip_filter,
+            sh_degree == 0,
+            setup.opacity_sh_degree.is_some(),
+            setup.sh_channel_degrees.is_some(),
+            setup.rgb_colors,
+            setup.no_color,
+            depth_grads.is_some(),
+            sigmoid_opacity,
+            exp_density_opacity,
+        )
+        .with_workgroup_size([setup.splat_workgroup_size, 1, 1]),
+        CubeCount::Dynamic(num_vis_wg.handle.clone().binding()),
+        Bindings::new().with_buffers(buffers),
     );
+}
+
+/// Rasterize a projected view, into the scratch image it was projected with.
+fn rasterize_view(
+    view: ProjectedView,
+    options: &RenderOptions,
+    timer: &mut StageTimer,
+) -> (CubeTensor<WgpuRuntime>, RenderAux<MainBackendBase>) {
+    let ProjectedView {
+        img_size,
+        tile_bounds,
+        uniforms_buffer,
+        projected_splats,
+        depth_grads,
+        tile_offsets,
+        compact_gid_from_isect,
+        global_from_compact_gid,
+        total_splats,
+        bwd_info,
+        nothing_visible: _,
+        out_img,
+        final_index,
+        background,
+        pixel_mask,
+    } = view;
+    let device = &out_img.device.clone();
+    let client = &out_img.client.clone();
+
+    // The optional inputs are always bound, with a dummy buffer in place of the ones that aren't
+    // used, so every input keeps its binding.
+    let background_image = background.is_some();
+    let masked = pixel_mask.is_some();
+    let pixel_resort = options.pixel_resort.is_some();
+    let depth_grads =
+        pixel_resort.then(|| depth_grads.expect("Per pixel re-sorting needs the depth gradients."));
+    let dummy = create_tensor::<1, _>([2], device, client, DType::F32);
+    let optional = |buffer: Option<CubeTensor<WgpuRuntime>>| {
+        buffer.unwrap_or_else(|| dummy.clone()).handle.binding()
+    };
 
     let mut bindings = Bindings::new().with_buffers(vec![
         uniforms_buffer.clone().handle.binding(),
         compact_gid_from_isect.handle.clone().binding(),
         tile_offsets.handle.clone().binding(),
         projected_splats.handle.clone().binding(),
+        optional(background),
+        optional(pixel_mask),
+        optional(depth_grads),
         out_img.handle.clone().binding(),
     ]);
 
-    let (visible, final_index) = if bwd_info {
+    let visible = if bwd_info {
         let visible = MainBackendBase::float_zeros([total_splats].into(), device);
 
-        // Buffer containing the final visible splat per tile.
-        let final_index = create_tensor::<2, _>(
-            [img_size.y as usize, img_size.x as usize],
-            device,
-            client,
-            DType::I32,
-        );
-
         // Add the buffer to the bindings
         bindings = bindings.with_buffers(vec![
             global_from_compact_gid.handle.clone().binding(),
@@ -305,26 +2262,74 @@ pub(crate) fn render_forward(
             visible.handle.clone().binding(),
         ]);
 
-        (visible, final_index)
+        visible
     } else {
-        let visible = create_tensor::<1, _>([1], device, client, DType::F32);
-
-        // Buffer containing the final visible splat per tile.
-        let final_index = create_tensor::<2, _>([1, 1], device, client, DType::I32);
-        (visible, final_index)
+        create_tensor::<1, _>([1], device, client, DType::F32)
     };
 
     // Compile the kernel, including/excluding info for backwards pass.
     // see the BWD_INFO define in the rasterize shader.
-    let raster_task = Rasterize::task(bwd_info);
+    // Packed u32 images are always sRGB, see `ColorSpace`.
+    // Tonemapping happens in linear light, after which the colors are encoded in the output color space.
+    let tonemap = options.tonemap.filter(|_| bwd_info);
+    let linear_output =
+        bwd_info && (options.color_space == ColorSpace::Linear || tonemap.is_some());
+    let f16_output = bwd_info && options.output_dtype == OutputDType::F16;
+    let depth_output = bwd_info && options.render_depth;
+    assert!(
+        !(f16_output && depth_output),
+        "Rendering depth requires F32 output."
+    );
+    assert!(
+        !(f16_output && tonemap.is_some()),
+        "Tonemapping requires F32 output."
+    );
+    let straight_alpha = options.alpha_mode == AlphaMode::Straight;
+    let gray_output = bwd_info && options.grayscale;
+    let raster_task = RasterizeFlags {
+        bwd_info,
+        linear_output,
+        f16_output,
+        depth_output,
+        straight_alpha,
+        background_image,
+        disparity: depth_output && options.depth_as_disparity,
+        depth_peel: options.depth_peel_layers.is_some(),
+        alpha_gamma: options.alpha_gamma.is_some_and(|gamma| gamma.get() != 1.0),
+        pixel_mask: masked,
+        pixel_resort,
+        // Gray images have no color channels to reorder.
+        bgra_output: !gray_output && options.channel_order == ChannelOrder::Bgra,
+        gray_output,
+        blend_add: options.blend_mode == BlendMode::Additive,
+        blend_max: options.blend_mode == BlendMode::Max,
+    }
+    .task();
 
     // Use safe execution as kernel has some looping which might be unbounded (depending on overflow rules?
     // idk, the slow down seems tiny anyway so might as well).
-    client.execute(
-        raster_task,
-        calc_cube_count([img_size.x, img_size.y], Rasterize::WORKGROUP_SIZE),
-        bindings,
-    );
+    timer.stage("Rasterize", device, || {
+        tracing::trace_span!("Rasterize", sync_burn = true).in_scope(|| {
+            client.execute(
+                raster_task,
+                calc_cube_count(
+                    [img_size.x, tile_bounds.y * shaders::helpers::TILE_WIDTH],
+                    Rasterize::WORKGROUP_SIZE,
+                ),
+                bindings,
+            );
+        });
+    });
+
+    let out_img = if let Some(operator) = tonemap {
+        timer.stage("Tonemap", device, || {
+            tracing::trace_span!("Tonemap", sync_burn = true).in_scope(|| {
+                tonemap_image(out_img, operator, options.alpha_mode, options.color_space)
+            })
+        })
+    } else {
+        out_img
+    };
 
     (
         out_img,
@@ -1,19 +1,94 @@
-use std::mem::offset_of;
+use std::mem::{offset_of, size_of};
 
 use burn::{
     prelude::Backend,
     tensor::{
-        ElementConversion, Int, Tensor, TensorMetadata,
+        ElementConversion, Int, Tensor, TensorData, TensorMetadata, TensorPrimitive,
         ops::{FloatTensor, IntTensor},
         s,
     },
 };
 
+use brush_sort::MAX_SORT_KEYS;
+
 use crate::{
-    GAUSSIANS_UPPER_BOUND, INTERSECTS_UPPER_BOUND,
+    GAUSSIANS_UPPER_BOUND, RenderStats,
     shaders::{self, helpers::TILE_WIDTH},
 };
 
+/// Number of intersections in each tile of a render.
+///
+/// Each tile is rasterized by one workgroup, so tiles with many intersections are the hot-spots
+/// that dominate rasterization time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileOccupancy {
+    /// Number of tiles horizontally and vertically. Tiles are `TILE_WIDTH` pixels wide.
+    pub tile_bounds: glam::UVec2,
+    /// Number of intersections of each tile, row by row, starting at the top left.
+    pub counts: Vec<u32>,
+}
+
+impl TileOccupancy {
+    /// Number of intersections of the tile at column `x` and row `y`.
+    pub fn get(&self, x: u32, y: u32) -> u32 {
+        self.counts[(y * self.tile_bounds.x + x) as usize]
+    }
+
+    /// Position and number of intersections of the most occupied tile.
+    pub fn max_tile(&self) -> Option<(glam::UVec2, u32)> {
+        let (index, &count) = self
+            .counts
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, count)| **count)?;
+        let index = index as u32;
+        Some((
+            glam::uvec2(index % self.tile_bounds.x, index / self.tile_bounds.x),
+            count,
+        ))
+    }
+
+    /// Total number of intersections over all tiles.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().map(|&c| c as u64).sum()
+    }
+
+    /// Count tiles in `num_bins` bins of equal width, spanning 0 to the most occupied tile.
+    pub fn histogram(&self, num_bins: usize) -> Vec<u32> {
+        assert!(num_bins > 0, "Histogram needs at least one bin");
+        let max = self.counts.iter().copied().max().unwrap_or(0) as u64;
+        let mut bins = vec![0; num_bins];
+        for &count in &self.counts {
+            let bin = if max == 0 {
+                0
+            } else {
+                // The most occupied tiles end up in the last bin.
+                (count as u64 * num_bins as u64 / max).min(num_bins as u64 - 1) as usize
+            };
+            bins[bin] += 1;
+        }
+        bins
+    }
+}
+
+/// A visible splat as projected to the image, read back from [`RenderAux::projected_splats`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VisibleSplat {
+    /// Index of the splat in the rendered splats.
+    pub global_id: u32,
+    /// Center of the splat, in pixels.
+    pub center: glam::Vec2,
+    /// Upper triangle (xx, xy, yy) of the inverse of the 2D covariance, in pixels. This includes
+    /// the blur that's added to every splat.
+    pub conic: glam::Vec3,
+    /// Color of the splat as seen from the camera.
+    pub color: glam::Vec3,
+    /// Opacity of the splat, after the mip filter if enabled.
+    pub opacity: f32,
+    /// Camera space depth of the splat.
+    pub depth: f32,
+}
+
 #[derive(Debug, Clone)]
 pub struct RenderAux<B: Backend> {
     /// The packed projected splat information, see `ProjectedSplat` in helpers.wgsl
@@ -52,6 +127,201 @@ impl<B: Backend> RenderAux<B> {
         Tensor::from_primitive(self.uniforms_buffer.clone()).slice(s![num_vis_field_offset])
     }
 
+    /// Number of splats that were culled as their mean, scale, rotation or opacity has a NaN or
+    /// infinite value.
+    ///
+    /// These splats are left out of the render, so a few broken splats, eg. after a training step
+    /// diverged, don't corrupt the rest of the image.
+    pub fn num_non_finite(&self) -> Tensor<B, 1, Int> {
+        let field_offset = offset_of!(shaders::helpers::RenderUniforms, num_non_finite) / 4;
+        Tensor::from_primitive(self.uniforms_buffer.clone()).slice(s![field_offset])
+    }
+
+    /// Global indices of the splats that were visible in this render, ie. that passed culling.
+    ///
+    /// Indices are in no particular order. This blocks until the data is read back, which isn't
+    /// possible on wasm, use [`Self::visible_global_ids_async`] there instead.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn visible_global_ids(&self) -> Vec<u32> {
+        let num_visible = self.num_visible().into_scalar().elem::<i32>();
+        let global_from_compact_gid = self.global_from_compact_gid().into_data();
+        visible_ids_from_data(num_visible, global_from_compact_gid)
+    }
+
+    /// Global indices of the splats that were visible in this render, without blocking. See
+    /// [`Self::visible_global_ids`].
+    pub async fn visible_global_ids_async(&self) -> Vec<u32> {
+        let num_visible = self.num_visible().into_scalar_async().await.elem::<i32>();
+        let global_from_compact_gid = self.global_from_compact_gid().into_data_async().await;
+        visible_ids_from_data(num_visible, global_from_compact_gid)
+    }
+
+    /// Read back the number of visible splats and intersections of this render. See
+    /// [`RenderStats`], the kernel timings are left empty.
+    ///
+    /// This blocks until the data is read back.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn read_stats(&self) -> RenderStats {
+        let tile_offsets = Tensor::<B, 1, Int>::from_primitive(self.tile_offsets.clone())
+            .into_data()
+            .to_vec::<i32>()
+            .expect("Failed to fetch tile offsets");
+        let max_tile_intersections = tile_offsets
+            .windows(2)
+            .map(|w| (w[1] - w[0]).max(0) as u32)
+            .max()
+            .unwrap_or(0);
+
+        RenderStats {
+            num_visible: self.num_visible().into_scalar().elem::<i32>().max(0) as u32,
+            num_intersections: tile_offsets.last().copied().unwrap_or(0).max(0) as u32,
+            num_tiles: tile_offsets.len().saturating_sub(1) as u32,
+            max_tile_intersections,
+            num_non_finite: self.num_non_finite().into_scalar().elem::<i32>().max(0) as u32,
+            kernel_timings: vec![],
+            gpu_timings: false,
+        }
+    }
+
+    /// Read back the number of intersections of each tile. See [`TileOccupancy`].
+    ///
+    /// This blocks until the data is read back, which isn't possible on wasm, use
+    /// [`Self::read_tile_occupancy_async`] there instead.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn read_tile_occupancy(&self) -> TileOccupancy {
+        tile_occupancy_from_data(
+            &Tensor::<B, 1, Int>::from_primitive(self.uniforms_buffer.clone()).into_data(),
+            &Tensor::<B, 1, Int>::from_primitive(self.tile_offsets.clone()).into_data(),
+        )
+    }
+
+    /// Read back the number of intersections of each tile, without blocking. See
+    /// [`Self::read_tile_occupancy`].
+    pub async fn read_tile_occupancy_async(&self) -> TileOccupancy {
+        tile_occupancy_from_data(
+            &Tensor::<B, 1, Int>::from_primitive(self.uniforms_buffer.clone())
+                .into_data_async()
+                .await,
+            &Tensor::<B, 1, Int>::from_primitive(self.tile_offsets.clone())
+                .into_data_async()
+                .await,
+        )
+    }
+
+    /// Read back the number of tiles each splat intersects, indexed by the global id of the
+    /// splat. Culled splats hit no tiles.
+    ///
+    /// Every intersection is blended by all pixels of its tile, so this is a rough estimate of
+    /// the cost of each splat, eg. to find the most expensive splats to prune. Multiply by the
+    /// opacity to favor splats that contribute little. The counts sum to
+    /// [`Self::num_intersections`].
+    ///
+    /// This blocks until the data is read back, which isn't possible on wasm, use
+    /// [`Self::read_splat_tile_hits_async`] there instead.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn read_splat_tile_hits(&self) -> Vec<u32> {
+        let num_intersections = self.num_intersections().into_scalar().elem::<i32>();
+        let compact_gid_from_isect = self
+            .compact_gid_from_isect(num_intersections)
+            .map(Tensor::into_data);
+        splat_tile_hits_from_data(
+            compact_gid_from_isect.as_ref(),
+            &self.global_from_compact_gid().into_data(),
+        )
+    }
+
+    /// Read back the number of tiles each splat intersects, without blocking. See
+    /// [`Self::read_splat_tile_hits`].
+    pub async fn read_splat_tile_hits_async(&self) -> Vec<u32> {
+        let num_intersections = self
+            .num_intersections()
+            .into_scalar_async()
+            .await
+            .elem::<i32>();
+        let compact_gid_from_isect = match self.compact_gid_from_isect(num_intersections) {
+            Some(ids) => Some(ids.into_data_async().await),
+            None => None,
+        };
+        splat_tile_hits_from_data(
+            compact_gid_from_isect.as_ref(),
+            &self.global_from_compact_gid().into_data_async().await,
+        )
+    }
+
+    /// The first `num_intersections` entries of `compact_gid_from_isect`, the rest is stale.
+    /// `None` without intersections.
+    fn compact_gid_from_isect(&self, num_intersections: i32) -> Option<Tensor<B, 1, Int>> {
+        let compact_gid_from_isect: Tensor<B, 1, Int> =
+            Tensor::from_primitive(self.compact_gid_from_isect.clone());
+        let len = (num_intersections.max(0) as usize).min(compact_gid_from_isect.dims()[0]);
+        (len > 0).then(|| compact_gid_from_isect.slice(s![0..len]))
+    }
+
+    /// Visualize the number of intersections of each tile as an image, without reading back
+    /// to the CPU. See [`TileOccupancy`] to inspect the counts themselves.
+    ///
+    /// Counts are normalized by the most occupied tile, and mapped through a black-red-yellow-white
+    /// heat colormap. The image is `[tiles_y, tiles_x, 3]`, or `[h, w, 3]` with each tile
+    /// covering its pixels if `full_res` is set. `img_size` is the size of the render.
+    #[cfg(feature = "debug_heatmap")]
+    pub fn tile_heatmap(&self, img_size: glam::UVec2, full_res: bool) -> Tensor<B, 3> {
+        let tile_bounds = crate::render::calc_tile_bounds(img_size);
+        let [tx, ty] = [tile_bounds.x as usize, tile_bounds.y as usize];
+
+        let tile_offsets: Tensor<B, 1, Int> = Tensor::from_primitive(self.tile_offsets.clone());
+        let counts = (tile_offsets.clone().slice(s![1..=tx * ty])
+            - tile_offsets.slice(s![0..tx * ty]))
+        .float()
+        .clamp_min(0.0);
+        let t = counts.clone() / counts.max().clamp_min(1.0);
+
+        let channel = |start: f32| (t.clone() * 3.0 - start).clamp(0.0, 1.0);
+        let heatmap = Tensor::stack::<2>(vec![channel(0.0), channel(1.0), channel(2.0)], 1)
+            .reshape([ty, tx, 3]);
+        if !full_res {
+            return heatmap;
+        }
+
+        let tile = TILE_WIDTH as usize;
+        let [w, h] = [img_size.x as usize, img_size.y as usize];
+        heatmap
+            .reshape([ty, 1, tx, 1, 3])
+            .repeat_dim(1, tile)
+            .repeat_dim(3, tile)
+            .reshape([ty * tile, tx * tile, 3])
+            .slice([0..h, 0..w])
+    }
+
+    /// Read back the projected splats of this render, in the order they were compacted.
+    ///
+    /// This blocks until the data is read back, which isn't possible on wasm, use
+    /// [`Self::read_projected_splats_async`] there instead.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn read_projected_splats(&self) -> Vec<VisibleSplat> {
+        visible_splats_from_data(
+            self.num_visible().into_scalar().elem::<i32>(),
+            &Tensor::<B, 2>::from_primitive(TensorPrimitive::Float(self.projected_splats.clone()))
+                .into_data(),
+            &self.global_from_compact_gid().into_data(),
+        )
+    }
+
+    /// Read back the projected splats of this render, without blocking. See
+    /// [`Self::read_projected_splats`].
+    pub async fn read_projected_splats_async(&self) -> Vec<VisibleSplat> {
+        visible_splats_from_data(
+            self.num_visible().into_scalar_async().await.elem::<i32>(),
+            &Tensor::<B, 2>::from_primitive(TensorPrimitive::Float(self.projected_splats.clone()))
+                .into_data_async()
+                .await,
+            &self.global_from_compact_gid().into_data_async().await,
+        )
+    }
+
+    fn global_from_compact_gid(&self) -> Tensor<B, 1, Int> {
+        Tensor::from_primitive(self.global_from_compact_gid.clone())
+    }
+
     pub fn debug_assert_valid(&self) {
         let num_intersects: Tensor<B, 1, Int> = self.num_intersections();
         let compact_gid_from_isect: Tensor<B, 1, Int> =
@@ -63,8 +333,8 @@ impl<B: Backend> RenderAux<B> {
         let num_visible = num_visible.into_scalar().elem::<i32>();
 
         assert!(
-            num_intersections >= 0 && num_intersections < INTERSECTS_UPPER_BOUND as i32,
-            "Too many intersections, Brush currently can't handle this. {num_intersections} > {INTERSECTS_UPPER_BOUND}"
+            num_intersections >= 0 && num_intersections < MAX_SORT_KEYS as i32,
+            "Too many intersections, Brush currently can't handle this. {num_intersections} > {MAX_SORT_KEYS}"
         );
 
         assert!(
@@ -156,3 +426,97 @@ impl<B: Backend> RenderAux<B> {
         }
     }
 }
+
+fn visible_ids_from_data(num_visible: i32, global_from_compact_gid: TensorData) -> Vec<u32> {
+    // Only the first num_visible entries are written, the rest of the buffer is stale.
+    let mut ids = global_from_compact_gid
+        .to_vec::<i32>()
+        .expect("Failed to fetch global_from_compact_gid");
+    ids.truncate(num_visible.max(0) as usize);
+    ids.into_iter().map(|id| id as u32).collect()
+}
+
+fn tile_occupancy_from_data(uniforms: &TensorData, tile_offsets: &TensorData) -> TileOccupancy {
+    let uniforms = uniforms.to_vec::<i32>().expect("Failed to fetch uniforms");
+    let bounds_offset = offset_of!(shaders::helpers::RenderUniforms, tile_bounds) / 4;
+    let tile_bounds = glam::uvec2(
+        uniforms[bounds_offset] as u32,
+        uniforms[bounds_offset + 1] as u32,
+    );
+
+    let tile_offsets = tile_offsets
+        .to_vec::<i32>()
+        .expect("Failed to fetch tile offsets");
+    let num_tiles = (tile_bounds.x * tile_bounds.y) as usize;
+    let counts = tile_offsets
+        .windows(2)
+        .take(num_tiles)
+        .map(|w| (w[1] - w[0]).max(0) as u32)
+        .collect();
+
+    TileOccupancy {
+        tile_bounds,
+        counts,
+    }
+}
+
+fn splat_tile_hits_from_data(
+    compact_gid_from_isect: Option<&TensorData>,
+    global_from_compact_gid: &TensorData,
+) -> Vec<u32> {
+    let global_from_compact_gid = global_from_compact_gid
+        .to_vec::<i32>()
+        .expect("Failed to fetch global_from_compact_gid");
+    let mut hits = vec![0; global_from_compact_gid.len()];
+    let compact_gid_from_isect = compact_gid_from_isect.map_or_else(Vec::new, |ids| {
+        ids.to_vec::<i32>()
+            .expect("Failed to fetch compact_gid_from_isect")
+    });
+    for compact_gid in compact_gid_from_isect {
+        let global_gid = global_from_compact_gid[compact_gid as usize];
+        hits[global_gid as usize] += 1;
+    }
+    hits
+}
+
+/// Unpack the `ProjectedSplat` records of the first `num_visible` splats.
+///
+/// Each record is 10 floats: the center (x, y) at 0, the conic (xx, xy, yy) at 2, the color (r, g,
+/// b) at 5, the opacity at 8 and the depth at 9. The offsets are taken from the struct generated
+/// from helpers.wgsl, so they follow any change to the shader.
+fn visible_splats_from_data(
+    num_visible: i32,
+    projected_splats: &TensorData,
+    global_from_compact_gid: &TensorData,
+) -> Vec<VisibleSplat> {
+    use shaders::helpers::ProjectedSplat;
+
+    let field = |offset: usize| offset / size_of::<f32>();
+    let stride = field(size_of::<ProjectedSplat>());
+    let xy = field(offset_of!(ProjectedSplat, xy_x));
+    let conic = field(offset_of!(ProjectedSplat, conic_x));
+    let color = field(offset_of!(ProjectedSplat, color_r));
+    let opacity = field(offset_of!(ProjectedSplat, color_a));
+    let depth = field(offset_of!(ProjectedSplat, depth));
+
+    let projected = projected_splats
+        .to_vec::<f32>()
+        .expect("Failed to fetch projected splats");
+    let global_ids = global_from_compact_gid
+        .to_vec::<i32>()
+        .expect("Failed to fetch global_from_compact_gid");
+
+    projected
+        .chunks_exact(stride)
+        .zip(global_ids)
+        .take(num_visible.max(0) as usize)
+        .map(|(splat, global_id)| VisibleSplat {
+            global_id: global_id as u32,
+            center: glam::Vec2::from_slice(&splat[xy..]),
+            conic: glam::Vec3::from_slice(&splat[conic..]),
+            color: glam::Vec3::from_slice(&splat[color..]),
+            opacity: splat[opacity],
+            depth: splat[depth],
+        })
+        .collect()
+}
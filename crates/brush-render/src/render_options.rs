@@ -0,0 +1,834 @@
+use std::num::NonZeroU32;
+
+use crate::gaussian_splats::OpacityActivation;
+
+/// An option value splats can't be rendered with.
+#[derive(Debug, Clone, Copy, PartialEq, thiserror::Error)]
+pub enum InvalidRenderOption {
+    #[error("The transmittance threshold must be in [0, 1), got {0}.")]
+    TransmittanceThreshold(f32),
+    #[error("The log depth range must have 0 < near < far, got {near} to {far}.")]
+    LogDepthRange { near: f32, far: f32 },
+    #[error("Clip planes must have a finite, non-zero normal, got {0}.")]
+    ClipPlaneNormal(glam::Vec3),
+    #[error("Per pixel re-sorting needs 1 to {MAX_RESORT_WINDOW} splats, got {0}.")]
+    ResortWindow(u32),
+    #[error("The alpha gamma must be finite and positive, got {0}.")]
+    AlphaGamma(f32),
+    #[error("The scale of the world transform must be finite and positive, got {0}.")]
+    WorldScale(f32),
+}
+
+/// Color space of rendered images.
+///
+/// Splat colors are trained against sRGB encoded images, so they are stored in sRGB space.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ColorSpace {
+    /// Output colors as stored in the splats, ready to display on screen.
+    #[default]
+    Srgb,
+    /// Decode colors to linear light at the end of rasterization, for compositing or HDR
+    /// workflows.
+    ///
+    /// This only applies to float images. Packed u32 images always contain sRGB encoded bytes.
+    Linear,
+}
+
+/// How the colors and opacities of splats are clamped before blending.
+///
+/// Colors evaluated from spherical harmonics aren't bounded, and neither are opacities of view
+/// dependent opacity, so imported scenes can have splats brighter than white, which blend into
+/// overbright pixels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ClampPolicy {
+    /// Only clamp negative colors to zero, when blending. This is how splats are trained.
+    #[default]
+    None,
+    /// Clamp colors and opacities to `[0, 1]`.
+    Clamp,
+    /// Roll off colors above 0.8 smoothly towards one, which keeps some detail in highlights that
+    /// a hard clamp flattens, and clamp opacities to `[0, 1]`.
+    SoftClip,
+}
+
+/// How the splats of a pixel are composited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BlendMode {
+    /// Blend splats front to back over each other, so nearer splats occlude the ones behind
+    /// them. This is how splats are trained.
+    #[default]
+    AlphaOver,
+    /// Sum the colors of all splats, premultiplied by their alpha, without occlusion. Useful to
+    /// visualize densities or emissive scenes, eg. as a heat map of how many splats cover each
+    /// pixel.
+    Additive,
+    /// Keep the brightest color of any splat, premultiplied by its alpha, per channel.
+    Max,
+}
+
+/// Basis of the view directions spherical harmonics are evaluated at.
+///
+/// Spherical harmonics give the color of a splat for the direction it is seen from, the
+/// normalized direction from the camera to the mean of the splat. Which axes that direction is
+/// expressed in changes the colors, so scenes only look right in the basis their coefficients
+/// were trained in. Mismatched bases show up as subtly wrong view dependent tints, while the base
+/// colors stay the same.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ShBasis {
+    /// Directions in the space the splats are stored in, so view dependent colors rotate along
+    /// with [`RenderOptions::world_transform`]. Without a world transform, this is exactly the
+    /// convention of the 3DGS reference implementation, and of splats trained by Brush.
+    #[default]
+    Splats,
+    /// Directions in world space, after the world transform, so view dependent colors stay fixed
+    /// in the world when the splats are rotated. Without a world transform, this is the same as
+    /// [`Self::Splats`].
+    World,
+    /// Directions in the space of the splats, rotated by this rotation. This fixes scenes whose
+    /// means and rotations were converted to another axis convention without rotating their
+    /// coefficients, eg. a Y up to a Z up world: pass the rotation from the space of the splats
+    /// back to the space the scene was trained in.
+    Rotated(glam::Quat),
+}
+
+impl ShBasis {
+    /// Rotation from the space of the splats to this basis.
+    pub(crate) fn rotation(self, transform: &WorldTransform) -> glam::Quat {
+        match self {
+            Self::Splats => glam::Quat::IDENTITY,
+            Self::World => transform.rotation.normalize(),
+            Self::Rotated(rotation) => rotation.normalize(),
+        }
+    }
+}
+
+/// Algorithm that sorts the intersections of splats and tiles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SortAlgorithm {
+    /// Pick the fastest algorithm for the most intersections the render allocates buffers for,
+    /// see [`brush_sort::sort_backend_for`].
+    #[default]
+    Auto,
+    /// Always use the multi pass radix sort.
+    Radix,
+    /// Use the single workgroup bitonic sort, which is faster for very few intersections. Renders
+    /// with room for more than [`brush_sort::BITONIC_MAX_KEYS`] intersections still use the radix
+    /// sort.
+    Bitonic,
+}
+
+impl SortAlgorithm {
+    /// The sort backend to sort at most `max_keys` keys with.
+    pub fn backend(self, max_keys: u32) -> &'static dyn brush_sort::SortBackend {
+        match self {
+            Self::Auto => brush_sort::sort_backend_for(max_keys),
+            Self::Bitonic if max_keys <= brush_sort::BITONIC_MAX_KEYS => &brush_sort::BitonicSort,
+            Self::Radix | Self::Bitonic => &brush_sort::RadixSort,
+        }
+    }
+}
+
+/// How the depths of splats are turned into the keys intersections are sorted by.
+///
+/// Intersections are sorted on 64 bit keys, with the tile id as high half and a 32 bit depth key
+/// as low half, so depths are sorted with the same precision at any image size.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DepthKey {
+    /// Sort on the bits of the float depth, which has the same relative precision at any depth.
+    #[default]
+    Float,
+    /// Sort on the log of the depth, spread over all bits of the key over a [`LogDepthRange`].
+    ///
+    /// Float depths waste most of their bits on exponents the scene never uses, which shows when
+    /// [`RenderOptions::stable_depth_ties`] quantizes the key. A log key over the depth range of
+    /// the scene keeps about 1e-6 relative precision for a range of a few orders of magnitude,
+    /// even with stable ties. Depths outside the range are clamped to it, so splats beyond it
+    /// aren't sorted among each other.
+    Log(LogDepthRange),
+}
+
+impl DepthKey {
+    /// Sort on the log of depths between `near` and `far`, see [`Self::Log`].
+    pub fn log(near: f32, far: f32) -> Result<Self, InvalidRenderOption> {
+        LogDepthRange::new(near, far).map(Self::Log)
+    }
+}
+
+/// Range of depths a [`DepthKey::Log`] spreads its bits over.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "[f32; 2]", into = "[f32; 2]")
+)]
+pub struct LogDepthRange {
+    near: f32,
+    far: f32,
+}
+
+impl LogDepthRange {
+    /// The depths from `near` to `far`, which needs `0 < near < far`.
+    pub fn new(near: f32, far: f32) -> Result<Self, InvalidRenderOption> {
+        if near > 0.0 && far > near && far.is_finite() {
+            Ok(Self { near, far })
+        } else {
+            Err(InvalidRenderOption::LogDepthRange { near, far })
+        }
+    }
+
+    pub fn near(&self) -> f32 {
+        self.near
+    }
+
+    pub fn far(&self) -> f32 {
+        self.far
+    }
+}
+
+impl TryFrom<[f32; 2]> for LogDepthRange {
+    type Error = InvalidRenderOption;
+
+    fn try_from([near, far]: [f32; 2]) -> Result<Self, Self::Error> {
+        Self::new(near, far)
+    }
+}
+
+impl From<LogDepthRange> for [f32; 2] {
+    fn from(range: LogDepthRange) -> Self {
+        [range.near, range.far]
+    }
+}
+
+/// Data type of float images.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OutputDType {
+    #[default]
+    F32,
+    /// Write half floats, which halves the size of the image. This only applies to float
+    /// images, and isn't supported when rendering differentiably.
+    F16,
+}
+
+/// How the alpha channel relates to the color channels of rendered images.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AlphaMode {
+    /// Colors are multiplied by alpha, which is how splats are blended. This is the same as
+    /// compositing over a black background.
+    ///
+    /// Training compares premultiplied renders against the ground truth, after compositing them
+    /// over the background, so this is the mode training expects.
+    #[default]
+    Premultiplied,
+    /// Colors are divided by alpha, giving the color of the splats regardless of their coverage.
+    ///
+    /// Pixels without any coverage are black. This isn't supported when rendering differentiably.
+    Straight,
+}
+
+/// Order of the color channels of each pixel of rendered images.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChannelOrder {
+    /// Red, green, blue, alpha.
+    #[default]
+    Rgba,
+    /// Blue, green, red, alpha, as many swapchains and texture formats expect, eg.
+    /// `Bgra8Unorm`.
+    Bgra,
+}
+
+/// Curve which maps HDR colors in linear light to the displayable range `[0, 1]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TonemapOperator {
+    /// `c / (1 + c)`, which compresses highlights smoothly but flattens contrast.
+    Reinhard,
+    /// A fit of the ACES filmic curve, with more contrast, which clips very bright colors to
+    /// white.
+    Aces,
+}
+
+/// Transmittance at which pixels stop blending splats, if no other threshold is set. This matches
+/// the original 3DGS implementation.
+pub const DEFAULT_TRANSMITTANCE_THRESHOLD: f32 = 1e-4;
+
+/// Transmittance at which pixels stop blending splats, see
+/// [`RenderOptions::transmittance_threshold`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "f32", into = "f32")
+)]
+pub struct TransmittanceThreshold(f32);
+
+impl TransmittanceThreshold {
+    /// The threshold `value`, which has to be in `[0, 1)`.
+    pub fn new(value: f32) -> Result<Self, InvalidRenderOption> {
+        if (0.0..1.0).contains(&value) {
+            Ok(Self(value))
+        } else {
+            Err(InvalidRenderOption::TransmittanceThreshold(value))
+        }
+    }
+
+    pub fn get(self) -> f32 {
+        self.0
+    }
+}
+
+impl TryFrom<f32> for TransmittanceThreshold {
+    type Error = InvalidRenderOption;
+
+    fn try_from(value: f32) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl From<TransmittanceThreshold> for f32 {
+    fn from(threshold: TransmittanceThreshold) -> Self {
+        threshold.0
+    }
+}
+
+/// Power the alpha of splats is raised to before blending, see [`RenderOptions::alpha_gamma`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "f32", into = "f32")
+)]
+pub struct AlphaGamma(f32);
+
+impl AlphaGamma {
+    /// The gamma `value`, which has to be finite and positive.
+    pub fn new(value: f32) -> Result<Self, InvalidRenderOption> {
+        if value.is_finite() && value > 0.0 {
+            Ok(Self(value))
+        } else {
+            Err(InvalidRenderOption::AlphaGamma(value))
+        }
+    }
+
+    pub fn get(self) -> f32 {
+        self.0
+    }
+}
+
+impl TryFrom<f32> for AlphaGamma {
+    type Error = InvalidRenderOption;
+
+    fn try_from(value: f32) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl From<AlphaGamma> for f32 {
+    fn from(gamma: AlphaGamma) -> Self {
+        gamma.0
+    }
+}
+
+/// Most front splats of a pixel that can be re-sorted, see [`RenderOptions::pixel_resort`].
+pub const MAX_RESORT_WINDOW: u32 = crate::shaders::helpers::MAX_PIXEL_RESORT;
+
+/// Number of front splats of each pixel to re-sort, see [`RenderOptions::pixel_resort`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "u32", into = "u32")
+)]
+pub struct ResortWindow(u32);
+
+impl ResortWindow {
+    /// The window of `splats` splats, which has to be 1 to [`MAX_RESORT_WINDOW`].
+    pub fn new(splats: u32) -> Result<Self, InvalidRenderOption> {
+        if (1..=MAX_RESORT_WINDOW).contains(&splats) {
+            Ok(Self(splats))
+        } else {
+            Err(InvalidRenderOption::ResortWindow(splats))
+        }
+    }
+
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl TryFrom<u32> for ResortWindow {
+    type Error = InvalidRenderOption;
+
+    fn try_from(splats: u32) -> Result<Self, Self::Error> {
+        Self::new(splats)
+    }
+}
+
+impl From<ResortWindow> for u32 {
+    fn from(window: ResortWindow) -> Self {
+        window.0
+    }
+}
+
+/// Weights of the red, green and blue channel in the gray value of a color, see
+/// [`RenderOptions::grayscale`]. These are the Rec. 709 luma weights, like the `image` crate
+/// uses, and sum to one, so gray colors keep their value.
+pub const LUMA_WEIGHTS: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// A rectangle of pixels, from `min` up to but excluding `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PixelRect {
+    pub min: glam::UVec2,
+    pub max: glam::UVec2,
+}
+
+impl PixelRect {
+    pub fn new(min: glam::UVec2, max: glam::UVec2) -> Self {
+        Self { min, max }
+    }
+
+    /// The rectangle covering a whole image of `img_size`.
+    pub fn full(img_size: glam::UVec2) -> Self {
+        Self::new(glam::UVec2::ZERO, img_size)
+    }
+
+    /// The part of this rectangle inside an image of `img_size`.
+    pub fn clamp_to(self, img_size: glam::UVec2) -> Self {
+        let max = self.max.min(img_size);
+        Self::new(self.min.min(max), max)
+    }
+
+    /// The same pixels, with rows counted from the other edge of an image `height` pixels tall.
+    pub fn flip_y(self, height: u32) -> Self {
+        Self::new(
+            glam::uvec2(self.min.x, height.saturating_sub(self.max.y)),
+            glam::uvec2(self.max.x, height.saturating_sub(self.min.y)),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpge(self.max).any()
+    }
+}
+
+/// A plane in world space, which hides the splats on one side of it.
+///
+/// Splats are kept when `normal.dot(mean) >= offset`, so the normal points towards the splats
+/// that stay visible. The normal has to be finite and non-zero.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "[f32; 4]", into = "[f32; 4]")
+)]
+pub struct ClipPlane {
+    normal: glam::Vec3,
+    offset: f32,
+}
+
+impl ClipPlane {
+    pub fn new(normal: glam::Vec3, offset: f32) -> Result<Self, InvalidRenderOption> {
+        if normal.is_finite() && normal != glam::Vec3::ZERO {
+            Ok(Self { normal, offset })
+        } else {
+            Err(InvalidRenderOption::ClipPlaneNormal(normal))
+        }
+    }
+
+    /// The plane through `point`, keeping the splats on the side `normal` points to.
+    pub fn through_point(
+        point: glam::Vec3,
+        normal: glam::Vec3,
+    ) -> Result<Self, InvalidRenderOption> {
+        Self::new(normal, normal.dot(point))
+    }
+
+    pub fn normal(&self) -> glam::Vec3 {
+        self.normal
+    }
+
+    pub fn offset(&self) -> f32 {
+        self.offset
+    }
+
+    /// Whether a splat with its mean at `point` is kept.
+    pub fn keeps(&self, point: glam::Vec3) -> bool {
+        self.normal.dot(point) >= self.offset
+    }
+
+    /// The plane in the space splats are stored in, as the normal and offset, given the
+    /// transform from that space to world space.
+    pub(crate) fn to_local(self, to_world: glam::Affine3A) -> [f32; 4] {
+        let normal = to_world.matrix3.transpose() * glam::Vec3A::from(self.normal);
+        let offset = self.offset - self.normal.dot(to_world.translation.into());
+        [normal.x, normal.y, normal.z, offset]
+    }
+}
+
+impl TryFrom<[f32; 4]> for ClipPlane {
+    type Error = InvalidRenderOption;
+
+    fn try_from([x, y, z, offset]: [f32; 4]) -> Result<Self, Self::Error> {
+        Self::new(glam::vec3(x, y, z), offset)
+    }
+}
+
+impl From<ClipPlane> for [f32; 4] {
+    fn from(plane: ClipPlane) -> Self {
+        [plane.normal.x, plane.normal.y, plane.normal.z, plane.offset]
+    }
+}
+
+/// Uniform scale of a [`WorldTransform`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "f32", into = "f32")
+)]
+pub struct WorldScale(f32);
+
+impl WorldScale {
+    pub const ONE: Self = Self(1.0);
+
+    /// The scale `value`, which has to be finite and positive.
+    pub fn new(value: f32) -> Result<Self, InvalidRenderOption> {
+        if value.is_finite() && value > 0.0 {
+            Ok(Self(value))
+        } else {
+            Err(InvalidRenderOption::WorldScale(value))
+        }
+    }
+
+    pub fn get(self) -> f32 {
+        self.0
+    }
+}
+
+impl TryFrom<f32> for WorldScale {
+    type Error = InvalidRenderOption;
+
+    fn try_from(value: f32) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl From<WorldScale> for f32 {
+    fn from(scale: WorldScale) -> Self {
+        scale.0
+    }
+}
+
+/// Placement of splats in the world, applied at render time: a uniform scale, then a rotation,
+/// then a translation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WorldTransform {
+    pub translation: glam::Vec3,
+    pub rotation: glam::Quat,
+    pub scale: WorldScale,
+}
+
+impl WorldTransform {
+    pub const IDENTITY: Self = Self {
+        translation: glam::Vec3::ZERO,
+        rotation: glam::Quat::IDENTITY,
+        scale: WorldScale::ONE,
+    };
+
+    pub fn new(translation: glam::Vec3, rotation: glam::Quat, scale: WorldScale) -> Self {
+        Self {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    /// The transform from the space the splats are stored in to world space.
+    pub fn to_affine(&self) -> glam::Affine3A {
+        glam::Affine3A::from_scale_rotation_translation(
+            glam::Vec3::splat(self.scale.get()),
+            self.rotation,
+            self.translation,
+        )
+    }
+}
+
+impl Default for WorldTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// Options that change how splats are rendered, without changing the splats themselves.
+///
+/// The default options match the standard 3DGS rendering.
+///
+/// With the `serde` feature, missing fields are deserialized as their defaults.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct RenderOptions {
+    /// Apply a 3D smoothing filter to each gaussian, as in Mip-Splatting (Yu et al. 2024).
+    ///
+    /// The filter dilates each gaussian so it is never smaller than the pixel footprint at its
+    /// depth, and lowers its opacity to preserve the total density. This prevents sub-pixel
+    /// splats from flickering when the camera moves or zooms.
+    pub mip_filter: bool,
+
+    /// Maximum degree of spherical harmonics to evaluate.
+    ///
+    /// Bands above this degree don't contribute to the color, and receive no gradients when
+    /// rendering differentiably. `None` evaluates all bands of the input coefficients.
+    ///
+    /// A degree of 0 colors splats by their base color only, ignoring view dependent effects,
+    /// and skips the math of the higher bands entirely, which makes projection cheaper. This is
+    /// useful for quick previews, or for scenes without view dependent effects.
+    pub max_sh_degree: Option<u32>,
+
+    /// Degrees of the spherical harmonics of the red, green and blue channels, when they differ,
+    /// or `None` when all channels share the degree of the coefficients.
+    ///
+    /// Each channel can then have its own degree, eg. a higher degree for the channel that carries
+    /// most of the luminance. The coefficients are stored planar as `[N, K]`, with all the
+    /// coefficients of red, then green, then blue, so `K` is the sum of the coefficient counts of
+    /// the degrees. See [`crate::sh::planar_channel_sh`] to convert coefficients. `max_sh_degree`
+    /// still limits the evaluated degree of each channel. This isn't supported when rendering
+    /// differentiably.
+    pub sh_channel_degrees: Option<[u32; 3]>,
+
+    /// How colors and opacities of splats are clamped. See [`ClampPolicy`].
+    ///
+    /// Splats are clamped before blending, so with a clamp the rendered pixels also stay within
+    /// `[0, 1]`. When rendering differentiably, clamped colors receive the gradient of the clamp.
+    pub clamp_policy: ClampPolicy,
+
+    /// Activation that maps the opacities passed to the renderer to the opacity of each splat, or
+    /// `None` to use them as is. See [`OpacityActivation`].
+    ///
+    /// The activation is applied in the projection kernels, and differentiable renders apply its
+    /// derivative to the opacity gradients. [`Splats`](crate::gaussian_splats::Splats) always
+    /// render their raw opacities with their own activation. This isn't supported for view
+    /// dependent opacities.
+    pub opacity_activation: Option<OpacityActivation>,
+
+    /// Color space of the output image. See [`ColorSpace`].
+    pub color_space: ColorSpace,
+
+    /// Data type of the output image when rendering floats. See [`OutputDType`].
+    pub output_dtype: OutputDType,
+
+    /// Whether colors are premultiplied by alpha. See [`AlphaMode`].
+    pub alpha_mode: AlphaMode,
+
+    /// Order of the channels of each pixel, for both packed and float images. See
+    /// [`ChannelOrder`].
+    ///
+    /// Use [`crate::read_image::unpack_color`] to unpack BGRA pixels, the other read helpers
+    /// return the channels in the order they are stored in. Only RGBA is supported when
+    /// rendering differentiably.
+    pub channel_order: ChannelOrder,
+
+    /// Render float images with a single gray channel, the luma of the color (see
+    /// [`LUMA_WEIGHTS`]), followed by alpha (and depth), instead of RGBA.
+    ///
+    /// This is meant for single channel images, eg. training on grayscale cameras. The splats can
+    /// have gray coefficients of shape `[N, C, 1]`, see
+    /// [`crate::gaussian_splats::Splats::to_grayscale`], which also render as gray colors without
+    /// this option. Packed images are always
+    /// RGBA, and grayscale images can't be tonemapped or rendered as F16.
+    ///
+    /// Differentiable renders don't use the gray kernels: gray coefficients are repeated to
+    /// three channels, and the RGBA render is converted to gray after, so training on gray
+    /// images does the same work as training in color.
+    pub grayscale: bool,
+
+    /// Cull splats outside of the view frustum before projecting them.
+    ///
+    /// This reduces the work of projection for large scenes, where most splats are off-screen,
+    /// but adds a compaction pass which isn't worth it for small scenes. Culling is conservative,
+    /// so the rendered image is the same either way.
+    ///
+    /// Either way, views without visible splats, eg. looking away from the scene, skip the rest
+    /// of the pipeline and only render the background. Finding these views reads back the number
+    /// of visible splats. Blocking readbacks aren't possible on wasm, where the rest of the
+    /// pipeline is dispatched with no work instead.
+    pub frustum_cull: bool,
+
+    /// Render the depth of the splats as a fifth channel after RGBA.
+    ///
+    /// The depth is the camera space depth of each splat, blended like the colors, so it is
+    /// weighted by the rendered alpha. Divide by the alpha channel to get the expected depth of
+    /// the visible surface. This only applies to F32 float images, and is differentiable, so
+    /// can be used for depth losses.
+    pub render_depth: bool,
+
+    /// Render the disparity (inverse depth) in the depth channel, instead of the depth.
+    ///
+    /// The disparity of each splat is blended like the depth, so empty pixels have a disparity of
+    /// zero, like geometry infinitely far away. Divide by the alpha channel to get the expected
+    /// disparity. Unlike the depth this stays well conditioned for distant geometry. This only
+    /// applies when rendering depth.
+    pub depth_as_disparity: bool,
+
+    /// Maximum number of splats to rasterize per tile, or `None` to rasterize all of them.
+    ///
+    /// Tiles covered by more splats only blend the nearest ones, and drop the splats behind
+    /// them. This bounds the cost of pathological tiles where thousands of splats overlap, which
+    /// can dominate frame time on weak GPUs. The tradeoff is quality: dropped splats leave holes
+    /// where the kept splats aren't opaque, and tile edges can become visible. This is meant for
+    /// approximate real-time rendering, and isn't supported when rendering differentiably.
+    pub tile_budget: Option<NonZeroU32>,
+
+    /// Break ties between splats at nearly the same depth by their index, instead of the order
+    /// they happen to be projected in.
+    ///
+    /// Splats whose depths are within about 1e-4 of each other (relative to their depth) are
+    /// drawn in the order they're stored in. This keeps their order stable when the camera moves
+    /// slightly, which prevents overlapping coplanar splats from shimmering. The tradeoff is that
+    /// splats closer than this are no longer sorted exactly by depth.
+    pub stable_depth_ties: bool,
+
+    /// Stop blending splats into a pixel once its transmittance would drop to this value, or
+    /// `None` for [`DEFAULT_TRANSMITTANCE_THRESHOLD`].
+    ///
+    /// Pixels behind nearly opaque splats barely change, so a higher threshold (eg. 1e-2) stops
+    /// rasterizing them sooner, which speeds up real-time previews of dense scenes at a small cost
+    /// in quality. Renders used for training or final output should keep the default.
+    pub transmittance_threshold: Option<TransmittanceThreshold>,
+
+    /// Raise the alpha of each splat at each pixel to this power before blending it, or `None`
+    /// to blend alphas as is, like a gamma of one.
+    ///
+    /// A gamma above one lowers the faint falloff of splats more than their opaque centers, which
+    /// sharpens silhouettes, and a gamma below one softens them. Splats still only cover their
+    /// usual extent, so with a low gamma their edges are cut off. This is meant for stylized
+    /// renders, and isn't supported when rendering differentiably.
+    pub alpha_gamma: Option<AlphaGamma>,
+
+    /// Tonemap the rendered colors, for scenes whose colors exceed the displayable range.
+    ///
+    /// Colors are tonemapped in linear light after rasterization, and then encoded in the output
+    /// [`ColorSpace`], so combine this with [`ColorSpace::Srgb`] to get an image ready for display.
+    /// This only applies to F32 float images, and isn't supported when rendering differentiably.
+    /// See [`crate::tonemap::tonemap_image`] to tonemap an existing image.
+    pub tonemap: Option<TonemapOperator>,
+
+    /// Only backpropagate through the pixels in this region, or `None` for the whole image.
+    ///
+    /// The region is in pixels of the output image. Gradients from pixels outside of it are
+    /// ignored, and tiles that don't overlap it are skipped by the backward pass entirely, which
+    /// makes it much cheaper when only a small part of the image changed, eg. while refining
+    /// interactively. This doesn't change the forward render. To ignore arbitrary pixels instead,
+    /// zero their loss; pixels without gradient are also skipped, but per pixel.
+    pub backward_region: Option<PixelRect>,
+
+    /// Shift the principal point of the camera by this many pixels, zero by default.
+    ///
+    /// Varying a sub-pixel offset between training steps, eg. with a low discrepancy sequence,
+    /// samples each pixel at different points, which averages out aliasing at edges over many
+    /// steps. Offsets should stay within a pixel or so, as frustum culling only allows for a
+    /// small margin.
+    pub subpixel_offset: glam::Vec2,
+
+    /// Place the splats in the world with this transform, the identity by default.
+    ///
+    /// The stored splats aren't changed, so one set of splats can be rendered at many places. The
+    /// transform is folded into the view matrix, and the camera is moved into the space of the
+    /// splats, so view dependent colors rotate along with the splats. When rendering
+    /// differentiably, gradients are with respect to the stored splats.
+    pub world_transform: WorldTransform,
+
+    /// Hide the splats behind any of these planes, eg. to show a cross section of the scene.
+    ///
+    /// Splats are culled by their mean, so splats straddling a plane are either drawn whole or
+    /// not at all. Combine planes to cut out a box or a wedge. Culled splats aren't visible, so
+    /// they don't get gradients when rendering differentiably.
+    pub clip_planes: Vec<ClipPlane>,
+
+    /// Most intersections of splats and tiles to allocate buffers for, or `None` for
+    /// [`crate::INTERSECTS_UPPER_BOUND`].
+    ///
+    /// Buffers are sized for an estimate of the number of intersections, based on the image size
+    /// and the number of splats, clamped to this bound. Memory grows linearly with the bound, so
+    /// lowering it avoids running out of memory on constrained devices, and raising it lets huge
+    /// scenes render correctly on devices with memory to spare. Bounds above
+    /// [`brush_sort::MAX_SORT_KEYS`] are clamped to it. When a view has more intersections than
+    /// buffers are allocated for, the intersections past the end are dropped, which leaves holes
+    /// in dense tiles, most visibly where many splats overlap.
+    pub intersects_upper_bound: Option<NonZeroU32>,
+
+    /// Blend the front layers of nearly coincident splats of each pixel order independently, or
+    /// `None` to blend all splats in depth order.
+    ///
+    /// Splats are blended in the order of their depth, so when a few splats are at nearly the same
+    /// depth, their order can flip as the camera moves, which makes the pixel pop between their
+    /// colors. With depth peeling, the splats of each pixel are grouped into layers of splats
+    /// within 0.1% of the depth of the front splat of the layer. The first this many layers are
+    /// each blended as a single splat, with the alpha weighted mean color of its splats, which
+    /// doesn't depend on their order. Splats behind these layers are blended as usual.
+    ///
+    /// This is meant for high quality offline renders. It makes rasterization somewhat slower,
+    /// more so with more layers, as more splats are held back per pixel, and isn't supported when
+    /// rendering differentiably.
+    pub depth_peel_layers: Option<NonZeroU32>,
+
+    /// Re-sort this many front splats of each pixel by their depth at the pixel, or `None` to
+    /// blend splats in the order of the depth of their centers.
+    ///
+    /// Splats are sorted once per tile, by the depth of their centers, but a splat seen at an
+    /// angle is nearer on one side than the other, so where splats cross each other, the nearest
+    /// splat differs from pixel to pixel. With re-sorting, each pixel holds back this many splats
+    /// of the tile order and always blends the nearest of them at the pixel first, which fixes
+    /// the order of crossing splats that are close in the tile order.
+    ///
+    /// This is meant for high quality offline renders. It makes rasterization a good deal slower,
+    /// at most [`MAX_RESORT_WINDOW`] splats can be held back, and it isn't supported with depth
+    /// peeling, blend modes other than alpha over, or when rendering differentiably.
+    pub pixel_resort: Option<ResortWindow>,
+
+    /// Algorithm to sort the intersections of splats and tiles with. By default this is picked
+    /// based on how many intersections the render has room for.
+    pub sort_algorithm: SortAlgorithm,
+
+    /// How depths are turned into sort keys. By default the float depth is sorted on.
+    pub depth_key: DepthKey,
+
+    /// Basis of the view directions spherical harmonics are evaluated at. See [`ShBasis`].
+    pub sh_basis: ShBasis,
+
+    /// How the splats of each pixel are composited. See [`BlendMode`].
+    ///
+    /// With additive or max blending, splats don't occlude each other, so every splat of a pixel
+    /// is blended and the transmittance threshold doesn't apply. The alpha of the image is still
+    /// the coverage of the splats, and depths are still blended front to back. These modes
+    /// aren't supported with depth peeling, or when rendering differentiably.
+    pub blend_mode: BlendMode,
+}
+
+impl RenderOptions {
+    /// The bound on the number of intersections, see [`Self::intersects_upper_bound`].
+    pub(crate) fn intersects_bound(&self) -> u32 {
+        self.intersects_upper_bound
+            .map_or(crate::INTERSECTS_UPPER_BOUND, NonZeroU32::get)
+            .min(brush_sort::MAX_SORT_KEYS)
+    }
+
+    /// The transmittance threshold, see [`Self::transmittance_threshold`].
+    pub(crate) fn transmittance(&self) -> f32 {
+        self.transmittance_threshold
+            .map_or(DEFAULT_TRANSMITTANCE_THRESHOLD, TransmittanceThreshold::get)
+    }
+}
@@ -1,5 +1,7 @@
 use crate::shaders;
 
+use burn::prelude::Backend;
+use burn::tensor::{Tensor, s};
 use glam::Vec3;
 const SH_C0: f32 = shaders::project_visible::SH_C0;
 
@@ -14,14 +16,49 @@ pub fn sh_degree_from_coeffs(coeffs_per_channel: u32) -> u32 {
         9 => 2,
         16 => 3,
         25 => 4,
+        36 => 5,
+        49 => 6,
         _ => panic!("Invalid nr. of sh bases {coeffs_per_channel}"),
     }
 }
 
+/// Number of coefficients per splat of planar coefficients with the given degree per channel,
+/// see [`crate::render_options::RenderOptions::sh_channel_degrees`].
+pub fn planar_coeffs_for_degrees(degrees: [u32; 3]) -> u32 {
+    degrees.into_iter().map(sh_coeffs_for_degree).sum()
+}
+
+/// Convert coefficients of shape `[N, C, 3]` to the planar layout of per channel degrees, keeping
+/// the first bands of each channel up to its degree in `degrees`.
+pub fn planar_channel_sh<B: Backend>(coeffs: Tensor<B, 3>, degrees: [u32; 3]) -> Tensor<B, 2> {
+    let [n, stored, _] = coeffs.dims();
+    let channels = degrees
+        .into_iter()
+        .enumerate()
+        .map(|(channel, degree)| {
+            let count = sh_coeffs_for_degree(degree) as usize;
+            assert!(
+                count <= stored,
+                "Channel degree {degree} is higher than the stored degree"
+            );
+            coeffs
+                .clone()
+                .slice(s![.., 0..count, channel..channel + 1])
+                .reshape([n, count])
+        })
+        .collect();
+    Tensor::cat(channels, 1)
+}
+
 pub fn channel_to_sh(rgb: f32) -> f32 {
     (rgb - 0.5) / SH_C0
 }
 
+/// The base sh coefficient of a view dependent opacity, which is `opacity` from all directions.
+pub fn opacity_to_sh(opacity: f32) -> f32 {
+    opacity / SH_C0
+}
+
 pub fn rgb_to_sh(rgb: Vec3) -> Vec3 {
     glam::vec3(
         channel_to_sh(rgb.x),
@@ -29,3 +66,23 @@ pub fn rgb_to_sh(rgb: Vec3) -> Vec3 {
         channel_to_sh(rgb.z),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{sh_coeffs_for_degree, sh_degree_from_coeffs};
+
+    #[test]
+    fn coeffs_degree_roundtrip() {
+        let counts = [1, 4, 9, 16, 25, 36, 49];
+        for (degree, count) in counts.into_iter().enumerate() {
+            assert_eq!(sh_coeffs_for_degree(degree as u32), count);
+            assert_eq!(sh_degree_from_coeffs(count), degree as u32);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid nr. of sh bases")]
+    fn invalid_coeff_count() {
+        sh_degree_from_coeffs(64);
+    }
+}
@@ -0,0 +1,58 @@
+#import helpers;
+
+struct Uniforms {
+    // World space frustum planes (normal + offset). Splats are culled when they are fully on
+    // the negative side of any plane.
+    plane_left: vec4f,
+    plane_right: vec4f,
+    plane_top: vec4f,
+    plane_bottom: vec4f,
+    plane_near: vec4f,
+    total_splats: u32,
+    pad_a: u32,
+    pad_b: u32,
+    pad_c: u32,
+}
+
+@group(0) @binding(0) var<storage, read> uniforms: Uniforms;
+@group(0) @binding(1) var<storage, read> means: array<helpers::PackedVec3>;
+@group(0) @binding(2) var<storage, read> log_scales: array<helpers::PackedVec3>;
+
+@group(0) @binding(3) var<storage, read_write> global_from_culled_gid: array<u32>;
+@group(0) @binding(4) var<storage, read_write> num_culled: array<atomic<u32>>;
+
+// Splats are bounded by a sphere of this many standard deviations. This is larger than the
+// extent used when projecting splats, so culling never removes a visible splat.
+const CULL_SIGMAS: f32 = 3.5;
+
+@compute
+@workgroup_size(helpers::MAIN_WG, 1, 1)
+fn main(@builtin(global_invocation_id) global_id: vec3u) {
+    let global_gid = global_id.x;
+
+    if global_gid >= uniforms.total_splats {
+        return;
+    }
+
+    let mean = helpers::as_vec(means[global_gid]);
+    let scale = exp(helpers::as_vec(log_scales[global_gid]));
+    let radius = CULL_SIGMAS * max(scale.x, max(scale.y, scale.z));
+
+    // Phrase as positive to bail on NaN.
+    var inside = true;
+    inside &= dot(uniforms.plane_left.xyz, mean) + uniforms.plane_left.w > -radius;
+    inside &= dot(uniforms.plane_right.xyz, mean) + uniforms.plane_right.w > -radius;
+    inside &= dot(uniforms.plane_top.xyz, mean) + uniforms.plane_top.w > -radius;
+    inside &= dot(uniforms.plane_bottom.xyz, mean) + uniforms.plane_bottom.w > -radius;
+    // Splats are projected based on their mean, so behind the near plane they are never visible.
+    inside &= dot(uniforms.plane_near.xyz, mean) + uniforms.plane_near.w > 0.0;
+
+    // Splats with NaN or infinite parameters are passed on, so projection can count them.
+    let finite = helpers::all_finite(vec4f(mean, 0.0)) && helpers::all_finite(vec4f(scale, 0.0));
+    if !inside && finite {
+        return;
+    }
+
+    let write_id = atomicAdd(&num_culled[0], 1u);
+    global_from_culled_gid[write_id] = global_gid;
+}
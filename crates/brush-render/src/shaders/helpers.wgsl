@@ -4,6 +4,28 @@ const TILE_SIZE: u32 = TILE_WIDTH * TILE_WIDTH;
 
 const MAIN_WG: u32 = 256u;
 
+// Splats closer to the camera than this (in view space) are culled. Projecting them divides by
+// a depth close to zero, giving huge or NaN conics, and the depth sort key needs depth > 0.
+const NEAR_PLANE: f32 = 0.01;
+// Splats whose depth is within this fraction of the depth of the front splat of a layer are part
+// of the same layer when depth peeling.
+const PEEL_DEPTH_TOLERANCE: f32 = 1e-3;
+// Most front splats of a pixel that can be re-sorted by their depth at the pixel, see
+// rasterize.wgsl.
+const MAX_PIXEL_RESORT: u32 = 8u;
+// Splats further away than this are culled.
+const FAR_PLANE: f32 = 1e10;
+
+// How colors and opacities of splats are clamped, see `ClampPolicy`.
+const CLAMP_NONE: u32 = 0u;
+const CLAMP_UNIT: u32 = 1u;
+const CLAMP_SOFT: u32 = 2u;
+
+// Soft clipping leaves colors up to this value as is, and rolls off brighter colors towards one.
+const SOFT_CLIP_KNEE: f32 = 0.8;
+// Rec. 709 weights of the luma of a linear RGB color. Must match render_options::LUMA_WEIGHTS.
+const LUMA_WEIGHTS: vec3f = vec3f(0.2126, 0.7152, 0.0722);
+
 struct RenderUniforms {
     // View matrix transform world to view position.
     viewmat: mat4x4f,
@@ -19,8 +41,12 @@ struct RenderUniforms {
 
     // Position of camera (xyz + pad)
     camera_position: vec4f,
+    // Rotation (w, x, y, z) from the space of the splats to the basis spherical harmonics are
+    // evaluated in.
+    sh_rotation: vec4f,
 
-    // Degree of sh coeffecients used.
+    // Degree of sh coeffecients used. This can be lower than the degree of
+    // the coefficients that are stored, to only evaluate some bands.
     sh_degree: u32,
 
 #ifdef UNIFORM_WRITE
@@ -35,6 +61,52 @@ struct RenderUniforms {
 
     total_splats: u32,
     max_intersects: u32,
+
+    // Number of sh coefficients stored per splat.
+    sh_coeffs_per_splat: u32,
+
+    // Max number of splats rasterized per tile, 0 for no limit.
+    tile_budget: u32,
+    // Pixels stop blending splats once their transmittance would drop to this.
+    transmittance_threshold: f32,
+    // Degree of the view dependent opacity, only used when opacities are sh coefficients.
+    opacity_sh_degree: u32,
+
+    // First tile row that is rendered. Rendering a band of the image only handles the rows
+    // [tile_row_offset, tile_row_offset + tile_bounds.y) of the tile grid of the full image. Tile ids
+    // are relative to the band, pixel coordinates are those of the full image.
+    tile_row_offset: u32,
+    // Whether rows of the output are stored bottom up, for cameras with their image origin at
+    // the bottom left. Projection and rasterization still work top down.
+    flip_y: u32,
+    // Uniform scale of the world transform, which is folded into the view matrix. Splat scales
+    // are in units before this scale.
+    world_scale: f32,
+    // Number of front layers of nearly coincident splats to blend order independently, see
+    // rasterize.wgsl.
+    peel_layers: u32,
+    // Stored sh degree of the red, green and blue channel, only used with CHANNEL_SH. The
+    // coefficients of each channel are then stored after those of the previous channel, unless
+    // w is 1, in which case all channels share the coefficients of a single gray channel.
+    sh_channel_degrees: vec4u,
+    // How colors and opacities are clamped, one of the CLAMP_ constants.
+    clamp_policy: u32,
+    // Power the alpha of splats is raised to before blending, only used with ALPHA_GAMMA.
+    alpha_gamma: f32,
+    // Log2 of the nearest depth of the sort key range, and one over the log2 of the range, only
+    // used with LOG_DEPTH.
+    log_depth_near: f32,
+    log_depth_scale: f32,
+    // Number of front splats of each pixel to re-sort by their depth at the pixel, only used with
+    // PIXEL_RESORT.
+    resort_window: u32,
+
+#ifdef UNIFORM_WRITE
+    // Number of splats culled for NaN or infinite parameters, written by project_forward.
+    num_non_finite: atomic<u32>,
+#else
+    num_non_finite: u32,
+#endif
 }
 
 // nb: this struct has a bunch of padding but that's probably fine.
@@ -48,10 +120,21 @@ struct ProjectedSplat {
     color_g: f32,
     color_b: f32,
     color_a: f32,
+    // Camera space depth.
+    depth: f32,
+}
+
+fn create_projected_splat(xy: vec2f, conic: vec3f, color: vec4f, depth: f32) -> ProjectedSplat {
+    return ProjectedSplat(xy.x, xy.y, conic.x, conic.y, conic.z, color.r, color.g, color.b, color.a, depth);
 }
 
-fn create_projected_splat(xy: vec2f, conic: vec3f, color: vec4f) -> ProjectedSplat {
-    return ProjectedSplat(xy.x, xy.y, conic.x, conic.y, conic.z, color.r, color.g, color.b, color.a);
+// Depth of a splat at a pixel: the mean depth of the gaussian along the ray through the pixel.
+// `depth_grad` is the change of the depth per pixel along the x and y axis of the image, which
+// is only computed with DEPTH_GRADS. This is linear in the pixel coordinates to the same
+// approximation the 2D covariance is.
+fn pixel_depth(projected: ProjectedSplat, depth_grad: vec2f, pixel_coord: vec2f) -> f32 {
+    let delta = pixel_coord - vec2f(projected.xy_x, projected.xy_y);
+    return projected.depth + dot(delta, depth_grad);
 }
 
 struct PackedVec3 {
@@ -63,10 +146,11 @@ struct PackedVec3 {
 fn get_bbox(center: vec2f, dims: vec2f, bounds: vec2u) -> vec4u {
     // get bounding box with center and dims, within bounds
     // bounding box coords returned in tile coords, inclusive min, exclusive max
-    // clamp between 0 and tile bounds
-    let min = vec2u(clamp(center - dims, vec2f(0.0), vec2f(bounds)));
-    let max = vec2u(clamp(center + dims + vec2f(1.0), vec2f(0.0), vec2f(bounds)));
-    return vec4u(min, max);
+    // clamp between 0 and tile bounds. Clamp again after converting, as converting
+    // a NaN or out of range float to an integer isn't well defined.
+    let bbox_max = min(vec2u(clamp(center + dims + vec2f(1.0), vec2f(0.0), vec2f(bounds))), bounds);
+    let bbox_min = min(vec2u(clamp(center - dims, vec2f(0.0), vec2f(bounds))), bbox_max);
+    return vec4u(bbox_min, bbox_max);
 }
 
 fn get_tile_bbox(pix_center: vec2f, pix_radius: f32, tile_bounds: vec2u) -> vec4u {
@@ -135,6 +219,25 @@ fn calc_cov3d(scale: vec3f, quat: vec4f) -> mat3x3f {
     return M * transpose(M);
 }
 
+// Variance (in pixels^2) of the 3D smoothing filter, as in Mip-Splatting (Yu et al. 2024).
+const MIP_FILTER_VAR: f32 = 0.2;
+
+// Apply a 3D low-pass filter to a gaussian with the given scale at the given depth.
+//
+// The filter is sized to the world space footprint of a pixel at this depth, so splats can't
+// become smaller than a pixel. The footprint is divided by `world_scale`, to get it in the units
+// of the splat. Returns the filtered scale in xyz, and the factor to multiply the opacity by to
+// keep the integrated density the same in w.
+fn mip_filter_scale(scale: vec3f, depth: f32, focal: vec2f, world_scale: f32) -> vec4f {
+    let pixel_size = depth / (max(focal.x, focal.y) * world_scale);
+    let scale_sq = scale * scale;
+    let filtered_sq = scale_sq + MIP_FILTER_VAR * pixel_size * pixel_size;
+    // Ratio of determinants, computed per axis to avoid underflow for tiny splats.
+    let ratio = scale_sq / filtered_sq;
+    let compensation = sqrt(ratio.x * ratio.y * ratio.z);
+    return vec4f(sqrt(filtered_sq), compensation);
+}
+
 fn calc_cam_J(mean_c: vec3f, focal: vec2f, img_size: vec2u, pixel_center: vec2f) -> mat3x2f {
     let tan_fov = 0.5 * vec2f(img_size.xy) / focal;
 
@@ -283,6 +386,13 @@ fn can_be_visible(tile: vec2u, xy: vec2f, conic: mat2x2f, opac: f32) -> bool {
     return ellipse_intersects_aabb(tile_center, tile_extent, xy, conic_scaled);
 }
 
+// Index of a pixel in the per-pixel buffers, which are stored in the row order of the image
+// origin of the camera.
+fn output_pixel_id(pix: vec2u, img_size: vec2u, flip_y: u32) -> u32 {
+    let row = select(pix.y, img_size.y - 1u - pix.y, flip_y != 0u);
+    return pix.x + row * img_size.x;
+}
+
 fn ceil_div(a: u32, b: u32) -> u32 {
     return (a + b - 1) / b;
 }
@@ -295,6 +405,81 @@ fn as_packed(vec: vec3f) -> PackedVec3 {
     return PackedVec3(vec.x, vec.y, vec.z);
 }
 
-// fn sigmoid(x: f32) -> f32 {
-//     return 1.0 / (1.0 + exp(-x));
-// }
+// Whether all components are neither NaN nor infinite. Phrased as positive, so NaN fails.
+fn all_finite(v: vec4f) -> bool {
+    return all(abs(v) <= vec4f(3.40282347e38));
+}
+
+// Map the raw opacity of a splat to its opacity, with the activation picked by the
+// SIGMOID_OPACITY or EXP_DENSITY_OPACITY flag. Without either, opacities are used as is.
+// Nb: Must match OpacityActivation::activate.
+fn activate_opacity(raw: f32) -> f32 {
+#ifdef SIGMOID_OPACITY
+    return 1.0 / (1.0 + exp(-raw));
+#else
+#ifdef EXP_DENSITY_OPACITY
+    return 1.0 - exp(-exp(raw));
+#else
+    return raw;
+#endif
+#endif
+}
+
+// Derivative of activate_opacity at the raw opacity.
+fn activate_opacity_grad(raw: f32) -> f32 {
+#ifdef SIGMOID_OPACITY
+    let opac = activate_opacity(raw);
+    return opac * (1.0 - opac);
+#else
+#ifdef EXP_DENSITY_OPACITY
+    let density = exp(raw);
+    return density * exp(-density);
+#else
+    return 1.0;
+#endif
+#endif
+}
+
+// Clamp the color of a splat with the given policy. Negative colors are left as is, as they're
+// clamped when blending.
+fn clamp_color(color: vec3f, policy: u32) -> vec3f {
+    if policy == CLAMP_UNIT {
+        return min(color, vec3f(1.0));
+    }
+    if policy == CLAMP_SOFT {
+        // The identity up to the knee, continuing with the same slope into a tanh towards one.
+        let width = 1.0 - SOFT_CLIP_KNEE;
+        let rolled = SOFT_CLIP_KNEE + width * tanh((color - SOFT_CLIP_KNEE) / width);
+        return select(color, rolled, color > vec3f(SOFT_CLIP_KNEE));
+    }
+    return color;
+}
+
+// Derivative of clamp_color, given the clamped color.
+fn clamp_color_grad(clamped: vec3f, policy: u32) -> vec3f {
+    if policy == CLAMP_UNIT {
+        return select(vec3f(1.0), vec3f(0.0), clamped >= vec3f(1.0));
+    }
+    if policy == CLAMP_SOFT {
+        // d/dx tanh(x) = 1 - tanh(x)^2.
+        let t = (clamped - SOFT_CLIP_KNEE) / (1.0 - SOFT_CLIP_KNEE);
+        return select(vec3f(1.0), 1.0 - t * t, clamped > vec3f(SOFT_CLIP_KNEE));
+    }
+    return vec3f(1.0);
+}
+
+// Convert sRGB encoded colors to linear light, using the piecewise sRGB transfer curve.
+fn srgb_to_linear(color: vec3f) -> vec3f {
+    let c = max(color, vec3f(0.0));
+    let lo = c / 12.92;
+    let hi = pow((c + 0.055) / 1.055, vec3f(2.4));
+    return select(hi, lo, c <= vec3f(0.04045));
+}
+
+// Encode linear colors with the sRGB transfer curve. The inverse of srgb_to_linear.
+fn linear_to_srgb(color: vec3f) -> vec3f {
+    let c = max(color, vec3f(0.0));
+    let lo = c * 12.92;
+    let hi = 1.055 * pow(c, vec3f(1.0 / 2.4)) - 0.055;
+    return select(hi, lo, c <= vec3f(0.0031308));
+}
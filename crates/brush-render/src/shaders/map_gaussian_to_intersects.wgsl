@@ -6,12 +6,28 @@
 #ifdef PREPASS
     @group(0) @binding(2) var<storage, read_write> splat_intersect_counts: array<atomic<i32>>;
     @group(0) @binding(3) var<storage, read_write> tile_intersect_counts: array<atomic<i32>>;
+
+    #ifdef STABLE_TIES
+        @group(0) @binding(4) var<storage, read> global_from_compact_gid: array<u32>;
+    #endif
 #else
     @group(0) @binding(2) var<storage, read> splat_cum_hit_counts: array<i32>;
-    @group(0) @binding(3) var<storage, read_write> tile_id_from_isect: array<i32>;
-    @group(0) @binding(4) var<storage, read_write> compact_gid_from_isect: array<i32>;
+    // The intersections are sorted on 64 bit keys, with the tile id as high half and the depth
+    // as low half.
+    @group(0) @binding(3) var<storage, read_write> tile_from_isect: array<u32>;
+    @group(0) @binding(4) var<storage, read_write> depth_key_from_isect: array<u32>;
+    @group(0) @binding(5) var<storage, read_write> compact_gid_from_isect: array<i32>;
+    @group(0) @binding(6) var<storage, read> depths: array<f32>;
+
+    #ifdef STABLE_TIES
+        @group(0) @binding(7) var<storage, read> global_from_compact_gid: array<u32>;
+    #endif
 #endif
 
+// Number of low bits of the depth key that are dropped when breaking ties, so splats at nearly
+// the same depth tie. This leaves a relative depth precision of about 1e-4.
+const TIE_BITS: u32 = 10u;
+
 
 @compute
 @workgroup_size(256, 1, 1)
@@ -30,16 +46,46 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     let conic = mat2x2f(projected.conic_x, projected.conic_y, projected.conic_y, projected.conic_z);
     let cov_from_conic = helpers::inverse(conic);
     let radius = helpers::radius_from_cov(cov_from_conic, opac);
-    let tile_minmax = helpers::get_tile_bbox(mean2d, radius, uniforms.tile_bounds);
-    let tile_min = tile_minmax.xy;
+    // The bounding box is clamped to the tile grid, so splats extending past the image edge
+    // only ever produce tile ids in [0, num_tiles). When rendering a band, the box is clamped
+    // to the rows of the band instead.
+    let band_end = uniforms.tile_row_offset + uniforms.tile_bounds.y;
+    let tile_minmax = helpers::get_tile_bbox(mean2d, radius, vec2u(uniforms.tile_bounds.x, band_end));
+    let tile_min = vec2u(tile_minmax.x, max(tile_minmax.y, uniforms.tile_row_offset));
     let tile_max = tile_minmax.zw;
 
     var num_tiles_hit = 0;
 
+    #ifdef STABLE_TIES
+        // The compacted order of splats depends on the scheduling of the projection, so splats
+        // with the same key could swap order between frames. Lay out the intersections of the
+        // splats in the order of their global IDs instead, which the stable sort keeps for
+        // splats with the same key.
+        let count_id = global_from_compact_gid[compact_gid];
+    #else
+        let count_id = compact_gid;
+    #endif
+
     #ifdef PREPASS
         var base_isect_id = 0;
     #else
-        var base_isect_id = splat_cum_hit_counts[compact_gid];
+        var base_isect_id = splat_cum_hit_counts[count_id];
+        #ifdef LOG_DEPTH
+            // Spread the log of the depths in the range over all bits of the key. The largest
+            // float below 2^32 keeps the far end in range of a u32.
+            let log_depth = (log2(depths[compact_gid]) - uniforms.log_depth_near) * uniforms.log_depth_scale;
+            var depth_key = u32(clamp(log_depth, 0.0, 1.0) * 4294967040.0);
+        #else
+            // Interpret the depth as a u32. This sorts correctly as long as the depth > 0.0,
+            // which ProjectSplats guarantees by culling everything in front of the near plane.
+            var depth_key = bitcast<u32>(depths[compact_gid]);
+        #endif
+
+        #ifdef STABLE_TIES
+            // Quantize the depth, so splats at nearly the same depth are ordered by their
+            // global IDs.
+            depth_key = depth_key & ~((1u << TIE_BITS) - 1u);
+        #endif
     #endif
 
     // Nb: It's really really important here the two dispatches
@@ -50,7 +96,7 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     for (var ty = tile_min.y; ty < tile_max.y; ty++) {
         for (var tx = tile_min.x; tx < tile_max.x; tx++) {
             if helpers::can_be_visible(vec2u(tx, ty), mean2d, conic, opac) {
-                let tile_id = tx + ty * uniforms.tile_bounds.x;
+                let tile_id = tx + (ty - uniforms.tile_row_offset) * uniforms.tile_bounds.x;
 
             #ifdef PREPASS
                 // TODO: Want to bail here if the tile is saturated with gaussians, but not clear
@@ -61,7 +107,8 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
                 // Nb: isect_id MIGHT be out of bounds here for degenerate cases.
                 // These kernels should be launched with bounds checking, so that these
                 // writes are ignored. This will skip these intersections.
-                tile_id_from_isect[isect_id] = i32(tile_id);
+                tile_from_isect[isect_id] = tile_id;
+                depth_key_from_isect[isect_id] = depth_key;
                 compact_gid_from_isect[isect_id] = i32(compact_gid);
             #endif
 
@@ -71,6 +118,6 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     }
 
     #ifdef PREPASS
-        splat_intersect_counts[compact_gid + 1u] = num_tiles_hit;
+        splat_intersect_counts[count_id + 1u] = num_tiles_hit;
     #endif
 }
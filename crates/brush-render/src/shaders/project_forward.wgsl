@@ -8,34 +8,121 @@
 @group(0) @binding(1) var<storage, read> means: array<helpers::PackedVec3>;
 @group(0) @binding(2) var<storage, read> quats: array<vec4f>;
 @group(0) @binding(3) var<storage, read> log_scales: array<helpers::PackedVec3>;
+// With OPACITY_SH, these are the sh coefficients of the opacity of each splat.
 @group(0) @binding(4) var<storage, read> opacities: array<f32>;
 
 @group(0) @binding(5) var<storage, read_write> global_from_compact_gid: array<u32>;
 @group(0) @binding(6) var<storage, read_write> depths: array<f32>;
 
+#ifdef FRUSTUM_CULL
+    // Splats that survived frustum culling, see cull_frustum.wgsl.
+    @group(0) @binding(7) var<storage, read> global_from_culled_gid: array<u32>;
+    @group(0) @binding(8) var<storage, read> num_culled: array<u32>;
+#endif
+
+#ifdef CLIP_PLANES
+    // Planes as their normal and offset, in the space of the splats. Splats are kept when
+    // dot(normal, mean) >= offset for all planes.
+    #ifdef FRUSTUM_CULL
+        @group(0) @binding(9) var<storage, read> clip_planes: array<vec4f>;
+    #else
+        @group(0) @binding(7) var<storage, read> clip_planes: array<vec4f>;
+    #endif
+#endif
+
+#ifdef OPACITY_SH
+    // Upper bound of the view dependent opacity, over all view directions.
+    //
+    // Each real sh basis function of band l is at most sqrt((2l + 1) / 4pi) in magnitude.
+    fn max_opacity(global_gid: u32) -> f32 {
+        let num_coeffs = (uniforms.opacity_sh_degree + 1u) * (uniforms.opacity_sh_degree + 1u);
+        let base_id = global_gid * num_coeffs;
+        var bound = 0.0;
+        for (var band = 0u; band <= uniforms.opacity_sh_degree; band++) {
+            let basis_max = sqrt(f32(2u * band + 1u) / (4.0 * 3.14159265358979f));
+            for (var i = band * band; i < (band + 1u) * (band + 1u); i++) {
+                bound += basis_max * abs(opacities[base_id + i]);
+            }
+        }
+        return bound;
+    }
+#endif
+
 @compute
 @workgroup_size(helpers::MAIN_WG, 1, 1)
 fn main(@builtin(global_invocation_id) global_id: vec3u) {
+#ifdef FRUSTUM_CULL
+    if global_id.x >= num_culled[0] {
+        return;
+    }
+    let global_gid = global_from_culled_gid[global_id.x];
+#else
     let global_gid = global_id.x;
 
     if global_gid >= uniforms.total_splats {
         return;
     }
+#endif
 
-    // Project world space to camera space.
     let mean = helpers::as_vec(means[global_gid]);
+    let log_scale = helpers::as_vec(log_scales[global_gid]);
+    var quat = quats[global_gid];
+#ifdef OPACITY_SH
+    // The opacity depends on the view direction, which is only evaluated for visible splats. Cull
+    // conservatively until then.
+    var opac = max_opacity(global_gid);
+#else
+    var opac = opacities[global_gid];
+#endif
+
+    // A NaN or infinite parameter, eg. from a diverged training step, would give the splat a
+    // garbage depth, which breaks the sort of all splats. Cull these splats, and count them.
+    let finite = helpers::all_finite(vec4f(mean, opac)) &&
+        helpers::all_finite(vec4f(log_scale, 0.0)) && helpers::all_finite(quat);
+    if !finite {
+        atomicAdd(&uniforms.num_non_finite, 1u);
+        return;
+    }
+#ifdef OPACITY_SH
+    opac = min(opac, 1.0);
+#else
+    // Nb: Must match the activation in project_visible.
+    opac = helpers::activate_opacity(opac);
+#endif
+
+#ifdef CLIP_PLANES
+    for (var i = 0u; i < arrayLength(&clip_planes); i++) {
+        let plane = clip_planes[i];
+        // Phrase as positive to bail on NaN.
+        if !(dot(plane.xyz, mean) >= plane.w) {
+            return;
+        }
+    }
+#endif
 
+    // Project world space to camera space.
     let img_size = uniforms.img_size;
     let viewmat = uniforms.viewmat;
     let R = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
     let mean_c = R * mean + viewmat[3].xyz;
 
+    // Cull splats at or behind the camera before projecting anything, as the projection
+    // divides by the depth. Phrase as positive to bail on NaN.
+    let in_depth_range = mean_c.z > helpers::NEAR_PLANE && mean_c.z < helpers::FAR_PLANE;
+    if !in_depth_range {
+        return;
+    }
+
     // Check if this splat is 'valid' (aka visible). Phrase as positive to bail on NaN.
     var valid = true;
-    valid &= (mean_c.z > 0.01 && mean_c.z < 1e10);
 
-    let scale = exp(helpers::as_vec(log_scales[global_gid]));
-    var quat = quats[global_gid];
+    var scale = exp(log_scale);
+
+#ifdef MIP_FILTER
+    let filtered = helpers::mip_filter_scale(scale, mean_c.z, uniforms.focal, uniforms.world_scale);
+    scale = filtered.xyz;
+    opac *= filtered.w;
+#endif
 
     // Skip any invalid rotations. This will mean overtime
     // these gaussians just die off while optimizing. For the viewer, the importer
@@ -56,7 +143,9 @@ fn main(@builtin(global_invocation_id) global_id: vec3u) {
     // compute the projected mean
     let mean2d = uniforms.focal * mean_c.xy * (1.0 / mean_c.z) + uniforms.pixel_center;
 
-    let opac = opacities[global_gid];
+    // Splats just past the near plane can still overflow. Phrase as positive to bail on NaN and inf.
+    valid &= all(abs(conic[0]) < vec2f(1e30)) && all(abs(conic[1]) < vec2f(1e30)) &&
+        all(abs(mean2d) < vec2f(1e30));
 
     // Phrase as positive to bail on NaN.
     valid &= opac > 1.0 / 255.0;
@@ -12,13 +12,24 @@ struct IsectInfo {
 @group(0) @binding(1) var<storage, read> means: array<helpers::PackedVec3>;
 @group(0) @binding(2) var<storage, read> log_scales: array<helpers::PackedVec3>;
 @group(0) @binding(3) var<storage, read> quats: array<vec4f>;
-@group(0) @binding(4) var<storage, read> coeffs: array<helpers::PackedVec3>;
+#ifdef CHANNEL_SH
+    // Planar coefficients, with a different degree per channel.
+    @group(0) @binding(4) var<storage, read> coeffs: array<f32>;
+#else
+    @group(0) @binding(4) var<storage, read> coeffs: array<helpers::PackedVec3>;
+#endif
+// With OPACITY_SH, these are the sh coefficients of the opacity of each splat.
 @group(0) @binding(5) var<storage, read> opacities: array<f32>;
 
 @group(0) @binding(6) var<storage, read> global_from_compact_gid: array<i32>;
 
 @group(0) @binding(7) var<storage, read_write> projected: array<helpers::ProjectedSplat>;
 
+#ifdef DEPTH_GRADS
+    // Change of the depth of each splat per pixel, see helpers::pixel_depth.
+    @group(0) @binding(8) var<storage, read_write> depth_grads: array<vec2f>;
+#endif
+
 struct ShCoeffs {
     b0_c0: vec3f,
 
@@ -49,6 +60,32 @@ struct ShCoeffs {
     b4_c6: vec3f,
     b4_c7: vec3f,
     b4_c8: vec3f,
+
+    b5_c0: vec3f,
+    b5_c1: vec3f,
+    b5_c2: vec3f,
+    b5_c3: vec3f,
+    b5_c4: vec3f,
+    b5_c5: vec3f,
+    b5_c6: vec3f,
+    b5_c7: vec3f,
+    b5_c8: vec3f,
+    b5_c9: vec3f,
+    b5_c10: vec3f,
+
+    b6_c0: vec3f,
+    b6_c1: vec3f,
+    b6_c2: vec3f,
+    b6_c3: vec3f,
+    b6_c4: vec3f,
+    b6_c5: vec3f,
+    b6_c6: vec3f,
+    b6_c7: vec3f,
+    b6_c8: vec3f,
+    b6_c9: vec3f,
+    b6_c10: vec3f,
+    b6_c11: vec3f,
+    b6_c12: vec3f,
 }
 
 const SH_C0: f32 = 0.2820947917738781f;
@@ -151,18 +188,173 @@ fn sh_coeffs_to_color(
                 pSH22 * sh.b4_c6 +
                 pSH23 * sh.b4_c7 +
                 pSH24 * sh.b4_c8;
+
+    if (degree == 4) {
+        return colors;
+    }
+
+    let fTmp0E = 2.03100960115899f * z * fTmp0D - 0.9910312089651149f * fTmp0C;
+    let fTmp1D = z * (7.190305177459987f * z2 - 2.396768392486662f);
+    let fTmp2C = -4.403144694917254f * z2 + 0.4892382994352505f;
+    let fTmp3B = 2.075662314881041f * z;
+    let fTmp4A = -0.6563820568401702f;
+    let fC4 = x * fC3 - y * fS3;
+    let fS4 = x * fS3 + y * fC3;
+    let pSH30 = 1.98997487421324f * z * pSH20 - 1.002853072844814f * pSH12;
+    let pSH31 = fTmp0E * x;
+    let pSH29 = fTmp0E * y;
+    let pSH32 = fTmp1D * fC1;
+    let pSH28 = fTmp1D * fS1;
+    let pSH33 = fTmp2C * fC2;
+    let pSH27 = fTmp2C * fS2;
+    let pSH34 = fTmp3B * fC3;
+    let pSH26 = fTmp3B * fS3;
+    let pSH35 = fTmp4A * fC4;
+    let pSH25 = fTmp4A * fS4;
+    colors += pSH25 * sh.b5_c0 +
+                pSH26 * sh.b5_c1 +
+                pSH27 * sh.b5_c2 +
+                pSH28 * sh.b5_c3 +
+                pSH29 * sh.b5_c4 +
+                pSH30 * sh.b5_c5 +
+                pSH31 * sh.b5_c6 +
+                pSH32 * sh.b5_c7 +
+                pSH33 * sh.b5_c8 +
+                pSH34 * sh.b5_c9 +
+                pSH35 * sh.b5_c10;
+
+    if (degree == 5) {
+        return colors;
+    }
+
+    let fTmp0F = 2.021314989237028f * z * fTmp0E - 0.9952267030562386f * fTmp0D;
+    let fTmp1E = 2.11394181566097f * z * fTmp1D - 0.9736101204623269f * fTmp1C;
+    let fTmp2D = 2.301368353023109f * z * fTmp2C - 0.9251848886516145f * fTmp2B;
+    let fTmp3C = 5.550213908015966f * z2 - 0.5045649007287242f;
+    let fTmp4B = -2.366619162231752f * z;
+    let fTmp5A = 0.6831841051919143f;
+    let fC5 = x * fC4 - y * fS4;
+    let fS5 = x * fS4 + y * fC4;
+    let pSH42 = 1.993043457183566f * z * pSH30 - 1.001542020962219f * pSH20;
+    let pSH43 = fTmp0F * x;
+    let pSH41 = fTmp0F * y;
+    let pSH44 = fTmp1E * fC1;
+    let pSH40 = fTmp1E * fS1;
+    let pSH45 = fTmp2D * fC2;
+    let pSH39 = fTmp2D * fS2;
+    let pSH46 = fTmp3C * fC3;
+    let pSH38 = fTmp3C * fS3;
+    let pSH47 = fTmp4B * fC4;
+    let pSH37 = fTmp4B * fS4;
+    let pSH48 = fTmp5A * fC5;
+    let pSH36 = fTmp5A * fS5;
+    colors += pSH36 * sh.b6_c0 +
+                pSH37 * sh.b6_c1 +
+                pSH38 * sh.b6_c2 +
+                pSH39 * sh.b6_c3 +
+                pSH40 * sh.b6_c4 +
+                pSH41 * sh.b6_c5 +
+                pSH42 * sh.b6_c6 +
+                pSH43 * sh.b6_c7 +
+                pSH44 * sh.b6_c8 +
+                pSH45 * sh.b6_c9 +
+                pSH46 * sh.b6_c10 +
+                pSH47 * sh.b6_c11 +
+                pSH48 * sh.b6_c12;
     return colors;
 }
 
-fn num_sh_coeffs(degree: u32) -> u32 {
-    return (degree + 1) * (degree + 1);
+// Sh coefficients with only a first channel, to evaluate a single value.
+fn scalar_sh_coeffs(values: array<f32, 49>) -> ShCoeffs {
+    var sh = ShCoeffs();
+    sh.b0_c0.x = values[0];
+    sh.b1_c0.x = values[1];
+    sh.b1_c1.x = values[2];
+    sh.b1_c2.x = values[3];
+    sh.b2_c0.x = values[4];
+    sh.b2_c1.x = values[5];
+    sh.b2_c2.x = values[6];
+    sh.b2_c3.x = values[7];
+    sh.b2_c4.x = values[8];
+    sh.b3_c0.x = values[9];
+    sh.b3_c1.x = values[10];
+    sh.b3_c2.x = values[11];
+    sh.b3_c3.x = values[12];
+    sh.b3_c4.x = values[13];
+    sh.b3_c5.x = values[14];
+    sh.b3_c6.x = values[15];
+    sh.b4_c0.x = values[16];
+    sh.b4_c1.x = values[17];
+    sh.b4_c2.x = values[18];
+    sh.b4_c3.x = values[19];
+    sh.b4_c4.x = values[20];
+    sh.b4_c5.x = values[21];
+    sh.b4_c6.x = values[22];
+    sh.b4_c7.x = values[23];
+    sh.b4_c8.x = values[24];
+    sh.b5_c0.x = values[25];
+    sh.b5_c1.x = values[26];
+    sh.b5_c2.x = values[27];
+    sh.b5_c3.x = values[28];
+    sh.b5_c4.x = values[29];
+    sh.b5_c5.x = values[30];
+    sh.b5_c6.x = values[31];
+    sh.b5_c7.x = values[32];
+    sh.b5_c8.x = values[33];
+    sh.b5_c9.x = values[34];
+    sh.b5_c10.x = values[35];
+    sh.b6_c0.x = values[36];
+    sh.b6_c1.x = values[37];
+    sh.b6_c2.x = values[38];
+    sh.b6_c3.x = values[39];
+    sh.b6_c4.x = values[40];
+    sh.b6_c5.x = values[41];
+    sh.b6_c6.x = values[42];
+    sh.b6_c7.x = values[43];
+    sh.b6_c8.x = values[44];
+    sh.b6_c9.x = values[45];
+    sh.b6_c10.x = values[46];
+    sh.b6_c11.x = values[47];
+    sh.b6_c12.x = values[48];
+    return sh;
 }
 
-fn read_coeffs(base_id: ptr<function, u32>) -> vec3f {
-    let ret = helpers::as_vec(coeffs[*base_id]);
-    *base_id += 1u;
-    return ret;
-}
+#ifdef CHANNEL_SH
+    // Evaluate one color channel of planar coefficients, whose first coefficient is at base_id.
+    fn eval_channel(base_id: u32, degree: u32, viewdir: vec3f) -> f32 {
+        let num_coeffs = (degree + 1u) * (degree + 1u);
+
+        var values: array<f32, 49>;
+        for (var i = 0u; i < num_coeffs; i++) {
+            values[i] = coeffs[base_id + i];
+        }
+        return sh_coeffs_to_color(degree, viewdir, scalar_sh_coeffs(values)).x;
+    }
+#else
+    fn read_coeffs(base_id: ptr<function, u32>) -> vec3f {
+        let ret = helpers::as_vec(coeffs[*base_id]);
+        *base_id += 1u;
+        return ret;
+    }
+#endif
+
+#ifdef OPACITY_SH
+    // Evaluate the view dependent opacity. The coefficients are stored in the first channel, so
+    // the color evaluation can be reused.
+    fn eval_opacity(global_gid: u32, viewdir: vec3f) -> f32 {
+        let degree = uniforms.opacity_sh_degree;
+        let num_coeffs = (degree + 1u) * (degree + 1u);
+        let base_id = global_gid * num_coeffs;
+
+        var coeffs: array<f32, 49>;
+        for (var i = 0u; i < num_coeffs; i++) {
+            coeffs[i] = opacities[base_id + i];
+        }
+
+        let sh = scalar_sh_coeffs(coeffs);
+        return clamp(sh_coeffs_to_color(degree, viewdir, sh).x, 0.0, 1.0);
+    }
+#endif
 
 @compute
 @workgroup_size(helpers::MAIN_WG, 1, 1)
@@ -177,15 +369,29 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
 
     // Project world space to camera space.
     let mean = helpers::as_vec(means[global_gid]);
-    let scale = exp(helpers::as_vec(log_scales[global_gid]));
+    var scale = exp(helpers::as_vec(log_scales[global_gid]));
     // Safe to normalize, splats with length(quat) == 0 are invisible.
     let quat = normalize(quats[global_gid]);
-    let opac = opacities[global_gid];
+    let viewdir = helpers::quat_to_mat(uniforms.sh_rotation) * normalize(mean - uniforms.camera_position.xyz);
+
+#ifdef OPACITY_SH
+    // Nb: Must stay below the bound used to cull in project_forward.
+    var opac = eval_opacity(u32(global_gid), viewdir);
+#else
+    var opac = helpers::activate_opacity(opacities[global_gid]);
+#endif
 
     let viewmat = uniforms.viewmat;
     let R = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
     let mean_c = R * mean + viewmat[3].xyz;
 
+#ifdef MIP_FILTER
+    // Nb: Must match the filtering in project_forward.
+    let filtered = helpers::mip_filter_scale(scale, mean_c.z, uniforms.focal, uniforms.world_scale);
+    scale = filtered.xyz;
+    opac *= filtered.w;
+#endif
+
     let covar = helpers::calc_cov3d(scale, quat);
     let cov2d = helpers::calc_cov2d(covar, mean_c, uniforms.focal, uniforms.img_size, uniforms.pixel_center, viewmat);
     let conic = helpers::inverse(cov2d);
@@ -194,9 +400,48 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     let rz = 1.0 / mean_c.z;
     let mean2d = uniforms.focal * mean_c.xy * rz + uniforms.pixel_center;
 
+#ifdef NO_COLOR
+    // Only the geometry is rendered, so the coefficients aren't read at all.
+    var color = vec3f(0.0);
+#else
+#ifdef FLAT_COLOR
+    // Only the base color is used, which doesn't depend on the view direction.
+    let base_id = u32(global_gid) * uniforms.sh_coeffs_per_splat;
+#ifdef CHANNEL_SH
+    let degrees = uniforms.sh_channel_degrees;
+    var green_id = base_id + (degrees.x + 1u) * (degrees.x + 1u);
+    var blue_id = green_id + (degrees.y + 1u) * (degrees.y + 1u);
+    if degrees.w == 1u {
+        green_id = base_id;
+        blue_id = base_id;
+    }
+    let base_color = vec3f(coeffs[base_id], coeffs[green_id], coeffs[blue_id]);
+#else
+    let base_color = helpers::as_vec(coeffs[base_id]);
+#endif
+#ifdef RGB_COLORS
+    // The colors are precomputed, and stored in place of the coefficients.
+    var color = base_color;
+#else
+    var color = SH_C0 * base_color + vec3f(0.5);
+#endif
+#else
+#ifdef CHANNEL_SH
+    // Each channel has its own degree, evaluated up to at most sh_degree.
+    var color = vec3f(0.5);
+    var base_id = u32(global_gid) * uniforms.sh_coeffs_per_splat;
+    for (var channel = 0u; channel < 3u; channel++) {
+        let stored_degree = uniforms.sh_channel_degrees[channel];
+        let degree = min(stored_degree, uniforms.sh_degree);
+        color[channel] += eval_channel(base_id, degree, viewdir);
+        // Gray coefficients are shared by all channels.
+        if uniforms.sh_channel_degrees.w == 0u {
+            base_id += (stored_degree + 1u) * (stored_degree + 1u);
+        }
+    }
+#else
     let sh_degree = uniforms.sh_degree;
-    let num_coeffs = num_sh_coeffs(sh_degree);
-    var base_id = u32(global_gid) * num_coeffs;
+    var base_id = u32(global_gid) * uniforms.sh_coeffs_per_splat;
 
     var sh = ShCoeffs();
     sh.b0_c0 = read_coeffs(&base_id);
@@ -232,18 +477,64 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
                     sh.b4_c6 = read_coeffs(&base_id);
                     sh.b4_c7 = read_coeffs(&base_id);
                     sh.b4_c8 = read_coeffs(&base_id);
+
+                    if sh_degree >= 5 {
+                        sh.b5_c0 = read_coeffs(&base_id);
+                        sh.b5_c1 = read_coeffs(&base_id);
+                        sh.b5_c2 = read_coeffs(&base_id);
+                        sh.b5_c3 = read_coeffs(&base_id);
+                        sh.b5_c4 = read_coeffs(&base_id);
+                        sh.b5_c5 = read_coeffs(&base_id);
+                        sh.b5_c6 = read_coeffs(&base_id);
+                        sh.b5_c7 = read_coeffs(&base_id);
+                        sh.b5_c8 = read_coeffs(&base_id);
+                        sh.b5_c9 = read_coeffs(&base_id);
+                        sh.b5_c10 = read_coeffs(&base_id);
+
+                        if sh_degree >= 6 {
+                            sh.b6_c0 = read_coeffs(&base_id);
+                            sh.b6_c1 = read_coeffs(&base_id);
+                            sh.b6_c2 = read_coeffs(&base_id);
+                            sh.b6_c3 = read_coeffs(&base_id);
+                            sh.b6_c4 = read_coeffs(&base_id);
+                            sh.b6_c5 = read_coeffs(&base_id);
+                            sh.b6_c6 = read_coeffs(&base_id);
+                            sh.b6_c7 = read_coeffs(&base_id);
+                            sh.b6_c8 = read_coeffs(&base_id);
+                            sh.b6_c9 = read_coeffs(&base_id);
+                            sh.b6_c10 = read_coeffs(&base_id);
+                            sh.b6_c11 = read_coeffs(&base_id);
+                            sh.b6_c12 = read_coeffs(&base_id);
+                        }
+                    }
                 }
             }
         }
     }
 
-    // Write projected splat information.
-    let viewdir = normalize(mean - uniforms.camera_position.xyz);
     var color = sh_coeffs_to_color(sh_degree, viewdir, sh) + vec3f(0.5);
+#endif
+#endif
+#endif
 
+    if uniforms.clamp_policy != helpers::CLAMP_NONE {
+        color = helpers::clamp_color(color, uniforms.clamp_policy);
+        opac = clamp(opac, 0.0, 1.0);
+    }
+
+#ifdef DEPTH_GRADS
+    // The covariance of the image position and depth, mapped through the inverse image covariance,
+    // gives how the expected depth changes across the image.
+    let covar_cam = R * covar * transpose(R);
+    let J = helpers::calc_cam_J(mean_c, uniforms.focal, uniforms.img_size, uniforms.pixel_center);
+    depth_grads[compact_gid] = conic * (J * covar_cam[2]);
+#endif
+
+    // Write projected splat information.
     projected[compact_gid] = helpers::create_projected_splat(
         mean2d,
         vec3f(conic[0][0], conic[0][1], conic[1][1]),
-        vec4f(color, opac)
+        vec4f(color, opac),
+        mean_c.z,
     );
 }
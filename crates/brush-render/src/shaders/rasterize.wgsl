@@ -5,14 +5,42 @@
 @group(0) @binding(2) var<storage, read> tile_offsets: array<i32>;
 @group(0) @binding(3) var<storage, read> projected_splats: array<helpers::ProjectedSplat>;
 
+// Optional inputs, at the same bindings whether or not their feature is on. When a feature is
+// off, a dummy buffer is bound in its place.
+// Opaque RGB background, with 3 floats per pixel, see BACKGROUND_IMAGE.
+@group(0) @binding(4) var<storage, read> background_img: array<f32>;
+// Whether to render each pixel, nonzero to render, see PIXEL_MASK.
+@group(0) @binding(5) var<storage, read> pixel_mask: array<i32>;
+// Change of the depth of each splat per pixel, see helpers::pixel_depth and PIXEL_RESORT.
+@group(0) @binding(6) var<storage, read> depth_grads: array<vec2f>;
+
 #ifdef BWD_INFO
-    @group(0) @binding(4) var<storage, read_write> out_img: array<vec4f>;
+    #ifdef F16_OUTPUT
+        // Each pixel is 4 half floats, packed in 2 u32's.
+        @group(0) @binding(7) var<storage, read_write> out_img: array<vec2u>;
+    #else
+        #ifdef DEPTH_OUTPUT
+            // Each pixel is RGBA followed by the depth, or gray and alpha followed by the depth.
+            @group(0) @binding(7) var<storage, read_write> out_img: array<f32>;
+        #else
+            #ifdef GRAY_OUTPUT
+                // Each pixel is the luma of the color and alpha.
+                @group(0) @binding(7) var<storage, read_write> out_img: array<vec2f>;
+            #else
+                @group(0) @binding(7) var<storage, read_write> out_img: array<vec4f>;
+            #endif
+        #endif
+    #endif
 
-    @group(0) @binding(5) var<storage, read> global_from_compact_gid: array<i32>;
-    @group(0) @binding(6) var<storage, read_write> final_index: array<i32>;
-    @group(0) @binding(7) var<storage, read_write> visible: array<f32>;
+    @group(0) @binding(8) var<storage, read> global_from_compact_gid: array<i32>;
+    @group(0) @binding(9) var<storage, read_write> final_index: array<i32>;
+    @group(0) @binding(10) var<storage, read_write> visible: array<f32>;
 #else
-    @group(0) @binding(4) var<storage, read_write> out_img: array<u32>;
+    @group(0) @binding(7) var<storage, read_write> out_img: array<u32>;
+#endif
+
+#ifdef PIXEL_RESORT
+    var<workgroup> local_depth_grads: array<vec2f, helpers::TILE_SIZE>;
 #endif
 
 var<workgroup> local_batch: array<helpers::ProjectedSplat, helpers::TILE_SIZE>;
@@ -24,6 +52,20 @@ var<workgroup> local_batch: array<helpers::ProjectedSplat, helpers::TILE_SIZE>;
 var<workgroup> done_count: atomic<u32>;
 var<workgroup> done_count_uniform: u32;
 
+// Blend a splat into a pixel, unless that would drop the transmittance to the threshold.
+// Returns whether the pixel is done.
+fn blend(T: ptr<function, f32>, pix_out: ptr<function, vec3f>, depth_out: ptr<function, f32>, alpha: f32, rgb: vec3f, depth: f32) -> bool {
+    let next_T = *T * (1.0 - alpha);
+    if next_T <= uniforms.transmittance_threshold {
+        return true;
+    }
+    let vis = alpha * *T;
+    *pix_out += rgb * vis;
+    *depth_out += depth * vis;
+    *T = next_T;
+    return false;
+}
+
 // kernel function for rasterizing each tile
 // each thread treats a single pixel
 // each thread group uses the same gaussian data in a tile
@@ -34,29 +76,41 @@ fn main(
     @builtin(local_invocation_index) local_idx: u32,
     @builtin(workgroup_id) workgroup_id: vec3u,
 ) {
+    // Use the optional inputs even when their feature is off, which keeps their bindings in the
+    // layout of the kernel, so the dummy buffers bound in their place are valid.
+    _ = arrayLength(&background_img) + arrayLength(&pixel_mask) + arrayLength(&depth_grads);
+
     let img_size = uniforms.img_size;
 
-    // Get index of tile being drawn.
-    let pix_id = global_id.x + global_id.y * img_size.x;
+    // Get index of tile being drawn. Tiles are relative to the rendered band, pixels are in the
+    // full image.
+    let pix = global_id.xy + vec2u(0u, uniforms.tile_row_offset * helpers::TILE_WIDTH);
+    let pix_id = helpers::output_pixel_id(pix, img_size, uniforms.flip_y);
     let tile_id = workgroup_id.x + workgroup_id.y * uniforms.tile_bounds.x;
-    let pixel_coord = vec2f(global_id.xy) + 0.5;
+    let pixel_coord = vec2f(pix) + 0.5;
 
     // return if out of bounds
     // keep not rasterizing threads around for reading data
-    let inside = global_id.x < img_size.x && global_id.y < img_size.y;
+    let inside = pix.x < img_size.x && pix.y < img_size.y;
     var done = !inside;
 
     // have all threads in tile process the same gaussians in batches
     // first collect gaussians between the bin counts.
-    let range = vec2u(
+    var range = vec2u(
         u32(clamp(tile_offsets[tile_id], 0, i32(uniforms.max_intersects))),
         u32(clamp(tile_offsets[tile_id + 1], 0, i32(uniforms.max_intersects)))
     );
 
+    // Intersections are sorted front to back, so this keeps the nearest splats of the tile.
+    if uniforms.tile_budget > 0u {
+        range.y = min(range.y, range.x + uniforms.tile_budget);
+    }
+
     let num_batches = helpers::ceil_div(range.y - range.x, u32(helpers::TILE_SIZE));
     // current visibility left to render
     var T = 1.0;
     var pix_out = vec3f(0.0);
+    var depth_out = 0.0;
 
     // collect and process batches of gaussians
     // each thread loads one gaussian at a time before rasterizing its
@@ -64,8 +118,41 @@ fn main(
     var t = 0;
     var final_idx = 0u;
 
+#ifdef DEPTH_PEEL
+    // The layer of nearly coincident splats being collected. Its splats are blended as one, with
+    // the alpha weighted mean of their colors and depths, so their order doesn't matter.
+    var num_layers = 0u;
+    var layer_front = 0.0;
+    var layer_T = 1.0;
+    var layer_alpha = 0.0;
+    var layer_rgb = vec3f(0.0);
+    var layer_depth = 0.0;
+#endif
+
+#ifdef PIXEL_RESORT
+    // The front splats of the pixel that aren't blended yet, sorted by their depth at the pixel.
+    // Tiles are sorted by the depth of the splat centers, but where splats at different angles
+    // overlap, their order can differ from pixel to pixel. Holding back a few splats and
+    // blending the nearest one first fixes the order of splats that are close in the tile order.
+    var window_len = 0u;
+    var window_key: array<f32, helpers::MAX_PIXEL_RESORT>;
+    var window_alpha: array<f32, helpers::MAX_PIXEL_RESORT>;
+    var window_rgb: array<vec3f, helpers::MAX_PIXEL_RESORT>;
+    var window_depth: array<f32, helpers::MAX_PIXEL_RESORT>;
+#endif
+
     atomicStore(&done_count, 0u);
 
+#ifdef PIXEL_MASK
+    // Masked out pixels are done before blending anything, so tiles that are entirely masked out
+    // skip all their splats. Pixels outside the image count as masked out for this.
+    workgroupBarrier();
+    if !inside || pixel_mask[pix_id] == 0 {
+        done = true;
+        atomicAdd(&done_count, 1u);
+    }
+#endif
+
     // each thread loads one gaussian at a time before rasterizing its
     // designated pixel
     for (var b = 0u; b < num_batches; b++) {
@@ -86,6 +173,9 @@ fn main(
             let load_isect_id = batch_start + local_idx;
             let compact_gid = compact_gid_from_isect[load_isect_id];
             local_batch[local_idx] = projected_splats[compact_gid];
+            #ifdef PIXEL_RESORT
+                local_depth_grads[local_idx] = depth_grads[compact_gid];
+            #endif
 
             // Visibility is written to global ID's.
             #ifdef BWD_INFO
@@ -104,19 +194,142 @@ fn main(
 
             let delta = xy - pixel_coord;
             let sigma = 0.5f * (conic.x * delta.x * delta.x + conic.z * delta.y * delta.y) + conic.y * delta.x * delta.y;
-            let alpha = min(0.999f, color.a * exp(-sigma));
+            #ifdef ALPHA_GAMMA
+                let alpha = pow(min(0.999f, color.a * exp(-sigma)), uniforms.alpha_gamma);
+            #else
+                let alpha = min(0.999f, color.a * exp(-sigma));
+            #endif
 
             if (sigma < 0.0f || alpha < 1.0f / 255.0f) {
                 continue;
             }
 
+            #ifdef PIXEL_RESORT
+                let key = helpers::pixel_depth(projected, local_depth_grads[t], pixel_coord);
+                let splat_rgb = max(color.rgb, vec3f(0.0));
+                #ifdef DISPARITY
+                    let splat_depth = 1.0 / projected.depth;
+                #else
+                    let splat_depth = projected.depth;
+                #endif
+
+                #ifdef BWD_INFO
+                    visible[load_gid[t]] = 1.0;
+                #endif
+                final_idx = batch_start + t + 1;
+
+                // Once the window is full, blend the nearest of the window and this splat, and
+                // keep the others in the window. Ties keep the tile order.
+                var insert = window_len;
+                if window_len == uniforms.resort_window {
+                    if key < window_key[0] {
+                        if blend(&T, &pix_out, &depth_out, alpha, splat_rgb, splat_depth) {
+                            atomicAdd(&done_count, 1u);
+                            done = true;
+                            break;
+                        }
+                        continue;
+                    }
+                    let front_alpha = window_alpha[0];
+                    let front_rgb = window_rgb[0];
+                    let front_depth = window_depth[0];
+                    for (var i = 1u; i < window_len; i++) {
+                        window_key[i - 1u] = window_key[i];
+                        window_alpha[i - 1u] = window_alpha[i];
+                        window_rgb[i - 1u] = window_rgb[i];
+                        window_depth[i - 1u] = window_depth[i];
+                    }
+                    window_len -= 1u;
+                    insert = window_len;
+                    if blend(&T, &pix_out, &depth_out, front_alpha, front_rgb, front_depth) {
+                        atomicAdd(&done_count, 1u);
+                        done = true;
+                        break;
+                    }
+                }
+
+                // Insertion sort the splat into the window.
+                while insert > 0u && window_key[insert - 1u] > key {
+                    window_key[insert] = window_key[insert - 1u];
+                    window_alpha[insert] = window_alpha[insert - 1u];
+                    window_rgb[insert] = window_rgb[insert - 1u];
+                    window_depth[insert] = window_depth[insert - 1u];
+                    insert -= 1u;
+                }
+                window_key[insert] = key;
+                window_alpha[insert] = alpha;
+                window_rgb[insert] = splat_rgb;
+                window_depth[insert] = splat_depth;
+                window_len += 1u;
+                continue;
+            #endif
+
+            #ifdef DEPTH_PEEL
+                if num_layers < uniforms.peel_layers {
+                    let splat_rgb = max(color.rgb, vec3f(0.0));
+                    #ifdef DISPARITY
+                        let splat_depth = 1.0 / projected.depth;
+                    #else
+                        let splat_depth = projected.depth;
+                    #endif
+
+                    let opens_layer = projected.depth > layer_front * (1.0 + helpers::PEEL_DEPTH_TOLERANCE);
+                    if layer_alpha > 0.0 && opens_layer {
+                        // This splat is behind the current layer, so the layer is complete.
+                        let layer_done = blend(&T, &pix_out, &depth_out, 1.0 - layer_T, layer_rgb / layer_alpha, layer_depth / layer_alpha);
+                        layer_alpha = 0.0;
+                        if layer_done {
+                            atomicAdd(&done_count, 1u);
+                            done = true;
+                            break;
+                        }
+                        num_layers += 1u;
+                    }
+
+                    // Past the peeled layers, splats are blended one by one.
+                    if num_layers == uniforms.peel_layers && blend(&T, &pix_out, &depth_out, alpha, splat_rgb, splat_depth) {
+                        atomicAdd(&done_count, 1u);
+                        done = true;
+                        break;
+                    }
+
+                    #ifdef BWD_INFO
+                        visible[load_gid[t]] = 1.0;
+                    #endif
+                    final_idx = batch_start + t + 1;
+
+                    if num_layers == uniforms.peel_layers {
+                        continue;
+                    }
+
+                    if layer_alpha == 0.0 {
+                        layer_front = projected.depth;
+                        layer_T = 1.0;
+                        layer_rgb = vec3f(0.0);
+                        layer_depth = 0.0;
+                    }
+                    layer_T *= 1.0 - alpha;
+                    layer_alpha += alpha;
+                    layer_rgb += alpha * splat_rgb;
+                    layer_depth += alpha * splat_depth;
+                    continue;
+                }
+            #endif
+
             let next_T = T * (1.0 - alpha);
 
-            if next_T <= 1e-4f {
-                atomicAdd(&done_count, 1u);
-                done = true;
-                break;
-            }
+            // Only blending over occludes, other blend modes blend every splat.
+            #ifdef BLEND_ADD
+            #else
+            #ifdef BLEND_MAX
+            #else
+                if next_T <= uniforms.transmittance_threshold {
+                    atomicAdd(&done_count, 1u);
+                    done = true;
+                    break;
+                }
+            #endif
+            #endif
 
             #ifdef BWD_INFO
                 let gid = load_gid[t];
@@ -125,7 +338,20 @@ fn main(
 
             let vis = alpha * T;
             let clamped_rgb = max(color.rgb, vec3f(0.0));
-            pix_out += clamped_rgb * vis;
+            #ifdef BLEND_ADD
+                pix_out += clamped_rgb * alpha;
+            #else
+            #ifdef BLEND_MAX
+                pix_out = max(pix_out, clamped_rgb * alpha);
+            #else
+                pix_out += clamped_rgb * vis;
+            #endif
+            #endif
+            #ifdef DISPARITY
+                depth_out += vis / projected.depth;
+            #else
+                depth_out += projected.depth * vis;
+            #endif
             T = next_T;
 
             let isect_id = batch_start + t;
@@ -133,14 +359,85 @@ fn main(
         }
     }
 
+#ifdef PIXEL_RESORT
+    // Blend the splats still held back, front to back.
+    if !done {
+        for (var i = 0u; i < window_len; i++) {
+            if blend(&T, &pix_out, &depth_out, window_alpha[i], window_rgb[i], window_depth[i]) {
+                break;
+            }
+        }
+    }
+#endif
+
+#ifdef DEPTH_PEEL
+    // Blend the last layer, if the pixel ran out of splats while collecting it.
+    if layer_alpha > 0.0 {
+        _ = blend(&T, &pix_out, &depth_out, 1.0 - layer_T, layer_rgb / layer_alpha, layer_depth / layer_alpha);
+    }
+#endif
+
     if inside {
-        let img_alpha = (1.0 - T);
-        let final_color = vec4f(pix_out, img_alpha);
+        var img_alpha = (1.0 - T);
+
+        #ifdef BACKGROUND_IMAGE
+            // The background shows through by the remaining transmittance, and covers the pixel.
+            let bg_base = pix_id * 3u;
+            let background = vec3f(background_img[bg_base], background_img[bg_base + 1u], background_img[bg_base + 2u]);
+            pix_out += T * background;
+            img_alpha = 1.0;
+        #endif
+
+        var final_color = vec4f(pix_out, img_alpha);
+
+        #ifdef LINEAR_OUTPUT
+            // Colors are premultiplied by alpha, so decode the straight color.
+            if img_alpha > 0.0 {
+                final_color = vec4f(helpers::srgb_to_linear(pix_out / img_alpha) * img_alpha, img_alpha);
+            }
+        #endif
+
+        #ifdef STRAIGHT_ALPHA
+            // Undo the premultiplication of blending, leaving uncovered pixels black.
+            if img_alpha > 0.0 {
+                final_color = vec4f(final_color.rgb / img_alpha, img_alpha);
+            }
+        #endif
+
+        #ifdef BGRA_OUTPUT
+            final_color = final_color.bgra;
+        #endif
 
         #ifdef BWD_INFO
-            out_img[pix_id] = final_color;
+            #ifdef F16_OUTPUT
+                out_img[pix_id] = vec2u(pack2x16float(final_color.xy), pack2x16float(final_color.zw));
+            #else
+                #ifdef GRAY_OUTPUT
+                    let gray = dot(final_color.rgb, helpers::LUMA_WEIGHTS);
+                    #ifdef DEPTH_OUTPUT
+                        let base = pix_id * 3u;
+                        out_img[base + 0u] = gray;
+                        out_img[base + 1u] = final_color.a;
+                        out_img[base + 2u] = depth_out;
+                    #else
+                        out_img[pix_id] = vec2f(gray, final_color.a);
+                    #endif
+                #else
+                    #ifdef DEPTH_OUTPUT
+                        let base = pix_id * 5u;
+                        out_img[base + 0u] = final_color.r;
+                        out_img[base + 1u] = final_color.g;
+                        out_img[base + 2u] = final_color.b;
+                        out_img[base + 3u] = final_color.a;
+                        out_img[base + 4u] = depth_out;
+                    #else
+                        out_img[pix_id] = final_color;
+                    #endif
+                #endif
+            #endif
             final_index[pix_id] = i32(final_idx);
         #else
+            // Nb: Must match the packing in read_image::pack_color.
             let colors_u = vec4u(clamp(final_color * 255.0, vec4f(0.0), vec4f(255.0)));
             let packed: u32 = colors_u.x | (colors_u.y << 8u) | (colors_u.z << 16u) | (colors_u.w << 24u);
             out_img[pix_id] = packed;
@@ -0,0 +1,99 @@
+#import helpers
+
+// Finds the splat that contributes most to each pixel, for picking. This blends like rasterize,
+// but only tracks the blending weights, so is cheaper than rendering colors.
+
+@group(0) @binding(0) var<storage, read> uniforms: helpers::RenderUniforms;
+@group(0) @binding(1) var<storage, read> compact_gid_from_isect: array<i32>;
+@group(0) @binding(2) var<storage, read> tile_offsets: array<i32>;
+@group(0) @binding(3) var<storage, read> projected_splats: array<helpers::ProjectedSplat>;
+@group(0) @binding(4) var<storage, read> global_from_compact_gid: array<i32>;
+// Global id of the splat with the highest weight in each pixel, or -1 for empty pixels.
+@group(0) @binding(5) var<storage, read_write> out_ids: array<i32>;
+
+var<workgroup> local_batch: array<helpers::ProjectedSplat, helpers::TILE_SIZE>;
+var<workgroup> local_gid: array<i32, helpers::TILE_SIZE>;
+
+var<workgroup> done_count: atomic<u32>;
+var<workgroup> done_count_uniform: u32;
+
+@compute
+@workgroup_size(helpers::TILE_WIDTH, helpers::TILE_WIDTH, 1)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3u,
+    @builtin(local_invocation_index) local_idx: u32,
+    @builtin(workgroup_id) workgroup_id: vec3u,
+) {
+    let img_size = uniforms.img_size;
+    let pix = global_id.xy + vec2u(0u, uniforms.tile_row_offset * helpers::TILE_WIDTH);
+    let pix_id = helpers::output_pixel_id(pix, img_size, uniforms.flip_y);
+    let tile_id = workgroup_id.x + workgroup_id.y * uniforms.tile_bounds.x;
+    let pixel_coord = vec2f(pix) + 0.5;
+
+    let inside = pix.x < img_size.x && pix.y < img_size.y;
+    var done = !inside;
+
+    var range = vec2u(
+        u32(clamp(tile_offsets[tile_id], 0, i32(uniforms.max_intersects))),
+        u32(clamp(tile_offsets[tile_id + 1], 0, i32(uniforms.max_intersects)))
+    );
+    if uniforms.tile_budget > 0u {
+        range.y = min(range.y, range.x + uniforms.tile_budget);
+    }
+    let num_batches = helpers::ceil_div(range.y - range.x, u32(helpers::TILE_SIZE));
+
+    var T = 1.0;
+    var best_weight = 0.0;
+    var best_id = -1;
+
+    atomicStore(&done_count, 0u);
+
+    for (var b = 0u; b < num_batches; b++) {
+        let batch_start = range.x + b * helpers::TILE_SIZE;
+
+        done_count_uniform = atomicLoad(&done_count);
+        if workgroupUniformLoad(&done_count_uniform) >= helpers::TILE_SIZE {
+            break;
+        }
+
+        let remaining = min(helpers::TILE_SIZE, range.y - batch_start);
+        if local_idx < remaining {
+            let compact_gid = compact_gid_from_isect[batch_start + local_idx];
+            local_batch[local_idx] = projected_splats[compact_gid];
+            local_gid[local_idx] = global_from_compact_gid[compact_gid];
+        }
+        workgroupBarrier();
+
+        for (var t = 0u; t < remaining && !done; t++) {
+            let projected = local_batch[t];
+            let xy = vec2f(projected.xy_x, projected.xy_y);
+            let conic = vec3f(projected.conic_x, projected.conic_y, projected.conic_z);
+
+            let sigma = helpers::calc_sigma(pixel_coord, conic, xy);
+            let alpha = min(0.999f, projected.color_a * exp(-sigma));
+            if sigma < 0.0f || alpha < 1.0f / 255.0f {
+                continue;
+            }
+
+            let next_T = T * (1.0 - alpha);
+            if next_T <= uniforms.transmittance_threshold {
+                atomicAdd(&done_count, 1u);
+                done = true;
+                break;
+            }
+
+            // The remaining transmittance only shrinks, so the first splat with the highest
+            // weight wins ties, which is the front-most one.
+            let weight = alpha * T;
+            if weight > best_weight {
+                best_weight = weight;
+                best_id = local_gid[t];
+            }
+            T = next_T;
+        }
+    }
+
+    if inside {
+        out_ids[pix_id] = best_id;
+    }
+}
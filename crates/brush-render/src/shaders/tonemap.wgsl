@@ -0,0 +1,65 @@
+#import helpers;
+
+struct Uniforms {
+    num_pixels: u32,
+    // Channels per pixel. RGBA comes first, any further channels are copied unchanged.
+    channels: u32,
+    pad_a: u32,
+    pad_b: u32,
+}
+
+@group(0) @binding(0) var<storage, read> uniforms: Uniforms;
+@group(0) @binding(1) var<storage, read> in_img: array<f32>;
+@group(0) @binding(2) var<storage, read_write> out_img: array<f32>;
+
+// Fit of the ACES filmic curve by Krzysztof Narkowicz, see
+// https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/
+fn aces(x: vec3f) -> vec3f {
+    let mapped = (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+    return clamp(mapped, vec3f(0.0), vec3f(1.0));
+}
+
+fn reinhard(x: vec3f) -> vec3f {
+    return x / (1.0 + x);
+}
+
+@compute
+@workgroup_size(helpers::MAIN_WG, 1, 1)
+fn main(@builtin(global_invocation_id) global_id: vec3u) {
+    let pix_id = global_id.x;
+    if pix_id >= uniforms.num_pixels {
+        return;
+    }
+
+    let base = pix_id * uniforms.channels;
+    let alpha = in_img[base + 3u];
+    var color = max(vec3f(in_img[base], in_img[base + 1u], in_img[base + 2u]), vec3f(0.0));
+
+    #ifdef PREMULTIPLIED
+        // Tonemap the color of the splats, not their color times coverage.
+        if alpha > 0.0 {
+            color /= alpha;
+        }
+    #endif
+
+    #ifdef ACES
+        color = aces(color);
+    #else
+        color = reinhard(color);
+    #endif
+
+    #ifdef SRGB_OUTPUT
+        color = helpers::linear_to_srgb(color);
+    #endif
+
+    #ifdef PREMULTIPLIED
+        color *= alpha;
+    #endif
+
+    out_img[base] = color.r;
+    out_img[base + 1u] = color.g;
+    out_img[base + 2u] = color.b;
+    for (var c = 3u; c < uniforms.channels; c++) {
+        out_img[base + c] = in_img[base + c];
+    }
+}
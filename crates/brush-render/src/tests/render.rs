@@ -1,10 +1,80 @@
-use crate::{SplatForward, camera::Camera};
+#[cfg(feature = "gpu_timestamps")]
+use crate::render::StageTimer;
+use crate::{
+    MainBackendBase, SplatForward,
+    camera::{Camera, ImageOrigin},
+    cpu_reference::render_reference,
+    cubemap::{CUBEMAP_FACES, cubemap_face_uv},
+    gaussian_splats::{OpacityActivation, Splats},
+    lod::SplatLod,
+    mask::polygon_mask,
+    read_image::{read_image_u8, unpack_color},
+    render::{
+        RenderContext, RenderInput, project_and_sort, rasterize_stage, render_forward,
+        render_forward_banded, render_forward_depth, render_forward_ids, render_forward_into,
+        render_forward_partial, render_forward_sh_degrees, render_forward_with_context,
+        tile_sort_bits, with_thread_full_pipeline,
+    },
+    render_options::{
+        AlphaGamma, AlphaMode, BlendMode, ChannelOrder, ClampPolicy, ClipPlane, ColorSpace,
+        DEFAULT_TRANSMITTANCE_THRESHOLD, DepthKey, InvalidRenderOption, MAX_RESORT_WINDOW,
+        OutputDType, RenderOptions, ResortWindow, ShBasis, TransmittanceThreshold, WorldScale,
+        WorldTransform,
+    },
+    sh::{opacity_to_sh, planar_channel_sh, rgb_to_sh},
+    shaders::helpers::ProjectedSplat,
+    tuning::{
+        DEFAULT_SPLAT_WORKGROUP_SIZE, SPLAT_WORKGROUP_SIZES, with_thread_splat_workgroup_size,
+    },
+};
 use assert_approx_eq::assert_approx_eq;
-use burn::tensor::{Tensor, TensorPrimitive};
-use burn_wgpu::{Wgpu, WgpuDevice};
+use burn::prelude::Backend;
+use burn::tensor::{DType, Distribution, ElementConversion, Int, Tensor, TensorPrimitive, s};
+use burn_cubecl::cubecl::future::block_on;
+#[cfg(feature = "gpu_timestamps")]
+use burn_cubecl::cubecl::{Runtime, profile::TimingMethod};
+use burn_wgpu::{CubeTensor, Wgpu, WgpuDevice, WgpuRuntime};
+use std::num::NonZeroU32;
 
 type Back = Wgpu;
 
+/// The means, log scales, quats, sh coefficients and raw opacities of some splats.
+type SplatTensors<B> = (
+    Tensor<B, 2>,
+    Tensor<B, 2>,
+    Tensor<B, 2>,
+    Tensor<B, 3>,
+    Tensor<B, 1>,
+);
+
+/// `num_points` small splats with random colors, spread around the origin. The splats aren't
+/// rotated, and are half transparent.
+fn random_splats<B: Backend>(
+    num_points: usize,
+    sh_coeffs: usize,
+    device: &B::Device,
+) -> SplatTensors<B> {
+    let means = Tensor::random([num_points, 3], Distribution::Uniform(-1.0, 1.0), device);
+    let log_scales = Tensor::ones([num_points, 3], device) * -2.0;
+    let quats = Tensor::<B, 1>::from_floats(glam::Quat::IDENTITY.to_array(), device)
+        .unsqueeze_dim(0)
+        .repeat_dim(0, num_points);
+    let sh_coeffs = Tensor::random([num_points, sh_coeffs, 3], Distribution::Default, device);
+    let raw_opacity = Tensor::zeros([num_points], device);
+    (means, log_scales, quats, sh_coeffs, raw_opacity)
+}
+
+/// A camera looking at the origin from 4 units away, which sees all of [`random_splats`].
+fn test_camera() -> Camera {
+    Camera::new(
+        glam::vec3(0.0, 0.0, -4.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.6,
+        glam::vec2(0.5, 0.5),
+    )
+}
+
 #[test]
 fn renders_at_all() {
     // Check if rendering doesn't hard crash or anything.
@@ -37,6 +107,7 @@ fn renders_at_all() {
         sh_coeffs.into_primitive().tensor(),
         raw_opacity.into_primitive().tensor(),
         true,
+        &RenderOptions::default(),
     );
     aux.debug_assert_valid();
 
@@ -52,3 +123,4708 @@ fn renders_at_all() {
     assert_approx_eq!(rgb_mean, 0.0, 1e-5);
     assert_approx_eq!(alpha_mean, 0.0);
 }
+
+/// Measures how much the shape of a small splat changes while moving the camera towards it.
+///
+/// Returns the coefficient of variation of the peak alpha relative to the total alpha across frames.
+fn small_splat_flicker(options: &RenderOptions) -> f32 {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+
+    // A single splat, a bit smaller than a pixel on screen.
+    let means = Tensor::<Back, 2>::from_floats([[0.3, 0.2, 4.0]], &device);
+    let log_scales = Tensor::<Back, 2>::from_floats([[0.02f32.ln(); 3]], &device);
+    let quats = Tensor::<Back, 2>::from_floats([glam::Quat::IDENTITY.to_array()], &device);
+    let sh_coeffs = Tensor::<Back, 3>::ones([1, 1, 3], &device);
+    let opacity = Tensor::<Back, 1>::ones([1], &device);
+
+    let ratios: Vec<f32> = (0..40)
+        .map(|i| {
+            let cam = Camera::new(
+                glam::vec3(0.0, 0.0, i as f32 / 40.0),
+                glam::Quat::IDENTITY,
+                0.6,
+                0.6,
+                glam::vec2(0.5, 0.5),
+            );
+            let (output, _) = <Back as SplatForward<Back>>::render_splats(
+                &cam,
+                img_size,
+                means.clone().into_primitive().tensor(),
+                log_scales.clone().into_primitive().tensor(),
+                quats.clone().into_primitive().tensor(),
+                sh_coeffs.clone().into_primitive().tensor(),
+                opacity.clone().into_primitive().tensor(),
+                true,
+                options,
+            );
+            let output: Tensor<Back, 3> = Tensor::from_primitive(TensorPrimitive::Float(output));
+            let alpha = output.slice([0..32, 0..32, 3..4]);
+            let max = alpha.clone().max().into_scalar();
+            let sum = alpha.sum().into_scalar();
+            max / sum
+        })
+        .collect();
+
+    let mean = ratios.iter().sum::<f32>() / ratios.len() as f32;
+    let var = ratios.iter().map(|r| (r - mean).powi(2)).sum::<f32>() / ratios.len() as f32;
+    var.sqrt() / mean
+}
+
+#[test]
+fn mip_filter_reduces_flicker() {
+    let unfiltered = small_splat_flicker(&RenderOptions::default());
+    let filtered = small_splat_flicker(&RenderOptions {
+        mip_filter: true,
+        ..Default::default()
+    });
+    assert!(
+        filtered < unfiltered,
+        "Mip filter should reduce flicker, {filtered} >= {unfiltered}"
+    );
+}
+
+fn batch_matches_single<B: Backend + SplatForward<B>>(device: &B::Device) {
+    let img_size = glam::uvec2(32, 24);
+    let (means, log_scales, quats, sh_coeffs, raw_opacity) = random_splats::<B>(16, 1, device);
+    let cameras: Vec<_> = (0..3)
+        .map(|i| Camera {
+            position: glam::vec3(0.1 * i as f32, 0.0, -4.0),
+            ..test_camera()
+        })
+        .collect();
+
+    let (batch, auxes) = B::render_splats_batch(
+        &cameras,
+        img_size,
+        means.clone().into_primitive().tensor(),
+        log_scales.clone().into_primitive().tensor(),
+        quats.clone().into_primitive().tensor(),
+        sh_coeffs.clone().into_primitive().tensor(),
+        raw_opacity.clone().into_primitive().tensor(),
+        true,
+        &RenderOptions::default(),
+    );
+    let batch: Tensor<B, 4> = Tensor::from_primitive(TensorPrimitive::Float(batch));
+    assert_eq!(batch.dims(), [3, 24, 32, 4]);
+    assert_eq!(auxes.len(), 3);
+
+    for (i, (cam, aux)) in cameras.iter().zip(auxes).enumerate() {
+        let (single, single_aux) = B::render_splats(
+            cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            raw_opacity.clone().into_primitive().tensor(),
+            true,
+            &RenderOptions::default(),
+        );
+        let single: Tensor<B, 3> = Tensor::from_primitive(TensorPrimitive::Float(single));
+        let view: Tensor<B, 3> = batch.clone().slice([i..i + 1]).squeeze(0);
+        let diff = (view - single).abs().max().into_scalar().elem::<f32>();
+        assert!(
+            diff < 1e-6,
+            "View {i} differs from a single render by {diff}"
+        );
+        // Each view keeps its own results, which later views don't overwrite.
+        assert_eq!(
+            aux.num_intersections().into_scalar().elem::<i32>(),
+            single_aux.num_intersections().into_scalar().elem::<i32>(),
+            "View {i} has different intersections than a single render"
+        );
+    }
+}
+
+#[test]
+fn render_batch_matches_single() {
+    let device = WgpuDevice::DefaultDevice;
+    batch_matches_single::<Back>(&device);
+    batch_matches_single::<MainBackendBase>(&device);
+}
+
+#[test]
+fn render_context_matches_fresh_render() {
+    type Base = MainBackendBase;
+
+    let device = WgpuDevice::DefaultDevice;
+    let num_points = 16;
+    let (means, log_scales, quats, sh_coeffs, raw_opacity) =
+        random_splats::<Base>(num_points, 1, &device);
+
+    let mut context = RenderContext::new();
+
+    // Render a few frames, changing the image size inbetween so buffers are reallocated.
+    for (i, img_size) in [
+        glam::uvec2(32, 24),
+        glam::uvec2(32, 24),
+        glam::uvec2(16, 40),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let cam = Camera::new(
+            glam::vec3(0.1 * i as f32, 0.0, -4.0),
+            glam::Quat::IDENTITY,
+            0.8,
+            0.6,
+            glam::vec2(0.5, 0.5),
+        );
+
+        let (reused, _) = render_forward_with_context(
+            &mut context,
+            &cam,
+            img_size,
+            RenderInput::new(
+                means.clone().into_primitive().tensor(),
+                log_scales.clone().into_primitive().tensor(),
+                quats.clone().into_primitive().tensor(),
+                sh_coeffs.clone().into_primitive().tensor(),
+                raw_opacity.clone().into_primitive().tensor(),
+            ),
+            true,
+            &RenderOptions::default(),
+        );
+        let reused: Tensor<Base, 3> = Tensor::from_primitive(TensorPrimitive::Float(reused));
+
+        let (fresh, _) = render_forward(
+            &cam,
+            img_size,
+            RenderInput::new(
+                means.clone().into_primitive().tensor(),
+                log_scales.clone().into_primitive().tensor(),
+                quats.clone().into_primitive().tensor(),
+                sh_coeffs.clone().into_primitive().tensor(),
+                raw_opacity.clone().into_primitive().tensor(),
+            ),
+            true,
+            &RenderOptions::default(),
+        );
+        let fresh: Tensor<Base, 3> = Tensor::from_primitive(TensorPrimitive::Float(fresh));
+
+        assert_eq!(reused.dims(), [img_size.y as usize, img_size.x as usize, 4]);
+        let diff = (reused - fresh).abs().max().into_scalar().elem::<f32>();
+        assert!(
+            diff < 1e-6,
+            "Frame {i} differs from a fresh render by {diff}"
+        );
+    }
+}
+
+#[test]
+fn linear_output_decodes_srgb() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(16, 16);
+
+    // A large opaque splat covering the whole image, with a flat color.
+    let means = Tensor::<Back, 2>::from_floats([[0.0, 0.0, 2.0]], &device);
+    let log_scales = Tensor::<Back, 2>::from_floats([[10.0f32.ln(); 3]], &device);
+    let quats = Tensor::<Back, 2>::from_floats([glam::Quat::IDENTITY.to_array()], &device);
+    // SH coefficients are offset by 0.5 when converted to colors, so this gives a color of 0.5.
+    let sh_coeffs = Tensor::<Back, 3>::zeros([1, 1, 3], &device);
+    let opacity = Tensor::<Back, 1>::ones([1], &device) * 10.0;
+
+    let cam = Camera::new(
+        glam::Vec3::ZERO,
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let render = |color_space| {
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            opacity.clone().into_primitive().tensor(),
+            true,
+            &RenderOptions {
+                color_space,
+                ..Default::default()
+            },
+        );
+        let output: Tensor<Back, 3> = Tensor::from_primitive(TensorPrimitive::Float(output));
+        output
+            .slice([8..9, 8..9, 0..4])
+            .into_data()
+            .to_vec::<f32>()
+            .expect("Wrong type")
+    };
+
+    let srgb = render(ColorSpace::Srgb);
+    let linear = render(ColorSpace::Linear);
+
+    let alpha = srgb[3];
+    assert_approx_eq!(linear[3], alpha, 1e-6);
+    for c in 0..3 {
+        let straight = srgb[c] / alpha;
+        let expected = ((straight + 0.055) / 1.055).powf(2.4) * alpha;
+        assert_approx_eq!(linear[c], expected, 1e-4);
+    }
+}
+
+#[test]
+fn f16_output_matches_f32() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 24);
+    let num_points = 32;
+    let (means, log_scales, quats, sh_coeffs, raw_opacity) =
+        random_splats::<Back>(num_points, 1, &device);
+    let cam = test_camera();
+
+    let render = |output_dtype| {
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            raw_opacity.clone().into_primitive().tensor(),
+            true,
+            &RenderOptions {
+                output_dtype,
+                ..Default::default()
+            },
+        );
+        let output: Tensor<Back, 3> = Tensor::from_primitive(TensorPrimitive::Float(output));
+        output.into_data()
+    };
+
+    let full = render(OutputDType::F32);
+    let half = render(OutputDType::F16);
+    assert_eq!(half.dtype, DType::F16);
+    assert_eq!(half.shape, full.shape);
+
+    let full = full.to_vec::<f32>().expect("Wrong type");
+    let half = half.convert::<f32>().to_vec::<f32>().expect("Wrong type");
+    for (f, h) in full.iter().zip(&half) {
+        // Half floats have 11 bits of precision.
+        assert!(
+            (f - h).abs() <= f.abs() * 1e-3 + 1e-6,
+            "F16 value {h} doesn't match F32 value {f}"
+        );
+    }
+}
+
+#[test]
+fn read_image_packed_matches_float() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 24);
+    let num_points = 32;
+    let (means, log_scales, quats, sh_coeffs, raw_opacity) =
+        random_splats::<Back>(num_points, 1, &device);
+    let cam = test_camera();
+
+    let render = |bwd_info| {
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            raw_opacity.clone().into_primitive().tensor(),
+            bwd_info,
+            &RenderOptions::default(),
+        );
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+    };
+
+    let packed = read_image_u8(render(false));
+    let float = read_image_u8(render(true));
+    assert_eq!((packed.width, packed.height, packed.channels), (32, 24, 4));
+    assert_eq!(packed.data.len(), float.data.len());
+
+    // The packed image truncates rather than rounds, so allow for one step of difference.
+    for (p, f) in packed.data.iter().zip(&float.data) {
+        assert!(
+            p.abs_diff(*f) <= 1,
+            "Packed value {p} doesn't match float value {f}"
+        );
+    }
+}
+
+#[test]
+fn renders_in_channel_order() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 24);
+    let num_points = 32;
+    let (means, log_scales, quats, sh_coeffs, _) = random_splats::<Back>(num_points, 1, &device);
+    let opacity = Tensor::<Back, 1>::ones([num_points], &device) * 0.8;
+    let cam = test_camera();
+
+    let render = |bwd_info, channel_order| {
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            opacity.clone().into_primitive().tensor(),
+            bwd_info,
+            &RenderOptions {
+                channel_order,
+                ..Default::default()
+            },
+        );
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output)).into_data()
+    };
+    let packed = |order| -> Vec<u32> {
+        render(false, order)
+            .as_bytes()
+            .chunks_exact(4)
+            .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    };
+
+    let rgba = packed(ChannelOrder::Rgba);
+    let bgra = packed(ChannelOrder::Bgra);
+    for (&rgba, &bgra) in rgba.iter().zip(&bgra) {
+        let [r, g, b, a] = unpack_color(rgba, ChannelOrder::Rgba);
+        assert_eq!(bgra.to_le_bytes(), [b, g, r, a]);
+        assert_eq!(unpack_color(bgra, ChannelOrder::Bgra), [r, g, b, a]);
+    }
+    assert!(
+        rgba.iter()
+            .any(|&p| p.to_le_bytes()[0] != p.to_le_bytes()[2]),
+        "The render must have pixels where red and blue differ"
+    );
+
+    let rgba = render(true, ChannelOrder::Rgba)
+        .into_vec::<f32>()
+        .expect("Wrong type");
+    let bgra = render(true, ChannelOrder::Bgra)
+        .into_vec::<f32>()
+        .expect("Wrong type");
+    for (rgba, bgra) in rgba.chunks_exact(4).zip(bgra.chunks_exact(4)) {
+        assert_eq!(bgra, [rgba[2], rgba[1], rgba[0], rgba[3]]);
+    }
+}
+
+#[test]
+fn frustum_cull_matches_unculled() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(48, 32);
+
+    // Splats all around the camera, so most of them are outside of the frustum.
+    let num_points = 2048;
+    let means =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-10.0, 10.0), &device);
+    let log_scales =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-4.0, 0.0), &device);
+    let quats = Tensor::<Back, 2>::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    let sh_coeffs = Tensor::<Back, 3>::random([num_points, 1, 3], Distribution::Default, &device);
+    let raw_opacity =
+        Tensor::<Back, 1>::random([num_points], Distribution::Uniform(-2.0, 2.0), &device);
+
+    let cam = Camera::new(
+        glam::vec3(0.5, -0.3, 1.0),
+        glam::Quat::from_euler(glam::EulerRot::YXZ, 0.7, 0.2, 0.1),
+        0.9,
+        0.6,
+        glam::vec2(0.45, 0.55),
+    );
+
+    let render = |frustum_cull| {
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            raw_opacity.clone().into_primitive().tensor(),
+            true,
+            &RenderOptions {
+                frustum_cull,
+                ..Default::default()
+            },
+        );
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+    };
+
+    let culled = render(true);
+    let unculled = render(false);
+    let diff = (culled - unculled).abs().max().into_scalar().elem::<f32>();
+    assert!(diff < 1e-6, "Culled render differs by {diff}");
+}
+
+#[test]
+fn intersections_sorted_by_depth_per_tile() {
+    let device = WgpuDevice::DefaultDevice;
+    // A 1080p image has 8160 tiles, which leaves only 19 bits of a 32 bit key for the depth.
+    let img_size = glam::uvec2(1920, 1080);
+    let num_points = 1024;
+    // Splats in a thin slab, so depths only differ in their lowest bits.
+    let xy = Tensor::<Back, 2>::random([num_points, 2], Distribution::Uniform(-1.0, 1.0), &device);
+    let z = Tensor::<Back, 2>::random([num_points, 1], Distribution::Uniform(-1e-3, 1e-3), &device);
+    let means = Tensor::cat(vec![xy, z], 1);
+    let log_scales = Tensor::<Back, 2>::ones([num_points, 3], &device) * -2.5;
+    let quats: Tensor<Back, 2> =
+        Tensor::<Back, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+            .unsqueeze_dim(0)
+            .repeat_dim(0, num_points);
+    let sh_coeffs = Tensor::<Back, 3>::ones([num_points, 1, 3], &device);
+    let raw_opacity = Tensor::<Back, 1>::zeros([num_points], &device);
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.6,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let (_, aux) = <Back as SplatForward<Back>>::render_splats(
+        &cam,
+        img_size,
+        means.into_primitive().tensor(),
+        log_scales.into_primitive().tensor(),
+        quats.into_primitive().tensor(),
+        sh_coeffs.into_primitive().tensor(),
+        raw_opacity.into_primitive().tensor(),
+        true,
+        &RenderOptions::default(),
+    );
+    aux.debug_assert_valid();
+
+    let read_ints = |tensor| {
+        Tensor::<Back, 1, burn::tensor::Int>::from_primitive(tensor)
+            .into_data()
+            .to_vec::<i32>()
+            .expect("Wrong type")
+    };
+    let tile_offsets = read_ints(aux.tile_offsets.clone());
+    let compact_gid_from_isect = read_ints(aux.compact_gid_from_isect.clone());
+
+    // Compare the depths the GPU sorted on, instead of recomputing them.
+    let projected: Vec<f32> =
+        Tensor::<Back, 2>::from_primitive(TensorPrimitive::Float(aux.projected_splats.clone()))
+            .into_data()
+            .to_vec()
+            .expect("Wrong type");
+    let row = size_of::<ProjectedSplat>() / size_of::<f32>();
+    let depth_offset = std::mem::offset_of!(ProjectedSplat, depth) / size_of::<f32>();
+    let depth = |compact_gid: i32| projected[compact_gid as usize * row + depth_offset];
+
+    let mut num_checked = 0;
+    for range in tile_offsets.windows(2) {
+        let isects = &compact_gid_from_isect[range[0] as usize..range[1] as usize];
+        for pair in isects.windows(2) {
+            assert!(
+                depth(pair[0]) <= depth(pair[1]),
+                "Intersections in a tile must be sorted front to back"
+            );
+            num_checked += 1;
+        }
+    }
+    assert!(
+        num_checked > 0,
+        "Expected some tiles with overlapping splats"
+    );
+}
+
+#[test]
+fn visible_global_ids_skip_culled_splats() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+
+    // Even splats are in front of the camera, odd splats are behind it.
+    let num_points = 64;
+    let means_data: Vec<f32> = (0..num_points)
+        .flat_map(|i| {
+            let z = if i % 2 == 0 { 0.0 } else { -6.0 };
+            [0.01 * i as f32, 0.0, z]
+        })
+        .collect();
+    let means =
+        Tensor::<Back, 1>::from_floats(means_data.as_slice(), &device).reshape([num_points, 3]);
+    let log_scales = Tensor::<Back, 2>::ones([num_points, 3], &device) * -2.0;
+    let quats: Tensor<Back, 2> =
+        Tensor::<Back, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+            .unsqueeze_dim(0)
+            .repeat_dim(0, num_points);
+    let sh_coeffs = Tensor::<Back, 3>::ones([num_points, 1, 3], &device);
+    let raw_opacity = Tensor::<Back, 1>::zeros([num_points], &device);
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.8,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let (_, aux) = <Back as SplatForward<Back>>::render_splats(
+        &cam,
+        img_size,
+        means.into_primitive().tensor(),
+        log_scales.into_primitive().tensor(),
+        quats.into_primitive().tensor(),
+        sh_coeffs.into_primitive().tensor(),
+        raw_opacity.into_primitive().tensor(),
+        true,
+        &RenderOptions::default(),
+    );
+
+    let mut visible = aux.visible_global_ids();
+    visible.sort_unstable();
+    let expected: Vec<u32> = (0..num_points as u32).step_by(2).collect();
+    assert_eq!(visible, expected);
+}
+
+#[test]
+fn tile_budget_keeps_nearest_splats() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(48, 48);
+    let num_points = 16;
+
+    // Splats stacked behind each other, so every tile they touch sees them in the same order.
+    let means_data: Vec<f32> = (0..num_points)
+        .flat_map(|i| [0.0, 0.0, i as f32 * 0.1])
+        .collect();
+    let colors: Vec<f32> = (0..num_points)
+        .flat_map(|i| {
+            if i == 0 {
+                [1.0, 0.0, 0.0]
+            } else {
+                [0.0, 1.0, 0.0]
+            }
+        })
+        .collect();
+
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::IDENTITY,
+        0.6,
+        0.6,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let render = |count: usize, tile_budget| {
+        let means =
+            Tensor::<Back, 1>::from_floats(&means_data[..count * 3], &device).reshape([count, 3]);
+        let sh_coeffs =
+            Tensor::<Back, 1>::from_floats(&colors[..count * 3], &device).reshape([count, 1, 3]);
+        let log_scales = Tensor::<Back, 2>::ones([count, 3], &device) * -1.5;
+        let quats: Tensor<Back, 2> =
+            Tensor::<Back, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+                .unsqueeze_dim(0)
+                .repeat_dim(0, count);
+        let raw_opacity = Tensor::<Back, 1>::zeros([count], &device);
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.into_primitive().tensor(),
+            log_scales.into_primitive().tensor(),
+            quats.into_primitive().tensor(),
+            sh_coeffs.into_primitive().tensor(),
+            raw_opacity.into_primitive().tensor(),
+            true,
+            &RenderOptions {
+                tile_budget,
+                ..Default::default()
+            },
+        );
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+    };
+
+    let budgeted = render(num_points, NonZeroU32::new(1));
+    let nearest = render(1, None);
+    let diff = (budgeted.clone() - nearest)
+        .abs()
+        .max()
+        .into_scalar()
+        .elem::<f32>();
+    assert!(
+        diff < 1e-6,
+        "Budgeted render differs from nearest splat by {diff}"
+    );
+
+    // A budget larger than any tile doesn't change the image.
+    let unbudgeted = render(num_points, None);
+    let generous = render(num_points, NonZeroU32::new(num_points as u32));
+    let diff = (unbudgeted.clone() - generous)
+        .abs()
+        .max()
+        .into_scalar()
+        .elem::<f32>();
+    assert!(diff < 1e-6, "Generous budget changes the render by {diff}");
+
+    let dropped = (unbudgeted - budgeted)
+        .abs()
+        .max()
+        .into_scalar()
+        .elem::<f32>();
+    assert!(dropped > 0.0, "The budget must drop the splats behind");
+}
+
+#[test]
+fn render_context_collects_stats() {
+    type Base = MainBackendBase;
+
+    let device = WgpuDevice::DefaultDevice;
+    let num_points = 64;
+    let (means, log_scales, quats, sh_coeffs, raw_opacity) =
+        random_splats::<Base>(num_points, 1, &device);
+    let cam = test_camera();
+    let img_size = glam::uvec2(64, 40);
+
+    let mut context = RenderContext::new();
+    let render = |context: &mut RenderContext| {
+        render_forward_with_context(
+            context,
+            &cam,
+            img_size,
+            RenderInput::new(
+                means.clone().into_primitive().tensor(),
+                log_scales.clone().into_primitive().tensor(),
+                quats.clone().into_primitive().tensor(),
+                sh_coeffs.clone().into_primitive().tensor(),
+                raw_opacity.clone().into_primitive().tensor(),
+            ),
+            false,
+            &RenderOptions::default(),
+        )
+    };
+
+    render(&mut context);
+    assert!(context.last_stats().is_none(), "Stats are off by default");
+
+    context.set_collect_stats(true);
+    let (_, aux) = render(&mut context);
+    let stats = context.last_stats().expect("Stats must be collected");
+
+    assert_eq!(
+        stats.num_visible,
+        aux.num_visible().into_scalar().elem::<i32>() as u32
+    );
+    assert_eq!(
+        stats.num_intersections,
+        aux.num_intersections().into_scalar().elem::<i32>() as u32
+    );
+    assert_eq!(stats.num_tiles, 4 * 3);
+    assert!(
+        stats.max_tile_intersections > 0 && stats.max_tile_intersections <= stats.num_intersections,
+        "Invalid max tile intersections {}",
+        stats.max_tile_intersections
+    );
+    let stages: Vec<_> = stats.kernel_timings.iter().map(|(name, _)| *name).collect();
+    assert_eq!(stages.first(), Some(&"ProjectSplats"));
+    assert_eq!(stages.last(), Some(&"Rasterize"));
+    assert!(
+        cfg!(feature = "gpu_timestamps") || !stats.gpu_timings,
+        "GPU timings need the gpu_timestamps feature"
+    );
+}
+
+#[cfg(feature = "gpu_timestamps")]
+#[test]
+fn stage_timer_records_named_stages() {
+    let device = WgpuDevice::DefaultDevice;
+    let mut timer = StageTimer::start(&device);
+
+    let tensor = timer.stage("Fill", &device, || {
+        Tensor::<Back, 1>::random([1 << 16], Distribution::Default, &device)
+    });
+    let sum = timer.stage("Sum", &device, || (tensor.clone() * tensor).sum());
+    assert!(sum.into_scalar() > 0.0);
+
+    let supports_timestamps =
+        WgpuRuntime::client(&device).properties().timing_method == TimingMethod::Device;
+    assert_eq!(timer.gpu_timed(), supports_timestamps);
+
+    let timings = timer.into_timings();
+    let stages: Vec<_> = timings.iter().map(|(name, _)| *name).collect();
+    assert_eq!(stages, ["Fill", "Sum"]);
+    for (name, duration) in timings {
+        assert!(
+            duration.as_secs_f64() >= 0.0 && duration < std::time::Duration::from_secs(10),
+            "Invalid duration {duration:?} for stage {name}"
+        );
+    }
+}
+
+#[test]
+fn independent_focal_lengths_project_anisotropically() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(64, 64);
+
+    // A square image, but with twice the focal length along x.
+    let cam = Camera::from_intrinsics(
+        glam::vec3(0.0, 0.0, -4.0),
+        glam::Quat::IDENTITY,
+        glam::dvec2(160.0, 80.0),
+        glam::vec2(32.0, 32.0),
+        img_size,
+    );
+    let focal = cam.focal(img_size);
+    assert!(
+        (focal.x - 160.0).abs() < 1e-3 && (focal.y - 80.0).abs() < 1e-3,
+        "Focal {focal} doesn't match the intrinsics"
+    );
+    assert!(
+        (cam.center(img_size) - glam::vec2(32.0, 32.0)).length() < 1e-4,
+        "Center doesn't match the intrinsics"
+    );
+
+    // A single round splat in front of the camera.
+    let means = Tensor::<Back, 2>::zeros([1, 3], &device);
+    let log_scales = Tensor::<Back, 2>::ones([1, 3], &device) * -1.5;
+    let quats: Tensor<Back, 2> =
+        Tensor::<Back, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device).unsqueeze_dim(0);
+    let sh_coeffs = Tensor::<Back, 3>::ones([1, 1, 3], &device);
+    let raw_opacity = Tensor::<Back, 1>::ones([1], &device) * 5.0;
+    let (output, _) = <Back as SplatForward<Back>>::render_splats(
+        &cam,
+        img_size,
+        means.into_primitive().tensor(),
+        log_scales.into_primitive().tensor(),
+        quats.into_primitive().tensor(),
+        sh_coeffs.into_primitive().tensor(),
+        raw_opacity.into_primitive().tensor(),
+        true,
+        &RenderOptions::default(),
+    );
+    let alpha: Vec<f32> = Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+        .slice([0..64, 0..64, 3..4])
+        .into_data()
+        .to_vec()
+        .expect("Wrong type");
+
+    // Measure the footprint through the center row and column.
+    let width = (0..64).filter(|&x| alpha[32 * 64 + x] > 0.5).count() as f32;
+    let height = (0..64).filter(|&y| alpha[y * 64 + 32] > 0.5).count() as f32;
+    assert!(height > 0.0, "Splat must be visible");
+    assert!(
+        (width / height - 2.0).abs() < 0.25,
+        "Splat footprint {width}x{height} must be twice as wide as high"
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serialized_camera_renders_identically() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 24);
+    let num_points = 32;
+    let (means, log_scales, quats, sh_coeffs, raw_opacity) =
+        random_splats::<Back>(num_points, 1, &device);
+
+    let cam = Camera::new(
+        glam::vec3(0.3, -0.2, -3.5),
+        glam::Quat::from_euler(glam::EulerRot::YXZ, 0.2, -0.1, 0.05),
+        0.9,
+        0.7,
+        glam::vec2(0.45, 0.55),
+    );
+    let options = RenderOptions {
+        color_space: ColorSpace::Linear,
+        tile_budget: NonZeroU32::new(64),
+        ..Default::default()
+    };
+
+    let cam_json = serde_json::to_string_pretty(&cam).expect("Failed to serialize camera");
+    assert!(
+        cam_json.contains("focal") && !cam_json.contains("fov"),
+        "Cameras must store intrinsics, got {cam_json}"
+    );
+    let options_json = serde_json::to_string(&options).expect("Failed to serialize options");
+    let cam_loaded: Camera = serde_json::from_str(&cam_json).expect("Failed to load camera");
+    let options_loaded: RenderOptions =
+        serde_json::from_str(&options_json).expect("Failed to load options");
+
+    // Missing options use their defaults.
+    let partial: RenderOptions =
+        serde_json::from_str(r#"{ "mip_filter": true }"#).expect("Failed to load options");
+    assert!(
+        partial.mip_filter && partial.tile_budget.is_none(),
+        "Missing options must use defaults"
+    );
+    // Invalid values fail to load, instead of failing the render.
+    for invalid in [
+        r#"{ "tile_budget": 0 }"#,
+        r#"{ "transmittance_threshold": 1.5 }"#,
+        r#"{ "clip_planes": [[0.0, 0.0, 0.0, 1.0]] }"#,
+    ] {
+        assert!(
+            serde_json::from_str::<RenderOptions>(invalid).is_err(),
+            "{invalid} must be rejected"
+        );
+    }
+
+    let render = |cam: &Camera, options: &RenderOptions| {
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            raw_opacity.clone().into_primitive().tensor(),
+            true,
+            options,
+        );
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+    };
+
+    let diff = (render(&cam, &options) - render(&cam_loaded, &options_loaded))
+        .abs()
+        .max()
+        .into_scalar()
+        .elem::<f32>();
+    assert!(diff < 1e-7, "Loaded camera renders differently by {diff}");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn camera_json_stores_relative_intrinsics() {
+    let img_size = glam::uvec2(640, 480);
+    let cam: Camera = serde_json::from_str(
+        r#"{
+            "position": [0.0, 0.0, -4.0],
+            "rotation": [0.0, 0.0, 0.0, 1.0],
+            "focal": [0.5, 1.0],
+            "center": [0.5, 0.25]
+        }"#,
+    )
+    .expect("Failed to load camera");
+
+    assert_approx_eq!(cam.focal(img_size).x, 320.0, 1e-3);
+    assert_approx_eq!(cam.focal(img_size).y, 480.0, 1e-3);
+    assert_eq!(cam.center(img_size), glam::vec2(320.0, 120.0));
+    assert_eq!(cam.origin, ImageOrigin::TopLeft);
+}
+
+#[test]
+fn flat_color_matches_base_color() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+
+    let num_points = 256;
+    let means =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-0.5, 0.5), &device);
+    let log_scales = Tensor::<Back, 2>::ones([num_points, 3], &device) * -2.5;
+    let quats = Tensor::<Back, 2>::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    // Degree 3 spherical harmonics.
+    let sh_coeffs =
+        Tensor::<Back, 3>::random([num_points, 16, 3], Distribution::Normal(0.0, 0.5), &device);
+    let raw_opacity = Tensor::<Back, 1>::zeros([num_points], &device);
+
+    let cam = Camera::new(
+        glam::vec3(0.3, 0.0, -4.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let render = |sh_coeffs: Tensor<Back, 3>, flat_color: bool| {
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.into_primitive().tensor(),
+            raw_opacity.clone().into_primitive().tensor(),
+            true,
+            &RenderOptions {
+                max_sh_degree: flat_color.then_some(0),
+                ..Default::default()
+            },
+        );
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+    };
+
+    let flat = render(sh_coeffs.clone(), true);
+    let base_color = render(sh_coeffs.clone().slice([0..num_points, 0..1]), false);
+    let full = render(sh_coeffs, false);
+
+    let diff = (flat.clone() - base_color)
+        .abs()
+        .max()
+        .into_scalar()
+        .elem::<f32>();
+    assert!(diff < 1e-6, "Flat render differs from base color by {diff}");
+
+    let full_diff = (flat - full).abs().max().into_scalar().elem::<f32>();
+    assert!(
+        full_diff > 1e-3,
+        "Flat render should ignore view dependent colors"
+    );
+}
+
+#[test]
+fn opacity_activation_change_keeps_render() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+
+    let num_points = 128;
+    let splats = Splats::<Back>::from_tensor_data(
+        Tensor::random([num_points, 3], Distribution::Uniform(-0.5, 0.5), &device),
+        Tensor::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device),
+        Tensor::ones([num_points, 3], &device) * -2.5,
+        Tensor::random([num_points, 1, 3], Distribution::Default, &device),
+        Tensor::random([num_points], Distribution::Uniform(-3.0, 3.0), &device),
+    );
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -4.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let (sigmoid_img, _) = splats.render(&cam, img_size, true);
+    let sigmoid_opac = splats.opacities();
+
+    let splats = splats.with_opacity_activation(OpacityActivation::ExpDensity);
+    let opac_diff = (splats.opacities() - sigmoid_opac)
+        .abs()
+        .max()
+        .into_scalar()
+        .elem::<f32>();
+    assert!(opac_diff < 1e-5, "Opacities changed by {opac_diff}");
+
+    let (density_img, _) = splats.render(&cam, img_size, true);
+    let diff = (density_img - sigmoid_img)
+        .abs()
+        .max()
+        .into_scalar()
+        .elem::<f32>();
+    assert!(diff < 1e-4, "Render changed by {diff}");
+}
+
+#[test]
+fn straight_alpha_divides_by_alpha() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+
+    // Small translucent splats, so some pixels are partially covered and some are empty.
+    let num_points = 16;
+    let means =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-0.5, 0.5), &device);
+    let log_scales = Tensor::<Back, 2>::ones([num_points, 3], &device) * -3.0;
+    let quats = Tensor::<Back, 2>::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    let sh_coeffs = Tensor::<Back, 3>::random([num_points, 1, 3], Distribution::Default, &device);
+    let opacity = Tensor::<Back, 1>::ones([num_points], &device) * 0.6;
+
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -4.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let render = |alpha_mode| {
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            opacity.clone().into_primitive().tensor(),
+            true,
+            &RenderOptions {
+                alpha_mode,
+                ..Default::default()
+            },
+        );
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+            .into_data()
+            .to_vec::<f32>()
+            .expect("Wrong type")
+    };
+
+    let premultiplied = render(AlphaMode::Premultiplied);
+    let straight = render(AlphaMode::Straight);
+
+    let mut partial = 0;
+    for (p, s) in premultiplied.chunks(4).zip(straight.chunks(4)) {
+        assert_approx_eq!(p[3], s[3], 1e-6);
+        let alpha = p[3];
+        if alpha > 0.0 {
+            for c in 0..3 {
+                assert_approx_eq!(s[c] * alpha, p[c], 1e-5);
+            }
+            if alpha < 0.9 {
+                partial += 1;
+            }
+        } else {
+            assert!(
+                s[..3].iter().all(|&c| c.abs() < 1e-7),
+                "Empty pixels must be black"
+            );
+        }
+    }
+    assert!(partial > 0, "Expected partially covered pixels");
+}
+
+#[test]
+fn tile_occupancy_matches_intersections() {
+    let device = WgpuDevice::DefaultDevice;
+    let num_points = 64;
+    let means =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-1.0, 1.0), &device);
+    let log_scales = Tensor::<Back, 2>::ones([num_points, 3], &device) * -2.0;
+    let quats = Tensor::<Back, 2>::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    let sh_coeffs = Tensor::<Back, 3>::random([num_points, 1, 3], Distribution::Default, &device);
+    let opacity = Tensor::<Back, 1>::ones([num_points], &device) * 0.5;
+    let cam = test_camera();
+
+    let (_, aux) = <Back as SplatForward<Back>>::render_splats(
+        &cam,
+        glam::uvec2(64, 40),
+        means.into_primitive().tensor(),
+        log_scales.into_primitive().tensor(),
+        quats.into_primitive().tensor(),
+        sh_coeffs.into_primitive().tensor(),
+        opacity.into_primitive().tensor(),
+        false,
+        &RenderOptions::default(),
+    );
+    let occupancy = aux.read_tile_occupancy();
+
+    assert_eq!(occupancy.tile_bounds, glam::uvec2(4, 3));
+    assert_eq!(occupancy.counts.len(), 4 * 3);
+    assert_eq!(
+        occupancy.total(),
+        aux.num_intersections().into_scalar().elem::<i32>() as u64
+    );
+
+    let (max_pos, max_count) = occupancy.max_tile().expect("Need tiles");
+    assert_eq!(occupancy.get(max_pos.x, max_pos.y), max_count);
+    assert!(
+        occupancy.counts.iter().all(|&c| c <= max_count),
+        "Max tile must have the most intersections"
+    );
+
+    let histogram = occupancy.histogram(4);
+    assert_eq!(histogram.iter().sum::<u32>(), 4 * 3);
+    assert!(histogram[3] > 0, "The max tile falls in the last bin");
+}
+
+#[cfg(feature = "debug_heatmap")]
+#[test]
+fn tile_heatmap_follows_occupancy() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(64, 40);
+    let num_points = 64;
+    let means =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-1.0, 1.0), &device);
+    let log_scales = Tensor::<Back, 2>::ones([num_points, 3], &device) * -2.0;
+    let quats = Tensor::<Back, 2>::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    let sh_coeffs = Tensor::<Back, 3>::random([num_points, 1, 3], Distribution::Default, &device);
+    let opacity = Tensor::<Back, 1>::ones([num_points], &device) * 0.5;
+    let cam = test_camera();
+
+    let (_, aux) = <Back as SplatForward<Back>>::render_splats(
+        &cam,
+        img_size,
+        means.into_primitive().tensor(),
+        log_scales.into_primitive().tensor(),
+        quats.into_primitive().tensor(),
+        sh_coeffs.into_primitive().tensor(),
+        opacity.into_primitive().tensor(),
+        false,
+        &RenderOptions::default(),
+    );
+    let occupancy = aux.read_tile_occupancy();
+    let (max_pos, _) = occupancy.max_tile().expect("Need tiles");
+
+    let heatmap = aux.tile_heatmap(img_size, false);
+    assert_eq!(heatmap.dims(), [3, 4, 3]);
+    let heatmap = heatmap.into_data().to_vec::<f32>().expect("Wrong type");
+    for y in 0..3 {
+        for x in 0..4 {
+            let rgb = &heatmap[((y * 4 + x) * 3) as usize..][..3];
+            if (x, y) == (max_pos.x, max_pos.y) {
+                assert!(
+                    rgb.iter().all(|&c| c == 1.0),
+                    "The most occupied tile must be white"
+                );
+            } else if occupancy.get(x, y) == 0 {
+                assert!(rgb.iter().all(|&c| c == 0.0), "Empty tiles must be black");
+            }
+        }
+    }
+
+    let full = aux.tile_heatmap(img_size, true);
+    assert_eq!(full.dims(), [40, 64, 3]);
+    let full = full.into_data().to_vec::<f32>().expect("Wrong type");
+    let pixel = max_pos * 16;
+    let rgb = &full[((pixel.y * 64 + pixel.x) * 3) as usize..][..3];
+    assert!(
+        rgb.iter().all(|&c| c == 1.0),
+        "Pixels of the most occupied tile must be white"
+    );
+}
+
+#[test]
+fn stable_depth_ties_keep_order_under_camera_jitter() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+
+    // A red and a green splat at nearly the same depth, both in the same depth bucket. Rotating
+    // the camera by a tiny angle swaps which one is in front.
+    let means = [-0.01, 0.0, 2.5e-4, 0.01, 0.0, 2.4e-4];
+    let colors = [1.0, -1.0, -1.0, -1.0, 1.0, -1.0];
+
+    let render = |angle: f32, stable_depth_ties| {
+        let cam = Camera::new(
+            glam::vec3(0.0, 0.0, -5.0),
+            glam::Quat::from_rotation_y(angle),
+            0.5,
+            0.5,
+            glam::vec2(0.5, 0.5),
+        );
+        let means = Tensor::<Back, 1>::from_floats(means, &device).reshape([2, 3]);
+        let sh_coeffs = Tensor::<Back, 1>::from_floats(colors, &device).reshape([2, 1, 3]);
+        let log_scales = Tensor::<Back, 2>::ones([2, 3], &device) * -1.0;
+        let quats: Tensor<Back, 2> =
+            Tensor::<Back, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+                .unsqueeze_dim(0)
+                .repeat_dim(0, 2);
+        let opacity = Tensor::<Back, 1>::ones([2], &device) * 0.99;
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.into_primitive().tensor(),
+            log_scales.into_primitive().tensor(),
+            quats.into_primitive().tensor(),
+            sh_coeffs.into_primitive().tensor(),
+            opacity.into_primitive().tensor(),
+            true,
+            &RenderOptions {
+                stable_depth_ties,
+                ..Default::default()
+            },
+        );
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+    };
+
+    // Jitter the camera both ways, as only one direction swaps the depth order.
+    let max_change = |stable| {
+        let base = render(0.0, stable);
+        [-1e-3, 1e-3]
+            .into_iter()
+            .map(|angle| {
+                (render(angle, stable) - base.clone())
+                    .abs()
+                    .max()
+                    .into_scalar()
+                    .elem::<f32>()
+            })
+            .fold(0.0, f32::max)
+    };
+
+    let unstable = max_change(false);
+    assert!(
+        unstable > 0.1,
+        "Jitter must swap the depth order, changed by {unstable}"
+    );
+    let stable = max_change(true);
+    assert!(
+        stable < 0.02,
+        "Tie-stable render changed by {stable} under camera jitter"
+    );
+}
+
+#[test]
+fn stable_depth_ties_order_by_full_index() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+
+    // A red splat and a green splat at the same spot, with indices that only differ above the
+    // low 10 bits. The splats in between are behind the camera.
+    let num_points = 1026;
+    let means: Vec<f32> = (0..num_points)
+        .flat_map(|i| {
+            if i == 1 || i == 1025 {
+                [0.0, 0.0, 0.0]
+            } else {
+                [0.0, 0.0, -10.0]
+            }
+        })
+        .collect();
+    let colors: Vec<f32> = (0..num_points)
+        .flat_map(|i| {
+            if i == 1 {
+                [1.0, -1.0, -1.0]
+            } else {
+                [-1.0, 1.0, -1.0]
+            }
+        })
+        .collect();
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -5.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let means = Tensor::<Back, 1>::from_floats(means.as_slice(), &device).reshape([num_points, 3]);
+    let sh_coeffs =
+        Tensor::<Back, 1>::from_floats(colors.as_slice(), &device).reshape([num_points, 1, 3]);
+    let log_scales = Tensor::<Back, 2>::ones([num_points, 3], &device) * -1.0;
+    let quats: Tensor<Back, 2> =
+        Tensor::<Back, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+            .unsqueeze_dim(0)
+            .repeat_dim(0, num_points);
+    let opacity = Tensor::<Back, 1>::ones([num_points], &device) * 0.99;
+    let (output, _) = <Back as SplatForward<Back>>::render_splats(
+        &cam,
+        img_size,
+        means.into_primitive().tensor(),
+        log_scales.into_primitive().tensor(),
+        quats.into_primitive().tensor(),
+        sh_coeffs.into_primitive().tensor(),
+        opacity.into_primitive().tensor(),
+        true,
+        &RenderOptions {
+            stable_depth_ties: true,
+            ..Default::default()
+        },
+    );
+    let center = Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+        .slice(s![16, 16, 0..3])
+        .into_data()
+        .to_vec::<f32>()
+        .expect("Wrong tensor type");
+    assert!(
+        center[0] > center[1],
+        "The splat with the lower index must be in front, got {center:?}"
+    );
+}
+
+#[test]
+fn subset_render_matches_selected_splats() {
+    type Base = MainBackendBase;
+
+    fn pick<const D: usize>(
+        tensor: Tensor<Base, D>,
+        indices: Option<&Tensor<Base, 1, Int>>,
+    ) -> Tensor<Base, D> {
+        match indices {
+            Some(indices) => tensor.select(0, indices.clone()),
+            None => tensor,
+        }
+    }
+
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+    let num_points = 24;
+    let (means, log_scales, quats, sh_coeffs, _) = random_splats::<Base>(num_points, 1, &device);
+    let opacity = Tensor::<Base, 1>::ones([num_points], &device) * 0.8;
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -4.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.8,
+        glam::vec2(0.5, 0.5),
+    );
+
+    // Either render a subset of the splats, or copy out the selected splats and render those.
+    let render = |indices: Option<&Tensor<Base, 1, Int>>, select: bool| {
+        let (selected, subset) = if select {
+            (indices, None)
+        } else {
+            (
+                None,
+                indices.map(|indices| indices.clone().into_primitive()),
+            )
+        };
+        let (output, _) = render_forward(
+            &cam,
+            img_size,
+            RenderInput {
+                subset,
+                ..RenderInput::new(
+                    pick(means.clone(), selected).into_primitive().tensor(),
+                    pick(log_scales.clone(), selected).into_primitive().tensor(),
+                    pick(quats.clone(), selected).into_primitive().tensor(),
+                    pick(sh_coeffs.clone(), selected).into_primitive().tensor(),
+                    pick(opacity.clone(), selected).into_primitive().tensor(),
+                )
+            },
+            true,
+            &RenderOptions::default(),
+        );
+        Tensor::<Base, 3>::from_primitive(TensorPrimitive::Float(output))
+    };
+
+    let max_diff =
+        |a: Tensor<Base, 3>, b: Tensor<Base, 3>| (a - b).abs().max().into_scalar().elem::<f32>();
+
+    let full = render(None, false);
+    let all = Tensor::<Base, 1, Int>::arange(0..num_points as i64, &device);
+    let diff = max_diff(render(Some(&all), false), full.clone());
+    assert!(diff < 1e-6, "Subset of all splats differs by {diff}");
+
+    let even = Tensor::<Base, 1, Int>::arange_step(0..num_points as i64, 2, &device);
+    let diff = max_diff(render(Some(&even), false), render(Some(&even), true));
+    assert!(
+        diff < 1e-6,
+        "Subset differs from rendering the selected splats by {diff}"
+    );
+
+    let none = Tensor::<Base, 1, Int>::empty([0], &device);
+    let empty = render(Some(&none), false);
+    assert!(
+        max_diff(empty.clone(), empty.zeros_like()) < 1e-7,
+        "Empty subset must render nothing"
+    );
+    assert!(
+        max_diff(full.clone(), full.zeros_like()) > 0.0,
+        "Full render must show some splats"
+    );
+}
+
+#[test]
+fn background_image_fills_transmittance() {
+    type Base = MainBackendBase;
+
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 24);
+    let num_points = 8;
+    let means =
+        Tensor::<Base, 2>::random([num_points, 3], Distribution::Uniform(-0.5, 0.5), &device);
+    let log_scales = Tensor::<Base, 2>::ones([num_points, 3], &device) * -2.0;
+    let quats: Tensor<Base, 2> =
+        Tensor::<Base, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+            .unsqueeze_dim(0)
+            .repeat_dim(0, num_points);
+    let sh_coeffs = Tensor::<Base, 3>::random([num_points, 1, 3], Distribution::Default, &device);
+    let opacity = Tensor::<Base, 1>::ones([num_points], &device) * 0.7;
+    let background = Tensor::<Base, 3>::random(
+        [img_size.y as usize, img_size.x as usize, 3],
+        Distribution::Default,
+        &device,
+    );
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.8,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let render = |background: Option<Tensor<Base, 3>>| {
+        let (output, _) = render_forward(
+            &cam,
+            img_size,
+            RenderInput {
+                background: background.map(|background| background.into_primitive().tensor()),
+                ..RenderInput::new(
+                    means.clone().into_primitive().tensor(),
+                    log_scales.clone().into_primitive().tensor(),
+                    quats.clone().into_primitive().tensor(),
+                    sh_coeffs.clone().into_primitive().tensor(),
+                    opacity.clone().into_primitive().tensor(),
+                )
+            },
+            true,
+            &RenderOptions::default(),
+        );
+        Tensor::<Base, 3>::from_primitive(TensorPrimitive::Float(output))
+    };
+
+    let plain = render(None);
+    let composited = render(Some(background.clone()));
+
+    let rgb = plain.clone().slice([0..24, 0..32, 0..3]);
+    let transmittance = 1.0f32 - plain.slice([0..24, 0..32, 3..4]);
+    let expected = rgb + transmittance * background;
+    let diff = (composited.clone().slice([0..24, 0..32, 0..3]) - expected)
+        .abs()
+        .max()
+        .into_scalar()
+        .elem::<f32>();
+    assert!(diff < 1e-5, "Background compositing differs by {diff}");
+
+    let alpha_err = (composited.slice([0..24, 0..32, 3..4]) - 1.0f32)
+        .abs()
+        .max()
+        .into_scalar()
+        .elem::<f32>();
+    assert!(
+        alpha_err < 1e-7,
+        "Composited image must be opaque, alpha is off by {alpha_err}"
+    );
+}
+
+#[test]
+fn gpu_render_matches_cpu_reference() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(45, 38);
+    let num_points = 64;
+    let sh_coeffs_per_splat = 16;
+
+    let to_vec = |tensor: Tensor<Back, 2>| {
+        tensor
+            .into_data()
+            .into_vec::<f32>()
+            .expect("Wrong tensor type")
+    };
+    let means =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-1.0, 1.0), &device);
+    let log_scales =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-3.0, -1.5), &device);
+    let quats = Tensor::<Back, 2>::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    let sh_coeffs = Tensor::<Back, 3>::random(
+        [num_points, sh_coeffs_per_splat, 3],
+        Distribution::Uniform(-0.5, 0.5),
+        &device,
+    );
+    let opacities =
+        Tensor::<Back, 1>::random([num_points], Distribution::Uniform(0.2, 1.0), &device);
+    let data = [
+        to_vec(means.clone()),
+        to_vec(log_scales.clone()),
+        to_vec(quats.clone()),
+        to_vec(sh_coeffs.clone().reshape([num_points as i32, -1])),
+        to_vec(opacities.clone().unsqueeze_dim(1)),
+    ];
+
+    let cam = Camera::new(
+        glam::vec3(0.2, -0.1, -3.0),
+        glam::Quat::from_rotation_y(0.1),
+        0.9,
+        0.7,
+        glam::vec2(0.45, 0.55),
+    );
+
+    for options in [
+        RenderOptions::default(),
+        RenderOptions {
+            max_sh_degree: Some(1),
+            mip_filter: true,
+            ..Default::default()
+        },
+        RenderOptions {
+            render_depth: true,
+            color_space: ColorSpace::Linear,
+            ..Default::default()
+        },
+        RenderOptions {
+            render_depth: true,
+            depth_as_disparity: true,
+            ..Default::default()
+        },
+        RenderOptions {
+            tile_budget: NonZeroU32::new(4),
+            alpha_mode: AlphaMode::Straight,
+            stable_depth_ties: true,
+            transmittance_threshold: TransmittanceThreshold::new(1e-2).ok(),
+            ..Default::default()
+        },
+        RenderOptions {
+            clamp_policy: ClampPolicy::SoftClip,
+            ..Default::default()
+        },
+        RenderOptions {
+            alpha_gamma: AlphaGamma::new(2.0).ok(),
+            ..Default::default()
+        },
+        RenderOptions {
+            pixel_resort: ResortWindow::new(3).ok(),
+            ..Default::default()
+        },
+    ] {
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            opacities.clone().into_primitive().tensor(),
+            true,
+            &options,
+        );
+        let gpu = Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+            .into_data()
+            .into_vec::<f32>()
+            .expect("Wrong tensor type");
+
+        let [means, log_scales, quats, sh_coeffs, opacities] = &data;
+        let cpu = render_reference(
+            &cam, img_size, means, log_scales, quats, sh_coeffs, opacities, &options,
+        );
+
+        assert_eq!(gpu.len(), cpu.len(), "Image sizes differ for {options:?}");
+        let max_diff = gpu
+            .iter()
+            .zip(&cpu)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0f32, f32::max);
+        assert!(
+            max_diff < 5e-3,
+            "GPU render differs from the CPU reference by {max_diff} for {options:?}"
+        );
+        assert!(
+            cpu.iter().any(|&v| v > 0.0),
+            "The scene must be visible for {options:?}"
+        );
+    }
+}
+
+#[test]
+fn projected_splats_read_back() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(40, 30);
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.6,
+        glam::vec2(0.5, 0.5),
+    );
+
+    // One splat straight ahead, and one behind the camera that is culled.
+    let means =
+        Tensor::<Back, 1>::from_floats([0.0, 0.0, 5.0, 0.0, 0.0, -5.0], &device).reshape([2, 3]);
+    let log_scales = Tensor::<Back, 2>::ones([2, 3], &device) * 0.2f32.ln();
+    let quats: Tensor<Back, 2> =
+        Tensor::<Back, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+            .unsqueeze_dim(0)
+            .repeat_dim(0, 2);
+    let sh_coeffs = Tensor::<Back, 3>::ones([2, 1, 3], &device) * 0.5;
+    let opacity = Tensor::<Back, 1>::from_floats([0.7, 0.7], &device);
+    let (_, aux) = <Back as SplatForward<Back>>::render_splats(
+        &cam,
+        img_size,
+        means.into_primitive().tensor(),
+        log_scales.into_primitive().tensor(),
+        quats.into_primitive().tensor(),
+        sh_coeffs.into_primitive().tensor(),
+        opacity.into_primitive().tensor(),
+        true,
+        &RenderOptions::default(),
+    );
+
+    let splats = aux.read_projected_splats();
+    assert_eq!(splats.len(), 1, "Only the splat in front is visible");
+    let splat = splats[0];
+    assert_eq!(splat.global_id, 0, "Wrong splat is visible");
+
+    let center = cam.center(img_size);
+    assert_approx_eq!(splat.center.x, center.x, 1e-4);
+    assert_approx_eq!(splat.center.y, center.y, 1e-4);
+    assert_approx_eq!(splat.depth, 5.0, 1e-4);
+    assert_approx_eq!(splat.opacity, 0.7, 1e-6);
+
+    // An isotropic splat has a diagonal conic, with the blur added to its projected variance.
+    let focal = cam.focal(img_size);
+    let var = |f: f32| (f * 0.2 / 5.0).powi(2) + 0.3;
+    assert_approx_eq!(splat.conic.x, 1.0 / var(focal.x), 1e-4);
+    assert_approx_eq!(splat.conic.y, 0.0, 1e-6);
+    assert_approx_eq!(splat.conic.z, 1.0 / var(focal.y), 1e-4);
+
+    let color = 0.5 * crate::shaders::project_visible::SH_C0 + 0.5;
+    assert_approx_eq!(splat.color.x, color, 1e-5);
+}
+
+#[test]
+fn transmittance_threshold_stops_early() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+    let num_points = 8;
+
+    // Nearly opaque splats stacked behind each other.
+    let means_data: Vec<f32> = (0..num_points)
+        .flat_map(|i| [0.0, 0.0, i as f32 * 0.1])
+        .collect();
+    let means =
+        Tensor::<Back, 1>::from_floats(means_data.as_slice(), &device).reshape([num_points, 3]);
+    let log_scales = Tensor::<Back, 2>::ones([num_points, 3], &device) * -1.0;
+    let quats: Tensor<Back, 2> =
+        Tensor::<Back, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+            .unsqueeze_dim(0)
+            .repeat_dim(0, num_points);
+    let sh_coeffs = Tensor::<Back, 3>::random([num_points, 1, 3], Distribution::Default, &device);
+    let opacity = Tensor::<Back, 1>::ones([num_points], &device) * 0.9;
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::IDENTITY,
+        0.6,
+        0.6,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let render = |threshold: Option<f32>| {
+        let transmittance_threshold =
+            threshold.map(|t| TransmittanceThreshold::new(t).expect("Valid threshold"));
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            opacity.clone().into_primitive().tensor(),
+            true,
+            &RenderOptions {
+                transmittance_threshold,
+                ..Default::default()
+            },
+        );
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+    };
+
+    let default = render(None);
+    let explicit = render(Some(DEFAULT_TRANSMITTANCE_THRESHOLD));
+    let diff = (default.clone() - explicit)
+        .abs()
+        .max()
+        .into_scalar()
+        .elem::<f32>();
+    assert!(
+        diff < 1e-7,
+        "Default threshold changes the render by {diff}"
+    );
+
+    // Stopping earlier leaves more of the pixel uncovered.
+    let early = render(Some(0.05));
+    let alpha_gain = (early.slice([0..32, 0..32, 3..4]) - default.slice([0..32, 0..32, 3..4]))
+        .into_data()
+        .into_vec::<f32>()
+        .expect("Wrong tensor type");
+    let max_gain = alpha_gain.iter().copied().fold(f32::MIN, f32::max);
+    let max_loss = -alpha_gain.iter().copied().fold(f32::MAX, f32::min);
+    assert!(
+        max_gain < 1e-6,
+        "Stopping early can't add coverage, got {max_gain}"
+    );
+    assert!(
+        max_loss > 1e-3,
+        "A higher threshold must stop blending sooner"
+    );
+}
+
+#[test]
+fn splats_at_camera_are_culled() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+    let cam_pos = glam::vec3(0.0, 0.0, -3.0);
+    let cam = Camera::new(
+        cam_pos,
+        glam::Quat::IDENTITY,
+        0.6,
+        0.6,
+        glam::vec2(0.5, 0.5),
+    );
+
+    // One regular splat, followed by splats exactly at, just in front of, and just behind the
+    // camera origin. Large scales make any leaked projection cover the whole image.
+    let means_data = [
+        [0.0, 0.0, 0.0],
+        cam_pos.to_array(),
+        (cam_pos + glam::vec3(0.0, 0.0, 1e-3)).to_array(),
+        (cam_pos - glam::vec3(0.0, 0.0, 1e-3)).to_array(),
+        (cam_pos + glam::vec3(0.0, 0.0, 1e-7)).to_array(),
+    ];
+    let num_points = means_data.len();
+
+    let render = |count: usize| {
+        let means = Tensor::<Back, 1>::from_floats(means_data[..count].as_flattened(), &device)
+            .reshape([count, 3]);
+        let log_scales = Tensor::<Back, 2>::zeros([count, 3], &device);
+        let quats: Tensor<Back, 2> =
+            Tensor::<Back, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+                .unsqueeze_dim(0)
+                .repeat_dim(0, count);
+        let sh_coeffs = Tensor::<Back, 3>::ones([count, 1, 3], &device) * 0.5;
+        let opacity = Tensor::<Back, 1>::ones([count], &device) * 0.8;
+        let (output, aux) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.into_primitive().tensor(),
+            log_scales.into_primitive().tensor(),
+            quats.into_primitive().tensor(),
+            sh_coeffs.into_primitive().tensor(),
+            opacity.into_primitive().tensor(),
+            true,
+            &RenderOptions::default(),
+        );
+        (
+            Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output)),
+            aux,
+        )
+    };
+
+    let (reference, _) = render(1);
+    let (output, aux) = render(num_points);
+
+    let pixels = output
+        .clone()
+        .into_data()
+        .into_vec::<f32>()
+        .expect("Wrong tensor type");
+    assert!(
+        pixels.iter().all(|p| p.is_finite()),
+        "Splats at the camera must not produce NaNs"
+    );
+    let diff = (output - reference).abs().max().into_scalar().elem::<f32>();
+    assert!(
+        diff < 1e-6,
+        "Splats at the camera changed the render by {diff}"
+    );
+    assert_eq!(
+        aux.visible_global_ids(),
+        vec![0],
+        "Only the splat in front of the camera should be visible"
+    );
+}
+
+#[test]
+fn every_projection_variant_culls_splats_at_camera() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+    let cam_pos = glam::vec3(0.0, 0.0, -3.0);
+    let cam = Camera::new(
+        cam_pos,
+        glam::Quat::IDENTITY,
+        0.6,
+        0.6,
+        glam::vec2(0.5, 0.5),
+    );
+
+    // A regular splat and one just past the near plane, whose conic overflows. Each combination
+    // of options below compiles a different variant of the projection shader.
+    let means_data = [
+        [0.0, 0.0, 0.0],
+        (cam_pos + glam::vec3(0.0, 0.0, 1e-7)).to_array(),
+    ];
+    let count = means_data.len();
+    let means =
+        Tensor::<Back, 1>::from_floats(means_data.as_flattened(), &device).reshape([count, 3]);
+    let log_scales = Tensor::<Back, 2>::zeros([count, 3], &device);
+    let quats: Tensor<Back, 2> =
+        Tensor::<Back, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+            .unsqueeze_dim(0)
+            .repeat_dim(0, count);
+    let sh_coeffs = Tensor::<Back, 3>::ones([count, 1, 3], &device) * 0.5;
+    let opacity = Tensor::<Back, 1>::ones([count], &device) * 0.8;
+
+    for mip_filter in [false, true] {
+        for frustum_cull in [false, true] {
+            let plane = ClipPlane::new(glam::Vec3::Z, -10.0).expect("Valid plane");
+            for clip_planes in [vec![], vec![plane]] {
+                let options = RenderOptions {
+                    mip_filter,
+                    frustum_cull,
+                    clip_planes,
+                    ..Default::default()
+                };
+                let (output, aux) = <Back as SplatForward<Back>>::render_splats(
+                    &cam,
+                    img_size,
+                    means.clone().into_primitive().tensor(),
+                    log_scales.clone().into_primitive().tensor(),
+                    quats.clone().into_primitive().tensor(),
+                    sh_coeffs.clone().into_primitive().tensor(),
+                    opacity.clone().into_primitive().tensor(),
+                    true,
+                    &options,
+                );
+                let pixels = Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+                    .into_data()
+                    .into_vec::<f32>()
+                    .expect("Wrong tensor type");
+                assert!(
+                    pixels.iter().all(|p| p.is_finite()),
+                    "Overflowing splats must not produce NaNs for {options:?}"
+                );
+                assert_eq!(
+                    aux.visible_global_ids(),
+                    vec![0],
+                    "Only the regular splat should be visible for {options:?}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn splats_past_image_edge_stay_in_tile_grid() {
+    let device = WgpuDevice::DefaultDevice;
+    // Not a multiple of the tile size, so the last row and column of tiles are partial.
+    let img_size = glam::uvec2(40, 24);
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.6,
+        glam::vec2(0.5, 0.5),
+    );
+
+    // Place splats at the corners of the image, and a bit beyond them.
+    let (focal, center) = (cam.focal(img_size), cam.center(img_size));
+    let size = img_size.as_vec2();
+    let pixels = [
+        glam::vec2(0.0, 0.0),
+        glam::vec2(size.x, 0.0),
+        glam::vec2(0.0, size.y),
+        size,
+        glam::vec2(-6.0, -6.0),
+        size + 6.0,
+    ];
+    let means_data: Vec<f32> = pixels
+        .iter()
+        .flat_map(|&px| {
+            let xy = (px - center) / focal * 3.0;
+            [xy.x, xy.y, 0.0]
+        })
+        .collect();
+    let num_points = pixels.len();
+    let log_scales_data = vec![-1.5; num_points * 3];
+    let quats_data: Vec<f32> = (0..num_points)
+        .flat_map(|_| glam::Quat::IDENTITY.to_array())
+        .collect();
+    let sh_coeffs_data = vec![0.5; num_points * 3];
+    let opacities_data = vec![0.8; num_points];
+
+    let options = RenderOptions::default();
+    let (output, aux) = <Back as SplatForward<Back>>::render_splats(
+        &cam,
+        img_size,
+        Tensor::<Back, 1>::from_floats(means_data.as_slice(), &device)
+            .reshape([num_points, 3])
+            .into_primitive()
+            .tensor(),
+        Tensor::<Back, 1>::from_floats(log_scales_data.as_slice(), &device)
+            .reshape([num_points, 3])
+            .into_primitive()
+            .tensor(),
+        Tensor::<Back, 1>::from_floats(quats_data.as_slice(), &device)
+            .reshape([num_points, 4])
+            .into_primitive()
+            .tensor(),
+        Tensor::<Back, 1>::from_floats(sh_coeffs_data.as_slice(), &device)
+            .reshape([num_points, 1, 3])
+            .into_primitive()
+            .tensor(),
+        Tensor::<Back, 1>::from_floats(opacities_data.as_slice(), &device)
+            .into_primitive()
+            .tensor(),
+        true,
+        &options,
+    );
+    aux.debug_assert_valid();
+
+    // Every intersection must be counted by one of the tiles of the grid.
+    let occupancy = aux.read_tile_occupancy();
+    assert_eq!(occupancy.tile_bounds, glam::uvec2(3, 2));
+    assert_eq!(
+        occupancy.total(),
+        aux.num_intersections().into_scalar().elem::<i32>() as u64,
+        "Intersections were assigned to tiles outside of the grid"
+    );
+    for (x, y) in [(0, 0), (2, 0), (0, 1), (2, 1)] {
+        assert!(
+            occupancy.get(x, y) > 0,
+            "Corner tile ({x}, {y}) should be covered"
+        );
+    }
+
+    let gpu = Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+        .into_data()
+        .into_vec::<f32>()
+        .expect("Wrong tensor type");
+    let cpu = render_reference(
+        &cam,
+        img_size,
+        &means_data,
+        &log_scales_data,
+        &quats_data,
+        &sh_coeffs_data,
+        &opacities_data,
+        &options,
+    );
+    let max_diff = gpu
+        .iter()
+        .zip(&cpu)
+        .map(|(a, b)| (a - b).abs())
+        .fold(0.0f32, f32::max);
+    assert!(
+        max_diff < 5e-3,
+        "Render of edge splats differs from the CPU reference by {max_diff}"
+    );
+}
+
+#[test]
+fn constant_opacity_sh_matches_scalar_opacity() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(48, 32);
+    let num_points = 64;
+    let means =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-1.0, 1.0), &device);
+    let log_scales =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-3.0, -1.5), &device);
+    let quats = Tensor::<Back, 2>::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    let sh_coeffs = Tensor::<Back, 3>::random([num_points, 4, 3], Distribution::Default, &device);
+    let opacity_data: Vec<f32> =
+        Tensor::<Back, 1>::random([num_points], Distribution::Uniform(0.2, 1.0), &device)
+            .into_data()
+            .into_vec()
+            .expect("Wrong tensor type");
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::from_rotation_y(0.2),
+        0.8,
+        0.6,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let render = |opacities: Tensor<Back, 2>| {
+        let opacities = if opacities.dims()[1] == 1 {
+            opacities.squeeze::<1>(1).into_primitive().tensor()
+        } else {
+            opacities.into_primitive().tensor()
+        };
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            opacities,
+            true,
+            &RenderOptions::default(),
+        );
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+    };
+    let max_diff =
+        |a: Tensor<Back, 3>, b: Tensor<Back, 3>| (a - b).abs().max().into_scalar().elem::<f32>();
+
+    let scalar =
+        render(Tensor::<Back, 1>::from_floats(opacity_data.as_slice(), &device).unsqueeze_dim(1));
+
+    // Degree 0 and degree 2 coefficients, with only the constant band set.
+    let dc: Vec<f32> = opacity_data.iter().map(|&o| opacity_to_sh(o)).collect();
+    let dc = Tensor::<Back, 1>::from_floats(dc.as_slice(), &device).reshape([num_points, 1]);
+    let degree_0 = render(dc.clone());
+    let degree_2 = render(Tensor::cat(
+        vec![dc.clone(), Tensor::zeros([num_points, 8], &device)],
+        1,
+    ));
+
+    let diff = max_diff(scalar.clone(), degree_0);
+    assert!(
+        diff < 1e-5,
+        "Degree 0 opacity sh differs from scalar opacity by {diff}"
+    );
+    let diff = max_diff(scalar.clone(), degree_2);
+    assert!(
+        diff < 1e-5,
+        "Constant degree 2 opacity sh differs from scalar opacity by {diff}"
+    );
+
+    // Higher bands make the opacity view dependent.
+    let view_dependent = render(Tensor::cat(
+        vec![dc, Tensor::ones([num_points, 3], &device) * 0.5],
+        1,
+    ));
+    let diff = max_diff(scalar, view_dependent);
+    assert!(diff > 1e-2, "Degree 1 opacity sh must change the render");
+}
+
+#[test]
+fn splat_workgroup_sizes_render_the_same() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(48, 32);
+    // Not a multiple of any workgroup size, so the last workgroup is partially filled.
+    let num_points = 300;
+    let means =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-1.0, 1.0), &device);
+    let log_scales =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-3.0, -1.5), &device);
+    let quats = Tensor::<Back, 2>::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    let sh_coeffs = Tensor::<Back, 3>::random([num_points, 4, 3], Distribution::Default, &device);
+    let opacities =
+        Tensor::<Back, 1>::random([num_points], Distribution::Uniform(0.2, 1.0), &device);
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::from_rotation_y(0.2),
+        0.8,
+        0.6,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let render = || {
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            opacities.clone().into_primitive().tensor(),
+            true,
+            &RenderOptions::default(),
+        );
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+    };
+
+    let reference = with_thread_splat_workgroup_size(DEFAULT_SPLAT_WORKGROUP_SIZE, render);
+
+    for size in SPLAT_WORKGROUP_SIZES {
+        let diff = (with_thread_splat_workgroup_size(size, render) - reference.clone())
+            .abs()
+            .max()
+            .into_scalar()
+            .elem::<f32>();
+        assert!(
+            diff < 1e-6,
+            "Workgroup size {size} renders differently, by {diff}"
+        );
+    }
+}
+
+#[test]
+fn banded_render_matches_single_render() {
+    type Base = MainBackendBase;
+
+    let device = WgpuDevice::DefaultDevice;
+    // Five tile rows, the last of which is partially covered by the image.
+    let img_size = glam::uvec2(40, 70);
+    let num_points = 64;
+    let means =
+        Tensor::<Base, 2>::random([num_points, 3], Distribution::Uniform(-1.0, 1.0), &device);
+    let log_scales =
+        Tensor::<Base, 2>::random([num_points, 3], Distribution::Uniform(-3.0, -1.0), &device);
+    let quats = Tensor::<Base, 2>::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    let sh_coeffs = Tensor::<Base, 3>::random([num_points, 4, 3], Distribution::Default, &device);
+    let opacity = Tensor::<Base, 1>::random([num_points], Distribution::Uniform(0.2, 1.0), &device);
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::from_rotation_y(0.2),
+        0.6,
+        0.9,
+        glam::vec2(0.45, 0.55),
+    );
+    let bits = |img: CubeTensor<WgpuRuntime>| -> Vec<u32> {
+        Tensor::<Base, 3>::from_primitive(TensorPrimitive::Float(img))
+            .into_data()
+            .into_vec::<f32>()
+            .expect("Wrong tensor type")
+            .into_iter()
+            .map(f32::to_bits)
+            .collect()
+    };
+
+    for bwd_info in [false, true] {
+        let (single, _) = render_forward(
+            &cam,
+            img_size,
+            RenderInput::new(
+                means.clone().into_primitive().tensor(),
+                log_scales.clone().into_primitive().tensor(),
+                quats.clone().into_primitive().tensor(),
+                sh_coeffs.clone().into_primitive().tensor(),
+                opacity.clone().into_primitive().tensor(),
+            ),
+            bwd_info,
+            &RenderOptions::default(),
+        );
+        let single = bits(single);
+
+        // No budget renders a single tile row at a time, an unlimited one the whole image.
+        for budget in [0, 1 << 12, u64::MAX] {
+            let banded = render_forward_banded(
+                &cam,
+                img_size,
+                RenderInput::new(
+                    means.clone().into_primitive().tensor(),
+                    log_scales.clone().into_primitive().tensor(),
+                    quats.clone().into_primitive().tensor(),
+                    sh_coeffs.clone().into_primitive().tensor(),
+                    opacity.clone().into_primitive().tensor(),
+                ),
+                bwd_info,
+                &RenderOptions::default(),
+                budget,
+            );
+            assert!(
+                bits(banded) == single,
+                "Banded render with a budget of {budget} bytes differs (bwd_info: {bwd_info})"
+            );
+        }
+    }
+}
+
+#[test]
+fn disparity_is_inverse_depth() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+    // A single round splat straight ahead of the camera, covering the center of the image.
+    let depth = 4.0;
+    let means = Tensor::<Back, 2>::from_floats([[0.0, 0.0, depth]], &device);
+    let log_scales = Tensor::<Back, 2>::ones([1, 3], &device) * -1.5;
+    let quats = Tensor::<Back, 2>::from_floats([glam::Quat::IDENTITY.to_array()], &device);
+    let sh_coeffs = Tensor::<Back, 3>::ones([1, 1, 3], &device);
+    let opacities = Tensor::<Back, 1>::from_floats([0.9], &device);
+    let cam = Camera::new(
+        glam::Vec3::ZERO,
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let render = |depth_as_disparity: bool| {
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            opacities.clone().into_primitive().tensor(),
+            true,
+            &RenderOptions {
+                render_depth: true,
+                depth_as_disparity,
+                ..Default::default()
+            },
+        );
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+            .into_data()
+            .into_vec::<f32>()
+            .expect("Wrong tensor type")
+    };
+    let depths = render(false);
+    let disparities = render(true);
+
+    let mut covered = 0;
+    for (depth_px, disparity_px) in depths.chunks(5).zip(disparities.chunks(5)) {
+        let alpha = disparity_px[3];
+        if alpha > 0.1 {
+            let expected_depth = depth_px[4] / alpha;
+            let disparity = disparity_px[4] / alpha;
+            assert!(
+                (expected_depth - depth).abs() < 1e-4,
+                "Expected depth {expected_depth} of a single splat at {depth}"
+            );
+            assert!(
+                (disparity - 1.0 / expected_depth).abs() < 1e-5,
+                "Disparity {disparity} isn't the inverse of depth {expected_depth}"
+            );
+            covered += 1;
+        } else if alpha == 0.0 {
+            assert!(
+                disparity_px[4] == 0.0,
+                "Empty pixels must have zero disparity"
+            );
+        }
+    }
+    assert!(covered > 0, "The splat must cover some pixels");
+    let corner = &disparities[..5];
+    assert!(corner[3] == 0.0, "The corner of the image must be empty");
+}
+
+#[test]
+fn bottom_left_origin_mirrors_render() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(45, 38);
+    let num_points = 64;
+    let means =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-1.0, 1.0), &device);
+    let log_scales =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-3.0, -1.5), &device);
+    let quats = Tensor::<Back, 2>::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    let sh_coeffs = Tensor::<Back, 3>::random([num_points, 1, 3], Distribution::Default, &device);
+    let opacities =
+        Tensor::<Back, 1>::random([num_points], Distribution::Uniform(0.2, 1.0), &device);
+
+    // The principal point is off center, and measured from the bottom with a bottom left origin.
+    let top_left = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.7,
+        glam::vec2(0.45, 0.3),
+    );
+    let bottom_left = Camera {
+        center_uv: glam::vec2(0.45, 0.7),
+        ..top_left.clone()
+    }
+    .with_origin(ImageOrigin::BottomLeft);
+
+    let render = |cam: &Camera| {
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            opacities.clone().into_primitive().tensor(),
+            true,
+            &RenderOptions {
+                frustum_cull: true,
+                ..Default::default()
+            },
+        );
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+    };
+    let top_left = render(&top_left);
+    let mirrored = render(&bottom_left).flip([0]);
+
+    let diff = (top_left.clone() - mirrored)
+        .abs()
+        .max()
+        .into_scalar()
+        .elem::<f32>();
+    assert!(
+        diff < 1e-6,
+        "A bottom left render must mirror the top left render, max diff {diff}"
+    );
+    let asymmetry = (top_left.clone() - top_left.flip([0]))
+        .abs()
+        .max()
+        .into_scalar()
+        .elem::<f32>();
+    assert!(
+        asymmetry > 0.01,
+        "The scene must not be vertically symmetric"
+    );
+}
+
+#[test]
+fn subpixel_offset_shifts_render() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(48, 32);
+    let num_points = 64;
+    // Splats well inside the view, so the clamping of projection near the image edges doesn't
+    // change when shifted.
+    let means =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-0.5, 0.5), &device);
+    let log_scales =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-3.0, -1.5), &device);
+    let quats = Tensor::<Back, 2>::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    let sh_coeffs = Tensor::<Back, 3>::random([num_points, 1, 3], Distribution::Default, &device);
+    let opacities =
+        Tensor::<Back, 1>::random([num_points], Distribution::Uniform(0.2, 1.0), &device);
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.6,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let render = |subpixel_offset: glam::Vec2| {
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            opacities.clone().into_primitive().tensor(),
+            true,
+            &RenderOptions {
+                subpixel_offset,
+                ..Default::default()
+            },
+        );
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+    };
+    let max_diff =
+        |a: Tensor<Back, 3>, b: Tensor<Back, 3>| (a - b).abs().max().into_scalar().elem::<f32>();
+
+    // A zero offset is the default, and renders exactly the same.
+    let reference = render(glam::Vec2::ZERO);
+    let (default, _) = <Back as SplatForward<Back>>::render_splats(
+        &cam,
+        img_size,
+        means.clone().into_primitive().tensor(),
+        log_scales.clone().into_primitive().tensor(),
+        quats.clone().into_primitive().tensor(),
+        sh_coeffs.clone().into_primitive().tensor(),
+        opacities.clone().into_primitive().tensor(),
+        true,
+        &RenderOptions::default(),
+    );
+    let default = Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(default));
+    assert_eq!(
+        max_diff(reference.clone(), default),
+        0.0,
+        "A zero offset must not change the render"
+    );
+
+    // Shifting by whole pixels moves the image by as many pixels.
+    let shifted = render(glam::vec2(1.0, 2.0));
+    let diff = max_diff(
+        shifted.slice(s![2..32, 1..48, ..]),
+        reference.clone().slice(s![0..30, 0..47, ..]),
+    );
+    assert!(diff < 1e-4, "Shifted render doesn't match, max diff {diff}");
+
+    // A sub-pixel offset changes the render a little.
+    let jittered = render(glam::vec2(0.25, -0.25));
+    let diff = max_diff(jittered, reference);
+    assert!(
+        diff > 1e-4 && diff < 0.5,
+        "A sub-pixel offset must change the render slightly, max diff {diff}"
+    );
+}
+
+#[test]
+fn async_render_matches_sync_render() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 24);
+    let num_points = 32;
+    let splats = Splats::<Back>::from_tensor_data(
+        Tensor::random([num_points, 3], Distribution::Uniform(-1.0, 1.0), &device),
+        Tensor::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device),
+        Tensor::random([num_points, 3], Distribution::Uniform(-3.0, -1.5), &device),
+        Tensor::random([num_points, 1, 3], Distribution::Default, &device),
+        Tensor::random([num_points], Distribution::Uniform(-1.0, 2.0), &device),
+    );
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.6,
+        glam::vec2(0.5, 0.5),
+    );
+    let options = RenderOptions::default();
+
+    let (sync_img, _) = splats.render_with_options(&cam, img_size, false, &options);
+    let sync_img = read_image_u8(sync_img);
+
+    let (async_img, _) = block_on(splats.render_async(&cam, img_size, false, &options));
+    assert_eq!(read_image_u8(async_img), sync_img);
+
+    let (image, aux) = block_on(splats.render_image_async(&cam, img_size, &options));
+    assert_eq!(image, sync_img);
+    assert!(aux.read_stats().num_visible > 0, "Splats must be visible");
+}
+
+#[test]
+fn merged_scenes_render_like_overlaid_passes() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 24);
+    let num_points = 16;
+    // Two scenes that don't overlap in depth, with different SH degrees.
+    let scene = |z: f32, sh_coeffs: usize| {
+        let offset = Tensor::<Back, 1>::from_floats([0.0, 0.0, z], &device).unsqueeze_dim(0);
+        Splats::<Back>::from_tensor_data(
+            Tensor::random([num_points, 3], Distribution::Uniform(-0.2, 0.2), &device) + offset,
+            Tensor::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device),
+            Tensor::random([num_points, 3], Distribution::Uniform(-3.0, -2.0), &device),
+            Tensor::random([num_points, sh_coeffs, 3], Distribution::Default, &device),
+            Tensor::random([num_points], Distribution::Uniform(-1.0, 0.0), &device),
+        )
+    };
+    let front = scene(-1.0, 1);
+    let back = scene(1.0, 4);
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.6,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let (front_img, _) = front.render(&cam, img_size, true);
+    let (back_img, _) = back.render(&cam, img_size, true);
+    let merged = Splats::merge(vec![front, back]);
+    assert_eq!(merged.num_splats(), 2 * num_points as u32);
+    assert_eq!(merged.sh_degree(), 1);
+    let (merged_img, _) = merged.render(&cam, img_size, true);
+
+    // The back scene shows through the transmittance of the front scene.
+    let [h, w] = [img_size.y as usize, img_size.x as usize];
+    let front_t = 1.0f32 - front_img.clone().slice(s![.., .., 3..4]);
+    let back_t = 1.0f32 - back_img.clone().slice(s![.., .., 3..4]);
+    let rgb =
+        front_img.slice(s![.., .., 0..3]) + front_t.clone() * back_img.slice(s![.., .., 0..3]);
+    let alpha = 1.0f32 - front_t * back_t;
+    let expected = Tensor::cat(vec![rgb, alpha], 2);
+    assert_eq!(merged_img.dims(), [h, w, 4]);
+
+    let diff = (merged_img - expected)
+        .abs()
+        .max()
+        .into_scalar()
+        .elem::<f32>();
+    assert!(diff < 1e-4, "Merged render differs by {diff}");
+}
+
+#[test]
+fn world_transform_matches_transformed_splats() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(40, 32);
+    let num_points = 24;
+    let waves = |i: usize, freq: glam::Vec3| {
+        let f = i as f32;
+        glam::vec3((f * freq.x).sin(), (f * freq.y).cos(), (f * freq.z).sin())
+    };
+    let means: Vec<_> = (0..num_points)
+        .map(|i| waves(i, glam::vec3(1.3, 2.1, 0.7)) * 0.6)
+        .collect();
+    let rotations: Vec<_> = (0..num_points)
+        .map(|i| {
+            let angles = waves(i, glam::vec3(0.3, 0.5, 0.9));
+            glam::Quat::from_euler(glam::EulerRot::XYZ, angles.x, angles.y, angles.z)
+        })
+        .collect();
+    let log_scales: Vec<_> = (0..num_points)
+        .map(|i| waves(i, glam::vec3(1.7, 0.4, 2.3)) * 0.3 - 2.5)
+        .collect();
+    let colors: Vec<f32> = (0..num_points)
+        .flat_map(|i| waves(i, glam::vec3(0.8, 1.9, 2.7)).to_array())
+        .collect();
+    let opacities = vec![0.5; num_points];
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -5.0),
+        glam::Quat::IDENTITY,
+        0.6,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let transform = WorldTransform::new(
+        glam::vec3(0.3, -0.2, 0.5),
+        glam::Quat::from_rotation_y(0.4) * glam::Quat::from_rotation_x(0.2),
+        WorldScale::new(1.5).expect("Valid scale"),
+    );
+    let affine = transform.to_affine();
+    let placed_means: Vec<_> = means.iter().map(|&m| affine.transform_point3(m)).collect();
+    let placed_rotations: Vec<_> = rotations.iter().map(|&r| transform.rotation * r).collect();
+    let placed_log_scales: Vec<_> = log_scales
+        .iter()
+        .map(|&s| s + transform.scale.get().ln())
+        .collect();
+
+    let splats = Splats::<Back>::from_raw(
+        &means,
+        Some(&rotations),
+        Some(&log_scales),
+        Some(&colors),
+        Some(&opacities),
+        &device,
+    );
+    let placed = Splats::<Back>::from_raw(
+        &placed_means,
+        Some(&placed_rotations),
+        Some(&placed_log_scales),
+        Some(&colors),
+        Some(&opacities),
+        &device,
+    );
+    let max_diff =
+        |a: Tensor<Back, 3>, b: Tensor<Back, 3>| (a - b).abs().max().into_scalar().elem::<f32>();
+
+    for mip_filter in [false, true] {
+        let options = RenderOptions {
+            mip_filter,
+            ..Default::default()
+        };
+        let (plain, _) = splats.render_with_options(&cam, img_size, true, &options);
+        let identity = RenderOptions {
+            world_transform: WorldTransform::IDENTITY,
+            ..options.clone()
+        };
+        let (identity, _) = splats.render_with_options(&cam, img_size, true, &identity);
+        let diff = max_diff(identity, plain.clone());
+        assert!(diff < 1e-6, "Identity transform differs by {diff}");
+
+        let transformed = RenderOptions {
+            world_transform: transform,
+            ..options.clone()
+        };
+        let (transformed, aux) = splats.render_with_options(&cam, img_size, true, &transformed);
+        let (expected, _) = placed.render_with_options(&cam, img_size, true, &options);
+        assert!(aux.read_stats().num_visible > 0, "Splats must be visible");
+        let diff = max_diff(transformed.clone(), expected);
+        assert!(
+            diff < 1e-4,
+            "Transformed render differs from transformed splats by {diff} (mip filter: {mip_filter})"
+        );
+        assert!(
+            max_diff(transformed, plain) > 1e-2,
+            "The transform must move the splats"
+        );
+    }
+}
+
+#[test]
+fn empty_view_skips_to_background() {
+    type Base = MainBackendBase;
+
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(40, 24);
+    let num_points = 64;
+    let means =
+        Tensor::<Base, 2>::random([num_points, 3], Distribution::Uniform(-0.5, 0.5), &device);
+    let log_scales = Tensor::<Base, 2>::ones([num_points, 3], &device) * -2.0;
+    let quats: Tensor<Base, 2> =
+        Tensor::<Base, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+            .unsqueeze_dim(0)
+            .repeat_dim(0, num_points);
+    let sh_coeffs = Tensor::<Base, 3>::random([num_points, 1, 3], Distribution::Default, &device);
+    let opacity = Tensor::<Base, 1>::ones([num_points], &device) * 0.7;
+    let background = Tensor::<Base, 3>::random(
+        [img_size.y as usize, img_size.x as usize, 3],
+        Distribution::Default,
+        &device,
+    );
+    // Looking away from the splats.
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::from_rotation_y(std::f32::consts::PI),
+        0.8,
+        0.6,
+        glam::vec2(0.5, 0.5),
+    );
+
+    // Culling finds that no splats are in view, so the projection is dispatched with no work,
+    // without culling the splats are projected and found invisible. Either way the view then skips
+    // to the background, unless running the full pipeline like on wasm.
+    let render = |frustum_cull| {
+        let (output, aux) = render_forward(
+            &cam,
+            img_size,
+            RenderInput {
+                background: Some(background.clone().into_primitive().tensor()),
+                ..RenderInput::new(
+                    means.clone().into_primitive().tensor(),
+                    log_scales.clone().into_primitive().tensor(),
+                    quats.clone().into_primitive().tensor(),
+                    sh_coeffs.clone().into_primitive().tensor(),
+                    opacity.clone().into_primitive().tensor(),
+                )
+            },
+            true,
+            &RenderOptions {
+                frustum_cull,
+                ..Default::default()
+            },
+        );
+        let stats = aux.read_stats();
+        assert_eq!(stats.num_visible, 0, "No splats must be visible");
+        assert_eq!(stats.num_intersections, 0, "No tiles must be hit");
+        Tensor::<Base, 3>::from_primitive(TensorPrimitive::Float(output))
+    };
+
+    let skipped = render(true);
+    let full = with_thread_full_pipeline(|| render(false));
+    for skipped in [skipped.clone(), render(false)] {
+        let diff = (skipped - full.clone())
+            .abs()
+            .max()
+            .into_scalar()
+            .elem::<f32>();
+        assert!(diff < 1e-7, "Skipped render differs by {diff}");
+    }
+
+    let diff = (skipped.slice(s![.., .., 0..3]) - background)
+        .abs()
+        .max()
+        .into_scalar()
+        .elem::<f32>();
+    assert!(
+        diff < 1e-7,
+        "Empty view must show the background, differs by {diff}"
+    );
+}
+
+#[test]
+fn depth_peeling_stops_coincident_popping() {
+    type Base = MainBackendBase;
+
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(48, 32);
+    let num_pairs = 24;
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.6,
+        glam::vec2(0.5, 0.5),
+    );
+
+    // Pairs of a red and a blue splat at nearly the same depth. Swapping which one is in front
+    // is what happens when the camera moves slightly, and flips their blending order.
+    let scene = |red_in_front: bool| {
+        let mut means = vec![];
+        for i in 0..num_pairs {
+            let f = i as f32;
+            let center = glam::vec3(
+                (f * 1.7).sin() * 0.6,
+                (f * 2.3).cos() * 0.4,
+                (f * 0.9).sin(),
+            );
+            let (near, far) = (center, center + glam::vec3(0.0, 0.0, 1e-4));
+            let (red, blue) = if red_in_front {
+                (near, far)
+            } else {
+                (far, near)
+            };
+            means.extend(red.to_array());
+            means.extend(blue.to_array());
+        }
+        let num_points = 2 * num_pairs;
+        let colors: Vec<f32> = (0..num_pairs)
+            .flat_map(|_| [1.5, -1.5, -1.5, -1.5, -1.5, 1.5])
+            .collect();
+        (
+            Tensor::<Base, 1>::from_floats(means.as_slice(), &device).reshape([num_points, 3]),
+            Tensor::<Base, 2>::ones([num_points, 3], &device) * -2.5,
+            Tensor::<Base, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+                .unsqueeze_dim::<2>(0)
+                .repeat_dim(0, num_points),
+            Tensor::<Base, 1>::from_floats(colors.as_slice(), &device).reshape([num_points, 1, 3]),
+            Tensor::<Base, 1>::ones([num_points], &device) * 0.6,
+        )
+    };
+
+    let render = |red_in_front: bool, depth_peel_layers: Option<NonZeroU32>| {
+        let (means, log_scales, quats, sh_coeffs, opacity) = scene(red_in_front);
+        let (output, _) = render_forward(
+            &cam,
+            img_size,
+            RenderInput::new(
+                means.into_primitive().tensor(),
+                log_scales.into_primitive().tensor(),
+                quats.into_primitive().tensor(),
+                sh_coeffs.into_primitive().tensor(),
+                opacity.into_primitive().tensor(),
+            ),
+            true,
+            &RenderOptions {
+                depth_peel_layers,
+                ..Default::default()
+            },
+        );
+        Tensor::<Base, 3>::from_primitive(TensorPrimitive::Float(output))
+    };
+    // Mean change of the pixels when the order of the pairs flips.
+    let popping = |depth_peel_layers: Option<NonZeroU32>| {
+        (render(true, depth_peel_layers) - render(false, depth_peel_layers))
+            .abs()
+            .mean()
+            .into_scalar()
+            .elem::<f32>()
+    };
+
+    let single_pass = popping(None);
+    let peeled = popping(NonZeroU32::new(4));
+    assert!(
+        single_pass > 1e-2,
+        "The stress scene must pop, got {single_pass}"
+    );
+    assert!(
+        peeled < single_pass * 0.01,
+        "Depth peeling must reduce popping, got {peeled} versus {single_pass}"
+    );
+
+    // A single layer of splats still looks the same as blending them in order.
+    let coverage = |image: Tensor<Base, 3>| {
+        image
+            .slice(s![.., .., 3..4])
+            .sum()
+            .into_scalar()
+            .elem::<f32>()
+    };
+    let diff = coverage(render(true, NonZeroU32::new(1))) - coverage(render(true, None));
+    assert!(
+        diff.abs() < 1e-2,
+        "Peeling must keep the coverage, differs by {diff}"
+    );
+}
+
+#[test]
+fn coarse_lod_renders_like_full_scene() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+    // A dense sheet of tiny splats, colored by quadrant.
+    let side = 64;
+    let spacing = 2.0 / side as f32;
+    let mut means = vec![];
+    let mut sh_coeffs = vec![];
+    for y in 0..side {
+        for x in 0..side {
+            let pos = glam::vec3(x as f32, y as f32, 0.0) * spacing - glam::vec3(1.0, 1.0, 0.0);
+            means.push(pos);
+            let color = match (pos.x < 0.0, pos.y < 0.0) {
+                (true, true) => [0.9, 0.1, 0.1],
+                (true, false) => [0.1, 0.9, 0.1],
+                (false, true) => [0.1, 0.1, 0.9],
+                (false, false) => [0.9, 0.9, 0.1],
+            };
+            sh_coeffs.extend(color.map(crate::sh::channel_to_sh));
+        }
+    }
+    let num_points = means.len();
+    let log_scales = vec![glam::Vec3::splat((0.5 * spacing).ln()); num_points];
+    let rotations = vec![glam::Quat::IDENTITY; num_points];
+    let raw_opacities = vec![crate::gaussian_splats::inverse_opacity_activation(0.8); num_points];
+    let splats = Splats::<Back>::from_raw(
+        &means,
+        Some(&rotations),
+        Some(&log_scales),
+        Some(&sh_coeffs),
+        Some(&raw_opacities),
+        &device,
+    );
+
+    let lod = block_on(SplatLod::build(splats, 3));
+    assert_eq!(lod.levels.len(), 3);
+    let full = &lod.levels[0].splats;
+    let coarse = &lod.levels[2].splats;
+    assert!(
+        coarse.num_splats() * 32 < full.num_splats(),
+        "Coarse level must merge most splats, has {}",
+        coarse.num_splats()
+    );
+
+    // From far away, cells project to about a pixel.
+    let far = Camera::new(
+        glam::vec3(0.0, 0.0, -12.0),
+        glam::Quat::IDENTITY,
+        0.4,
+        0.4,
+        glam::vec2(0.5, 0.5),
+    );
+    assert_eq!(lod.level_for(&far, img_size, 4.0), 2);
+    let (full_img, _) = full.render(&far, img_size, true);
+    let (coarse_img, _) = coarse.render(&far, img_size, true);
+    let diff = (full_img - coarse_img)
+        .abs()
+        .mean()
+        .into_scalar()
+        .elem::<f32>();
+    assert!(diff < 0.1, "Coarse level renders differently by {diff}");
+
+    // Up close, the full splats are needed.
+    let near = Camera::new(
+        glam::vec3(0.0, 0.0, -0.5),
+        glam::Quat::IDENTITY,
+        0.4,
+        0.4,
+        glam::vec2(0.5, 0.5),
+    );
+    assert_eq!(lod.level_for(&near, img_size, 4.0), 0);
+}
+
+#[test]
+fn channel_sh_degrees_match_uniform_degree() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(40, 32);
+    let num_points = 64;
+    let means =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-1.0, 1.0), &device);
+    let log_scales =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-3.0, -1.5), &device);
+    let quats = Tensor::<Back, 2>::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    let sh_coeffs = Tensor::<Back, 3>::random(
+        [num_points, 9, 3],
+        Distribution::Uniform(-0.5, 0.5),
+        &device,
+    );
+    let opacities =
+        Tensor::<Back, 1>::random([num_points], Distribution::Uniform(0.2, 1.0), &device);
+    let cam = Camera::new(
+        glam::vec3(0.2, -0.1, -3.0),
+        glam::Quat::from_rotation_y(0.1),
+        0.9,
+        0.7,
+        glam::vec2(0.45, 0.55),
+    );
+
+    let render = |sh_coeffs: CubeTensor<WgpuRuntime>, options: &RenderOptions| {
+        let (output, _) = render_forward(
+            &cam,
+            img_size,
+            RenderInput::new(
+                means.clone().into_primitive().tensor(),
+                log_scales.clone().into_primitive().tensor(),
+                quats.clone().into_primitive().tensor(),
+                sh_coeffs,
+                opacities.clone().into_primitive().tensor(),
+            ),
+            false,
+            options,
+        );
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+    };
+    let max_diff =
+        |a: Tensor<Back, 3>, b: Tensor<Back, 3>| (a - b).abs().max().into_scalar().elem::<f32>();
+
+    for options in [
+        RenderOptions::default(),
+        RenderOptions {
+            max_sh_degree: Some(1),
+            ..Default::default()
+        },
+        RenderOptions {
+            max_sh_degree: Some(0),
+            ..Default::default()
+        },
+    ] {
+        let uniform = render(sh_coeffs.clone().into_primitive().tensor(), &options);
+
+        // Equal degrees per channel render the same as the uniform degree.
+        let degrees = [2, 2, 2];
+        let planar = planar_channel_sh(sh_coeffs.clone(), degrees);
+        assert_eq!(planar.dims(), [num_points, 27]);
+        let channel_options = RenderOptions {
+            sh_channel_degrees: Some(degrees),
+            ..options.clone()
+        };
+        let channel = render(planar.into_primitive().tensor(), &channel_options);
+        let diff = max_diff(channel, uniform);
+        assert!(diff < 1e-5, "Equal channel degrees differ by {diff}");
+
+        // Lower degrees per channel render like the uniform degree with the bands above zeroed.
+        let degrees = [2, 0, 1];
+        let mask: Vec<f32> = (0..9)
+            .flat_map(|coeff| {
+                degrees.map(|degree| f32::from(u8::from(coeff < (degree + 1) * (degree + 1))))
+            })
+            .collect();
+        let mask = Tensor::<Back, 1>::from_floats(mask.as_slice(), &device).reshape([1, 9, 3]);
+        let truncated = render(
+            (sh_coeffs.clone() * mask).into_primitive().tensor(),
+            &options,
+        );
+        let channel_options = RenderOptions {
+            sh_channel_degrees: Some(degrees),
+            ..options.clone()
+        };
+        let planar = planar_channel_sh(sh_coeffs.clone(), degrees);
+        assert_eq!(planar.dims(), [num_points, 9 + 1 + 4]);
+        let channel = render(planar.into_primitive().tensor(), &channel_options);
+        let diff = max_diff(channel, truncated);
+        assert!(diff < 1e-5, "Channel degrees {degrees:?} differ by {diff}");
+    }
+}
+
+#[test]
+fn render_stages_compose_to_full_render() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(48, 40);
+    let num_points = 128;
+    let means =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-1.0, 1.0), &device);
+    let log_scales =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-3.0, -1.5), &device);
+    let quats = Tensor::<Back, 2>::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    let sh_coeffs = Tensor::<Back, 3>::random(
+        [num_points, 4, 3],
+        Distribution::Uniform(-0.5, 0.5),
+        &device,
+    );
+    let opacities =
+        Tensor::<Back, 1>::random([num_points], Distribution::Uniform(0.2, 1.0), &device);
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::IDENTITY,
+        0.9,
+        0.7,
+        glam::vec2(0.5, 0.5),
+    );
+    let options = RenderOptions::default();
+
+    let (full, full_aux) = render_forward(
+        &cam,
+        img_size,
+        RenderInput::new(
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            opacities.clone().into_primitive().tensor(),
+        ),
+        true,
+        &options,
+    );
+
+    let view = project_and_sort(
+        &cam,
+        img_size,
+        RenderInput::new(
+            means.into_primitive().tensor(),
+            log_scales.into_primitive().tensor(),
+            quats.into_primitive().tensor(),
+            sh_coeffs.into_primitive().tensor(),
+            opacities.into_primitive().tensor(),
+        ),
+        true,
+        &options,
+    );
+    let num_tiles = (view.tile_bounds.x * view.tile_bounds.y) as usize;
+    assert_eq!(view.tile_offsets.shape.dims, [num_tiles + 1]);
+
+    // A custom rasterizer sees the same sorted intersections as the full render.
+    let tile_offsets = |offsets: CubeTensor<WgpuRuntime>| {
+        Tensor::<Back, 1, Int>::from_primitive(offsets)
+            .into_data()
+            .into_vec::<i32>()
+            .expect("Wrong tensor type")
+    };
+    let offsets = tile_offsets(view.tile_offsets.clone());
+    assert_eq!(offsets, tile_offsets(full_aux.tile_offsets));
+    assert!(
+        offsets.windows(2).all(|w| w[0] <= w[1]),
+        "Tile offsets must be increasing"
+    );
+    assert!(
+        offsets[num_tiles] > 0,
+        "The splats must intersect some tiles"
+    );
+
+    let (staged, _) = rasterize_stage(view, &options);
+    let diff = (Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(staged))
+        - Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(full)))
+    .abs()
+    .max()
+    .into_scalar()
+    .elem::<f32>();
+    assert!(diff < 1e-6, "Staged render differs by {diff}");
+}
+
+#[test]
+fn clamp_policy_limits_overbright_colors() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(16, 16);
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -2.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    // One large splat, which is overbright in red and blue.
+    let color = [1.1, 0.5, 2.0];
+    let splats = Splats::<Back>::from_raw(
+        &[glam::Vec3::ZERO],
+        Some(&[glam::Quat::IDENTITY]),
+        Some(&[glam::Vec3::splat(0.0)]),
+        Some(&color.map(crate::sh::channel_to_sh)),
+        Some(&[crate::gaussian_splats::inverse_opacity_activation(0.5)]),
+        &device,
+    );
+
+    let soft = |c: f32| {
+        if c > 0.8 {
+            0.8 + 0.2 * ((c - 0.8) / 0.2).tanh()
+        } else {
+            c
+        }
+    };
+    for (clamp_policy, expected) in [
+        (ClampPolicy::None, color),
+        (ClampPolicy::Clamp, color.map(|c: f32| c.min(1.0))),
+        (ClampPolicy::SoftClip, color.map(soft)),
+    ] {
+        let options = RenderOptions {
+            clamp_policy,
+            ..Default::default()
+        };
+        let (img, _) = splats.render_with_options(&cam, img_size, true, &options);
+        let center = img
+            .slice(s![8..9, 8..9, ..])
+            .into_data()
+            .into_vec::<f32>()
+            .expect("Wrong tensor type");
+        let alpha = center[3];
+        assert!(
+            alpha > 0.4,
+            "The splat must cover the center, alpha {alpha}"
+        );
+        for channel in 0..3 {
+            let straight = center[channel] / alpha;
+            assert!(
+                (straight - expected[channel]).abs() < 1e-3,
+                "{clamp_policy:?} channel {channel}: {straight} != {}",
+                expected[channel]
+            );
+        }
+    }
+}
+
+#[test]
+fn sh_degree_stack_matches_capped_renders() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(40, 32);
+    let num_points = 96;
+    let means =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-1.0, 1.0), &device);
+    let log_scales =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-3.0, -1.5), &device);
+    let quats = Tensor::<Back, 2>::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    let sh_coeffs = Tensor::<Back, 3>::random(
+        [num_points, 16, 3],
+        Distribution::Uniform(-0.5, 0.5),
+        &device,
+    );
+    let opacities =
+        Tensor::<Back, 1>::random([num_points], Distribution::Uniform(0.2, 1.0), &device);
+    let cam = Camera::new(
+        glam::vec3(0.2, -0.1, -3.0),
+        glam::Quat::from_rotation_y(0.1),
+        0.9,
+        0.7,
+        glam::vec2(0.45, 0.55),
+    );
+
+    let stack = render_forward_sh_degrees(
+        &cam,
+        img_size,
+        RenderInput::new(
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            opacities.clone().into_primitive().tensor(),
+        ),
+        true,
+        &RenderOptions::default(),
+    );
+    let stack = Tensor::<Back, 4>::from_primitive(TensorPrimitive::Float(stack));
+    assert_eq!(
+        stack.dims(),
+        [4, img_size.y as usize, img_size.x as usize, 4]
+    );
+
+    for degree in 0..4 {
+        let (capped, _) = render_forward(
+            &cam,
+            img_size,
+            RenderInput::new(
+                means.clone().into_primitive().tensor(),
+                log_scales.clone().into_primitive().tensor(),
+                quats.clone().into_primitive().tensor(),
+                sh_coeffs.clone().into_primitive().tensor(),
+                opacities.clone().into_primitive().tensor(),
+            ),
+            true,
+            &RenderOptions {
+                max_sh_degree: Some(degree),
+                ..Default::default()
+            },
+        );
+        let capped = Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(capped));
+        let slice = stack
+            .clone()
+            .slice(s![degree as usize..degree as usize + 1])
+            .squeeze::<3>(0);
+        let diff = (slice - capped).abs().max().into_scalar().elem::<f32>();
+        assert!(diff < 1e-6, "Degree {degree} differs by {diff}");
+    }
+}
+
+#[test]
+fn non_finite_splats_are_culled() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -4.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let num_splats = 16;
+    let means: Vec<_> = (0..num_splats)
+        .map(|i| {
+            let t = i as f32 / num_splats as f32;
+            glam::vec3(t * 2.0 - 1.0, (t * 13.0).sin() * 0.5, t)
+        })
+        .collect();
+    let log_scales = vec![glam::Vec3::splat(-2.5); num_splats];
+    let colors: Vec<f32> = (0..num_splats * 3)
+        .map(|i| crate::sh::channel_to_sh((i % 7) as f32 / 7.0))
+        .collect();
+    let opacities = vec![crate::gaussian_splats::inverse_opacity_activation(0.7); num_splats];
+
+    let splats_without = |skip: &[usize]| {
+        let keep: Vec<usize> = (0..num_splats).filter(|i| !skip.contains(i)).collect();
+        let pick = |data: &[glam::Vec3]| keep.iter().map(|&i| data[i]).collect::<Vec<_>>();
+        let colors: Vec<f32> = keep
+            .iter()
+            .flat_map(|&i| colors[i * 3..i * 3 + 3].to_vec())
+            .collect();
+        Splats::<Back>::from_raw(
+            &pick(&means),
+            None,
+            Some(&pick(&log_scales)),
+            Some(&colors),
+            Some(&vec![opacities[0]; keep.len()]),
+            &device,
+        )
+    };
+
+    // Break a mean with NaN and a scale with infinity.
+    let mut broken_means = means.clone();
+    broken_means[3].x = f32::NAN;
+    let mut broken_scales = log_scales.clone();
+    broken_scales[9].y = f32::INFINITY;
+    let broken = Splats::<Back>::from_raw(
+        &broken_means,
+        None,
+        Some(&broken_scales),
+        Some(&colors),
+        Some(&opacities),
+        &device,
+    );
+    let clean = splats_without(&[3, 9]);
+
+    for frustum_cull in [false, true] {
+        let options = RenderOptions {
+            frustum_cull,
+            ..Default::default()
+        };
+        let (img, aux) = broken.render_with_options(&cam, img_size, true, &options);
+        let (reference, _) = clean.render_with_options(&cam, img_size, true, &options);
+
+        assert_eq!(
+            aux.read_stats().num_non_finite,
+            2,
+            "Both broken splats must be counted"
+        );
+        let img = img
+            .into_data()
+            .into_vec::<f32>()
+            .expect("Wrong tensor type");
+        let reference = reference
+            .into_data()
+            .into_vec::<f32>()
+            .expect("Wrong tensor type");
+        assert!(
+            img.iter().all(|v| v.is_finite()),
+            "The image must not contain NaN"
+        );
+        let max_diff = img
+            .iter()
+            .zip(&reference)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max);
+        assert!(
+            max_diff < 1e-5,
+            "Other splats must render as without the broken ones, max diff {max_diff}"
+        );
+    }
+}
+
+#[test]
+fn alpha_gamma_sharpens_silhouettes() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -2.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let splats = Splats::<Back>::from_raw(
+        &[glam::Vec3::ZERO],
+        Some(&[glam::Quat::IDENTITY]),
+        Some(&[glam::Vec3::splat(-2.0)]),
+        Some(&[0.3, 0.6, 0.9].map(crate::sh::channel_to_sh)),
+        Some(&[crate::gaussian_splats::inverse_opacity_activation(0.95)]),
+        &device,
+    );
+    let render = |alpha_gamma: Option<f32>| {
+        let options = RenderOptions {
+            alpha_gamma: alpha_gamma.map(|gamma| AlphaGamma::new(gamma).expect("Valid gamma")),
+            ..Default::default()
+        };
+        let (img, _) = splats.render_with_options(&cam, img_size, false, &options);
+        img.into_data()
+    };
+
+    // A gamma of one doesn't change anything.
+    let linear = render(None);
+    assert_eq!(
+        linear.as_bytes(),
+        render(Some(1.0)).as_bytes(),
+        "A gamma of one must render exactly as before"
+    );
+
+    // Alpha along the row through the center of the splat, from the center outwards.
+    let profile = |data: &burn::tensor::TensorData| {
+        let values = data.to_vec::<f32>().expect("Wrong tensor type");
+        (16..32)
+            .map(|x| values[(16 * 32 + x) * 4 + 3])
+            .collect::<Vec<_>>()
+    };
+    let linear = profile(&linear);
+    let sharp = profile(&render(Some(3.0)));
+
+    // Each alpha is raised to the gamma, so faint edges fade much more than the opaque center.
+    for (&a, &b) in linear.iter().zip(&sharp) {
+        let expected = a.powf(3.0);
+        if expected > 0.01 {
+            assert!(
+                (b - expected).abs() < 1e-3,
+                "Alpha {a} must become {expected}, got {b}"
+            );
+        }
+    }
+    let edge = linear
+        .iter()
+        .position(|&a| a < 0.2)
+        .expect("The edge must be visible");
+    assert!(
+        sharp[edge] / sharp[0] < 0.5 * linear[edge] / linear[0],
+        "The falloff must be sharper, {sharp:?} vs {linear:?}"
+    );
+}
+
+#[test]
+fn pixel_mask_skips_masked_pixels() {
+    type Base = MainBackendBase;
+
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(48, 40);
+    let num_points = 32;
+    let means =
+        Tensor::<Base, 2>::random([num_points, 3], Distribution::Uniform(-0.5, 0.5), &device);
+    let log_scales = Tensor::<Base, 2>::ones([num_points, 3], &device) * -2.0;
+    let quats: Tensor<Base, 2> =
+        Tensor::<Base, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+            .unsqueeze_dim(0)
+            .repeat_dim(0, num_points);
+    let sh_coeffs = Tensor::<Base, 3>::random([num_points, 1, 3], Distribution::Default, &device);
+    let opacity = Tensor::<Base, 1>::ones([num_points], &device) * 0.7;
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -2.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.8,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let render = |mask: Option<&[i32]>| {
+        let mask = mask.map(|mask| {
+            Tensor::<Base, 2, Int>::from_data(
+                burn::tensor::TensorData::new(
+                    mask.to_vec(),
+                    [img_size.y as usize, img_size.x as usize],
+                ),
+                &device,
+            )
+            .into_primitive()
+        });
+        let (output, _) = render_forward(
+            &cam,
+            img_size,
+            RenderInput {
+                pixel_mask: mask,
+                ..RenderInput::new(
+                    means.clone().into_primitive().tensor(),
+                    log_scales.clone().into_primitive().tensor(),
+                    quats.clone().into_primitive().tensor(),
+                    sh_coeffs.clone().into_primitive().tensor(),
+                    opacity.clone().into_primitive().tensor(),
+                )
+            },
+            true,
+            &RenderOptions::default(),
+        );
+        Tensor::<Base, 3>::from_primitive(TensorPrimitive::Float(output))
+            .into_data()
+            .into_vec::<f32>()
+            .expect("Wrong tensor type")
+    };
+
+    let unmasked = render(None);
+    let full_mask = vec![1; (img_size.x * img_size.y) as usize];
+    assert_eq!(
+        render(Some(&full_mask)),
+        unmasked,
+        "A mask keeping all pixels must render as without a mask"
+    );
+
+    // A triangle over part of the image, which leaves some tiles entirely masked out.
+    let triangle = [
+        glam::vec2(4.0, 3.0),
+        glam::vec2(30.0, 8.0),
+        glam::vec2(10.0, 26.0),
+    ];
+    let mask = polygon_mask(img_size, &triangle);
+    assert!(
+        mask.iter().any(|&m| m != 0) && mask.iter().any(|&m| m == 0),
+        "The mask must keep part of the image"
+    );
+    let masked = render(Some(&mask));
+    for (pixel, &keep) in mask.iter().enumerate() {
+        let range = pixel * 4..pixel * 4 + 4;
+        if keep != 0 {
+            assert_eq!(
+                masked[range.clone()],
+                unmasked[range],
+                "Pixel {pixel} inside the mask must render as without a mask"
+            );
+        } else {
+            assert_eq!(
+                masked[range], [0.0; 4],
+                "Pixel {pixel} outside the mask must be transparent"
+            );
+        }
+    }
+    assert!(
+        unmasked
+            .chunks(4)
+            .zip(&mask)
+            .any(|(rgba, &keep)| keep == 0 && rgba[3] > 0.0),
+        "Splats must cover some masked out pixels"
+    );
+}
+
+#[test]
+fn splat_tile_hits_sum_to_intersections() {
+    let device = WgpuDevice::DefaultDevice;
+    let num_points = 64;
+
+    // Odd splats are behind the camera, so are culled and hit no tiles.
+    let means_data: Vec<f32> = (0..num_points)
+        .flat_map(|i| {
+            let z = if i % 2 == 0 { 0.0 } else { -6.0 };
+            [0.02 * i as f32 - 0.6, 0.01 * i as f32 - 0.3, z]
+        })
+        .collect();
+    let means =
+        Tensor::<Back, 1>::from_floats(means_data.as_slice(), &device).reshape([num_points, 3]);
+    let log_scales =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-3.0, -1.5), &device);
+    let quats = Tensor::<Back, 2>::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    let sh_coeffs = Tensor::<Back, 3>::random([num_points, 1, 3], Distribution::Default, &device);
+    let opacity = Tensor::<Back, 1>::zeros([num_points], &device);
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.6,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let (_, aux) = <Back as SplatForward<Back>>::render_splats(
+        &cam,
+        glam::uvec2(64, 48),
+        means.into_primitive().tensor(),
+        log_scales.into_primitive().tensor(),
+        quats.into_primitive().tensor(),
+        sh_coeffs.into_primitive().tensor(),
+        opacity.into_primitive().tensor(),
+        false,
+        &RenderOptions::default(),
+    );
+    let hits = aux.read_splat_tile_hits();
+
+    assert_eq!(hits.len(), num_points);
+    let num_intersections = aux.num_intersections().into_scalar().elem::<i32>();
+    assert!(num_intersections > 0, "Splats must intersect some tiles");
+    assert_eq!(
+        hits.iter().map(|&h| i64::from(h)).sum::<i64>(),
+        i64::from(num_intersections),
+        "Tile hits must sum to the number of intersections"
+    );
+    assert!(
+        hits.iter().skip(1).step_by(2).all(|&h| h == 0),
+        "Culled splats must not hit tiles: {hits:?}"
+    );
+    assert_eq!(hits, block_on(aux.read_splat_tile_hits_async()));
+}
+
+#[test]
+fn id_render_picks_splat_under_pixel() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(64, 48);
+
+    // A ring of small splats around a lone splat in the center, with another splat hidden
+    // behind it.
+    let mut means: Vec<glam::Vec3> = (0..6)
+        .map(|i| {
+            let angle = i as f32 * std::f32::consts::TAU / 6.0;
+            glam::vec3(angle.cos(), angle.sin(), 0.0) * 0.4
+        })
+        .collect();
+    let center_id = means.len() as i32;
+    means.push(glam::Vec3::ZERO);
+    means.push(glam::vec3(0.0, 0.0, 1.0));
+    let num_points = means.len();
+
+    let means = Tensor::<Back, 1>::from_floats(
+        means
+            .iter()
+            .flat_map(|m| m.to_array())
+            .collect::<Vec<_>>()
+            .as_slice(),
+        &device,
+    )
+    .reshape([num_points, 3]);
+    let log_scales = Tensor::<Back, 2>::ones([num_points, 3], &device) * -3.0;
+    let quats: Tensor<Back, 2> =
+        Tensor::<Back, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+            .unsqueeze_dim(0)
+            .repeat_dim(0, num_points);
+    let sh_coeffs = Tensor::<Back, 3>::ones([num_points, 1, 3], &device);
+    let raw_opacity = Tensor::<Back, 1>::ones([num_points], &device) * 2.0;
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.8,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let ids = render_forward_ids(
+        &cam,
+        img_size,
+        RenderInput::new(
+            means.into_primitive().tensor(),
+            log_scales.into_primitive().tensor(),
+            quats.into_primitive().tensor(),
+            sh_coeffs.into_primitive().tensor(),
+            raw_opacity.into_primitive().tensor(),
+        ),
+        &RenderOptions::default(),
+    );
+    assert_eq!(ids.shape.dims, [48, 64]);
+    let ids = Tensor::<Back, 2, Int>::from_primitive(ids)
+        .into_data()
+        .to_vec::<i32>()
+        .expect("Wrong tensor type");
+
+    let at = |x: usize, y: usize| ids[y * 64 + x];
+    assert_eq!(
+        at(32, 24),
+        center_id,
+        "The center must pick the front splat"
+    );
+    assert_eq!(at(0, 0), -1, "Empty pixels must have no id");
+    assert!(
+        ids.iter().all(|&id| id >= -1 && id < num_points as i32),
+        "Ids must be global splat ids"
+    );
+    assert!(
+        !ids.contains(&(center_id + 1)),
+        "The hidden splat must never be picked"
+    );
+}
+
+#[test]
+fn log_depth_keys_order_distant_splats() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+
+    // A green splat stored first, just behind a red splat, both a thousand units away. Their
+    // depths fall in the same bucket of the float key once stable ties drop its low bits.
+    let means = [0.0, 0.0, 995.05, 0.0, 0.0, 995.01];
+    let colors = [-1.0, 1.0, -1.0, 1.0, -1.0, -1.0];
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -5.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let render = |depth_key| {
+        let means = Tensor::<Back, 1>::from_floats(means, &device).reshape([2, 3]);
+        let sh_coeffs = Tensor::<Back, 1>::from_floats(colors, &device).reshape([2, 1, 3]);
+        let log_scales = Tensor::<Back, 2>::ones([2, 3], &device) * 4.0;
+        let quats: Tensor<Back, 2> =
+            Tensor::<Back, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+                .unsqueeze_dim(0)
+                .repeat_dim(0, 2);
+        let opacity = Tensor::<Back, 1>::ones([2], &device) * 0.99;
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.into_primitive().tensor(),
+            log_scales.into_primitive().tensor(),
+            quats.into_primitive().tensor(),
+            sh_coeffs.into_primitive().tensor(),
+            opacity.into_primitive().tensor(),
+            true,
+            &RenderOptions {
+                stable_depth_ties: true,
+                depth_key,
+                ..Default::default()
+            },
+        );
+        let center = Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+            .slice(s![16, 16, 0..3])
+            .into_data()
+            .to_vec::<f32>()
+            .expect("Wrong tensor type");
+        (center[0], center[1])
+    };
+
+    let (red, green) = render(DepthKey::Float);
+    assert!(
+        green > red,
+        "Float keys must tie the splats, and draw them in stored order"
+    );
+    let (red, green) = render(DepthKey::log(0.1, 1e4).expect("Valid depth range"));
+    assert!(
+        red > green,
+        "Log keys must draw the front splat in front, got red {red}, green {green}"
+    );
+}
+
+#[test]
+fn clip_planes_cull_splats_behind_them() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(64, 64);
+
+    // A row of splats along x, all in view.
+    let xs: Vec<f32> = (0..20).map(|i| -0.95 + 0.1 * i as f32).collect();
+    let means: Vec<f32> = xs.iter().flat_map(|&x| [x, 0.0, 0.0]).collect();
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -4.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.8,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let num_visible = |options: &RenderOptions| {
+        let means = Tensor::<Back, 1>::from_floats(means.as_slice(), &device).reshape([20, 3]);
+        let log_scales = Tensor::<Back, 2>::ones([20, 3], &device) * -4.0;
+        let quats: Tensor<Back, 2> =
+            Tensor::<Back, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+                .unsqueeze_dim(0)
+                .repeat_dim(0, 20);
+        let sh_coeffs = Tensor::<Back, 3>::ones([20, 1, 3], &device);
+        let opacity = Tensor::<Back, 1>::ones([20], &device) * 0.9;
+        let (_, aux) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.into_primitive().tensor(),
+            log_scales.into_primitive().tensor(),
+            quats.into_primitive().tensor(),
+            sh_coeffs.into_primitive().tensor(),
+            opacity.into_primitive().tensor(),
+            false,
+            options,
+        );
+        aux.num_visible().into_scalar().elem::<i32>() as usize
+    };
+
+    assert_eq!(num_visible(&RenderOptions::default()), 20);
+
+    let keeps = |planes: &[ClipPlane], shift: f32| {
+        xs.iter()
+            .filter(|&&x| {
+                planes
+                    .iter()
+                    .all(|plane| plane.keeps(glam::vec3(x + shift, 0.0, 0.0)))
+            })
+            .count()
+    };
+
+    // A plane through the middle of the row, keeping positive x.
+    let half =
+        vec![ClipPlane::through_point(glam::Vec3::ZERO, glam::Vec3::X).expect("Valid plane")];
+    let options = RenderOptions {
+        clip_planes: half.clone(),
+        ..Default::default()
+    };
+    assert_eq!(keeps(&half, 0.0), 10);
+    assert_eq!(
+        num_visible(&options),
+        10,
+        "Only splats with x >= 0 must remain"
+    );
+
+    // Two planes cut out a slab, also when frustum culling.
+    let slab = vec![
+        ClipPlane::new(glam::Vec3::X, -0.3).expect("Valid plane"),
+        ClipPlane::new(-glam::Vec3::X, -0.42).expect("Valid plane"),
+    ];
+    let options = RenderOptions {
+        clip_planes: slab.clone(),
+        frustum_cull: true,
+        ..Default::default()
+    };
+    assert_eq!(num_visible(&options), keeps(&slab, 0.0));
+
+    let options = RenderOptions {
+        clip_planes: half.clone(),
+        world_transform: WorldTransform::new(
+            glam::vec3(0.2, 0.0, 0.0),
+            glam::Quat::IDENTITY,
+            WorldScale::ONE,
+        ),
+        ..Default::default()
+    };
+    assert_eq!(
+        num_visible(&options),
+        keeps(&half, 0.2),
+        "Planes must clip in world space"
+    );
+}
+
+#[test]
+fn unnormalized_quats_render_like_normalized() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(48, 48);
+    let num_splats = 16;
+
+    let means: Vec<f32> = (0..num_splats)
+        .flat_map(|i| {
+            let angle = i as f32 * 0.7;
+            [angle.cos() * 0.6, angle.sin() * 0.6, (i % 3) as f32 * 0.2]
+        })
+        .collect();
+    let rotations: Vec<glam::Quat> = (0..num_splats)
+        .map(|i| glam::Quat::from_euler(glam::EulerRot::XYZ, i as f32, 0.5 * i as f32, 0.3))
+        .collect();
+    // Scale each quat by a different factor, from far below to far above unit length.
+    let unnormalized: Vec<f32> = rotations
+        .iter()
+        .enumerate()
+        .flat_map(|(i, rot)| (*rot * (0.1 + 0.5 * i as f32)).to_array())
+        .collect();
+    let normalized: Vec<f32> = rotations.iter().flat_map(|rot| rot.to_array()).collect();
+
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.8,
+        glam::vec2(0.5, 0.5),
+    );
+    let render = |quats: &[f32]| {
+        let means =
+            Tensor::<Back, 1>::from_floats(means.as_slice(), &device).reshape([num_splats, 3]);
+        let log_scales =
+            Tensor::<Back, 1>::from_floats([-1.5, -2.5, -3.0], &device).repeat_dim(0, num_splats);
+        let log_scales = log_scales.reshape([num_splats, 3]);
+        let quats = Tensor::<Back, 1>::from_floats(quats, &device).reshape([num_splats, 4]);
+        let sh_coeffs = Tensor::<Back, 3>::ones([num_splats, 1, 3], &device);
+        let opacity = Tensor::<Back, 1>::ones([num_splats], &device) * 0.8;
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.into_primitive().tensor(),
+            log_scales.into_primitive().tensor(),
+            quats.into_primitive().tensor(),
+            sh_coeffs.into_primitive().tensor(),
+            opacity.into_primitive().tensor(),
+            true,
+            &RenderOptions::default(),
+        );
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(output))
+    };
+
+    let expected = render(&normalized);
+    assert!(
+        expected
+            .clone()
+            .slice(s![.., .., 3])
+            .max()
+            .into_scalar()
+            .elem::<f32>()
+            > 0.5,
+        "The splats must be visible"
+    );
+    let diff = (render(&unnormalized) - expected)
+        .abs()
+        .max()
+        .into_scalar()
+        .elem::<f32>();
+    assert!(
+        diff < 1e-4,
+        "Unnormalized quats must render like normalized ones, differed by {diff}"
+    );
+}
+
+#[test]
+fn render_into_target_matches_allocating_render() {
+    type Base = MainBackendBase;
+
+    let device = WgpuDevice::DefaultDevice;
+    let num_points = 32;
+    let (means, log_scales, quats, sh_coeffs, raw_opacity) =
+        random_splats::<Base>(num_points, 1, &device);
+    let cam = test_camera();
+    let img_size = glam::uvec2(40, 24);
+    let mut context = RenderContext::new();
+
+    for (bwd_info, channels) in [(false, 1), (true, 4)] {
+        let (expected, _) = render_forward(
+            &cam,
+            img_size,
+            RenderInput::new(
+                means.clone().into_primitive().tensor(),
+                log_scales.clone().into_primitive().tensor(),
+                quats.clone().into_primitive().tensor(),
+                sh_coeffs.clone().into_primitive().tensor(),
+                raw_opacity.clone().into_primitive().tensor(),
+            ),
+            bwd_info,
+            &RenderOptions::default(),
+        );
+        let expected = Tensor::<Base, 3>::from_primitive(TensorPrimitive::Float(expected));
+
+        // Render twice into the same target, like a viewer would every frame.
+        let target = Tensor::<Base, 3>::zeros(
+            [img_size.y as usize, img_size.x as usize, channels],
+            &device,
+        );
+        let target = target.into_primitive().tensor();
+        for _ in 0..2 {
+            let aux = render_forward_into(
+                &mut context,
+                &target,
+                &cam,
+                RenderInput::new(
+                    means.clone().into_primitive().tensor(),
+                    log_scales.clone().into_primitive().tensor(),
+                    quats.clone().into_primitive().tensor(),
+                    sh_coeffs.clone().into_primitive().tensor(),
+                    raw_opacity.clone().into_primitive().tensor(),
+                ),
+                bwd_info,
+                &RenderOptions::default(),
+            );
+            assert!(aux.num_visible().into_scalar().elem::<i32>() > 0);
+        }
+        let written = Tensor::<Base, 3>::from_primitive(TensorPrimitive::Float(target));
+
+        // Packed colors aren't meaningful floats, so compare the bytes.
+        assert_eq!(
+            written.into_data().as_bytes(),
+            expected.into_data().as_bytes(),
+            "Rendering into a target must match an allocating render (bwd_info: {bwd_info})"
+        );
+    }
+}
+
+#[test]
+fn cubemap_faces_are_continuous_across_edges() {
+    let device = WgpuDevice::DefaultDevice;
+    let face_size = 64;
+
+    // A dense sphere of splats around the camera, with colors that vary smoothly by direction.
+    let num_splats = 4000;
+    let golden_angle = std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+    let dirs: Vec<glam::Vec3> = (0..num_splats)
+        .map(|i| {
+            let y = 1.0 - 2.0 * (i as f32 + 0.5) / num_splats as f32;
+            let r = (1.0 - y * y).sqrt();
+            let angle = golden_angle * i as f32;
+            glam::vec3(r * angle.cos(), y, r * angle.sin())
+        })
+        .collect();
+    let means: Vec<_> = dirs.iter().map(|dir| *dir * 5.0).collect();
+    let log_scales = vec![glam::Vec3::splat(0.3f32.ln()); num_splats];
+    let sh_coeffs: Vec<f32> = dirs
+        .iter()
+        .flat_map(|dir| (0.4 * *dir / 0.282_094_8).to_array())
+        .collect();
+    let splats = Splats::<Back>::from_raw(
+        &means,
+        None,
+        Some(&log_scales),
+        Some(&sh_coeffs),
+        Some(&vec![3.0; num_splats]),
+        &device,
+    );
+
+    let (faces, auxes) =
+        splats.render_cubemap(glam::Vec3::ZERO, face_size, true, &RenderOptions::default());
+    assert_eq!(faces.dims(), [CUBEMAP_FACES, 64, 64, 4]);
+    assert_eq!(auxes.len(), CUBEMAP_FACES);
+    let faces = faces
+        .into_data()
+        .to_vec::<f32>()
+        .expect("Wrong tensor type");
+
+    let sample = |dir: glam::Vec3| {
+        let (face, uv) = cubemap_face_uv(dir).expect("Valid direction");
+        let [x, y] = (uv * face_size as f32)
+            .as_uvec2()
+            .min(glam::UVec2::splat(face_size - 1))
+            .to_array()
+            .map(|v| v as usize);
+        let base = ((face * 64 + y) * 64 + x) * 4;
+        (face, glam::Vec3::from_slice(&faces[base..base + 3]))
+    };
+
+    // Sample both sides of each of the twelve edges where two faces meet.
+    let axes = [
+        glam::Vec3::X,
+        glam::Vec3::NEG_X,
+        glam::Vec3::Y,
+        glam::Vec3::NEG_Y,
+        glam::Vec3::Z,
+        glam::Vec3::NEG_Z,
+    ];
+    let mut num_edges = 0;
+    let mut max_diff = 0.0f32;
+    for (i, a) in axes.iter().enumerate() {
+        for b in &axes[i + 1..] {
+            if a.dot(*b) != 0.0 {
+                continue;
+            }
+            num_edges += 1;
+            let along = a.cross(*b);
+            for step in -4..=4 {
+                let edge = *a + *b + along * (0.2 * step as f32);
+                let (face_a, color_a) = sample(edge + 0.02 * *a);
+                let (face_b, color_b) = sample(edge + 0.02 * *b);
+                assert_ne!(face_a, face_b, "Samples must be on both sides of the edge");
+                max_diff = max_diff.max((color_a - color_b).abs().max_element());
+            }
+        }
+    }
+    assert_eq!(num_edges, 12);
+    assert!(
+        max_diff < 0.1,
+        "Colors must be continuous across face edges, differed by {max_diff}"
+    );
+}
+
+#[test]
+fn tile_sort_bits_cover_largest_tile_id() {
+    assert_eq!(tile_sort_bits(1), 0, "A single tile needs no tile sort");
+    assert_eq!(tile_sort_bits(2), 1);
+    assert_eq!(tile_sort_bits(4), 2);
+    assert_eq!(tile_sort_bits(5), 3);
+    assert_eq!(tile_sort_bits(16), 4);
+    for num_tiles in 1..1000u32 {
+        let bits = tile_sort_bits(num_tiles);
+        assert!(
+            u64::from(num_tiles - 1) < 1u64 << bits,
+            "{bits} bits must fit tile id {}",
+            num_tiles - 1
+        );
+    }
+}
+
+#[test]
+fn few_tile_renders_match_larger_renders() {
+    let device = WgpuDevice::DefaultDevice;
+    let num_points = 64;
+    let means =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-1.0, 1.0), &device);
+    let log_scales =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-3.0, -1.5), &device);
+    let quats: Tensor<Back, 2> =
+        Tensor::<Back, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+            .unsqueeze_dim(0)
+            .repeat_dim(0, num_points);
+    let sh_coeffs = Tensor::<Back, 3>::random([num_points, 1, 3], Distribution::Default, &device);
+    let raw_opacity = Tensor::<Back, 1>::zeros([num_points], &device);
+
+    let render = |img_size: glam::UVec2| {
+        // The same intrinsics in pixels at every size, so the top left of a larger render shows
+        // the same part of the scene.
+        let cam = Camera::from_intrinsics(
+            glam::vec3(0.0, 0.0, -3.0),
+            glam::Quat::IDENTITY,
+            glam::dvec2(24.0, 24.0),
+            glam::vec2(12.0, 12.0),
+            img_size,
+        );
+        let (img, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            raw_opacity.clone().into_primitive().tensor(),
+            true,
+            &RenderOptions::default(),
+        );
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(img))
+    };
+
+    let large = render(glam::uvec2(80, 80));
+    // A single tile, and a power of two number of tiles.
+    for size in [16, 32] {
+        let small = render(glam::uvec2(size, size));
+        assert!(
+            small
+                .clone()
+                .slice(s![.., .., 3])
+                .max()
+                .into_scalar()
+                .elem::<f32>()
+                > 0.1,
+            "The splats must be visible"
+        );
+        let crop = large
+            .clone()
+            .slice(s![0..size as usize, 0..size as usize, ..]);
+        let diff = (small - crop).abs().max().into_scalar().elem::<f32>();
+        assert!(
+            diff < 1e-6,
+            "A {size}x{size} render must match the general path, differed by {diff}"
+        );
+    }
+}
+
+#[test]
+fn precomputed_colors_match_base_sh() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(48, 32);
+    let num_points = 64;
+    let (means, log_scales, quats, _, raw_opacity) = random_splats::<Back>(num_points, 1, &device);
+    let cam = test_camera();
+
+    let colors: Vec<glam::Vec3> = (0..num_points)
+        .map(|i| {
+            let t = i as f32 / num_points as f32;
+            glam::vec3(t, 1.0 - t, (7.0 * t).fract())
+        })
+        .collect();
+    let flat_colors: Vec<f32> = colors.iter().flat_map(|c| c.to_array()).collect();
+    let sh_coeffs: Vec<f32> = colors
+        .iter()
+        .flat_map(|c| rgb_to_sh(*c).to_array())
+        .collect();
+
+    for bwd_info in [false, true] {
+        let (expected, _) = render_forward(
+            &cam,
+            img_size,
+            RenderInput::new(
+                means.clone().into_primitive().tensor(),
+                log_scales.clone().into_primitive().tensor(),
+                quats.clone().into_primitive().tensor(),
+                Tensor::<Back, 1>::from_floats(sh_coeffs.as_slice(), &device)
+                    .reshape([num_points, 1, 3])
+                    .into_primitive()
+                    .tensor(),
+                raw_opacity.clone().into_primitive().tensor(),
+            ),
+            bwd_info,
+            &RenderOptions::default(),
+        );
+        let (img, _) = render_forward(
+            &cam,
+            img_size,
+            RenderInput::from_colors(
+                means.clone().into_primitive().tensor(),
+                log_scales.clone().into_primitive().tensor(),
+                quats.clone().into_primitive().tensor(),
+                Tensor::<Back, 1>::from_floats(flat_colors.as_slice(), &device)
+                    .reshape([num_points, 3])
+                    .into_primitive()
+                    .tensor(),
+                raw_opacity.clone().into_primitive().tensor(),
+            ),
+            bwd_info,
+            &RenderOptions::default(),
+        );
+
+        let expected = Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(expected));
+        let img = Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(img));
+        if bwd_info {
+            let diff = (img - expected).abs().max().into_scalar().elem::<f32>();
+            assert!(diff < 1e-5, "Precomputed colors differ from sh by {diff}");
+        } else {
+            // Packed colors can round to a neighbouring byte.
+            let to_bytes = |img: Tensor<Back, 3>| img.into_data().as_bytes().to_vec();
+            let (img, expected) = (to_bytes(img), to_bytes(expected));
+            let max_diff = img
+                .iter()
+                .zip(&expected)
+                .map(|(a, b)| a.abs_diff(*b))
+                .max()
+                .unwrap_or(0);
+            assert!(max_diff <= 1, "Packed colors differ by {max_diff}");
+        }
+    }
+}
+
+/// Degree 3 spherical harmonics as evaluated by the 3DGS reference implementation.
+fn reference_3dgs_sh(coeffs: &[glam::Vec3; 16], dir: glam::Vec3) -> glam::Vec3 {
+    const C0: f32 = 0.282_094_8;
+    const C1: f32 = 0.488_602_5;
+    const C2: [f32; 5] = [
+        1.092_548_4,
+        -1.092_548_4,
+        0.315_391_57,
+        -1.092_548_4,
+        0.546_274_2,
+    ];
+    const C3: [f32; 7] = [
+        -0.590_043_6,
+        2.890_611_4,
+        -0.457_045_8,
+        0.373_176_33,
+        -0.457_045_8,
+        1.445_305_7,
+        -0.590_043_6,
+    ];
+    let (x, y, z) = (dir.x, dir.y, dir.z);
+    let (xx, yy, zz) = (x * x, y * y, z * z);
+    let c = coeffs;
+    C0 * c[0] - C1 * y * c[1] + C1 * z * c[2] - C1 * x * c[3]
+        + C2[0] * x * y * c[4]
+        + C2[1] * y * z * c[5]
+        + C2[2] * (2.0 * zz - xx - yy) * c[6]
+        + C2[3] * x * z * c[7]
+        + C2[4] * (xx - yy) * c[8]
+        + C3[0] * y * (3.0 * xx - yy) * c[9]
+        + C3[1] * x * y * z * c[10]
+        + C3[2] * y * (4.0 * zz - xx - yy) * c[11]
+        + C3[3] * z * (2.0 * zz - 3.0 * xx - 3.0 * yy) * c[12]
+        + C3[4] * x * (4.0 * zz - xx - yy) * c[13]
+        + C3[5] * z * (xx - yy) * c[14]
+        + C3[6] * x * (xx - 3.0 * yy) * c[15]
+        + 0.5
+}
+
+#[test]
+fn sh_colors_match_3dgs_reference() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+    let mean = glam::vec3(0.3, -0.2, 0.1);
+    let eye = glam::vec3(1.5, -1.0, -3.0);
+    let cam = Camera::look_at(eye, mean, glam::Vec3::NEG_Y);
+    let dir = (mean - eye).normalize();
+
+    let coeffs: [glam::Vec3; 16] = std::array::from_fn(|k| {
+        let k = k as f32;
+        0.03 * glam::vec3(
+            (3.0 * k + 1.0).sin(),
+            (5.0 * k + 2.0).cos(),
+            (k + 0.5).sin(),
+        )
+    });
+    let flat: Vec<f32> = coeffs.iter().flat_map(|c| c.to_array()).collect();
+
+    let rotation = glam::Quat::from_euler(glam::EulerRot::YXZ, 0.7, -0.4, 1.1);
+    for (basis, basis_dir) in [
+        (ShBasis::Splats, dir),
+        (ShBasis::Rotated(rotation), rotation * dir),
+    ] {
+        let options = RenderOptions {
+            sh_basis: basis,
+            ..Default::default()
+        };
+        let (img, _) = render_forward(
+            &cam,
+            img_size,
+            RenderInput::new(
+                Tensor::<Back, 1>::from_floats(mean.to_array(), &device)
+                    .reshape([1, 3])
+                    .into_primitive()
+                    .tensor(),
+                Tensor::<Back, 2>::zeros([1, 3], &device)
+                    .into_primitive()
+                    .tensor(),
+                Tensor::<Back, 2>::from_floats([[1.0, 0.0, 0.0, 0.0]], &device)
+                    .into_primitive()
+                    .tensor(),
+                Tensor::<Back, 1>::from_floats(flat.as_slice(), &device)
+                    .reshape([1, 16, 3])
+                    .into_primitive()
+                    .tensor(),
+                Tensor::<Back, 1>::ones([1], &device)
+                    .into_primitive()
+                    .tensor(),
+            ),
+            true,
+            &options,
+        );
+        let img = Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(img));
+        let pixel: Vec<f32> = img
+            .slice(s![16, 16, ..])
+            .into_data()
+            .into_vec()
+            .expect("Wrong type");
+        let color = glam::vec3(pixel[0], pixel[1], pixel[2]) / pixel[3];
+
+        let expected = reference_3dgs_sh(&coeffs, basis_dir);
+        assert!(
+            (color - expected).abs().max_element() < 1e-3,
+            "{basis:?}: rendered {color}, the 3DGS reference gives {expected}"
+        );
+    }
+}
+
+#[test]
+fn partial_renders_match_full_renders_after_edits() {
+    type Base = MainBackendBase;
+
+    let device = WgpuDevice::DefaultDevice;
+    let num_points = 256;
+    let mut means =
+        Tensor::<Base, 2>::random([num_points, 3], Distribution::Uniform(-1.0, 1.0), &device);
+    let log_scales =
+        Tensor::<Base, 2>::random([num_points, 3], Distribution::Uniform(-3.0, -2.0), &device);
+    let quats = Tensor::<Base, 2>::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    let mut sh_coeffs = Tensor::<Base, 3>::random(
+        [num_points, 4, 3],
+        Distribution::Uniform(-0.3, 0.3),
+        &device,
+    );
+    let opacities = Tensor::<Base, 1>::ones([num_points], &device) * 0.6;
+    let img_size = glam::uvec2(48, 40);
+    let options = RenderOptions::default();
+    let mut context = RenderContext::new();
+
+    let ids = |ids: &[i32]| Tensor::<Base, 1, Int>::from_ints(ids, &device);
+    let mut check = |cam: &Camera, means: &Tensor<Base, 2>, sh_coeffs: &Tensor<Base, 3>, dirty| {
+        let (expected, _) = render_forward(
+            cam,
+            img_size,
+            RenderInput::new(
+                means.clone().into_primitive().tensor(),
+                log_scales.clone().into_primitive().tensor(),
+                quats.clone().into_primitive().tensor(),
+                sh_coeffs.clone().into_primitive().tensor(),
+                opacities.clone().into_primitive().tensor(),
+            ),
+            true,
+            &options,
+        );
+        let (img, aux) = render_forward_partial(
+            &mut context,
+            cam,
+            img_size,
+            RenderInput::new(
+                means.clone().into_primitive().tensor(),
+                log_scales.clone().into_primitive().tensor(),
+                quats.clone().into_primitive().tensor(),
+                sh_coeffs.clone().into_primitive().tensor(),
+                opacities.clone().into_primitive().tensor(),
+            ),
+            dirty,
+            true,
+            &options,
+        );
+        let expected = Tensor::<Base, 3>::from_primitive(TensorPrimitive::Float(expected));
+        let img = Tensor::<Base, 3>::from_primitive(TensorPrimitive::Float(img));
+        let diff = (img - expected.clone())
+            .abs()
+            .max()
+            .into_scalar()
+            .elem::<f32>();
+        assert!(
+            diff < 1e-5,
+            "Partial render differs from a full render by {diff}"
+        );
+        assert!(aux.num_visible().into_scalar().elem::<i32>() > 0);
+        expected.sum().into_scalar().elem::<f32>()
+    };
+
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -4.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.7,
+        glam::vec2(0.5, 0.5),
+    );
+    let first = check(&cam, &means, &sh_coeffs, None);
+
+    // Move some splats, and change the colors of others.
+    let moved = [3, 17, 200];
+    means = means.select_assign(
+        0,
+        ids(&moved),
+        Tensor::<Base, 2>::from_floats([[0.3, -0.2, 0.5]; 3], &device),
+    );
+    let recolored = [17, 64];
+    sh_coeffs = sh_coeffs.select_assign(
+        0,
+        ids(&recolored),
+        Tensor::<Base, 3>::ones([2, 4, 3], &device) * 0.4,
+    );
+    let edited = check(
+        &cam,
+        &means,
+        &sh_coeffs,
+        Some(ids(&[3, 17, 64, 200]).into_primitive()),
+    );
+    assert!(
+        (first - edited).abs() > 1e-3,
+        "The edits must change the image"
+    );
+
+    // Nothing changed.
+    check(&cam, &means, &sh_coeffs, Some(ids(&[]).into_primitive()));
+
+    // Move a splat out of view, and back in.
+    for offset in [100.0, -100.0] {
+        means = means.select_assign(
+            0,
+            ids(&[5]),
+            Tensor::<Base, 2>::from_floats([[offset, 0.0, 0.0]], &device),
+        );
+        check(&cam, &means, &sh_coeffs, Some(ids(&[5]).into_primitive()));
+    }
+
+    // Moving the camera projects all splats again.
+    let moved_cam = Camera::look_at(
+        glam::vec3(1.0, -0.5, -3.5),
+        glam::Vec3::ZERO,
+        glam::Vec3::NEG_Y,
+    );
+    check(
+        &moved_cam,
+        &means,
+        &sh_coeffs,
+        Some(ids(&[]).into_primitive()),
+    );
+}
+
+#[test]
+fn blend_modes_match_hand_computed_colors() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+    let cam = Camera::new(
+        glam::Vec3::ZERO,
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+
+    // Two large splats straight ahead, so the gaussians are flat at the center of the image and
+    // each splat blends with its opacity there.
+    let near = (glam::vec3(1.0, 0.0, 0.2), 0.5);
+    let far = (glam::vec3(0.2, 1.0, 0.6), 0.8);
+    let means = [0.0, 0.0, 2.0, 0.0, 0.0, 4.0];
+    let log_scales = [0.0; 6];
+    let quats = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+    let sh_coeffs: Vec<f32> = [near.0, far.0]
+        .iter()
+        .flat_map(|&rgb| rgb_to_sh(rgb).to_array())
+        .collect();
+    let opacities = [near.1, far.1];
+
+    let coverage = 1.0 - (1.0 - near.1) * (1.0 - far.1);
+    for (mode, expected) in [
+        (
+            BlendMode::AlphaOver,
+            near.0 * near.1 + far.0 * far.1 * (1.0 - near.1),
+        ),
+        (BlendMode::Additive, near.0 * near.1 + far.0 * far.1),
+        (BlendMode::Max, (near.0 * near.1).max(far.0 * far.1)),
+    ] {
+        let options = RenderOptions {
+            blend_mode: mode,
+            ..Default::default()
+        };
+        let (img, _) = render_forward(
+            &cam,
+            img_size,
+            RenderInput::new(
+                Tensor::<Back, 1>::from_floats(means, &device)
+                    .reshape([2, 3])
+                    .into_primitive()
+                    .tensor(),
+                Tensor::<Back, 1>::from_floats(log_scales, &device)
+                    .reshape([2, 3])
+                    .into_primitive()
+                    .tensor(),
+                Tensor::<Back, 1>::from_floats(quats, &device)
+                    .reshape([2, 4])
+                    .into_primitive()
+                    .tensor(),
+                Tensor::<Back, 1>::from_floats(sh_coeffs.as_slice(), &device)
+                    .reshape([2, 1, 3])
+                    .into_primitive()
+                    .tensor(),
+                Tensor::<Back, 1>::from_floats(opacities, &device)
+                    .into_primitive()
+                    .tensor(),
+            ),
+            true,
+            &options,
+        );
+        let img = Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(img));
+        let gpu: Vec<f32> = img
+            .slice(s![16, 16, ..])
+            .into_data()
+            .into_vec()
+            .expect("Wrong type");
+        let cpu = render_reference(
+            &cam,
+            img_size,
+            &means,
+            &log_scales,
+            &quats,
+            &sh_coeffs,
+            &opacities,
+            &options,
+        );
+        let offset = (16 * img_size.x as usize + 16) * 4;
+
+        for (name, pixel) in [("GPU", &gpu[..]), ("CPU", &cpu[offset..offset + 4])] {
+            let color = glam::vec3(pixel[0], pixel[1], pixel[2]);
+            assert!(
+                (color - expected).abs().max_element() < 2e-3,
+                "{mode:?}: {name} rendered {color}, expected {expected}"
+            );
+            assert!(
+                (pixel[3] - coverage).abs() < 2e-3,
+                "{mode:?}: {name} alpha {} must be the coverage {coverage}",
+                pixel[3]
+            );
+        }
+    }
+}
+
+#[test]
+fn pixel_resort_fixes_crossing_splats() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(64, 64);
+    let cam = Camera::new(
+        glam::Vec3::ZERO,
+        glam::Quat::IDENTITY,
+        1.0,
+        1.0,
+        glam::vec2(0.5, 0.5),
+    );
+
+    // Pairs of flat splats that cross: one facing the camera, and one turned 45 degrees just
+    // behind it. The tile order puts the facing splat in front, but on one side of each pair the
+    // turned splat is nearer.
+    let tilt = glam::Quat::from_rotation_y(std::f32::consts::FRAC_PI_4);
+    let mut means = vec![];
+    let mut log_scales = vec![];
+    let mut quats = vec![];
+    let mut sh_coeffs = vec![];
+    for (i, (x, y)) in [(-1.2, -1.2), (1.2, -1.2), (-1.2, 1.2), (1.2, 1.2)]
+        .into_iter()
+        .enumerate()
+    {
+        let turned = if i % 2 == 0 { tilt } else { tilt.inverse() };
+        for (z, rotation, rgb) in [
+            (4.0, glam::Quat::IDENTITY, glam::vec3(0.1, 0.9, 0.2)),
+            (4.05, turned, glam::vec3(0.9, 0.1, 0.3)),
+        ] {
+            means.extend([x, y, z]);
+            log_scales.extend([-1.2, -1.2, -6.0]);
+            quats.extend([rotation.w, rotation.x, rotation.y, rotation.z]);
+            sh_coeffs.extend(rgb_to_sh(rgb).to_array());
+        }
+    }
+    let opacities = vec![0.95; 8];
+
+    let render = |options: &RenderOptions| {
+        let (img, _) = render_forward(
+            &cam,
+            img_size,
+            RenderInput::new(
+                Tensor::<Back, 1>::from_floats(means.as_slice(), &device)
+                    .reshape([8, 3])
+                    .into_primitive()
+                    .tensor(),
+                Tensor::<Back, 1>::from_floats(log_scales.as_slice(), &device)
+                    .reshape([8, 3])
+                    .into_primitive()
+                    .tensor(),
+                Tensor::<Back, 1>::from_floats(quats.as_slice(), &device)
+                    .reshape([8, 4])
+                    .into_primitive()
+                    .tensor(),
+                Tensor::<Back, 1>::from_floats(sh_coeffs.as_slice(), &device)
+                    .reshape([8, 1, 3])
+                    .into_primitive()
+                    .tensor(),
+                Tensor::<Back, 1>::from_floats(opacities.as_slice(), &device)
+                    .into_primitive()
+                    .tensor(),
+            ),
+            true,
+            options,
+        );
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(img))
+            .into_data()
+            .into_vec::<f32>()
+            .expect("Wrong type")
+    };
+
+    // With at most two splats per pixel, a window of 8 splats sorts every pixel exactly.
+    let exact = render_reference(
+        &cam,
+        img_size,
+        &means,
+        &log_scales,
+        &quats,
+        &sh_coeffs,
+        &opacities,
+        &RenderOptions {
+            pixel_resort: ResortWindow::new(8).ok(),
+            ..Default::default()
+        },
+    );
+    let order_error = |img: &[f32]| {
+        img.iter()
+            .zip(&exact)
+            .map(|(a, b)| (a - b).abs())
+            .sum::<f32>()
+            / exact.len() as f32
+    };
+
+    let per_tile = order_error(&render(&RenderOptions::default()));
+    let per_pixel = order_error(&render(&RenderOptions {
+        pixel_resort: ResortWindow::new(2).ok(),
+        ..Default::default()
+    }));
+    assert!(
+        per_tile > 5e-3,
+        "The crossing splats must be out of order per tile, error {per_tile}"
+    );
+    assert!(
+        per_pixel < 0.1 * per_tile,
+        "Re-sorting must fix the order, error {per_pixel} versus {per_tile} per tile"
+    );
+}
+
+#[test]
+fn grayscale_render_matches_red_channel() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 24);
+    let num_points = 64;
+    let means = Tensor::random([num_points, 3], Distribution::Uniform(-1.0, 1.0), &device);
+    let rotations = Tensor::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    let log_scales = Tensor::random([num_points, 3], Distribution::Uniform(-3.0, -1.5), &device);
+    let opacities = Tensor::random([num_points], Distribution::Uniform(-1.0, 2.0), &device);
+    let scene = |sh_coeffs: Tensor<Back, 3>| {
+        Splats::<Back>::from_tensor_data(
+            means.clone(),
+            rotations.clone(),
+            log_scales.clone(),
+            sh_coeffs,
+            opacities.clone(),
+        )
+    };
+
+    // A gray scene with view dependent colors, stored with a single channel, and with three
+    // equal channels.
+    let gray_coeffs = Tensor::random(
+        [num_points, 4, 1],
+        Distribution::Uniform(-0.5, 0.5),
+        &device,
+    );
+    let gray = scene(gray_coeffs.clone());
+    let rgb = scene(gray_coeffs.repeat_dim(2, 3));
+    assert!(
+        gray.is_grayscale() && !rgb.is_grayscale(),
+        "Only single channel coefficients are gray"
+    );
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::from_rotation_y(0.3),
+        0.8,
+        0.6,
+        glam::vec2(0.5, 0.5),
+    );
+    let max_diff =
+        |a: Tensor<Back, 3>, b: Tensor<Back, 3>| (a - b).abs().max().into_scalar().elem::<f32>();
+
+    for render_depth in [false, true] {
+        let options = RenderOptions {
+            render_depth,
+            ..Default::default()
+        };
+        let (rgb_img, _) = rgb.render_with_options(&cam, img_size, true, &options);
+
+        // Gray coefficients render as gray colors in color images too.
+        let (gray_rgb_img, _) = gray.render_with_options(&cam, img_size, true, &options);
+        let diff = max_diff(gray_rgb_img, rgb_img.clone());
+        assert!(
+            diff < 1e-5,
+            "Gray coefficients changed the render by {diff}"
+        );
+
+        let grayscale = RenderOptions {
+            grayscale: true,
+            ..options
+        };
+        let (gray_img, _) = gray.render_with_options(&cam, img_size, true, &grayscale);
+        let channels = if render_depth { 3 } else { 2 };
+        assert_eq!(
+            gray_img.dims(),
+            [img_size.y as usize, img_size.x as usize, channels]
+        );
+        // Gray, followed by the alpha (and depth) of the color render.
+        let expected = Tensor::cat(
+            vec![
+                rgb_img.clone().slice(s![.., .., 0..1]),
+                rgb_img.slice(s![.., .., 3..channels + 2]),
+            ],
+            2,
+        );
+        let diff = max_diff(gray_img, expected);
+        assert!(
+            diff < 1e-5,
+            "The gray render must match the red channel, depth {render_depth}, diff {diff}"
+        );
+    }
+}
+
+#[test]
+fn depth_render_matches_depth_channel() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(48, 40);
+    let num_points = 256;
+    let means =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-1.0, 1.0), &device);
+    let log_scales =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-3.0, -1.5), &device);
+    let quats = Tensor::<Back, 2>::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    let sh_coeffs = Tensor::<Back, 3>::random([num_points, 4, 3], Distribution::Default, &device);
+    let opacities =
+        Tensor::<Back, 1>::random([num_points], Distribution::Uniform(0.2, 1.0), &device);
+    let splats = [
+        means.into_primitive().tensor(),
+        log_scales.into_primitive().tensor(),
+        quats.into_primitive().tensor(),
+        sh_coeffs.into_primitive().tensor(),
+        opacities.into_primitive().tensor(),
+    ];
+
+    for (depth_as_disparity, origin, alpha_gamma) in [
+        (false, ImageOrigin::TopLeft, None),
+        (true, ImageOrigin::BottomLeft, None),
+        (false, ImageOrigin::TopLeft, AlphaGamma::new(2.0).ok()),
+    ] {
+        let cam = Camera::new(
+            glam::vec3(0.0, 0.0, -3.0),
+            glam::Quat::from_rotation_x(0.2),
+            0.8,
+            0.7,
+            glam::vec2(0.5, 0.5),
+        )
+        .with_origin(origin);
+        let options = RenderOptions {
+            render_depth: true,
+            depth_as_disparity,
+            alpha_gamma,
+            ..Default::default()
+        };
+        let [means, log_scales, quats, sh_coeffs, opacities] = splats.clone();
+        let (img, _) = render_forward(
+            &cam,
+            img_size,
+            RenderInput::new(
+                means.clone(),
+                log_scales.clone(),
+                quats.clone(),
+                sh_coeffs.clone(),
+                opacities.clone(),
+            ),
+            true,
+            &options,
+        );
+        let expected = Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(img))
+            .slice(s![.., .., 4..5])
+            .squeeze::<2>(2);
+
+        let depth = render_forward_depth(
+            &cam,
+            img_size,
+            RenderInput::new(means, log_scales, quats, sh_coeffs, opacities),
+            &options,
+        );
+        assert_eq!(depth.shape.dims, [40, 48]);
+        let depth = Tensor::<Back, 2>::from_primitive(TensorPrimitive::Float(depth));
+
+        let max_depth = expected.clone().abs().max().into_scalar().elem::<f32>();
+        assert!(max_depth > 0.0, "Splats must be visible");
+        let diff = (depth - expected).abs().max().into_scalar().elem::<f32>();
+        assert!(
+            diff < 1e-4 * max_depth,
+            "Depth differs by {diff}, disparity {depth_as_disparity}, gamma {alpha_gamma:?}"
+        );
+    }
+}
+
+#[test]
+#[should_panic(expected = "Depth renders only support alpha blending")]
+fn depth_render_rejects_depth_peeling() {
+    let device = WgpuDevice::DefaultDevice;
+    let (means, log_scales, quats, sh_coeffs, raw_opacity) = random_splats::<Back>(4, 1, &device);
+    let input = RenderInput::new(
+        means.into_primitive().tensor(),
+        log_scales.into_primitive().tensor(),
+        quats.into_primitive().tensor(),
+        sh_coeffs.into_primitive().tensor(),
+        raw_opacity.into_primitive().tensor(),
+    );
+    let options = RenderOptions {
+        depth_peel_layers: NonZeroU32::new(1),
+        ..Default::default()
+    };
+    let _ = render_forward_depth(&test_camera(), glam::uvec2(32, 24), input, &options);
+}
+
+#[test]
+fn invalid_options_are_rejected() {
+    assert_eq!(
+        TransmittanceThreshold::new(1.0),
+        Err(InvalidRenderOption::TransmittanceThreshold(1.0))
+    );
+    assert!(TransmittanceThreshold::new(-1e-3).is_err());
+    assert!(TransmittanceThreshold::new(f32::NAN).is_err());
+    assert!(TransmittanceThreshold::new(0.0).is_ok());
+
+    assert_eq!(
+        DepthKey::log(0.0, 10.0),
+        Err(InvalidRenderOption::LogDepthRange {
+            near: 0.0,
+            far: 10.0
+        })
+    );
+    assert!(DepthKey::log(10.0, 1.0).is_err());
+    assert!(DepthKey::log(0.1, f32::INFINITY).is_err());
+
+    assert_eq!(
+        ClipPlane::new(glam::Vec3::ZERO, 1.0),
+        Err(InvalidRenderOption::ClipPlaneNormal(glam::Vec3::ZERO))
+    );
+    assert!(ClipPlane::through_point(glam::Vec3::ZERO, glam::Vec3::NAN).is_err());
+
+    assert_eq!(
+        ResortWindow::new(0),
+        Err(InvalidRenderOption::ResortWindow(0))
+    );
+    assert!(ResortWindow::new(MAX_RESORT_WINDOW + 1).is_err());
+    assert!(ResortWindow::new(MAX_RESORT_WINDOW).is_ok());
+
+    assert_eq!(
+        AlphaGamma::new(0.0),
+        Err(InvalidRenderOption::AlphaGamma(0.0))
+    );
+    assert!(AlphaGamma::new(f32::NAN).is_err());
+    assert!(AlphaGamma::new(f32::INFINITY).is_err());
+
+    assert_eq!(
+        WorldScale::new(-1.0),
+        Err(InvalidRenderOption::WorldScale(-1.0))
+    );
+    assert!(WorldScale::new(f32::NAN).is_err());
+    assert!(WorldScale::new(0.0).is_err());
+
+    // Bounds past what can be sorted are clamped instead.
+    let options = RenderOptions {
+        intersects_upper_bound: NonZeroU32::new(u32::MAX),
+        ..Default::default()
+    };
+    assert_eq!(options.intersects_bound(), brush_sort::MAX_SORT_KEYS);
+}
@@ -0,0 +1,147 @@
+use crate::{
+    SplatForward,
+    camera::Camera,
+    render_options::{AlphaMode, ColorSpace, RenderOptions, TonemapOperator},
+    tonemap::tonemap_image,
+};
+use burn::tensor::{Distribution, ElementConversion, Tensor, TensorPrimitive};
+use burn_wgpu::{Wgpu, WgpuDevice};
+
+type Back = Wgpu;
+
+#[test]
+fn tonemap_matches_reference_values() {
+    let device = WgpuDevice::DefaultDevice;
+
+    // An opaque HDR pixel, and the same color at half coverage. Both have a depth channel.
+    let hdr = [0.5, 1.0, 4.0];
+    let pixels = [
+        hdr[0],
+        hdr[1],
+        hdr[2],
+        1.0,
+        7.0, //
+        hdr[0] * 0.5,
+        hdr[1] * 0.5,
+        hdr[2] * 0.5,
+        0.5,
+        3.0,
+    ];
+    let img = Tensor::<Back, 1>::from_floats(pixels.as_slice(), &device).reshape([1, 2, 5]);
+
+    // Reference values of each curve at the HDR color, in linear and sRGB encoding.
+    let cases = [
+        (
+            TonemapOperator::Reinhard,
+            ColorSpace::Linear,
+            [0.333_333, 0.5, 0.8],
+        ),
+        (
+            TonemapOperator::Reinhard,
+            ColorSpace::Srgb,
+            [0.612_501, 0.735_357, 0.906_332],
+        ),
+        (
+            TonemapOperator::Aces,
+            ColorSpace::Linear,
+            [0.616_307, 0.803_797, 0.973_417],
+        ),
+        (
+            TonemapOperator::Aces,
+            ColorSpace::Srgb,
+            [0.807_319, 0.908_23, 0.988_223],
+        ),
+    ];
+
+    for (operator, color_space, expected) in cases {
+        let out = tonemap_image(
+            img.clone().into_primitive().tensor(),
+            operator,
+            AlphaMode::Premultiplied,
+            color_space,
+        );
+        let out = Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(out))
+            .into_data()
+            .into_vec::<f32>()
+            .expect("Wrong tensor type");
+
+        for (pixel, alpha, depth) in [(&out[0..5], 1.0, 7.0), (&out[5..10], 0.5, 3.0)] {
+            for c in 0..3 {
+                assert!(
+                    (pixel[c] - expected[c] * alpha).abs() < 1e-4,
+                    "{operator:?} to {color_space:?} gives {} instead of {}",
+                    pixel[c],
+                    expected[c] * alpha
+                );
+            }
+            assert!(
+                (pixel[3] - alpha).abs() < 1e-7 && (pixel[4] - depth).abs() < 1e-7,
+                "Alpha and depth must be copied unchanged"
+            );
+        }
+    }
+}
+
+#[test]
+fn tonemapped_render_matches_tonemapped_image() {
+    let device = WgpuDevice::DefaultDevice;
+    let img_size = glam::uvec2(32, 32);
+    let num_points = 32;
+    let means =
+        Tensor::<Back, 2>::random([num_points, 3], Distribution::Uniform(-1.0, 1.0), &device);
+    let log_scales = Tensor::<Back, 2>::ones([num_points, 3], &device) * -2.0;
+    let quats = Tensor::<Back, 2>::random([num_points, 4], Distribution::Normal(0.0, 1.0), &device);
+    // Bright colors, well outside the displayable range.
+    let sh_coeffs =
+        Tensor::<Back, 3>::random([num_points, 1, 3], Distribution::Uniform(2.0, 8.0), &device);
+    let opacity = Tensor::<Back, 1>::ones([num_points], &device) * 0.7;
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, -3.0),
+        glam::Quat::IDENTITY,
+        0.8,
+        0.8,
+        glam::vec2(0.5, 0.5),
+    );
+
+    let render = |options: &RenderOptions| {
+        let (output, _) = <Back as SplatForward<Back>>::render_splats(
+            &cam,
+            img_size,
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            opacity.clone().into_primitive().tensor(),
+            true,
+            options,
+        );
+        output
+    };
+
+    let tonemapped =
+        Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(render(&RenderOptions {
+            tonemap: Some(TonemapOperator::Aces),
+            ..Default::default()
+        })));
+    let linear = render(&RenderOptions {
+        color_space: ColorSpace::Linear,
+        ..Default::default()
+    });
+    let expected = Tensor::<Back, 3>::from_primitive(TensorPrimitive::Float(tonemap_image(
+        linear,
+        TonemapOperator::Aces,
+        AlphaMode::Premultiplied,
+        ColorSpace::Srgb,
+    )));
+
+    assert!(
+        tonemapped.clone().max().into_scalar().elem::<f32>() <= 1.0 + 1e-6,
+        "Tonemapped colors must be displayable"
+    );
+    let diff = (tonemapped - expected)
+        .abs()
+        .max()
+        .into_scalar()
+        .elem::<f32>();
+    assert!(diff < 1e-6, "Tonemapped render differs by {diff}");
+}
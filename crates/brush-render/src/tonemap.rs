@@ -0,0 +1,63 @@
+use brush_kernel::{calc_cube_count, create_tensor, create_uniform_buffer};
+use burn::tensor::DType;
+use burn_cubecl::cubecl::server::Bindings;
+use burn_wgpu::{CubeTensor, WgpuRuntime};
+
+use crate::{
+    kernels::TonemapImage,
+    render_options::{AlphaMode, ColorSpace, TonemapOperator},
+    shaders,
+};
+
+/// Tonemap a float image, whose colors are in linear light.
+///
+/// `img` is a `[height, width, channels]` F32 image, with RGBA in the first four channels. Any
+/// further channels, like the depth, are copied unchanged. `alpha_mode` is how the colors of `img`
+/// relate to its alpha, which the result keeps. Premultiplied colors are divided by alpha before
+/// tonemapping, so partially covered pixels map like the splats covering them.
+///
+/// The result is encoded in `color_space`, so [`ColorSpace::Srgb`] gives an image ready for
+/// display.
+pub fn tonemap_image(
+    img: CubeTensor<WgpuRuntime>,
+    operator: TonemapOperator,
+    alpha_mode: AlphaMode,
+    color_space: ColorSpace,
+) -> CubeTensor<WgpuRuntime> {
+    let dims = img.shape.dims.clone();
+    assert!(
+        dims.len() == 3 && dims[2] >= 4 && img.dtype == DType::F32,
+        "Can only tonemap float images with at least 4 (RGBA) channels."
+    );
+    assert!(img.is_contiguous(), "Can only tonemap contiguous images.");
+
+    let (device, client) = (&img.device, &img.client);
+    let num_pixels = dims[0] * dims[1];
+    let uniforms = shaders::tonemap::Uniforms {
+        num_pixels: num_pixels as u32,
+        channels: dims[2] as u32,
+        pad_a: 0,
+        pad_b: 0,
+    };
+    let uniforms_buffer = create_uniform_buffer(uniforms, device, client);
+    let out_img = create_tensor([dims[0], dims[1], dims[2]], device, client, DType::F32);
+
+    // SAFETY: Kernel checked to have no OOB, bounded loops.
+    unsafe {
+        client.execute_unchecked(
+            TonemapImage::task(
+                operator == TonemapOperator::Aces,
+                color_space == ColorSpace::Srgb,
+                alpha_mode == AlphaMode::Premultiplied,
+            ),
+            calc_cube_count([num_pixels as u32], TonemapImage::WORKGROUP_SIZE),
+            Bindings::new().with_buffers(vec![
+                uniforms_buffer.handle.binding(),
+                img.handle.binding(),
+                out_img.handle.clone().binding(),
+            ]),
+        );
+    }
+
+    out_img
+}
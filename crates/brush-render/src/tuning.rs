@@ -0,0 +1,164 @@
+//! Per device tuning of kernel launch parameters.
+//!
+//! The kernels that process one splat per thread (projection and intersection mapping) work with
+//! any workgroup size. Which size is fastest depends on the GPU, so it can be picked per device,
+//! either by hand with [`set_splat_workgroup_size`] or by measuring with
+//! [`tune_splat_workgroup_size`]. Devices set up with [`crate::burn_init_device`] or
+//! [`crate::burn_init_setup`] are tuned when they're created. Other devices use
+//! [`DEFAULT_SPLAT_WORKGROUP_SIZE`].
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+use burn_wgpu::WgpuDevice;
+
+use crate::shaders;
+
+/// Workgroup sizes the per-splat kernels can be dispatched with.
+pub const SPLAT_WORKGROUP_SIZES: [u32; 3] = [64, 128, 256];
+
+/// Workgroup size of the per-splat kernels on devices that aren't tuned. This is the size the
+/// kernels are written with, and what every device used before tuning was possible.
+pub const DEFAULT_SPLAT_WORKGROUP_SIZE: u32 = shaders::helpers::MAIN_WG;
+
+static SPLAT_WORKGROUP_SIZE: LazyLock<Mutex<HashMap<WgpuDevice, u32>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[cfg(test)]
+thread_local! {
+    static THREAD_SPLAT_WORKGROUP_SIZE: std::cell::Cell<Option<u32>> =
+        const { std::cell::Cell::new(None) };
+}
+
+/// Run `func` with the per-splat kernels using workgroup size `size` on the current thread,
+/// without touching the per device sizes other tests might be rendering with.
+#[cfg(test)]
+pub(crate) fn with_thread_splat_workgroup_size<O>(size: u32, func: impl FnOnce() -> O) -> O {
+    assert!(SPLAT_WORKGROUP_SIZES.contains(&size));
+    let previous = THREAD_SPLAT_WORKGROUP_SIZE.replace(Some(size));
+    let out = func();
+    THREAD_SPLAT_WORKGROUP_SIZE.set(previous);
+    out
+}
+
+/// The workgroup size the per-splat kernels use on `device`.
+pub fn splat_workgroup_size(device: &WgpuDevice) -> u32 {
+    #[cfg(test)]
+    if let Some(size) = THREAD_SPLAT_WORKGROUP_SIZE.get() {
+        return size;
+    }
+    SPLAT_WORKGROUP_SIZE
+        .lock()
+        .expect("Workgroup size cache poisoned")
+        .get(device)
+        .copied()
+        .unwrap_or(DEFAULT_SPLAT_WORKGROUP_SIZE)
+}
+
+/// Use `size` as the workgroup size of the per-splat kernels on `device`, for all later renders.
+///
+/// `size` has to be one of [`SPLAT_WORKGROUP_SIZES`].
+pub fn set_splat_workgroup_size(device: &WgpuDevice, size: u32) {
+    assert!(
+        SPLAT_WORKGROUP_SIZES.contains(&size),
+        "Unsupported splat workgroup size {size}, expected one of {SPLAT_WORKGROUP_SIZES:?}"
+    );
+    SPLAT_WORKGROUP_SIZE
+        .lock()
+        .expect("Workgroup size cache poisoned")
+        .insert(device.clone(), size);
+}
+
+/// Find the fastest workgroup size of the per-splat kernels on `device`, and use it for all
+/// later renders.
+///
+/// This renders a random scene a few times with each of [`SPLAT_WORKGROUP_SIZES`], which takes
+/// a moment, so it only measures once per device. Later calls return the cached size.
+#[cfg(not(target_family = "wasm"))]
+pub fn tune_splat_workgroup_size(device: &WgpuDevice) -> u32 {
+    use std::time::{Duration, Instant};
+
+    use burn::prelude::Backend;
+    use burn::tensor::{Distribution, Tensor};
+
+    use crate::{
+        MainBackendBase,
+        camera::Camera,
+        render::{RenderInput, render_forward},
+    };
+
+    const NUM_SPLATS: usize = 1 << 18;
+    const ITERS: u32 = 5;
+
+    if let Some(size) = SPLAT_WORKGROUP_SIZE
+        .lock()
+        .expect("Workgroup size cache poisoned")
+        .get(device)
+    {
+        return *size;
+    }
+
+    let img_size = glam::uvec2(512, 512);
+    let camera = Camera::new(
+        glam::vec3(0.0, 0.0, -8.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let means = Tensor::<MainBackendBase, 2>::random(
+        [NUM_SPLATS, 3],
+        Distribution::Uniform(-2.0, 2.0),
+        device,
+    );
+    let log_scales = Tensor::<MainBackendBase, 2>::random(
+        [NUM_SPLATS, 3],
+        Distribution::Uniform(-5.0, -3.0),
+        device,
+    );
+    let quats = Tensor::<MainBackendBase, 1>::from_floats(glam::Quat::IDENTITY.to_array(), device)
+        .unsqueeze_dim::<2>(0)
+        .repeat_dim(0, NUM_SPLATS);
+    let sh_coeffs = Tensor::<MainBackendBase, 3>::random(
+        [NUM_SPLATS, 1, 3],
+        Distribution::Uniform(0.0, 1.0),
+        device,
+    );
+    let opacities =
+        Tensor::<MainBackendBase, 1>::random([NUM_SPLATS], Distribution::Uniform(0.0, 1.0), device);
+
+    let render = || {
+        let input = RenderInput::new(
+            means.clone().into_primitive().tensor(),
+            log_scales.clone().into_primitive().tensor(),
+            quats.clone().into_primitive().tensor(),
+            sh_coeffs.clone().into_primitive().tensor(),
+            opacities.clone().into_primitive().tensor(),
+        );
+        let _ = render_forward(&camera, img_size, input, false, &Default::default());
+    };
+
+    let mut best = (DEFAULT_SPLAT_WORKGROUP_SIZE, Duration::MAX);
+    for size in SPLAT_WORKGROUP_SIZES {
+        set_splat_workgroup_size(device, size);
+
+        // Warm up, so the kernels are compiled before timing.
+        render();
+        MainBackendBase::sync(device);
+
+        let start = Instant::now();
+        for _ in 0..ITERS {
+            render();
+        }
+        MainBackendBase::sync(device);
+        let elapsed = start.elapsed();
+
+        if elapsed < best.1 {
+            best = (size, elapsed);
+        }
+    }
+
+    tracing::info!("Tuned splat workgroup size to {} on {device:?}", best.0);
+    set_splat_workgroup_size(device, best.0);
+    best.0
+}
@@ -71,10 +71,10 @@ pub trait BurnToImage {
 impl<B: Backend> BurnToImage for Tensor<B, 3> {
     async fn into_rerun_image(self) -> rerun::Image {
         let [h, w, c] = self.dims();
-        let color_model = if c == 3 {
-            ColorModel::RGB
-        } else {
-            ColorModel::RGBA
+        let color_model = match c {
+            1 => ColorModel::L,
+            3 => ColorModel::RGB,
+            _ => ColorModel::RGBA,
         };
         rerun::Image::from_color_model_and_bytes(
             self.into_data_async().await.as_bytes().to_vec(),
@@ -3,6 +3,7 @@ use miette::IntoDiagnostic;
 fn main() -> miette::Result<()> {
     brush_wgsl::build_modules(
         &[
+            "src/shaders/sort_bitonic.wgsl",
             "src/shaders/sort_count.wgsl",
             "src/shaders/sort_reduce.wgsl",
             "src/shaders/sort_scan_add.wgsl",
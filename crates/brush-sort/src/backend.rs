@@ -0,0 +1,139 @@
+//! Choice of the algorithm that sorts by 32 or 64 bit keys.
+//!
+//! The radix sort handles any number of keys, but runs a few dispatches per pass over the keys,
+//! which dominates for small counts. Callers that know an upper bound on the number of keys can
+//! pick a backend with [`sort_backend_for`], or pick one themselves.
+
+use burn_wgpu::{CubeTensor, WgpuRuntime};
+
+use crate::{
+    BITONIC_MAX_KEYS, MAX_SORT_KEYS, bitonic_argsort_u64, radix_argsort, radix_argsort_u64,
+};
+
+/// An algorithm to sort values by 32 or 64 bit keys on the GPU.
+///
+/// All backends sort like [`radix_argsort`] and [`radix_argsort_u64`]: stable, returning the
+/// sorted (high) keys and values.
+pub trait SortBackend: Send + Sync {
+    /// Name of the algorithm, for traces and benchmarks.
+    fn name(&self) -> &'static str;
+
+    /// Most keys this backend can sort, which bounds the length of the key buffers.
+    fn max_keys(&self) -> u32;
+
+    /// Sort `input_values` by all 32 bits of `input_keys`, see [`radix_argsort`].
+    fn argsort(
+        &self,
+        input_keys: CubeTensor<WgpuRuntime>,
+        input_values: CubeTensor<WgpuRuntime>,
+        n_sort: &CubeTensor<WgpuRuntime>,
+    ) -> (CubeTensor<WgpuRuntime>, CubeTensor<WgpuRuntime>);
+
+    /// Sort `input_values` by the keys, on the lowest `high_bits` of the high keys and all bits
+    /// of the low keys, see [`radix_argsort_u64`].
+    fn argsort_u64(
+        &self,
+        input_keys_high: CubeTensor<WgpuRuntime>,
+        input_keys_low: CubeTensor<WgpuRuntime>,
+        input_values: CubeTensor<WgpuRuntime>,
+        n_sort: &CubeTensor<WgpuRuntime>,
+        high_bits: u32,
+    ) -> (CubeTensor<WgpuRuntime>, CubeTensor<WgpuRuntime>);
+}
+
+/// The multi pass radix sort, which works for any number of keys.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RadixSort;
+
+impl SortBackend for RadixSort {
+    fn name(&self) -> &'static str {
+        "radix"
+    }
+
+    fn max_keys(&self) -> u32 {
+        MAX_SORT_KEYS
+    }
+
+    fn argsort(
+        &self,
+        input_keys: CubeTensor<WgpuRuntime>,
+        input_values: CubeTensor<WgpuRuntime>,
+        n_sort: &CubeTensor<WgpuRuntime>,
+    ) -> (CubeTensor<WgpuRuntime>, CubeTensor<WgpuRuntime>) {
+        radix_argsort(input_keys, input_values, n_sort, 32)
+    }
+
+    fn argsort_u64(
+        &self,
+        input_keys_high: CubeTensor<WgpuRuntime>,
+        input_keys_low: CubeTensor<WgpuRuntime>,
+        input_values: CubeTensor<WgpuRuntime>,
+        n_sort: &CubeTensor<WgpuRuntime>,
+        high_bits: u32,
+    ) -> (CubeTensor<WgpuRuntime>, CubeTensor<WgpuRuntime>) {
+        radix_argsort_u64(
+            input_keys_high,
+            input_keys_low,
+            input_values,
+            n_sort,
+            high_bits,
+        )
+    }
+}
+
+/// The single workgroup bitonic sort, for at most [`BITONIC_MAX_KEYS`] keys.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BitonicSort;
+
+impl SortBackend for BitonicSort {
+    fn name(&self) -> &'static str {
+        "bitonic"
+    }
+
+    fn max_keys(&self) -> u32 {
+        BITONIC_MAX_KEYS
+    }
+
+    fn argsort(
+        &self,
+        input_keys: CubeTensor<WgpuRuntime>,
+        input_values: CubeTensor<WgpuRuntime>,
+        n_sort: &CubeTensor<WgpuRuntime>,
+    ) -> (CubeTensor<WgpuRuntime>, CubeTensor<WgpuRuntime>) {
+        // Without any high bits the high keys aren't compared, they're just carried along. Pass
+        // the keys themselves, so they're returned sorted.
+        bitonic_argsort_u64(input_keys.clone(), input_keys, input_values, n_sort, 0)
+    }
+
+    fn argsort_u64(
+        &self,
+        input_keys_high: CubeTensor<WgpuRuntime>,
+        input_keys_low: CubeTensor<WgpuRuntime>,
+        input_values: CubeTensor<WgpuRuntime>,
+        n_sort: &CubeTensor<WgpuRuntime>,
+        high_bits: u32,
+    ) -> (CubeTensor<WgpuRuntime>, CubeTensor<WgpuRuntime>) {
+        bitonic_argsort_u64(
+            input_keys_high,
+            input_keys_low,
+            input_values,
+            n_sort,
+            high_bits,
+        )
+    }
+}
+
+/// The fastest backend to sort at most `max_keys` keys.
+///
+/// The bitonic sort does `O(n log² n)` work but in one dispatch, while the radix sort runs four
+/// dispatches for each of its passes, 8 for 32 bit keys and up to 16 for 64 bit keys. For the
+/// few keys that fit a single workgroup the dispatch overhead dominates, so the bitonic sort is
+/// picked whenever the keys fit. The `sort` group of the render benchmarks in `brush-bench-test`
+/// compares both.
+pub fn sort_backend_for(max_keys: u32) -> &'static dyn SortBackend {
+    if max_keys <= BITONIC_MAX_KEYS {
+        &BitonicSort
+    } else {
+        &RadixSort
+    }
+}
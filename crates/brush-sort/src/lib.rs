@@ -10,6 +10,7 @@ use burn_cubecl::CubeBackend;
 use burn_cubecl::cubecl::server::Bindings;
 use burn_wgpu::CubeTensor;
 use burn_wgpu::WgpuRuntime;
+use shaders::sort_bitonic;
 use shaders::sort_count;
 use shaders::sort_reduce;
 use shaders::sort_scan;
@@ -18,33 +19,214 @@ use shaders::sort_scatter;
 
 use brush_kernel::kernel_source_gen;
 
+mod backend;
 mod shaders;
 
+pub use backend::{BitonicSort, RadixSort, SortBackend, sort_backend_for};
+
 const WG: u32 = shaders::sorting::WG;
 const ELEMENTS_PER_THREAD: u32 = shaders::sorting::ELEMENTS_PER_THREAD;
 const BLOCK_SIZE: u32 = WG * ELEMENTS_PER_THREAD;
 const BIN_COUNT: u32 = shaders::sorting::BIN_COUNT;
 
+/// Most keys a single sort can handle, as each workgroup sorts a block of keys and a dispatch
+/// has at most 65535 workgroups.
+pub const MAX_SORT_KEYS: u32 = BLOCK_SIZE * u16::MAX as u32;
+
+/// Most keys [`bitonic_argsort_u64`] can handle, as all keys are sorted in a single workgroup.
+pub const BITONIC_MAX_KEYS: u32 = shaders::sort_bitonic::MAX_KEYS;
+
+kernel_source_gen!(SortBitonic { wide_values }, sort_bitonic);
 kernel_source_gen!(SortCount {}, sort_count);
 kernel_source_gen!(SortReduce {}, sort_reduce);
 kernel_source_gen!(SortScanAdd {}, sort_scan_add);
 kernel_source_gen!(SortScan {}, sort_scan);
-kernel_source_gen!(SortScatter {}, sort_scatter);
-
+kernel_source_gen!(
+    SortScatter {
+        extra_values,
+        dynamic_bits,
+        wide_values
+    },
+    sort_scatter
+);
+
+type BurnBackend = CubeBackend<WgpuRuntime, f32, i32, u32>;
+
+/// Sort values by their u32 keys, stored as i32's. The sort is stable.
+///
+/// Keys are sorted on their lowest `sorting_bits`. The values are an arbitrary payload, of any 32
+/// bit (eg. I32 or U32) or 64 bit (eg. U64) type, and are returned with the same type.
 pub fn radix_argsort(
     input_keys: CubeTensor<WgpuRuntime>,
     input_values: CubeTensor<WgpuRuntime>,
     n_sort: &CubeTensor<WgpuRuntime>,
     sorting_bits: u32,
+) -> (CubeTensor<WgpuRuntime>, CubeTensor<WgpuRuntime>) {
+    let _span = tracing::trace_span!("Radix sort").entered();
+    let (keys, values, _) = sort_passes(input_keys, input_values, None, n_sort, None, sorting_bits);
+    (keys, values)
+}
+
+/// Like [`radix_argsort`], but skips the passes above the highest set bit of `max_key`.
+///
+/// `max_key` is a single element buffer holding an upper bound of the keys, computed on the GPU
+/// like `n_sort`. Passes where no key has any bits set are turned into a cheap copy, so keys that
+/// cluster in a narrow range need fewer full passes than `max_bits`, without reading anything
+/// back. Keys must not have bits set above `max_bits`.
+pub fn radix_argsort_dynamic_bits(
+    input_keys: CubeTensor<WgpuRuntime>,
+    input_values: CubeTensor<WgpuRuntime>,
+    n_sort: &CubeTensor<WgpuRuntime>,
+    max_key: &CubeTensor<WgpuRuntime>,
+    max_bits: u32,
+) -> (CubeTensor<WgpuRuntime>, CubeTensor<WgpuRuntime>) {
+    assert_eq!(max_key.shape.dims[0], 1, "Max key must have one element");
+    let _span = tracing::trace_span!("Radix sort dynamic").entered();
+    let (keys, values, _) = sort_passes(
+        input_keys,
+        input_values,
+        None,
+        n_sort,
+        Some(max_key),
+        max_bits,
+    );
+    (keys, values)
+}
+
+/// Number of keys to sort in the pass at `shift`, or zero if no key has bits set at or above it.
+fn pass_sort_count(
+    n_sort: &CubeTensor<WgpuRuntime>,
+    max_key: &CubeTensor<WgpuRuntime>,
+    shift: u32,
+) -> CubeTensor<WgpuRuntime> {
+    let n_sort: Tensor<BurnBackend, 1, Int> = Tensor::from_primitive(n_sort.clone());
+    let max_key: Tensor<BurnBackend, 1, Int> = Tensor::from_primitive(max_key.clone());
+    // Keys are u32's stored as i32, so keys with the top bit set are negative.
+    let active = max_key
+        .clone()
+        .lower_elem(0)
+        .bool_or(max_key.greater_equal_elem(1i64 << shift));
+    (n_sort * active.int()).into_primitive()
+}
+
+/// Sort values by a 64 bit key, given as separate high and low 32 bit halves.
+///
+/// Only the lowest `high_bits` of the high half are sorted on, the low half is always sorted on
+/// all 32 bits. Returns the sorted high keys and the sorted values. Like [`radix_argsort`], the
+/// sort is stable.
+pub fn radix_argsort_u64(
+    input_keys_high: CubeTensor<WgpuRuntime>,
+    input_keys_low: CubeTensor<WgpuRuntime>,
+    input_values: CubeTensor<WgpuRuntime>,
+    n_sort: &CubeTensor<WgpuRuntime>,
+    high_bits: u32,
+) -> (CubeTensor<WgpuRuntime>, CubeTensor<WgpuRuntime>) {
+    assert_eq!(
+        input_keys_high.shape.dims[0], input_keys_low.shape.dims[0],
+        "High and low keys must have the same number of elements"
+    );
+
+    let _span = tracing::trace_span!("Radix sort 64").entered();
+
+    // This is an LSD radix sort, so first sort on the low half, carrying the high half along.
+    // Then a stable sort on the high half gives the full ordering.
+    let (_, values, keys_high) = sort_passes(
+        input_keys_low,
+        input_values,
+        Some(input_keys_high),
+        n_sort,
+        None,
+        32,
+    );
+    let keys_high = keys_high.expect("Extra values must be sorted along");
+    let (keys_high, values, _) = sort_passes(keys_high, values, None, n_sort, None, high_bits);
+    (keys_high, values)
+}
+
+/// Sort values by a 64 bit key like [`radix_argsort_u64`], in a single workgroup.
+///
+/// This sorts with a bitonic network in workgroup memory, which takes a single dispatch instead
+/// of several per radix pass, so it's faster for small numbers of keys. The key buffers can hold
+/// at most [`BITONIC_MAX_KEYS`] keys.
+pub fn bitonic_argsort_u64(
+    input_keys_high: CubeTensor<WgpuRuntime>,
+    input_keys_low: CubeTensor<WgpuRuntime>,
+    input_values: CubeTensor<WgpuRuntime>,
+    n_sort: &CubeTensor<WgpuRuntime>,
+    high_bits: u32,
 ) -> (CubeTensor<WgpuRuntime>, CubeTensor<WgpuRuntime>) {
+    assert_eq!(
+        input_keys_high.shape.dims[0], input_keys_low.shape.dims[0],
+        "High and low keys must have the same number of elements"
+    );
+    assert_eq!(
+        input_keys_low.shape.dims[0], input_values.shape.dims[0],
+        "Input keys and values must have the same number of elements"
+    );
+    assert_eq!(n_sort.shape.dims[0], 1, "Sort count must have one element");
+    assert!(high_bits <= 32, "Can only sort up to 32 bits");
+    let max_n = input_keys_low.shape.dims[0] as u32;
+    assert!(
+        max_n <= BITONIC_MAX_KEYS,
+        "Bitonic sort can sort at most {BITONIC_MAX_KEYS} keys, got {max_n}"
+    );
+    let wide_values = match input_values.dtype().size() {
+        4 => false,
+        8 => true,
+        _ => panic!("Values must be a 32 or 64 bit type"),
+    };
+
+    let _span = tracing::trace_span!("Bitonic sort 64").entered();
+
+    let client = &input_keys_low.client.clone();
+    let device = &input_keys_low.device.clone();
+    let uniforms_buffer: CubeTensor<WgpuRuntime> =
+        create_uniform_buffer(sort_bitonic::Uniforms { high_bits }, device, client);
+    let output_keys =
+        create_tensor::<1, _>([max_n as usize], device, client, input_keys_high.dtype());
+    let output_values =
+        create_tensor::<1, _>([max_n as usize], device, client, input_values.dtype());
+
+    client.execute(
+        SortBitonic::task(wide_values),
+        CubeCount::Static(1, 1, 1),
+        Bindings::new().with_buffers(vec![
+            uniforms_buffer.handle.binding(),
+            n_sort.clone().handle.binding(),
+            input_keys_high.handle.binding(),
+            input_keys_low.handle.binding(),
+            input_values.handle.binding(),
+            output_keys.handle.clone().binding(),
+            output_values.handle.clone().binding(),
+        ]),
+    );
+
+    (output_keys, output_values)
+}
+
+fn sort_passes(
+    input_keys: CubeTensor<WgpuRuntime>,
+    input_values: CubeTensor<WgpuRuntime>,
+    input_extra: Option<CubeTensor<WgpuRuntime>>,
+    n_sort: &CubeTensor<WgpuRuntime>,
+    max_key: Option<&CubeTensor<WgpuRuntime>>,
+    sorting_bits: u32,
+) -> (
+    CubeTensor<WgpuRuntime>,
+    CubeTensor<WgpuRuntime>,
+    Option<CubeTensor<WgpuRuntime>>,
+) {
     assert_eq!(
         input_keys.shape.dims[0], input_values.shape.dims[0],
         "Input keys and values must have the same number of elements"
     );
     assert_eq!(n_sort.shape.dims[0], 1, "Sort count must have one element");
     assert!(sorting_bits <= 32, "Can only sort up to 32 bits");
-
-    let _span = tracing::trace_span!("Radix sort").entered();
+    let wide_values = match input_values.dtype().size() {
+        4 => false,
+        8 => true,
+        _ => panic!("Values must be a 32 or 64 bit type"),
+    };
 
     let client = &input_keys.client.clone();
     let max_n = input_keys.shape.dims[0] as u32;
@@ -55,13 +237,17 @@ pub fn radix_argsort(
     let max_needed_wgs = max_n.div_ceil(BLOCK_SIZE);
 
     let num_wgs = create_dispatch_buffer(n_sort.clone(), [BLOCK_SIZE, 1, 1]);
-    let num_reduce_wgs: Tensor<CubeBackend<WgpuRuntime, f32, i32, u32>, 1, Int> =
-        Tensor::from_primitive(create_dispatch_buffer(num_wgs.clone(), [BLOCK_SIZE, 1, 1]))
-            * Tensor::from_ints([BIN_COUNT, 1, 1], device);
-    let num_reduce_wgs: CubeTensor<WgpuRuntime> = num_reduce_wgs.into_primitive();
+    let reduce_dispatch = |num_wgs: &CubeTensor<WgpuRuntime>| -> CubeTensor<WgpuRuntime> {
+        let num_reduce_wgs: Tensor<BurnBackend, 1, Int> =
+            Tensor::from_primitive(create_dispatch_buffer(num_wgs.clone(), [BLOCK_SIZE, 1, 1]))
+                * Tensor::from_ints([BIN_COUNT, 1, 1], device);
+        num_reduce_wgs.into_primitive()
+    };
+    let num_reduce_wgs = reduce_dispatch(&num_wgs);
 
     let mut cur_keys = input_keys;
     let mut cur_vals = input_values;
+    let mut cur_extra = input_extra;
 
     for pass in 0..sorting_bits.div_ceil(4) {
         let uniforms_buffer: CubeTensor<WgpuRuntime> = create_uniform_buffer(
@@ -70,6 +256,17 @@ pub fn radix_argsort(
             client,
         );
 
+        // With a dynamic bit count, the counting passes only run when this digit has bits set.
+        // The scatter always runs, and copies the keys when the pass is skipped.
+        let pass_n_sort = max_key.map(|max_key| pass_sort_count(n_sort, max_key, pass * 4));
+        let (pass_n, pass_wgs, pass_reduce_wgs) = if let Some(pass_n) = &pass_n_sort {
+            let pass_wgs = create_dispatch_buffer(pass_n.clone(), [BLOCK_SIZE, 1, 1]);
+            let pass_reduce_wgs = reduce_dispatch(&pass_wgs);
+            (pass_n, pass_wgs, pass_reduce_wgs)
+        } else {
+            (n_sort, num_wgs.clone(), num_reduce_wgs.clone())
+        };
+
         let count_buf = create_tensor::<1, WgpuRuntime>(
             [(max_needed_wgs as usize) * 16],
             device,
@@ -80,10 +277,10 @@ pub fn radix_argsort(
         // use safe distpatch as dynamic work count isn't verified.
         client.execute(
             SortCount::task(),
-            CubeCount::Dynamic(num_wgs.clone().handle.binding()),
+            CubeCount::Dynamic(pass_wgs.handle.binding()),
             Bindings::new().with_buffers(vec![
                 uniforms_buffer.clone().handle.binding(),
-                n_sort.clone().handle.binding(),
+                pass_n.clone().handle.binding(),
                 cur_keys.handle.clone().binding(),
                 count_buf.clone().handle.binding(),
             ]),
@@ -95,9 +292,9 @@ pub fn radix_argsort(
 
             client.execute(
                 SortReduce::task(),
-                CubeCount::Dynamic(num_reduce_wgs.clone().handle.binding()),
+                CubeCount::Dynamic(pass_reduce_wgs.clone().handle.binding()),
                 Bindings::new().with_buffers(vec![
-                    n_sort.clone().handle.binding(),
+                    pass_n.clone().handle.binding(),
                     count_buf.clone().handle.binding(),
                     reduced_buf.clone().handle.binding(),
                 ]),
@@ -109,7 +306,7 @@ pub fn radix_argsort(
                     SortScan::task(),
                     CubeCount::Static(1, 1, 1),
                     Bindings::new().with_buffers(vec![
-                        n_sort.clone().handle.binding(),
+                        pass_n.clone().handle.binding(),
                         reduced_buf.clone().handle.binding(),
                     ]),
                 );
@@ -117,9 +314,9 @@ pub fn radix_argsort(
 
             client.execute(
                 SortScanAdd::task(),
-                CubeCount::Dynamic(num_reduce_wgs.handle.clone().binding()),
+                CubeCount::Dynamic(pass_reduce_wgs.handle.binding()),
                 Bindings::new().with_buffers(vec![
-                    n_sort.clone().handle.binding(),
+                    pass_n.clone().handle.binding(),
                     reduced_buf.clone().handle.binding(),
                     count_buf.clone().handle.binding(),
                 ]),
@@ -130,30 +327,51 @@ pub fn radix_argsort(
         let output_values =
             create_tensor::<1, _>([max_n as usize], device, client, cur_vals.dtype());
 
+        let mut bindings = Bindings::new().with_buffers(vec![
+            uniforms_buffer.handle.clone().binding(),
+            n_sort.clone().handle.binding(),
+            cur_keys.handle.clone().binding(),
+            cur_vals.handle.clone().binding(),
+            count_buf.handle.clone().binding(),
+            output_keys.handle.clone().binding(),
+            output_values.handle.clone().binding(),
+        ]);
+
+        let output_extra = cur_extra
+            .as_ref()
+            .map(|extra| create_tensor::<1, _>([max_n as usize], device, client, extra.dtype()));
+
+        if let (Some(extra), Some(output_extra)) = (&cur_extra, &output_extra) {
+            bindings = bindings.with_buffers(vec![
+                extra.handle.clone().binding(),
+                output_extra.handle.clone().binding(),
+            ]);
+        }
+
+        if let Some(pass_n) = &pass_n_sort {
+            bindings = bindings.with_buffers(vec![pass_n.handle.clone().binding()]);
+        }
+
         client.execute(
-            SortScatter::task(),
+            SortScatter::task(output_extra.is_some(), pass_n_sort.is_some(), wide_values),
             CubeCount::Dynamic(num_wgs.clone().handle.binding()),
-            Bindings::new().with_buffers(vec![
-                uniforms_buffer.handle.clone().binding(),
-                n_sort.clone().handle.binding(),
-                cur_keys.handle.clone().binding(),
-                cur_vals.handle.clone().binding(),
-                count_buf.handle.clone().binding(),
-                output_keys.handle.clone().binding(),
-                output_values.handle.clone().binding(),
-            ]),
+            bindings,
         );
 
         cur_keys = output_keys;
         cur_vals = output_values;
+        cur_extra = output_extra;
     }
-    (cur_keys, cur_vals)
+    (cur_keys, cur_vals, cur_extra)
 }
 
 #[cfg(all(test, not(target_family = "wasm")))]
 mod tests {
-    use crate::radix_argsort;
-    use burn::tensor::{Int, Tensor};
+    use crate::{
+        BITONIC_MAX_KEYS, BurnBackend, MAX_SORT_KEYS, bitonic_argsort_u64, radix_argsort,
+        radix_argsort_dynamic_bits, radix_argsort_u64, sort_backend_for,
+    };
+    use burn::tensor::{DType, Element, Int, Tensor, TensorData, ops::IntTensorOps};
     use burn_wgpu::{CubeBackend, WgpuRuntime};
     use rand::Rng;
 
@@ -267,4 +485,259 @@ mod tests {
             assert_eq!(*val, ref_val as i32);
         }
     }
+
+    #[test]
+    fn test_sorting_u64() {
+        let mut rng = rand::rng();
+        let n = 5000;
+        // Few distinct high keys, so the order within them depends on the low keys.
+        let high_inp: Vec<u32> = (0..n).map(|_| rng.random_range(0..37)).collect();
+        let low_inp: Vec<u32> = (0..n).map(|_| rng.random_range(0..64) << 26).collect();
+        let values_inp: Vec<i32> = (0..n as i32).collect();
+
+        let device = Default::default();
+        let to_tensor = |data: &[u32]| {
+            let data: Vec<i32> = data.iter().map(|&x| x as i32).collect();
+            Tensor::<Backend, 1, Int>::from_ints(data.as_slice(), &device).into_primitive()
+        };
+        let values =
+            Tensor::<Backend, 1, Int>::from_ints(values_inp.as_slice(), &device).into_primitive();
+        let num_points = Tensor::<Backend, 1, Int>::from_ints([n as i32], &device).into_primitive();
+
+        let (ret_keys, ret_values) = radix_argsort_u64(
+            to_tensor(&high_inp),
+            to_tensor(&low_inp),
+            values,
+            &num_points,
+            6,
+        );
+
+        let ret_keys = Tensor::<Backend, 1, Int>::from_primitive(ret_keys).to_data();
+        let ret_values = Tensor::<Backend, 1, Int>::from_primitive(ret_values).to_data();
+
+        let keys_inp: Vec<u64> = high_inp
+            .iter()
+            .zip(&low_inp)
+            .map(|(&high, &low)| ((high as u64) << 32) | low as u64)
+            .collect();
+        let inds = argsort(&keys_inp);
+
+        for ((key, val), ref_ind) in ret_keys
+            .as_slice::<i32>()
+            .expect("Wrong type")
+            .iter()
+            .zip(ret_values.as_slice::<i32>().expect("Wrong type"))
+            .zip(inds)
+        {
+            assert_eq!(*key, high_inp[ref_ind] as i32);
+            assert_eq!(*val, values_inp[ref_ind]);
+        }
+    }
+
+    #[test]
+    fn test_sorting_dynamic_bits() {
+        let mut rng = rand::rng();
+        let device = Default::default();
+
+        // Keys in a narrow range skip most passes, keys with the top bit set need all of them.
+        for max_key in [0u32, 1, 1000, 1 << 20, u32::MAX] {
+            let keys_inp: Vec<u32> = (0..3000).map(|_| rng.random_range(0..=max_key)).collect();
+            let values_inp: Vec<i32> = (0..keys_inp.len() as i32).collect();
+
+            let keys: Vec<i32> = keys_inp.iter().map(|&x| x as i32).collect();
+            let keys = Tensor::<Backend, 1, Int>::from_ints(keys.as_slice(), &device);
+            let max_key = Tensor::<Backend, 1, Int>::from_ints([max_key as i32], &device);
+            let values = Tensor::<Backend, 1, Int>::from_ints(values_inp.as_slice(), &device);
+            let num_points = Tensor::<Backend, 1, Int>::from_ints([keys_inp.len() as i32], &device);
+
+            let (ret_keys, ret_values) = radix_argsort_dynamic_bits(
+                keys.into_primitive(),
+                values.into_primitive(),
+                &num_points.into_primitive(),
+                &max_key.into_primitive(),
+                32,
+            );
+
+            let ret_keys = Tensor::<Backend, 1, Int>::from_primitive(ret_keys).to_data();
+            let ret_values = Tensor::<Backend, 1, Int>::from_primitive(ret_values).to_data();
+
+            let inds = argsort(&keys_inp);
+            for ((key, val), ref_ind) in ret_keys
+                .as_slice::<i32>()
+                .expect("Wrong type")
+                .iter()
+                .zip(ret_values.as_slice::<i32>().expect("Wrong type"))
+                .zip(inds)
+            {
+                assert_eq!(*key, keys_inp[ref_ind] as i32);
+                assert_eq!(*val, values_inp[ref_ind]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bitonic_matches_radix() {
+        let mut rng = rand::rng();
+        let device = Default::default();
+
+        // Counts below the buffer size, and few distinct keys to check the sort is stable.
+        for (len, n) in [(1, 1), (100, 37), (1000, 1000), (1024, 700)] {
+            let high_inp: Vec<u32> = (0..len).map(|_| rng.random_range(0..9)).collect();
+            let low_inp: Vec<u32> = (0..len).map(|_| rng.random_range(0..4) << 30).collect();
+            let values_inp: Vec<i32> = (0..len as i32).collect();
+
+            let to_tensor = |data: &[u32]| {
+                let data: Vec<i32> = data.iter().map(|&x| x as i32).collect();
+                Tensor::<Backend, 1, Int>::from_ints(data.as_slice(), &device).into_primitive()
+            };
+            let num_points =
+                Tensor::<Backend, 1, Int>::from_ints([n as i32], &device).into_primitive();
+
+            let (ret_keys, ret_values) = bitonic_argsort_u64(
+                to_tensor(&high_inp),
+                to_tensor(&low_inp),
+                Tensor::<Backend, 1, Int>::from_ints(values_inp.as_slice(), &device)
+                    .into_primitive(),
+                &num_points,
+                4,
+            );
+            let ret_keys = Tensor::<Backend, 1, Int>::from_primitive(ret_keys).to_data();
+            let ret_values = Tensor::<Backend, 1, Int>::from_primitive(ret_values).to_data();
+
+            let keys_u64: Vec<u64> = high_inp[..n]
+                .iter()
+                .zip(&low_inp[..n])
+                .map(|(&high, &low)| ((high as u64) << 32) | low as u64)
+                .collect();
+            let inds = argsort(&keys_u64);
+
+            let ret_keys = &ret_keys.as_slice::<i32>().expect("Wrong type")[..n];
+            let ret_values = &ret_values.as_slice::<i32>().expect("Wrong type")[..n];
+            for ((key, val), ref_ind) in ret_keys.iter().zip(ret_values).zip(inds) {
+                assert_eq!(*key, high_inp[ref_ind] as i32);
+                assert_eq!(*val, values_inp[ref_ind]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_backend_selection() {
+        assert_eq!(sort_backend_for(BITONIC_MAX_KEYS).name(), "bitonic");
+        assert_eq!(sort_backend_for(BITONIC_MAX_KEYS + 1).name(), "radix");
+        assert_eq!(sort_backend_for(MAX_SORT_KEYS).max_keys(), MAX_SORT_KEYS);
+    }
+
+    #[test]
+    fn test_backends_sort_u32_keys() {
+        let mut rng = rand::rng();
+        let device = Default::default();
+        let n = BITONIC_MAX_KEYS as usize;
+        // Random keys on all 32 bits, so about half of them don't fit an i32.
+        let keys_inp: Vec<u32> = (0..n).map(|_| rng.random()).collect();
+        let values_inp: Vec<i32> = (0..n as i32).collect();
+        let expected: Vec<i32> = argsort(&keys_inp).into_iter().map(|i| i as i32).collect();
+        let num_points = Tensor::<Backend, 1, Int>::from_ints([n as i32], &device).into_primitive();
+
+        for backend in [
+            sort_backend_for(BITONIC_MAX_KEYS),
+            sort_backend_for(MAX_SORT_KEYS),
+        ] {
+            let keys: Vec<i32> = keys_inp.iter().map(|&x| x as i32).collect();
+            let (_, ret_values) = backend.argsort(
+                Tensor::<Backend, 1, Int>::from_ints(keys.as_slice(), &device).into_primitive(),
+                Tensor::<Backend, 1, Int>::from_ints(values_inp.as_slice(), &device)
+                    .into_primitive(),
+                &num_points,
+            );
+            let ret_values = Tensor::<Backend, 1, Int>::from_primitive(ret_values)
+                .into_data()
+                .to_vec::<i32>()
+                .expect("Wrong type");
+            assert_eq!(ret_values, expected, "{} sort is wrong", backend.name());
+        }
+    }
+
+    /// Sort random keys with a payload of type `T`, and check the payload is permuted with them.
+    fn check_payload<T: Element + PartialEq>(values_inp: &[T], dtype: DType) {
+        let mut rng = rand::rng();
+        let device = Default::default();
+        let n = values_inp.len();
+        let keys_inp: Vec<u32> = (0..n).map(|_| rng.random_range(0..1 << 20)).collect();
+        let high_inp: Vec<u32> = (0..n).map(|_| rng.random_range(0..5)).collect();
+        let to_keys = |data: &[u32]| {
+            let data: Vec<i32> = data.iter().map(|&x| x as i32).collect();
+            Tensor::<Backend, 1, Int>::from_ints(data.as_slice(), &device).into_primitive()
+        };
+        let values =
+            || BurnBackend::int_from_data(TensorData::new(values_inp.to_vec(), [n]), &device);
+        let num_points = Tensor::<Backend, 1, Int>::from_ints([n as i32], &device).into_primitive();
+
+        let read = |values| {
+            let data = Tensor::<Backend, 1, Int>::from_primitive(values).into_data();
+            assert_eq!(data.dtype, dtype, "Sorted values must keep their type");
+            data.to_vec::<T>().expect("Wrong type")
+        };
+
+        let (_, ret_values) = radix_argsort(to_keys(&keys_inp), values(), &num_points, 20);
+        let expected: Vec<T> = argsort(&keys_inp)
+            .into_iter()
+            .map(|i| values_inp[i])
+            .collect();
+        assert_eq!(read(ret_values), expected);
+
+        let (_, ret_values) = radix_argsort_u64(
+            to_keys(&high_inp),
+            to_keys(&keys_inp),
+            values(),
+            &num_points,
+            3,
+        );
+        let keys_u64: Vec<u64> = high_inp
+            .iter()
+            .zip(&keys_inp)
+            .map(|(&high, &low)| ((high as u64) << 32) | low as u64)
+            .collect();
+        let expected: Vec<T> = argsort(&keys_u64)
+            .into_iter()
+            .map(|i| values_inp[i])
+            .collect();
+        assert_eq!(read(ret_values), expected);
+
+        // The bitonic sort gathers the payload by index, so check it at its largest size.
+        let small = BITONIC_MAX_KEYS as usize;
+        let small_n =
+            Tensor::<Backend, 1, Int>::from_ints([small as i32], &device).into_primitive();
+        let small_values = BurnBackend::int_from_data(
+            TensorData::new(values_inp[..small].to_vec(), [small]),
+            &device,
+        );
+        let (_, ret_values) = bitonic_argsort_u64(
+            to_keys(&high_inp[..small]),
+            to_keys(&keys_inp[..small]),
+            small_values,
+            &small_n,
+            3,
+        );
+        let expected: Vec<T> = argsort(&keys_u64[..small])
+            .into_iter()
+            .map(|i| values_inp[i])
+            .collect();
+        assert_eq!(read(ret_values), expected);
+    }
+
+    #[test]
+    fn test_sorting_u32_payload() {
+        // Values with the top bit set, which don't fit an i32.
+        let values: Vec<u32> = (0..4000).map(|i| u32::MAX - i * 7).collect();
+        check_payload(&values, DType::U32);
+    }
+
+    #[test]
+    fn test_sorting_u64_payload() {
+        // Both words differ per value, so a mixup between them shows up.
+        let values: Vec<u64> = (0..4000u64)
+            .map(|i| (i << 40) | (i * 0x9e37_79b9) | (1 << 63))
+            .collect();
+        check_payload(&values, DType::U64);
+    }
 }
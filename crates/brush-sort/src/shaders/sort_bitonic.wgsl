@@ -0,0 +1,93 @@
+// Sorts a small number of 64 bit keys in a single workgroup, with a bitonic sorting network in
+// workgroup memory. This needs a single dispatch, where a radix sort needs a handful per pass.
+
+const WG: u32 = 256u;
+const MAX_KEYS: u32 = 1024u;
+
+struct Uniforms {
+    high_bits: u32,
+}
+
+#ifdef WIDE_VALUES
+    // 64 bit values, as their low and high words.
+    alias Value = vec2u;
+#else
+    // 32 bit values, of any type.
+    alias Value = u32;
+#endif
+
+@group(0) @binding(0) var<storage, read> config: Uniforms;
+@group(0) @binding(1) var<storage, read> num_keys_arr: array<u32>;
+@group(0) @binding(2) var<storage, read> keys_high: array<u32>;
+@group(0) @binding(3) var<storage, read> keys_low: array<u32>;
+@group(0) @binding(4) var<storage, read> values: array<Value>;
+@group(0) @binding(5) var<storage, read_write> out_keys_high: array<u32>;
+@group(0) @binding(6) var<storage, read_write> out_values: array<Value>;
+
+var<workgroup> high: array<u32, MAX_KEYS>;
+var<workgroup> low: array<u32, MAX_KEYS>;
+// Index of each key in the input. Ties are broken on this, which makes the sort stable.
+var<workgroup> order: array<u32, MAX_KEYS>;
+
+fn key_less(a: u32, b: u32) -> bool {
+    if high[a] != high[b] {
+        return high[a] < high[b];
+    }
+    if low[a] != low[b] {
+        return low[a] < low[b];
+    }
+    return order[a] < order[b];
+}
+
+@compute
+@workgroup_size(WG, 1, 1)
+fn main(@builtin(local_invocation_index) local_idx: u32) {
+    let num_keys = min(num_keys_arr[0], min(MAX_KEYS, arrayLength(&keys_low)));
+    // Only the lowest bits of the high keys are sorted on, like the radix sort.
+    let high_mask = select(0xffffffffu, (1u << config.high_bits) - 1u, config.high_bits < 32u);
+
+    // The network sorts a full block, so pad it with keys that sort after all others.
+    for (var i = local_idx; i < MAX_KEYS; i += WG) {
+        if i < num_keys {
+            high[i] = keys_high[i] & high_mask;
+            low[i] = keys_low[i];
+            order[i] = i;
+        } else {
+            high[i] = 0xffffffffu;
+            low[i] = 0xffffffffu;
+            order[i] = 0xffffffffu;
+        }
+    }
+    workgroupBarrier();
+
+    for (var k = 2u; k <= MAX_KEYS; k <<= 1u) {
+        for (var j = k >> 1u; j > 0u; j >>= 1u) {
+            for (var t = local_idx; t < MAX_KEYS / 2u; t += WG) {
+                // Compare each key with the key j further along, within runs of length k that
+                // alternate between ascending and descending order.
+                let a = 2u * t - (t & (j - 1u));
+                let b = a + j;
+                let ascending = (a & k) == 0u;
+
+                if select(key_less(a, b), key_less(b, a), ascending) {
+                    let high_a = high[a];
+                    let low_a = low[a];
+                    let order_a = order[a];
+                    high[a] = high[b];
+                    low[a] = low[b];
+                    order[a] = order[b];
+                    high[b] = high_a;
+                    low[b] = low_a;
+                    order[b] = order_a;
+                }
+            }
+            workgroupBarrier();
+        }
+    }
+
+    for (var i = local_idx; i < num_keys; i += WG) {
+        let src = order[i];
+        out_keys_high[i] = keys_high[src];
+        out_values[i] = values[src];
+    }
+}
@@ -7,10 +7,33 @@ struct Uniforms {
 @group(0) @binding(0) var<storage, read> config: Uniforms;
 @group(0) @binding(1) var<storage, read> num_keys_arr: array<u32>;
 @group(0) @binding(2) var<storage, read> src: array<u32>;
-@group(0) @binding(3) var<storage, read> values: array<u32>;
+#ifdef WIDE_VALUES
+    // 64 bit values, as their low and high words.
+    alias Value = vec2u;
+#else
+    // 32 bit values, of any type.
+    alias Value = u32;
+#endif
+
+@group(0) @binding(3) var<storage, read> values: array<Value>;
 @group(0) @binding(4) var<storage, read> counts: array<u32>;
 @group(0) @binding(5) var<storage, read_write> out: array<u32>;
-@group(0) @binding(6) var<storage, read_write> out_values: array<u32>;
+@group(0) @binding(6) var<storage, read_write> out_values: array<Value>;
+
+#ifdef EXTRA_VALUES
+    // A second payload that is permuted along with the values, eg. the other half of a 64 bit key.
+    @group(0) @binding(7) var<storage, read> extra_values: array<u32>;
+    @group(0) @binding(8) var<storage, read_write> out_extra_values: array<u32>;
+#endif
+
+#ifdef DYNAMIC_BITS
+    // Number of keys sorted in this pass, zero when the pass is skipped.
+#ifdef EXTRA_VALUES
+    @group(0) @binding(9) var<storage, read> pass_keys_arr: array<u32>;
+#else
+    @group(0) @binding(7) var<storage, read> pass_keys_arr: array<u32>;
+#endif
+#endif
 
 var<workgroup> lds_sums: array<u32, sorting::WG>;
 var<workgroup> lds_scratch: array<u32, sorting::WG>;
@@ -33,6 +56,24 @@ fn main(
         return;
     }
 
+#ifdef DYNAMIC_BITS
+    // No key has any bits set at or above this pass, so the order can't change. Copy the
+    // input through, so the result ends up in the same buffers as for a sorting pass.
+    if pass_keys_arr[0] == 0u {
+        for (var i = 0u; i < sorting::ELEMENTS_PER_THREAD; i++) {
+            let data_index = sorting::BLOCK_SIZE * group_id + i * sorting::WG + local_id.x;
+            if data_index < num_keys {
+                out[data_index] = src[data_index];
+                out_values[data_index] = values[data_index];
+#ifdef EXTRA_VALUES
+                out_extra_values[data_index] = extra_values[data_index];
+#endif
+            }
+        }
+        return;
+    }
+#endif
+
     if local_id.x < sorting::BIN_COUNT {
         bin_offset_cache[local_id.x] = counts[local_id.x * num_wgs + group_id];
     }
@@ -45,11 +86,15 @@ fn main(
             local_histogram[local_id.x] = 0u;
         }
         var local_key = ~0u;
-        var local_value = 0u;
+        var local_value = Value();
+        var local_extra = 0u;
 
         if data_index < num_keys {
             local_key = src[data_index];
             local_value = values[data_index];
+#ifdef EXTRA_VALUES
+            local_extra = extra_values[data_index];
+#endif
         }
 
         for (var bit_shift = 0u; bit_shift < sorting::BITS_PER_PASS; bit_shift += 2u) {
@@ -81,10 +126,29 @@ fn main(
             local_key = lds_sums[local_id.x];
             workgroupBarrier();
         
+#ifdef WIDE_VALUES
+            lds_sums[key_offset] = local_value.x;
+            workgroupBarrier();
+            local_value.x = lds_sums[local_id.x];
+            workgroupBarrier();
+
+            lds_sums[key_offset] = local_value.y;
+            workgroupBarrier();
+            local_value.y = lds_sums[local_id.x];
+            workgroupBarrier();
+#else
             lds_sums[key_offset] = local_value;
             workgroupBarrier();
             local_value = lds_sums[local_id.x];
             workgroupBarrier();
+#endif
+
+#ifdef EXTRA_VALUES
+            lds_sums[key_offset] = local_extra;
+            workgroupBarrier();
+            local_extra = lds_sums[local_id.x];
+            workgroupBarrier();
+#endif
         }
         let key_index = (local_key >> config.shift) & 0xfu;
         atomicAdd(&local_histogram[key_index], 1u);
@@ -118,6 +182,9 @@ fn main(
         if total_offset < num_keys {
             out[total_offset] = local_key;
             out_values[total_offset] = local_value;
+#ifdef EXTRA_VALUES
+            out_extra_values[total_offset] = local_extra;
+#endif
         }
         if local_id.x < sorting::BIN_COUNT {
             bin_offset_cache[local_id.x] += local_histogram[local_id.x];
@@ -18,6 +18,7 @@ use burn::{
 pub(crate) struct AdamScaled {
     momentum: AdaptiveMomentum,
     weight_decay: Option<WeightDecay>,
+    decoupled_weight_decay: Option<f32>,
 }
 
 /// Adam configuration.
@@ -34,6 +35,9 @@ pub(crate) struct AdamScaledConfig {
     epsilon: f32,
     /// [Weight decay](WeightDecayConfig) config.
     weight_decay: Option<WeightDecayConfig>,
+    /// Decoupled weight decay, as in AdamW, which shrinks the parameters directly instead of
+    /// adding to their gradients.
+    decoupled_weight_decay: Option<f32>,
     /// [Gradient Clipping](GradientClippingConfig) config.
     grad_clipping: Option<GradientClippingConfig>,
 }
@@ -51,22 +55,42 @@ pub(crate) struct AdamState<B: Backend, const D: usize> {
     /// The current adaptive momentum.
     pub momentum: Option<AdaptiveMomentumState<B, D>>,
     pub scaling: Option<Tensor<B, D>>,
+    /// Which rows of the parameter are updated in the next step, as 1 or 0, broadcast over the
+    /// other dimensions. Other rows keep their value and moments, which makes for a sparse Adam.
+    /// The update is still computed densely, and masked after. This is part of the record, so
+    /// changing it changes the checkpoint format.
+    pub visible: Option<Tensor<B, D>>,
+}
+
+impl<B: Backend, const D: usize> Default for AdamState<B, D> {
+    fn default() -> Self {
+        Self {
+            momentum: None,
+            scaling: None,
+            visible: None,
+        }
+    }
 }
 
 impl AdamScaledConfig {
-    /// Initialize Adam optimizer.
-    pub(crate) fn init<B: AutodiffBackend, M: AutodiffModule<B>>(
-        &self,
-    ) -> OptimizerAdaptor<AdamScaled, M, B> {
-        let optim = AdamScaled {
+    /// Create the Adam optimizer, without wrapping it for a module.
+    pub(crate) fn build(&self) -> AdamScaled {
+        AdamScaled {
             momentum: AdaptiveMomentum {
                 beta_1: self.beta_1,
                 beta_2: self.beta_2,
                 epsilon: self.epsilon,
             },
             weight_decay: self.weight_decay.as_ref().map(WeightDecay::new),
-        };
-        let mut optim = OptimizerAdaptor::from(optim);
+            decoupled_weight_decay: self.decoupled_weight_decay,
+        }
+    }
+
+    /// Initialize Adam optimizer.
+    pub(crate) fn init<B: AutodiffBackend, M: AutodiffModule<B>>(
+        &self,
+    ) -> OptimizerAdaptor<AdamScaled, M, B> {
+        let mut optim = OptimizerAdaptor::from(self.build());
         if let Some(config) = &self.grad_clipping {
             optim = optim.with_grad_clipping(config.init());
         }
@@ -84,36 +108,52 @@ impl<B: Backend> SimpleOptimizer<B> for AdamScaled {
         mut grad: Tensor<B, D>,
         state: Option<Self::State<D>>,
     ) -> (Tensor<B, D>, Option<Self::State<D>>) {
-        let mut state_momentum = None;
-        let mut scaling = None;
-
-        if let Some(state) = state {
-            state_momentum = state.momentum;
-            scaling = state.scaling;
-        }
+        let state = state.unwrap_or_default();
 
         if let Some(weight_decay) = &self.weight_decay {
             grad = weight_decay.transform(grad, tensor.clone());
         }
 
-        let (grad, state_momentum) = self.momentum.transform(grad, state_momentum);
+        let previous = state.momentum.clone();
+        let (mut grad, mut state_momentum) = self.momentum.transform(grad, state.momentum);
 
-        let state = AdamState {
-            momentum: Some(state_momentum),
-            scaling: scaling.clone(),
-        };
+        if let Some(decay) = self.decoupled_weight_decay {
+            grad = grad + tensor.clone().mul_scalar(decay);
+        }
 
-        let delta = if let Some(scale) = scaling {
-            grad * (scale * lr).unsqueeze()
+        let mut delta = if let Some(scale) = &state.scaling {
+            grad * (scale.clone() * lr).unsqueeze()
         } else {
             grad * lr
         };
 
+        // Rows that aren't updated keep their moments, as if this step didn't happen for them.
+        if let Some(visible) = &state.visible {
+            let hidden = visible.clone().neg().add_scalar(1.0);
+            let keep = |new: Tensor<B, D>, old: Option<Tensor<B, D>>| match old {
+                Some(old) => new * visible.clone() + old * hidden.clone(),
+                None => new * visible.clone(),
+            };
+            state_momentum.moment_1 = keep(
+                state_momentum.moment_1,
+                previous.as_ref().map(|p| p.moment_1.clone()),
+            );
+            state_momentum.moment_2 = keep(state_momentum.moment_2, previous.map(|p| p.moment_2));
+            delta = delta * visible.clone();
+        }
+
+        let state = AdamState {
+            momentum: Some(state_momentum),
+            scaling: state.scaling,
+            visible: state.visible,
+        };
+
         (tensor - delta, Some(state))
     }
 
     fn to_device<const D: usize>(mut state: Self::State<D>, device: &Device<B>) -> Self::State<D> {
         state.momentum = state.momentum.map(|m| m.to_device(device));
+        state.visible = state.visible.map(|v| v.to_device(device));
         state
     }
 }
@@ -163,3 +203,92 @@ impl AdaptiveMomentum {
         (grad, state)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{AdamScaledConfig, AdamState};
+    use brush_render::MainBackend;
+    use burn::{
+        backend::wgpu::WgpuDevice,
+        optim::SimpleOptimizer,
+        tensor::{Distribution, ElementConversion, Tensor},
+    };
+
+    #[test]
+    fn sparse_adam_matches_dense_when_all_visible() {
+        let device = WgpuDevice::DefaultDevice;
+        let adam = AdamScaledConfig::new().with_epsilon(1e-15).build();
+
+        let mut dense = Tensor::<MainBackend, 2>::random([32, 3], Distribution::Default, &device);
+        let mut sparse = dense.clone();
+        let mut dense_state = None;
+        let mut sparse_state = Some(AdamState {
+            visible: Some(Tensor::ones([32, 1], &device)),
+            ..Default::default()
+        });
+        for _ in 0..3 {
+            let grad = Tensor::random([32, 3], Distribution::Normal(0.0, 1.0), &device);
+            (dense, dense_state) = adam.step(1e-2, dense, grad.clone(), dense_state);
+            (sparse, sparse_state) = adam.step(1e-2, sparse, grad, sparse_state);
+        }
+
+        let diff = (dense - sparse).abs().max().into_scalar().elem::<f32>();
+        assert_eq!(
+            diff, 0.0,
+            "Sparse Adam must match Adam when all rows are visible"
+        );
+    }
+
+    #[test]
+    fn sparse_adam_keeps_hidden_rows() {
+        let device = WgpuDevice::DefaultDevice;
+        let adam = AdamScaledConfig::new().with_epsilon(1e-15).build();
+        let grad = || Tensor::random([8, 3], Distribution::Normal(0.0, 1.0), &device);
+
+        // Build up momentum with a dense step, then hide every other row.
+        let params = Tensor::<MainBackend, 2>::random([8, 3], Distribution::Default, &device);
+        let (params, state) = adam.step(1e-2, params, grad(), None);
+        let mut state = state.expect("Adam must have state");
+        let moment = state
+            .momentum
+            .clone()
+            .expect("Adam must have momentum")
+            .moment_1;
+        state.visible = Some(
+            Tensor::<MainBackend, 1>::from_floats(
+                [1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0],
+                &device,
+            )
+            .unsqueeze_dim(1),
+        );
+        let (next, state) = adam.step(1e-2, params.clone(), grad(), Some(state));
+
+        let moved = (next - params)
+            .abs()
+            .sum_dim(1)
+            .into_data()
+            .to_vec::<f32>()
+            .expect("Wrong tensor type");
+        let moment_change = (state
+            .and_then(|state| state.momentum)
+            .expect("Adam must have momentum")
+            .moment_1
+            - moment)
+            .abs()
+            .sum_dim(1)
+            .into_data()
+            .to_vec::<f32>()
+            .expect("Wrong tensor type");
+        for row in 0..8 {
+            if row % 2 == 0 {
+                assert!(moved[row] > 0.0, "Visible row {row} must be updated");
+            } else {
+                assert_eq!(moved[row], 0.0, "Hidden row {row} must not move");
+                assert_eq!(
+                    moment_change[row], 0.0,
+                    "Hidden row {row} must keep its moments"
+                );
+            }
+        }
+    }
+}
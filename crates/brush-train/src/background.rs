@@ -0,0 +1,99 @@
+use burn::{
+    module::{Module, Param},
+    prelude::Backend,
+    tensor::{Tensor, s},
+};
+
+/// A constant background color behind the splats, which can be optimized during training.
+#[derive(Module, Debug)]
+pub(crate) struct Background<B: Backend> {
+    pub color: Param<Tensor<B, 1>>,
+}
+
+impl<B: Backend> Background<B> {
+    pub(crate) fn new(color: glam::Vec3, device: &B::Device) -> Self {
+        let color = Tensor::from_floats(color.to_array(), device);
+        Self {
+            color: Param::from_tensor(color),
+        }
+    }
+
+    /// Blend a rendered, premultiplied RGBA image over the background, returning RGB.
+    ///
+    /// The background shows through by the final transmittance of each pixel, ie. one minus
+    /// the rendered alpha, so it receives gradients wherever the splats don't cover the image.
+    pub(crate) fn composite(&self, img: Tensor<B, 3>) -> Tensor<B, 3> {
+        composite_over(img, self.color.val().reshape([1, 1, 3]))
+    }
+}
+
+/// Blend a rendered, premultiplied RGBA image over an RGB background, returning RGB. Grayscale
+/// images, with a gray channel and alpha, are blended over a gray background the same way.
+///
+/// The background is either a full `[height, width, channels]` image, or a single color broadcast
+/// to all pixels.
+pub(crate) fn composite_over<B: Backend>(
+    img: Tensor<B, 3>,
+    background: Tensor<B, 3>,
+) -> Tensor<B, 3> {
+    let channels = img.dims()[2] - 1;
+    let color = img.clone().slice(s![.., .., 0..channels]);
+    let transmittance = 1.0f32 - img.slice(s![.., .., channels..channels + 1]);
+    color + transmittance * background
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Background;
+    use crate::{
+        adam_scaled::{AdamScaled, AdamScaledConfig},
+        test_scene::{random_splats, test_camera},
+    };
+    use brush_render::MainBackend;
+    use burn::{
+        backend::{Autodiff, wgpu::WgpuDevice},
+        optim::{GradientsParams, Optimizer, adaptor::OptimizerAdaptor},
+        tensor::Tensor,
+    };
+
+    type DiffBack = Autodiff<MainBackend>;
+
+    #[test]
+    fn learns_constant_background() {
+        let device = WgpuDevice::DefaultDevice;
+
+        // A handful of splats covering part of the view, the rest shows the background.
+        let splats = random_splats::<MainBackend>(32, &device);
+        let (img, _) = splats.render(&test_camera(-4.0), glam::uvec2(32, 32), true);
+        let img = Tensor::<DiffBack, 3>::from_inner(img);
+
+        let target_color = glam::vec3(0.2, 0.5, 0.8);
+        let gt = Background::<DiffBack>::new(target_color, &device).composite(img.clone());
+
+        let mut background = Background::<DiffBack>::new(glam::Vec3::ZERO, &device);
+        let mut optim: OptimizerAdaptor<AdamScaled, Background<DiffBack>, DiffBack> =
+            AdamScaledConfig::new().init();
+
+        for _ in 0..300 {
+            let pred = background.composite(img.clone());
+            let loss = (pred - gt.clone()).powi_scalar(2).mean();
+            let mut grads = loss.backward();
+            let grads =
+                GradientsParams::from_params(&mut grads, &background, &[background.color.id]);
+            background = optim.step(0.01, background, grads);
+        }
+
+        let color = background
+            .color
+            .val()
+            .into_data()
+            .to_vec::<f32>()
+            .expect("Wrong type");
+        for (learned, target) in color.iter().zip(target_color.to_array()) {
+            assert!(
+                (learned - target).abs() < 0.02,
+                "Learned background {color:?} doesn't match {target_color}"
+            );
+        }
+    }
+}
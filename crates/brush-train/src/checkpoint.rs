@@ -0,0 +1,120 @@
+use anyhow::{Context, Result, bail};
+
+/// Version of the checkpoint format. This has to be bumped whenever the contents of a checkpoint
+/// change, so old checkpoints are rejected instead of being misread.
+pub const CHECKPOINT_VERSION: u32 = 6;
+
+const MAGIC: &[u8; 8] = b"BRUSHCKP";
+
+/// The raw contents of a checkpoint: a small header, followed by opaque sections.
+///
+/// The meaning of each section is defined by the trainer, for the current version.
+#[derive(Debug, PartialEq)]
+pub(crate) struct CheckpointData {
+    pub iter: u32,
+    pub active_sh_degree: u32,
+    pub sections: Vec<Vec<u8>>,
+}
+
+impl CheckpointData {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(CHECKPOINT_VERSION.to_le_bytes());
+        bytes.extend(self.iter.to_le_bytes());
+        bytes.extend(self.active_sh_degree.to_le_bytes());
+        bytes.extend((self.sections.len() as u32).to_le_bytes());
+        for section in &self.sections {
+            bytes.extend((section.len() as u64).to_le_bytes());
+            bytes.extend(section);
+        }
+        bytes
+    }
+
+    pub(crate) fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            bail!("Not a brush checkpoint");
+        }
+        let version = reader.u32()?;
+        if version != CHECKPOINT_VERSION {
+            bail!("Unsupported checkpoint version {version}, expected {CHECKPOINT_VERSION}");
+        }
+        let iter = reader.u32()?;
+        let active_sh_degree = reader.u32()?;
+        let num_sections = reader.u32()?;
+        let sections = (0..num_sections)
+            .map(|_| {
+                let len = usize::try_from(reader.u64()?).context("Section too large")?;
+                Ok(reader.take(len)?.to_vec())
+            })
+            .collect::<Result<_>>()?;
+        if !reader.bytes.is_empty() {
+            bail!("Trailing data after checkpoint");
+        }
+        Ok(Self {
+            iter,
+            active_sh_degree,
+            sections,
+        })
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            bail!("Checkpoint is truncated");
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CHECKPOINT_VERSION, CheckpointData};
+
+    #[test]
+    fn round_trip() {
+        let data = CheckpointData {
+            iter: 1234,
+            active_sh_degree: 2,
+            sections: vec![vec![1, 2, 3], vec![], vec![4; 100]],
+        };
+        let decoded = CheckpointData::decode(&data.encode()).expect("Failed to decode");
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let data = CheckpointData {
+            iter: 1,
+            active_sh_degree: 0,
+            sections: vec![vec![1]],
+        };
+        let mut bytes = data.encode();
+        bytes[8..12].copy_from_slice(&(CHECKPOINT_VERSION + 1).to_le_bytes());
+        assert!(
+            CheckpointData::decode(&bytes).is_err(),
+            "Future versions must be rejected"
+        );
+
+        let bytes = data.encode();
+        assert!(
+            CheckpointData::decode(&bytes[..bytes.len() - 1]).is_err(),
+            "Truncated checkpoints must be rejected"
+        );
+    }
+}
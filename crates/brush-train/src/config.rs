@@ -1,5 +1,38 @@
+use brush_render::gaussian_splats::OpacityActivation;
 use burn::config::Config;
-use clap::Args;
+use clap::{Args, ValueEnum};
+
+/// Variant of Adam that optimizes the splat parameters.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, ValueEnum,
+)]
+pub enum OptimizerKind {
+    /// Adam, with the weight decay added to the gradients (L2 regularization).
+    #[default]
+    Adam,
+    /// Adam with decoupled weight decay (Loshchilov & Hutter 2019), which shrinks the parameters
+    /// directly, so the decay isn't normalized away by the second moments.
+    #[value(name = "adamw")]
+    AdamW,
+    /// Adam that only updates the splats visible in the views of a step, as in Taming 3DGS
+    /// (Mallick et al. 2024). Other splats keep their values and moments, instead of drifting on
+    /// their momentum while they aren't seen. This changes what is optimized, not how fast: the
+    /// update is computed for all splats and masked to the visible ones.
+    SparseAdam,
+}
+
+/// How the next view to train on is picked.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, ValueEnum,
+)]
+pub enum ViewSampling {
+    /// Visit every view once per epoch, in a shuffled order.
+    #[default]
+    Uniform,
+    /// Sample views weighted by their recent loss, so views that are fit badly are trained on more
+    /// often. See `view_sampling_temperature`.
+    LossWeighted,
+}
 
 #[derive(Config, Args)]
 pub struct TrainConfig {
@@ -63,6 +96,143 @@ pub struct TrainConfig {
     #[arg(long, help_heading = "Training options", default_value = "1e-3")]
     pub lr_rotation: f64,
 
+    /// Optimizer for the splat parameters.
+    #[config(default = "OptimizerKind::Adam")]
+    #[arg(
+        long,
+        value_enum,
+        help_heading = "Training options",
+        default_value = "adam"
+    )]
+    pub optimizer: OptimizerKind,
+
+    /// Decay rate of the first moment (the running mean of the gradients) of the optimizer.
+    #[config(default = 0.9)]
+    #[arg(long, help_heading = "Training options", default_value = "0.9")]
+    pub adam_beta_1: f32,
+
+    /// Decay rate of the second moment (the running mean of the squared gradients) of the
+    /// optimizer.
+    #[config(default = 0.999)]
+    #[arg(long, help_heading = "Training options", default_value = "0.999")]
+    pub adam_beta_2: f32,
+
+    /// Added to the root of the second moment of the optimizer, for numerical stability.
+    #[config(default = 1e-15)]
+    #[arg(long, help_heading = "Training options", default_value = "1e-15")]
+    pub adam_epsilon: f32,
+
+    /// Weight decay of the splat parameters, relative to the learning rate. With `adamw` it is
+    /// decoupled from the gradients, otherwise it's added to them. 0 disables weight decay.
+    #[config(default = 0.0)]
+    #[arg(long, help_heading = "Training options", default_value = "0.0")]
+    pub weight_decay: f32,
+
+    /// Learn a constant background color behind the splats, for scenes where the background
+    /// tint is unknown. This is meant for opaque images, images with transparency already
+    /// define what is background.
+    #[config(default = false)]
+    #[arg(long, help_heading = "Training options", default_value = "false")]
+    pub learn_background: bool,
+
+    /// Learning rate for the background color, when it is learned.
+    #[config(default = 1e-2)]
+    #[arg(long, help_heading = "Training options", default_value = "1e-2")]
+    pub lr_background: f64,
+
+    /// Train on grayscale images: the training images are converted to gray, and the splats keep
+    /// a single gray channel of sh coefficients instead of red, green and blue. Exported splats
+    /// are still saved with three equal color channels. Not supported with a learned background.
+    /// Views are still rasterized in color, so this saves memory for the splats, but not time.
+    #[config(default = false)]
+    #[arg(long, help_heading = "Training options", default_value = "false")]
+    pub grayscale: bool,
+
+    /// Number of views to accumulate gradients over for each optimizer step. The loss is
+    /// averaged over the views, so the learning rates don't need to change. Only one view is
+    /// rendered at a time, so this increases the effective batch size without using more memory.
+    #[config(default = 1)]
+    #[arg(long, help_heading = "Training options", default_value = "1")]
+    pub grad_accum_views: u32,
+
+    /// How the next view to train on is picked.
+    #[config(default = "ViewSampling::Uniform")]
+    #[arg(
+        long,
+        value_enum,
+        help_heading = "Training options",
+        default_value = "uniform"
+    )]
+    pub view_sampling: ViewSampling,
+
+    /// Temperature of loss weighted view sampling. Views are sampled proportionally to their
+    /// loss to the power of 1 / temperature: higher temperatures sample more uniformly, lower
+    /// temperatures focus more on the views with the highest loss.
+    #[config(default = 1.0)]
+    #[arg(long, help_heading = "Training options", default_value = "1.0")]
+    pub view_sampling_temperature: f32,
+
+    /// Number of steps between activating each additional band of spherical harmonics.
+    /// Training starts with only the base color, so view dependent effects are learned
+    /// after it has settled. Set to 0 to train all bands from the start.
+    #[config(default = 1000)]
+    #[arg(long, help_heading = "Training options", default_value = "1000")]
+    pub sh_degree_interval: u32,
+
+    /// Seed for the random initialization of splats, when not starting from existing splats.
+    /// Defaults to the process seed.
+    #[arg(long, help_heading = "Training options")]
+    pub init_seed: Option<u64>,
+
+    /// Number of nearest neighbours used to estimate the initial scale of random splats, and of
+    /// splats created from a COLMAP point cloud.
+    #[config(default = 3)]
+    #[arg(long, help_heading = "Training options", default_value = "3")]
+    pub init_knn: usize,
+
+    /// Multiplier of the initial scale of random and point cloud splats, which is estimated from
+    /// their nearest neighbours. Lower this when initial splats overlap too much.
+    #[config(default = 1.0)]
+    #[arg(long, help_heading = "Training options", default_value = "1.0")]
+    pub init_scale_multiplier: f32,
+
+    /// Initial scale of random and point cloud splats without neighbours to estimate their scale
+    /// from, in world units.
+    #[config(default = 0.1)]
+    #[arg(long, help_heading = "Training options", default_value = "0.1")]
+    pub init_fallback_scale: f32,
+
+    /// Opacity of the initial random and point cloud splats. Defaults to random opacities between
+    /// 0.1 and 0.25.
+    #[arg(long, help_heading = "Training options")]
+    pub init_opacity: Option<f32>,
+
+    /// Activation that maps the raw opacity parameters of splats to their opacity, either
+    /// `sigmoid` or `exp-density`. Splats are converted to it before training, keeping their
+    /// opacities.
+    #[config(default = "OpacityActivation::Sigmoid")]
+    #[arg(long, help_heading = "Training options", default_value = "sigmoid")]
+    pub opacity_activation: OpacityActivation,
+
+    /// Start training at a lower resolution, and double it every this many steps until the
+    /// full resolution is reached. This speeds up early training, when only coarse structure
+    /// is learned. 0 trains at full resolution from the start.
+    #[config(default = 0)]
+    #[arg(long, help_heading = "Training options", default_value = "0")]
+    pub resolution_doubling_interval: u32,
+
+    /// How many times the resolution is halved at the start of training, when
+    /// `resolution_doubling_interval` is set.
+    #[config(default = 2)]
+    #[arg(long, help_heading = "Training options", default_value = "2")]
+    pub resolution_levels: u32,
+
+    /// Jitter the principal point by a sub-pixel offset each step, which reduces aliasing at
+    /// edges. Offsets follow a Halton sequence, so they cover the pixel evenly.
+    #[config(default = false)]
+    #[arg(long, help_heading = "Training options", default_value = "false")]
+    pub subpixel_jitter: bool,
+
     /// Weight of the opacity loss.
     #[config(default = 1e-8)]
     #[arg(long, help_heading = "Training options", default_value = "1e-8")]
@@ -79,6 +249,26 @@ pub struct TrainConfig {
     #[arg(long, help_heading = "Refine options", default_value = "0.00085")]
     pub growth_grad_threshold: f32,
 
+    /// Grow splats by their mean screen space gradient over the views they were visible in since
+    /// the last refine, instead of their largest gradient. The mean is lower than the largest
+    /// gradient, so this needs a lower growth threshold.
+    #[config(default = false)]
+    #[arg(long, help_heading = "Refine options", default_value = "false")]
+    pub growth_mean_grad: bool,
+
+    /// Prune splats that never project larger than this radius in pixels, in any of the views
+    /// they were visible in since the last refine. These are likely noise. 0 disables this.
+    #[config(default = 0.0)]
+    #[arg(long, help_heading = "Refine options", default_value = "0.0")]
+    pub prune_min_radius: f32,
+
+    /// Prune splats that always project larger than this radius in pixels, in all of the views
+    /// they were visible in since the last refine. These are likely floaters covering the image.
+    /// 0 disables this.
+    #[config(default = 0.0)]
+    #[arg(long, help_heading = "Refine options", default_value = "0.0")]
+    pub prune_max_radius: f32,
+
     /// What fraction of splats that are deemed as needing to grow do actually grow.
     /// Increase this to make splats grow more aggressively.
     #[config(default = 0.1)]
@@ -90,6 +280,11 @@ pub struct TrainConfig {
     #[arg(long, help_heading = "Refine options", default_value = "12500")]
     pub growth_stop_iter: u32,
 
+    /// Seed for the random choices of refinement: which splats are replaced or grown, and where
+    /// their copies are placed. Defaults to the process seed.
+    #[arg(long, help_heading = "Refine options")]
+    pub refine_seed: Option<u64>,
+
     /// Weight of l1 loss on alpha if input view has transparency.
     #[config(default = 0.1)]
     #[arg(long, help_heading = "Refine options", default_value = "0.1")]
@@ -100,3 +295,110 @@ pub struct TrainConfig {
     #[arg(long, help_heading = "Refine options", default_value = "10000000")]
     pub max_splats: u32,
 }
+
+impl TrainConfig {
+    /// The highest SH degree that is trained at the given step, for splats with `max_degree`
+    /// bands. Higher bands are not rendered, and receive no gradients.
+    pub fn active_sh_degree(&self, iter: u32, max_degree: u32) -> u32 {
+        if self.sh_degree_interval == 0 {
+            return max_degree;
+        }
+        (iter / self.sh_degree_interval).min(max_degree)
+    }
+
+    /// Factor views are downsampled by at the given step, following the progressive
+    /// resolution schedule. This is 1 once the full resolution is reached.
+    pub fn resolution_factor(&self, iter: u32) -> u32 {
+        if self.resolution_doubling_interval == 0 {
+            return 1;
+        }
+        let stage = iter / self.resolution_doubling_interval;
+        1 << self.resolution_levels.saturating_sub(stage).min(16)
+    }
+
+    /// Offset of the principal point at the given step, in pixels, see
+    /// [`brush_render::render_options::RenderOptions::subpixel_offset`]. This is zero unless
+    /// `subpixel_jitter` is enabled.
+    pub fn subpixel_offset(&self, iter: u32) -> glam::Vec2 {
+        if !self.subpixel_jitter {
+            return glam::Vec2::ZERO;
+        }
+        // Skip the first point of the sequence, which is 0 in both dimensions.
+        glam::vec2(halton(iter + 1, 2), halton(iter + 1, 3)) - 0.5
+    }
+}
+
+/// The `index`th element of the van der Corput sequence in `base`, in [0, 1).
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut scale = 1.0 / base as f32;
+    while index > 0 {
+        result += (index % base) as f32 * scale;
+        index /= base;
+        scale /= base as f32;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrainConfig;
+
+    #[test]
+    fn sh_degree_ramp() {
+        let config = TrainConfig::new().with_sh_degree_interval(1000);
+        assert_eq!(config.active_sh_degree(0, 3), 0);
+        assert_eq!(config.active_sh_degree(999, 3), 0);
+        assert_eq!(config.active_sh_degree(1000, 3), 1);
+        assert_eq!(config.active_sh_degree(2500, 3), 2);
+        assert_eq!(config.active_sh_degree(30000, 3), 3);
+        assert_eq!(config.active_sh_degree(30000, 0), 0);
+    }
+
+    #[test]
+    fn resolution_schedule_doubles() {
+        assert_eq!(TrainConfig::new().resolution_factor(0), 1);
+
+        let config = TrainConfig::new()
+            .with_resolution_doubling_interval(500)
+            .with_resolution_levels(2);
+        let factors: Vec<_> = [0, 499, 500, 999, 1000, 30000]
+            .into_iter()
+            .map(|iter| config.resolution_factor(iter))
+            .collect();
+        assert_eq!(factors, [4, 4, 2, 2, 1, 1]);
+    }
+
+    #[test]
+    fn subpixel_offsets_cover_pixel() {
+        assert_eq!(TrainConfig::new().subpixel_offset(7), glam::Vec2::ZERO);
+
+        let config = TrainConfig::new().with_subpixel_jitter(true);
+        assert_eq!(config.subpixel_offset(0), glam::vec2(0.0, 1.0 / 3.0 - 0.5));
+        let offsets: Vec<_> = (0..64).map(|i| config.subpixel_offset(i)).collect();
+        assert!(
+            offsets
+                .iter()
+                .all(|o| o.cmpge(glam::Vec2::splat(-0.5)).all()
+                    && o.cmplt(glam::Vec2::splat(0.5)).all()),
+            "Offsets must stay within the pixel"
+        );
+        // Every quadrant of the pixel is sampled equally.
+        for quadrant in [(false, false), (false, true), (true, false), (true, true)] {
+            let count = offsets
+                .iter()
+                .filter(|o| (o.x >= 0.0, o.y >= 0.0) == quadrant)
+                .count();
+            assert!(
+                (12..=20).contains(&count),
+                "Uneven coverage {count} of {quadrant:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn sh_degree_ramp_disabled() {
+        let config = TrainConfig::new().with_sh_degree_interval(0);
+        assert_eq!(config.active_sh_degree(0, 3), 3);
+    }
+}
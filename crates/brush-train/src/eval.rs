@@ -3,12 +3,75 @@ use brush_dataset::scene::{SceneView, sample_to_tensor, view_to_sample_image};
 use brush_render::SplatForward;
 use brush_render::gaussian_splats::Splats;
 use brush_render::render_aux::RenderAux;
+use brush_render::render_options::LUMA_WEIGHTS;
 use burn::prelude::Backend;
 use burn::tensor::{Tensor, s};
 use image::DynamicImage;
 
 use crate::ssim::Ssim;
 
+/// Size of the gaussian window SSIM is computed over, in pixels.
+const SSIM_WINDOW: usize = 11;
+
+/// Quality of a rendered image compared to a reference image, as single element tensors.
+///
+/// The metrics stay on the device, so evaluating many views doesn't need a readback per view.
+pub struct ImageMetrics<B: Backend> {
+    /// Peak signal to noise ratio in decibels, for images in [0, 1].
+    pub psnr: Tensor<B, 1>,
+    /// Mean structural similarity, 1 for identical images.
+    pub ssim: Tensor<B, 1>,
+}
+
+/// Mean of `values` over the pixels where `mask` is set, weighted by the mask. Without a mask
+/// this is the mean over all pixels.
+fn masked_mean<B: Backend>(values: Tensor<B, 3>, mask: Option<Tensor<B, 2>>) -> Tensor<B, 1> {
+    let Some(mask) = mask else {
+        return values.mean();
+    };
+    let [_, _, channels] = values.dims();
+    let mask = mask.unsqueeze_dim::<3>(2);
+    (values * mask.clone()).sum() / (mask.sum() * channels as f32).clamp_min(f32::EPSILON)
+}
+
+/// PSNR of `rendered` compared to `reference`, both `[h, w, c]` images in [0, 1].
+///
+/// With a `[h, w]` mask, only pixels with a non zero mask count, weighted by the mask.
+pub fn psnr<B: Backend>(
+    rendered: Tensor<B, 3>,
+    reference: Tensor<B, 3>,
+    mask: Option<Tensor<B, 2>>,
+) -> Tensor<B, 1> {
+    let mse = masked_mean((rendered - reference).powi_scalar(2), mask);
+    mse.recip().log() * 10.0 / std::f32::consts::LN_10
+}
+
+/// Mean SSIM of `rendered` compared to `reference`, both `[h, w, c]` images.
+///
+/// With a `[h, w]` mask, the SSIM is averaged over the pixels with a non zero mask, weighted by
+/// the mask. The SSIM of each pixel still depends on the pixels in a window around it.
+pub fn ssim<B: Backend>(
+    rendered: Tensor<B, 3>,
+    reference: Tensor<B, 3>,
+    mask: Option<Tensor<B, 2>>,
+) -> Tensor<B, 1> {
+    let [_, _, channels] = rendered.dims();
+    let ssim = Ssim::new(SSIM_WINDOW, channels, &rendered.device());
+    masked_mean(ssim.ssim(rendered, reference), mask)
+}
+
+/// PSNR and SSIM of `rendered` compared to `reference`, see [`psnr`] and [`ssim`].
+pub fn image_metrics<B: Backend>(
+    rendered: Tensor<B, 3>,
+    reference: Tensor<B, 3>,
+    mask: Option<Tensor<B, 2>>,
+) -> ImageMetrics<B> {
+    ImageMetrics {
+        psnr: psnr(rendered.clone(), reference.clone(), mask.clone()),
+        ssim: ssim(rendered, reference, mask),
+    }
+}
+
 pub struct EvalSample<B: Backend> {
     pub gt_img: DynamicImage,
     pub rendered: Tensor<B, 3>,
@@ -33,6 +96,13 @@ pub async fn eval_stats<B: Backend + SplatForward<B>>(
     );
 
     let gt_rgb = gt_tensor.slice(s![.., .., 0..3]);
+    // Gray splats render equal color channels, so compare against the gray reference.
+    let gt_rgb = if splats.is_grayscale() {
+        let weights = Tensor::<B, 1>::from_floats(LUMA_WEIGHTS, device).reshape([1, 1, 3]);
+        (gt_rgb * weights).sum_dim(2).repeat_dim(2, 3)
+    } else {
+        gt_rgb
+    };
 
     let (rendered, aux) = splats.render(&eval_view.camera, res, true);
     let render_rgb = rendered.slice(s![.., .., 0..3]);
@@ -40,11 +110,7 @@ pub async fn eval_stats<B: Backend + SplatForward<B>>(
     // Simulate an 8-bit roundtrip for fair comparison.
     let render_rgb = (render_rgb * 255.0).round() / 255.0;
 
-    let mse = (render_rgb.clone() - gt_rgb.clone()).powi_scalar(2).mean();
-
-    let psnr = mse.recip().log() * 10.0 / std::f32::consts::LN_10;
-    let ssim_measure = Ssim::new(11, 3, device);
-    let ssim = ssim_measure.ssim(render_rgb.clone(), gt_rgb).mean();
+    let ImageMetrics { psnr, ssim } = image_metrics(render_rgb.clone(), gt_rgb, None);
 
     Ok(EvalSample {
         gt_img,
@@ -54,3 +120,60 @@ pub async fn eval_stats<B: Backend + SplatForward<B>>(
         aux,
     })
 }
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use super::{image_metrics, psnr, ssim};
+    use burn::{
+        backend::{Wgpu, wgpu::WgpuDevice},
+        tensor::{Distribution, Tensor, s},
+    };
+
+    #[test]
+    fn metrics_of_identical_images() {
+        let device = WgpuDevice::DefaultDevice;
+        let img = Tensor::<Wgpu, 3>::random([16, 24, 3], Distribution::Uniform(0.0, 1.0), &device);
+        let metrics = image_metrics(img.clone(), img, None);
+        assert!(metrics.psnr.into_scalar().is_infinite());
+        let ssim = metrics.ssim.into_scalar();
+        assert!((ssim - 1.0).abs() < 1e-5, "SSIM of equal images is {ssim}");
+    }
+
+    #[test]
+    fn psnr_of_constant_error() {
+        let device = WgpuDevice::DefaultDevice;
+        let reference = Tensor::<Wgpu, 3>::full([16, 16, 3], 0.5, &device);
+
+        // An error of 0.1 everywhere is an MSE of 0.01, or 20 dB.
+        let rendered = reference.clone() + 0.1;
+        let value = psnr(rendered, reference.clone(), None).into_scalar();
+        assert!((value - 20.0).abs() < 1e-3, "Expected 20 dB, got {value}");
+
+        // Masking out the right half leaves only the pixels with an error of 0.01, or 40 dB.
+        let rendered = (reference.clone() + 0.01)
+            .slice_assign(s![.., 8..16, ..], Tensor::full([16, 8, 3], 1.0, &device));
+        let mask = Tensor::<Wgpu, 2>::ones([16, 16], &device)
+            .slice_assign(s![.., 8..16], Tensor::zeros([16, 8], &device));
+        let value = psnr(rendered, reference, Some(mask)).into_scalar();
+        assert!((value - 40.0).abs() < 1e-2, "Expected 40 dB, got {value}");
+    }
+
+    #[test]
+    fn ssim_of_constant_images() {
+        let device = WgpuDevice::DefaultDevice;
+        let rendered = Tensor::<Wgpu, 3>::full([24, 24, 3], 0.5, &device);
+        let reference = Tensor::<Wgpu, 3>::full([24, 24, 3], 0.6, &device);
+
+        // Constant images have no variance, so away from the zero padded borders the SSIM only
+        // compares the means: (2 * 0.5 * 0.6 + c1) / (0.5^2 + 0.6^2 + c1).
+        let c1 = 0.01f32.powi(2);
+        let expected = (2.0 * 0.5 * 0.6 + c1) / (0.5 * 0.5 + 0.6 * 0.6 + c1);
+        let interior = Tensor::<Wgpu, 2>::zeros([24, 24], &device)
+            .slice_assign(s![6..18, 6..18], Tensor::ones([12, 12], &device));
+        let value = ssim(rendered, reference, Some(interior)).into_scalar();
+        assert!(
+            (value - expected).abs() < 1e-4,
+            "Expected SSIM {expected}, got {value}"
+        );
+    }
+}
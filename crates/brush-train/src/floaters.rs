@@ -0,0 +1,221 @@
+//! Removing floaters after training: splats far away from the rest of the scene, or that barely
+//! contribute to any of the training views.
+
+use brush_render::{
+    SplatForward,
+    camera::Camera,
+    gaussian_splats::{Splats, knn_mean_distances},
+};
+use burn::{
+    prelude::Backend,
+    tensor::{Int, Tensor, TensorData, TensorPrimitive},
+};
+use glam::Vec3;
+
+/// Thresholds for what counts as a floater, see [`find_floaters`].
+#[derive(Debug, Clone, Copy)]
+pub struct FloaterOptions {
+    /// Number of nearest neighbours the distance of a splat to the rest of the scene is measured
+    /// with.
+    pub knn: usize,
+    /// Splats whose mean distance to their nearest neighbours is more than this many times the
+    /// median of that distance over all splats are outliers. Set to infinity to keep all splats
+    /// regardless of their distance.
+    pub distance_factor: f32,
+    /// Splats visible in less than this fraction of views are floaters. Set to 0 to keep all
+    /// splats regardless of their visibility.
+    pub min_visibility: f32,
+}
+
+impl Default for FloaterOptions {
+    fn default() -> Self {
+        Self {
+            knn: 8,
+            distance_factor: 10.0,
+            min_visibility: 0.0,
+        }
+    }
+}
+
+/// Fraction of the views each splat contributes to, to pass to [`find_floaters`].
+///
+/// NB: This doesn't work on a differentiable backend.
+pub fn accumulate_visibility<B: Backend + SplatForward<B>>(
+    splats: &Splats<B>,
+    cameras: &[Camera],
+    img_size: glam::UVec2,
+) -> Tensor<B, 1> {
+    let device = splats.device();
+    let mut visible = Tensor::zeros([splats.num_splats() as usize], &device);
+    for camera in cameras {
+        let (_, aux) = splats.render(camera, img_size, true);
+        visible = visible + Tensor::from_primitive(TensorPrimitive::Float(aux.visible));
+    }
+    visible / cameras.len().max(1) as f32
+}
+
+/// Flags the splats that are floaters: splats that are spatial outliers, or that are visible in
+/// too few views.
+///
+/// `visibility` is the fraction of views each splat is visible in, see
+/// [`accumulate_visibility`]. Without it, splats are only judged by their distance to their
+/// neighbours.
+pub fn find_floaters(
+    means: &[Vec3],
+    visibility: Option<&[f32]>,
+    options: &FloaterOptions,
+) -> Vec<bool> {
+    let mut floaters = vec![false; means.len()];
+
+    if let Some(visibility) = visibility {
+        assert_eq!(
+            visibility.len(),
+            means.len(),
+            "Need the visibility of every splat"
+        );
+        for (floater, &vis) in floaters.iter_mut().zip(visibility) {
+            *floater |= vis < options.min_visibility;
+        }
+    }
+
+    if options.distance_factor.is_finite() && options.knn > 0 && means.len() > options.knn {
+        let distances = knn_mean_distances(means, options.knn);
+        let mut sorted = distances.clone();
+        sorted.sort_unstable_by(f32::total_cmp);
+        let max_distance = sorted[sorted.len() / 2] * options.distance_factor;
+        for (floater, dist) in floaters.iter_mut().zip(distances) {
+            *floater |= dist > max_distance;
+        }
+    }
+
+    floaters
+}
+
+/// Removes the floaters from the splats, see [`find_floaters`]. Returns the remaining splats
+/// and the number of splats removed.
+///
+/// Floaters are kept if all splats would be removed.
+pub async fn remove_floaters<B: Backend>(
+    splats: Splats<B>,
+    visibility: Option<Tensor<B, 1>>,
+    options: &FloaterOptions,
+) -> (Splats<B>, u32) {
+    let means: Vec<f32> = splats
+        .means
+        .val()
+        .into_data_async()
+        .await
+        .into_vec()
+        .expect("Failed to read means");
+    let means: Vec<Vec3> = means.chunks_exact(3).map(Vec3::from_slice).collect();
+
+    let visibility = match visibility {
+        Some(visibility) => Some(
+            visibility
+                .into_data_async()
+                .await
+                .into_vec::<f32>()
+                .expect("Failed to read visibility"),
+        ),
+        None => None,
+    };
+
+    let floaters = find_floaters(&means, visibility.as_deref(), options);
+    let keep: Vec<i32> = (0..means.len() as i32)
+        .filter(|&i| !floaters[i as usize])
+        .collect();
+    let removed = (means.len() - keep.len()) as u32;
+
+    if removed == 0 {
+        return (splats, 0);
+    }
+    if keep.is_empty() {
+        log::warn!("All splats are floaters, not removing any");
+        return (splats, 0);
+    }
+
+    let device = splats.device();
+    let inds = Tensor::<B, 1, Int>::from_data(TensorData::new(keep.clone(), [keep.len()]), &device);
+    let activation = splats.opacity_activation.0;
+    let mut kept = Splats::from_tensor_data(
+        splats.means.val().select(0, inds.clone()),
+        splats.rotation.val().select(0, inds.clone()),
+        splats.log_scales.val().select(0, inds.clone()),
+        splats.sh_coeffs.val().select(0, inds.clone()),
+        splats.raw_opacity.val().select(0, inds),
+    );
+    kept.opacity_activation.0 = activation;
+    (kept, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FloaterOptions, find_floaters, remove_floaters};
+    use brush_render::{MainBackend, gaussian_splats::Splats};
+    use burn::{
+        backend::wgpu::WgpuDevice,
+        tensor::{Tensor, TensorData},
+    };
+    use burn_cubecl::cubecl::future::block_on;
+    use glam::Vec3;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    fn scene_with_floater() -> Vec<Vec3> {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut means: Vec<_> = (0..400)
+            .map(|_| {
+                Vec3::new(
+                    rng.random_range(-1.0..1.0),
+                    rng.random_range(-1.0..1.0),
+                    rng.random_range(-1.0..1.0),
+                )
+            })
+            .collect();
+        means.push(Vec3::new(15.0, -12.0, 20.0));
+        means
+    }
+
+    #[test]
+    fn distant_splat_is_a_floater() {
+        let means = scene_with_floater();
+        let floaters = find_floaters(&means, None, &FloaterOptions::default());
+        assert!(floaters[400], "The seeded floater must be found");
+        assert!(
+            floaters[..400].iter().all(|&f| !f),
+            "The main body must survive"
+        );
+
+        // Splats that are barely visible are floaters even inside the main body.
+        let mut visibility = vec![1.0; means.len()];
+        visibility[10] = 0.0;
+        let options = FloaterOptions {
+            distance_factor: f32::INFINITY,
+            min_visibility: 0.1,
+            ..Default::default()
+        };
+        let floaters = find_floaters(&means, Some(&visibility), &options);
+        assert_eq!(floaters.iter().filter(|&&f| f).count(), 1);
+        assert!(floaters[10], "The invisible splat must be found");
+    }
+
+    #[test]
+    fn remove_seeded_floater() {
+        let device = WgpuDevice::DefaultDevice;
+        let means = scene_with_floater();
+        let num = means.len();
+        let flat: Vec<f32> = means.iter().flat_map(|m| m.to_array()).collect();
+        let splats = Splats::<MainBackend>::from_tensor_data(
+            Tensor::from_data(TensorData::new(flat, [num, 3]), &device),
+            Tensor::ones([num, 4], &device),
+            Tensor::ones([num, 3], &device) * -3.0,
+            Tensor::zeros([num, 1, 3], &device),
+            Tensor::zeros([num], &device),
+        );
+
+        let (splats, removed) = block_on(remove_floaters(splats, None, &FloaterOptions::default()));
+        assert_eq!(removed, 1);
+        assert_eq!(splats.num_splats(), num as u32 - 1);
+        let max = splats.means.val().abs().max().into_scalar();
+        assert!(max <= 1.0, "Only the floater must be removed, got {max}");
+    }
+}
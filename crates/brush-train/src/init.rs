@@ -0,0 +1,243 @@
+pub use brush_render::gaussian_splats::InitOptions;
+use brush_render::{bounding_box::BoundingBox, gaussian_splats::Splats, sh::rgb_to_sh};
+use burn::prelude::Backend;
+use glam::{Quat, Vec3};
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::index};
+
+use crate::config::TrainConfig;
+
+/// Where to place the initial splats.
+pub enum InitPoints<'a> {
+    /// Sample positions uniformly in a box, with random colors.
+    Bounds(BoundingBox),
+    /// Sample positions from a point cloud. Points without colors get random colors.
+    PointCloud {
+        positions: &'a [Vec3],
+        colors: Option<&'a [Vec3]>,
+    },
+}
+
+/// The initialization options of a training config.
+pub fn init_options(config: &TrainConfig) -> InitOptions {
+    InitOptions {
+        knn: config.init_knn,
+        scale_multiplier: config.init_scale_multiplier,
+        fallback_scale: config.init_fallback_scale,
+        opacity: config.init_opacity,
+    }
+}
+
+/// Initial splat attributes, sampled on the CPU so they only depend on the seed.
+struct InitData {
+    means: Vec<Vec3>,
+    colors: Vec<Vec3>,
+    raw_opacities: Vec<f32>,
+}
+
+fn sample_init_data(
+    seed: u64,
+    count: usize,
+    points: &InitPoints,
+    options: &InitOptions,
+) -> InitData {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let (means, colors): (Vec<_>, Vec<_>) = match points {
+        InitPoints::Bounds(bounds) => {
+            let (min, max) = (bounds.min(), bounds.max());
+            (0..count)
+                .map(|_| {
+                    let pos = Vec3::new(
+                        rng.random_range(min.x..=max.x),
+                        rng.random_range(min.y..=max.y),
+                        rng.random_range(min.z..=max.z),
+                    );
+                    (pos, random_color(&mut rng))
+                })
+                .unzip()
+        }
+        InitPoints::PointCloud { positions, colors } => {
+            // Pick distinct points, as duplicates would have no distance to their neighbours.
+            let count = count.min(positions.len());
+            let mut picked = index::sample(&mut rng, positions.len(), count).into_vec();
+            picked.sort_unstable();
+            picked
+                .into_iter()
+                .map(|i| {
+                    let color = colors.map_or_else(|| random_color(&mut rng), |c| c[i]);
+                    (positions[i], color)
+                })
+                .unzip()
+        }
+    };
+
+    let raw_opacities = options.raw_opacities(means.len(), &mut rng);
+
+    InitData {
+        means,
+        colors,
+        raw_opacities,
+    }
+}
+
+fn random_color(rng: &mut impl Rng) -> Vec3 {
+    Vec3::new(rng.random(), rng.random(), rng.random())
+}
+
+/// Create `count` initial splats, deterministically from `seed`.
+///
+/// Splats start out unrotated and isotropic, with a scale of the mean distance to their nearest
+/// neighbours, as in 3DGS. When there are fewer than `knn + 1` splats all others are used. See
+/// [`InitOptions`] for how the scale and opacity are chosen. Colors are set as the SH base color.
+///
+/// All random values are drawn on the CPU, so the same seed gives identical splats on every
+/// platform. When sampling from a point cloud with fewer than `count` points, every point becomes
+/// a splat.
+pub fn seeded_init_splats<B: Backend>(
+    seed: u64,
+    count: usize,
+    points: &InitPoints,
+    options: &InitOptions,
+    device: &B::Device,
+) -> Splats<B> {
+    let data = sample_init_data(seed, count, points, options);
+    let rotations = vec![Quat::IDENTITY; data.means.len()];
+    let log_scales = options.log_scales(&data.means);
+    let sh_coeffs: Vec<f32> = data
+        .colors
+        .iter()
+        .flat_map(|&c| rgb_to_sh(c).to_array())
+        .collect();
+
+    Splats::from_raw(
+        &data.means,
+        Some(&rotations),
+        Some(&log_scales),
+        Some(&sh_coeffs),
+        Some(&data.raw_opacities),
+        device,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InitOptions, InitPoints, sample_init_data};
+    use brush_render::bounding_box::BoundingBox;
+    use brush_render::gaussian_splats::OpacityActivation;
+    use glam::Vec3;
+
+    #[test]
+    fn same_seed_same_splats() {
+        let bounds = InitPoints::Bounds(BoundingBox::from_min_max(Vec3::splat(-1.0), Vec3::ONE));
+        let a = sample_init_data(7, 100, &bounds, &InitOptions::default());
+        let b = sample_init_data(7, 100, &bounds, &InitOptions::default());
+        let c = sample_init_data(8, 100, &bounds, &InitOptions::default());
+        assert_eq!(a.means, b.means);
+        assert_eq!(a.colors, b.colors);
+        assert_eq!(a.raw_opacities, b.raw_opacities);
+        assert_ne!(a.means, c.means);
+        assert!(
+            a.means
+                .iter()
+                .all(|m| m.cmpge(Vec3::splat(-1.0)).all() && m.cmple(Vec3::ONE).all()),
+            "Means must be inside the bounds"
+        );
+    }
+
+    #[test]
+    fn point_cloud_samples_distinct_points() {
+        let positions: Vec<Vec3> = (0..50).map(|i| Vec3::splat(i as f32)).collect();
+        let colors: Vec<Vec3> = (0..50).map(|i| Vec3::splat(i as f32 / 50.0)).collect();
+        let points = InitPoints::PointCloud {
+            positions: &positions,
+            colors: Some(&colors),
+        };
+
+        let data = sample_init_data(3, 20, &points, &InitOptions::default());
+        assert_eq!(data.means.len(), 20);
+        for (mean, color) in data.means.iter().zip(&data.colors) {
+            assert_eq!(*color, *mean / 50.0, "Colors must match their points");
+        }
+        let mut xs: Vec<_> = data.means.iter().map(|m| m.x as i32).collect();
+        xs.dedup();
+        assert_eq!(xs.len(), 20, "Points must be distinct");
+
+        // Asking for more splats than points uses every point.
+        let data = sample_init_data(3, 100, &points, &InitOptions::default());
+        assert_eq!(data.means, positions);
+    }
+
+    #[test]
+    fn knn_scales() {
+        let means = [
+            Vec3::ZERO,
+            Vec3::X,
+            Vec3::X * 3.0,
+            Vec3::X * 6.0,
+            Vec3::X * 10.0,
+        ];
+        let scales = InitOptions::default().log_scales(&means);
+        // Neighbours of the origin are at 1, 3 and 6.
+        assert!(
+            (scales[0].x - (10.0f32 / 3.0).ln()).abs() < 1e-5,
+            "Scale must be the mean neighbour distance"
+        );
+        assert_eq!(
+            scales[0],
+            Vec3::splat(scales[0].x),
+            "Scales must be isotropic"
+        );
+
+        // With fewer points than neighbours, all other points are used.
+        let scales = InitOptions::default().log_scales(&means[..2]);
+        assert!(
+            scales[0].x.abs() < 1e-5 && scales[1].x.abs() < 1e-5,
+            "Two points must be scaled by their distance"
+        );
+
+        // A single point has no neighbours to measure.
+        let scales = InitOptions::default().log_scales(&means[..1]);
+        assert!(
+            (scales[0].x - InitOptions::default().fallback_scale.ln()).abs() < 1e-6,
+            "A lone point must use the fallback scale"
+        );
+    }
+
+    #[test]
+    fn init_options_set_scale_and_opacity() {
+        let options = InitOptions {
+            knn: 1,
+            scale_multiplier: 0.5,
+            fallback_scale: 0.02,
+            opacity: Some(0.3),
+        };
+        let means = [Vec3::ZERO, Vec3::X * 2.0, Vec3::X * 5.0];
+        let scales = options.log_scales(&means);
+        // The nearest neighbour of the origin is at 2.
+        assert!(
+            (scales[0].x - 1.0f32.ln()).abs() < 1e-5,
+            "Scales must be multiplied, got {}",
+            scales[0].x.exp()
+        );
+        let scales = options.log_scales(&means[..1]);
+        assert!(
+            (scales[0].x - 0.02f32.ln()).abs() < 1e-6,
+            "A lone point must use the configured fallback scale"
+        );
+
+        let bounds = InitPoints::Bounds(BoundingBox::from_min_max(Vec3::splat(-1.0), Vec3::ONE));
+        let data = sample_init_data(7, 50, &bounds, &options);
+        for &raw in &data.raw_opacities {
+            let opacity = OpacityActivation::Sigmoid.activate_value(raw);
+            assert!(
+                (opacity - 0.3).abs() < 1e-6,
+                "Raw opacity {raw} must activate to the requested opacity, got {opacity}"
+            );
+        }
+        // The opacity doesn't change which positions are sampled.
+        assert_eq!(
+            data.means,
+            sample_init_data(7, 50, &bounds, &InitOptions::default()).means
+        );
+    }
+}
@@ -1,12 +1,20 @@
 #![recursion_limit = "256"]
 
+pub mod checkpoint;
 pub mod config;
 pub mod eval;
+pub mod floaters;
+pub mod init;
 pub mod msg;
 pub mod train;
+pub mod view_sampling;
 
 mod adam_scaled;
+mod background;
 mod multinomial;
 mod quat_vec;
 mod ssim;
 mod stats;
+
+#[cfg(test)]
+mod test_scene;
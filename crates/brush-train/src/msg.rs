@@ -16,6 +16,8 @@ pub struct TrainStepStats<B: Backend> {
     pub num_intersections: Tensor<B, 1, Int>,
     pub num_visible: Tensor<B, 1, Int>,
     pub loss: Tensor<B, 1>,
+    /// Loss of each view of the step, in the order of the batches.
+    pub view_losses: Vec<Tensor<B, 1>>,
 
     pub lr_mean: f64,
     pub lr_rotation: f64,
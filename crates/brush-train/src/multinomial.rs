@@ -1,7 +1,8 @@
-pub(crate) fn multinomial_sample(weights: &[f32], n: u32) -> Vec<i32> {
-    let mut rng = rand::rng();
+use rand::Rng;
+
+pub(crate) fn multinomial_sample(weights: &[f32], n: u32, rng: &mut impl Rng) -> Vec<i32> {
     rand::seq::index::sample_weighted(
-        &mut rng,
+        rng,
         weights.len(),
         |i| if weights[i].is_nan() { 0.0 } else { weights[i] },
         n as usize,
@@ -18,3 +19,21 @@ pub(crate) fn multinomial_sample(weights: &[f32], n: u32) -> Vec<i32> {
     .map(|x| x as i32)
     .collect()
 }
+
+/// Sample `count` values from a normal distribution with the given standard deviation.
+///
+/// This draws from `rng` on the CPU, so the same seed gives the same values on every device.
+pub(crate) fn normal_sample(count: usize, std_dev: f32, rng: &mut impl Rng) -> Vec<f32> {
+    // Box-Muller transform, which turns two uniform samples into two normal samples.
+    let mut samples = Vec::with_capacity(count + 1);
+    while samples.len() < count {
+        let u1 = 1.0 - rng.random::<f32>();
+        let u2 = rng.random::<f32>();
+        let radius = (-2.0 * u1.ln()).sqrt() * std_dev;
+        let angle = std::f32::consts::TAU * u2;
+        samples.push(radius * angle.cos());
+        samples.push(radius * angle.sin());
+    }
+    samples.truncate(count);
+    samples
+}
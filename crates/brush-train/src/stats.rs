@@ -1,5 +1,7 @@
 use brush_kernel::create_dispatch_buffer;
-use brush_render::{MainBackend, MainBackendBase};
+use std::mem::offset_of;
+
+use brush_render::{MainBackend, MainBackendBase, shaders::helpers::ProjectedSplat};
 use burn::{prelude::Backend, tensor::ops::IntTensor};
 use burn_cubecl::cubecl::{self, CubeDim, cube, prelude::*};
 use burn_fusion::client::FusionClient;
@@ -12,9 +14,17 @@ fn stats_gather_kernel(
     gs_ids: &Tensor<u32>,
     num_visible: &Tensor<u32>,
     refine_weight: &Tensor<Line<f32>>,
+    visible: &Tensor<f32>,
+    projected_splats: &Tensor<f32>,
     accum_refine_weight: &mut Tensor<f32>,
+    accum_grad_norm: &mut Tensor<f32>,
+    accum_visible_count: &mut Tensor<f32>,
+    accum_min_radius: &mut Tensor<f32>,
+    accum_max_radius: &mut Tensor<f32>,
     #[comptime] w: u32,
     #[comptime] h: u32,
+    #[comptime] projected_stride: u32,
+    #[comptime] conic_offset: u32,
 ) {
     let compact_gid = ABSOLUTE_POS_X;
     let num_vis = num_visible[0];
@@ -25,6 +35,12 @@ fn stats_gather_kernel(
 
     let global_gid = gs_ids[compact_gid];
 
+    // Projected splats can still be hidden behind others, only count those that contributed to
+    // a pixel.
+    if visible[global_gid] <= 0.0 {
+        terminate!();
+    }
+
     let mut line: Line<f32> = Line::empty(2u32);
     // Nb: Clippy reports a warning here about a useless conversion but it's wrong.
     line[0] = comptime!(w as f32 / 2.0);
@@ -34,26 +50,85 @@ fn stats_gather_kernel(
     let refine_norm =
         f32::sqrt(refine_grads[0] * refine_grads[0] + refine_grads[1] * refine_grads[1]);
     accum_refine_weight[global_gid] = f32::max(accum_refine_weight[global_gid], refine_norm);
+    accum_grad_norm[global_gid] += refine_norm;
+
+    // Radius of the splat in pixels, 3 standard deviations along the major axis. The conic is the
+    // inverse of the 2D covariance, so its smallest eigenvalue is the inverse of the largest
+    // variance.
+    let conic_base = compact_gid * projected_stride + conic_offset;
+    let a = projected_splats[conic_base];
+    let b = projected_splats[conic_base + 1];
+    let c = projected_splats[conic_base + 2];
+    let mid = 0.5 * (a + c);
+    let min_eigen = mid - f32::sqrt(f32::max(mid * mid - (a * c - b * b), 0.0));
+    let radius = 3.0 / f32::sqrt(f32::max(min_eigen, 1e-12));
+
+    accum_max_radius[global_gid] = f32::max(accum_max_radius[global_gid], radius);
+    if accum_visible_count[global_gid] == 0.0 {
+        accum_min_radius[global_gid] = radius;
+    } else {
+        accum_min_radius[global_gid] = f32::min(accum_min_radius[global_gid], radius);
+    }
+    accum_visible_count[global_gid] += 1.0;
 }
 
 pub(crate) struct RefineRecord<B: Backend> {
     // Helper tensors for accumulating the viewspace_xy gradients and the number
     // of observations per gaussian. Used in pruning and densification.
     pub refine_weight_norm: burn::tensor::Tensor<B, 1>,
+    // Sum of the viewspace xy gradient norms, and the number of views each splat was visible in.
+    pub grad_norm_sum: burn::tensor::Tensor<B, 1>,
+    pub visible_count: burn::tensor::Tensor<B, 1>,
+    // Smallest and largest projected radius in pixels of each splat, over the views it was
+    // visible in. Both are zero for splats that weren't visible.
+    pub min_radius: burn::tensor::Tensor<B, 1>,
+    pub max_radius: burn::tensor::Tensor<B, 1>,
 }
 
 impl<B: Backend> RefineRecord<B> {
     pub(crate) fn new(num_points: u32, device: &B::Device) -> Self {
+        let zeros = || burn::tensor::Tensor::<B, 1>::zeros([num_points as usize], device);
         Self {
-            refine_weight_norm: burn::tensor::Tensor::<B, 1>::zeros([num_points as usize], device),
+            refine_weight_norm: zeros(),
+            grad_norm_sum: zeros(),
+            visible_count: zeros(),
+            min_radius: zeros(),
+            max_radius: zeros(),
         }
     }
+
+    /// The mean viewspace xy gradient norm of each splat, over the views it was visible in.
+    /// Splats that weren't visible in any view have a mean of zero.
+    pub(crate) fn mean_grad_norm(&self) -> burn::tensor::Tensor<B, 1> {
+        self.grad_norm_sum.clone() / self.visible_count.clone().clamp_min(1.0)
+    }
+
+    /// Splats that were visible, but never projected larger than `min_radius` pixels, or always
+    /// projected larger than `max_radius` pixels. A threshold of zero disables that check.
+    pub(crate) fn radius_outliers(
+        &self,
+        min_radius: f32,
+        max_radius: f32,
+    ) -> burn::tensor::Tensor<B, 1, burn::tensor::Bool> {
+        // Radii are never negative, or larger than infinity.
+        let max_radius = if max_radius > 0.0 {
+            max_radius
+        } else {
+            f32::INFINITY
+        };
+        let small = self.max_radius.clone().lower_elem(min_radius);
+        let large = self.min_radius.clone().greater_elem(max_radius);
+        let visible = self.visible_count.clone().greater_elem(0.0);
+        small.bool_or(large).bool_and(visible)
+    }
 }
 
 impl RefineRecord<MainBackend> {
     pub(crate) fn gather_stats(
         &self,
         refine_weight: burn::tensor::Tensor<MainBackend, 1>,
+        visible: burn::tensor::Tensor<MainBackend, 1>,
+        projected_splats: burn::tensor::Tensor<MainBackend, 2>,
         resolution: UVec2,
         global_from_compact_gid: IntTensor<MainBackend>,
         num_visible: IntTensor<MainBackend>,
@@ -73,9 +148,30 @@ impl RefineRecord<MainBackend> {
         let refine_weight =
             client.resolve_tensor_float::<MainBackendBase>(refine_weight.into_primitive().tensor());
 
+        let visible =
+            client.resolve_tensor_float::<MainBackendBase>(visible.into_primitive().tensor());
+        let projected_splats = client
+            .resolve_tensor_float::<MainBackendBase>(projected_splats.into_primitive().tensor());
+
         let refine_accum = client.resolve_tensor_float::<MainBackendBase>(
             self.refine_weight_norm.clone().into_primitive().tensor(),
         );
+        let grad_norm_accum = client.resolve_tensor_float::<MainBackendBase>(
+            self.grad_norm_sum.clone().into_primitive().tensor(),
+        );
+        let visible_count_accum = client.resolve_tensor_float::<MainBackendBase>(
+            self.visible_count.clone().into_primitive().tensor(),
+        );
+        let min_radius_accum = client.resolve_tensor_float::<MainBackendBase>(
+            self.min_radius.clone().into_primitive().tensor(),
+        );
+        let max_radius_accum = client.resolve_tensor_float::<MainBackendBase>(
+            self.max_radius.clone().into_primitive().tensor(),
+        );
+
+        let field = |offset: usize| (offset / size_of::<f32>()) as u32;
+        let projected_stride = field(size_of::<ProjectedSplat>());
+        let conic_offset = field(offset_of!(ProjectedSplat, conic_x));
 
         const WG_SIZE: u32 = 256;
         // Execute lazily the kernel with the launch information and the given buffers. For
@@ -91,9 +187,17 @@ impl RefineRecord<MainBackend> {
             compact_gid.as_tensor_arg::<u32>(1),
             num_visible.as_tensor_arg::<u32>(1),
             refine_weight.as_tensor_arg::<f32>(2),
+            visible.as_tensor_arg::<f32>(1),
+            projected_splats.as_tensor_arg::<f32>(1),
             refine_accum.as_tensor_arg::<f32>(1),
+            grad_norm_accum.as_tensor_arg::<f32>(1),
+            visible_count_accum.as_tensor_arg::<f32>(1),
+            min_radius_accum.as_tensor_arg::<f32>(1),
+            max_radius_accum.as_tensor_arg::<f32>(1),
             w,
             h,
+            projected_stride,
+            conic_offset,
         );
     }
 }
@@ -101,7 +205,115 @@ impl RefineRecord<MainBackend> {
 impl<B: Backend> RefineRecord<B> {
     pub(crate) fn keep(self, indices: burn::tensor::Tensor<B, 1, burn::prelude::Int>) -> Self {
         Self {
-            refine_weight_norm: self.refine_weight_norm.select(0, indices),
+            refine_weight_norm: self.refine_weight_norm.select(0, indices.clone()),
+            grad_norm_sum: self.grad_norm_sum.select(0, indices.clone()),
+            visible_count: self.visible_count.select(0, indices.clone()),
+            min_radius: self.min_radius.select(0, indices.clone()),
+            max_radius: self.max_radius.select(0, indices),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::offset_of;
+
+    use super::RefineRecord;
+    use brush_render::{MainBackend, shaders::helpers::ProjectedSplat};
+    use burn::{
+        backend::wgpu::WgpuDevice,
+        tensor::{Int, Tensor, TensorData},
+    };
+
+    #[test]
+    fn accumulates_visible_grad_norms() {
+        let device = WgpuDevice::DefaultDevice;
+        let record = RefineRecord::<MainBackend>::new(4, &device);
+        let resolution = glam::uvec2(4, 2);
+
+        // Splats 2, 0 and 3 are projected, but splat 3 is hidden and splat 1 is culled.
+        let global_from_compact_gid =
+            Tensor::<MainBackend, 1, Int>::from_ints([2, 0, 3, 0], &device);
+        let num_visible = Tensor::<MainBackend, 1, Int>::from_ints([3], &device);
+        let visible = Tensor::<MainBackend, 1>::from_floats([1.0, 0.0, 1.0, 0.0], &device);
+
+        // Screen space xy gradients per compacted splat, for two steps.
+        let steps = [
+            [[0.5, 0.0], [0.0, 1.0], [1.0, 1.0], [0.0, 0.0]],
+            [[0.0, 1.5], [1.0, 0.0], [1.0, 1.0], [0.0, 0.0]],
+        ];
+        // Standard deviation in pixels of each compacted splat, for the same two steps.
+        let sigmas = [[2.0, 1.0, 1.0, 1.0], [2.0, 4.0, 1.0, 1.0]];
+        let stride = size_of::<ProjectedSplat>() / size_of::<f32>();
+        let conic_offset = offset_of!(ProjectedSplat, conic_x) / size_of::<f32>();
+
+        let mut expected_sum = [0.0f32; 4];
+        let mut expected_count = [0.0f32; 4];
+        for (grads, sigmas) in steps.into_iter().zip(sigmas) {
+            let flat: Vec<f32> = grads.iter().flatten().copied().collect();
+            // Isotropic conics, the inverse of the variance on the diagonal.
+            let mut projected = vec![0.0f32; 4 * stride];
+            for (compact_gid, sigma) in sigmas.iter().enumerate() {
+                let base = compact_gid * stride + conic_offset;
+                projected[base] = 1.0 / (sigma * sigma);
+                projected[base + 2] = 1.0 / (sigma * sigma);
+            }
+            record.gather_stats(
+                Tensor::from_data(TensorData::new(flat, [8]), &device),
+                visible.clone(),
+                Tensor::from_data(TensorData::new(projected, [4, stride]), &device),
+                resolution,
+                global_from_compact_gid.clone().into_primitive(),
+                num_visible.clone().into_primitive(),
+            );
+
+            for (compact_gid, global_gid) in [(0, 2), (1, 0)] {
+                let [x, y] = grads[compact_gid];
+                // Gradients are scaled from NDC to pixels.
+                let norm = (x * resolution.x as f32 / 2.0).hypot(y * resolution.y as f32 / 2.0);
+                expected_sum[global_gid] += norm;
+                expected_count[global_gid] += 1.0;
+            }
+        }
+
+        let read = |tensor: Tensor<MainBackend, 1>| -> Vec<f32> {
+            tensor.into_data().into_vec().expect("Wrong tensor type")
+        };
+        let sum = read(record.grad_norm_sum.clone());
+        for (sum, expected) in sum.iter().zip(expected_sum) {
+            assert!(
+                (sum - expected).abs() < 1e-5,
+                "Accumulated {sum}, expected {expected}"
+            );
+        }
+        assert_eq!(read(record.visible_count.clone()), expected_count);
+        let mean = read(record.mean_grad_norm());
+        assert!(
+            (mean[0] - expected_sum[0] / 2.0).abs() < 1e-5 && mean[1] == 0.0,
+            "Unexpected mean gradient norms {mean:?}"
+        );
+
+        // Radii are three standard deviations, over the views each splat was visible in.
+        let min_radius = read(record.min_radius.clone());
+        let max_radius = read(record.max_radius.clone());
+        for (global_gid, min, max) in [(0, 3.0, 12.0), (2, 6.0, 6.0)] {
+            assert!(
+                (min_radius[global_gid] - min).abs() < 1e-4
+                    && (max_radius[global_gid] - max).abs() < 1e-4,
+                "Splat {global_gid} has radii {min_radius:?} {max_radius:?}"
+            );
         }
+        let outliers: Vec<bool> = record
+            .radius_outliers(4.0, 10.0)
+            .into_data()
+            .into_vec()
+            .expect("Wrong tensor type");
+        assert_eq!(outliers, [false, false, false, false]);
+        let outliers: Vec<bool> = record
+            .radius_outliers(7.0, 5.0)
+            .into_data()
+            .into_vec()
+            .expect("Wrong tensor type");
+        assert_eq!(outliers, [false, false, true, false]);
     }
 }
@@ -0,0 +1,37 @@
+//! A small scene the tests of this crate train on.
+
+use brush_dataset::scene::SceneBatch;
+use brush_render::{camera::Camera, gaussian_splats::Splats};
+use burn::{
+    prelude::Backend,
+    tensor::{Distribution, Tensor},
+};
+
+/// `num_splats` small splats with random rotations and colors, spread around the origin. The
+/// splats are half transparent.
+pub(crate) fn random_splats<B: Backend>(num_splats: usize, device: &B::Device) -> Splats<B> {
+    Splats::from_tensor_data(
+        Tensor::random([num_splats, 3], Distribution::Uniform(-0.5, 0.5), device),
+        Tensor::random([num_splats, 4], Distribution::Normal(0.0, 1.0), device),
+        Tensor::ones([num_splats, 3], device) * -2.5,
+        Tensor::random([num_splats, 1, 3], Distribution::Default, device),
+        Tensor::zeros([num_splats], device),
+    )
+}
+
+/// A camera at `z` along the view axis, looking at the origin, which sees all of
+/// [`random_splats`].
+pub(crate) fn test_camera(z: f32) -> Camera {
+    Camera::look_at(glam::vec3(0.5, 0.0, z), glam::Vec3::ZERO, glam::Vec3::NEG_Y)
+}
+
+/// A view of a random 32x32 image, from [`test_camera`] at `z`.
+pub(crate) fn random_batch<B: Backend>(z: f32, device: &B::Device) -> SceneBatch<B> {
+    SceneBatch {
+        img_tensor: Tensor::random([32, 32, 3], Distribution::Default, device),
+        alpha_is_mask: false,
+        background: None,
+        camera: test_camera(z),
+        view_index: None,
+    }
+}
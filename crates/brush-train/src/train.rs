@@ -1,18 +1,23 @@
 use crate::{
     adam_scaled::{AdamScaled, AdamScaledConfig, AdamState},
-    config::TrainConfig,
+    background::{Background, composite_over},
+    checkpoint::CheckpointData,
+    config::{OptimizerKind, TrainConfig},
     msg::{RefineStats, TrainStepStats},
-    multinomial::multinomial_sample,
+    multinomial::{multinomial_sample, normal_sample},
     quat_vec::quaternion_vec_multiply,
     ssim::Ssim,
     stats::RefineRecord,
 };
 
+use anyhow::{Result, anyhow};
 use brush_dataset::scene::SceneBatch;
 use brush_render::sh::sh_coeffs_for_degree;
 use brush_render::{
     MainBackend,
-    gaussian_splats::{Splats, inverse_sigmoid},
+    gaussian_splats::{OpacityActivation, Splats},
+    render_aux::RenderAux,
+    render_options::RenderOptions,
 };
 use brush_render_bwd::burn_glue::SplatForwardDiff;
 use burn::{
@@ -24,16 +29,19 @@ use burn::{
         LrScheduler,
         exponential::{ExponentialLrScheduler, ExponentialLrSchedulerConfig},
     },
-    module::ParamId,
-    optim::{GradientsParams, Optimizer, adaptor::OptimizerAdaptor, record::AdaptorRecord},
-    prelude::Backend,
+    module::{Ignored, Module, ParamId},
+    optim::{
+        GradientsAccumulator, GradientsParams, Optimizer, adaptor::OptimizerAdaptor,
+        decay::WeightDecayConfig, record::AdaptorRecord,
+    },
+    record::{BinBytesRecorder, FullPrecisionSettings, Recorder},
     tensor::{
-        Bool, Distribution, Tensor, TensorData, TensorPrimitive, activation::sigmoid,
-        backend::AutodiffBackend, s,
+        Bool, Distribution, Tensor, TensorData, TensorPrimitive, backend::AutodiffBackend, s,
     },
 };
 use burn_cubecl::cubecl::Runtime;
 use hashbrown::{HashMap, HashSet};
+use rand::{SeedableRng, rngs::StdRng};
 use std::f64::consts::SQRT_2;
 use tracing::trace_span;
 
@@ -41,6 +49,8 @@ const MIN_OPACITY: f32 = 0.9 / 255.0;
 
 type OptimizerType =
     OptimizerAdaptor<AdamScaled, Splats<Autodiff<MainBackend>>, Autodiff<MainBackend>>;
+type BackgroundOptimizer =
+    OptimizerAdaptor<AdamScaled, Background<Autodiff<MainBackend>>, Autodiff<MainBackend>>;
 
 pub struct SplatTrainer {
     config: TrainConfig,
@@ -49,19 +59,107 @@ pub struct SplatTrainer {
     ssim: Ssim<Autodiff<MainBackend>>,
     refine_record: Option<RefineRecord<MainBackend>>,
     optim: Option<OptimizerType>,
+    background: Option<(Background<Autodiff<MainBackend>>, BackgroundOptimizer)>,
+    // Seed of the random choices of refinement, see `TrainConfig::refine_seed`.
+    refine_seed: u64,
+}
+
+fn create_optimizer(config: &TrainConfig) -> OptimizerType {
+    let adam = AdamScaledConfig::new()
+        .with_beta_1(config.adam_beta_1)
+        .with_beta_2(config.adam_beta_2)
+        .with_epsilon(config.adam_epsilon);
+    let decay = (config.weight_decay > 0.0).then_some(config.weight_decay);
+    match config.optimizer {
+        OptimizerKind::AdamW => adam.with_decoupled_weight_decay(decay),
+        OptimizerKind::Adam | OptimizerKind::SparseAdam => {
+            adam.with_weight_decay(decay.map(WeightDecayConfig::new))
+        }
+    }
+    .init()
+}
+
+/// Only update the splats that are `visible` in the next optimizer step, for
+/// [`OptimizerKind::SparseAdam`].
+fn with_visible_rows(
+    optimizer: OptimizerType,
+    splats: &Splats<Autodiff<MainBackend>>,
+    visible: Tensor<MainBackend, 1>,
+) -> OptimizerType {
+    let mut record = optimizer.to_record();
+    let visible = visible.greater_elem(0.0).float();
+    let rows = visible.clone().unsqueeze_dim::<2>(1);
+    set_visible(
+        &mut record,
+        splats.sh_coeffs.id,
+        visible.clone().reshape([-1, 1, 1]),
+    );
+    set_visible(&mut record, splats.rotation.id, rows.clone());
+    set_visible(&mut record, splats.log_scales.id, rows.clone());
+    set_visible(&mut record, splats.means.id, rows);
+    set_visible(&mut record, splats.raw_opacity.id, visible);
+    optimizer.load_record(record)
+}
+
+fn set_visible<const D: usize>(
+    record: &mut HashMap<ParamId, AdaptorRecord<AdamScaled, Autodiff<MainBackend>>>,
+    param_id: ParamId,
+    visible: Tensor<MainBackend, D>,
+) {
+    let mut state: AdamState<MainBackend, D> = record
+        .remove(&param_id)
+        .map(AdaptorRecord::into_state)
+        .unwrap_or_default();
+    state.visible = Some(visible);
+    record.insert(param_id, AdaptorRecord::from_state(state));
+}
+
+/// Move the gradient of a single parameter into its own [`GradientsParams`], so it can be
+/// stepped with its own learning rate.
+fn split_grad<const D: usize>(grads: &mut GradientsParams, id: ParamId) -> GradientsParams {
+    let mut split = GradientsParams::new();
+    if let Some(grad) = grads.remove::<MainBackend, D>(id) {
+        split.register(id, grad);
+    }
+    split
+}
+
+/// The rendered view and loss of a single training view.
+struct ViewLoss {
+    loss: Tensor<Autodiff<MainBackend>, 1>,
+    pred_image: Tensor<Autodiff<MainBackend>, 3>,
+    aux: RenderAux<Autodiff<MainBackend>>,
+    refine_weight_holder: Tensor<Autodiff<MainBackend>, 1>,
+    visible: Tensor<Autodiff<MainBackend>, 1>,
+    img_size: glam::UVec2,
 }
 
-fn inv_sigmoid<B: Backend>(x: Tensor<B, 1>) -> Tensor<B, 1> {
-    (x.clone() / (1.0f32 - x)).log()
+/// What's kept of a view after its backward pass.
+struct ViewGrads {
+    loss: Tensor<MainBackend, 1>,
+    pred_image: Tensor<MainBackend, 3>,
+    aux: RenderAux<Autodiff<MainBackend>>,
+    refine_weight: Tensor<MainBackend, 1>,
+    visible: Tensor<MainBackend, 1>,
+    img_size: glam::UVec2,
 }
 
-fn create_default_optimizer() -> OptimizerType {
-    AdamScaledConfig::new().with_epsilon(1e-15).init()
+/// Gradients summed over the views of a step.
+struct AccumulatedGrads {
+    grads: GradientsParams,
+    background_grads: Option<GradientsParams>,
+    loss: Tensor<MainBackend, 1>,
+    views: Vec<ViewGrads>,
 }
 
 impl SplatTrainer {
     pub fn new(config: &TrainConfig, device: &WgpuDevice) -> Self {
-        let ssim = Ssim::new(config.ssim_window_size, 3, device);
+        assert!(
+            !(config.grayscale && config.learn_background),
+            "A background color can't be learned when training on grayscale images."
+        );
+        let color_channels = if config.grayscale { 1 } else { 3 };
+        let ssim = Ssim::new(config.ssim_window_size, color_channels, device);
 
         let decay = (config.lr_mean_end / config.lr_mean).powf(1.0 / config.total_steps as f64);
         let lr_mean = ExponentialLrSchedulerConfig::new(config.lr_mean, decay);
@@ -69,6 +167,9 @@ impl SplatTrainer {
         let decay = (config.lr_scale_end / config.lr_scale).powf(1.0 / config.total_steps as f64);
         let lr_scale = ExponentialLrSchedulerConfig::new(config.lr_scale, decay);
 
+        // Without a seed refinement is still random, but differs between runs.
+        let refine_seed = config.refine_seed.unwrap_or_else(rand::random);
+
         Self {
             config: config.clone(),
             sched_mean: lr_mean.init().expect("Mean lr schedule must be valid."),
@@ -76,9 +177,25 @@ impl SplatTrainer {
             optim: None,
             refine_record: None,
             ssim,
+            background: config.learn_background.then(|| {
+                let background = Background::new(glam::Vec3::ZERO, device);
+                (
+                    background,
+                    AdamScaledConfig::new().with_epsilon(1e-15).init(),
+                )
+            }),
+            refine_seed,
         }
     }
 
+    /// The learned background color, if `learn_background` is enabled.
+    pub fn background(&self) -> Option<Tensor<MainBackend, 1>> {
+        self.background
+            .as_ref()
+            .map(|(background, _)| background.color.val().inner())
+    }
+
+    /// Take a training step on a single view. See [`Self::step_batches`].
     pub fn step(
         &mut self,
         scene_extent: f32,
@@ -86,71 +203,31 @@ impl SplatTrainer {
         batch: &SceneBatch<Autodiff<MainBackend>>,
         splats: Splats<Autodiff<MainBackend>>,
     ) -> (Splats<Autodiff<MainBackend>>, TrainStepStats<MainBackend>) {
-        let mut splats = splats;
-
-        let [img_h, img_w, _] = batch.img_tensor.dims();
-        let camera = &batch.camera;
+        self.step_batches(scene_extent, iter, std::slice::from_ref(batch), splats)
+    }
 
+    /// Take a training step on multiple views, accumulating their gradients.
+    ///
+    /// Each view is rendered and backpropagated on its own, so only one view is in memory at a
+    /// time. The loss is averaged over the views, so the learning rates mean the same as when
+    /// training on single views, and the optimizer takes a single step per call. The learning
+    /// rate schedules and `total_steps` count calls to this, not views.
+    ///
+    /// The returned stats are of the last view, except the loss, which is the averaged loss, and
+    /// the loss of each view.
+    pub fn step_batches(
+        &mut self,
+        scene_extent: f32,
+        iter: u32,
+        batches: &[SceneBatch<Autodiff<MainBackend>>],
+        splats: Splats<Autodiff<MainBackend>>,
+    ) -> (Splats<Autodiff<MainBackend>>, TrainStepStats<MainBackend>) {
+        let mut splats = splats;
         let current_opacity = splats.opacities();
-        let (pred_image, aux, refine_weight_holder) = {
-            let diff_out = <Autodiff<MainBackend> as SplatForwardDiff<_>>::render_splats(
-                camera,
-                glam::uvec2(img_w as u32, img_h as u32),
-                splats.means.val().into_primitive().tensor(),
-                splats.log_scales.val().into_primitive().tensor(),
-                splats.rotation.val().into_primitive().tensor(),
-                splats.sh_coeffs.val().into_primitive().tensor(),
-                current_opacity.clone().into_primitive().tensor(),
-            );
-            let img = Tensor::from_primitive(TensorPrimitive::Float(diff_out.img));
-            (img, diff_out.aux, diff_out.refine_weight_holder)
-        };
-
         let train_t = (iter as f32 / self.config.total_steps as f32).clamp(0.0, 1.0);
 
-        let _span = trace_span!("Calculate losses", sync_burn = true).entered();
-
-        let pred_rgb = pred_image.clone().slice(s![.., .., 0..3]);
-        let gt_rgb = batch.img_tensor.clone().slice(s![.., .., 0..3]);
-
-        let l1_rgb = (pred_rgb.clone() - gt_rgb).abs();
-
-        let total_err = if self.config.ssim_weight > 0.0 {
-            let gt_rgb = batch.img_tensor.clone().slice(s![.., .., 0..3]);
-            let ssim_err = self.ssim.ssim(pred_rgb, gt_rgb);
-            l1_rgb * (1.0 - self.config.ssim_weight) - (ssim_err * self.config.ssim_weight)
-        } else {
-            l1_rgb
-        };
-
-        let loss = if batch.has_alpha() {
-            let alpha_input = batch.img_tensor.clone().slice(s![.., .., 3..4]);
-
-            if batch.alpha_is_mask {
-                (total_err * alpha_input).mean()
-            } else {
-                let pred_alpha = pred_image.clone().slice(s![.., .., 3..4]);
-                total_err.mean()
-                    + (alpha_input - pred_alpha).abs().mean() * self.config.match_alpha_weight
-            }
-        } else {
-            total_err.mean()
-        };
-
-        let opac_loss_weight = self.config.opac_loss_weight;
-        let visible: Tensor<_, 1> =
-            Tensor::from_primitive(TensorPrimitive::Float(aux.visible.clone()));
-
-        let loss = if opac_loss_weight > 0.0 {
-            // Invisible splats still have a tiny bit of loss. Otherwise,
-            // they would never die off.
-            let visible = visible.clone() + 1e-3;
-            loss + (current_opacity.clone() * visible).sum() * (opac_loss_weight * (1.0 - train_t))
-        } else {
-            loss
-        };
-
-        let mut grads = trace_span!("Backward pass", sync_burn = true).in_scope(|| loss.backward());
+        let accumulated = self.accumulate_grads(iter, batches, &splats, &current_opacity);
+        let mut grads = accumulated.grads;
 
         let (lr_mean, lr_rotation, lr_scale, lr_coeffs, lr_opac) = (
             self.sched_mean.step() * scene_extent as f64,
@@ -162,7 +239,15 @@ impl SplatTrainer {
             self.config.lr_opac,
         );
 
-        let optimizer = self.optim.get_or_insert_with(|| {
+        // Splats visible in any of the views.
+        let visible = accumulated
+            .views
+            .iter()
+            .map(|view| view.visible.clone())
+            .reduce(|a, b| a.max_pair(b))
+            .expect("Need at least one view to train on");
+
+        let mut optimizer = self.optim.take().unwrap_or_else(|| {
             let sh_degree = splats.sh_degree();
             let device = splats.device();
 
@@ -175,64 +260,88 @@ impl SplatTrainer {
             let sh_lr_scales = Tensor::<_, 1>::from_floats(sh_lr_scales.as_slice(), &device)
                 .reshape([1, coeff_count, 1]);
 
-            create_default_optimizer().load_record(HashMap::from([(
+            create_optimizer(&self.config).load_record(HashMap::from([(
                 splats.sh_coeffs.id,
                 AdaptorRecord::from_state(AdamState {
-                    momentum: None,
                     scaling: Some(sh_lr_scales),
+                    ..Default::default()
                 }),
             )]))
         });
+        if self.config.optimizer == OptimizerKind::SparseAdam {
+            optimizer = with_visible_rows(optimizer, &splats, visible.clone());
+        }
 
         splats = trace_span!("Optimizer step", sync_burn = true).in_scope(|| {
             splats = trace_span!("SH Coeffs step", sync_burn = true).in_scope(|| {
-                let grad_coeff =
-                    GradientsParams::from_params(&mut grads, &splats, &[splats.sh_coeffs.id]);
+                let grad_coeff = split_grad::<3>(&mut grads, splats.sh_coeffs.id);
                 optimizer.step(lr_coeffs, splats, grad_coeff)
             });
             splats = trace_span!("Rotation step", sync_burn = true).in_scope(|| {
-                let grad_rot =
-                    GradientsParams::from_params(&mut grads, &splats, &[splats.rotation.id]);
+                let grad_rot = split_grad::<2>(&mut grads, splats.rotation.id);
                 optimizer.step(lr_rotation, splats, grad_rot)
             });
             splats = trace_span!("Scale step", sync_burn = true).in_scope(|| {
-                let grad_scale =
-                    GradientsParams::from_params(&mut grads, &splats, &[splats.log_scales.id]);
+                let grad_scale = split_grad::<2>(&mut grads, splats.log_scales.id);
                 optimizer.step(lr_scale, splats, grad_scale)
             });
             splats = trace_span!("Mean step", sync_burn = true).in_scope(|| {
-                let grad_means =
-                    GradientsParams::from_params(&mut grads, &splats, &[splats.means.id]);
+                let grad_means = split_grad::<2>(&mut grads, splats.means.id);
                 optimizer.step(lr_mean, splats, grad_means)
             });
             splats = trace_span!("Opacity step", sync_burn = true).in_scope(|| {
-                let grad_opac =
-                    GradientsParams::from_params(&mut grads, &splats, &[splats.raw_opacity.id]);
+                let grad_opac = split_grad::<1>(&mut grads, splats.raw_opacity.id);
                 optimizer.step(lr_opac, splats, grad_opac)
             });
             splats
         });
+        self.optim = Some(optimizer);
+
+        if let Some((background, mut optim)) = self.background.take() {
+            let grad_bg = accumulated
+                .background_grads
+                .expect("Background gradients must be accumulated when learning the background");
+            let mut background = optim.step(self.config.lr_background, background, grad_bg);
+            background.color = background
+                .color
+                .map(|c| Tensor::from_inner(c.inner().clamp(0.0, 1.0)).require_grad());
+            self.background = Some((background, optim));
+        }
 
         let _housekeep = trace_span!("Housekeeping", sync_burn = true);
-        // Get the xy gradient norm from the dummy tensor.
-        let refine_weight = refine_weight_holder
-            .grad_remove(&mut grads)
-            .expect("XY gradients need to be calculated.");
-
         let device = splats.device();
         let num_splats = splats.num_splats();
         let record = self
             .refine_record
             .get_or_insert_with(|| RefineRecord::new(num_splats, &device));
 
-        record.gather_stats(
-            refine_weight,
-            glam::uvec2(img_w as u32, img_h as u32),
-            aux.global_from_compact_gid.clone(),
-            aux.num_visible().into_primitive(),
-        );
+        for view in &accumulated.views {
+            record.gather_stats(
+                view.refine_weight.clone(),
+                view.visible.clone(),
+                Tensor::<Autodiff<MainBackend>, 2>::from_primitive(TensorPrimitive::Float(
+                    view.aux.projected_splats.clone(),
+                ))
+                .inner(),
+                view.img_size,
+                view.aux.global_from_compact_gid.clone(),
+                view.aux.num_visible().into_primitive(),
+            );
+        }
         drop(_housekeep);
 
+        let last_view = accumulated
+            .views
+            .last()
+            .expect("Need at least one view to train on");
+        let (pred_image, aux) = (last_view.pred_image.clone(), last_view.aux.clone());
+        let loss = accumulated.loss;
+        let view_losses = accumulated
+            .views
+            .iter()
+            .map(|view| view.loss.clone())
+            .collect();
+
         let mean_noise_weight_scale = self.config.mean_noise_weight * (1.0 - train_t);
 
         if mean_noise_weight_scale > 0.0 {
@@ -244,7 +353,7 @@ impl SplatTrainer {
             let noise_weight = (one - current_opacity.inner())
                 .powi_scalar(100)
                 .clamp(0.0, 1.0);
-            let noise_weight = noise_weight * visible.inner(); // Only noise visible gaussians.
+            let noise_weight = noise_weight * visible; // Only noise visible gaussians.
             let noise_weight = noise_weight.unsqueeze_dim(1);
 
             let samples = quaternion_vec_multiply(
@@ -263,10 +372,11 @@ impl SplatTrainer {
         }
 
         let stats = TrainStepStats {
-            pred_image: pred_image.inner(),
+            pred_image,
             num_visible: aux.num_visible().inner(),
             num_intersections: aux.num_intersections().inner(),
-            loss: loss.inner(),
+            loss,
+            view_losses,
             lr_mean,
             lr_rotation,
             lr_scale,
@@ -277,6 +387,359 @@ impl SplatTrainer {
         (splats, stats)
     }
 
+    /// Serialize the full training state after `iter` steps, to resume training later.
+    ///
+    /// This includes the splats, optimizer moments, learning rate schedules, refinement
+    /// statistics and seed, and learned background. Tensors are stored at full precision, so
+    /// resuming continues with bit-identical state, and refines make the same random choices. See
+    /// [`Self::load_checkpoint`].
+    ///
+    /// The order the training views are loaded in isn't part of the trainer, so isn't stored: a
+    /// resumed run trains on the views in a different order than the original run would have.
+    /// The random noise added to the means isn't resumed either.
+    pub fn save_checkpoint(
+        &self,
+        iter: u32,
+        splats: &Splats<Autodiff<MainBackend>>,
+    ) -> Result<Vec<u8>> {
+        let recorder = BinBytesRecorder::<FullPrecisionSettings>::default();
+
+        // Missing state is stored as an empty section.
+        let sections = vec![
+            recorder.record(splats.clone().into_record(), ())?,
+            self.optim
+                .as_ref()
+                .map(|optim| recorder.record(optim.to_record(), ()))
+                .transpose()?
+                .unwrap_or_default(),
+            self.sched_mean
+                .to_record::<MainBackend>()
+                .to_le_bytes()
+                .to_vec(),
+            self.sched_scale
+                .to_record::<MainBackend>()
+                .to_le_bytes()
+                .to_vec(),
+            self.refine_record
+                .as_ref()
+                .map(|record| recorder.record(record.refine_weight_norm.clone(), ()))
+                .transpose()?
+                .unwrap_or_default(),
+            self.refine_record
+                .as_ref()
+                .map(|record| recorder.record(record.grad_norm_sum.clone(), ()))
+                .transpose()?
+                .unwrap_or_default(),
+            self.refine_record
+                .as_ref()
+                .map(|record| recorder.record(record.visible_count.clone(), ()))
+                .transpose()?
+                .unwrap_or_default(),
+            self.refine_record
+                .as_ref()
+                .map(|record| recorder.record(record.min_radius.clone(), ()))
+                .transpose()?
+                .unwrap_or_default(),
+            self.refine_record
+                .as_ref()
+                .map(|record| recorder.record(record.max_radius.clone(), ()))
+                .transpose()?
+                .unwrap_or_default(),
+            self.background
+                .as_ref()
+                .map(|(background, _)| recorder.record(background.clone().into_record(), ()))
+                .transpose()?
+                .unwrap_or_default(),
+            self.background
+                .as_ref()
+                .map(|(_, optim)| recorder.record(optim.to_record(), ()))
+                .transpose()?
+                .unwrap_or_default(),
+            vec![match splats.opacity_activation.0 {
+                OpacityActivation::Sigmoid => 0,
+                OpacityActivation::ExpDensity => 1,
+            }],
+            self.refine_seed.to_le_bytes().to_vec(),
+        ];
+
+        Ok(CheckpointData {
+            iter,
+            active_sh_degree: self.config.active_sh_degree(iter, splats.sh_degree()),
+            sections,
+        }
+        .encode())
+    }
+
+    /// Restore the training state from a checkpoint made by [`Self::save_checkpoint`].
+    ///
+    /// Returns the number of steps taken and the splats, so training continues at that step.
+    /// The trainer should be created with the same config as when the checkpoint was saved.
+    pub fn load_checkpoint(
+        &mut self,
+        bytes: &[u8],
+        device: &WgpuDevice,
+    ) -> Result<(u32, Splats<Autodiff<MainBackend>>)> {
+        let data = CheckpointData::decode(bytes)?;
+        let [
+            splats,
+            optim,
+            lr_mean,
+            lr_scale,
+            refine,
+            refine_grad_sum,
+            refine_visible_count,
+            refine_min_radius,
+            refine_max_radius,
+            background,
+            background_optim,
+            opacity_activation,
+            refine_seed,
+        ]: [Vec<u8>; 13] = data
+            .sections
+            .try_into()
+            .map_err(|_| anyhow!("Unexpected number of checkpoint sections"))?;
+        let recorder = BinBytesRecorder::<FullPrecisionSettings>::default();
+
+        // Any splats can be used as a template, the record replaces all their tensors.
+        let mut splats = Splats::from_raw(&[glam::Vec3::ZERO], None, None, None, None, device)
+            .load_record(recorder.load(splats, device)?);
+        // The raw opacities are already stored for this activation, so don't convert them.
+        splats.opacity_activation = Ignored(match opacity_activation.as_slice() {
+            [0] => OpacityActivation::Sigmoid,
+            [1] => OpacityActivation::ExpDensity,
+            _ => return Err(anyhow!("Unknown opacity activation in checkpoint")),
+        });
+
+        self.optim = if optim.is_empty() {
+            None
+        } else {
+            Some(create_optimizer(&self.config).load_record(recorder.load(optim, device)?))
+        };
+        self.sched_mean = self
+            .sched_mean
+            .clone()
+            .load_record::<MainBackend>(f64::from_le_bytes(lr_mean.as_slice().try_into()?));
+        self.sched_scale = self
+            .sched_scale
+            .clone()
+            .load_record::<MainBackend>(f64::from_le_bytes(lr_scale.as_slice().try_into()?));
+        self.refine_seed = u64::from_le_bytes(refine_seed.as_slice().try_into()?);
+        self.refine_record = if refine.is_empty() {
+            None
+        } else {
+            Some(RefineRecord {
+                refine_weight_norm: recorder.load(refine, device)?,
+                grad_norm_sum: recorder.load(refine_grad_sum, device)?,
+                visible_count: recorder.load(refine_visible_count, device)?,
+                min_radius: recorder.load(refine_min_radius, device)?,
+                max_radius: recorder.load(refine_max_radius, device)?,
+            })
+        };
+
+        // A background is only restored when it is still being learned.
+        if let Some((background_module, optim)) = self.background.take() {
+            self.background = Some(if background.is_empty() {
+                (background_module, optim)
+            } else {
+                (
+                    background_module.load_record(recorder.load(background, device)?),
+                    optim.load_record(recorder.load(background_optim, device)?),
+                )
+            });
+        }
+
+        let active_sh_degree = self.config.active_sh_degree(data.iter, splats.sh_degree());
+        if active_sh_degree != data.active_sh_degree {
+            log::warn!(
+                "Checkpoint was trained with SH degree {}, but resumes with degree {active_sh_degree}",
+                data.active_sh_degree
+            );
+        }
+
+        Ok((data.iter, splats))
+    }
+
+    /// Render a view and calculate its loss.
+    fn view_loss(
+        &self,
+        iter: u32,
+        batch: &SceneBatch<Autodiff<MainBackend>>,
+        splats: &Splats<Autodiff<MainBackend>>,
+        current_opacity: Tensor<Autodiff<MainBackend>, 1>,
+    ) -> ViewLoss {
+        let [img_h, img_w, _] = batch.img_tensor.dims();
+        let img_size = glam::uvec2(img_w as u32, img_h as u32);
+        let camera = &batch.camera;
+
+        let options = RenderOptions {
+            max_sh_degree: Some(self.config.active_sh_degree(iter, splats.sh_degree())),
+            subpixel_offset: self.config.subpixel_offset(iter),
+            grayscale: self.config.grayscale,
+            opacity_activation: Some(splats.opacity_activation.0),
+            ..Default::default()
+        };
+        let (pred_image, aux, refine_weight_holder) = {
+            let diff_out = <Autodiff<MainBackend> as SplatForwardDiff<_>>::render_splats(
+                camera,
+                img_size,
+                splats.means.val().into_primitive().tensor(),
+                splats.log_scales.val().into_primitive().tensor(),
+                splats.rotation.val().into_primitive().tensor(),
+                splats.sh_coeffs.val().into_primitive().tensor(),
+                splats.raw_opacity.val().into_primitive().tensor(),
+                &options,
+            );
+            let img = Tensor::from_primitive(TensorPrimitive::Float(diff_out.img));
+            (img, diff_out.aux, diff_out.refine_weight_holder)
+        };
+
+        let train_t = (iter as f32 / self.config.total_steps as f32).clamp(0.0, 1.0);
+
+        let _span = trace_span!("Calculate losses", sync_burn = true).entered();
+
+        // The losses are calculated in sRGB space: the dataset images are sRGB encoded, and splats
+        // are rendered with `ColorSpace::Srgb`. Grayscale images have a single color channel.
+        let channels = if self.config.grayscale { 1 } else { 3 };
+        let pred_rgb = if let Some(backplate) = &batch.background {
+            // A known background takes precedence over the learned background color.
+            composite_over(pred_image.clone(), backplate.clone())
+        } else if let Some((background, _)) = &self.background {
+            background.composite(pred_image.clone())
+        } else {
+            pred_image.clone().slice(s![.., .., 0..channels])
+        };
+        let gt_rgb = batch.img_tensor.clone().slice(s![.., .., 0..channels]);
+
+        let l1_rgb = (pred_rgb.clone() - gt_rgb).abs();
+
+        let total_err = if self.config.ssim_weight > 0.0 {
+            let gt_rgb = batch.img_tensor.clone().slice(s![.., .., 0..channels]);
+            let ssim_err = self.ssim.ssim(pred_rgb, gt_rgb);
+            l1_rgb * (1.0 - self.config.ssim_weight) - (ssim_err * self.config.ssim_weight)
+        } else {
+            l1_rgb
+        };
+
+        let loss = if batch.has_alpha() {
+            let alpha_input = batch
+                .img_tensor
+                .clone()
+                .slice(s![.., .., channels..channels + 1]);
+
+            if batch.alpha_is_mask {
+                (total_err * alpha_input).mean()
+            } else {
+                let pred_alpha = pred_image.clone().slice(s![.., .., channels..channels + 1]);
+                total_err.mean()
+                    + (alpha_input - pred_alpha).abs().mean() * self.config.match_alpha_weight
+            }
+        } else {
+            total_err.mean()
+        };
+
+        let opac_loss_weight = self.config.opac_loss_weight;
+        let visible: Tensor<_, 1> =
+            Tensor::from_primitive(TensorPrimitive::Float(aux.visible.clone()));
+
+        let loss = if opac_loss_weight > 0.0 {
+            // Invisible splats still have a tiny bit of loss. Otherwise,
+            // they would never die off.
+            let visible = visible.clone() + 1e-3;
+            loss + (current_opacity * visible).sum() * (opac_loss_weight * (1.0 - train_t))
+        } else {
+            loss
+        };
+
+        ViewLoss {
+            loss,
+            pred_image,
+            aux,
+            refine_weight_holder,
+            visible,
+            img_size,
+        }
+    }
+
+    /// Render each view and backpropagate its loss, summing the gradients of all views.
+    fn accumulate_grads(
+        &self,
+        iter: u32,
+        batches: &[SceneBatch<Autodiff<MainBackend>>],
+        splats: &Splats<Autodiff<MainBackend>>,
+        current_opacity: &Tensor<Autodiff<MainBackend>, 1>,
+    ) -> AccumulatedGrads {
+        assert!(!batches.is_empty(), "Need at least one view to train on");
+        let num_views = batches.len() as f32;
+        let param_ids = [
+            splats.sh_coeffs.id,
+            splats.rotation.id,
+            splats.log_scales.id,
+            splats.means.id,
+            splats.raw_opacity.id,
+        ];
+
+        let mut accumulator = GradientsAccumulator::new();
+        let mut background_accumulator = GradientsAccumulator::new();
+        let mut total_loss: Option<Tensor<MainBackend, 1>> = None;
+        let mut views = Vec::with_capacity(batches.len());
+
+        // Views are trained at the resolution of the schedule, with the same splats.
+        let resolution_factor = self.config.resolution_factor(iter);
+
+        for batch in batches {
+            let batch = &batch.downsample(resolution_factor);
+            let view = self.view_loss(iter, batch, splats, current_opacity.clone());
+
+            // Average the loss over the views, which sums to the same gradients as a batch.
+            let loss = view.loss / num_views;
+            let mut grads =
+                trace_span!("Backward pass", sync_burn = true).in_scope(|| loss.backward());
+
+            accumulator.accumulate(
+                splats,
+                GradientsParams::from_params(&mut grads, splats, &param_ids),
+            );
+            if let Some((background, _)) = &self.background {
+                background_accumulator.accumulate(
+                    background,
+                    GradientsParams::from_params(&mut grads, background, &[background.color.id]),
+                );
+            }
+
+            // Get the xy gradient norm from the dummy tensor. Undo the averaging, so refinement
+            // sees the same gradients as when training on single views.
+            let refine_weight = view
+                .refine_weight_holder
+                .grad_remove(&mut grads)
+                .expect("XY gradients need to be calculated.")
+                * num_views;
+
+            let loss = loss.inner();
+            total_loss = Some(match total_loss {
+                Some(total) => total + loss.clone(),
+                None => loss.clone(),
+            });
+            views.push(ViewGrads {
+                loss: loss * num_views,
+                pred_image: view.pred_image.inner(),
+                aux: view.aux,
+                refine_weight,
+                visible: view.visible.inner(),
+                img_size: view.img_size,
+            });
+        }
+
+        AccumulatedGrads {
+            grads: accumulator.grads(),
+            background_grads: self
+                .background
+                .is_some()
+                .then(|| background_accumulator.grads()),
+            loss: total_loss.expect("Need at least one view to train on"),
+            views,
+        }
+    }
+
     pub async fn refine_if_needed(
         &mut self,
         iter: u32,
@@ -290,6 +753,10 @@ impl SplatTrainer {
         let client = WgpuRuntime::client(&device);
         client.memory_cleanup();
 
+        // Each refine draws from its own generator, so the choices of a refine only depend on the
+        // seed and the step, and resuming from a checkpoint makes the same choices.
+        let mut rng = StdRng::seed_from_u64(self.refine_seed ^ u64::from(iter));
+
         // If not refining, update splat to step with gradients applied.
         // Prune dead splats. This ALWAYS happen even if we're not "refining" anymore.
         let mut record = self
@@ -301,14 +768,18 @@ impl SplatTrainer {
             .refine_record
             .take()
             .expect("Can only refine if refine stats are initialized");
+        let opacity_activation = splats.opacity_activation.0;
         let alpha_mask = splats
             .raw_opacity
             .val()
             .inner()
-            .lower_elem(inverse_sigmoid(MIN_OPACITY));
+            .lower_elem(opacity_activation.inverse_value(MIN_OPACITY));
+        let prune_mask = alpha_mask.bool_or(
+            refiner.radius_outliers(self.config.prune_min_radius, self.config.prune_max_radius),
+        );
 
         let (mut splats, refiner, pruned_count) =
-            prune_points(splats, &mut record, refiner, alpha_mask).await;
+            prune_points(splats, &mut record, refiner, prune_mask).await;
         let mut add_indices = HashSet::new();
 
         // Replace dead gaussians if we're still refining.
@@ -320,13 +791,17 @@ impl SplatTrainer {
                 .await
                 .to_vec::<f32>()
                 .expect("Failed to read weights");
-            let resampled_inds = multinomial_sample(&resampled_weights, pruned_count);
+            let resampled_inds = multinomial_sample(&resampled_weights, pruned_count, &mut rng);
             add_indices.extend(resampled_inds);
         }
 
         if iter < self.config.growth_stop_iter {
-            let above_threshold = refiner
-                .refine_weight_norm
+            let grad_norm = if self.config.growth_mean_grad {
+                refiner.mean_grad_norm()
+            } else {
+                refiner.refine_weight_norm
+            };
+            let above_threshold = grad_norm
                 .clone()
                 .greater_elem(self.config.growth_grad_threshold)
                 .int();
@@ -343,24 +818,25 @@ impl SplatTrainer {
 
             // If still growing, sample from indices which are over the threshold.
             if grow_count > 0 {
-                let weights = above_threshold.float() * refiner.refine_weight_norm;
+                let weights = above_threshold.float() * grad_norm;
                 let weights = weights
                     .into_data_async()
                     .await
                     .to_vec::<f32>()
                     .expect("Failed to read weights");
-                let growth_inds = multinomial_sample(&weights, grow_count);
+                let growth_inds = multinomial_sample(&weights, grow_count, &mut rng);
                 add_indices.extend(growth_inds);
             }
         }
 
+        // Sets iterate in an arbitrary order, which would change which splat gets which sample.
+        let mut add_indices: Vec<i32> = add_indices.into_iter().collect();
+        add_indices.sort_unstable();
         let refine_count = add_indices.len();
 
         if refine_count > 0 {
-            let refine_inds = Tensor::from_data(
-                TensorData::new(add_indices.into_iter().collect(), [refine_count]),
-                &device,
-            );
+            let refine_inds =
+                Tensor::from_data(TensorData::new(add_indices, [refine_count]), &device);
 
             let cur_means = splats.means.val().inner().select(0, refine_inds.clone());
             let cur_rots = splats
@@ -389,17 +865,22 @@ impl SplatTrainer {
             let scale_div = Tensor::ones_like(&cur_log_scale) * SQRT_2.ln();
 
             let one = Tensor::ones([1], &device);
-            let cur_opac = sigmoid(cur_raw_opac.clone());
+            let cur_opac = opacity_activation.activate(cur_raw_opac.clone());
             let new_opac = one.clone() - (one - cur_opac).sqrt();
-            let new_raw_opac = inv_sigmoid(new_opac.clamp(1e-24, 1.0 - 1e-24));
+            let new_raw_opac = opacity_activation.inverse(new_opac.clamp(1e-24, 1.0 - 1e-24));
 
             // Scatter needs [N, 3] indices for means and scales.
             let refine_inds_2d = refine_inds.clone().unsqueeze_dim(1).repeat_dim(1, 3);
 
             let samples = quaternion_vec_multiply(
                 cur_rots.clone(),
-                Tensor::random([refine_count, 3], Distribution::Normal(0.0, 0.5), &device)
-                    * cur_log_scale.clone().exp(),
+                Tensor::from_data(
+                    TensorData::new(
+                        normal_sample(refine_count * 3, 0.5, &mut rng),
+                        [refine_count, 3],
+                    ),
+                    &device,
+                ) * cur_log_scale.clone().exp(),
             );
 
             // Shrink & offset existing splats.
@@ -422,7 +903,7 @@ impl SplatTrainer {
             });
 
             // Concatenate new splats.
-            let sh_dim = splats.sh_coeffs.dims()[1];
+            let [_, sh_dim, sh_channels] = splats.sh_coeffs.dims();
             splats = map_splats_and_opt(
                 splats,
                 &mut record,
@@ -436,7 +917,10 @@ impl SplatTrainer {
                 |x| Tensor::cat(vec![x, Tensor::zeros([refine_count, 3], &device)], 0),
                 |x| {
                     Tensor::cat(
-                        vec![x, Tensor::zeros([refine_count, sh_dim, 3], &device)],
+                        vec![
+                            x,
+                            Tensor::zeros([refine_count, sh_dim, sh_channels], &device),
+                        ],
                         0,
                     )
                 },
@@ -444,7 +928,7 @@ impl SplatTrainer {
             );
         }
 
-        self.optim = Some(create_default_optimizer().load_record(record));
+        self.optim = Some(create_optimizer(&self.config).load_record(record));
 
         client.memory_cleanup();
 
@@ -516,6 +1000,8 @@ fn map_opt<B: AutodiffBackend, const D: usize>(
         moment.moment_2 = map_opt(moment.moment_2);
         moment
     });
+    // The visible rows are set again before each step, and no longer match the splats.
+    state.visible = None;
 
     record.insert(param_id, AdaptorRecord::from_state(state));
 }
@@ -574,3 +1060,161 @@ async fn prune_points(
     }
     (splats, refiner, start_splats - new_points)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::SplatTrainer;
+    use crate::{
+        config::TrainConfig,
+        test_scene::{random_batch, random_splats},
+    };
+    use brush_render::MainBackend;
+    use burn::{
+        backend::{Autodiff, wgpu::WgpuDevice},
+        module::ParamId,
+        optim::GradientsParams,
+        tensor::ElementConversion,
+    };
+    use burn_cubecl::cubecl::future::block_on;
+
+    type DiffBack = Autodiff<MainBackend>;
+
+    #[test]
+    fn accumulated_grads_match_batch() {
+        let device = WgpuDevice::DefaultDevice;
+        let splats = random_splats::<DiffBack>(64, &device);
+
+        let batches: Vec<_> = [-4.0, 4.0]
+            .into_iter()
+            .map(|z| random_batch(z, &device))
+            .collect();
+
+        let trainer = SplatTrainer::new(&TrainConfig::new(), &device);
+        let opacity = splats.opacities();
+
+        let accumulated = trainer.accumulate_grads(0, &batches, &splats, &opacity);
+
+        // Backpropagate the mean loss of both views at once.
+        let loss = batches
+            .iter()
+            .map(|batch| trainer.view_loss(0, batch, &splats, opacity.clone()).loss)
+            .reduce(|a, b| a + b)
+            .expect("Need views")
+            / batches.len() as f32;
+        let mut grads = loss.backward();
+        let param_ids = [
+            splats.sh_coeffs.id,
+            splats.rotation.id,
+            splats.log_scales.id,
+            splats.means.id,
+            splats.raw_opacity.id,
+        ];
+        let batch_grads = GradientsParams::from_params(&mut grads, &splats, &param_ids);
+
+        let accumulated = &accumulated.grads;
+        assert_grads_match::<3>("SH coefficients", accumulated, &batch_grads, param_ids[0]);
+        assert_grads_match::<2>("rotations", accumulated, &batch_grads, param_ids[1]);
+        assert_grads_match::<2>("scales", accumulated, &batch_grads, param_ids[2]);
+        assert_grads_match::<2>("means", accumulated, &batch_grads, param_ids[3]);
+        assert_grads_match::<1>("opacities", accumulated, &batch_grads, param_ids[4]);
+    }
+
+    fn assert_grads_match<const D: usize>(
+        name: &str,
+        accumulated: &GradientsParams,
+        batch: &GradientsParams,
+        id: ParamId,
+    ) {
+        let accumulated = accumulated
+            .get::<MainBackend, D>(id)
+            .unwrap_or_else(|| panic!("Missing accumulated {name} grads"));
+        let batch = batch
+            .get::<MainBackend, D>(id)
+            .unwrap_or_else(|| panic!("Missing batch {name} grads"));
+
+        let scale = batch.clone().abs().max().into_scalar().elem::<f32>();
+        let diff = (accumulated - batch)
+            .abs()
+            .max()
+            .into_scalar()
+            .elem::<f32>();
+        assert!(scale > 0.0, "The {name} must have gradients");
+        assert!(
+            diff < 1e-4 * scale,
+            "Accumulated {name} gradients differ from the batch by {diff} (scale {scale})"
+        );
+    }
+
+    #[test]
+    fn resumed_checkpoint_matches_training() {
+        let device = WgpuDevice::DefaultDevice;
+        let splats = random_splats::<DiffBack>(64, &device);
+        let batch = random_batch(-4.0, &device);
+
+        // Noise on the means is random, which would make the next steps differ.
+        let config = TrainConfig::new().with_mean_noise_weight(0.0);
+        let mut trainer = SplatTrainer::new(&config, &device);
+        let (splats, _) = trainer.step(1.0, 0, &batch, splats);
+        let checkpoint = trainer
+            .save_checkpoint(1, &splats)
+            .expect("Failed to save checkpoint");
+        let (expected, _) = trainer.step(1.0, 1, &batch, splats);
+
+        let mut resumed = SplatTrainer::new(&config, &device);
+        let (iter, splats) = resumed
+            .load_checkpoint(&checkpoint, &device)
+            .expect("Failed to load checkpoint");
+        assert_eq!(iter, 1);
+        let (splats, _) = resumed.step(1.0, iter, &batch, splats);
+
+        // Gradients are accumulated with atomics, so allow for tiny differences in ordering.
+        let diff = (splats.means.val() - expected.means.val())
+            .abs()
+            .max()
+            .into_scalar()
+            .elem::<f32>();
+        assert!(diff < 1e-6, "Resumed training differs by {diff}");
+    }
+
+    #[test]
+    fn seeded_refine_is_reproducible() {
+        let device = WgpuDevice::DefaultDevice;
+        let splats = random_splats::<DiffBack>(64, &device);
+        let batch = random_batch(-4.0, &device);
+
+        // Grow every splat with a gradient, so the refine splits splats at random offsets.
+        let refine = |seed: u64| {
+            let config = TrainConfig::new()
+                .with_mean_noise_weight(0.0)
+                .with_refine_every(1)
+                .with_growth_grad_threshold(0.0)
+                .with_growth_select_fraction(1.0)
+                .with_refine_seed(Some(seed));
+            let mut trainer = SplatTrainer::new(&config, &device);
+            let (splats, _) = trainer.step(1.0, 0, &batch, splats.clone());
+            let (splats, stats) = block_on(trainer.refine_if_needed(1, splats));
+            let stats = stats.expect("Must refine");
+            assert!(stats.num_added > 0, "Refine must grow splats");
+            splats.means.val()
+        };
+
+        let first = refine(3);
+        let second = refine(3);
+        assert_eq!(
+            first.dims(),
+            second.dims(),
+            "Refines must add as many splats"
+        );
+        // Gradients are accumulated with atomics, so allow for tiny differences in ordering.
+        let diff = (first.clone() - second)
+            .abs()
+            .max()
+            .into_scalar()
+            .elem::<f32>();
+        assert!(diff < 1e-6, "Refines with the same seed differ by {diff}");
+
+        let other = refine(4);
+        let diff = (first - other).abs().max().into_scalar().elem::<f32>();
+        assert!(diff > 1e-3, "Refines with other seeds must differ");
+    }
+}
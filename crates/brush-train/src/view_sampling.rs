@@ -0,0 +1,87 @@
+//! Loss weighted sampling of training views: views that are fit badly are trained on more often
+//! than views that are already fit well.
+
+/// How much of the running loss estimate of a view is replaced by each new loss of the view.
+const LOSS_UPDATE_RATE: f32 = 0.5;
+
+/// Running estimate of the loss of each training view, to weight how often views are sampled.
+/// See [`crate::config::ViewSampling::LossWeighted`].
+#[derive(Debug, Clone)]
+pub struct ViewLossEstimates {
+    losses: Vec<Option<f32>>,
+}
+
+impl ViewLossEstimates {
+    pub fn new(num_views: usize) -> Self {
+        Self {
+            losses: vec![None; num_views],
+        }
+    }
+
+    /// Update the estimate of `view` with a new loss. Losses that aren't finite are ignored.
+    pub fn record(&mut self, view: usize, loss: f32) {
+        if !loss.is_finite() {
+            return;
+        }
+        let estimate = &mut self.losses[view];
+        *estimate = Some(match *estimate {
+            Some(old) => old + (loss - old) * LOSS_UPDATE_RATE,
+            None => loss,
+        });
+    }
+
+    /// Sampling weight of each view: its loss estimate relative to the mean estimate, to the
+    /// power of `1 / temperature`.
+    ///
+    /// Views without an estimate yet get the highest weight, so every view is tried early on.
+    /// Before any loss is known, all views are weighted equally.
+    pub fn weights(&self, temperature: f32) -> Vec<f32> {
+        assert!(
+            temperature > 0.0,
+            "The view sampling temperature must be positive"
+        );
+        let known: Vec<f32> = self.losses.iter().flatten().copied().collect();
+        if known.is_empty() {
+            return vec![1.0; self.losses.len()];
+        }
+        let mean = known.iter().sum::<f32>() / known.len() as f32;
+        let max = known.iter().copied().fold(0.0, f32::max);
+        if mean <= 0.0 {
+            return vec![1.0; self.losses.len()];
+        }
+
+        self.losses
+            .iter()
+            .map(|loss| (loss.unwrap_or(max) / mean).powf(1.0 / temperature))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ViewLossEstimates;
+
+    #[test]
+    fn hard_views_weigh_more() {
+        let mut estimates = ViewLossEstimates::new(4);
+        assert_eq!(estimates.weights(1.0), vec![1.0; 4]);
+
+        for view in 0..3 {
+            estimates.record(view, 0.1);
+        }
+        estimates.record(1, 0.4);
+        estimates.record(2, f32::NAN);
+
+        // View 1 moved halfway to its new loss, and the unseen view 3 weighs as much as it.
+        let weights = estimates.weights(1.0);
+        assert!(
+            weights[1] > 2.0 * weights[0] && weights[0] == weights[2],
+            "Got {weights:?}"
+        );
+        assert_eq!(weights[3], weights[1]);
+
+        // Higher temperatures flatten the weights.
+        let flat = estimates.weights(4.0);
+        assert!(flat[1] / flat[0] < weights[1] / weights[0], "Got {flat:?}");
+    }
+}
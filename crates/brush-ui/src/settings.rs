@@ -27,7 +27,7 @@ impl AppPanel for SettingsPanel {
         egui::ScrollArea::vertical().show(ui, |ui| {
             ui.heading("Model Settings");
             ui.label("Spherical Harmonics Degree:");
-            ui.add(Slider::new(&mut self.args.model_config.sh_degree, 0..=4));
+            ui.add(Slider::new(&mut self.args.model_config.sh_degree, 0..=6));
 
             ui.label("Max image resolution");
             ui.add(
@@ -55,6 +55,7 @@ fn spawn_train_loop(
             img_tensor: sample_to_tensor(&image, &device).unsqueeze(),
             alpha_is_mask: false,
             camera: cam,
+            view_index: None,
         };
 
         let mut iter = 0;